@@ -0,0 +1,243 @@
+//! `git add`: stage what's in the working tree.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::ignore::IgnoreStack;
+use crate::core::index::IndexEntry;
+use crate::core::object::GitObject;
+use crate::core::odb::{LooseObjectWriter, NullObjectWriter, ObjectWriter};
+use crate::core::worktree::{self, relative_path};
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// Stage the files at `paths`, and everything under the ones that are
+/// directories, returning the paths whose entries changed, sorted.
+/// Untracked files the ignore rules match are skipped inside directories,
+/// but one named in `paths` itself is staged, as `--force` would. Tracked
+/// files gone from the working tree are removed from the index, and
+/// staging a conflicted path resolves it.
+pub fn add(repo: &Repository, paths: &[&Path]) -> GitResult<Vec<PathBuf>> {
+    add_with(repo, paths, &mut LooseObjectWriter::new(repo.odb()))
+}
+
+/// Work out what [`add`] would stage, without writing any blobs or the
+/// index.
+pub fn add_dry_run(repo: &Repository, paths: &[&Path]) -> GitResult<Vec<PathBuf>> {
+    add_with(repo, paths, &mut NullObjectWriter::default())
+}
+
+/// [`add`], handing the blobs to `writer`. The index is only written when
+/// the writer [stores](ObjectWriter::stores) them, so a
+/// [`NullObjectWriter`] makes this a dry run.
+pub fn add_with(
+    repo: &Repository,
+    paths: &[&Path],
+    writer: &mut dyn ObjectWriter,
+) -> GitResult<Vec<PathBuf>> {
+    let work_dir = repo.require_work_dir()?;
+    let mut index = repo.read_index()?;
+
+    let mut walker = Walker {
+        work_dir,
+        rules: IgnoreStack::new(repo)?,
+        tracked: index.entries().iter().map(|e| e.path.as_str()).collect(),
+        tracked_dirs: index.directories(),
+        found: BTreeSet::new(),
+    };
+    let mut gone = BTreeSet::new();
+    for &path in paths {
+        let rel = relative_path(work_dir, path)?;
+        let prefix = format!("{}/", rel);
+        let mut tracked = false;
+        for entry in index.entries() {
+            if rel.is_empty() || entry.path == rel || entry.path.starts_with(&prefix) {
+                tracked = true;
+                if fs::symlink_metadata(work_dir.join(&entry.path)).is_err() {
+                    gone.insert(entry.path.clone());
+                }
+            }
+        }
+        match fs::symlink_metadata(work_dir.join(&rel)) {
+            Ok(meta) if meta.is_dir() => {
+                // The rules in force inside `rel` take in its parents'.
+                let mut parents = Vec::new();
+                if !rel.is_empty() {
+                    parents.push("");
+                    parents.extend(rel.match_indices('/').map(|(i, _)| &rel[..i]));
+                }
+                for parent in &parents {
+                    walker.rules.push(parent)?;
+                }
+                walker.walk(&rel)?;
+                for _ in &parents {
+                    walker.rules.pop();
+                }
+            }
+            Ok(_) => {
+                walker.found.insert(rel);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound && tracked => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(GitError::NoSuchPath(path.to_path_buf()));
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let found = walker.found;
+
+    let mut changed = BTreeSet::new();
+    for path in gone {
+        index.remove(&path);
+        changed.insert(path);
+    }
+    for path in found {
+        let full = work_dir.join(&path);
+        let meta = fs::symlink_metadata(&full)?;
+        let mode = worktree::mode_of(&meta);
+        let oid = writer.write(&GitObject::Blob(worktree::read_content(&full, &meta)?))?;
+        if index
+            .get(&path, 0)
+            .is_none_or(|e| e.oid != oid || e.mode != mode)
+        {
+            changed.insert(path.clone());
+        }
+        let mut entry = IndexEntry::new(&path, oid, mode);
+        entry.update_stat(&meta);
+        index.add(entry);
+    }
+    if writer.stores() {
+        repo.write_index(&index)?;
+    }
+    Ok(changed.into_iter().map(PathBuf::from).collect())
+}
+
+/// Finds the files to stage in a directory of the working tree.
+struct Walker<'a> {
+    work_dir: &'a Path,
+    rules: IgnoreStack,
+    tracked: HashSet<&'a str>,
+    /// See [`Index::directories`](crate::core::index::Index::directories).
+    tracked_dirs: HashSet<&'a str>,
+    found: BTreeSet<String>,
+}
+
+impl Walker<'_> {
+    /// Add the files to stage under `dir` to `found`: the tracked ones and
+    /// those that aren't ignored, leaving out nested repositories.
+    fn walk(&mut self, dir: &str) -> GitResult<()> {
+        self.rules.push(dir)?;
+        for entry in fs::read_dir(self.work_dir.join(dir))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == ".git" {
+                continue;
+            }
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            let is_dir = entry.file_type()?.is_dir();
+            let tracked = if is_dir {
+                self.tracked_dirs.contains(path.as_str())
+            } else {
+                self.tracked.contains(path.as_str())
+            };
+            if !tracked && self.rules.is_ignored(&path, is_dir) {
+                continue;
+            }
+            if !is_dir {
+                self.found.insert(path);
+            } else if tracked || !self.work_dir.join(&path).join(".git").exists() {
+                self.walk(&path)?;
+            }
+        }
+        self.rules.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{git, init_repo, stage_file, staged_oid, write_commit};
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn stages_new_changed_and_removed_files() {
+        let (_dir, repo) = init_repo();
+        let work_dir = repo.work_dir().unwrap();
+        stage_file(&repo, ".gitignore", "*.log\n");
+        stage_file(&repo, "same", "same\n");
+        stage_file(&repo, "changed", "old\n");
+        stage_file(&repo, "src/gone.rs", "gone\n");
+        fs::write(work_dir.join("changed"), "new\n").unwrap();
+        fs::remove_file(work_dir.join("src/gone.rs")).unwrap();
+        fs::create_dir_all(work_dir.join("src/deep")).unwrap();
+        fs::write(work_dir.join("src/deep/new.rs"), "new\n").unwrap();
+        fs::write(work_dir.join("build.log"), "x\n").unwrap();
+        fs::write(work_dir.join("src/debug.log"), "x\n").unwrap();
+
+        let expected = paths(&["changed", "src/deep/new.rs", "src/gone.rs"]);
+        if let Some(theirs) = git(&repo, &["add", "--dry-run", "."]) {
+            assert_eq!(
+                theirs,
+                "add 'changed'\nremove 'src/gone.rs'\nadd 'src/deep/new.rs'\n"
+            );
+        }
+        assert_eq!(add(&repo, &[work_dir]).unwrap(), expected);
+        assert_eq!(staged_oid(&repo, "src/gone.rs"), None);
+        assert!(staged_oid(&repo, "src/debug.log").is_none());
+        let blob = staged_oid(&repo, "src/deep/new.rs").unwrap();
+        assert_eq!(repo.odb().read_blob(&blob).unwrap(), b"new\n");
+        assert!(add(&repo, &[work_dir]).unwrap().is_empty());
+
+        // Named on its own, an ignored file is staged anyway.
+        let log = work_dir.join("build.log");
+        assert_eq!(add(&repo, &[&log]).unwrap(), paths(&["build.log"]));
+        assert!(matches!(
+            add(&repo, &[&work_dir.join("nope")]),
+            Err(GitError::NoSuchPath(_))
+        ));
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let (_dir, repo) = init_repo();
+        let work_dir = repo.work_dir().unwrap();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n")], "base");
+        repo.set_head_commit(&base, "").unwrap();
+        stage_file(&repo, "a.txt", "a\n");
+        fs::write(work_dir.join("a.txt"), "changed\n").unwrap();
+        fs::write(work_dir.join("b.txt"), "b\n").unwrap();
+        let objects = || {
+            let mut count = 0;
+            for fanout in fs::read_dir(repo.odb().objects_dir()).unwrap() {
+                let fanout = fanout.unwrap();
+                if fanout.file_name().len() == 2 {
+                    count += fs::read_dir(fanout.path()).unwrap().count();
+                }
+            }
+            count
+        };
+        let before = objects();
+        let index = fs::read(repo.git_dir().join("index")).unwrap();
+
+        let mut null = NullObjectWriter::default();
+        let preview = add_with(&repo, &[work_dir], &mut null).unwrap();
+        assert_eq!(preview, paths(&["a.txt", "b.txt"]));
+        assert_eq!(null.count, 2);
+        assert_eq!(add_dry_run(&repo, &[work_dir]).unwrap(), preview);
+        assert_eq!(objects(), before);
+        assert_eq!(fs::read(repo.git_dir().join("index")).unwrap(), index);
+
+        assert_eq!(add(&repo, &[work_dir]).unwrap(), preview);
+        assert_eq!(objects(), before + 2);
+    }
+}
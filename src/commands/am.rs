@@ -0,0 +1,484 @@
+//! `git am`: commit each patch of a mailbox, as `format-patch` writes them.
+//!
+//! A mailbox has one email for each patch, each starting at a `From `
+//! line. The email's `From:` and `Date:` are the commit's author, its
+//! `Subject:`, less any `[PATCH n/m]` prefix, and the body up to the patch
+//! are the message, and the rest is applied as [`apply`](super::apply)
+//! does, to the index as well as the working tree.
+//!
+//! Like git, a patch that doesn't apply stops `am` with the rest saved in
+//! `rebase-apply/`, numbered from `0001`, with `next` naming the one that
+//! failed and `last` how many there are. [`am_abort`] puts things back as
+//! they were before `am` started, from `ORIG_HEAD`.
+
+use std::fs;
+use std::time::SystemTime;
+
+use crate::commands::{apply, write_tree};
+use crate::core::date;
+use crate::core::diff;
+use crate::core::message::{self, CleanupMode};
+use crate::core::object::{Commit, GitObject, ObjectType};
+use crate::core::odb::LooseObjectWriter;
+use crate::core::oid::Oid;
+use crate::core::signature::{self, Signature};
+use crate::core::tree::{self, FlatTree};
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::repository::{Repository, RepositoryState};
+
+/// An email's commit: who wrote it, its message and the patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Mail {
+    author: Signature,
+    message: String,
+    patch: Vec<u8>,
+}
+
+/// Apply and commit each patch in `mbox` on top of HEAD, in order,
+/// returning the new commits. When one doesn't apply, the commits made
+/// before it are returned and the repository is left in the
+/// [`RepositoryState::ApplyMailbox`] state.
+pub fn am(repo: &Repository, mbox: &[u8]) -> GitResult<Vec<Oid>> {
+    match repo.state() {
+        RepositoryState::Clean => {}
+        state => return Err(GitError::OperationInProgress(state)),
+    }
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let mut head = repo.head_commit()?;
+    if let Some(head) = head {
+        fs::write(repo.git_dir().join("ORIG_HEAD"), format!("{}\n", head))?;
+    }
+
+    let mails = split_mbox(mbox);
+    let mut commits = Vec::new();
+    for (n, raw) in mails.iter().enumerate() {
+        let applied = parse_mail(raw).and_then(|mail| {
+            let paths = apply::apply_to_worktree(repo, &mail.patch, false)?;
+            Ok((mail, paths))
+        });
+        let (mail, paths) = match applied {
+            Ok(applied) => applied,
+            Err(_) => {
+                save_state(repo, &mails, n)?;
+                return Ok(commits);
+            }
+        };
+
+        let mut index = repo.read_index()?;
+        for path in &paths {
+            match worktree::hash_file(work_dir, path)? {
+                Some(entry) => {
+                    let full = work_dir.join(path);
+                    let content = worktree::read_content(&full, &fs::symlink_metadata(&full)?)?;
+                    let oid = odb.write_raw(ObjectType::Blob, &content)?;
+                    index.add(worktree::stat_entry(work_dir, path, oid, entry.mode)?);
+                }
+                None => {
+                    index.remove(path);
+                }
+            }
+        }
+        repo.write_index(&index)?;
+
+        let (_, committer) = signature::default_signatures(repo)?;
+        let commit = Commit {
+            tree: write_tree::write_index_tree(&index, &mut LooseObjectWriter::new(odb))?,
+            parents: head.into_iter().collect(),
+            author: mail.author,
+            committer,
+            extra_headers: Vec::new(),
+            message: mail.message,
+        };
+        let reflog_msg = format!("am: {}", commit.summary());
+        let id = odb.write(&GitObject::Commit(commit))?;
+        repo.set_head_commit(&id, &reflog_msg)?;
+        head = Some(id);
+        commits.push(id);
+    }
+    Ok(commits)
+}
+
+/// Give up on a stopped `am`: move HEAD back to the commit it was at
+/// before, resetting the index and working tree to it, and forget the
+/// remaining patches.
+pub fn am_abort(repo: &Repository) -> GitResult<()> {
+    if repo.state() != RepositoryState::ApplyMailbox {
+        return Err(GitError::NoOperationInProgress(
+            RepositoryState::ApplyMailbox,
+        ));
+    }
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let orig = match fs::read_to_string(repo.git_dir().join("ORIG_HEAD")) {
+        Ok(orig) => Some(orig.trim().parse::<Oid>()?),
+        Err(_) => None,
+    };
+    let flatten = |id: Option<Oid>| match id {
+        Some(id) => tree::flatten(odb, &odb.read_commit(&id)?.tree),
+        None => Ok(FlatTree::new()),
+    };
+    let current = flatten(repo.head_commit()?)?;
+    let target = flatten(orig)?;
+    worktree::update(odb, work_dir, &current, &target)?;
+    repo.write_index(&worktree::index_from_tree(work_dir, &target)?)?;
+    if let Some(orig) = orig {
+        repo.set_head_commit(&orig, "am --abort")?;
+    }
+    fs::remove_dir_all(repo.git_dir().join("rebase-apply"))?;
+    Ok(())
+}
+
+/// Keep the patches from `mails[failed]` on in `rebase-apply/`.
+fn save_state(repo: &Repository, mails: &[&[u8]], failed: usize) -> GitResult<()> {
+    let dir = repo.git_dir().join("rebase-apply");
+    fs::create_dir_all(&dir)?;
+    for (n, raw) in mails.iter().enumerate().skip(failed) {
+        fs::write(dir.join(format!("{:04}", n + 1)), raw)?;
+    }
+    fs::write(dir.join("next"), format!("{}\n", failed + 1))?;
+    fs::write(dir.join("last"), format!("{}\n", mails.len()))?;
+    fs::write(dir.join("applying"), "")?;
+    Ok(())
+}
+
+/// The emails in `mbox`, each without its `From ` line. Input that doesn't
+/// start with one is taken as a single email.
+fn split_mbox(mbox: &[u8]) -> Vec<&[u8]> {
+    let mut mails = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    let mut blank = true;
+    for line in diff::split_lines(mbox) {
+        if blank && line.starts_with(b"From ") {
+            if let Some(start) = start {
+                mails.push(&mbox[start..offset]);
+            }
+            start = Some(offset + line.len());
+        } else if start.is_none() {
+            start = Some(offset);
+        }
+        blank = line == b"\n" || line == b"\r\n";
+        offset += line.len();
+    }
+    if let Some(start) = start {
+        mails.push(&mbox[start..]);
+    }
+    mails
+}
+
+/// The author, message and patch of one email.
+fn parse_mail(raw: &[u8]) -> GitResult<Mail> {
+    let lines = diff::split_lines(raw);
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut at = 0;
+    while at < lines.len() {
+        let line = String::from_utf8_lossy(lines[at]);
+        let line = line.trim_end_matches(['\n', '\r']);
+        at += 1;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            // Folding only inserted the line break.
+            if let Some((_, value)) = headers.last_mut() {
+                value.push_str(line);
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let from =
+        header("from").ok_or_else(|| GitError::Corrupt("patch has no author".to_string()))?;
+    let (name, email) = parse_address(from);
+    let (time, offset) = match header("date") {
+        Some(date) => date::parse_git_date(date)?,
+        None => {
+            let now = date::to_unix(SystemTime::now());
+            (now, signature::local_offset(now))
+        }
+    };
+    let subject = decode_words(header("subject").unwrap_or(""));
+    let subject = strip_subject_prefix(&subject);
+
+    // The body runs up to the patch, which a `---` line may also mark off.
+    let body_start = at;
+    while at < lines.len() {
+        let line = lines[at];
+        let text = line.strip_suffix(b"\n").unwrap_or(line);
+        if text == b"---" || line.starts_with(b"diff -") || line.starts_with(b"Index: ") {
+            break;
+        }
+        at += 1;
+    }
+    let body: Vec<u8> = lines[body_start..at].concat();
+    let message = format!("{}\n\n{}", subject, String::from_utf8_lossy(&body));
+    Ok(Mail {
+        author: Signature::new(&name, &email, time, offset),
+        message: message::cleanup(&message, CleanupMode::Whitespace),
+        patch: lines[at..].concat(),
+    })
+}
+
+/// The display name and address of `Name <address>`, the name unquoted or
+/// decoded. A bare address is its own name.
+fn parse_address(value: &str) -> (String, String) {
+    let (name, email) = match value.rfind('<') {
+        Some(open) => (
+            value[..open].trim(),
+            value[open + 1..].trim_end().trim_end_matches('>'),
+        ),
+        None => (value.trim(), value.trim()),
+    };
+    let name = match name.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+        Some(quoted) => {
+            let mut unquoted = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => unquoted.extend(chars.next()),
+                    _ => unquoted.push(c),
+                }
+            }
+            unquoted
+        }
+        None => decode_words(name),
+    };
+    let name = if name.is_empty() {
+        email.to_string()
+    } else {
+        name
+    };
+    (name, email.to_string())
+}
+
+/// `value` with its RFC 2047 encoded words, `=?<charset>?<q|b>?<text>?=`,
+/// decoded, dropping the whitespace between two of them. Every charset is
+/// taken for UTF-8.
+fn decode_words(value: &str) -> String {
+    let mut out = Vec::with_capacity(value.len());
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        match encoded_word(&rest[start..]) {
+            Some((bytes, len)) => {
+                let between = &rest[..start];
+                if !(after_word && between.trim().is_empty()) {
+                    out.extend_from_slice(between.as_bytes());
+                }
+                out.extend_from_slice(&bytes);
+                rest = &rest[start + len..];
+                after_word = true;
+            }
+            None => {
+                out.extend_from_slice(&rest.as_bytes()[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            }
+        }
+    }
+    out.extend_from_slice(rest.as_bytes());
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The bytes of the encoded word `text` starts with, and its length.
+fn encoded_word(text: &str) -> Option<(Vec<u8>, usize)> {
+    let inner = text.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let bytes = match encoding {
+        "q" | "Q" => decode_q(&inner[..end])?,
+        "b" | "B" => decode_base64(&inner[..end])?,
+        _ => return None,
+    };
+    Some((bytes, 2 + charset.len() + 1 + encoding.len() + 1 + end + 2))
+}
+
+/// RFC 2047's `Q` encoding: `_` for a space and `=XX` for a byte.
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'_' => out.push(b' '),
+            b'=' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                out.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => out.push(b),
+        }
+    }
+    Some(out)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for b in text.bytes().take_while(|&b| b != b'=') {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6 | u32::from(value)) & 0xffffff;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// `subject` without the `Re:` and `[PATCH …]` prefixes mail adds.
+fn strip_subject_prefix(mut subject: &str) -> &str {
+    loop {
+        subject = subject.trim_start();
+        if subject.len() >= 3 && subject[..3].eq_ignore_ascii_case("re:") {
+            subject = &subject[3..];
+        } else if let Some(end) = subject.strip_prefix('[').and_then(|s| s.find(']')) {
+            subject = &subject[end + 2..];
+        } else {
+            return subject.trim_end();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::format_patch::format_patch;
+    use crate::test_utils::{checkout, init_repo, read_file, write_commit};
+
+    /// A commit on top of `base` for each path and content, each also
+    /// keeping `keep.txt`, by an author whose name has to be encoded, with
+    /// `master` checked out at the last: their ids, in order.
+    fn series(repo: &Repository, base: Oid, files: &[(&str, &str)]) -> Vec<Oid> {
+        let odb = repo.odb();
+        let mut parent = base;
+        let mut ids = Vec::new();
+        for (n, file) in files.iter().enumerate() {
+            let id = write_commit(repo, &[parent], &[("keep.txt", "keep\n"), *file], "x");
+            let mut commit = odb.read_commit(&id).unwrap();
+            commit.author = Signature::new("Zoë \"Z\" Ünal", "z@example.com", 1_600_000_000, -150);
+            commit.message = format!(
+                "Patch number {} with a subject long enough to be folded over\n\nBody {}.\n",
+                n + 1,
+                n + 1
+            );
+            parent = odb.write(&GitObject::Commit(commit)).unwrap();
+            ids.push(parent);
+        }
+        checkout(repo, "master", &parent);
+        ids
+    }
+
+    #[test]
+    fn round_trips_format_patch() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(
+            &repo,
+            &[],
+            &[("keep.txt", "keep\n"), ("a.txt", "one\ntwo\n")],
+            "base",
+        );
+        let picked = series(
+            &repo,
+            base,
+            &[("a.txt", "one\n2\n"), ("b/new.txt", "new\n")],
+        );
+        let mbox = format_patch(&repo, &base.to_string()).unwrap().join("\n");
+        checkout(&repo, "master", &base);
+
+        let commits = am(&repo, mbox.as_bytes()).unwrap();
+        assert_eq!(commits.len(), 2);
+        let odb = repo.odb();
+        for (ours, theirs) in commits.iter().zip(&picked) {
+            let ours = odb.read_commit(ours).unwrap();
+            let theirs = odb.read_commit(theirs).unwrap();
+            assert_eq!(ours.tree, theirs.tree);
+            assert_eq!(ours.author, theirs.author);
+            assert_eq!(ours.message, theirs.message);
+        }
+        assert_eq!(
+            odb.read_commit(&commits[1]).unwrap().parents,
+            vec![commits[0]]
+        );
+        assert_eq!(repo.head_commit().unwrap(), Some(commits[1]));
+        assert_eq!(read_file(&repo, "b/new.txt"), "new\n");
+        let index = repo.read_index().unwrap();
+        assert!(index.get("a.txt", 0).is_none());
+        assert!(index.get("b/new.txt", 0).is_some());
+        assert_eq!(repo.state(), RepositoryState::Clean);
+    }
+
+    #[test]
+    fn stops_at_a_patch_that_does_not_apply() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(
+            &repo,
+            &[],
+            &[("keep.txt", "keep\n"), ("a.txt", "a\n")],
+            "base",
+        );
+        series(&repo, base, &[("b.txt", "b\n"), ("c.txt", "c\n")]);
+        let mbox = format_patch(&repo, &base.to_string()).unwrap().join("\n");
+        // The first patch removes `a.txt`, which no longer matches.
+        let start = write_commit(
+            &repo,
+            &[],
+            &[("keep.txt", "keep\n"), ("a.txt", "changed\n")],
+            "start",
+        );
+        checkout(&repo, "master", &start);
+
+        let commits = am(&repo, mbox.as_bytes()).unwrap();
+        assert!(commits.is_empty());
+        assert_eq!(repo.state(), RepositoryState::ApplyMailbox);
+        let state = repo.git_dir().join("rebase-apply");
+        assert_eq!(fs::read_to_string(state.join("next")).unwrap(), "1\n");
+        assert_eq!(fs::read_to_string(state.join("last")).unwrap(), "2\n");
+        assert!(state.join("0002").exists());
+        assert!(matches!(
+            am(&repo, mbox.as_bytes()),
+            Err(GitError::OperationInProgress(RepositoryState::ApplyMailbox))
+        ));
+
+        am_abort(&repo).unwrap();
+        assert_eq!(repo.state(), RepositoryState::Clean);
+        assert_eq!(repo.head_commit().unwrap(), Some(start));
+        assert_eq!(read_file(&repo, "a.txt"), "changed\n");
+    }
+
+    #[test]
+    fn decodes_headers() {
+        assert_eq!(
+            parse_address("=?UTF-8?q?Zo=C3=AB=20=22Z=22?= <z@example.com>"),
+            ("Zoë \"Z\"".to_string(), "z@example.com".to_string())
+        );
+        assert_eq!(
+            parse_address("\"A. U. \\\"Thor\\\"\" <a@example.com>"),
+            ("A. U. \"Thor\"".to_string(), "a@example.com".to_string())
+        );
+        assert_eq!(
+            decode_words("=?UTF-8?q?h=C3=A9llo?= =?utf-8?B?d8O2cmxk?= =?x"),
+            "héllowörld =?x"
+        );
+        assert_eq!(
+            strip_subject_prefix("Re: [PATCH 2/3] [tag] fix it "),
+            "fix it"
+        );
+    }
+}
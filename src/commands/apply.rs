@@ -0,0 +1,485 @@
+//! `git apply`: patch the working tree with a unified diff.
+//!
+//! Each file's changes start at a `diff --git` line or a `---`/`+++`
+//! pair, with paths taken past their first component (`a/`, `b/`) as
+//! `-p1` does. `/dev/null` on the old side creates the file and on the
+//! new side deletes it; `new file mode`, `deleted file mode`, `rename
+//! from` and `rename to` lines say the same for patches without hunks.
+//! Anything else outside a hunk, such as a commit message, is ignored.
+//!
+//! A hunk is applied where its context and removed lines are found,
+//! searching outwards from the line it names. Like git, a hunk starting
+//! at the first line has to match there, and one without trailing context
+//! has to match at the end, unless it has no context at all.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::core::diff;
+use crate::core::object::MODE_FILE;
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+#[derive(Debug, Default)]
+struct FilePatch {
+    /// `None` when the patch creates the file.
+    old_path: Option<String>,
+    /// `None` when the patch deletes the file.
+    new_path: Option<String>,
+    new_mode: Option<u32>,
+    /// Whether the paths came from `---` and `+++` lines.
+    has_header: bool,
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug)]
+struct Hunk {
+    old_start: usize,
+    old_len: usize,
+    /// Each line with its ` `, `-` or `+` prefix split off.
+    lines: Vec<(u8, Vec<u8>)>,
+}
+
+/// Apply `patch` to the files in the working tree. With `check`, only
+/// make sure it would apply cleanly. Either every file is patched or,
+/// when any hunk fails, none is.
+pub fn apply(repo: &Repository, patch: &[u8], check: bool) -> GitResult<()> {
+    apply_to_worktree(repo, patch, check).map(|_| ())
+}
+
+/// [`apply`], returning every path it creates, changes or removes, sorted.
+pub(crate) fn apply_to_worktree(
+    repo: &Repository,
+    patch: &[u8],
+    check: bool,
+) -> GitResult<Vec<String>> {
+    let work_dir = repo.require_work_dir()?;
+    let patches = parse(patch)?;
+    if patches.is_empty() {
+        return Err(GitError::Corrupt("no valid patches in input".to_string()));
+    }
+
+    // What each touched path will hold, `None` for removed, so later
+    // patches to the same file see the earlier ones' results.
+    let mut results: BTreeMap<String, (Option<Vec<u8>>, u32)> = BTreeMap::new();
+    for file in &patches {
+        let old = match &file.old_path {
+            Some(path) => Some(match results.get(path) {
+                Some((content, _)) => content.clone().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{}: removed already", path),
+                    )
+                })?,
+                None => fs::read(work_dir.join(path))?,
+            }),
+            None => None,
+        };
+        let (path, mode) = match (&file.old_path, &file.new_path) {
+            (_, Some(path)) => (path, file.new_mode.unwrap_or(MODE_FILE)),
+            (Some(path), None) => (path, MODE_FILE),
+            (None, None) => unreachable!("parse gives every patch a path"),
+        };
+        if file.old_path.is_none() {
+            let pending = results.get(path).map(|(content, _)| content.is_some());
+            if pending.unwrap_or_else(|| work_dir.join(path).exists()) {
+                return Err(GitError::WouldOverwrite(PathBuf::from(path)));
+            }
+        }
+        let patched = apply_hunks(path, old.as_deref().unwrap_or_default(), &file.hunks)?;
+        match (&file.old_path, &file.new_path) {
+            (Some(old_path), None) => {
+                if !patched.is_empty() {
+                    return Err(GitError::PatchDoesNotApply {
+                        path: PathBuf::from(old_path),
+                        hunk: file.hunks.len(),
+                    });
+                }
+                results.insert(old_path.clone(), (None, mode));
+            }
+            (old_path, Some(new_path)) => {
+                if let Some(old_path) = old_path.as_ref().filter(|old| *old != new_path) {
+                    results.insert(old_path.clone(), (None, mode));
+                }
+                results.insert(new_path.clone(), (Some(patched), mode));
+            }
+            (None, None) => {}
+        }
+    }
+    let paths = results.keys().cloned().collect();
+    if check {
+        return Ok(paths);
+    }
+
+    for (path, (content, mode)) in results {
+        match content {
+            None => worktree::remove_file(work_dir, &path)?,
+            // Writing over an existing file keeps its permissions.
+            Some(content) if work_dir.join(&path).is_file() => {
+                fs::write(work_dir.join(&path), content)?
+            }
+            Some(content) => worktree::write_content(work_dir, &path, mode, &content)?,
+        }
+    }
+    Ok(paths)
+}
+
+/// `content` with `hunks` applied in turn, each found near where it says
+/// it goes, shifted by however far the ones before it moved.
+fn apply_hunks(path: &str, content: &[u8], hunks: &[Hunk]) -> GitResult<Vec<u8>> {
+    let mut lines: Vec<&[u8]> = diff::split_lines(content);
+    let mut offset: isize = 0;
+    for (n, hunk) in hunks.iter().enumerate() {
+        let preimage: Vec<&[u8]> = hunk
+            .lines
+            .iter()
+            .filter(|(tag, _)| *tag != b'+')
+            .map(|(_, line)| line.as_slice())
+            .collect();
+        let postimage: Vec<&[u8]> = hunk
+            .lines
+            .iter()
+            .filter(|(tag, _)| *tag != b'-')
+            .map(|(_, line)| line.as_slice())
+            .collect();
+        let leading = hunk
+            .lines
+            .iter()
+            .take_while(|(tag, _)| *tag == b' ')
+            .count();
+        let trailing = hunk
+            .lines
+            .iter()
+            .rev()
+            .take_while(|(tag, _)| *tag == b' ')
+            .count();
+        let has_context = leading > 0 || trailing > 0;
+        let match_beginning = hunk.old_start <= 1 && has_context;
+        let match_end = trailing == 0 && has_context;
+
+        // An empty range names the line before it.
+        let position = if hunk.old_len == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start - 1
+        };
+        let expected = (position as isize + offset).clamp(0, lines.len() as isize) as usize;
+        let fits = |at: usize| {
+            at + preimage.len() <= lines.len()
+                && lines[at..at + preimage.len()] == preimage[..]
+                && (!match_beginning || at == 0)
+                && (!match_end || at + preimage.len() == lines.len())
+        };
+        let found = (0..=lines.len())
+            .flat_map(|distance| {
+                let before = expected.checked_sub(distance);
+                let after = Some(expected + distance).filter(|_| distance > 0);
+                before.into_iter().chain(after)
+            })
+            .filter(|&at| at <= lines.len())
+            .find(|&at| fits(at));
+        let at = found.ok_or_else(|| GitError::PatchDoesNotApply {
+            path: PathBuf::from(path),
+            hunk: n + 1,
+        })?;
+        lines.splice(at..at + preimage.len(), postimage.iter().copied());
+        offset =
+            at as isize - position as isize + postimage.len() as isize - preimage.len() as isize;
+    }
+    Ok(lines.concat())
+}
+
+fn parse(patch: &[u8]) -> GitResult<Vec<FilePatch>> {
+    let lines = diff::split_lines(patch);
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let corrupt = |at: usize| GitError::Corrupt(format!("corrupt patch at line {}", at + 1));
+    let mut i = 0;
+    while i < lines.len() {
+        let line = String::from_utf8_lossy(lines[i]);
+        let line = line.trim_end_matches(['\n', '\r']);
+        i += 1;
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            let path = git_header_path(paths).ok_or_else(|| corrupt(i - 1))?;
+            patches.push(FilePatch {
+                old_path: Some(path.clone()),
+                new_path: Some(path),
+                ..FilePatch::default()
+            });
+            continue;
+        }
+        if line.starts_with("--- ") && lines.get(i).is_some_and(|next| next.starts_with(b"+++ ")) {
+            // A `---` straight after `diff --git` belongs to it.
+            let fresh = patches
+                .last()
+                .is_none_or(|last| last.has_header || !last.hunks.is_empty());
+            if fresh {
+                patches.push(FilePatch::default());
+            }
+            let new_line = String::from_utf8_lossy(lines[i]);
+            let last = patches.last_mut().expect("a patch was just pushed");
+            last.old_path = patch_path(line[4..].trim_end());
+            last.new_path = patch_path(new_line[4..].trim_end_matches(['\n', '\r']));
+            last.has_header = true;
+            if last.old_path.is_none() && last.new_path.is_none() {
+                return Err(corrupt(i - 1));
+            }
+            i += 1;
+            continue;
+        }
+        let last = match patches.last_mut() {
+            Some(last) => last,
+            None => continue,
+        };
+        if let Some(mode) = line.strip_prefix("new file mode ") {
+            last.old_path = None;
+            last.new_mode = u32::from_str_radix(mode, 8).ok();
+        } else if line.starts_with("deleted file mode ") {
+            last.new_path = None;
+        } else if let Some(mode) = line.strip_prefix("new mode ") {
+            last.new_mode = u32::from_str_radix(mode, 8).ok();
+        } else if let Some(from) = line.strip_prefix("rename from ") {
+            last.old_path = Some(from.to_string());
+        } else if let Some(to) = line.strip_prefix("rename to ") {
+            last.new_path = Some(to.to_string());
+        } else if line.starts_with("@@ ") {
+            let (hunk, next) = parse_hunk(&lines, i - 1).ok_or_else(|| corrupt(i - 1))?;
+            last.hunks.push(hunk);
+            i = next;
+        }
+    }
+    for patch in &patches {
+        for path in patch.old_path.iter().chain(&patch.new_path) {
+            if path
+                .split('/')
+                .any(|part| part.is_empty() || part == "." || part == "..")
+            {
+                return Err(GitError::Corrupt(format!("invalid path '{}'", path)));
+            }
+        }
+    }
+    Ok(patches)
+}
+
+/// The hunk whose `@@` header is `lines[at]`, and the index of the line
+/// after it.
+fn parse_hunk(lines: &[&[u8]], at: usize) -> Option<(Hunk, usize)> {
+    let header = std::str::from_utf8(lines[at]).ok()?;
+    let ranges = header.strip_prefix("@@ -")?;
+    let (ranges, _) = ranges.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let ((old_start, old_len), (_, new_len)) = (range(old)?, range(new)?);
+    // Only an empty range can sit before the first line.
+    if old_start == 0 && old_len > 0 {
+        return None;
+    }
+
+    let mut hunk = Hunk {
+        old_start,
+        old_len,
+        lines: Vec::new(),
+    };
+    let (mut old_seen, mut new_seen) = (0, 0);
+    let mut i = at + 1;
+    while old_seen < old_len || new_seen < new_len {
+        let line = *lines.get(i)?;
+        i += 1;
+        let (tag, text) = match line.split_first()? {
+            // Some editors strip the space off empty context lines.
+            (b'\n', _) => (b' ', line),
+            (b'\\', _) => {
+                strip_newline(&mut hunk)?;
+                continue;
+            }
+            (&tag, text) => (tag, text),
+        };
+        match tag {
+            b' ' => {
+                old_seen += 1;
+                new_seen += 1;
+            }
+            b'-' => old_seen += 1,
+            b'+' => new_seen += 1,
+            _ => return None,
+        }
+        hunk.lines.push((tag, text.to_vec()));
+    }
+    if old_seen > old_len || new_seen > new_len {
+        return None;
+    }
+    if lines.get(i).is_some_and(|line| line.starts_with(b"\\")) {
+        strip_newline(&mut hunk)?;
+        i += 1;
+    }
+    Some((hunk, i))
+}
+
+/// Apply a `\ No newline at end of file` marker to the line before it.
+fn strip_newline(hunk: &mut Hunk) -> Option<()> {
+    let (_, line) = hunk.lines.last_mut()?;
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    Some(())
+}
+
+/// A `---` or `+++` path past its first component, `None` for
+/// `/dev/null`. Anything after a tab, such as a timestamp, isn't part of
+/// it.
+fn patch_path(path: &str) -> Option<String> {
+    let path = path.split('\t').next().unwrap_or(path);
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(
+        path.split_once('/')
+            .map_or(path, |(_, rest)| rest)
+            .to_string(),
+    )
+}
+
+/// The new side's path in `a/<old> b/<new>`. When both sides are the
+/// same the header splits in the middle, which works for names with
+/// ` b/` in them too; renames say which paths they mean in later lines.
+fn git_header_path(paths: &str) -> Option<String> {
+    let half = paths.len().checked_sub(1)? / 2;
+    if let (Some(old), Some(" "), Some(new)) = (
+        paths.get(..half),
+        paths.get(half..half + 1),
+        paths.get(half + 1..),
+    ) {
+        if old.starts_with("a/") && new.starts_with("b/") && old[2..] == new[2..] {
+            return Some(new[2..].to_string());
+        }
+    }
+    let (old, new) = paths.split_once(" b/")?;
+    old.strip_prefix("a/").map(|_| new.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{git, init_repo, read_file, stage_file};
+
+    /// A git-style patch to `path` from `old` to `new`.
+    fn patch(path: &str, old: &str, new: &str) -> String {
+        let hunks = diff::unified_hunks(old.as_bytes(), new.as_bytes(), 3);
+        format!(
+            "diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n{1}",
+            path,
+            String::from_utf8(hunks).unwrap()
+        )
+    }
+
+    fn numbered(lines: std::ops::Range<usize>) -> String {
+        lines.map(|n| format!("line {}\n", n)).collect()
+    }
+
+    #[test]
+    fn round_trips_a_diff() {
+        let (_dir, repo) = init_repo();
+        let old = numbered(1..30);
+        let new = format!(
+            "line 1\nline two\n{}inserted\n{}line 29 changed\nno newline",
+            numbered(3..15),
+            numbered(15..29)
+        );
+        let work_dir = repo.work_dir().unwrap();
+        fs::write(work_dir.join("file.txt"), &old).unwrap();
+        fs::write(work_dir.join("gone.txt"), "bye\n").unwrap();
+        let mut full = patch("file.txt", &old, &new);
+        full.push_str(
+            "diff --git a/new/made.txt b/new/made.txt\nnew file mode 100644\n\
+             --- /dev/null\n+++ b/new/made.txt\n@@ -0,0 +1,2 @@\n+made\n+here\n\
+             diff --git a/gone.txt b/gone.txt\ndeleted file mode 100644\n\
+             --- a/gone.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-bye\n",
+        );
+
+        apply(&repo, full.as_bytes(), true).unwrap();
+        assert_eq!(read_file(&repo, "file.txt"), old);
+        assert!(!work_dir.join("new").exists());
+
+        apply(&repo, full.as_bytes(), false).unwrap();
+        assert_eq!(read_file(&repo, "file.txt"), new);
+        assert_eq!(read_file(&repo, "new/made.txt"), "made\nhere\n");
+        assert!(!work_dir.join("gone.txt").exists());
+        assert!(matches!(
+            apply(&repo, full.as_bytes(), true),
+            Err(GitError::PatchDoesNotApply { hunk: 1, .. })
+        ));
+
+        // Lines added above a hunk since are skipped over.
+        let later = old.replace("line 20\n", "line twenty\n");
+        let shifted = format!("{}{}", numbered(100..105), old);
+        fs::write(work_dir.join("file.txt"), &shifted).unwrap();
+        apply(&repo, patch("file.txt", &old, &later).as_bytes(), false).unwrap();
+        assert_eq!(
+            read_file(&repo, "file.txt"),
+            format!("{}{}", numbered(100..105), later)
+        );
+    }
+
+    #[test]
+    fn applies_what_git_diff_writes() {
+        let (_dir, repo) = init_repo();
+        let old = numbered(1..20);
+        let new = old
+            .replace("line 4\n", "")
+            .replace("line 17\n", "line 17\nextra\n");
+        stage_file(&repo, "f.txt", &old);
+        fs::write(repo.work_dir().unwrap().join("f.txt"), &new).unwrap();
+        let theirs = match git(&repo, &["diff"]) {
+            Some(theirs) => theirs,
+            None => return,
+        };
+        let ours = diff::unified_hunks(old.as_bytes(), new.as_bytes(), 3);
+        let hunks = theirs.find("@@").unwrap();
+        assert_eq!(theirs[hunks..], String::from_utf8(ours).unwrap());
+        fs::write(repo.work_dir().unwrap().join("f.txt"), &old).unwrap();
+        apply(&repo, theirs.as_bytes(), false).unwrap();
+        assert_eq!(read_file(&repo, "f.txt"), new);
+    }
+
+    #[test]
+    fn refuses_a_patch_whose_context_moved_on() {
+        let (_dir, repo) = init_repo();
+        let old = numbered(1..30);
+        let new = old
+            .replace("line 2\n", "line 2!\n")
+            .replace("line 25\n", "");
+        let work_dir = repo.work_dir().unwrap();
+        let edited = old.replace("line 24\n", "line 24 edited\n");
+        fs::write(work_dir.join("f.txt"), &edited).unwrap();
+        fs::write(work_dir.join("other.txt"), "a\n").unwrap();
+        let both = patch("other.txt", "a\n", "b\n") + &patch("f.txt", &old, &new);
+
+        assert!(matches!(
+            apply(&repo, both.as_bytes(), false),
+            Err(GitError::PatchDoesNotApply { path, hunk: 2 }) if path == std::path::Path::new("f.txt")
+        ));
+        // Nothing was written, not even the file that would have applied.
+        assert_eq!(read_file(&repo, "other.txt"), "a\n");
+        assert_eq!(read_file(&repo, "f.txt"), edited);
+        assert!(matches!(
+            apply(&repo, b"not a patch\n", true),
+            Err(GitError::Corrupt(_))
+        ));
+        assert!(matches!(
+            apply(&repo, b"--- a/a\n+++ b/a\n@@ -0,1 +0,1 @@\n-x\n+y\n", true),
+            Err(GitError::Corrupt(_))
+        ));
+        let creates = "--- /dev/null\n+++ b/f.txt\n@@ -0,0 +1 @@\n+x\n";
+        assert!(matches!(
+            apply(&repo, creates.as_bytes(), true),
+            Err(GitError::WouldOverwrite(_))
+        ));
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::core::diff::{self, DiffOp};
+use crate::core::object::Commit;
+use crate::core::oid::Oid;
+use crate::core::pretty;
+use crate::core::signature::{self, Signature};
+use crate::core::tree;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// A run of lines blamed on one commit, consecutive both in the file and
+/// in the commit's version of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameHunk {
+    pub commit: Oid,
+    pub author: Signature,
+    /// The 1-based line numbers the lines had in `commit`.
+    pub original_lines: Range<usize>,
+    /// The 1-based line numbers the lines have in the blamed revision.
+    pub final_lines: Range<usize>,
+    /// Each line's text, without its terminator.
+    pub content: Vec<String>,
+}
+
+/// Attribute each line of `path` as of `rev` (HEAD by default) to the
+/// commit that introduced it, as hunks in file order.
+///
+/// Starting from `rev`, the lines still to be explained are handed back to
+/// each parent whose version of the file has them unchanged, and whatever
+/// no parent has is blamed on the commit. The walk goes newest first and
+/// stops as soon as every line has been attributed, rather than reading the
+/// rest of history. At a merge the first parent is offered the lines
+/// first, so a line both sides have is blamed down the first parent's
+/// history.
+pub fn blame(repo: &Repository, path: &Path, rev: Option<&str>) -> GitResult<Vec<BlameHunk>> {
+    let odb = repo.odb();
+    let rev = rev.unwrap_or("HEAD");
+    let path_str = tree_path(path);
+    let start = odb.peel_to_commit(&repo.resolve_rev(rev)?)?;
+    let mut commits: HashMap<Oid, Commit> = HashMap::new();
+    let commit = odb.read_commit(&start)?;
+    let blob = tree::find_path(odb, &commit.tree, &path_str)?
+        .filter(|entry| !entry.is_tree())
+        .ok_or_else(|| GitError::PathNotInRevision {
+            path: path.to_path_buf(),
+            rev: rev.to_string(),
+        })?
+        .oid;
+    let data = odb.read_blob(&blob)?;
+    let final_lines = diff::split_lines(&data);
+    let mut blamed: Vec<Option<(Oid, usize)>> = vec![None; final_lines.len()];
+
+    // Each suspect commit has the lines it might be to blame for, as
+    // (final line, line in the commit's version) pairs, and its blob.
+    let mut suspects: HashMap<Oid, (Oid, Vec<(usize, usize)>)> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    suspects.insert(
+        start,
+        (blob, (0..final_lines.len()).map(|i| (i, i)).collect()),
+    );
+    queue.push((commit.committer.time, start));
+    commits.insert(start, commit);
+
+    while let Some((_, id)) = queue.pop() {
+        let (blob, mut lines) = match suspects.remove(&id) {
+            Some(suspect) => suspect,
+            None => continue,
+        };
+        let parents = commits[&id].parents.clone();
+        let data = odb.read_blob(&blob)?;
+        let ours = diff::split_lines(&data);
+        for parent in parents {
+            if lines.is_empty() {
+                break;
+            }
+            if let Entry::Vacant(entry) = commits.entry(parent) {
+                entry.insert(odb.read_commit(&parent)?);
+            }
+            let parent_commit = &commits[&parent];
+            let parent_blob = match tree::find_path(odb, &parent_commit.tree, &path_str)? {
+                Some(entry) if !entry.is_tree() => entry.oid,
+                _ => continue,
+            };
+            let passed = if parent_blob == blob {
+                std::mem::take(&mut lines)
+            } else {
+                let parent_data = odb.read_blob(&parent_blob)?;
+                let theirs = diff::split_lines(&parent_data);
+                // Where each of our lines sits in the parent, if unchanged.
+                let mut in_parent = vec![None; ours.len()];
+                for op in diff::diff(&theirs, &ours) {
+                    if let DiffOp::Equal { old, new, len } = op {
+                        for i in 0..len {
+                            in_parent[new + i] = Some(old + i);
+                        }
+                    }
+                }
+                let mut passed = Vec::new();
+                let mut kept = Vec::new();
+                for (final_line, ours) in lines {
+                    match in_parent[ours] {
+                        Some(theirs) => passed.push((final_line, theirs)),
+                        None => kept.push((final_line, ours)),
+                    }
+                }
+                lines = kept;
+                passed
+            };
+            if passed.is_empty() {
+                continue;
+            }
+            let time = parent_commit.committer.time;
+            let suspect = suspects.entry(parent).or_insert_with(|| {
+                queue.push((time, parent));
+                (parent_blob, Vec::new())
+            });
+            suspect.1.extend(passed);
+        }
+        for (final_line, ours) in lines {
+            blamed[final_line] = Some((id, ours));
+        }
+    }
+
+    let mut hunks: Vec<BlameHunk> = Vec::new();
+    for (final_line, (blame, line)) in blamed.into_iter().zip(final_lines).enumerate() {
+        let (commit, ours) = blame.expect("every line is blamed on some commit");
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let content = String::from_utf8_lossy(line).into_owned();
+        match hunks.last_mut() {
+            Some(hunk) if hunk.commit == commit && hunk.original_lines.end == ours + 1 => {
+                hunk.original_lines.end += 1;
+                hunk.final_lines.end += 1;
+                hunk.content.push(content);
+            }
+            _ => hunks.push(BlameHunk {
+                commit,
+                author: commits[&commit].author.clone(),
+                original_lines: ours + 1..ours + 2,
+                final_lines: final_line + 1..final_line + 2,
+                content: vec![content],
+            }),
+        }
+    }
+    Ok(hunks)
+}
+
+/// Write `hunks`, a blame of `path`, as `git blame --line-porcelain` does:
+/// every line with a header naming its commit and line numbers and the
+/// details of that commit, then the line itself after a tab.
+pub fn write_line_porcelain<W: Write>(
+    mut out: W,
+    repo: &Repository,
+    path: &Path,
+    hunks: &[BlameHunk],
+) -> GitResult<()> {
+    let odb = repo.odb();
+    let path_str = tree_path(path);
+    let mut commits: HashMap<Oid, (Commit, Option<Oid>)> = HashMap::new();
+    for hunk in hunks {
+        if let Entry::Vacant(entry) = commits.entry(hunk.commit) {
+            let commit = odb.read_commit(&hunk.commit)?;
+            // git names the first parent that has the file as `previous`.
+            let mut previous = None;
+            for parent in &commit.parents {
+                let tree = odb.read_commit(parent)?.tree;
+                if tree::find_path(odb, &tree, &path_str)?.is_some() {
+                    previous = Some(*parent);
+                    break;
+                }
+            }
+            entry.insert((commit, previous));
+        }
+        let (commit, previous) = &commits[&hunk.commit];
+        let hex = hunk.commit.to_string();
+        let lines = hunk
+            .original_lines
+            .clone()
+            .zip(hunk.final_lines.clone())
+            .zip(&hunk.content);
+        for (i, ((original, final_line), content)) in lines.enumerate() {
+            write!(out, "{} {} {}", hex, original, final_line)?;
+            if i == 0 {
+                write!(out, " {}", hunk.content.len())?;
+            }
+            writeln!(out)?;
+            for (role, person) in [("author", &commit.author), ("committer", &commit.committer)] {
+                writeln!(out, "{} {}", role, person.name)?;
+                writeln!(out, "{}-mail <{}>", role, person.email)?;
+                writeln!(out, "{}-time {}", role, person.time)?;
+                writeln!(
+                    out,
+                    "{}-tz {}",
+                    role,
+                    signature::format_offset(person.offset)
+                )?;
+            }
+            writeln!(out, "summary {}", pretty::subject(&commit.message))?;
+            match previous {
+                Some(parent) => writeln!(out, "previous {} {}", parent, path_str)?,
+                None if commit.parents.is_empty() => writeln!(out, "boundary")?,
+                None => {}
+            }
+            writeln!(out, "filename {}", path_str)?;
+            writeln!(out, "\t{}", content)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// `path` as trees name it, with `/` between its components.
+fn tree_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::refs;
+    use crate::test_utils::{git, init_repo, write_commit};
+
+    #[test]
+    fn blames_each_region_on_the_commit_that_wrote_it() {
+        let (_dir, repo) = init_repo();
+        let first = write_commit(
+            &repo,
+            &[],
+            &[("f.txt", "a\nb\nc\nd\ne\nf\n"), ("x", "x")],
+            "first",
+        );
+        let second = write_commit(
+            &repo,
+            &[first],
+            &[("f.txt", "a\nB\nc\nd\ne\nf\n"), ("x", "y")],
+            "second",
+        );
+        let third = write_commit(
+            &repo,
+            &[second],
+            &[("f.txt", "a\nB\nc\nd\nE\nf\ng\n"), ("x", "y")],
+            "third",
+        );
+        refs::update(&repo, "refs/heads/master", third, None, "").unwrap();
+
+        let hunks = blame(&repo, Path::new("f.txt"), None).unwrap();
+        let summary: Vec<_> = hunks
+            .iter()
+            .map(|h| {
+                let content: Vec<&str> = h.content.iter().map(String::as_str).collect();
+                (
+                    h.commit,
+                    h.original_lines.clone(),
+                    h.final_lines.clone(),
+                    content,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (first, 1..2, 1..2, vec!["a"]),
+                (second, 2..3, 2..3, vec!["B"]),
+                (first, 3..5, 3..5, vec!["c", "d"]),
+                (third, 5..6, 5..6, vec!["E"]),
+                (first, 6..7, 6..7, vec!["f"]),
+                (third, 7..8, 7..8, vec!["g"]),
+            ]
+        );
+        assert_eq!(hunks[0].author.name, "A U Thor");
+
+        let older = blame(&repo, Path::new("f.txt"), Some(&second.to_string())).unwrap();
+        assert_eq!(older.iter().map(|h| h.content.len()).sum::<usize>(), 6);
+        assert!(matches!(
+            blame(&repo, Path::new("missing"), None),
+            Err(GitError::PathNotInRevision { .. })
+        ));
+    }
+
+    #[test]
+    fn writes_line_porcelain_like_git() {
+        let (_dir, repo) = init_repo();
+        let first = write_commit(&repo, &[], &[("f.txt", "a\nb\nc\n")], "first\n\nbody\n");
+        let second = write_commit(&repo, &[first], &[("f.txt", "a\nB\nc\n")], "second");
+        let third = write_commit(&repo, &[second], &[("f.txt", "a\nB\nc\nd\n")], "third");
+        refs::update(&repo, "refs/heads/master", third, None, "").unwrap();
+
+        let hunks = blame(&repo, Path::new("f.txt"), None).unwrap();
+        let mut out = Vec::new();
+        write_line_porcelain(&mut out, &repo, Path::new("f.txt"), &hunks).unwrap();
+        let ours = String::from_utf8(out).unwrap();
+        assert!(ours.starts_with(&format!("{} 1 1 1\nauthor A U Thor\n", first)));
+        assert!(ours.contains(&format!("previous {} f.txt\n", first)));
+        let args = ["blame", "--line-porcelain", "master", "--", "f.txt"];
+        if let Some(theirs) = git(&repo, &args) {
+            assert_eq!(ours, theirs);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use std::fs;
+use std::io;
+
+use crate::commands::{config as config_cmd, merge_base};
+use crate::core::config::Config;
+use crate::core::oid::Oid;
+use crate::core::reflog;
+use crate::core::refs;
+use crate::core::refspec::RefSpec;
+use crate::error::{GitError, GitResult};
+use crate::repository::{Head, Repository};
+
+/// A local branch, as `git branch -vv` shows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    /// The short name, e.g. `main` for `refs/heads/main`.
+    pub name: String,
+    pub tip: Oid,
+    /// Whether HEAD points at this branch.
+    pub is_current: bool,
+    /// The configured upstream as `<remote>/<branch>`, or just the branch
+    /// name when it tracks another local branch.
+    pub upstream: Option<String>,
+}
+
+fn full_name(name: &str) -> String {
+    format!("refs/heads/{}", name)
+}
+
+/// The branch HEAD points at, unless HEAD is detached.
+fn current_branch(repo: &Repository) -> GitResult<Option<String>> {
+    let refname = match repo.head()? {
+        Head::Branch(refname, _) | Head::Unborn(refname) => refname,
+        Head::Detached(_) => return Ok(None),
+    };
+    Ok(refname.strip_prefix("refs/heads/").map(str::to_string))
+}
+
+/// Branch names follow git's ref name rules, and may not be `HEAD` or look
+/// like an option.
+fn check_branch_name(name: &str) -> GitResult<()> {
+    let reason = match name {
+        "HEAD" => "it is reserved for HEAD",
+        _ if name.starts_with('-') => "it starts with '-'",
+        _ => return refs::validate_name(name, true),
+    };
+    Err(GitError::InvalidRefName {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    })
+}
+
+/// Create branch `name` at the commit `start_point` resolves to. An
+/// existing branch is only moved when `force` is set, and never when it's
+/// checked out.
+pub fn create(repo: &Repository, name: &str, start_point: &str, force: bool) -> GitResult<Oid> {
+    check_branch_name(name)?;
+    let refname = full_name(name);
+    let start = repo.odb().peel_to_commit(&repo.resolve_rev(start_point)?)?;
+    let exists = refs::read(repo, &refname)?.is_some();
+    if exists && !force {
+        return Err(GitError::RefExists(refname));
+    }
+    if exists && current_branch(repo)?.as_deref() == Some(name) {
+        return Err(GitError::BranchCheckedOut(name.to_string()));
+    }
+    let message = if exists {
+        format!("branch: Reset to {}", start_point)
+    } else {
+        format!("branch: Created from {}", start_point)
+    };
+    let expected = if force { None } else { Some(None) };
+    refs::update(repo, &refname, start, expected, &message)?;
+    Ok(start)
+}
+
+/// Delete branch `name`. Unless `force` is set, the branch has to be fully
+/// merged into HEAD.
+pub fn delete(repo: &Repository, name: &str, force: bool) -> GitResult<()> {
+    let refname = full_name(name);
+    let tip = match refs::follow(repo, &refname)? {
+        (_, Some(tip)) => tip,
+        (_, None) => return Err(GitError::RefNotFound(refname)),
+    };
+    if current_branch(repo)?.as_deref() == Some(name) {
+        return Err(GitError::BranchCheckedOut(name.to_string()));
+    }
+    if !force {
+        let merged = match repo.head_commit()? {
+            Some(head) => merge_base::is_ancestor(repo, tip, head)?,
+            None => false,
+        };
+        if !merged {
+            return Err(GitError::BranchNotMerged(name.to_string()));
+        }
+    }
+    refs::delete(repo, &refname, Some(tip))
+}
+
+/// Rename branch `old` to `new`, taking its reflog along and keeping HEAD
+/// on it if it was checked out. An existing `new` is only replaced when
+/// `force` is set.
+pub fn rename(repo: &Repository, old: &str, new: &str, force: bool) -> GitResult<()> {
+    check_branch_name(new)?;
+    let (old_ref, new_ref) = (full_name(old), full_name(new));
+    let is_current = current_branch(repo)?.as_deref() == Some(old);
+    let tip = refs::follow(repo, &old_ref)?.1;
+    if tip.is_none() && !is_current {
+        return Err(GitError::RefNotFound(old_ref));
+    }
+    if old == new {
+        return Ok(());
+    }
+    if refs::read(repo, &new_ref)?.is_some() && !force {
+        return Err(GitError::RefExists(new_ref));
+    }
+
+    let message = format!("Branch: renamed {} to {}", old_ref, new_ref);
+    if let Some(tip) = tip {
+        // The log moves first so deleting the old ref doesn't take it
+        // along, and the old ref goes before the new one is written so
+        // `a/b` can be renamed to `a`.
+        move_reflog(repo, &old_ref, &new_ref)?;
+        refs::delete(repo, &old_ref, Some(tip))?;
+        let expected = if force { None } else { Some(None) };
+        if let Err(err) = refs::update(repo, &new_ref, tip, expected, &message) {
+            move_reflog(repo, &new_ref, &old_ref)?;
+            refs::update(repo, &old_ref, tip, Some(None), "")?;
+            return Err(err);
+        }
+    }
+    if is_current {
+        refs::update_symbolic(repo, "HEAD", &new_ref, Some(&message))?;
+    }
+    Ok(())
+}
+
+/// Every local branch, sorted by name. Like `git branch`, a detached HEAD
+/// is listed first as `(HEAD detached at <short id>)`.
+pub fn list(repo: &Repository) -> GitResult<Vec<Branch>> {
+    let current = current_branch(repo)?;
+    let config = repo.config()?;
+    let mut branches = Vec::new();
+    if let Head::Detached(id) = repo.head()? {
+        branches.push(Branch {
+            name: format!("(HEAD detached at {})", id.abbrev(7)),
+            tip: id,
+            is_current: true,
+            upstream: None,
+        });
+    }
+    for reference in refs::iter_prefixed(repo, "refs/heads/")? {
+        let reference = reference?;
+        let name = reference.name["refs/heads/".len()..].to_string();
+        let upstream =
+            tracking_ref(&config, &name).map(|upstream| refs::shorten(&upstream).to_string());
+        branches.push(Branch {
+            is_current: current.as_deref() == Some(name.as_str()),
+            name,
+            tip: reference.target,
+            upstream,
+        });
+    }
+    Ok(branches)
+}
+
+/// The remote-tracking ref `branch` follows, as `refs/remotes/origin/main`,
+/// or a local branch's full name when it tracks one. `None` when its
+/// `branch.<name>.remote` and `branch.<name>.merge` aren't both set.
+pub fn upstream_of(repo: &Repository, branch: &str) -> GitResult<Option<String>> {
+    Ok(tracking_ref(&repo.config()?, branch))
+}
+
+/// Make `branch` track `upstream`, a remote-tracking branch such as
+/// `origin/main` or a local branch, as `git branch --set-upstream-to`
+/// does, by writing `branch.<name>.remote` and `branch.<name>.merge`.
+pub fn set_upstream(repo: &Repository, branch: &str, upstream: &str) -> GitResult<()> {
+    let refname = full_name(branch);
+    if refs::read(repo, &refname)?.is_none() {
+        return Err(GitError::RefNotFound(refname));
+    }
+    let config = repo.config()?;
+    let (remote, merge) = match refs::expand(repo, upstream)? {
+        Some(full) if full.starts_with("refs/heads/") => (".".to_string(), full),
+        Some(full) if full.starts_with("refs/remotes/") => remote_branch_for(&config, &full)
+            .ok_or_else(|| GitError::RefNotFound(upstream.to_string()))?,
+        _ => return Err(GitError::RefNotFound(upstream.to_string())),
+    };
+    config_cmd::config_set(repo, &format!("branch.{}.remote", branch), &remote)?;
+    config_cmd::config_set(repo, &format!("branch.{}.merge", branch), &merge)
+}
+
+/// Where `branch`'s configured upstream is fetched to: the `merge` ref
+/// mapped through the remote's fetch refspecs, which default to git's
+/// `refs/heads/*:refs/remotes/<remote>/*`.
+fn tracking_ref(config: &Config, branch: &str) -> Option<String> {
+    let remote = config.get("branch", Some(branch), "remote")?;
+    let merge = config.get("branch", Some(branch), "merge")?;
+    if remote == "." {
+        return Some(merge.to_string());
+    }
+    let default = format!("refs/heads/*:refs/remotes/{}/*", remote);
+    fetch_refspecs(config, remote, &default)
+        .iter()
+        .find_map(|spec| RefSpec::parse(spec).ok()?.matches(merge))
+}
+
+/// The remote and the branch on it that fetch into the remote-tracking
+/// ref `full`: the reverse of [`tracking_ref`]. A remote with no fetch
+/// refspecs configured is assumed to use git's default.
+fn remote_branch_for(config: &Config, full: &str) -> Option<(String, String)> {
+    let mut remotes: Vec<&str> = config
+        .entries()
+        .iter()
+        .filter(|e| e.section == "remote")
+        .filter_map(|e| e.subsection.as_deref())
+        .collect();
+    remotes.dedup();
+    for remote in remotes {
+        let default = format!("refs/heads/*:refs/remotes/{}/*", remote);
+        for spec in fetch_refspecs(config, remote, &default) {
+            if let Some(merge) = RefSpec::parse(&spec).ok().and_then(|s| s.reverse(full)) {
+                return Some((remote.to_string(), merge));
+            }
+        }
+    }
+    // Without any remote configured, take the first component as its name.
+    let (remote, branch) = full.strip_prefix("refs/remotes/")?.split_once('/')?;
+    Some((remote.to_string(), format!("refs/heads/{}", branch)))
+}
+
+fn fetch_refspecs(config: &Config, remote: &str, default: &str) -> Vec<String> {
+    let specs = config.get_all("remote", Some(remote), "fetch");
+    if specs.is_empty() {
+        vec![default.to_string()]
+    } else {
+        specs.into_iter().map(str::to_string).collect()
+    }
+}
+
+fn move_reflog(repo: &Repository, from: &str, to: &str) -> GitResult<()> {
+    let logs = repo.git_dir().join("logs");
+    let (from, to) = (reflog::path(repo, from), reflog::path(repo, to));
+    let log = match fs::read(&from) {
+        Ok(log) => log,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    // Like the ref itself, the old log may sit where the new one's
+    // directory has to go.
+    fs::remove_file(&from)?;
+    let mut dir = from.parent();
+    while let Some(current) = dir {
+        if current == logs || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(to, log)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::refs::RefTarget;
+    use crate::test_utils::{checkout, git, init_repo, write_commit};
+
+    #[test]
+    fn creates_and_lists_branches() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        checkout(&repo, "master", &base);
+        let mut config = fs::read_to_string(repo.config_path()).unwrap();
+        config.push_str("[branch \"topic\"]\n\tremote = origin\n\tmerge = refs/heads/main\n");
+        fs::write(repo.config_path(), config).unwrap();
+
+        assert_eq!(create(&repo, "topic", "master", false).unwrap(), base);
+        assert!(matches!(
+            create(&repo, "topic", "master", false),
+            Err(GitError::RefExists(_))
+        ));
+        assert!(matches!(
+            create(&repo, "bad..name", "master", false),
+            Err(GitError::InvalidRefName { .. })
+        ));
+
+        let branches = list(&repo).unwrap();
+        assert_eq!(
+            branches,
+            vec![
+                Branch {
+                    name: "master".to_string(),
+                    tip: base,
+                    is_current: true,
+                    upstream: None,
+                },
+                Branch {
+                    name: "topic".to_string(),
+                    tip: base,
+                    is_current: false,
+                    upstream: Some("origin/main".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sets_and_resolves_upstreams() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        checkout(&repo, "master", &base);
+        refs::update(&repo, "refs/heads/topic", base, None, "").unwrap();
+        refs::update(&repo, "refs/remotes/origin/main", base, None, "").unwrap();
+        refs::update(&repo, "refs/remotes/up/stream/dev", base, None, "").unwrap();
+        config_cmd::config_set(&repo, "remote.origin.url", "/elsewhere").unwrap();
+        config_cmd::config_set(
+            &repo,
+            "remote.up.fetch",
+            "+refs/heads/*:refs/remotes/up/stream/*",
+        )
+        .unwrap();
+        assert_eq!(upstream_of(&repo, "master").unwrap(), None);
+
+        set_upstream(&repo, "master", "origin/main").unwrap();
+        let config = repo.config().unwrap();
+        assert_eq!(
+            config.get("branch", Some("master"), "remote"),
+            Some("origin")
+        );
+        assert_eq!(
+            config.get("branch", Some("master"), "merge"),
+            Some("refs/heads/main")
+        );
+        assert_eq!(
+            upstream_of(&repo, "master").unwrap().as_deref(),
+            Some("refs/remotes/origin/main")
+        );
+        set_upstream(&repo, "topic", "up/stream/dev").unwrap();
+        assert_eq!(
+            repo.config().unwrap().get("branch", Some("topic"), "merge"),
+            Some("refs/heads/dev")
+        );
+        assert_eq!(
+            upstream_of(&repo, "topic").unwrap().as_deref(),
+            Some("refs/remotes/up/stream/dev")
+        );
+        if let Some(theirs) = git(&repo, &["rev-parse", "--symbolic-full-name", "topic@{u}"]) {
+            assert_eq!(theirs, "refs/remotes/up/stream/dev\n");
+        }
+
+        set_upstream(&repo, "topic", "master").unwrap();
+        assert_eq!(
+            upstream_of(&repo, "topic").unwrap().as_deref(),
+            Some("refs/heads/master")
+        );
+        let names: Vec<Option<String>> = list(&repo)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.upstream)
+            .collect();
+        assert_eq!(
+            names,
+            [Some("origin/main".to_string()), Some("master".to_string())]
+        );
+        assert!(matches!(
+            set_upstream(&repo, "topic", "origin/missing"),
+            Err(GitError::RefNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_to_delete_checked_out_or_unmerged_branches() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        let ahead = write_commit(&repo, &[base], &[("a", "b")], "ahead");
+        checkout(&repo, "master", &base);
+        refs::update(&repo, "refs/heads/ahead", ahead, None, "").unwrap();
+        refs::update(&repo, "refs/heads/merged", base, None, "").unwrap();
+
+        assert!(matches!(
+            delete(&repo, "master", true),
+            Err(GitError::BranchCheckedOut(_))
+        ));
+        assert!(matches!(
+            delete(&repo, "ahead", false),
+            Err(GitError::BranchNotMerged(_))
+        ));
+        delete(&repo, "merged", false).unwrap();
+        delete(&repo, "ahead", true).unwrap();
+        let names: Vec<String> = list(&repo).unwrap().into_iter().map(|b| b.name).collect();
+        assert_eq!(names, vec!["master"]);
+    }
+
+    #[test]
+    fn renaming_the_current_branch_moves_head() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        checkout(&repo, "master", &base);
+        let logs = repo.git_dir().join("logs/refs/heads");
+        fs::create_dir_all(&logs).unwrap();
+        fs::write(logs.join("master"), "log\n").unwrap();
+
+        rename(&repo, "master", "main/trunk", false).unwrap();
+        assert_eq!(
+            refs::resolve_symbolic(&repo, "HEAD").unwrap(),
+            RefTarget::Symbolic("refs/heads/main/trunk".to_string())
+        );
+        assert_eq!(repo.head_commit().unwrap(), Some(base));
+        assert_eq!(refs::read(&repo, "refs/heads/master").unwrap(), None);
+        assert!(logs.join("main/trunk").is_file());
+        assert!(!logs.join("master").exists());
+
+        // The old directory is pruned, so renaming back up a level works.
+        rename(&repo, "main/trunk", "main", false).unwrap();
+        assert_eq!(current_branch(&repo).unwrap().as_deref(), Some("main"));
+    }
+}
@@ -0,0 +1,63 @@
+use std::io::{BufRead, Write};
+
+use crate::core::revparse;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// `git cat-file --batch-check`: for each revision in `input`, one per
+/// line, write `<id> <type> <size>` to `output`, or `<revision> missing`
+/// when it doesn't name an object. Objects are only inflated as far as
+/// their headers, and each line is written as soon as it's known.
+pub fn batch_check<R: BufRead, W: Write>(
+    repo: &Repository,
+    input: R,
+    mut output: W,
+) -> GitResult<()> {
+    for line in input.lines() {
+        let line = line?;
+        let rev = line.strip_suffix('\r').unwrap_or(&line);
+        let header = match revparse::parse(repo, rev) {
+            Ok(id) => repo.odb().read_header(&id).map(|header| (id, header)),
+            Err(err) => Err(err),
+        };
+        match header {
+            Ok((id, (kind, size))) => writeln!(output, "{} {} {}", id, kind, size)?,
+            Err(GitError::Io(err)) => return Err(GitError::Io(err)),
+            Err(_) => writeln!(output, "{} missing", rev)?,
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::object::ObjectType;
+    use crate::test_utils::{git_with_input, init_repo, write_commit};
+
+    #[test]
+    fn reports_type_and_size_or_missing() {
+        let (_dir, repo) = init_repo();
+        let blob = repo.odb().write_raw(ObjectType::Blob, b"hello\n").unwrap();
+        let commit = write_commit(&repo, &[], &[("a", "a")], "base");
+        repo.set_head_commit(&commit, "").unwrap();
+        let commit_size = repo.odb().read_raw(&commit).unwrap().1.len();
+
+        let input = format!("{}\nmaster\nnot-a-thing\n", blob.abbrev(8));
+        let mut output = Vec::new();
+        batch_check(&repo, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "{} blob 6\n{} commit {}\nnot-a-thing missing\n",
+                blob, commit, commit_size
+            )
+        );
+
+        if let Some(expected) = git_with_input(&repo, &["cat-file", "--batch-check"], &input) {
+            assert_eq!(output, expected);
+        }
+    }
+}
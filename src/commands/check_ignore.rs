@@ -0,0 +1,228 @@
+//! `git check-ignore`: which rule, if any, decides whether a path is
+//! ignored.
+
+use std::path::PathBuf;
+
+use crate::core::ignore::{IgnoreStack, Pattern};
+use crate::core::quote::quote_path;
+use crate::core::worktree::relative_path;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// Whether one path is ignored, and the rule that decided it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreMatch {
+    /// The path as given.
+    pub path: PathBuf,
+    pub ignored: bool,
+    /// The last pattern to match the path. A negation here means the path
+    /// was explicitly re-included.
+    pub pattern: Option<Pattern>,
+}
+
+/// Check each of `paths`, given relative to the top of the working tree or
+/// as absolute paths inside it. Only ignored paths are reported unless
+/// `verbose` is set, in which case every path is, along with whatever
+/// pattern matched it. A path given with a trailing `/` is checked as a
+/// directory whether or not there is one.
+///
+/// Only the `.gitignore` files on the way down to each path are read.
+pub fn check_ignore(
+    repo: &Repository,
+    paths: &[PathBuf],
+    verbose: bool,
+) -> GitResult<Vec<IgnoreMatch>> {
+    let work_dir = repo.require_work_dir()?;
+    let mut stack = IgnoreStack::new(repo)?;
+    // The directories pushed onto `stack`, outermost first.
+    let mut pushed: Vec<String> = Vec::new();
+    let mut matches = Vec::new();
+    for path in paths {
+        let relative = relative_path(work_dir, path)?;
+        let is_dir = path.to_string_lossy().ends_with('/') || work_dir.join(&relative).is_dir();
+
+        let mut dirs = vec![String::new()];
+        let mut end = 0;
+        while let Some(slash) = relative[end..].find('/') {
+            end += slash;
+            dirs.push(relative[..end].to_string());
+            end += 1;
+        }
+        let common = pushed.iter().zip(&dirs).take_while(|(a, b)| a == b).count();
+        for _ in common..pushed.len() {
+            stack.pop();
+        }
+        pushed.truncate(common);
+        for dir in &dirs[common..] {
+            stack.push(dir)?;
+            pushed.push(dir.clone());
+        }
+
+        let pattern = stack.matching(&relative, is_dir);
+        let ignored = pattern.is_some_and(|p| !p.negated);
+        if ignored || verbose {
+            matches.push(IgnoreMatch {
+                path: path.clone(),
+                ignored,
+                pattern: pattern.cloned(),
+            });
+        }
+    }
+    Ok(matches)
+}
+
+/// `matches` as `git check-ignore` prints them. In `verbose` mode each line
+/// starts with the pattern's `source:line:pattern` columns, left empty for
+/// a path nothing matched, and a tab.
+pub fn format_matches(matches: &[IgnoreMatch], verbose: bool) -> String {
+    let mut out = String::new();
+    for m in matches {
+        let path = quote_path(&m.path.to_string_lossy());
+        if !verbose {
+            out.push_str(&format!("{}\n", path));
+            continue;
+        }
+        match &m.pattern {
+            Some(p) => {
+                let source = p
+                    .source
+                    .as_ref()
+                    .map(|s| s.display().to_string())
+                    .unwrap_or_default();
+                out.push_str(&format!("{}:{}:{}\t{}\n", source, p.line, p.text, path));
+            }
+            None => out.push_str(&format!("::\t{}\n", path)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::error::GitError;
+    use crate::test_utils::{git_with_input, init_repo};
+
+    #[test]
+    fn reports_the_deciding_pattern() {
+        let (_dir, repo) = init_repo();
+        let work_dir = repo.work_dir().unwrap();
+        fs::write(
+            work_dir.join(".gitignore"),
+            "# build output\n*.o\n!keep.o\n",
+        )
+        .unwrap();
+        fs::create_dir_all(work_dir.join("sub")).unwrap();
+        fs::write(work_dir.join("sub/.gitignore"), "main.o\n").unwrap();
+
+        let paths: Vec<PathBuf> = ["a.o", "keep.o", "sub/main.o", "src/lib.rs"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let quiet = check_ignore(&repo, &paths, false).unwrap();
+        let ignored: Vec<&Path> = quiet.iter().map(|m| m.path.as_path()).collect();
+        assert_eq!(ignored, vec![Path::new("a.o"), Path::new("sub/main.o")]);
+
+        let verbose = check_ignore(&repo, &paths, true).unwrap();
+        // In the style of `git check-ignore -v -n`.
+        let lines: Vec<String> = verbose
+            .iter()
+            .map(|m| match &m.pattern {
+                Some(p) => format!(
+                    "{}:{}:{}\t{}",
+                    p.source.as_ref().unwrap().display(),
+                    p.line,
+                    p.text,
+                    m.path.display()
+                ),
+                None => format!("::\t{}", m.path.display()),
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ".gitignore:2:*.o\ta.o",
+                ".gitignore:3:!keep.o\tkeep.o",
+                "sub/.gitignore:1:main.o\tsub/main.o",
+                "::\tsrc/lib.rs",
+            ]
+        );
+        assert!(!verbose[1].ignored);
+    }
+
+    #[test]
+    fn inner_negations_override_outer_ignores_like_git() {
+        let (_dir, repo) = init_repo();
+        let work_dir = repo.work_dir().unwrap();
+        fs::write(work_dir.join(".gitignore"), "build/\n*.log\n").unwrap();
+        fs::create_dir_all(work_dir.join("sub/deeper")).unwrap();
+        fs::write(work_dir.join("sub/.gitignore"), "!keep.log\n").unwrap();
+        fs::write(work_dir.join("sub/deeper/.gitignore"), "keep.log\n").unwrap();
+
+        let given = [
+            "sub/keep.log",
+            "sub/a.log",
+            "sub/deeper/keep.log",
+            "build/",
+            "build",
+            "build/x",
+            "tab\t.log",
+            "src/lib.rs",
+        ];
+        let paths: Vec<PathBuf> = given.iter().map(PathBuf::from).collect();
+        let verbose = check_ignore(&repo, &paths, true).unwrap();
+        assert!(!verbose[0].ignored && verbose[1].ignored && verbose[2].ignored);
+        let out = format_matches(&verbose, true);
+        assert_eq!(
+            out.lines().take(4).collect::<Vec<_>>(),
+            [
+                "sub/.gitignore:1:!keep.log\tsub/keep.log",
+                ".gitignore:2:*.log\tsub/a.log",
+                "sub/deeper/.gitignore:1:keep.log\tsub/deeper/keep.log",
+                ".gitignore:1:build/\tbuild/",
+            ]
+        );
+        let input: String = given.iter().map(|p| format!("{}\0", p)).collect();
+        if let Some(expected) = git_with_input(
+            &repo,
+            &["check-ignore", "-v", "-n", "--stdin", "-z"],
+            &input,
+        ) {
+            // `-z` separates the columns with NULs and leaves paths unquoted.
+            let expected: Vec<&str> = expected.split('\0').collect();
+            let expected: Vec<String> = expected
+                .chunks(4)
+                .filter(|c| c.len() == 4)
+                .map(|c| {
+                    format!(
+                        "{}:{}:{}\t{}",
+                        c[0],
+                        c[1],
+                        c[2],
+                        crate::core::quote::quote_path(c[3])
+                    )
+                })
+                .collect();
+            assert_eq!(out.lines().collect::<Vec<_>>(), expected);
+        }
+
+        let quiet = check_ignore(&repo, &paths, false).unwrap();
+        assert_eq!(
+            format_matches(&quiet, false),
+            "sub/a.log\nsub/deeper/keep.log\nbuild/\nbuild/x\n\"tab\\t.log\"\n"
+        );
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_work_tree() {
+        let (_dir, repo) = init_repo();
+        let outside = vec![PathBuf::from("../elsewhere")];
+        assert!(matches!(
+            check_ignore(&repo, &outside, false),
+            Err(GitError::PathOutsideRepository(_))
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+//! `git checkout`: moving HEAD to a branch or commit and bringing the
+//! index and working tree along.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use crate::core::index::Index;
+use crate::core::oid::Oid;
+use crate::core::refs;
+use crate::core::tree::{self, FlatEntry, FlatTree};
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::repository::{Head, Repository};
+
+/// Options for [`checkout_branch`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckoutOptions {
+    /// Throw away local changes and overwrite untracked files in the way,
+    /// like `git checkout --force`, rather than refusing to switch.
+    pub force: bool,
+    /// Start `name` as a new branch with no history, like `--orphan`:
+    /// HEAD points at it unborn and the index and working tree are kept.
+    pub orphan: bool,
+}
+
+/// Switch to the branch `name`, or detach HEAD at the commit `name` names
+/// if there's no such branch. Paths that differ between HEAD and the
+/// target are brought up to date in the index and working tree; local
+/// changes to other paths are carried over.
+///
+/// Without `force`, local changes to a path the switch would update, or
+/// an untracked file where the target has a tracked one, stop it before
+/// anything is touched.
+pub fn checkout_branch(repo: &Repository, name: &str, options: &CheckoutOptions) -> GitResult<()> {
+    let full = format!("refs/heads/{}", name);
+    let message = format!("checkout: moving from {} to {}", head_name(repo)?, name);
+    if options.orphan {
+        refs::validate_name(&full, false)?;
+        if refs::read(repo, &full)?.is_some() {
+            return Err(GitError::RefExists(full));
+        }
+        return refs::update_symbolic(repo, "HEAD", &full, Some(&message));
+    }
+    if refs::read(repo, &full)?.is_none() {
+        let target = repo.odb().peel_to_commit(&repo.resolve_rev(name)?)?;
+        switch_to(repo, &target, options.force)?;
+        return refs::update_no_deref(repo, "HEAD", target, None, &message);
+    }
+    let target = refs::resolve(repo, &full)?;
+    switch_to(repo, &target, options.force)?;
+    refs::update_symbolic(repo, "HEAD", &full, Some(&message))
+}
+
+/// Check out the commit `rev` names with HEAD detached at it, like
+/// `git checkout --detach`. Local changes to paths that differ between
+/// HEAD and the target stop the checkout; other changes are carried over.
+pub fn checkout_detached(repo: &Repository, rev: &str) -> GitResult<Oid> {
+    let target = repo.odb().peel_to_commit(&repo.resolve_rev(rev)?)?;
+    let message = format!("checkout: moving from {} to {}", head_name(repo)?, rev);
+    switch_to(repo, &target, false)?;
+    refs::update_no_deref(repo, "HEAD", target, None, &message)?;
+    Ok(target)
+}
+
+/// What HEAD is on, as a checkout's reflog message names it.
+fn head_name(repo: &Repository) -> GitResult<String> {
+    Ok(match repo.head()? {
+        Head::Branch(name, _) | Head::Unborn(name) => refs::shorten(&name).to_string(),
+        Head::Detached(id) => id.to_string(),
+    })
+}
+
+/// Bring the index and working tree from HEAD's commit to `target`'s,
+/// leaving HEAD itself alone.
+fn switch_to(repo: &Repository, target: &Oid, force: bool) -> GitResult<()> {
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let ours = match repo.head_commit()? {
+        Some(id) => tree::flatten(odb, &odb.read_commit(&id)?.tree)?,
+        None => FlatTree::new(),
+    };
+    let theirs = tree::flatten(odb, &odb.read_commit(target)?.tree)?;
+    let mut index = repo.read_index()?;
+
+    if force {
+        // Everything goes back to the target, whatever was changed.
+        let staged = tree::from_index(&index);
+        for path in ours.keys().chain(staged.keys()) {
+            if !theirs.contains_key(path) {
+                worktree::remove_file(work_dir, path)?;
+            }
+        }
+        worktree::update(odb, work_dir, &FlatTree::new(), &theirs)?;
+        return repo.write_index(&worktree::index_from_tree(work_dir, &theirs)?);
+    }
+
+    check_overwrites(repo, &index, &ours, &theirs)?;
+    worktree::update(odb, work_dir, &ours, &theirs)?;
+    let paths: BTreeSet<&String> = ours.keys().chain(theirs.keys()).collect();
+    for path in paths {
+        match (ours.get(path), theirs.get(path)) {
+            (a, b) if a == b => {}
+            (_, Some(entry)) => {
+                index.add(worktree::stat_entry(work_dir, path, entry.oid, entry.mode)?)
+            }
+            (_, None) => {
+                index.remove(path);
+            }
+        }
+    }
+    repo.write_index(&index)
+}
+
+/// Refuse a switch from `ours` to `theirs` that would lose staged or
+/// unstaged changes, or an untracked file, at a path the switch updates.
+/// A path that already matches the target in both is fine either way.
+fn check_overwrites(
+    repo: &Repository,
+    index: &Index,
+    ours: &FlatTree,
+    theirs: &FlatTree,
+) -> GitResult<()> {
+    let work_dir = repo.require_work_dir()?;
+    let paths: BTreeSet<&String> = ours.keys().chain(theirs.keys()).collect();
+    let (mut dirty, mut untracked) = (Vec::new(), Vec::new());
+    for path in paths {
+        let (head, target) = (ours.get(path).copied(), theirs.get(path).copied());
+        if head == target {
+            continue;
+        }
+        let staged = index.get(path, 0).map(|e| FlatEntry {
+            mode: e.mode,
+            oid: e.oid,
+        });
+        let on_disk = worktree::hash_file(work_dir, path)?;
+        if staged == target && on_disk == target {
+            continue;
+        }
+        if head.is_none() && staged.is_none() {
+            if on_disk.is_some() {
+                untracked.push(PathBuf::from(path));
+            }
+        } else if staged != head || on_disk != head {
+            dirty.push(PathBuf::from(path));
+        }
+    }
+    if !dirty.is_empty() {
+        Err(GitError::LocalChanges(dirty))
+    } else if !untracked.is_empty() {
+        Err(GitError::UntrackedWouldBeOverwritten(untracked))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::commands::branch;
+    use crate::commands::commit::{commit, CommitOptions};
+    use crate::core::reflog;
+    use crate::core::refs::RefTarget;
+    use crate::test_utils::{checkout, git, init_repo, read_file, stage_file, write_commit};
+
+    #[test]
+    fn commits_on_a_detached_head() {
+        let (_dir, repo) = init_repo();
+        let first = write_commit(&repo, &[], &[("a.txt", "one\n")], "first");
+        let second = write_commit(&repo, &[first], &[("a.txt", "two\n")], "second");
+        checkout(&repo, "master", &second);
+
+        assert_eq!(checkout_detached(&repo, &first.to_string()).unwrap(), first);
+        assert_eq!(repo.head().unwrap(), Head::Detached(first));
+        assert_eq!(
+            refs::resolve_symbolic(&repo, "HEAD").unwrap(),
+            RefTarget::Direct(first)
+        );
+        assert_eq!(read_file(&repo, "a.txt"), "one\n");
+
+        stage_file(&repo, "b.txt", "b\n");
+        let id = commit(&repo, Some("on a detached head"), &CommitOptions::default()).unwrap();
+        let head_file = fs::read_to_string(repo.git_dir().join("HEAD")).unwrap();
+        assert_eq!(head_file, format!("{}\n", id));
+        assert_eq!(repo.odb().read_commit(&id).unwrap().parents, vec![first]);
+        // The branch stayed where it was.
+        assert_eq!(refs::resolve(&repo, "master").unwrap(), second);
+
+        let branches = branch::list(&repo).unwrap();
+        let expected = format!("(HEAD detached at {})", id.abbrev(7));
+        assert_eq!(branches[0].name, expected);
+        assert!(branches[0].is_current);
+        assert!(!branches[1].is_current);
+    }
+
+    #[test]
+    fn switches_branches_and_refuses_to_clobber_changes() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a\n"), ("b", "b\n")], "base");
+        let topic = write_commit(
+            &repo,
+            &[base],
+            &[("a", "topic\n"), ("dir/c", "c\n")],
+            "topic",
+        );
+        checkout(&repo, "topic", &topic);
+        checkout(&repo, "master", &base);
+        fs::write(repo.work_dir().unwrap().join("notes"), "kept\n").unwrap();
+
+        checkout_branch(&repo, "topic", &CheckoutOptions::default()).unwrap();
+        assert_eq!(
+            repo.head().unwrap(),
+            Head::Branch("refs/heads/topic".to_string(), Some(topic))
+        );
+        assert_eq!(read_file(&repo, "a"), "topic\n");
+        assert_eq!(read_file(&repo, "dir/c"), "c\n");
+        assert!(!repo.work_dir().unwrap().join("b").exists());
+        assert_eq!(read_file(&repo, "notes"), "kept\n");
+        let index = repo.read_index().unwrap();
+        assert_eq!(
+            tree::from_index(&index),
+            tree::flatten(repo.odb(), &repo.odb().read_commit(&topic).unwrap().tree).unwrap()
+        );
+        let log = reflog::read(&repo, "HEAD").unwrap();
+        let last = log.last().unwrap();
+        assert_eq!(last.message, "checkout: moving from master to topic");
+        assert_eq!((last.old, last.new), (Some(base), topic));
+        if let Some(out) = git(&repo, &["status", "--porcelain"]) {
+            assert_eq!(out, "?? notes\n");
+        }
+
+        // A change to a file the switch would rewrite stops it.
+        fs::write(repo.work_dir().unwrap().join("a"), "edited\n").unwrap();
+        assert!(matches!(
+            checkout_branch(&repo, "master", &CheckoutOptions::default()),
+            Err(GitError::LocalChanges(paths)) if paths == [PathBuf::from("a")]
+        ));
+        fs::write(repo.work_dir().unwrap().join("a"), "topic\n").unwrap();
+        // So does an untracked file where master has a tracked one.
+        fs::write(repo.work_dir().unwrap().join("b"), "untracked\n").unwrap();
+        assert!(matches!(
+            checkout_branch(&repo, "master", &CheckoutOptions::default()),
+            Err(GitError::UntrackedWouldBeOverwritten(paths)) if paths == [PathBuf::from("b")]
+        ));
+        assert_eq!(refs::follow(&repo, "HEAD").unwrap().0, "refs/heads/topic");
+
+        // Unless forced, which throws the changes away.
+        fs::write(repo.work_dir().unwrap().join("a"), "edited\n").unwrap();
+        let force = CheckoutOptions {
+            force: true,
+            ..CheckoutOptions::default()
+        };
+        checkout_branch(&repo, "master", &force).unwrap();
+        assert_eq!(read_file(&repo, "a"), "a\n");
+        assert_eq!(read_file(&repo, "b"), "b\n");
+        assert!(!repo.work_dir().unwrap().join("dir").exists());
+        if let Some(out) = git(&repo, &["status", "--porcelain"]) {
+            assert_eq!(out, "?? notes\n");
+        }
+    }
+
+    #[test]
+    fn detaches_at_commits_and_starts_orphan_branches() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a\n")], "base");
+        let next = write_commit(&repo, &[base], &[("a", "next\n")], "next");
+        checkout(&repo, "master", &next);
+
+        let hex = base.to_string();
+        checkout_branch(&repo, &hex, &CheckoutOptions::default()).unwrap();
+        assert_eq!(repo.head().unwrap(), Head::Detached(base));
+        assert_eq!(read_file(&repo, "a"), "a\n");
+        assert_eq!(
+            reflog::read(&repo, "HEAD").unwrap().last().unwrap().message,
+            format!("checkout: moving from master to {}", hex)
+        );
+
+        let orphan = CheckoutOptions {
+            orphan: true,
+            ..CheckoutOptions::default()
+        };
+        checkout_branch(&repo, "fresh", &orphan).unwrap();
+        assert_eq!(
+            repo.head().unwrap(),
+            Head::Unborn("refs/heads/fresh".to_string())
+        );
+        assert_eq!(read_file(&repo, "a"), "a\n");
+        assert!(matches!(
+            checkout_branch(&repo, "master", &orphan),
+            Err(GitError::RefExists(_))
+        ));
+
+        // On the unborn branch everything staged counts as a local change,
+        // as it does for git, unless it's what master has anyway.
+        assert!(matches!(
+            checkout_branch(&repo, "master", &CheckoutOptions::default()),
+            Err(GitError::LocalChanges(_))
+        ));
+        stage_file(&repo, "a", "next\n");
+        checkout_branch(&repo, "master", &CheckoutOptions::default()).unwrap();
+        assert_eq!(
+            repo.head().unwrap(),
+            Head::Branch("refs/heads/master".to_string(), Some(next))
+        );
+        assert_eq!(read_file(&repo, "a"), "next\n");
+    }
+
+    #[test]
+    fn head_reports_branches_and_unborn_branches() {
+        let (_dir, repo) = init_repo();
+        assert_eq!(
+            repo.head().unwrap(),
+            Head::Unborn("refs/heads/master".to_string())
+        );
+        let first = write_commit(&repo, &[], &[("a.txt", "one\n")], "first");
+        checkout(&repo, "master", &first);
+        assert_eq!(
+            repo.head().unwrap(),
+            Head::Branch("refs/heads/master".to_string(), Some(first))
+        );
+    }
+}
@@ -0,0 +1,466 @@
+//! `git cherry-pick`: apply the change one commit made on top of HEAD.
+//!
+//! The change is a three-way merge of HEAD's tree with the picked commit's,
+//! taking the picked commit's parent as the base. A clean merge is
+//! committed with the picked commit's author and message; a conflicted one
+//! is left in the index and working tree, with `CHERRY_PICK_HEAD` and
+//! `MERGE_MSG` recording it until [`cherry_pick_continue`] commits the
+//! resolution or [`cherry_pick_abort`] throws it away.
+//!
+//! [`revert`](super::revert) is the same merge the other way round, and
+//! shares the rest of the machinery here.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::commands::merge::{self, WorktreeMerge};
+use crate::commands::write_tree;
+use crate::core::message::{self, CleanupMode};
+use crate::core::object::{Commit, GitObject};
+use crate::core::odb::{LooseObjectWriter, ObjectDatabase};
+use crate::core::oid::Oid;
+use crate::core::signature::{self, Signature};
+use crate::core::tree::{self, FlatTree};
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::repository::{Repository, RepositoryState};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CherryPickOptions {
+    /// End the message with a `(cherry picked from commit …)` line, as
+    /// `-x` does.
+    pub record_origin: bool,
+    /// Only update the index and working tree, as `--no-commit` does.
+    /// Nothing is recorded to continue or abort.
+    pub no_commit: bool,
+    /// For a merge commit, which parent, counting from 1, to take its
+    /// change against, as `--mainline` does.
+    pub mainline: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CherryPickOutcome {
+    /// The change applied cleanly and was committed as this.
+    MadeCommit(Oid),
+    /// The change applied cleanly and is staged, with `no_commit`.
+    Staged,
+    /// HEAD already has the change, so there's nothing to commit.
+    Empty,
+    /// These paths could not be merged automatically. Their conflict stages
+    /// are in the index and the working tree files hold conflict markers.
+    Conflicts(Vec<PathBuf>),
+}
+
+/// Apply the change the commit `rev` names made on top of HEAD. A merge
+/// commit needs [`CherryPickOptions::mainline`] to say which parent the
+/// change is against.
+pub fn cherry_pick(
+    repo: &Repository,
+    rev: &str,
+    options: &CherryPickOptions,
+) -> GitResult<CherryPickOutcome> {
+    let odb = repo.odb();
+    let id = &odb.peel_to_commit(&repo.resolve_rev(rev)?)?;
+    let picked = odb.read_commit(id)?;
+    let base = match mainline_parent(id, &picked, options.mainline)? {
+        Some(parent) => tree::flatten(odb, &odb.read_commit(&parent)?.tree)?,
+        None => FlatTree::new(),
+    };
+    let mut message = picked.message.clone();
+    if options.record_origin {
+        let line = format!("{}{})", message::CHERRY_PICKED_PREFIX, id);
+        message = message::append_to_trailers(&message, &line);
+    }
+    let label = label(odb, id, &picked)?;
+    pick(
+        repo,
+        Pick {
+            action: Action::CherryPick,
+            id: *id,
+            base,
+            theirs: tree::flatten(odb, &picked.tree)?,
+            their_label: label,
+            message,
+            author: picked.author,
+            no_commit: options.no_commit,
+        },
+    )
+}
+
+/// Commit the resolved index, once every conflict of a cherry-pick is
+/// staged, with the picked commit's author and the message in `MERGE_MSG`
+/// cleaned of its comments.
+pub fn cherry_pick_continue(repo: &Repository) -> GitResult<Oid> {
+    continue_pick(repo, Action::CherryPick)
+}
+
+/// Give up on a conflicted cherry-pick: put the index and working tree
+/// back as HEAD has them and forget the saved state. Changes to paths the
+/// cherry-pick didn't touch are kept.
+pub fn cherry_pick_abort(repo: &Repository) -> GitResult<()> {
+    abort_pick(repo, Action::CherryPick)
+}
+
+/// Forget about an in-progress cherry-pick or revert, once it's committed
+/// or aborted.
+pub fn clear_state(repo: &Repository) -> GitResult<()> {
+    for name in &["CHERRY_PICK_HEAD", "REVERT_HEAD", "MERGE_MSG"] {
+        match fs::remove_file(repo.git_dir().join(name)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Which of the operations sharing this machinery is under way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Action {
+    CherryPick,
+    Revert,
+}
+
+impl Action {
+    fn state(self) -> RepositoryState {
+        match self {
+            Action::CherryPick => RepositoryState::CherryPick,
+            Action::Revert => RepositoryState::Revert,
+        }
+    }
+
+    /// The file naming the commit while the operation is stopped.
+    fn head_file(self) -> &'static str {
+        match self {
+            Action::CherryPick => "CHERRY_PICK_HEAD",
+            Action::Revert => "REVERT_HEAD",
+        }
+    }
+}
+
+/// A change to merge into HEAD and commit.
+pub(crate) struct Pick {
+    pub action: Action,
+    /// The commit the change comes from.
+    pub id: Oid,
+    pub base: FlatTree,
+    pub theirs: FlatTree,
+    pub their_label: String,
+    pub message: String,
+    pub author: Signature,
+    pub no_commit: bool,
+}
+
+/// Merge `pick` into HEAD, the index and working tree, and commit it
+/// when that's clean, or record it to continue when not.
+pub(crate) fn pick(repo: &Repository, pick: Pick) -> GitResult<CherryPickOutcome> {
+    match repo.state() {
+        RepositoryState::Clean => {}
+        state => return Err(GitError::OperationInProgress(state)),
+    }
+    let odb = repo.odb();
+    let head = repo
+        .head_commit()?
+        .ok_or_else(|| GitError::UnknownRevision("HEAD".to_string()))?;
+    let ours = tree::flatten(odb, &odb.read_commit(&head)?.tree)?;
+    let mut message = pick.message;
+    match merge::merge_into_worktree(repo, &pick.base, &ours, &pick.theirs, &pick.their_label)? {
+        WorktreeMerge::Clean(merged) if merged == ours => Ok(CherryPickOutcome::Empty),
+        WorktreeMerge::Clean(_) if pick.no_commit => Ok(CherryPickOutcome::Staged),
+        WorktreeMerge::Clean(merged) => {
+            let tree = tree::build(&mut LooseObjectWriter::new(odb), &merged)?;
+            let commit = commit_pick(repo, pick.action, head, tree, pick.author, message)?;
+            Ok(CherryPickOutcome::MadeCommit(commit))
+        }
+        WorktreeMerge::Conflicts(conflicts) => {
+            merge::append_conflicts(&mut message, &conflicts);
+            fs::write(repo.git_dir().join("MERGE_MSG"), message)?;
+            if !pick.no_commit {
+                fs::write(
+                    repo.git_dir().join(pick.action.head_file()),
+                    format!("{}\n", pick.id),
+                )?;
+            }
+            Ok(CherryPickOutcome::Conflicts(conflicts))
+        }
+    }
+}
+
+/// Commit the resolved index with the message in `MERGE_MSG`. A
+/// cherry-pick keeps the picked commit's author.
+pub(crate) fn continue_pick(repo: &Repository, action: Action) -> GitResult<Oid> {
+    let picked = repo.odb().read_commit(&pick_head(repo, action)?)?;
+    let tree =
+        write_tree::write_index_tree(&repo.read_index()?, &mut LooseObjectWriter::new(repo.odb()))?;
+    let message = match fs::read_to_string(repo.git_dir().join("MERGE_MSG")) {
+        Ok(message) => message,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => picked.message.clone(),
+        Err(err) => return Err(err.into()),
+    };
+    let comment_char = message::comment_char(&repo.config()?);
+    let message = message::cleanup_with(&message, CleanupMode::Strip, comment_char);
+    if message.is_empty() {
+        return Err(GitError::EmptyCommitMessage);
+    }
+    let author = match action {
+        Action::CherryPick => picked.author,
+        Action::Revert => signature::default_signatures(repo)?.0,
+    };
+    let head = repo
+        .head_commit()?
+        .ok_or_else(|| GitError::UnknownRevision("HEAD".to_string()))?;
+    let commit = commit_pick(repo, action, head, tree, author, message)?;
+    clear_state(repo)?;
+    Ok(commit)
+}
+
+/// Put the index and working tree back as HEAD has them, keeping changes
+/// to paths the operation didn't touch, and forget the saved state.
+pub(crate) fn abort_pick(repo: &Repository, action: Action) -> GitResult<()> {
+    pick_head(repo, action)?;
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let head = match repo.head_commit()? {
+        Some(head) => tree::flatten(odb, &odb.read_commit(&head)?.tree)?,
+        None => FlatTree::new(),
+    };
+    let index = repo.read_index()?;
+    // Conflicted paths are only in the index at stages 1-3, so they're left
+    // out of `from` and written afresh, or removed if HEAD doesn't have them.
+    let from = tree::from_index(&index);
+    for conflict in index.conflicts() {
+        if !head.contains_key(&conflict.path) {
+            worktree::remove_file(work_dir, &conflict.path)?;
+        }
+    }
+    worktree::update(odb, work_dir, &from, &head)?;
+    repo.write_index(&worktree::index_from_tree(work_dir, &head)?)?;
+    clear_state(repo)
+}
+
+/// The parent of the commit `id` its change is taken against: its only
+/// one, the `mainline`th of a merge, or `None` for a root commit.
+pub(crate) fn mainline_parent(
+    id: &Oid,
+    commit: &Commit,
+    mainline: Option<usize>,
+) -> GitResult<Option<Oid>> {
+    match (commit.parents.as_slice(), mainline) {
+        ([], None) => Ok(None),
+        ([parent], None) => Ok(Some(*parent)),
+        (_, None) => Err(GitError::MainlineRequired(*id)),
+        (parents, Some(n)) => match n.checked_sub(1).and_then(|i| parents.get(i)) {
+            Some(parent) => Ok(Some(*parent)),
+            None => Err(GitError::UnknownRevision(format!("{}^{}", id, n))),
+        },
+    }
+}
+
+/// How conflict markers name the commit `id`: its abbreviation and
+/// subject.
+pub(crate) fn label(odb: &ObjectDatabase, id: &Oid, commit: &Commit) -> GitResult<String> {
+    Ok(format!("{} ({})", odb.abbreviate(id, 7)?, commit.summary()))
+}
+
+/// The commit being picked or reverted, from `action`'s head file.
+fn pick_head(repo: &Repository, action: Action) -> GitResult<Oid> {
+    match fs::read_to_string(repo.git_dir().join(action.head_file())) {
+        Ok(head) => head.lines().next().unwrap_or("").trim().parse::<Oid>(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            Err(GitError::NoOperationInProgress(action.state()))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Commit `tree` on top of `head` as `author` did, and move HEAD to it.
+fn commit_pick(
+    repo: &Repository,
+    action: Action,
+    head: Oid,
+    tree: Oid,
+    author: Signature,
+    message: String,
+) -> GitResult<Oid> {
+    let (_, committer) = signature::default_signatures(repo)?;
+    let commit = Commit {
+        tree,
+        parents: vec![head],
+        author,
+        committer,
+        extra_headers: Vec::new(),
+        message,
+    };
+    let reflog_msg = format!("{}: {}", action.state().as_str(), commit.summary());
+    let id = repo.odb().write(&GitObject::Commit(commit))?;
+    repo.set_head_commit(&id, &reflog_msg)?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{checkout, init_repo, read_file, set_ref, stage_file, write_commit};
+
+    /// A history where `feature` changed `a.txt` and added `b.txt` on the
+    /// side, by another author, and master is checked out: `(master,
+    /// feature)`.
+    fn fork(repo: &Repository, ours: &str) -> (Oid, Oid) {
+        let base = write_commit(repo, &[], &[("a.txt", "a\n"), ("c.txt", "c\n")], "base");
+        let master = write_commit(
+            repo,
+            &[base],
+            &[("a.txt", "a\n"), ("c.txt", ours)],
+            "change c",
+        );
+        let side = write_commit(
+            repo,
+            &[base],
+            &[("a.txt", "changed\n"), ("b.txt", "b\n"), ("c.txt", "c\n")],
+            "change a\n\nwith a body",
+        );
+        let odb = repo.odb();
+        let mut commit = odb.read_commit(&side).unwrap();
+        commit.author = Signature::new("O Ther", "other@example.com", 1_600_000_000, 120);
+        let feature = odb.write(&GitObject::Commit(commit)).unwrap();
+        checkout(repo, "master", &master);
+        (master, feature)
+    }
+
+    #[test]
+    fn picks_a_clean_change_onto_head() {
+        let (_dir, repo) = init_repo();
+        let (master, feature) = fork(&repo, "ours\n");
+        let options = CherryPickOptions {
+            record_origin: true,
+            ..Default::default()
+        };
+        let id = match cherry_pick(&repo, &feature.to_string(), &options).unwrap() {
+            CherryPickOutcome::MadeCommit(id) => id,
+            other => panic!("unexpected outcome {:?}", other),
+        };
+        let commit = repo.odb().read_commit(&id).unwrap();
+        assert_eq!(commit.parents, vec![master]);
+        assert_eq!(commit.author.name, "O Ther");
+        assert_eq!(
+            commit.message,
+            format!(
+                "change a\n\nwith a body\n\n(cherry picked from commit {})\n",
+                feature
+            )
+        );
+        assert_eq!(repo.head_commit().unwrap(), Some(id));
+        assert_eq!(read_file(&repo, "a.txt"), "changed\n");
+        assert_eq!(read_file(&repo, "b.txt"), "b\n");
+        assert_eq!(read_file(&repo, "c.txt"), "ours\n");
+        assert_eq!(repo.state(), RepositoryState::Clean);
+
+        // Without committing, the same change is left staged.
+        let (_dir, repo) = init_repo();
+        let (master, feature) = fork(&repo, "ours\n");
+        let options = CherryPickOptions {
+            no_commit: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            cherry_pick(&repo, &feature.to_string(), &options).unwrap(),
+            CherryPickOutcome::Staged
+        );
+        assert_eq!(repo.head_commit().unwrap(), Some(master));
+        assert!(repo.read_index().unwrap().get("b.txt", 0).is_some());
+        assert_eq!(read_file(&repo, "a.txt"), "changed\n");
+    }
+
+    #[test]
+    fn reports_a_change_head_has_as_empty() {
+        let (_dir, repo) = init_repo();
+        let (_, feature) = fork(&repo, "ours\n");
+        set_ref(&repo, "refs/heads/feature", &feature);
+        let picked = match cherry_pick(&repo, "feature", &CherryPickOptions::default()).unwrap() {
+            CherryPickOutcome::MadeCommit(id) => id,
+            other => panic!("unexpected outcome {:?}", other),
+        };
+        assert_eq!(
+            cherry_pick(&repo, "feature", &CherryPickOptions::default()).unwrap(),
+            CherryPickOutcome::Empty
+        );
+        assert_eq!(repo.head_commit().unwrap(), Some(picked));
+        assert_eq!(repo.state(), RepositoryState::Clean);
+    }
+
+    #[test]
+    fn conflicts_are_left_to_continue() {
+        let (_dir, repo) = init_repo();
+        let (master, feature) = fork(&repo, "c\n");
+        // Make master's `a.txt` clash with the picked change.
+        let head = write_commit(
+            &repo,
+            &[master],
+            &[("a.txt", "ours\n"), ("c.txt", "c\n")],
+            "clash",
+        );
+        checkout(&repo, "master", &head);
+
+        let outcome =
+            cherry_pick(&repo, &feature.to_string(), &CherryPickOptions::default()).unwrap();
+        assert_eq!(
+            outcome,
+            CherryPickOutcome::Conflicts(vec![PathBuf::from("a.txt")])
+        );
+        assert_eq!(repo.state(), RepositoryState::CherryPick);
+        assert_eq!(repo.head_commit().unwrap(), Some(head));
+        let label = format!("{} (change a)", repo.odb().abbreviate(&feature, 7).unwrap());
+        assert_eq!(
+            read_file(&repo, "a.txt"),
+            format!("<<<<<<< HEAD\nours\n=======\nchanged\n>>>>>>> {}\n", label)
+        );
+        assert_eq!(read_file(&repo, "b.txt"), "b\n");
+        assert!(matches!(
+            cherry_pick_continue(&repo),
+            Err(GitError::UnresolvedConflicts(_))
+        ));
+        assert!(matches!(
+            cherry_pick(&repo, &feature.to_string(), &CherryPickOptions::default()),
+            Err(GitError::OperationInProgress(RepositoryState::CherryPick))
+        ));
+
+        stage_file(&repo, "a.txt", "resolved\n");
+        let id = cherry_pick_continue(&repo).unwrap();
+        let commit = repo.odb().read_commit(&id).unwrap();
+        assert_eq!(commit.parents, vec![head]);
+        assert_eq!(commit.author.name, "O Ther");
+        assert_eq!(commit.message, "change a\n\nwith a body\n");
+        assert_eq!(repo.state(), RepositoryState::Clean);
+        assert!(!repo.git_dir().join("MERGE_MSG").exists());
+    }
+
+    #[test]
+    fn abort_restores_head() {
+        let (_dir, repo) = init_repo();
+        let (master, feature) = fork(&repo, "c\n");
+        let head = write_commit(
+            &repo,
+            &[master],
+            &[("a.txt", "ours\n"), ("c.txt", "c\n")],
+            "clash",
+        );
+        checkout(&repo, "master", &head);
+        fs::write(repo.work_dir().unwrap().join("c.txt"), "local\n").unwrap();
+
+        let outcome =
+            cherry_pick(&repo, &feature.to_string(), &CherryPickOptions::default()).unwrap();
+        assert!(matches!(outcome, CherryPickOutcome::Conflicts(_)));
+        cherry_pick_abort(&repo).unwrap();
+        assert_eq!(repo.state(), RepositoryState::Clean);
+        assert_eq!(read_file(&repo, "a.txt"), "ours\n");
+        assert!(!repo.work_dir().unwrap().join("b.txt").exists());
+        assert_eq!(read_file(&repo, "c.txt"), "local\n");
+        let index = repo.read_index().unwrap();
+        assert!(!index.has_conflicts());
+        assert!(index.get("b.txt", 0).is_none());
+        assert!(matches!(
+            cherry_pick_abort(&repo),
+            Err(GitError::NoOperationInProgress(RepositoryState::CherryPick))
+        ));
+    }
+}
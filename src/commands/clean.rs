@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::ignore::IgnoreStack;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CleanOptions {
+    /// Only report what would be removed.
+    pub dry_run: bool,
+    /// Remove untracked directories too, as `-d` does. Without it their
+    /// contents are left alone entirely.
+    pub directories: bool,
+    /// Actually delete things. Without it nothing is removed, whatever
+    /// `dry_run` says, like git's default `clean.requireForce`.
+    pub force: bool,
+    /// Remove ignored files as well, as `-x` does.
+    pub ignored: bool,
+}
+
+/// `git clean`: remove the untracked files in the working tree, and with
+/// `directories` the untracked directories, leaving ignored files alone.
+/// Returns the paths removed, or that would be without `force` or with
+/// `dry_run`, with a trailing `/` on directories removed as a whole.
+pub fn clean(
+    repo: &Repository,
+    dry_run: bool,
+    directories: bool,
+    force: bool,
+) -> GitResult<Vec<PathBuf>> {
+    clean_with(
+        repo,
+        &CleanOptions {
+            dry_run,
+            directories,
+            force,
+            ignored: false,
+        },
+    )
+}
+
+/// [`clean`] with every option, including removing ignored files.
+pub fn clean_with(repo: &Repository, options: &CleanOptions) -> GitResult<Vec<PathBuf>> {
+    let work_dir = repo.require_work_dir()?;
+    let index = repo.read_index()?;
+    let mut cleaner = Cleaner {
+        work_dir,
+        rules: IgnoreStack::new(repo)?,
+        tracked: index.entries().iter().map(|e| e.path.as_str()).collect(),
+        tracked_dirs: index.directories(),
+        options,
+    };
+    let (removable, _) = cleaner.scan("")?;
+
+    if options.force && !options.dry_run {
+        for path in &removable {
+            let full = work_dir.join(path);
+            if path.ends_with('/') {
+                fs::remove_dir_all(full)?;
+            } else {
+                fs::remove_file(full)?;
+            }
+        }
+    }
+    Ok(removable.into_iter().map(PathBuf::from).collect())
+}
+
+struct Cleaner<'a> {
+    work_dir: &'a Path,
+    rules: IgnoreStack,
+    tracked: HashSet<&'a str>,
+    /// See [`Index::directories`](crate::core::index::Index::directories).
+    tracked_dirs: HashSet<&'a str>,
+    options: &'a CleanOptions,
+}
+
+impl Cleaner<'_> {
+    /// What to remove under `dir`, in order, and whether that's everything
+    /// in it.
+    fn scan(&mut self, dir: &str) -> GitResult<(Vec<String>, bool)> {
+        self.rules.push(dir)?;
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.work_dir.join(dir))? {
+            let entry = entry?;
+            names.push((
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.file_type()?,
+            ));
+        }
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut removable = Vec::new();
+        let mut everything = true;
+        for (name, file_type) in names {
+            let path = if dir.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            if name == ".git" {
+                everything = false;
+                continue;
+            }
+            let is_dir = file_type.is_dir();
+            let ignored = !self.options.ignored && self.rules.is_ignored(&path, is_dir);
+            if !is_dir {
+                if self.tracked.contains(path.as_str()) || ignored {
+                    everything = false;
+                } else {
+                    removable.push(path);
+                }
+                continue;
+            }
+
+            let has_tracked = self.tracked_dirs.contains(path.as_str());
+            // Nested repositories are never cleaned, and untracked
+            // directories only when asked.
+            let nested_repo = !has_tracked && self.work_dir.join(&path).join(".git").exists();
+            if ignored || nested_repo || (!has_tracked && !self.options.directories) {
+                everything = false;
+                continue;
+            }
+            let (inside, all) = self.scan(&path)?;
+            if all && !has_tracked {
+                removable.push(format!("{}/", path));
+            } else {
+                everything = false;
+                removable.extend(inside);
+            }
+        }
+        self.rules.pop();
+        Ok((removable, everything))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{git, init_repo, read_file, stage_file};
+
+    fn paths(list: &[&str]) -> Vec<PathBuf> {
+        list.iter().map(PathBuf::from).collect()
+    }
+
+    /// A tracked file, untracked ones at the top and in a directory, and an
+    /// ignored one.
+    fn fixture(repo: &Repository) -> &Path {
+        let work_dir = repo.work_dir().unwrap();
+        stage_file(repo, "tracked", "keep");
+        stage_file(repo, ".gitignore", "*.log\n");
+        stage_file(repo, "src/lib.rs", "lib");
+        fs::write(work_dir.join("untracked"), "x").unwrap();
+        fs::write(work_dir.join("src/scratch.rs"), "x").unwrap();
+        fs::create_dir_all(work_dir.join("new/deeper")).unwrap();
+        fs::write(work_dir.join("new/deeper/file"), "x").unwrap();
+        fs::write(work_dir.join("build.log"), "x").unwrap();
+        work_dir
+    }
+
+    #[test]
+    fn lists_untracked_files_without_removing_them() {
+        let (_dir, repo) = init_repo();
+        let work_dir = fixture(&repo);
+
+        let listed = clean(&repo, true, false, true).unwrap();
+        assert_eq!(listed, paths(&["src/scratch.rs", "untracked"]));
+        // Without force nothing goes, even outside a dry run.
+        assert_eq!(
+            clean(&repo, false, true, false).unwrap(),
+            paths(&["new/", "src/scratch.rs", "untracked"])
+        );
+        assert!(work_dir.join("untracked").exists());
+        assert!(work_dir.join("new/deeper/file").exists());
+
+        let work_tree = work_dir.to_str().unwrap();
+        if let Some(output) = git(&repo, &["--work-tree", work_tree, "clean", "-n", "-d"]) {
+            assert_eq!(
+                output,
+                "Would remove new/\nWould remove src/scratch.rs\nWould remove untracked\n"
+            );
+        }
+    }
+
+    #[test]
+    fn forced_clean_keeps_tracked_and_ignored_files() {
+        let (_dir, repo) = init_repo();
+        let work_dir = fixture(&repo);
+
+        let removed = clean(&repo, false, true, true).unwrap();
+        assert_eq!(removed, paths(&["new/", "src/scratch.rs", "untracked"]));
+        assert!(!work_dir.join("untracked").exists());
+        assert!(!work_dir.join("new").exists());
+        assert!(!work_dir.join("src/scratch.rs").exists());
+        assert_eq!(read_file(&repo, "tracked"), "keep");
+        assert_eq!(read_file(&repo, "src/lib.rs"), "lib");
+        assert!(work_dir.join("build.log").exists());
+        assert!(repo.git_dir().join("HEAD").exists());
+
+        let options = CleanOptions {
+            force: true,
+            ignored: true,
+            ..CleanOptions::default()
+        };
+        assert_eq!(clean_with(&repo, &options).unwrap(), paths(&["build.log"]));
+        assert!(!work_dir.join("build.log").exists());
+    }
+
+    #[test]
+    fn reads_each_directorys_gitignore() {
+        let (_dir, repo) = init_repo();
+        let work_dir = fixture(&repo);
+        stage_file(&repo, "src/.gitignore", "*.tmp\n!keep.log\n");
+        fs::write(work_dir.join("src/scratch.tmp"), "x").unwrap();
+        fs::write(work_dir.join("src/keep.log"), "x").unwrap();
+        fs::write(work_dir.join("top.tmp"), "x").unwrap();
+
+        assert_eq!(
+            clean(&repo, true, false, true).unwrap(),
+            paths(&["src/keep.log", "src/scratch.rs", "top.tmp", "untracked"])
+        );
+        let work_tree = work_dir.to_str().unwrap();
+        if let Some(output) = git(&repo, &["--work-tree", work_tree, "clean", "-n"]) {
+            assert_eq!(
+                output,
+                "Would remove src/keep.log\nWould remove src/scratch.rs\n\
+                 Would remove top.tmp\nWould remove untracked\n"
+            );
+        }
+    }
+}
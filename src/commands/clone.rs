@@ -0,0 +1,168 @@
+//! `git clone`, from a repository on the same filesystem.
+//!
+//! The new repository gets every object the source's refs reach, its
+//! branches as `refs/remotes/origin/*` and its tags as they are, an
+//! `origin` remote pointing back at it, and the branch the source's HEAD
+//! is on checked out and tracking its remote counterpart.
+
+use std::fs;
+use std::path::Path;
+
+use crate::commands::branch;
+use crate::commands::remote::add_remote;
+use crate::core::refs::{self, Transaction};
+use crate::core::tree::{self, FlatTree};
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::remote::local;
+use crate::repository::Repository;
+
+/// Clone the repository at `src` into a new one with a working tree at
+/// `dst`, which must not exist yet or be empty. A source with a detached
+/// HEAD leaves the clone detached at the same commit; one with no commits
+/// leaves it on the same unborn branch.
+pub fn clone_local(src: &Path, dst: &Path) -> GitResult<Repository> {
+    let source = Repository::open(src)?;
+    if fs::read_dir(dst).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(GitError::WouldOverwrite(dst.to_path_buf()));
+    }
+    let url = fs::canonicalize(src)?.display().to_string();
+    let repo = Repository::init(dst)?;
+    add_remote(&repo, "origin", &url)?;
+
+    let advertised = local::advertise(&source)?;
+    let wants: Vec<_> = advertised.iter().map(|r| r.oid).collect();
+    local::copy_objects(&source, &repo, &wants)?;
+    let message = format!("clone: from {}", url);
+    let mut transaction = Transaction::new(&repo);
+    for advertised in &advertised {
+        if let Some(name) = advertised.name.strip_prefix("refs/heads/") {
+            transaction.create(&format!("refs/remotes/origin/{}", name), advertised.oid);
+        } else if advertised.name.starts_with("refs/tags/") {
+            transaction.create(&advertised.name, advertised.oid);
+        }
+    }
+    transaction.commit(&message)?;
+
+    let head = advertised.iter().find(|r| r.name == "HEAD");
+    let target = match head {
+        Some(head) => head.oid,
+        None => {
+            // Nothing to check out; stay on the source's unborn branch.
+            if let Some(refs::RefTarget::Symbolic(name)) = refs::read(&source, "HEAD")? {
+                refs::update_symbolic(&repo, "HEAD", &name, None)?;
+            }
+            return Ok(repo);
+        }
+    };
+    match head.and_then(|head| head.symref_target.as_deref()) {
+        Some(full) if full.starts_with("refs/heads/") => {
+            let name = &full["refs/heads/".len()..];
+            refs::update_symbolic(
+                &repo,
+                "refs/remotes/origin/HEAD",
+                &format!("refs/remotes/origin/{}", name),
+                None,
+            )?;
+            refs::update(&repo, full, target, Some(None), &message)?;
+            refs::update_symbolic(&repo, "HEAD", full, None)?;
+            branch::set_upstream(&repo, name, &format!("origin/{}", name))?;
+        }
+        _ => refs::update_no_deref(&repo, "HEAD", target, None, &message)?,
+    }
+
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let files = tree::flatten(odb, &odb.read_commit(&target)?.tree)?;
+    worktree::update(odb, work_dir, &FlatTree::new(), &files)?;
+    repo.write_index(&worktree::index_from_tree(work_dir, &files)?)?;
+    Ok(repo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config::config_get;
+    use crate::commands::tag;
+    use crate::core::refs::RefTarget;
+    use crate::repository::Head;
+    use crate::test_utils::{checkout, git, init_repo, read_file, write_commit};
+
+    #[test]
+    fn clones_refs_objects_and_the_checked_out_branch() {
+        let (src_dir, src) = init_repo();
+        let base = write_commit(&src, &[], &[("a", "a\n")], "base");
+        let main = write_commit(&src, &[base], &[("a", "a\n"), ("dir/b", "b\n")], "main");
+        checkout(&src, "master", &base);
+        checkout(&src, "main", &main);
+        tag::create_lightweight(&src, "v1", "master", false).unwrap();
+
+        let dst_dir = tempfile::tempdir().unwrap();
+        let dst = dst_dir.path().join("clone");
+        let repo = clone_local(src_dir.path(), &dst).unwrap();
+        assert_eq!(
+            repo.head().unwrap(),
+            Head::Branch("refs/heads/main".to_string(), Some(main))
+        );
+        assert_eq!(read_file(&repo, "a"), "a\n");
+        assert_eq!(read_file(&repo, "dir/b"), "b\n");
+        let names: Vec<String> = refs::list(&repo)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "refs/heads/main",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/main",
+                "refs/remotes/origin/master",
+                "refs/tags/v1",
+            ]
+        );
+        assert_eq!(
+            refs::read(&repo, "refs/remotes/origin/HEAD").unwrap(),
+            Some(RefTarget::Symbolic("refs/remotes/origin/main".to_string()))
+        );
+        let url = fs::canonicalize(src_dir.path()).unwrap();
+        assert_eq!(
+            config_get(&repo, "remote.origin.url").unwrap().as_deref(),
+            url.to_str()
+        );
+        assert_eq!(
+            branch::upstream_of(&repo, "main").unwrap().as_deref(),
+            Some("refs/remotes/origin/main")
+        );
+        if let Some(out) = git(&repo, &["status", "--short", "--branch"]) {
+            assert_eq!(out, "## main...origin/main\n");
+        }
+        if let Some(out) = git(&repo, &["fsck"]) {
+            assert_eq!(out, "");
+        }
+
+        // Nothing is cloned over what's already there.
+        assert!(matches!(
+            clone_local(src_dir.path(), &dst),
+            Err(GitError::WouldOverwrite(_))
+        ));
+    }
+
+    #[test]
+    fn follows_a_detached_or_unborn_head() {
+        let (src_dir, src) = init_repo();
+        let dst_dir = tempfile::tempdir().unwrap();
+        let unborn = clone_local(src_dir.path(), &dst_dir.path().join("unborn")).unwrap();
+        assert_eq!(
+            unborn.head().unwrap(),
+            Head::Unborn("refs/heads/master".to_string())
+        );
+
+        let base = write_commit(&src, &[], &[("a", "a\n")], "base");
+        checkout(&src, "master", &base);
+        refs::update_no_deref(&src, "HEAD", base, None, "detach").unwrap();
+        let detached = clone_local(src_dir.path(), &dst_dir.path().join("detached")).unwrap();
+        assert_eq!(detached.head().unwrap(), Head::Detached(base));
+        assert_eq!(read_file(&detached, "a"), "a\n");
+    }
+}
@@ -0,0 +1,441 @@
+use crate::commands::{cherry_pick, merge, write_tree};
+use crate::core::message::{self, CleanupMode};
+use crate::core::object::{Commit, GitObject};
+use crate::core::odb::{LooseObjectWriter, NullObjectWriter, ObjectWriter};
+use crate::core::oid::Oid;
+use crate::core::refs;
+use crate::core::signature;
+use crate::core::signing;
+use crate::core::tree;
+use crate::error::{GitError, GitResult};
+use crate::repository::{Repository, RepositoryState};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommitOptions {
+    /// Commit even when the tree is the same as the parent's, as
+    /// `--allow-empty` does.
+    pub allow_empty: bool,
+    /// Replace HEAD's commit rather than adding to it, as `--amend` does:
+    /// the new commit keeps its parents, author and, unless another is
+    /// given, message.
+    pub amend: bool,
+    /// When amending, take the author from the current identity too, as
+    /// `--reset-author` does.
+    pub reset_author: bool,
+    /// How a given message is cleaned up before it's stored, with
+    /// comments starting at `core.commentChar`.
+    pub cleanup: CleanupMode,
+    /// End the message with a `Signed-off-by:` trailer for the committer,
+    /// as `--signoff` does, unless its last trailer already is one.
+    pub signoff: bool,
+    /// Sign the commit, as `--gpg-sign` does; `commit.gpgSign` turns this
+    /// on for every commit. See [`signing`] for the program and key used.
+    pub sign: bool,
+}
+
+/// The tree and commit [`commit_with`] wrote, or for a dry run would
+/// have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitPreview {
+    pub tree: Oid,
+    pub commit: Oid,
+}
+
+/// Commit the staged contents of the index and advance HEAD, or the branch
+/// it points at. When a merge is in progress the commit gets `MERGE_HEAD`
+/// as a second parent and concludes the merge; otherwise committing the
+/// tree the parent already has is refused without `allow_empty`. A
+/// conflicted cherry-pick or revert is concluded too.
+///
+/// `message` may only be left out when amending, and must have something
+/// left after [`CommitOptions::cleanup`]. The author and committer
+/// are [`signature::default_signatures`]. HEAD only moves if nothing else
+/// moved it meanwhile.
+pub fn commit(repo: &Repository, message: Option<&str>, options: &CommitOptions) -> GitResult<Oid> {
+    let mut writer = LooseObjectWriter::new(repo.odb());
+    commit_with(repo, message, options, &mut writer).map(|preview| preview.commit)
+}
+
+/// Work out the tree and commit [`commit`] would create, without writing
+/// any objects or moving HEAD.
+pub fn commit_dry_run(
+    repo: &Repository,
+    message: Option<&str>,
+    options: &CommitOptions,
+) -> GitResult<CommitPreview> {
+    commit_with(repo, message, options, &mut NullObjectWriter::default())
+}
+
+/// [`commit`], handing the new objects to `writer`. HEAD and any merge or
+/// cherry-pick state are only updated when the writer
+/// [stores](ObjectWriter::stores) them, so a [`NullObjectWriter`] makes
+/// this a dry run.
+pub fn commit_with(
+    repo: &Repository,
+    message: Option<&str>,
+    options: &CommitOptions,
+    writer: &mut dyn ObjectWriter,
+) -> GitResult<CommitPreview> {
+    let merge_state = merge::merge_state(repo)?;
+    let (preview, reflog_msg, head) = write_commit(repo, message, options, writer)?;
+    if !writer.stores() {
+        return Ok(preview);
+    }
+    refs::update(repo, "HEAD", preview.commit, Some(head), &reflog_msg)?;
+    if merge_state.is_some() {
+        merge::clear_merge_state(repo)?;
+    }
+    if let RepositoryState::CherryPick | RepositoryState::Revert = repo.state() {
+        cherry_pick::clear_state(repo)?;
+    }
+    Ok(preview)
+}
+
+/// Build the commit for the index and hand its objects to `writer`,
+/// returning them along with the message for HEAD's reflog and the commit
+/// HEAD was at.
+fn write_commit(
+    repo: &Repository,
+    message: Option<&str>,
+    options: &CommitOptions,
+    writer: &mut dyn ObjectWriter,
+) -> GitResult<(CommitPreview, String, Option<Oid>)> {
+    // Only staged content is committed; the tree comes from the index
+    // alone, which mustn't be conflicted.
+    let tree = write_tree::write_index_tree(&repo.read_index()?, writer)?;
+    let odb = repo.odb();
+    let head = repo.head_commit()?;
+    let config = repo.config()?;
+
+    let (author, committer) = signature::default_signatures(repo)?;
+    let message = match message {
+        Some(message) => {
+            let comment_char = message::comment_char(&config);
+            let message = message::cleanup_with(message, options.cleanup, comment_char);
+            if message.trim().is_empty() {
+                return Err(GitError::EmptyCommitMessage);
+            }
+            Some(message)
+        }
+        None => None,
+    };
+    let (parents, author, message) = if options.amend {
+        // A stopped rebase is where amending is wanted most, so only the
+        // states whose next commit concludes them are refused.
+        match repo.state() {
+            RepositoryState::Merge | RepositoryState::CherryPick | RepositoryState::Revert => {
+                return Err(GitError::CannotAmend(repo.state()))
+            }
+            _ => {}
+        }
+        let amended =
+            odb.read_commit(&head.ok_or_else(|| GitError::UnknownRevision("HEAD".to_string()))?)?;
+        let author = if options.reset_author {
+            author
+        } else {
+            amended.author
+        };
+        let message = message.unwrap_or(amended.message);
+        (amended.parents, author, message)
+    } else {
+        let mut parents: Vec<Oid> = head.into_iter().collect();
+        if let Some(state) = merge::merge_state(repo)? {
+            parents.push(state.their_head);
+        }
+        let message = message.ok_or(GitError::EmptyCommitMessage)?;
+        (parents, author, message)
+    };
+    // A merge records something even when its tree is the first parent's.
+    if parents.len() < 2 && !options.allow_empty {
+        let parent_tree = match parents.first() {
+            Some(parent) => odb.read_commit(parent)?.tree,
+            None => tree::build(&mut NullObjectWriter::default(), &Default::default())?,
+        };
+        if parent_tree == tree {
+            return Err(GitError::NothingToCommit);
+        }
+    }
+
+    let mut message = message;
+    if options.signoff {
+        let identity = format!("{} <{}>", committer.name, committer.email);
+        message = message::append_trailer(&message, "Signed-off-by", &identity);
+    }
+    if !message.ends_with('\n') {
+        message.push('\n');
+    }
+    let mut commit = Commit {
+        tree,
+        parents,
+        author,
+        committer,
+        extra_headers: Vec::new(),
+        message,
+    };
+    let kind = if options.amend {
+        "commit (amend)"
+    } else if commit.parents.is_empty() {
+        "commit (initial)"
+    } else if commit.parents.len() > 1 {
+        "commit (merge)"
+    } else {
+        "commit"
+    };
+    let reflog_msg = format!("{}: {}", kind, commit.summary());
+    // A dry run's commit goes unsigned: signing runs an external program,
+    // which may well ask for a passphrase.
+    let sign = options.sign || config.get_bool("commit", None, "gpgsign")?.unwrap_or(false);
+    if sign && writer.stores() {
+        signing::sign_commit(repo, &mut commit)?;
+    }
+    let commit = writer.write(&GitObject::Commit(commit))?;
+    Ok((CommitPreview { tree, commit }, reflog_msg, head))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config::config_set;
+    use crate::commands::merge::{merge, merge_state, MergeOutcome};
+
+    use crate::core::refs;
+    use crate::test_utils::{checkout, init_repo, set_ref, stage_file, write_commit};
+
+    #[test]
+    fn first_commit_has_no_parents() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a.txt", "a\n");
+        let id = commit(&repo, Some("initial"), &CommitOptions::default()).unwrap();
+        let commit = repo.odb().read_commit(&id).unwrap();
+        assert!(commit.parents.is_empty());
+        assert_eq!(commit.message, "initial\n");
+        assert_eq!(repo.head_commit().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn second_commit_advances_the_branch() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a.txt", "a\n");
+        let first = commit(&repo, Some("first"), &CommitOptions::default()).unwrap();
+        stage_file(&repo, "b.txt", "b\n");
+        let second = commit(&repo, Some("second\n\nbody"), &CommitOptions::default()).unwrap();
+        let commit = repo.odb().read_commit(&second).unwrap();
+        assert_eq!(commit.parents, vec![first]);
+        assert_eq!(commit.author.name, "A U Thor");
+        assert_eq!(refs::resolve(&repo, "refs/heads/master").unwrap(), second);
+        let log = crate::core::reflog::read(&repo, "refs/heads/master").unwrap();
+        assert_eq!(log.last().unwrap().message, "commit: second");
+    }
+
+    #[test]
+    fn refuses_to_commit_an_unchanged_tree() {
+        let (_dir, repo) = init_repo();
+        assert!(matches!(
+            commit(&repo, Some("nothing"), &CommitOptions::default()),
+            Err(GitError::NothingToCommit)
+        ));
+        stage_file(&repo, "a.txt", "a\n");
+        let first = commit(&repo, Some("first"), &CommitOptions::default()).unwrap();
+        assert!(matches!(
+            commit(&repo, Some("again"), &CommitOptions::default()),
+            Err(GitError::NothingToCommit)
+        ));
+        assert_eq!(repo.head_commit().unwrap(), Some(first));
+
+        let allow_empty = CommitOptions {
+            allow_empty: true,
+            ..CommitOptions::default()
+        };
+        let empty = commit(&repo, Some("again"), &allow_empty).unwrap();
+        let odb = repo.odb();
+        assert_eq!(
+            odb.read_commit(&empty).unwrap().tree,
+            odb.read_commit(&first).unwrap().tree
+        );
+    }
+
+    #[test]
+    fn commits_merge_head_as_a_second_parent() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a.txt", "a\n");
+        let ours = commit(&repo, Some("ours"), &CommitOptions::default()).unwrap();
+        let theirs = write_commit(&repo, &[], &[("b.txt", "b\n")], "theirs");
+        std::fs::write(repo.git_dir().join("MERGE_HEAD"), format!("{}\n", theirs)).unwrap();
+        std::fs::write(repo.git_dir().join("MERGE_MSG"), "Merge theirs\n").unwrap();
+
+        // Recording a merge is never empty, even with our tree unchanged.
+        let merge = commit(&repo, Some("Merge theirs"), &CommitOptions::default()).unwrap();
+        assert_eq!(
+            repo.odb().read_commit(&merge).unwrap().parents,
+            vec![ours, theirs]
+        );
+        assert!(!repo.git_dir().join("MERGE_HEAD").exists());
+        assert!(!repo.git_dir().join("MERGE_MSG").exists());
+    }
+
+    #[test]
+    fn amends_head_keeping_its_parents_and_author() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n")], "base");
+        let old = write_commit(&repo, &[base], &[("a.txt", "b\n")], "old message");
+        checkout(&repo, "master", &old);
+        let amend = CommitOptions {
+            amend: true,
+            ..CommitOptions::default()
+        };
+        stage_file(&repo, "b.txt", "new\n");
+        let new = commit(&repo, None, &amend).unwrap();
+        let odb = repo.odb();
+        let (before, after) = (
+            odb.read_commit(&old).unwrap(),
+            odb.read_commit(&new).unwrap(),
+        );
+        assert_eq!(after.parents, vec![base]);
+        assert_eq!(after.message, "old message\n");
+        assert_eq!(after.author, before.author);
+        assert!(after.committer.time > before.committer.time);
+        assert_ne!(after.tree, before.tree);
+        assert_eq!(repo.head_commit().unwrap(), Some(new));
+        assert!(!crate::core::revwalk::ancestors(odb, &new)
+            .unwrap()
+            .contains(&old));
+        assert!(odb.contains(&old));
+        let log = crate::core::reflog::read(&repo, "refs/heads/master").unwrap();
+        assert_eq!(log.last().unwrap().message, "commit (amend): old message");
+
+        let reset = CommitOptions {
+            reset_author: true,
+            ..amend
+        };
+        let reworded = commit(&repo, Some("reworded"), &reset).unwrap();
+        let reworded = odb.read_commit(&reworded).unwrap();
+        assert_eq!(reworded.message, "reworded\n");
+        assert_eq!(reworded.parents, vec![base]);
+        assert!(reworded.author.time > before.author.time);
+    }
+
+    #[test]
+    fn refuses_to_amend_during_a_merge_or_revert() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a.txt", "a\n");
+        let head = commit(&repo, Some("first"), &CommitOptions::default()).unwrap();
+        std::fs::write(repo.git_dir().join("MERGE_HEAD"), format!("{}\n", head)).unwrap();
+        let amend = CommitOptions {
+            amend: true,
+            ..CommitOptions::default()
+        };
+        assert!(matches!(
+            commit(&repo, Some("amended"), &amend),
+            Err(GitError::CannotAmend(RepositoryState::Merge))
+        ));
+        std::fs::remove_file(repo.git_dir().join("MERGE_HEAD")).unwrap();
+        std::fs::write(repo.git_dir().join("REVERT_HEAD"), format!("{}\n", head)).unwrap();
+        assert!(matches!(
+            commit(&repo, Some("amended"), &amend),
+            Err(GitError::CannotAmend(RepositoryState::Revert))
+        ));
+        assert!(matches!(
+            commit(&repo, None, &CommitOptions::default()),
+            Err(GitError::EmptyCommitMessage)
+        ));
+    }
+
+    #[test]
+    fn cleans_up_and_signs_off_messages() {
+        let (_dir, repo) = init_repo();
+        config_set(&repo, "core.commentChar", ";").unwrap();
+        stage_file(&repo, "a.txt", "a\n");
+        let options = CommitOptions {
+            cleanup: CleanupMode::Strip,
+            signoff: true,
+            ..CommitOptions::default()
+        };
+        let messy = "\r\nSubject  \r\n\r\n; dropped\r\n# kept\r\n\r\n\r\n\
+                     Co-authored-by: B <b@example.com>\r\n\n";
+        let id = commit(&repo, Some(messy), &options).unwrap();
+        let message = repo.odb().read_commit(&id).unwrap().message;
+        assert_eq!(
+            message,
+            "Subject\n\n# kept\n\nCo-authored-by: B <b@example.com>\n\
+             Signed-off-by: A U Thor <author@example.com>\n"
+        );
+
+        let amend = CommitOptions {
+            amend: true,
+            ..options
+        };
+        let amended = commit(&repo, None, &amend).unwrap();
+        assert_eq!(repo.odb().read_commit(&amended).unwrap().message, message);
+        assert!(matches!(
+            commit(&repo, Some("; only a comment\n\n"), &amend),
+            Err(GitError::EmptyCommitMessage)
+        ));
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a.txt", "a\n");
+        let objects = || {
+            let mut count = 0;
+            for fanout in std::fs::read_dir(repo.odb().objects_dir()).unwrap() {
+                let fanout = fanout.unwrap();
+                if fanout.file_name().len() == 2 {
+                    count += std::fs::read_dir(fanout.path()).unwrap().count();
+                }
+            }
+            count
+        };
+        let before = objects();
+
+        // Nor does it sign, which would run the signing program.
+        config_set(&repo, "commit.gpgSign", "true").unwrap();
+        config_set(&repo, "gpg.program", "/nonexistent/gpg").unwrap();
+        let preview = commit_dry_run(&repo, Some("initial"), &CommitOptions::default()).unwrap();
+        assert_eq!(objects(), before);
+        assert!(!repo.odb().contains(&preview.tree));
+        assert_eq!(repo.head_commit().unwrap(), None);
+
+        config_set(&repo, "commit.gpgSign", "false").unwrap();
+        let id = commit(&repo, Some("initial"), &CommitOptions::default()).unwrap();
+        assert_eq!(repo.odb().read_commit(&id).unwrap().tree, preview.tree);
+    }
+
+    #[test]
+    fn concludes_a_conflicted_merge() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n")], "base");
+        let ours = write_commit(&repo, &[base], &[("a.txt", "ours\n")], "ours");
+        let theirs = write_commit(&repo, &[base], &[("a.txt", "theirs\n")], "theirs");
+        set_ref(&repo, "refs/heads/feature", &theirs);
+        checkout(&repo, "master", &ours);
+
+        assert!(matches!(
+            merge(&repo, "feature").unwrap(),
+            MergeOutcome::Conflicts(_)
+        ));
+        let state = merge_state(&repo).unwrap().unwrap();
+        assert_eq!(state.their_head, theirs);
+        assert!(state.message.starts_with("Merge branch 'feature'\n"));
+        assert!(matches!(
+            commit(&repo, Some(&state.message), &CommitOptions::default()),
+            Err(GitError::UnresolvedConflicts(_))
+        ));
+        assert!(matches!(
+            merge(&repo, "feature"),
+            Err(GitError::OperationInProgress(RepositoryState::Merge))
+        ));
+
+        stage_file(&repo, "a.txt", "resolved\n");
+        let id = commit(
+            &repo,
+            Some("Merge branch 'feature'"),
+            &CommitOptions::default(),
+        )
+        .unwrap();
+        let merged = repo.odb().read_commit(&id).unwrap();
+        assert_eq!(merged.parents, vec![ours, theirs]);
+        assert_eq!(merge_state(&repo).unwrap(), None);
+        assert!(!repo.git_dir().join("MERGE_MSG").exists());
+    }
+}
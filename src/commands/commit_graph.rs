@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use crate::core::commit_graph::{self, GraphCommit, GENERATION_MAX};
+use crate::core::lockfile::LockFile;
+use crate::core::object::ObjectType;
+use crate::core::oid::Oid;
+use crate::core::refs;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Graph the history of these commits instead of everything reachable
+    /// from refs, as `--stdin-commits` does.
+    pub commits: Option<Vec<Oid>>,
+}
+
+/// `git commit-graph write`: graph every commit reachable from the refs'
+/// tips, or from `options.commits`, replacing
+/// `objects/info/commit-graph` under its lock. Generation numbers are
+/// topological levels, as in version 1 graphs. Refs to anything other
+/// than a commit are skipped.
+pub fn write(repo: &Repository, options: &WriteOptions) -> GitResult<()> {
+    let odb = repo.odb();
+    let tips = match &options.commits {
+        Some(commits) => commits.clone(),
+        None => {
+            let mut tips = Vec::new();
+            for reference in refs::iter(repo)? {
+                if let (id, ObjectType::Commit) = odb.peel(&reference?.target)? {
+                    tips.push(id);
+                }
+            }
+            tips
+        }
+    };
+
+    let mut commits = BTreeMap::new();
+    let mut pending = tips;
+    while let Some(id) = pending.pop() {
+        if commits.contains_key(&id) {
+            continue;
+        }
+        let commit = odb.read_commit(&id)?;
+        pending.extend(
+            commit
+                .parents
+                .iter()
+                .filter(|parent| !commits.contains_key(*parent)),
+        );
+        commits.insert(
+            id,
+            GraphCommit {
+                tree: commit.tree,
+                parents: commit.parents,
+                generation: 0,
+                time: commit.committer.time,
+            },
+        );
+    }
+    let generations = generations(&commits);
+    for (id, commit) in commits.iter_mut() {
+        commit.generation = generations[id];
+    }
+
+    let data = commit_graph::serialize(&commits)?;
+    let mut lock = LockFile::acquire(&odb.objects_dir().join("info/commit-graph"))?;
+    lock.write_all(&data)?;
+    lock.commit()?;
+    Ok(())
+}
+
+/// Each commit's topological level: 1 for a root, otherwise one more than
+/// its highest parent's, found without recursing so that long histories
+/// can't overflow the stack.
+fn generations(commits: &BTreeMap<Oid, GraphCommit>) -> HashMap<Oid, u32> {
+    let mut generations: HashMap<Oid, u32> = HashMap::with_capacity(commits.len());
+    for start in commits.keys() {
+        let mut stack = vec![*start];
+        while let Some(&id) = stack.last() {
+            if generations.contains_key(&id) {
+                stack.pop();
+                continue;
+            }
+            let parents = &commits[&id].parents;
+            match parents
+                .iter()
+                .find(|parent| !generations.contains_key(*parent))
+            {
+                Some(parent) => stack.push(*parent),
+                None => {
+                    let highest = parents.iter().map(|p| generations[p]).max().unwrap_or(0);
+                    generations.insert(id, (highest + 1).min(GENERATION_MAX));
+                    stack.pop();
+                }
+            }
+        }
+    }
+    generations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tag;
+    use crate::core::commit_graph::CommitGraph;
+    use crate::core::object::GitObject;
+    use crate::test_utils::{git, init_repo, set_ref, signature, write_commit_at};
+    use std::fs;
+
+    #[test]
+    fn writes_a_graph_git_accepts() {
+        let (_dir, repo) = init_repo();
+        let mut ids: Vec<Oid> = Vec::new();
+        for (parents, name, time) in [
+            (&[][..], "root", 1_000),
+            (&[0][..], "a", 2_000),
+            (&[0][..], "b", 1_500),
+            (&[0][..], "c", 2_500),
+            (&[1, 2, 3][..], "octopus", 3_000),
+            (&[4, 2][..], "merge", 3_500),
+        ] {
+            let parents: Vec<Oid> = parents.iter().map(|&p| ids[p]).collect();
+            ids.push(write_commit_at(
+                &repo,
+                &parents,
+                &[(name, name)],
+                name,
+                time,
+            ));
+        }
+        set_ref(&repo, "refs/heads/master", &ids[5]);
+        set_ref(&repo, "refs/heads/side", &ids[2]);
+        tag::create_annotated(&repo, "v1", "master~1", "one\n", signature(), false).unwrap();
+        let blob = repo.odb().write(&GitObject::Blob(b"x".to_vec())).unwrap();
+        set_ref(&repo, "refs/tags/blob", &blob);
+
+        write(&repo, &WriteOptions::default()).unwrap();
+        let graph = CommitGraph::open(&repo).unwrap().unwrap();
+        assert_eq!(graph.len(), ids.len());
+        let generations: Vec<u32> = ids
+            .iter()
+            .map(|id| graph.get(id).unwrap().unwrap().generation)
+            .collect();
+        assert_eq!(generations, [1, 2, 2, 2, 3, 4]);
+        for id in &ids {
+            let commit = repo.odb().read_commit(id).unwrap();
+            let graphed = graph.get(id).unwrap().unwrap();
+            assert_eq!(
+                (graphed.tree, graphed.parents, graphed.time),
+                (commit.tree, commit.parents, commit.committer.time)
+            );
+        }
+
+        let path = repo.git_dir().join("objects/info/commit-graph");
+        let ours = fs::read(&path).unwrap();
+        if git(&repo, &["commit-graph", "verify"]).is_some() {
+            let args = [
+                "-c",
+                "commitGraph.generationVersion=1",
+                "commit-graph",
+                "write",
+                "--reachable",
+            ];
+            git(&repo, &args).unwrap();
+            assert_eq!(fs::read(&path).unwrap(), ours);
+        }
+
+        // Just the history of `side`, replacing the graph.
+        let options = WriteOptions {
+            commits: Some(vec![ids[2]]),
+        };
+        write(&repo, &options).unwrap();
+        let graph = CommitGraph::open(&repo).unwrap().unwrap();
+        assert_eq!(graph.len(), 2);
+        assert!(graph.lookup(&ids[5]).is_none());
+    }
+}
@@ -0,0 +1,162 @@
+use std::fs;
+use std::io::{self, Write};
+
+use crate::core::config;
+use crate::core::lockfile::LockFile;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// The value of `name` (e.g. `user.email`) in the repository config.
+pub fn config_get(repo: &Repository, name: &str) -> GitResult<Option<String>> {
+    let (section, subsection, key) = config::parse_key(name)?;
+    Ok(repo
+        .config()?
+        .get(&section, subsection.as_deref(), &key)
+        .map(str::to_string))
+}
+
+/// Every value of `name`, as `git config --get-all` lists them.
+pub fn config_get_all(repo: &Repository, name: &str) -> GitResult<Vec<String>> {
+    let (section, subsection, key) = config::parse_key(name)?;
+    Ok(repo
+        .config()?
+        .get_all(&section, subsection.as_deref(), &key)
+        .into_iter()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Set `name` in `.git/config`, keeping the rest of the file as it was.
+pub fn config_set(repo: &Repository, name: &str, value: &str) -> GitResult<()> {
+    let (section, subsection, key) = config::parse_key(name)?;
+    edit(repo, |text| {
+        config::set_value(text, &section, subsection.as_deref(), &key, value)
+    })
+}
+
+/// Add a value for `name` without replacing the ones it has, like
+/// `git config --add`.
+pub fn config_add(repo: &Repository, name: &str, value: &str) -> GitResult<()> {
+    let (section, subsection, key) = config::parse_key(name)?;
+    edit(repo, |text| {
+        Ok(config::add_value(
+            text,
+            &section,
+            subsection.as_deref(),
+            &key,
+            value,
+        ))
+    })
+}
+
+/// Remove `name` from `.git/config`, returning whether it was set. A key
+/// with several values is only removed with `all`, as with
+/// `git config --unset-all`.
+pub fn config_unset(repo: &Repository, name: &str, all: bool) -> GitResult<bool> {
+    let (section, subsection, key) = config::parse_key(name)?;
+    let mut removed = 0;
+    edit(repo, |text| {
+        let (text, count) = config::unset_value(text, &section, subsection.as_deref(), &key, all)?;
+        removed = count;
+        Ok(text)
+    })?;
+    Ok(removed > 0)
+}
+
+/// Remove a whole section, such as `remote.origin`, as
+/// `git config --remove-section` does, returning whether it was there.
+pub fn config_remove_section(repo: &Repository, name: &str) -> GitResult<bool> {
+    let (section, subsection) = match name.split_once('.') {
+        Some((section, subsection)) => (section, Some(subsection)),
+        None => (name, None),
+    };
+    let mut removed = false;
+    edit(repo, |text| {
+        let (text, found) = config::remove_section(text, section, subsection);
+        removed = found;
+        Ok(text)
+    })?;
+    Ok(removed)
+}
+
+/// Rewrite the config file under `config.lock`.
+fn edit<F>(repo: &Repository, change: F) -> GitResult<()>
+where
+    F: FnOnce(&str) -> GitResult<String>,
+{
+    let path = repo.config_path();
+    let mut lock = LockFile::acquire(&path)?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let updated = change(&text)?;
+    if updated != text {
+        lock.write_all(updated.as_bytes())?;
+        lock.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::GitError;
+    use crate::test_utils::init_repo;
+
+    #[test]
+    fn set_then_get_round_trips() {
+        let (_dir, repo) = init_repo();
+        config_set(&repo, "user.name", "Someone Else").unwrap();
+        config_set(&repo, "branch.main.remote", "origin").unwrap();
+        config_set(&repo, "core.bare", "false").unwrap();
+        assert_eq!(
+            config_get(&repo, "user.name").unwrap().as_deref(),
+            Some("Someone Else")
+        );
+        assert_eq!(
+            config_get(&repo, "branch.main.remote").unwrap().as_deref(),
+            Some("origin")
+        );
+        assert_eq!(
+            repo.config()
+                .unwrap()
+                .get_bool("core", None, "bare")
+                .unwrap(),
+            Some(false)
+        );
+        // The identity added by `init_repo` is still there.
+        assert_eq!(
+            config_get(&repo, "user.email").unwrap().as_deref(),
+            Some("author@example.com")
+        );
+
+        assert!(config_unset(&repo, "branch.main.remote", false).unwrap());
+        assert!(!config_unset(&repo, "branch.main.remote", false).unwrap());
+        assert_eq!(config_get(&repo, "branch.main.remote").unwrap(), None);
+    }
+
+    #[test]
+    fn unsetting_a_multivar_needs_all() {
+        let (_dir, repo) = init_repo();
+        config_add(&repo, "remote.origin.fetch", "a").unwrap();
+        config_add(&repo, "remote.origin.fetch", "b").unwrap();
+        assert_eq!(
+            config_get_all(&repo, "remote.origin.fetch").unwrap(),
+            vec!["a", "b"]
+        );
+        assert!(matches!(
+            config_set(&repo, "remote.origin.fetch", "c"),
+            Err(GitError::MultipleConfigValues(_))
+        ));
+
+        assert!(matches!(
+            config_unset(&repo, "remote.origin.fetch", false),
+            Err(GitError::MultipleConfigValues(_))
+        ));
+        assert!(!repo.git_dir().join("config.lock").exists());
+        assert!(config_unset(&repo, "remote.origin.fetch", true).unwrap());
+        assert_eq!(config_get(&repo, "remote.origin.fetch").unwrap(), None);
+    }
+}
@@ -0,0 +1,411 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::core::object::ObjectType;
+use crate::core::oid::Oid;
+use crate::core::refs;
+use crate::core::revwalk;
+use crate::core::tree;
+use crate::core::wildmatch::wildmatch;
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// Like git, only the first this many candidates the walk reaches, newest
+/// first, are weighed against each other.
+const MAX_CANDIDATES: usize = 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DescribeOptions {
+    /// Consider lightweight tags too, not just annotated ones.
+    pub tags: bool,
+    /// Consider every ref, named by its path under `refs/`, as
+    /// `heads/master` or `tags/v1.0`.
+    pub all: bool,
+    /// Only use a tag on the commit itself, failing otherwise.
+    pub exact_match: bool,
+    /// Fall back to the abbreviated commit id when no tag describes it.
+    pub always: bool,
+    /// Append this when the index or working tree differs from HEAD, as
+    /// `--dirty` does. Only meaningful when describing HEAD.
+    pub dirty: Option<String>,
+    /// Only consider tags whose names match one of these globs...
+    pub match_patterns: Vec<String>,
+    /// ...and none of these.
+    pub exclude_patterns: Vec<String>,
+}
+
+/// Name the commit `committish` resolves to after the nearest tag it
+/// contains, as `<tag>-<n>-g<abbrev>` where `n` counts the commits it has
+/// that the tag doesn't, or just `<tag>` when the commit is the tagged one.
+/// Of two tags equally near, the one the newest-first walk from the commit
+/// reaches first wins.
+pub fn describe(
+    repo: &Repository,
+    committish: &str,
+    options: &DescribeOptions,
+) -> GitResult<String> {
+    let odb = repo.odb();
+    let commit = odb.peel_to_commit(&repo.resolve_rev(committish)?)?;
+    let suffix = match &options.dirty {
+        Some(mark) if is_dirty(repo)? => mark.as_str(),
+        _ => "",
+    };
+    let names = known_names(repo, options)?;
+    if let Some(name) = names.get(&commit) {
+        return Ok(format!("{}{}", name.name, suffix));
+    }
+    if options.exact_match {
+        return Err(GitError::NoExactMatch(commit));
+    }
+
+    // One walk collects the whole history along with the named commits in
+    // the order they're reached. Every commit a tag contains is part of
+    // that history, so the distance to it is just the difference in size.
+    let mut history = HashSet::new();
+    let mut candidates = Vec::new();
+    let mut queue = BinaryHeap::new();
+    let mut pushed = 0usize;
+    queue.push((
+        odb.read_commit(&commit)?.committer.time,
+        Reverse(pushed),
+        commit,
+    ));
+    while let Some((_, _, id)) = queue.pop() {
+        if !history.insert(id) {
+            continue;
+        }
+        if candidates.len() < MAX_CANDIDATES {
+            if let Some(name) = names.get(&id) {
+                candidates.push((id, name));
+            }
+        }
+        for parent in odb.read_commit(&id)?.parents {
+            if !history.contains(&parent) {
+                pushed += 1;
+                let time = odb.read_commit(&parent)?.committer.time;
+                queue.push((time, Reverse(pushed), parent));
+            }
+        }
+    }
+    let mut best: Option<(usize, &Name)> = None;
+    for (id, name) in candidates {
+        let distance = history.len() - revwalk::ancestors(odb, &id)?.len();
+        if best.is_none_or(|(closest, _)| distance < closest) {
+            best = Some((distance, name));
+        }
+    }
+
+    let abbrev = odb.abbreviate(&commit, 7)?;
+    match best {
+        Some((distance, name)) => Ok(format!("{}-{}-g{}{}", name.name, distance, abbrev, suffix)),
+        None if options.always => Ok(format!("{}{}", abbrev, suffix)),
+        None => Err(GitError::NoTagFound(commit)),
+    }
+}
+
+/// How a commit is named, and how strongly: an annotated tag beats a
+/// lightweight one, which beats any other ref.
+#[derive(Debug)]
+struct Name {
+    name: String,
+    priority: u8,
+    /// For annotated tags, when the tag was made.
+    tagged_at: i64,
+}
+
+/// The name each eligible commit goes by. Where several refs name one
+/// commit the strongest wins, then the newest annotated tag, then the
+/// first by ref name.
+fn known_names(repo: &Repository, options: &DescribeOptions) -> GitResult<HashMap<Oid, Name>> {
+    let odb = repo.odb();
+    let mut names: HashMap<Oid, Name> = HashMap::new();
+    for reference in refs::list(repo)? {
+        // Patterns match tag names, and with `all` branch names too.
+        let (is_tag, matched) = match reference.name.strip_prefix("refs/tags/") {
+            Some(name) => (true, Some(name)),
+            None if !options.all => continue,
+            None => (
+                false,
+                reference
+                    .name
+                    .strip_prefix("refs/heads/")
+                    .or_else(|| reference.name.strip_prefix("refs/remotes/")),
+            ),
+        };
+        let filtered = !options.match_patterns.is_empty() || !options.exclude_patterns.is_empty();
+        match matched {
+            Some(name) if filtered => {
+                let glob = |pattern: &String| wildmatch(pattern.as_bytes(), name.as_bytes(), false);
+                if options.exclude_patterns.iter().any(glob)
+                    || (!options.match_patterns.is_empty()
+                        && !options.match_patterns.iter().any(glob))
+                {
+                    continue;
+                }
+            }
+            None if filtered => continue,
+            _ => {}
+        }
+
+        let (kind, _) = odb.read_header(&reference.target)?;
+        let (priority, tagged_at) = if kind == ObjectType::Tag {
+            let tag = odb.read_tag(&reference.target)?;
+            (2, tag.tagger.map_or(0, |tagger| tagger.time))
+        } else if is_tag {
+            (1, 0)
+        } else {
+            (0, 0)
+        };
+        if priority < 2 && !options.tags && !options.all {
+            continue;
+        }
+        let commit = match odb.peel(&reference.target)? {
+            (commit, ObjectType::Commit) => commit,
+            _ => continue,
+        };
+        let name = if options.all {
+            reference.name["refs/".len()..].to_string()
+        } else {
+            reference.name["refs/tags/".len()..].to_string()
+        };
+        let better = |known: &Name| {
+            known.priority < priority
+                || (priority == 2 && known.priority == 2 && known.tagged_at < tagged_at)
+        };
+        if names.get(&commit).is_none_or(better) {
+            names.insert(
+                commit,
+                Name {
+                    name,
+                    priority,
+                    tagged_at,
+                },
+            );
+        }
+    }
+    Ok(names)
+}
+
+/// Whether the index or the tracked files differ from HEAD: what
+/// `git diff-index HEAD` would report.
+fn is_dirty(repo: &Repository) -> GitResult<bool> {
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let index = repo.read_index()?;
+    if index.has_conflicts() {
+        return Ok(true);
+    }
+    let staged = tree::from_index(&index);
+    let head = match repo.head_commit()? {
+        Some(head) => tree::flatten(odb, &odb.read_commit(&head)?.tree)?,
+        None => tree::FlatTree::new(),
+    };
+    if staged != head {
+        return Ok(true);
+    }
+    for (path, entry) in &staged {
+        if worktree::hash_file(work_dir, path)?.as_ref() != Some(entry) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tag::{create_annotated, create_lightweight};
+
+    use crate::core::signature::Signature;
+    use crate::test_utils::{
+        checkout, git, init_repo, set_ref, signature, write_commit, write_commit_at,
+    };
+    use std::fs;
+
+    fn always() -> DescribeOptions {
+        DescribeOptions {
+            always: true,
+            ..DescribeOptions::default()
+        }
+    }
+
+    #[test]
+    fn counts_commits_since_the_nearest_tag() {
+        let (_dir, repo) = init_repo();
+        let first = write_commit(&repo, &[], &[("a", "1")], "first");
+        let tagged = write_commit(&repo, &[first], &[("a", "2")], "tagged");
+        let main = write_commit(&repo, &[tagged], &[("a", "3")], "main");
+        let side = write_commit(&repo, &[tagged], &[("b", "1")], "side");
+        let merge = write_commit(&repo, &[main, side], &[("a", "3"), ("b", "1")], "merge");
+        refs::update(&repo, "refs/heads/master", merge, None, "").unwrap();
+
+        assert!(matches!(
+            describe(&repo, "master", &DescribeOptions::default()),
+            Err(GitError::NoTagFound(_))
+        ));
+        assert_eq!(
+            describe(&repo, "master", &always()).unwrap(),
+            merge.abbrev(7)
+        );
+
+        create_annotated(&repo, "v0.9", &first.to_string(), "old", signature(), false).unwrap();
+        create_annotated(
+            &repo,
+            "v1.0",
+            &tagged.to_string(),
+            "1.0",
+            signature(),
+            false,
+        )
+        .unwrap();
+        // Only annotated tags count.
+        create_lightweight(&repo, "light", "master", false).unwrap();
+
+        // The shared history below the merge is only counted once.
+        let described = describe(&repo, "master", &DescribeOptions::default()).unwrap();
+        assert_eq!(described, format!("v1.0-3-g{}", merge.abbrev(7)));
+        assert_eq!(
+            describe(&repo, &tagged.to_string(), &DescribeOptions::default()).unwrap(),
+            "v1.0"
+        );
+        assert_eq!(
+            describe(&repo, "v1.0", &DescribeOptions::default()).unwrap(),
+            "v1.0"
+        );
+
+        if let Some(output) = git(&repo, &["describe", "master"]) {
+            assert_eq!(output.trim_end(), described);
+        }
+    }
+
+    #[test]
+    fn picks_between_tags_like_git() {
+        let (_dir, repo) = init_repo();
+        let at = |time| Signature {
+            time,
+            ..signature()
+        };
+        let c1 = write_commit_at(&repo, &[], &[("a", "1")], "c1", 1000);
+        let c2 = write_commit_at(&repo, &[c1], &[("a", "2")], "c2", 2000);
+        let c3 = write_commit_at(&repo, &[c2], &[("a", "3")], "c3", 3000);
+        let c4 = write_commit_at(&repo, &[c3], &[("a", "4")], "c4", 4000);
+        let side = write_commit_at(&repo, &[c2], &[("a", "2"), ("b", "1")], "side", 2500);
+        let files = [("a", "4"), ("b", "1")];
+        let merge = write_commit_at(&repo, &[c4, side], &files, "merge", 5000);
+        checkout(&repo, "master", &merge);
+        let hex = |id: &Oid| id.to_string();
+        create_annotated(&repo, "v1.0", &hex(&c1), "1.0", at(100), false).unwrap();
+        // Of two annotated tags on one commit the newer is used.
+        create_annotated(&repo, "a-newer", &hex(&c2), "new", at(300), false).unwrap();
+        create_annotated(&repo, "b-older", &hex(&c2), "old", at(200), false).unwrap();
+        create_annotated(&repo, "v1.5", &hex(&c3), "1.5", at(400), false).unwrap();
+        create_annotated(&repo, "v2.0-rc", &hex(&side), "rc", at(500), false).unwrap();
+        create_lightweight(&repo, "light", &hex(&c4), false).unwrap();
+        set_ref(&repo, "refs/heads/topic", &c4);
+
+        let patterns = |list: &[&str]| list.iter().map(|p| p.to_string()).collect();
+        let cases: Vec<(&[&str], DescribeOptions, Oid)> = vec![
+            // v1.5 and v2.0-rc are both three commits away; the date order
+            // walk reaches v1.5 first.
+            (&[], DescribeOptions::default(), merge),
+            (&[], DescribeOptions::default(), c2),
+            (
+                &["--tags"],
+                DescribeOptions {
+                    tags: true,
+                    ..DescribeOptions::default()
+                },
+                merge,
+            ),
+            (
+                &["--match", "v2*"],
+                DescribeOptions {
+                    match_patterns: patterns(&["v2*"]),
+                    ..DescribeOptions::default()
+                },
+                merge,
+            ),
+            (
+                &["--exclude", "v1.5", "--exclude", "v2*"],
+                DescribeOptions {
+                    exclude_patterns: patterns(&["v1.5", "v2*"]),
+                    ..DescribeOptions::default()
+                },
+                merge,
+            ),
+            (
+                &["--all"],
+                DescribeOptions {
+                    all: true,
+                    ..DescribeOptions::default()
+                },
+                merge,
+            ),
+            (
+                &["--all"],
+                DescribeOptions {
+                    all: true,
+                    ..DescribeOptions::default()
+                },
+                c4,
+            ),
+            (
+                &["--all", "--match", "top*"],
+                DescribeOptions {
+                    all: true,
+                    match_patterns: patterns(&["top*"]),
+                    ..DescribeOptions::default()
+                },
+                c4,
+            ),
+        ];
+        let abbrev = &hex(&merge)[..7];
+        let expected = [
+            format!("v1.5-3-g{}", abbrev),
+            "a-newer".to_string(),
+            format!("light-2-g{}", abbrev),
+            format!("v2.0-rc-3-g{}", abbrev),
+            format!("a-newer-4-g{}", abbrev),
+            "heads/master".to_string(),
+            "tags/light".to_string(),
+            "heads/topic".to_string(),
+        ];
+        for ((args, options, id), expected) in cases.iter().zip(&expected) {
+            let described = describe(&repo, &hex(id), options).unwrap();
+            assert_eq!(&described, expected, "{:?}", args);
+            let mut args = args.to_vec();
+            let id = hex(id);
+            args.insert(0, "describe");
+            args.push(&id);
+            if let Some(output) = git(&repo, &args) {
+                assert_eq!(output.trim_end(), described, "{:?}", args);
+            }
+        }
+
+        let exact = DescribeOptions {
+            exact_match: true,
+            always: true,
+            ..DescribeOptions::default()
+        };
+        assert!(matches!(
+            describe(&repo, "HEAD", &exact),
+            Err(GitError::NoExactMatch(id)) if id == merge
+        ));
+        assert_eq!(describe(&repo, "HEAD~2", &exact).unwrap(), "v1.5");
+
+        let dirty = DescribeOptions {
+            dirty: Some("-dirty".to_string()),
+            ..DescribeOptions::default()
+        };
+        assert_eq!(describe(&repo, "HEAD", &dirty).unwrap(), expected[0]);
+        let work_dir = repo.work_dir().unwrap();
+        fs::write(work_dir.join("a"), "changed").unwrap();
+        let described = describe(&repo, "HEAD", &dirty).unwrap();
+        assert_eq!(described, format!("{}-dirty", expected[0]));
+        let work_tree = work_dir.to_str().unwrap();
+        if let Some(output) = git(&repo, &["--work-tree", work_tree, "describe", "--dirty"]) {
+            assert_eq!(output.trim_end(), described);
+        }
+    }
+}
@@ -0,0 +1,916 @@
+//! `git diff`, `git diff --cached` and `git diff A B`: what's changed in
+//! the working tree since it was staged, in the index since HEAD, or
+//! between two commits.
+//!
+//! Each first collects a [`FileDelta`] for every changed path, then
+//! writes each as [`patch::write_file_patch_with`] does, or a summary of
+//! them all as `--stat`, `--numstat`, `--name-only` and `--name-status`
+//! do. Files are compared as git records them: executables by their mode
+//! and symlinks by their target. Untracked files aren't part of either
+//! diff.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+
+use crate::core::diff::{self, tree_diff, DiffAlgorithm, DiffOp};
+use crate::core::diffstat::{self, FileStat, StatChange};
+use crate::core::object::MODE_GITLINK;
+use crate::core::oid::Oid;
+use crate::core::patch::{self, PatchOptions};
+use crate::core::pathspec::Pathspec;
+use crate::core::quote;
+use crate::core::regex::Regex;
+use crate::core::rename::{self, RenameOptions};
+use crate::core::tree::{self, FlatTree};
+use crate::core::word_diff::WordDiffMode;
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+pub use crate::core::diff::{DeltaStatus, FileDelta};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// Compare HEAD's tree with the index rather than the index with the
+    /// working tree, as `--cached` does.
+    pub cached: bool,
+    /// Pair deleted files with added ones they were moved to, as `-M`
+    /// does.
+    pub renames: Option<RenameOptions>,
+    /// How lines are diffed, as `--diff-algorithm` picks it, or as
+    /// `diff.algorithm` does when `None`.
+    pub algorithm: Option<DiffAlgorithm>,
+    pub output: DiffOutput,
+    /// The columns `--stat` fits its lines into, or the terminal's width
+    /// when `None`.
+    pub stat_width: Option<usize>,
+    /// Show a patch's changed lines word by word, as `--word-diff` does.
+    pub word_diff: WordDiffMode,
+    /// What a word is, as `diff.wordRegex` or `--word-diff-regex` sets
+    /// it; runs of non-whitespace otherwise.
+    pub word_regex: Option<Regex>,
+    /// End each field of `--name-only` and `--name-status` with a NUL
+    /// and leave paths unquoted, as `-z` does.
+    pub nul_terminated: bool,
+}
+
+/// What a diff writes for the changes it finds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffOutput {
+    #[default]
+    Patch,
+    /// A line for each file with a bar of its changes, as `--stat`.
+    Stat,
+    /// Line counts for each file, as `--numstat`.
+    NumStat,
+    /// Each changed path, as `--name-only`.
+    NameOnly,
+    /// Each changed path after its status letter, and a renamed or copied
+    /// file's old path and similarity, as `--name-status`.
+    NameStatus,
+}
+
+/// A changed path, or a conflicted one, in the order a diff shows them.
+enum Entry<'a> {
+    Changed(&'a FileDelta),
+    Unmerged(&'a str),
+}
+
+/// Write what `options` asks for about every change to `out`.
+/// Conflicted paths show as `* Unmerged path` lines in a patch, as git
+/// writes them when it doesn't combine their stages.
+pub fn diff(repo: &Repository, options: &DiffOptions, out: impl Write) -> GitResult<()> {
+    let index = repo.read_index()?;
+    let unmerged: Vec<String> = index.conflicts().into_iter().map(|c| c.path).collect();
+    let deltas = changes(repo, options)?;
+
+    let mut entries = Vec::new();
+    let mut deltas = deltas.iter().peekable();
+    for path in &unmerged {
+        while let Some(delta) = deltas.next_if(|d| d.path < *path) {
+            entries.push(Entry::Changed(delta));
+        }
+        entries.push(Entry::Unmerged(path));
+    }
+    entries.extend(deltas.map(Entry::Changed));
+    write_entries(repo, options, &entries, out)
+}
+
+/// The changed paths `options` asks about, sorted by path, renames by
+/// where the file is now. Conflicted paths are left out.
+pub fn changes(repo: &Repository, options: &DiffOptions) -> GitResult<Vec<FileDelta>> {
+    let index = repo.read_index()?;
+    let staged = tree::from_index(&index);
+    let (old, new) = if options.cached {
+        let odb = repo.odb();
+        let head = match repo.head_commit()? {
+            Some(head) => tree::flatten(odb, &odb.read_commit(&head)?.tree)?,
+            None => FlatTree::new(),
+        };
+        let conflicted: Vec<String> = index.conflicts().into_iter().map(|c| c.path).collect();
+        let head = head
+            .into_iter()
+            .filter(|(path, _)| !conflicted.contains(path))
+            .collect();
+        (head, staged)
+    } else {
+        let work_dir = repo.require_work_dir()?;
+        let mut work = FlatTree::new();
+        for (path, staged) in &staged {
+            // A submodule's checkout isn't compared here.
+            if staged.mode == MODE_GITLINK {
+                work.insert(path.clone(), *staged);
+            } else if let Some(entry) = worktree::hash_file(work_dir, path)? {
+                work.insert(path.clone(), entry);
+            }
+        }
+        (staged, work)
+    };
+    let mut deltas = tree_deltas(&old, &new);
+    if let Some(renames) = &options.renames {
+        rename::detect_renames(repo.odb(), &mut deltas, renames)?;
+    }
+    Ok(deltas)
+}
+
+/// Write the patch between the trees of two commit-ish revisions, at the
+/// paths `pathspec` selects, to `out`. `options.cached` doesn't matter
+/// here.
+pub fn diff_revs(
+    repo: &Repository,
+    old: &str,
+    new: &str,
+    pathspec: &Pathspec,
+    options: &DiffOptions,
+    out: impl Write,
+) -> GitResult<()> {
+    let odb = repo.odb();
+    let (old, new) = (rev_tree(repo, old)?, rev_tree(repo, new)?);
+    let mut deltas = tree_diff(odb, Some(&old), Some(&new), pathspec)?;
+    if let Some(renames) = &options.renames {
+        rename::detect_renames(odb, &mut deltas, renames)?;
+    }
+    let options = DiffOptions {
+        cached: true,
+        ..options.clone()
+    };
+    let entries: Vec<Entry> = deltas.iter().map(Entry::Changed).collect();
+    write_entries(repo, &options, &entries, out)
+}
+
+fn write_entries(
+    repo: &Repository,
+    options: &DiffOptions,
+    entries: &[Entry],
+    mut out: impl Write,
+) -> GitResult<()> {
+    let algorithm = match options.algorithm {
+        Some(algorithm) => algorithm,
+        None => configured_algorithm(repo)?,
+    };
+    if options.output == DiffOutput::Patch {
+        let mut buf = Vec::new();
+        for entry in entries {
+            match entry {
+                Entry::Changed(delta) => write_delta(repo, options, algorithm, delta, &mut buf)?,
+                Entry::Unmerged(path) => {
+                    buf.extend_from_slice(format!("* Unmerged path {}\n", path).as_bytes())
+                }
+            }
+        }
+        out.write_all(&buf)?;
+        return Ok(());
+    }
+    if matches!(
+        options.output,
+        DiffOutput::NameOnly | DiffOutput::NameStatus
+    ) {
+        out.write_all(names(options, entries).as_bytes())?;
+        return Ok(());
+    }
+    let mut stats = Vec::with_capacity(entries.len());
+    for entry in entries {
+        stats.push(match entry {
+            Entry::Changed(delta) => file_stat(repo, options, algorithm, delta)?,
+            Entry::Unmerged(path) => FileStat {
+                name: path.to_string(),
+                change: StatChange::Unmerged,
+            },
+        });
+    }
+    let text = match options.output {
+        DiffOutput::NumStat => diffstat::format_numstat(&stats),
+        _ => diffstat::format_stat(&stats, options.stat_width.unwrap_or_else(terminal_width)),
+    };
+    out.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+/// `diff.algorithm`, Myers if it isn't set.
+fn configured_algorithm(repo: &Repository) -> GitResult<DiffAlgorithm> {
+    let config = repo.config()?;
+    match config.get("diff", None, "algorithm") {
+        None => Ok(DiffAlgorithm::default()),
+        Some(name) => DiffAlgorithm::from_name(name).ok_or_else(|| GitError::InvalidConfigValue {
+            key: "diff.algorithm".to_string(),
+            value: name.to_string(),
+        }),
+    }
+}
+
+/// How many lines `delta` adds and removes, or its sizes if it's binary.
+fn file_stat(
+    repo: &Repository,
+    options: &DiffOptions,
+    algorithm: DiffAlgorithm,
+    delta: &FileDelta,
+) -> GitResult<FileStat> {
+    let (old, new) = contents(repo, options, delta)?;
+    let change = if diff::is_binary(&old) || diff::is_binary(&new) {
+        StatChange::Binary {
+            old_size: old.len(),
+            new_size: new.len(),
+        }
+    } else {
+        let (old, new) = (diff::split_lines(&old), diff::split_lines(&new));
+        let (mut added, mut deleted) = (0, 0);
+        for op in diff::diff_with(&old, &new, algorithm) {
+            match op {
+                DiffOp::Insert { len, .. } => added += len,
+                DiffOp::Delete { len, .. } => deleted += len,
+                DiffOp::Equal { .. } => {}
+            }
+        }
+        StatChange::Lines { added, deleted }
+    };
+    let name = match &delta.from {
+        Some(from) => diffstat::rename_name(from, &delta.path),
+        None => delta.path.clone(),
+    };
+    Ok(FileStat { name, change })
+}
+
+/// The `--name-only` or `--name-status` lines for `entries`. Paths are
+/// quoted unless they're NUL-terminated.
+fn names(options: &DiffOptions, entries: &[Entry]) -> String {
+    let (separator, end) = if options.nul_terminated {
+        ('\0', '\0')
+    } else {
+        ('\t', '\n')
+    };
+    let path = |path: &str| {
+        if options.nul_terminated {
+            path.to_string()
+        } else {
+            quote::quote_path(path)
+        }
+    };
+    let mut out = String::new();
+    for entry in entries {
+        let (status, from, to) = match entry {
+            Entry::Changed(delta) => {
+                let status = match delta.status {
+                    DeltaStatus::Renamed { similarity } | DeltaStatus::Copied { similarity } => {
+                        format!("{}{:03}", delta.status.letter(), similarity)
+                    }
+                    status => status.letter().to_string(),
+                };
+                (status, delta.from.as_deref(), delta.path.as_str())
+            }
+            Entry::Unmerged(path) => ("U".to_string(), None, *path),
+        };
+        if options.output == DiffOutput::NameStatus {
+            out.push_str(&status);
+            out.push(separator);
+            if let Some(from) = from {
+                out.push_str(&path(from));
+                out.push(separator);
+            }
+        }
+        out.push_str(&path(to));
+        out.push(end);
+    }
+    out
+}
+
+/// The terminal's width as git finds it: `$COLUMNS`, then the terminal on
+/// stdout, then 80.
+fn terminal_width() -> usize {
+    if let Some(columns) = env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|&columns| columns > 0)
+    {
+        return columns;
+    }
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_col > 0
+    {
+        return usize::from(size.ws_col);
+    }
+    80
+}
+
+/// The tree a commit-ish (or tree-ish) revision names.
+pub(crate) fn rev_tree(repo: &Repository, rev: &str) -> GitResult<Oid> {
+    repo.resolve_rev(&format!("{}^{{tree}}", rev))
+}
+
+/// A delta for each path `old` and `new` disagree on.
+fn tree_deltas(old: &FlatTree, new: &FlatTree) -> Vec<FileDelta> {
+    let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let (old, new) = (old.get(path).copied(), new.get(path).copied());
+            let status = DeltaStatus::between(old.as_ref(), new.as_ref())?;
+            Some(FileDelta {
+                path: path.clone(),
+                from: None,
+                status,
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+/// The patch for `delta`, reading its new side from the working tree
+/// unless `options` compares with the index.
+fn write_delta(
+    repo: &Repository,
+    options: &DiffOptions,
+    algorithm: DiffAlgorithm,
+    delta: &FileDelta,
+    out: &mut Vec<u8>,
+) -> GitResult<()> {
+    let odb = repo.odb();
+    let (old_data, new_data) = contents(repo, options, delta)?;
+    let patch_options = PatchOptions {
+        algorithm,
+        word_diff: options.word_diff,
+        word_regex: options.word_regex.as_ref(),
+    };
+    match (&delta.from, delta.status, &delta.old, &delta.new) {
+        (Some(from), DeltaStatus::Renamed { similarity }, Some(old), Some(new)) => {
+            patch::write_rename_patch(
+                out,
+                odb,
+                (from, &delta.path),
+                similarity,
+                (old, &old_data),
+                (new, &new_data),
+                &patch_options,
+            )
+        }
+        (Some(from), DeltaStatus::Copied { similarity }, Some(old), Some(new)) => {
+            patch::write_copy_patch(
+                out,
+                odb,
+                (from, &delta.path),
+                similarity,
+                (old, &old_data),
+                (new, &new_data),
+                &patch_options,
+            )
+        }
+        _ => patch::write_file_patch_with(
+            out,
+            odb,
+            &delta.path,
+            delta.old.as_ref().map(|e| (e, &old_data[..])),
+            delta.new.as_ref().map(|e| (e, &new_data[..])),
+            &patch_options,
+        ),
+    }
+}
+
+/// Both sides of `delta`, reading the new side from the working tree
+/// unless `options` compares with the index.
+fn contents(
+    repo: &Repository,
+    options: &DiffOptions,
+    delta: &FileDelta,
+) -> GitResult<(Vec<u8>, Vec<u8>)> {
+    let odb = repo.odb();
+    let old = patch::content(odb, delta.old.as_ref())?;
+    let new = match &delta.new {
+        Some(entry) if !options.cached && entry.mode != MODE_GITLINK => {
+            let full = repo.require_work_dir()?.join(&delta.path);
+            worktree::read_content(&full, &fs::symlink_metadata(&full)?)?
+        }
+        new => patch::content(odb, new.as_ref())?,
+    };
+    Ok((old, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config::config_set;
+    use crate::core::index::IndexEntry;
+    use crate::core::object::{GitObject, MODE_EXECUTABLE, MODE_SYMLINK};
+    use crate::core::odb::LooseObjectWriter;
+
+    use crate::test_utils::{checkout, git, init_repo, stage_file, write_commit};
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    const FUNCTIONS: &str = "fn one() {\n    1\n}\n\nfn two() {\n    a\n    b\n    c\n    d\n}\n";
+
+    /// Stage `content` at `path` with `mode` without touching the working
+    /// tree.
+    fn stage(repo: &Repository, path: &str, mode: u32, content: &str) {
+        let oid = repo
+            .odb()
+            .write(&GitObject::Blob(content.as_bytes().to_vec()))
+            .unwrap();
+        let mut index = repo.read_index().unwrap();
+        index.add(IndexEntry::new(path, oid, mode));
+        repo.write_index(&index).unwrap();
+    }
+
+    fn run(repo: &Repository, options: &DiffOptions) -> String {
+        let mut out = Vec::new();
+        diff(repo, options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// A commit with a file of functions, a script, a file to delete and
+    /// a symlink, checked out.
+    fn fixture() -> (tempfile::TempDir, Repository) {
+        let (dir, repo) = init_repo();
+        let files = [
+            ("a.rs", FUNCTIONS),
+            ("gone.txt", "gone\n"),
+            ("run.sh", "echo hi\n"),
+        ];
+        let base = write_commit(&repo, &[], &files, "base");
+        checkout(&repo, "master", &base);
+        let work_dir = repo.work_dir().unwrap();
+        symlink("a.rs", work_dir.join("link")).unwrap();
+        stage(&repo, "link", MODE_SYMLINK, "a.rs");
+        let mut index = repo.read_index().unwrap();
+        for path in &["a.rs", "gone.txt", "link", "run.sh"] {
+            let (oid, mode) = {
+                let entry = index.get(path, 0).unwrap();
+                (entry.oid, entry.mode)
+            };
+            index.add(worktree::stat_entry(work_dir, path, oid, mode).unwrap());
+        }
+        repo.write_index(&index).unwrap();
+        (dir, repo)
+    }
+
+    #[test]
+    fn diffs_the_working_tree_against_the_index() {
+        let (_dir, repo) = fixture();
+        assert_eq!(run(&repo, &DiffOptions::default()), "");
+        let work_dir = repo.work_dir().unwrap();
+        fs::write(
+            work_dir.join("a.rs"),
+            FUNCTIONS.replace("    c\n", "    C\n"),
+        )
+        .unwrap();
+        fs::remove_file(work_dir.join("gone.txt")).unwrap();
+        fs::set_permissions(work_dir.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_file(work_dir.join("link")).unwrap();
+        symlink("elsewhere", work_dir.join("link")).unwrap();
+        fs::write(work_dir.join("untracked"), "ignored\n").unwrap();
+
+        let deltas = changes(&repo, &DiffOptions::default()).unwrap();
+        let statuses: Vec<(&str, char)> = deltas
+            .iter()
+            .map(|d| (d.path.as_str(), d.status.letter()))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("a.rs", 'M'),
+                ("gone.txt", 'D'),
+                ("link", 'M'),
+                ("run.sh", 'M')
+            ]
+        );
+        let out = run(&repo, &DiffOptions::default());
+        assert!(out
+            .contains("@@ -5,6 +5,6 @@ fn one() {\n fn two() {\n     a\n     b\n-    c\n+    C\n"));
+        assert!(out.contains("diff --git a/gone.txt b/gone.txt\ndeleted file mode 100644\n"));
+        assert!(out.contains(
+            "+++ b/link\n@@ -1 +1 @@\n-a.rs\n\\ No newline at end of file\n+elsewhere\n"
+        ));
+        assert!(out.ends_with("diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\n"));
+        if let Some(theirs) = git(&repo, &["diff"]) {
+            assert_eq!(out, theirs);
+        }
+    }
+
+    #[test]
+    fn diffs_the_index_against_head() {
+        let (_dir, repo) = fixture();
+        assert_eq!(
+            run(
+                &repo,
+                &DiffOptions {
+                    cached: true,
+                    ..Default::default()
+                }
+            ),
+            format!(
+                "diff --git a/link b/link\nnew file mode 120000\nindex 0000000..{}\n\
+                 --- /dev/null\n+++ b/link\n@@ -0,0 +1 @@\n+a.rs\n\\ No newline at end of file\n",
+                repo.odb()
+                    .abbreviate(&repo.read_index().unwrap().get("link", 0).unwrap().oid, 7)
+                    .unwrap()
+            )
+        );
+        stage_file(&repo, "new.txt", "new\n");
+        stage_file(&repo, "a.rs", &FUNCTIONS.replace("    1\n", "    one\n"));
+        stage(&repo, "run.sh", MODE_EXECUTABLE, "echo bye\n");
+        let mut index = repo.read_index().unwrap();
+        index.remove("gone.txt");
+        repo.write_index(&index).unwrap();
+        // The working tree doesn't matter here.
+        fs::write(repo.work_dir().unwrap().join("new.txt"), "changed\n").unwrap();
+
+        let options = DiffOptions {
+            cached: true,
+            ..Default::default()
+        };
+        let out = run(&repo, &options);
+        assert!(out.contains("diff --git a/new.txt b/new.txt\nnew file mode 100644\n"));
+        assert!(
+            out.contains("diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\nindex ")
+        );
+        assert!(!out.contains("changed"));
+        if let Some(theirs) = git(&repo, &["diff", "--cached"]) {
+            assert_eq!(out, theirs);
+        }
+    }
+
+    #[test]
+    fn diffs_two_commits() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(
+            &repo,
+            &[],
+            &[
+                ("a.rs", FUNCTIONS),
+                ("dir/file", "was a file\n"),
+                ("old", "o\n"),
+            ],
+            "base",
+        );
+        let tip = write_commit(
+            &repo,
+            &[base],
+            &[
+                ("a.rs", &FUNCTIONS.replace("    b\n", "    B\n")),
+                ("dir", "now a file\n"),
+                ("new", "n\n"),
+            ],
+            "tip",
+        );
+        let (base, tip) = (base.to_string(), tip.to_string());
+        let mut out = Vec::new();
+        diff_revs(
+            &repo,
+            &base,
+            &tip,
+            &Pathspec::default(),
+            &DiffOptions::default(),
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("diff --git a/dir b/dir\nnew file mode 100644\n"));
+        assert!(out.contains("diff --git a/dir/file b/dir/file\ndeleted file mode 100644\n"));
+        if let Some(theirs) = git(&repo, &["diff", &base, &tip]) {
+            assert_eq!(out, theirs);
+        }
+
+        let mut out = Vec::new();
+        let only = Pathspec::parse(&["a.rs"]);
+        diff_revs(&repo, &base, &tip, &only, &DiffOptions::default(), &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("diff --git a/a.rs b/a.rs\n"));
+    }
+
+    #[test]
+    fn writes_renames() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.rs", FUNCTIONS), ("b", "b\n")], "base");
+        let moved = FUNCTIONS.replace("    d\n", "    D\n");
+        let tip = write_commit(&repo, &[base], &[("src/a.rs", &moved), ("c", "b\n")], "tip");
+        let (base, tip) = (base.to_string(), tip.to_string());
+        let options = DiffOptions {
+            renames: Some(RenameOptions::default()),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        diff_revs(&repo, &base, &tip, &Pathspec::default(), &options, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(
+            "diff --git a/b b/c\nsimilarity index 100%\nrename from b\nrename to c\n\
+             diff --git a/a.rs b/src/a.rs\nsimilarity index 89%\n\
+             rename from a.rs\nrename to src/a.rs\nindex "
+        ));
+        assert!(out.contains("--- a/a.rs\n+++ b/src/a.rs\n"));
+        if let Some(theirs) = git(&repo, &["diff", "-M", &base, &tip]) {
+            assert_eq!(out, theirs);
+        }
+    }
+
+    /// Two commits with a bit of everything `--stat` shows: small and
+    /// large changes, a long path, binary files, renames and a mode change.
+    fn stat_fixture() -> (tempfile::TempDir, Repository, String, String) {
+        let (dir, repo) = init_repo();
+        let many: String = (0..200).map(|n| format!("line {}\n", n)).collect();
+        let long = "a/very/long/directory/name/that/goes/on/and/on/file.txt";
+        let base = write_commit(
+            &repo,
+            &[],
+            &[
+                ("small", "one\ntwo\n"),
+                ("many", &many),
+                (long, "x\n"),
+                ("bin", "\0\x01\x02"),
+                ("src/one/moved.rs", "fn a() {}\nfn b() {}\nfn c() {}\n"),
+                ("script", "echo\n"),
+            ],
+            "base",
+        );
+        let many_edited = many.replace("line 1", "LINE 1") + &"more\n".repeat(50);
+        let tip = write_commit(
+            &repo,
+            &[base],
+            &[
+                ("small", "one\n2\n"),
+                ("many", &many_edited),
+                (long, "y\n"),
+                ("bin", "\0\x01\x02\x03\x04"),
+                (
+                    "src/two/moved.rs",
+                    "fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\n",
+                ),
+                ("script", "echo\n"),
+            ],
+            "tip",
+        );
+        let odb = repo.odb();
+        // Make `script` executable in the tip.
+        let mut commit = odb.read_commit(&tip).unwrap();
+        let mut flat = tree::flatten(odb, &commit.tree).unwrap();
+        flat.get_mut("script").unwrap().mode = MODE_EXECUTABLE;
+        commit.tree = tree::build(&mut LooseObjectWriter::new(odb), &flat).unwrap();
+        let tip = odb.write(&GitObject::Commit(commit)).unwrap();
+        (dir, repo, base.to_string(), tip.to_string())
+    }
+
+    fn run_revs(repo: &Repository, old: &str, new: &str, options: &DiffOptions) -> String {
+        let mut out = Vec::new();
+        diff_revs(repo, old, new, &Pathspec::default(), options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_stats_like_git() {
+        let (_dir, repo, base, tip) = stat_fixture();
+        for width in [40, 60, 80, 120] {
+            for renames in [None, Some(RenameOptions::default())] {
+                let options = DiffOptions {
+                    output: DiffOutput::Stat,
+                    stat_width: Some(width),
+                    renames,
+                    ..Default::default()
+                };
+                let ours = run_revs(&repo, &base, &tip, &options);
+                let stat = format!("--stat={}", width);
+                let rename_flag = if renames.is_some() {
+                    "-M"
+                } else {
+                    "--no-renames"
+                };
+                if let Some(theirs) = git(&repo, &["diff", &stat, rename_flag, &base, &tip]) {
+                    assert_eq!(ours, theirs, "width {} {}", width, rename_flag);
+                }
+            }
+        }
+        let options = DiffOptions {
+            output: DiffOutput::Stat,
+            stat_width: Some(80),
+            renames: Some(RenameOptions::default()),
+            ..Default::default()
+        };
+        let out = run_revs(&repo, &base, &tip, &options);
+        assert!(out.contains(" bin  "));
+        assert!(out.contains("| Bin 3 -> 5 bytes\n"));
+        assert!(out.contains(" src/{one => two}/moved.rs "));
+        assert!(out.ends_with(" 6 files changed, 164 insertions(+), 113 deletions(-)\n"));
+    }
+
+    #[test]
+    fn writes_numstat_like_git() {
+        let (_dir, repo, base, tip) = stat_fixture();
+        let options = DiffOptions {
+            output: DiffOutput::NumStat,
+            renames: Some(RenameOptions::default()),
+            ..Default::default()
+        };
+        let out = run_revs(&repo, &base, &tip, &options);
+        assert!(out.starts_with(
+            "1\t1\ta/very/long/directory/name/that/goes/on/and/on/file.txt\n-\t-\tbin\n"
+        ));
+        assert!(out.ends_with("0\t0\tscript\n1\t1\tsmall\n1\t0\tsrc/{one => two}/moved.rs\n"));
+        if let Some(theirs) = git(&repo, &["diff", "--numstat", "-M", &base, &tip]) {
+            assert_eq!(out, theirs);
+        }
+        let staged = DiffOptions {
+            output: DiffOutput::NumStat,
+            cached: true,
+            ..Default::default()
+        };
+        checkout(&repo, "master", &tip.parse::<Oid>().unwrap());
+        stage_file(&repo, "small", "three\n");
+        let mut out = Vec::new();
+        diff(&repo, &staged, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1\t2\tsmall\n");
+    }
+
+    #[test]
+    fn writes_word_diffs_like_git() {
+        let (_dir, repo, base, tip) = stat_fixture();
+        for (mode, flag) in [
+            (WordDiffMode::Plain, "--word-diff=plain"),
+            (WordDiffMode::Porcelain, "--word-diff=porcelain"),
+        ] {
+            let options = DiffOptions {
+                word_diff: mode,
+                word_regex: Some(Regex::new("[^[:space:]]").unwrap()),
+                ..Default::default()
+            };
+            let out = run_revs(&repo, &base, &tip, &options);
+            let regex = "--word-diff-regex=[^[:space:]]";
+            let args = ["diff", "--no-renames", flag, regex, &base, &tip];
+            if let Some(theirs) = git(&repo, &args) {
+                assert_eq!(out, theirs);
+            }
+        }
+    }
+
+    #[test]
+    fn takes_the_algorithm_from_config() {
+        let (_dir, repo) = init_repo();
+        let old = "fn a() {\n    return;\n}\n\nfn b() {\n    return;\n    b1();\n}\n\n";
+        let new = "fn b() {\n    return;\n    b1();\n}\n\nfn z() {\n    return;\n}\n\n";
+        let base = write_commit(&repo, &[], &[("lib.rs", old)], "base").to_string();
+        let tip = write_commit(&repo, &[], &[("lib.rs", new)], "tip").to_string();
+        let myers = run_revs(&repo, &base, &tip, &DiffOptions::default());
+        config_set(&repo, "diff.algorithm", "patience").unwrap();
+        let patience = run_revs(&repo, &base, &tip, &DiffOptions::default());
+        assert_ne!(patience, myers);
+        assert!(patience.contains("\n-fn a() {\n-    return;\n-}\n-\n fn b() {\n"));
+        let args = ["diff", "--no-indent-heuristic", &base, &tip];
+        if let Some(theirs) = git(&repo, &args) {
+            assert_eq!(patience, theirs);
+        }
+
+        // An explicit algorithm wins over the config.
+        let options = DiffOptions {
+            algorithm: Some(DiffAlgorithm::Myers),
+            ..Default::default()
+        };
+        assert_eq!(run_revs(&repo, &base, &tip, &options), myers);
+        let args = [
+            "diff",
+            "--no-indent-heuristic",
+            "--diff-algorithm=myers",
+            &base,
+            &tip,
+        ];
+        if let Some(theirs) = git(&repo, &args) {
+            assert_eq!(myers, theirs);
+        }
+
+        config_set(&repo, "diff.algorithm", "quadratic").unwrap();
+        let mut out = Vec::new();
+        let result = diff_revs(
+            &repo,
+            &base,
+            &tip,
+            &Pathspec::default(),
+            &DiffOptions::default(),
+            &mut out,
+        );
+        assert!(matches!(result, Err(GitError::InvalidConfigValue { .. })));
+    }
+
+    #[test]
+    fn writes_names_like_git() {
+        let (_dir, repo) = init_repo();
+        let body: String = (0..20).map(|n| format!("line {}\n", n)).collect();
+        let edited = body.replace("line 5\n", "line five\n");
+        let other = body.replace("line", "row");
+        let other_edited = other.replace("row 9\n", "row nine\n");
+        let base = write_commit(
+            &repo,
+            &[],
+            &[
+                ("plain", "gone\n"),
+                ("tab\there", "t\n"),
+                ("new\nline", &other),
+                ("caf\u{e9}/\u{fc}n\u{ef}.txt", &body),
+                ("link", "target\n"),
+            ],
+            "base",
+        );
+        let tip = write_commit(
+            &repo,
+            &[base],
+            &[
+                ("tab\there", "t2\n"),
+                ("moved\nline", &other_edited),
+                ("caf\u{e9}/\u{fc}n\u{ef}.txt", &edited),
+                ("copy \"of\" it", &edited),
+                ("link", "target\n"),
+            ],
+            "tip",
+        );
+        // Make `link` a symlink in the tip.
+        let odb = repo.odb();
+        let mut commit = odb.read_commit(&tip).unwrap();
+        let mut flat = tree::flatten(odb, &commit.tree).unwrap();
+        flat.get_mut("link").unwrap().mode = MODE_SYMLINK;
+        commit.tree = tree::build(&mut LooseObjectWriter::new(odb), &flat).unwrap();
+        let tip = odb.write(&GitObject::Commit(commit)).unwrap();
+        let (base, tip) = (base.to_string(), tip.to_string());
+
+        let copies = RenameOptions {
+            copies: true,
+            ..RenameOptions::default()
+        };
+        for (output, flag) in [
+            (DiffOutput::NameOnly, "--name-only"),
+            (DiffOutput::NameStatus, "--name-status"),
+        ] {
+            for nul_terminated in [false, true] {
+                for (renames, detect) in [(None, "--no-renames"), (Some(copies), "-C")] {
+                    let options = DiffOptions {
+                        output,
+                        nul_terminated,
+                        renames,
+                        ..Default::default()
+                    };
+                    let ours = run_revs(&repo, &base, &tip, &options);
+                    let mut args = vec!["diff", flag, detect, &base, &tip];
+                    if nul_terminated {
+                        args.insert(1, "-z");
+                    }
+                    if let Some(theirs) = git(&repo, &args) {
+                        assert_eq!(ours, theirs, "{:?}", args);
+                    }
+                }
+            }
+        }
+
+        let options = DiffOptions {
+            output: DiffOutput::NameStatus,
+            renames: Some(copies),
+            ..Default::default()
+        };
+        assert_eq!(
+            run_revs(&repo, &base, &tip, &options),
+            concat!(
+                "M\t\"caf\\303\\251/\\303\\274n\\303\\257.txt\"\n",
+                "C093\t\"caf\\303\\251/\\303\\274n\\303\\257.txt\"\t\"copy \\\"of\\\" it\"\n",
+                "T\tlink\n",
+                "R093\t\"new\\nline\"\t\"moved\\nline\"\n",
+                "D\tplain\n",
+                "M\t\"tab\\there\"\n",
+            )
+        );
+        // Unquoted, the names come back as they went in.
+        let options = DiffOptions {
+            output: DiffOutput::NameOnly,
+            nul_terminated: true,
+            ..options
+        };
+        let names = run_revs(&repo, &base, &tip, &options);
+        let names: Vec<&str> = names.split_terminator('\0').collect();
+        assert_eq!(
+            names,
+            [
+                "caf\u{e9}/\u{fc}n\u{ef}.txt",
+                "copy \"of\" it",
+                "link",
+                "moved\nline",
+                "plain",
+                "tab\there"
+            ]
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! `git diff-tree -r`: the changed files between two trees, one line each.
+
+use std::io::Write;
+
+use crate::commands::diff::rev_tree;
+use crate::core::diff::{tree_diff, FileDelta};
+use crate::core::oid::NULL_OID;
+use crate::core::pathspec::Pathspec;
+use crate::core::tree::FlatEntry;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffTreeFormat {
+    /// `:<old mode> <new mode> <old id> <new id> <status>\t<path>`, as
+    /// `--raw` writes, with zeros for a side that's missing.
+    #[default]
+    Raw,
+    /// `<status>\t<path>`, as `--name-status` writes.
+    NameStatus,
+}
+
+/// Write a line in `format` for every file that differs between the
+/// trees of the commit-ish revisions `old` and `new`, recursing into
+/// subtrees, at the paths `pathspec` selects.
+pub fn diff_tree(
+    repo: &Repository,
+    old: &str,
+    new: &str,
+    pathspec: &Pathspec,
+    format: DiffTreeFormat,
+    mut out: impl Write,
+) -> GitResult<()> {
+    let (old, new) = (rev_tree(repo, old)?, rev_tree(repo, new)?);
+    let mut buf = String::new();
+    for delta in tree_diff(repo.odb(), Some(&old), Some(&new), pathspec)? {
+        buf.push_str(&line(&delta, format));
+    }
+    out.write_all(buf.as_bytes())?;
+    Ok(())
+}
+
+fn line(delta: &FileDelta, format: DiffTreeFormat) -> String {
+    let status = delta.status.letter();
+    match format {
+        DiffTreeFormat::NameStatus => format!("{}\t{}\n", status, delta.path),
+        DiffTreeFormat::Raw => {
+            let mode = |e: Option<&FlatEntry>| e.map_or(0, |e| e.mode);
+            let id = |e: Option<&FlatEntry>| e.map_or(NULL_OID, |e| e.oid);
+            format!(
+                ":{:06o} {:06o} {} {} {}\t{}\n",
+                mode(delta.old.as_ref()),
+                mode(delta.new.as_ref()),
+                id(delta.old.as_ref()),
+                id(delta.new.as_ref()),
+                status,
+                delta.path
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{git, init_repo, set_ref, write_commit};
+
+    fn run(repo: &Repository, pathspec: &[&str], format: DiffTreeFormat) -> String {
+        let mut out = Vec::new();
+        let pathspec = Pathspec::parse(pathspec);
+        diff_tree(repo, "HEAD~1", "HEAD", &pathspec, format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_raw_and_name_status_lines() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(
+            &repo,
+            &[],
+            &[("a.txt", "a\n"), ("lib/x.rs", "x\n"), ("lib/y.rs", "y\n")],
+            "base",
+        );
+        let tip = write_commit(
+            &repo,
+            &[base],
+            &[("a.txt", "A\n"), ("lib/x.rs", "x\n"), ("new.txt", "n\n")],
+            "tip",
+        );
+        set_ref(&repo, "refs/heads/master", &tip);
+
+        assert_eq!(
+            run(&repo, &[], DiffTreeFormat::NameStatus),
+            "M\ta.txt\nD\tlib/y.rs\nA\tnew.txt\n"
+        );
+        let raw = run(&repo, &[], DiffTreeFormat::Raw);
+        assert!(raw.starts_with(":100644 100644 "));
+        assert!(raw.contains(&format!(":000000 100644 {} ", NULL_OID)));
+        assert_eq!(
+            run(&repo, &["lib"], DiffTreeFormat::NameStatus),
+            "D\tlib/y.rs\n"
+        );
+        if let Some(theirs) = git(&repo, &["diff-tree", "-r", "--raw", "HEAD~1", "HEAD"]) {
+            assert_eq!(raw, theirs);
+        }
+        let args = [
+            "diff-tree",
+            "-r",
+            "--name-status",
+            "HEAD~1",
+            "HEAD",
+            "--",
+            "lib",
+        ];
+        if let Some(theirs) = git(&repo, &args) {
+            assert_eq!(run(&repo, &["lib"], DiffTreeFormat::NameStatus), theirs);
+        }
+    }
+}
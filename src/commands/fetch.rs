@@ -0,0 +1,228 @@
+//! `git fetch`, from a remote on the same filesystem.
+//!
+//! The remote's refs are mapped through its `fetch` refspecs onto
+//! remote-tracking refs, and the objects the updated ones need copied
+//! across. Local branches, `HEAD` and the working tree are left alone;
+//! tags aren't followed.
+
+use std::path::Path;
+
+use crate::commands::remote::find_remote;
+use crate::core::oid::Oid;
+use crate::core::refs::{self, Transaction};
+use crate::core::refspec::RefSpec;
+use crate::core::revwalk;
+use crate::error::{GitError, GitResult};
+use crate::remote::local;
+use crate::repository::Repository;
+
+/// How a fetch changed, or declined to change, one ref.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefUpdateKind {
+    /// The ref didn't exist before.
+    New,
+    FastForward,
+    /// The ref was moved to a commit that doesn't descend from its old
+    /// one, as a `+` refspec allows.
+    Forced,
+    /// The update wasn't a fast-forward and the refspec doesn't force it,
+    /// so the ref was left where it was.
+    Rejected,
+}
+
+/// One ref a fetch moved, or would have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefUpdate {
+    /// The ref on the remote.
+    pub remote_ref: String,
+    /// The local ref it's fetched into.
+    pub local_ref: String,
+    pub old: Option<Oid>,
+    pub new: Oid,
+    pub kind: RefUpdateKind,
+}
+
+/// Fetch from the remote called `remote`, updating the refs its refspecs
+/// map the remote's refs to and returning every update, in the order of
+/// the remote's refs. Refs that are already up to date aren't reported.
+/// The refs are moved together: if one can't be, none are.
+pub fn fetch(repo: &Repository, remote: &str) -> GitResult<Vec<RefUpdate>> {
+    let config = find_remote(repo, remote)?;
+    let url = config
+        .as_ref()
+        .and_then(|r| r.url.as_deref())
+        .ok_or_else(|| GitError::RemoteNotFound(remote.to_string()))?;
+    let source = Repository::open(Path::new(url.strip_prefix("file://").unwrap_or(url)))?;
+    let mut specs = Vec::new();
+    for spec in config.iter().flat_map(|r| &r.fetch) {
+        specs.push(RefSpec::parse(spec)?);
+    }
+
+    let mut updates = Vec::new();
+    for advertised in local::advertise(&source)? {
+        let mapped = specs
+            .iter()
+            .find_map(|spec| Some((spec.matches(&advertised.name)?, spec.is_force())));
+        let (local_ref, force) = match mapped {
+            Some(mapped) => mapped,
+            None => continue,
+        };
+        let old = refs::follow(repo, &local_ref)?.1;
+        if old == Some(advertised.oid)
+            || updates.iter().any(|u: &RefUpdate| u.local_ref == local_ref)
+        {
+            continue;
+        }
+        let kind = match old {
+            None => RefUpdateKind::New,
+            // The old commit has to be checked in the source: it's where
+            // the new one's history is.
+            Some(old)
+                if source.odb().contains(&old)
+                    && revwalk::is_ancestor(source.odb(), &old, &advertised.oid)? =>
+            {
+                RefUpdateKind::FastForward
+            }
+            Some(_) if force => RefUpdateKind::Forced,
+            Some(_) => RefUpdateKind::Rejected,
+        };
+        updates.push(RefUpdate {
+            remote_ref: advertised.name,
+            local_ref,
+            old,
+            new: advertised.oid,
+            kind,
+        });
+    }
+
+    let applied = updates.iter().filter(|u| u.kind != RefUpdateKind::Rejected);
+    let wants: Vec<Oid> = applied.clone().map(|u| u.new).collect();
+    local::copy_objects(&source, repo, &wants)?;
+    let mut transaction = Transaction::new(repo);
+    for update in applied {
+        transaction.update(&update.local_ref, update.new, Some(update.old));
+    }
+    transaction.commit(&format!("fetch {}", remote))?;
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::clone::clone_local;
+    use crate::commands::config::config_set;
+    use crate::core::reflog;
+    use crate::repository::Head;
+    use crate::test_utils::{checkout, init_repo, read_file, set_ref, write_commit};
+
+    #[test]
+    fn updates_only_the_remote_tracking_refs() {
+        let (src_dir, src) = init_repo();
+        let base = write_commit(&src, &[], &[("a", "a\n")], "base");
+        checkout(&src, "main", &base);
+        let dst_dir = tempfile::tempdir().unwrap();
+        let repo = clone_local(src_dir.path(), &dst_dir.path().join("clone")).unwrap();
+        assert_eq!(fetch(&repo, "origin").unwrap(), []);
+
+        let next = write_commit(&src, &[base], &[("a", "next\n")], "next");
+        set_ref(&src, "refs/heads/main", &next);
+        set_ref(&src, "refs/heads/topic", &base);
+        let updates = fetch(&repo, "origin").unwrap();
+        assert_eq!(
+            updates,
+            [
+                RefUpdate {
+                    remote_ref: "refs/heads/main".to_string(),
+                    local_ref: "refs/remotes/origin/main".to_string(),
+                    old: Some(base),
+                    new: next,
+                    kind: RefUpdateKind::FastForward,
+                },
+                RefUpdate {
+                    remote_ref: "refs/heads/topic".to_string(),
+                    local_ref: "refs/remotes/origin/topic".to_string(),
+                    old: None,
+                    new: base,
+                    kind: RefUpdateKind::New,
+                },
+            ]
+        );
+        assert_eq!(
+            refs::resolve(&repo, "refs/remotes/origin/main").unwrap(),
+            next
+        );
+        assert!(repo.odb().contains(&next));
+        assert_eq!(
+            repo.head().unwrap(),
+            Head::Branch("refs/heads/main".to_string(), Some(base))
+        );
+        assert_eq!(read_file(&repo, "a"), "a\n");
+        let log = reflog::read(&repo, "refs/remotes/origin/main").unwrap();
+        assert_eq!(log.last().unwrap().message, "fetch origin");
+
+        // History rewritten on the remote is only taken with a `+`.
+        let rewritten = write_commit(&src, &[], &[("a", "other\n")], "rewritten");
+        set_ref(&src, "refs/heads/main", &rewritten);
+        config_set(
+            &repo,
+            "remote.origin.fetch",
+            "refs/heads/*:refs/remotes/origin/*",
+        )
+        .unwrap();
+        let updates = fetch(&repo, "origin").unwrap();
+        assert_eq!(updates[0].kind, RefUpdateKind::Rejected);
+        assert_eq!(
+            refs::resolve(&repo, "refs/remotes/origin/main").unwrap(),
+            next
+        );
+        config_set(
+            &repo,
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/remotes/origin/*",
+        )
+        .unwrap();
+        assert_eq!(
+            fetch(&repo, "origin").unwrap()[0].kind,
+            RefUpdateKind::Forced
+        );
+        assert_eq!(
+            refs::resolve(&repo, "refs/remotes/origin/main").unwrap(),
+            rewritten
+        );
+
+        assert!(matches!(
+            fetch(&repo, "upstream"),
+            Err(GitError::RemoteNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn moves_no_ref_unless_it_can_move_them_all() {
+        let (src_dir, src) = init_repo();
+        let base = write_commit(&src, &[], &[("a", "a\n")], "base");
+        checkout(&src, "main", &base);
+        let dst_dir = tempfile::tempdir().unwrap();
+        let repo = clone_local(src_dir.path(), &dst_dir.path().join("clone")).unwrap();
+        let next = write_commit(&src, &[base], &[("a", "next\n")], "next");
+        set_ref(&src, "refs/heads/main", &next);
+        set_ref(&src, "refs/heads/topic", &next);
+
+        let lock = repo.git_dir().join("refs/remotes/origin/topic.lock");
+        std::fs::write(&lock, "").unwrap();
+        assert!(matches!(
+            fetch(&repo, "origin"),
+            Err(GitError::RefLockConflict(_))
+        ));
+        assert_eq!(
+            refs::resolve(&repo, "refs/remotes/origin/main").unwrap(),
+            base
+        );
+        assert_eq!(
+            refs::follow(&repo, "refs/remotes/origin/topic").unwrap().1,
+            None
+        );
+
+        std::fs::remove_file(&lock).unwrap();
+        assert_eq!(fetch(&repo, "origin").unwrap().len(), 2);
+    }
+}
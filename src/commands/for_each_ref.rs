@@ -0,0 +1,213 @@
+//! `git for-each-ref --format=...`.
+//!
+//! Supported fields, each written `%(field)`:
+//!
+//! | field | expands to |
+//! |---|---|
+//! | `refname` `refname:short` | the ref's full name, or without `refs/heads/` and the like |
+//! | `objectname` `objectname:short` | the id the ref points at, full or abbreviated |
+//! | `objecttype` `objectsize` | the type and size of that object |
+//!
+//! Prefixed with `*`, as in `%(*objectname)`, a field describes the object
+//! an annotated tag points at instead, and is empty for other refs. `%%`
+//! is a literal `%`, and `%` followed by two hex digits the byte they
+//! spell.
+
+use crate::core::object::ObjectType;
+use crate::core::oid::Oid;
+use crate::core::refs::{self, Reference};
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// How far `objectname:short` abbreviates ids.
+const ABBREV: usize = 7;
+
+/// Every ref under `refs/`, or those `pattern` selects as
+/// [`refs::iter_matching`] matches them, sorted by name and each expanded
+/// through `format`.
+pub fn for_each_ref(
+    repo: &Repository,
+    pattern: Option<&str>,
+    format: &str,
+) -> GitResult<Vec<String>> {
+    let refs = match pattern {
+        Some(pattern) => refs::iter_matching(repo, pattern)?,
+        None => refs::iter(repo)?,
+    };
+    let mut lines = Vec::new();
+    for reference in refs {
+        lines.push(expand(repo, &reference?, format)?);
+    }
+    Ok(lines)
+}
+
+/// `format` filled in for one ref.
+fn expand(repo: &Repository, reference: &Reference, format: &str) -> GitResult<String> {
+    let mut out = String::with_capacity(format.len());
+    let mut rest = format;
+    while let Some(percent) = rest.find('%') {
+        out.push_str(&rest[..percent]);
+        rest = &rest[percent + 1..];
+        if let Some(after) = rest.strip_prefix('%') {
+            out.push('%');
+            rest = after;
+        } else if let Some(field) = rest.strip_prefix('(') {
+            let end = field
+                .find(')')
+                .ok_or_else(|| GitError::UnknownFormatField(field.to_string()))?;
+            out.push_str(&field_value(repo, reference, &field[..end])?);
+            rest = &field[end + 1..];
+        } else if let Some(byte) = rest
+            .get(..2)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(char::from(byte));
+            rest = &rest[2..];
+        } else {
+            out.push('%');
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn field_value(repo: &Repository, reference: &Reference, field: &str) -> GitResult<String> {
+    let odb = repo.odb();
+    let unknown = || GitError::UnknownFormatField(field.to_string());
+    let (name, modifier) = match field.split_once(':') {
+        Some((name, modifier)) => (name, Some(modifier)),
+        None => (field, None),
+    };
+    let (name, id) = match name.strip_prefix('*') {
+        Some(name) => match peel_once(repo, &reference.target)? {
+            Some(id) => (name, id),
+            None => return Ok(String::new()),
+        },
+        None => (name, reference.target),
+    };
+    let value = match (name, modifier) {
+        ("refname", None) => reference.name.clone(),
+        ("refname", Some("short")) => refs::shorten(&reference.name).to_string(),
+        ("objectname", None) => id.to_string(),
+        ("objectname", Some("short")) => odb.abbreviate(&id, ABBREV)?,
+        ("objecttype", None) => odb.read_header(&id)?.0.as_str().to_string(),
+        ("objectsize", None) => odb.read_header(&id)?.1.to_string(),
+        _ => return Err(unknown()),
+    };
+    Ok(value)
+}
+
+/// What the tag `id` points at, if it is a tag.
+fn peel_once(repo: &Repository, id: &Oid) -> GitResult<Option<Oid>> {
+    let odb = repo.odb();
+    if odb.read_header(id)?.0 != ObjectType::Tag {
+        return Ok(None);
+    }
+    Ok(Some(odb.read_tag(id)?.object))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tag;
+    use crate::test_utils::{git, init_repo, set_ref, signature, write_commit};
+
+    #[test]
+    fn formats_refs_like_git() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        let tip = write_commit(&repo, &[base], &[("a", "b")], "tip");
+        set_ref(&repo, "refs/heads/master", &tip);
+        set_ref(&repo, "refs/heads/topic", &base);
+        set_ref(&repo, "refs/remotes/origin/master", &base);
+        tag::create_lightweight(&repo, "light", "master~1", false).unwrap();
+        let v1 = tag::create_annotated(&repo, "v1.0", "master", "Release\n", signature(), false)
+            .unwrap();
+
+        let format =
+            "%(refname) %(refname:short) %(objectname:short) %(objecttype)|%(*objectname)%%";
+        let lines = for_each_ref(&repo, None, format).unwrap();
+        let names: Vec<&str> = lines
+            .iter()
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "refs/heads/master",
+                "refs/heads/topic",
+                "refs/remotes/origin/master",
+                "refs/tags/light",
+                "refs/tags/v1.0",
+            ]
+        );
+        assert_eq!(
+            lines[4],
+            format!(
+                "refs/tags/v1.0 v1.0 {} tag|{}%",
+                repo.odb().abbreviate(&v1, ABBREV).unwrap(),
+                tip
+            )
+        );
+        if let Some(theirs) = git(&repo, &["for-each-ref", &format!("--format={}", format)]) {
+            assert_eq!(lines.join("\n") + "\n", theirs);
+        }
+
+        assert!(matches!(
+            for_each_ref(&repo, None, "%(bogus)"),
+            Err(GitError::UnknownFormatField(field)) if field == "bogus"
+        ));
+    }
+
+    #[test]
+    fn filters_by_pattern() {
+        let (_dir, repo) = init_repo();
+        let commit = write_commit(&repo, &[], &[("a", "a")], "base");
+        for name in [
+            "refs/heads/main",
+            "refs/tags/v1",
+            "refs/tags/v2",
+            "refs/notes/x",
+        ] {
+            set_ref(&repo, name, &commit);
+        }
+        let format = "%(refname:short) %(objectname)";
+        let tags = for_each_ref(&repo, Some("refs/tags/*"), format).unwrap();
+        let hex = commit.to_string();
+        assert_eq!(tags, [format!("v1 {}", hex), format!("v2 {}", hex)]);
+        let args = [
+            "for-each-ref",
+            "--format=%(refname:short) %(objectname)",
+            "refs/tags/*",
+        ];
+        if let Some(theirs) = git(&repo, &args) {
+            assert_eq!(tags.join("\n") + "\n", theirs);
+        }
+        assert_eq!(
+            for_each_ref(&repo, Some("refs/heads"), "%(refname)").unwrap(),
+            ["refs/heads/main"]
+        );
+    }
+
+    #[test]
+    fn lists_packed_refs_behind_loose_ones() {
+        let (_dir, repo) = init_repo();
+        std::fs::copy(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/packed-refs"),
+            repo.git_dir().join("packed-refs"),
+        )
+        .unwrap();
+        let loose = write_commit(&repo, &[], &[("a", "a")], "loose");
+        set_ref(&repo, "refs/tags/v1.0", &loose);
+        let tags =
+            for_each_ref(&repo, Some("refs/tags"), "%(refname:short) %(objectname)").unwrap();
+        assert_eq!(
+            tags,
+            [
+                format!("v1.0 {}", loose),
+                "v1.1-light 670284a0b9ad86abaf35be65616d427233a73ae2".to_string(),
+                "v2.0 5fd8265405141db909a28c093d9bdf6c2b14083b".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//! `git format-patch --stdout`: commits as emails in mbox format.
+//!
+//! Each patch has the headers git writes, the rest of the message as the
+//! email's body, and the commit's changes as a patch against its parent
+//! (or against nothing, for a root commit). Names and subjects that
+//! aren't plain ASCII are encoded as RFC 2047 says, and long subjects
+//! folded. There's no diffstat, as with `--no-stat`, and binary files are
+//! only reported as differing, as without `--binary`.
+
+use crate::commands::rev_list;
+use crate::core::object::Commit;
+use crate::core::oid::Oid;
+use crate::core::patch;
+use crate::core::tree::{self, FlatTree};
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// What ends each patch after the `-- ` line.
+pub const SIGNATURE: &str = concat!("grit ", env!("CARGO_PKG_VERSION"));
+
+/// The longest header line folding aims for.
+const MAX_LINE: usize = 78;
+
+/// One patch for each non-merge commit `range` selects, oldest first.
+/// `range` is `A..B`, or a single revision meaning everything since it,
+/// `<since>..HEAD`, as it does for git. With more than one patch the
+/// subjects are numbered `[PATCH 1/3]` and so on.
+pub fn format_patch(repo: &Repository, range: &str) -> GitResult<Vec<String>> {
+    let range = if range.contains("..") {
+        range.to_string()
+    } else {
+        format!("{}..HEAD", range)
+    };
+    let odb = repo.odb();
+    let mut commits = Vec::new();
+    for id in rev_list::rev_list(repo, &[range], &[], None)? {
+        let commit = odb.read_commit(&id)?;
+        if commit.parents.len() < 2 {
+            commits.push((id, commit));
+        }
+    }
+    commits.reverse();
+
+    let total = commits.len();
+    let mut patches = Vec::with_capacity(total);
+    for (n, (id, commit)) in commits.iter().enumerate() {
+        let prefix = if total > 1 {
+            format!("[PATCH {}/{}]", n + 1, total)
+        } else {
+            "[PATCH]".to_string()
+        };
+        let old = match commit.parents.first() {
+            Some(parent) => tree::flatten(odb, &odb.read_commit(parent)?.tree)?,
+            None => FlatTree::new(),
+        };
+        let mut diff = Vec::new();
+        patch::write_tree_patch(&mut diff, odb, &old, &tree::flatten(odb, &commit.tree)?)?;
+        patches.push(email(id, commit, &prefix, &String::from_utf8_lossy(&diff)));
+    }
+    Ok(patches)
+}
+
+fn email(id: &Oid, commit: &Commit, prefix: &str, diff: &str) -> String {
+    // The subject is the message's first paragraph on one line.
+    let message = commit.message.trim_start_matches('\n');
+    let (subject, body) = match message.find("\n\n") {
+        Some(end) => (&message[..end], message[end + 2..].trim_start_matches('\n')),
+        None => (message.trim_end_matches('\n'), ""),
+    };
+    let subject = subject.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+    let author = &commit.author;
+
+    let mut out = format!("From {} Mon Sep 17 00:00:00 2001\n", id);
+    out.push_str("From: ");
+    if author.name.is_ascii() {
+        out.push_str(&quote_name(&author.name));
+    } else {
+        add_rfc2047(&mut out, &author.name, true);
+    }
+    out.push_str(&format!(" <{}>\n", author.email));
+    out.push_str(&format!("Date: {}\n", author.to_rfc2822()));
+    out.push_str(&format!("Subject: {} ", prefix));
+    if needs_encoding(&subject) {
+        add_rfc2047(&mut out, &subject, false);
+    } else {
+        add_folded(&mut out, &subject);
+    }
+    out.push('\n');
+    if !commit.message.is_ascii() || !author.name.is_ascii() {
+        out.push_str(
+            "MIME-Version: 1.0\n\
+             Content-Type: text/plain; charset=UTF-8\n\
+             Content-Transfer-Encoding: 8bit\n",
+        );
+    }
+    out.push('\n');
+    out.push_str(body);
+    out.push('\n');
+    out.push_str(diff);
+    out.push_str(&format!("-- \n{}\n\n", SIGNATURE));
+    out
+}
+
+/// `name` as the display name of an address, in double quotes when it
+/// has characters that would otherwise mean something there.
+fn quote_name(name: &str) -> String {
+    if !name.contains(|c| "()<>[]:;@\\,.\"".contains(c)) {
+        return name.to_string();
+    }
+    let escaped: String = name
+        .chars()
+        .flat_map(|c| match c {
+            '"' | '\\' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect();
+    format!("\"{}\"", escaped)
+}
+
+/// Append `text` as words, starting a continuation line before any word
+/// that would take the line past [`MAX_LINE`].
+fn add_folded(out: &mut String, text: &str) {
+    let mut line_len = last_line_len(out);
+    for (n, word) in text.split(' ').enumerate() {
+        if n > 0 {
+            if line_len + 1 + word.len() > MAX_LINE {
+                out.push_str("\n ");
+                line_len = 1;
+            } else {
+                out.push(' ');
+                line_len += 1;
+            }
+        }
+        out.push_str(word);
+        line_len += word.len();
+    }
+}
+
+fn needs_encoding(text: &str) -> bool {
+    !text.is_ascii() || text.contains("=?")
+}
+
+/// Append `text` as RFC 2047 `Q`-encoded words, breaking into another
+/// encoded word on a continuation line where the line would pass 76
+/// characters, and never splitting a character.
+fn add_rfc2047(out: &mut String, text: &str, address: bool) {
+    const MAX_ENCODED: usize = 76;
+    const START: &str = "=?UTF-8?q?";
+    let mut line_len = last_line_len(out) + START.len();
+    out.push_str(START);
+    for c in text.chars() {
+        let mut bytes = [0; 4];
+        let bytes = c.encode_utf8(&mut bytes).as_bytes();
+        let special = bytes.len() > 1 || is_rfc2047_special(bytes[0], address);
+        let encoded_len = if special { 3 * bytes.len() } else { 1 };
+        if line_len + encoded_len + 2 > MAX_ENCODED {
+            out.push_str("?=\n ");
+            out.push_str(START);
+            line_len = START.len() + 1;
+        }
+        if special {
+            for byte in bytes {
+                out.push_str(&format!("={:02X}", byte));
+            }
+        } else {
+            out.push(c);
+        }
+        line_len += encoded_len;
+    }
+    out.push_str("?=");
+}
+
+fn is_rfc2047_special(byte: u8, address: bool) -> bool {
+    if !byte.is_ascii_graphic() || matches!(byte, b'=' | b'?' | b'_') {
+        return true;
+    }
+    address && !(byte.is_ascii_alphanumeric() || b"!*+-/".contains(&byte))
+}
+
+fn last_line_len(out: &str) -> usize {
+    out.len() - out.rfind('\n').map_or(0, |i| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::signature::Signature;
+    use crate::test_utils::{git, init_repo, set_ref, write_commit};
+
+    #[test]
+    fn formats_a_commit_as_an_email() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(
+            &repo,
+            &[],
+            &[("a.txt", "one\ntwo\n"), ("gone", "x\n")],
+            "base",
+        );
+        let tip = write_commit(
+            &repo,
+            &[base],
+            &[("a.txt", "one\n2\n"), ("new/b.txt", "b\n")],
+            "Change two\n\nThe body\nexplains it.",
+        );
+        set_ref(&repo, "refs/heads/master", &tip);
+
+        let patches = format_patch(&repo, "HEAD~1").unwrap();
+        assert_eq!(patches.len(), 1);
+        let patch = &patches[0];
+        let (headers, rest) = patch.split_once("\n\n").unwrap();
+        let headers: Vec<&str> = headers.lines().collect();
+        assert_eq!(headers[0], format!("From {} Mon Sep 17 00:00:00 2001", tip));
+        assert_eq!(headers[1], "From: A U Thor <author@example.com>");
+        assert!(headers[2].starts_with("Date: "));
+        assert_eq!(headers[3], "Subject: [PATCH] Change two");
+        let (body, diff) = rest.split_once("\ndiff --git").unwrap();
+        assert_eq!(body, "The body\nexplains it.\n");
+        let diff = format!("diff --git{}", diff);
+        let blob = |content: &str| {
+            let id = repo
+                .odb()
+                .write_raw(crate::core::object::ObjectType::Blob, content.as_bytes())
+                .unwrap();
+            repo.odb().abbreviate(&id, 7).unwrap()
+        };
+        assert_eq!(
+            diff,
+            format!(
+                "diff --git a/a.txt b/a.txt\nindex {}..{} 100644\n--- a/a.txt\n+++ b/a.txt\n\
+                 @@ -1,2 +1,2 @@\n one\n-two\n+2\n\
+                 diff --git a/gone b/gone\ndeleted file mode 100644\nindex {}..0000000\n\
+                 --- a/gone\n+++ /dev/null\n@@ -1 +0,0 @@\n-x\n\
+                 diff --git a/new/b.txt b/new/b.txt\nnew file mode 100644\nindex 0000000..{}\n\
+                 --- /dev/null\n+++ b/new/b.txt\n@@ -0,0 +1 @@\n+b\n\
+                 -- \n{}\n\n",
+                blob("one\ntwo\n"),
+                blob("one\n2\n"),
+                blob("x\n"),
+                blob("b\n"),
+                SIGNATURE
+            )
+        );
+        let args = [
+            "format-patch",
+            "--stdout",
+            "--no-stat",
+            "--signature",
+            SIGNATURE,
+            "HEAD~1",
+        ];
+        if let Some(theirs) = git(&repo, &args) {
+            assert_eq!(*patch, theirs);
+        }
+    }
+
+    #[test]
+    fn numbers_a_series_and_encodes_headers() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("f", "0\n")], "base");
+        let long =
+            "a subject long enough that it has to be folded onto a second line of the header";
+        let first = write_commit(&repo, &[base], &[("f", "1\n")], long);
+        let odb = repo.odb();
+        let mut commit = odb.read_commit(&first).unwrap();
+        commit.parents = vec![first];
+        commit.tree = odb.read_commit(&base).unwrap().tree;
+        commit.author = Signature::new("Zoë \"Z\" Ünal", "z@example.com", 1_700_000_000, 60);
+        commit.message = "héllo wörld = ok?\n".to_string();
+        let second = odb
+            .write(&crate::core::object::GitObject::Commit(commit))
+            .unwrap();
+        set_ref(&repo, "refs/heads/master", &second);
+
+        let patches = format_patch(&repo, "HEAD~2..HEAD").unwrap();
+        assert_eq!(patches.len(), 2);
+        assert!(patches[0].contains(
+            "Subject: [PATCH 1/2] a subject long enough that it has to be folded onto a\n \
+             second line of the header\n\n"
+        ));
+        assert!(patches[1].contains(
+            "From: =?UTF-8?q?Zo=C3=AB=20=22Z=22=20=C3=9Cnal?= <z@example.com>\n\
+             Date: Tue, 14 Nov 2023 23:13:20 +0100\n\
+             Subject: [PATCH 2/2] =?UTF-8?q?h=C3=A9llo=20w=C3=B6rld=20=3D=20ok=3F?=\n\
+             MIME-Version: 1.0\n"
+        ));
+        let args = [
+            "format-patch",
+            "--stdout",
+            "--no-stat",
+            "--signature",
+            SIGNATURE,
+            "HEAD~2",
+        ];
+        if let Some(theirs) = git(&repo, &args) {
+            // `--stdout` separates the patches with a blank line.
+            assert_eq!(patches.join("\n"), theirs);
+        }
+    }
+}
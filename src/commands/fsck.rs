@@ -0,0 +1,223 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::core::object::{hash_object, GitObject, ObjectType, MODE_GITLINK, MODE_TREE};
+use crate::core::odb::ObjectDatabase;
+use crate::core::oid::Oid;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// Something wrong with one object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsckProblem {
+    pub id: Oid,
+    pub kind: ProblemKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// The object can't be read or doesn't parse as its type.
+    Corrupt(String),
+    /// The object's contents hash to `actual` rather than its id.
+    HashMismatch { actual: Oid },
+    /// The object refers to a `kind` object that isn't in the database.
+    BrokenLink {
+        from: ObjectType,
+        kind: ObjectType,
+        target: Oid,
+    },
+}
+
+impl fmt::Display for FsckProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = self.id.to_string();
+        match &self.kind {
+            ProblemKind::Corrupt(reason) => write!(f, "error: {}: {}", hex, reason),
+            ProblemKind::HashMismatch { actual } => write!(
+                f,
+                "error: hash mismatch for {} (contents hash to {})",
+                hex, actual
+            ),
+            ProblemKind::BrokenLink { from, kind, target } => write!(
+                f,
+                "broken link from {:>6} {}\n              to {:>6} {}",
+                from, hex, kind, target
+            ),
+        }
+    }
+}
+
+/// Verify every loose object: that it reads and parses, that its contents
+/// hash to its id, and that everything it refers to exists. Problems come
+/// back sorted by object id.
+pub fn fsck(repo: &Repository) -> GitResult<Vec<FsckProblem>> {
+    let mut problems = Vec::new();
+    for fanout in 0..=255 {
+        problems.extend(check_fanout(repo.odb(), fanout)?);
+    }
+    Ok(problems)
+}
+
+/// [`fsck`] spread over `threads` threads, each taking whole fan-out
+/// directories at a time; `0` uses as many threads as the machine can run
+/// at once. The result is the same as the serial scan's.
+pub fn fsck_parallel(repo: &Repository, threads: usize) -> GitResult<Vec<FsckProblem>> {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(256);
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let fanout = next.fetch_add(1, Ordering::Relaxed);
+                if fanout > 255 {
+                    break;
+                }
+                let checked = check_fanout(repo.odb(), fanout as u8);
+                let failed = checked.is_err();
+                results
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .push((fanout, checked));
+                if failed {
+                    break;
+                }
+            });
+        }
+    });
+    let mut results = results
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    // Each directory's problems are already in id order, and the
+    // directories partition the id space.
+    results.sort_by_key(|(fanout, _)| *fanout);
+    let mut problems = Vec::new();
+    for (_, checked) in results {
+        problems.extend(checked?);
+    }
+    Ok(problems)
+}
+
+fn check_fanout(odb: &ObjectDatabase, fanout: u8) -> GitResult<Vec<FsckProblem>> {
+    let mut problems = Vec::new();
+    for id in odb.loose_objects_in(fanout)? {
+        check_object(odb, id, &mut problems);
+    }
+    Ok(problems)
+}
+
+fn check_object(odb: &ObjectDatabase, id: Oid, problems: &mut Vec<FsckProblem>) {
+    let mut report = |kind| problems.push(FsckProblem { id, kind });
+    let (kind, body) = match odb.read_raw(&id) {
+        Ok(raw) => raw,
+        Err(err) => return report(ProblemKind::Corrupt(err.to_string())),
+    };
+    let actual = hash_object(kind, &body);
+    if actual != id {
+        return report(ProblemKind::HashMismatch { actual });
+    }
+    let object = match GitObject::parse(kind, &body) {
+        Ok(object) => object,
+        Err(err) => return report(ProblemKind::Corrupt(err.to_string())),
+    };
+    let links: Vec<(ObjectType, Oid)> = match &object {
+        GitObject::Blob(_) => Vec::new(),
+        GitObject::Tree(tree) => tree
+            .entries
+            .iter()
+            .filter(|entry| entry.mode != MODE_GITLINK)
+            .map(|entry| match entry.mode {
+                MODE_TREE => (ObjectType::Tree, entry.oid),
+                _ => (ObjectType::Blob, entry.oid),
+            })
+            .collect(),
+        GitObject::Commit(commit) => std::iter::once((ObjectType::Tree, commit.tree))
+            .chain(commit.parents.iter().map(|&p| (ObjectType::Commit, p)))
+            .collect(),
+        GitObject::Tag(tag) => vec![(tag.kind, tag.object)],
+    };
+    for (target_kind, target) in links {
+        if !odb.contains(&target) {
+            report(ProblemKind::BrokenLink {
+                from: kind,
+                kind: target_kind,
+                target,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::core::object::Commit;
+    use crate::test_utils::{init_repo, signature, write_commit};
+
+    #[test]
+    fn parallel_and_serial_scans_agree() {
+        let (_dir, repo) = init_repo();
+        let mut parent = None;
+        for n in 0..60 {
+            let content = n.to_string();
+            let files = [("a", content.as_str()), ("sub/b", "b")];
+            let parents: Vec<Oid> = parent.into_iter().collect();
+            parent = Some(write_commit(&repo, &parents, &files, &content));
+        }
+        let tip = parent.unwrap();
+
+        // A commit whose parent is missing, an object stored under the
+        // wrong id, and a file that isn't an object at all.
+        let orphan = repo
+            .odb()
+            .write(&GitObject::Commit(Commit {
+                tree: repo.odb().read_commit(&tip).unwrap().tree,
+                parents: vec![Oid::from_raw([0xab; 20])],
+                author: signature(),
+                committer: signature(),
+                extra_headers: Vec::new(),
+                message: "orphan\n".to_string(),
+            }))
+            .unwrap();
+        let objects = repo.odb().objects_dir();
+        let tip_hex = tip.to_string();
+        let misplaced = Oid::from_raw([0x00; 20]);
+        fs::create_dir_all(objects.join("00")).unwrap();
+        fs::copy(
+            objects.join(&tip_hex[..2]).join(&tip_hex[2..]),
+            objects.join("00").join(&misplaced.to_string()[2..]),
+        )
+        .unwrap();
+        let garbage = Oid::from_raw([0xff; 20]);
+        fs::create_dir_all(objects.join("ff")).unwrap();
+        fs::write(
+            objects.join("ff").join(&garbage.to_string()[2..]),
+            "not zlib",
+        )
+        .unwrap();
+
+        let serial = fsck(&repo).unwrap();
+        let ids: Vec<Oid> = serial.iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec![misplaced, orphan, garbage]);
+        assert_eq!(serial[0].kind, ProblemKind::HashMismatch { actual: tip });
+        assert_eq!(
+            serial[1].kind,
+            ProblemKind::BrokenLink {
+                from: ObjectType::Commit,
+                kind: ObjectType::Commit,
+                target: Oid::from_raw([0xab; 20]),
+            }
+        );
+        assert!(matches!(serial[2].kind, ProblemKind::Corrupt(_)));
+
+        for threads in [0, 1, 3, 8] {
+            assert_eq!(fsck_parallel(&repo, threads).unwrap(), serial);
+        }
+    }
+}
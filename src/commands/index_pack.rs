@@ -0,0 +1,140 @@
+//! `git index-pack`: build the `.idx` for a pack that came without one,
+//! such as one fetched or copied in.
+//!
+//! Every entry is read in turn and its CRC32 taken, then the deltas are
+//! resolved against their bases, by offset or, for `REF_DELTA`s, by id
+//! among the pack's own objects, to find each object's id. Thin packs,
+//! with bases outside the pack, aren't supported.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::object::{hash_object, ObjectType};
+use crate::core::oid::Oid;
+use crate::core::pack::{self, EntryKind, PackFile};
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// Index the pack at `pack_path`, checking its trailing checksum, and
+/// write the index next to it, returning its path. A relative path is
+/// looked up in the repository's `objects/pack`.
+pub fn index_pack(repo: &Repository, pack_path: &Path) -> GitResult<PathBuf> {
+    let pack_path = repo.git_dir().join("objects/pack").join(pack_path);
+    let file = PackFile::load(&pack_path)?;
+
+    // (offset, crc, kind, data), in pack order.
+    let mut entries = Vec::with_capacity(file.object_count() as usize);
+    let mut offset = 12;
+    for _ in 0..file.object_count() {
+        let header = file.entry_header(offset)?;
+        let (data, end) = file.inflate(&header)?;
+        let crc = pack::crc32(file.raw(offset, end)?);
+        entries.push((offset, crc, header.kind, data));
+        offset = end;
+    }
+    if offset != file.entries_end() {
+        return Err(GitError::Corrupt(format!(
+            "{} has data after its last entry",
+            pack_path.display()
+        )));
+    }
+
+    // Each round rebuilds the deltas whose bases the one before found.
+    let mut resolved: HashMap<u64, (ObjectType, Vec<u8>)> = HashMap::new();
+    let mut ids: HashMap<Oid, u64> = HashMap::new();
+    let mut index = Vec::with_capacity(entries.len());
+    let mut pending: Vec<usize> = (0..entries.len()).collect();
+    while !pending.is_empty() {
+        let mut waiting = Vec::new();
+        for &i in &pending {
+            let (offset, crc, kind, data) = &entries[i];
+            let base = match kind {
+                EntryKind::Object(_) => None,
+                EntryKind::OfsDelta(base) => resolved.get(base),
+                EntryKind::RefDelta(id) => ids.get(id).and_then(|base| resolved.get(base)),
+            };
+            let object = match (kind, base) {
+                (EntryKind::Object(kind), _) => (*kind, data.clone()),
+                (_, Some((kind, base))) => (*kind, pack::apply_delta(base, data)?),
+                (_, None) => {
+                    waiting.push(i);
+                    continue;
+                }
+            };
+            let id = hash_object(object.0, &object.1);
+            resolved.insert(*offset, object);
+            ids.insert(id, *offset);
+            index.push((id, *crc, *offset));
+        }
+        if waiting.len() == pending.len() {
+            return Err(GitError::Corrupt(format!(
+                "the delta base of the entry at offset {} is not in the pack",
+                entries[waiting[0]].0
+            )));
+        }
+        pending = waiting;
+    }
+
+    let idx = pack::write_index(&mut index, &file.checksum());
+    let idx_path = pack_path.with_extension("idx");
+    let tmp = pack_path.with_extension("idx.tmp");
+    fs::write(&tmp, idx)?;
+    fs::rename(&tmp, &idx_path)?;
+    Ok(idx_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::repack::repack_delta;
+    use crate::test_utils::{git, init_repo, set_ref, write_commit};
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    #[test]
+    fn rebuilds_the_index_of_a_repacked_pack() {
+        let (_dir, repo) = init_repo();
+        let big: String = (0..3000).map(|n| format!("line {}\n", n)).collect();
+        let base = write_commit(&repo, &[], &[("big", &big), ("a", "a\n")], "base");
+        let edited = big.replace("line 7\n", "line seven\n");
+        let tip = write_commit(&repo, &[base], &[("big", &edited), ("a", "a\n")], "tip");
+        set_ref(&repo, "refs/heads/master", &tip);
+        let pack_path = repack_delta(&repo, 10).unwrap();
+        let idx_path = pack_path.with_extension("idx");
+        let original = fs::read(&idx_path).unwrap();
+        fs::remove_file(&idx_path).unwrap();
+
+        let name = pack_path.file_name().unwrap();
+        assert_eq!(index_pack(&repo, Path::new(name)).unwrap(), idx_path);
+        assert_eq!(fs::read(&idx_path).unwrap(), original);
+        for id in repo.odb().loose_objects().unwrap() {
+            repo.odb().remove_loose(&id).unwrap();
+        }
+        let fresh = Repository::open(repo.git_dir()).unwrap();
+        assert_eq!(fresh.odb().read_commit(&tip).unwrap().parents, [base]);
+        if let Some(out) = git(&repo, &["cat-file", "-p", "HEAD:big"]) {
+            assert_eq!(out, edited);
+        }
+    }
+
+    #[test]
+    fn indexes_packs_as_git_does() {
+        let (_dir, repo) = init_repo();
+        for name in ["ofs-delta", "ref-delta"] {
+            let fixture = Path::new(FIXTURES).join(name);
+            let pack_path = repo.git_dir().join(format!("objects/pack/{}.pack", name));
+            fs::copy(fixture.with_extension("pack"), &pack_path).unwrap();
+            let idx_path = index_pack(&repo, &pack_path).unwrap();
+            let theirs = fs::read(fixture.with_extension("idx")).unwrap();
+            assert_eq!(fs::read(idx_path).unwrap(), theirs, "{}", name);
+        }
+
+        let bad = repo.git_dir().join("objects/pack/bad.pack");
+        let mut data = fs::read(Path::new(FIXTURES).join("ofs-delta.pack")).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&bad, data).unwrap();
+        assert!(matches!(index_pack(&repo, &bad), Err(GitError::Corrupt(_))));
+    }
+}
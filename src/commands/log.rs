@@ -0,0 +1,575 @@
+use std::io::Write;
+use std::time::SystemTime;
+
+use crate::core::date;
+use crate::core::diff;
+use crate::core::object::Commit;
+use crate::core::odb::ObjectDatabase;
+use crate::core::oid::Oid;
+use crate::core::pathspec::Pathspec;
+use crate::core::pretty::{CommitFormatter, Decorations};
+use crate::core::revparse;
+use crate::core::revwalk::RevWalk;
+use crate::core::shallow;
+use crate::core::signature::DateFormat;
+use crate::core::tree;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// How each commit is shown, after git's `--pretty` formats of the same
+/// names.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Format {
+    /// The full id and the subject on one line.
+    Oneline,
+    /// The id, author and date, then the indented message.
+    #[default]
+    Medium,
+    /// Like `Medium`, but with the committer in place of the date.
+    Full,
+    /// The commit's headers as stored, then the indented message.
+    Raw,
+    /// A `--format=` string; see [`crate::core::pretty`] for the placeholders. Each
+    /// commit's expansion ends with a newline.
+    Custom(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogOptions {
+    /// Which history to show, as [`revparse::parse_range`] takes it; HEAD
+    /// when empty.
+    pub revisions: Vec<String>,
+    /// Only show commits that change these paths, as the pathspecs after
+    /// `--` select them.
+    pub paths: Vec<String>,
+    /// Keep following the one file in `paths` back past renames, as
+    /// `--follow` does.
+    pub follow: bool,
+    /// Stop after showing this many commits.
+    pub max_count: Option<usize>,
+    /// Leave out this many commits before showing any.
+    pub skip: usize,
+    /// Show the selected commits oldest first. Like git, `max_count` and
+    /// `skip` pick the commits before they're reversed.
+    pub reverse: bool,
+    pub format: Format,
+    /// How dates are shown, by `Medium` and by `%ad` and `%cd`.
+    pub date_format: DateFormat,
+    /// Only show commits made since then, by committer date; see
+    /// [`date::parse_approxidate`] for reading one from the command line.
+    pub since: Option<SystemTime>,
+    /// Only show commits made before then.
+    pub until: Option<SystemTime>,
+}
+
+/// `git log`: write the history `options` selects to `out`.
+pub fn log<W: Write>(repo: &Repository, options: &LogOptions, mut out: W) -> GitResult<()> {
+    let odb = repo.odb();
+    if options.follow && options.paths.len() != 1 {
+        return Err(GitError::FollowNeedsOnePath);
+    }
+    let shallow = shallow::shallow_commits(repo)?;
+    let mut walk = RevWalk::new(odb);
+    walk.push_specs(&revparse::parse_range(repo, &options.revisions)?)?
+        .shallow(shallow.clone());
+    if !options.follow {
+        walk.pathspec(Pathspec::parse(&options.paths));
+    }
+    if let Some(since) = options.since {
+        walk.since(date::to_unix(since));
+    }
+    if let Some(until) = options.until {
+        walk.until(date::to_unix(until));
+    }
+    // The path being followed, which changes as renames are found.
+    let mut followed = options.follow.then(|| options.paths[0].clone());
+    let mut skip = options.skip;
+    let mut commits = Vec::new();
+    for commit in walk {
+        if commits.len() == options.max_count.unwrap_or(usize::MAX) {
+            break;
+        }
+        let (id, commit) = commit?;
+        if let Some(path) = &mut followed {
+            let parents = if shallow.contains(&id) {
+                &[][..]
+            } else {
+                &commit.parents[..]
+            };
+            if !follow_file(odb, &commit.tree, parents, path)? {
+                continue;
+            }
+        }
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        commits.push((id, commit));
+    }
+    if options.reverse {
+        commits.reverse();
+    }
+    let decorations = match &options.format {
+        Format::Custom(template) if template.contains("%d") || template.contains("%D") => {
+            Some(Decorations::load(repo)?)
+        }
+        _ => None,
+    };
+    let mut formatter = CommitFormatter::new(odb).date_format(options.date_format);
+    if let Some(decorations) = &decorations {
+        formatter = formatter.decorations(decorations);
+    }
+    for (n, (id, commit)) in commits.iter().enumerate() {
+        if let Format::Custom(template) = &options.format {
+            writeln!(out, "{}", formatter.format(template, id, commit)?)?;
+            continue;
+        }
+        if n > 0 && options.format != Format::Oneline {
+            writeln!(out)?;
+        }
+        write_commit(&mut out, odb, id, commit, options)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The lowest [`diff::similarity`] at which an added file is taken to be a
+/// removed one renamed, as git's default.
+const RENAME_THRESHOLD: u32 = 50;
+
+/// For `--follow`: whether a commit with `tree` changes the file at `path`
+/// from all of its `parents`. When the file first appears, a file that
+/// the first parent has and the commit doesn't is looked for that matches
+/// it exactly or failing that is the most similar, and `path` moves to
+/// that one for the rest of the walk.
+fn follow_file(
+    odb: &ObjectDatabase,
+    tree: &Oid,
+    parents: &[Oid],
+    path: &mut String,
+) -> GitResult<bool> {
+    let entry = file_at(odb, tree, path)?;
+    let mut parent_trees = Vec::new();
+    for parent in parents.iter().filter(|parent| odb.contains(parent)) {
+        let parent_tree = odb.read_commit(parent)?.tree;
+        if file_at(odb, &parent_tree, path)? == entry {
+            return Ok(false);
+        }
+        parent_trees.push(parent_tree);
+    }
+    if let (Some(first), Some((_, oid))) = (parent_trees.first(), entry) {
+        if file_at(odb, first, path)?.is_none() {
+            if let Some(old_path) = find_rename(odb, first, tree, &oid)? {
+                *path = old_path;
+            }
+        }
+    }
+    Ok(entry.is_some() || !parent_trees.is_empty())
+}
+
+/// The mode and blob at `path` under `tree`, if it's a file.
+fn file_at(odb: &ObjectDatabase, tree: &Oid, path: &str) -> GitResult<Option<(u32, Oid)>> {
+    Ok(tree::find_path(odb, tree, path)?
+        .filter(|entry| !entry.is_tree())
+        .map(|entry| (entry.mode, entry.oid)))
+}
+
+/// Which of the files in `old` but not `new` became `blob`: one with the
+/// same contents, or else the one most like it above
+/// [`RENAME_THRESHOLD`].
+fn find_rename(
+    odb: &ObjectDatabase,
+    old: &Oid,
+    new: &Oid,
+    blob: &Oid,
+) -> GitResult<Option<String>> {
+    let new = tree::flatten(odb, new)?;
+    let removed: Vec<(String, Oid)> = tree::flatten(odb, old)?
+        .into_iter()
+        .filter(|(path, _)| !new.contains_key(path))
+        .map(|(path, entry)| (path, entry.oid))
+        .collect();
+    if let Some((path, _)) = removed.iter().find(|(_, oid)| oid == blob) {
+        return Ok(Some(path.clone()));
+    }
+    let data = odb.read_blob(blob)?;
+    let mut best: Option<(u32, &String)> = None;
+    for (path, oid) in &removed {
+        let score = diff::similarity(&odb.read_blob(oid)?, &data);
+        if score >= RENAME_THRESHOLD && best.is_none_or(|(high, _)| score > high) {
+            best = Some((score, path));
+        }
+    }
+    Ok(best.map(|(_, path)| path.clone()))
+}
+
+fn write_commit<W: Write>(
+    out: &mut W,
+    odb: &ObjectDatabase,
+    id: &Oid,
+    commit: &Commit,
+    options: &LogOptions,
+) -> GitResult<()> {
+    let format = &options.format;
+    let hex = id.to_string();
+    if *format == Format::Oneline {
+        writeln!(out, "{} {}", hex, commit.summary())?;
+        return Ok(());
+    }
+    writeln!(out, "commit {}", hex)?;
+    if *format == Format::Raw {
+        let raw = commit.serialize();
+        let raw = String::from_utf8_lossy(&raw);
+        let headers = raw.split("\n\n").next().unwrap_or("");
+        writeln!(out, "{}", headers)?;
+    } else {
+        if commit.parents.len() > 1 {
+            let mut parents = Vec::new();
+            for parent in &commit.parents {
+                parents.push(odb.abbreviate(parent, 7)?);
+            }
+            writeln!(out, "Merge: {}", parents.join(" "))?;
+        }
+        let author = &commit.author;
+        writeln!(out, "Author: {} <{}>", author.name, author.email)?;
+        if *format == Format::Full {
+            let committer = &commit.committer;
+            writeln!(out, "Commit: {} <{}>", committer.name, committer.email)?;
+        } else {
+            writeln!(out, "Date:   {}", author.format_date(options.date_format))?;
+        }
+    }
+    writeln!(out)?;
+    for line in commit.message.trim_end().lines() {
+        writeln!(out, "    {}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::object::GitObject;
+    use crate::core::signature::Signature;
+    use crate::test_utils::{git, init_repo, set_ref, write_commit, write_commit_at};
+
+    /// `base`, a side branch and a merge, with a multi-paragraph message
+    /// and a committer other than the author on the tip.
+    fn fixture(repo: &Repository) -> Oid {
+        let base = write_commit(repo, &[], &[("a", "a")], "base");
+        let side = write_commit(repo, &[base], &[("a", "side")], "side");
+        let main = write_commit(repo, &[base], &[("b", "b")], "main");
+        let merge = write_commit(repo, &[main, side], &[("a", "side"), ("b", "b")], "merge");
+        let commit = Commit {
+            tree: repo.odb().read_commit(&merge).unwrap().tree,
+            parents: vec![merge],
+            author: Signature::new("Ann Author", "ann@example.com", 1_112_911_993, -420),
+            committer: Signature::new("Cee Committer", "cee@example.com", 1_112_912_000, 330),
+            extra_headers: Vec::new(),
+            message: "Tip of the tree\n\nA body that runs\nover two lines.\n".to_string(),
+        };
+        let tip = repo.odb().write(&GitObject::Commit(commit)).unwrap();
+        set_ref(repo, "refs/heads/master", &tip);
+        tip
+    }
+
+    fn render(repo: &Repository, options: &LogOptions) -> String {
+        let mut out = Vec::new();
+        log(repo, options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn matches_git_log_in_every_format() {
+        let (_dir, repo) = init_repo();
+        fixture(&repo);
+        for (format, flag) in [
+            (Format::Oneline, "--pretty=oneline"),
+            (Format::Medium, "--pretty=medium"),
+            (Format::Full, "--pretty=full"),
+            (Format::Raw, "--pretty=raw"),
+        ] {
+            let options = LogOptions {
+                format,
+                ..LogOptions::default()
+            };
+            let ours = render(&repo, &options);
+            assert!(!ours.is_empty());
+            if let Some(theirs) = git(&repo, &["log", flag]) {
+                assert_eq!(ours, theirs, "{}", flag);
+            }
+        }
+    }
+
+    #[test]
+    fn stops_at_the_shallow_boundary() {
+        let (_dir, repo) = init_repo();
+        let tip = fixture(&repo);
+        let merge = repo.odb().read_commit(&tip).unwrap().parents[0];
+        std::fs::write(shallow::path(&repo), format!("{}\n", merge)).unwrap();
+        for (format, flag) in [
+            (Format::Oneline, "--pretty=oneline"),
+            (Format::Raw, "--pretty=raw"),
+        ] {
+            let options = LogOptions {
+                format,
+                ..LogOptions::default()
+            };
+            let ours = render(&repo, &options);
+            if flag == "--pretty=oneline" {
+                let subjects: Vec<&str> = ours.lines().map(|line| &line[41..]).collect();
+                assert_eq!(subjects, ["Tip of the tree", "merge"]);
+            }
+            if let Some(theirs) = git(&repo, &["log", flag]) {
+                assert_eq!(ours, theirs, "{}", flag);
+            }
+        }
+    }
+
+    #[test]
+    fn limits_by_date_despite_clock_skew() {
+        let (_dir, repo) = init_repo();
+        let base = 1_700_000_000;
+        let mut tip = Vec::new();
+        for (name, time) in [
+            ("c1", 1000),
+            ("c2", 2000),
+            ("c3", 1500),
+            ("c4", 1600),
+            ("c5", 1700),
+            ("c6", 1400),
+            ("c7", 4000),
+            ("c8", 5000),
+        ] {
+            let id = write_commit_at(&repo, &tip, &[("f", name)], name, base + time);
+            tip = vec![id];
+        }
+        set_ref(&repo, "refs/heads/master", &tip[0]);
+        let now = date::from_unix(base + 7000);
+        for (since, until, expected) in [
+            // c6 is older than the cutoff, so history stops there even
+            // though the commits behind it are newer.
+            (Some("2023-11-14T22:39:10Z"), None, "c8 c7"),
+            (Some("@1700001000"), None, "c8 c7 c6 c5 c4 c3 c2 c1"),
+            (None, Some("2023-11-14T22:43:20Z"), "c6 c5 c4 c3 c1"),
+            (Some("50.minutes.ago"), Some("40 minutes ago"), "c7"),
+        ] {
+            let parse =
+                |input: Option<&str>| input.map(|i| date::parse_approxidate(i, now).unwrap());
+            let options = LogOptions {
+                format: Format::Custom("%s".to_string()),
+                since: parse(since),
+                until: parse(until),
+                ..LogOptions::default()
+            };
+            let ours = render(&repo, &options)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            assert_eq!(ours, expected, "{:?} {:?}", since, until);
+            let mut args = vec!["log".to_string(), "--format=%s".to_string()];
+            args.extend(
+                options
+                    .since
+                    .map(|t| format!("--since=@{}", date::to_unix(t))),
+            );
+            args.extend(
+                options
+                    .until
+                    .map(|t| format!("--until=@{}", date::to_unix(t))),
+            );
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            if let Some(theirs) = git(&repo, &args) {
+                assert_eq!(
+                    theirs.split_whitespace().collect::<Vec<_>>().join(" "),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn counts_skips_and_reverses() {
+        let (_dir, repo) = init_repo();
+        let tip = fixture(&repo);
+        let options = LogOptions {
+            revisions: vec!["master".to_string()],
+            paths: Vec::new(),
+            follow: false,
+            max_count: Some(2),
+            skip: 1,
+            reverse: true,
+            format: Format::Oneline,
+            date_format: DateFormat::Default,
+            since: None,
+            until: None,
+        };
+        let ours = render(&repo, &options);
+        let subjects: Vec<&str> = ours.lines().map(|line| &line[41..]).collect();
+        assert_eq!(subjects, vec!["main", "merge"]);
+        if let Some(theirs) = git(
+            &repo,
+            &[
+                "log",
+                "--pretty=oneline",
+                "-n2",
+                "--skip=1",
+                "--reverse",
+                &tip.to_string(),
+            ],
+        ) {
+            assert_eq!(ours, theirs);
+        }
+    }
+
+    #[test]
+    fn shows_only_commits_touching_the_paths() {
+        let (_dir, repo) = init_repo();
+        let mut parents = Vec::new();
+        let mut tracked = "0".to_string();
+        let mut touching = Vec::new();
+        for n in 0..20 {
+            if [0, 7, 15].contains(&n) {
+                tracked = format!("version {}", n);
+            }
+            let other = n.to_string();
+            let files = [("dir/tracked.txt", tracked.as_str()), ("other.txt", &other)];
+            let message = format!("commit {}", n);
+            let id = write_commit_at(&repo, &parents, &files, &message, 1000 + n);
+            if [0, 7, 15].contains(&n) {
+                touching.push(id);
+            }
+            parents = vec![id];
+        }
+        set_ref(&repo, "refs/heads/master", &parents[0]);
+
+        for paths in [vec!["dir/tracked.txt"], vec!["dir/"], vec!["*.txt", "dir"]] {
+            let options = LogOptions {
+                paths: paths.iter().map(|p| p.to_string()).collect(),
+                format: Format::Oneline,
+                ..LogOptions::default()
+            };
+            let ours = render(&repo, &options);
+            let shown: Vec<String> = ours.lines().map(|l| l[..40].to_string()).collect();
+            let expected: Vec<String> = if paths[0] == "*.txt" {
+                // other.txt changes every time.
+                let all = render(&repo, &LogOptions::default());
+                all.lines()
+                    .filter_map(|l| l.strip_prefix("commit "))
+                    .map(str::to_string)
+                    .collect()
+            } else {
+                touching.iter().rev().map(Oid::to_string).collect()
+            };
+            assert_eq!(shown, expected, "{:?}", paths);
+            let mut args = vec!["log", "--pretty=oneline", "--"];
+            args.extend(paths.iter());
+            if let Some(theirs) = git(&repo, &args) {
+                assert_eq!(ours, theirs, "{:?}", paths);
+            }
+        }
+    }
+
+    #[test]
+    fn follows_a_file_through_renames() {
+        let (_dir, repo) = init_repo();
+        let body: String = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        let edited = body.replace("line 3\n", "line three\n");
+        let moved = edited.replace("line 8\n", "line eight\n");
+        let last = format!("{}line 11\n", moved);
+        let history: [(&str, &[(&str, &str)]); 7] = [
+            ("add a", &[("a.txt", &body), ("gone.txt", "x\ny\n")]),
+            ("edit a", &[("a.txt", &edited), ("gone.txt", "x\ny\n")]),
+            ("rename to b", &[("b.txt", &edited), ("gone.txt", "x\ny\n")]),
+            ("unrelated", &[("b.txt", &edited), ("other", "1")]),
+            ("move and edit", &[("dir/c.txt", &moved), ("other", "1")]),
+            ("unrelated again", &[("dir/c.txt", &moved), ("other", "2")]),
+            ("edit c", &[("dir/c.txt", &last), ("other", "2")]),
+        ];
+        let mut parents = Vec::new();
+        for (n, (message, files)) in history.iter().enumerate() {
+            parents = vec![write_commit_at(
+                &repo,
+                &parents,
+                files,
+                message,
+                1000 + n as i64,
+            )];
+        }
+        set_ref(&repo, "refs/heads/master", &parents[0]);
+
+        let subjects = |follow| {
+            let options = LogOptions {
+                paths: vec!["dir/c.txt".to_string()],
+                follow,
+                format: Format::Custom("%s".to_string()),
+                ..LogOptions::default()
+            };
+            render(&repo, &options)
+        };
+        assert_eq!(subjects(false), "edit c\nmove and edit\n");
+        let followed = subjects(true);
+        assert_eq!(
+            followed,
+            "edit c\nmove and edit\nrename to b\nedit a\nadd a\n"
+        );
+        if let Some(theirs) = git(
+            &repo,
+            &["log", "--follow", "--format=%s", "--", "dir/c.txt"],
+        ) {
+            assert_eq!(followed, theirs);
+        }
+
+        let options = LogOptions {
+            paths: vec!["a.txt".to_string(), "b.txt".to_string()],
+            follow: true,
+            ..LogOptions::default()
+        };
+        assert!(matches!(
+            log(&repo, &options, Vec::new()),
+            Err(GitError::FollowNeedsOnePath)
+        ));
+    }
+
+    #[test]
+    fn expands_format_strings_and_date_styles() {
+        let (_dir, repo) = init_repo();
+        fixture(&repo);
+        set_ref(
+            &repo,
+            "refs/tags/old",
+            &repo.resolve_rev("master~2").unwrap(),
+        );
+        let cases = [
+            (
+                Format::Custom("%h %s%d".to_string()),
+                DateFormat::Default,
+                vec!["--format=%h %s%d", "--decorate"],
+            ),
+            (
+                Format::Custom("%an %ad%n%cd".to_string()),
+                DateFormat::Unix,
+                vec!["--format=%an %ad%n%cd", "--date=unix"],
+            ),
+            (
+                Format::Medium,
+                DateFormat::Iso,
+                vec!["--pretty=medium", "--date=iso"],
+            ),
+        ];
+        for (format, date_format, args) in cases {
+            let options = LogOptions {
+                format,
+                date_format,
+                ..LogOptions::default()
+            };
+            let ours = render(&repo, &options);
+            assert!(!ours.is_empty());
+            let mut full = vec!["log"];
+            full.extend(args.iter());
+            if let Some(theirs) = git(&repo, &full) {
+                assert_eq!(ours, theirs, "{:?}", args);
+            }
+        }
+    }
+}
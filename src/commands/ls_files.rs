@@ -0,0 +1,108 @@
+use std::fmt;
+
+use crate::core::oid::Oid;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// One line of `git ls-files` output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsFilesEntry {
+    pub path: String,
+    /// Present when listing with `stage`, as `git ls-files --stage` does.
+    pub staged: Option<StagedBlob>,
+}
+
+/// What the index records for one stage of a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StagedBlob {
+    pub mode: u32,
+    pub oid: Oid,
+    /// 0 for a resolved path, 1/2/3 for the base/ours/theirs sides of a
+    /// conflict.
+    pub stage: u8,
+}
+
+impl fmt::Display for LsFilesEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.staged {
+            Some(staged) => write!(
+                f,
+                "{:06o} {} {}\t{}",
+                staged.mode, staged.oid, staged.stage, self.path
+            ),
+            None => write!(f, "{}", self.path),
+        }
+    }
+}
+
+/// List the paths tracked by the index in index order. Without `stage` a
+/// conflicted path is listed once; with it every stage gets its own entry.
+pub fn ls_files(repo: &Repository, stage: bool) -> GitResult<Vec<LsFilesEntry>> {
+    let index = repo.read_index()?;
+    let mut entries: Vec<LsFilesEntry> = Vec::new();
+    for entry in index.entries() {
+        if !stage && entries.last().is_some_and(|last| last.path == entry.path) {
+            continue;
+        }
+        entries.push(LsFilesEntry {
+            path: entry.path.clone(),
+            staged: if stage {
+                Some(StagedBlob {
+                    mode: entry.mode,
+                    oid: entry.oid,
+                    stage: entry.stage(),
+                })
+            } else {
+                None
+            },
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::merge::merge;
+    use crate::test_utils::{checkout, init_repo, set_ref, stage_file, write_commit};
+
+    #[test]
+    fn lists_staged_paths_in_order() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "src/main.rs", "fn main() {}\n");
+        stage_file(&repo, "README", "hello\n");
+        let paths: Vec<String> = ls_files(&repo, false)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(paths, vec!["README", "src/main.rs"]);
+    }
+
+    #[test]
+    fn shows_conflict_stages() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n")], "base");
+        let ours = write_commit(&repo, &[base], &[("a.txt", "ours\n")], "ours");
+        let theirs = write_commit(&repo, &[base], &[("a.txt", "theirs\n")], "theirs");
+        set_ref(&repo, "refs/heads/feature", &theirs);
+        checkout(&repo, "master", &ours);
+        merge(&repo, "feature").unwrap();
+
+        let lines: Vec<String> = ls_files(&repo, true)
+            .unwrap()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        // Blob ids as printed by `git ls-files --stage`.
+        assert_eq!(
+            lines,
+            vec![
+                "100644 78981922613b2afb6025042ff6bd878ac1994e85 1\ta.txt",
+                "100644 b19a1e93bec1317dc6097229e12afaffbfa74dc2 2\ta.txt",
+                "100644 950b81b7eee953d050aa05a641f8e056c85dd1bd 3\ta.txt",
+            ]
+        );
+        assert_eq!(ls_files(&repo, false).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,505 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::index::{Index, IndexEntry};
+use crate::core::merge::merge_blobs_with_labels;
+use crate::core::object::{Commit, GitObject, MODE_EXECUTABLE, MODE_FILE};
+use crate::core::odb::{LooseObjectWriter, ObjectDatabase};
+use crate::core::oid::Oid;
+use crate::core::revwalk;
+use crate::core::signature;
+use crate::core::tree::{self, FlatEntry, FlatTree};
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::repository::{Repository, RepositoryState};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// The other side is already contained in HEAD.
+    UpToDate,
+    /// HEAD was an ancestor of the other side and has been moved to it.
+    FastForward(Oid),
+    /// The histories diverged and merged cleanly into this commit.
+    MadeCommit(Oid),
+    /// These paths could not be merged automatically. Their conflict stages
+    /// are in the index and the working tree files hold conflict markers.
+    Conflicts(Vec<PathBuf>),
+}
+
+/// A path that changed differently on both sides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Conflict {
+    path: String,
+    base: Option<FlatEntry>,
+    ours: Option<FlatEntry>,
+    theirs: Option<FlatEntry>,
+    /// The working tree content with conflict markers, for paths both
+    /// sides modified.
+    content: Option<Vec<u8>>,
+}
+
+/// A conflicted merge waiting to be concluded with a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeState {
+    /// The commit being merged in, from `MERGE_HEAD`.
+    pub their_head: Oid,
+    /// The prepared commit message, from `MERGE_MSG`.
+    pub message: String,
+}
+
+/// The in-progress merge, if any.
+pub fn merge_state(repo: &Repository) -> GitResult<Option<MergeState>> {
+    let head = match fs::read_to_string(repo.git_dir().join("MERGE_HEAD")) {
+        Ok(head) => head,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let their_head = head.lines().next().unwrap_or("").trim().parse::<Oid>()?;
+    let message = match fs::read_to_string(repo.git_dir().join("MERGE_MSG")) {
+        Ok(message) => message,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(Some(MergeState {
+        their_head,
+        message,
+    }))
+}
+
+/// Forget about an in-progress merge, once it's committed or aborted.
+pub fn clear_merge_state(repo: &Repository) -> GitResult<()> {
+    for name in &["MERGE_HEAD", "MERGE_MSG"] {
+        match fs::remove_file(repo.git_dir().join(name)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Merge the commit named by `theirs` into HEAD. Refused while a merge,
+/// cherry-pick or anything else that [`Repository::state`] reports is
+/// stopped.
+pub fn merge(repo: &Repository, theirs: &str) -> GitResult<MergeOutcome> {
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    match repo.state() {
+        RepositoryState::Clean => {}
+        state => return Err(GitError::OperationInProgress(state)),
+    }
+    let our_id = repo
+        .head_commit()?
+        .ok_or_else(|| GitError::UnknownRevision("HEAD".to_string()))?;
+    let their_id = repo.resolve_rev(theirs)?;
+    let our_commit = odb.read_commit(&our_id)?;
+    let their_commit = odb.read_commit(&their_id)?;
+
+    let base_id = merge_base(odb, &our_id, &their_id)?;
+    if base_id == Some(their_id) {
+        return Ok(MergeOutcome::UpToDate);
+    }
+
+    let index = repo.read_index()?;
+    let ours = tree::flatten(odb, &our_commit.tree)?;
+    let their_tree = tree::flatten(odb, &their_commit.tree)?;
+
+    if base_id == Some(our_id) {
+        ensure_clean(repo, &index, &ours, &their_tree)?;
+        worktree::update(odb, work_dir, &ours, &their_tree)?;
+        repo.write_index(&worktree::index_from_tree(work_dir, &their_tree)?)?;
+        repo.set_head_commit(&their_id, &format!("merge {}: Fast-forward", theirs))?;
+        return Ok(MergeOutcome::FastForward(their_id));
+    }
+
+    let base = match base_id {
+        Some(id) => tree::flatten(odb, &odb.read_commit(&id)?.tree)?,
+        None => FlatTree::new(),
+    };
+    let conflicts = match merge_into_worktree(repo, &base, &ours, &their_tree, theirs)? {
+        WorktreeMerge::Clean(merged) => {
+            let (author, committer) = signature::default_signatures(repo)?;
+            let commit = Commit {
+                tree: tree::build(&mut LooseObjectWriter::new(odb), &merged)?,
+                parents: vec![our_id, their_id],
+                author,
+                committer,
+                extra_headers: Vec::new(),
+                message: merge_message(theirs),
+            };
+            let id = odb.write(&GitObject::Commit(commit))?;
+            let message = format!("merge {}: Merge made by the 'ort' strategy.", theirs);
+            repo.set_head_commit(&id, &message)?;
+            return Ok(MergeOutcome::MadeCommit(id));
+        }
+        WorktreeMerge::Conflicts(conflicts) => conflicts,
+    };
+
+    let mut message = merge_message(theirs);
+    append_conflicts(&mut message, &conflicts);
+    fs::write(repo.git_dir().join("MERGE_HEAD"), format!("{}\n", their_id))?;
+    fs::write(repo.git_dir().join("MERGE_MSG"), message)?;
+    Ok(MergeOutcome::Conflicts(conflicts))
+}
+
+/// What [`merge_into_worktree`] left behind.
+pub(crate) enum WorktreeMerge {
+    /// Everything merged, into this tree, which is now checked out and
+    /// staged.
+    Clean(FlatTree),
+    /// These paths have their conflict stages in the index and conflict
+    /// markers in the working tree; everything else is merged.
+    Conflicts(Vec<PathBuf>),
+}
+
+/// Three-way merge `ours`, the tree HEAD has, with `theirs` against
+/// `base` into the working tree and index, labelling their side of
+/// conflict markers `their_label`. Nothing is touched if a path the merge
+/// changes has local changes.
+pub(crate) fn merge_into_worktree(
+    repo: &Repository,
+    base: &FlatTree,
+    ours: &FlatTree,
+    theirs: &FlatTree,
+    their_label: &str,
+) -> GitResult<WorktreeMerge> {
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let (mut merged, candidates) = merge_trees(base, ours, theirs);
+    let mut conflicts = Vec::new();
+    for mut conflict in candidates {
+        match merge_contents(odb, their_label, &mut conflict)? {
+            Some(entry) => {
+                merged.insert(conflict.path.clone(), entry);
+            }
+            None => conflicts.push(conflict),
+        }
+    }
+
+    // Everything we're about to write over must match HEAD.
+    let mut target = merged.clone();
+    for conflict in &conflicts {
+        if let Some(entry) = conflict.theirs.or(conflict.ours) {
+            target.insert(conflict.path.clone(), entry);
+        }
+    }
+    ensure_clean(repo, &repo.read_index()?, ours, &target)?;
+
+    if conflicts.is_empty() {
+        worktree::update(odb, work_dir, ours, &merged)?;
+        repo.write_index(&worktree::index_from_tree(work_dir, &merged)?)?;
+        return Ok(WorktreeMerge::Clean(merged));
+    }
+
+    let mut resolved_ours = ours.clone();
+    for conflict in &conflicts {
+        resolved_ours.remove(&conflict.path);
+    }
+    worktree::update(odb, work_dir, &resolved_ours, &merged)?;
+    let mut new_index = worktree::index_from_tree(work_dir, &merged)?;
+    for conflict in &conflicts {
+        write_conflict(odb, work_dir, conflict)?;
+        let stages = [(1, conflict.base), (2, conflict.ours), (3, conflict.theirs)];
+        for (stage, entry) in stages.iter() {
+            if let Some(entry) = entry {
+                new_index
+                    .add(IndexEntry::new(&conflict.path, entry.oid, entry.mode).with_stage(*stage));
+            }
+        }
+    }
+    repo.write_index(&new_index)?;
+    Ok(WorktreeMerge::Conflicts(
+        conflicts
+            .into_iter()
+            .map(|c| PathBuf::from(c.path))
+            .collect(),
+    ))
+}
+
+/// List the conflicted `paths` at the end of a prepared commit message,
+/// as comments that cleaning it up strips again.
+pub(crate) fn append_conflicts(message: &mut String, paths: &[PathBuf]) {
+    message.push_str("\n# Conflicts:\n");
+    for path in paths {
+        message.push_str(&format!("#\t{}\n", path.display()));
+    }
+}
+
+/// Merge three trees without touching the working tree or index, as
+/// applying a stash does: the merged tree, or `WouldConflict` with every
+/// path that doesn't merge cleanly.
+pub(crate) fn merge_clean(
+    odb: &ObjectDatabase,
+    base: &FlatTree,
+    ours: &FlatTree,
+    theirs: &FlatTree,
+    their_name: &str,
+) -> GitResult<FlatTree> {
+    let (mut merged, candidates) = merge_trees(base, ours, theirs);
+    let mut conflicts = Vec::new();
+    for mut conflict in candidates {
+        match merge_contents(odb, their_name, &mut conflict)? {
+            Some(entry) => {
+                merged.insert(conflict.path, entry);
+            }
+            None => conflicts.push(PathBuf::from(conflict.path)),
+        }
+    }
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(GitError::WouldConflict(conflicts))
+    }
+}
+
+fn merge_message(theirs: &str) -> String {
+    format!("Merge branch '{}'\n", theirs)
+}
+
+/// Merge three flattened trees path by path: a side that didn't change
+/// takes the other side's version, and identical changes agree.
+fn merge_trees(base: &FlatTree, ours: &FlatTree, theirs: &FlatTree) -> (FlatTree, Vec<Conflict>) {
+    let paths: BTreeSet<&String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    let mut merged = FlatTree::new();
+    let mut conflicts = Vec::new();
+    for path in paths {
+        let b = base.get(path).copied();
+        let o = ours.get(path).copied();
+        let t = theirs.get(path).copied();
+        let result = if o == t || b == t {
+            o
+        } else if b == o {
+            t
+        } else {
+            conflicts.push(Conflict {
+                path: path.clone(),
+                base: b,
+                ours: o,
+                theirs: t,
+                content: None,
+            });
+            continue;
+        };
+        if let Some(entry) = result {
+            merged.insert(path.clone(), entry);
+        }
+    }
+    (merged, conflicts)
+}
+
+/// Try a line-level merge of a path both sides modified. Returns the
+/// merged entry when that's clean, otherwise stashes the marked-up content
+/// on the conflict.
+fn merge_contents(
+    odb: &ObjectDatabase,
+    their_name: &str,
+    conflict: &mut Conflict,
+) -> GitResult<Option<FlatEntry>> {
+    let (ours, theirs) = match (conflict.ours, conflict.theirs) {
+        (Some(o), Some(t)) if is_regular(o.mode) && is_regular(t.mode) => (o, t),
+        _ => return Ok(None),
+    };
+    let base = match conflict.base {
+        Some(b) if is_regular(b.mode) => odb.read_blob(&b.oid)?,
+        _ => Vec::new(),
+    };
+    let result = merge_blobs_with_labels(
+        &base,
+        &odb.read_blob(&ours.oid)?,
+        &odb.read_blob(&theirs.oid)?,
+        "HEAD",
+        their_name,
+    );
+    if result.conflicted {
+        conflict.content = Some(result.content);
+        return Ok(None);
+    }
+    // Keep a mode change made on either side.
+    let mode = match conflict.base {
+        Some(b) if b.mode == ours.mode => theirs.mode,
+        _ => ours.mode,
+    };
+    let oid = odb.write(&GitObject::Blob(result.content))?;
+    Ok(Some(FlatEntry { mode, oid }))
+}
+
+fn is_regular(mode: u32) -> bool {
+    mode == MODE_FILE || mode == MODE_EXECUTABLE
+}
+
+/// Leave a conflicted path in the working tree for the user to resolve.
+fn write_conflict(odb: &ObjectDatabase, work_dir: &Path, conflict: &Conflict) -> GitResult<()> {
+    match (&conflict.content, conflict.ours, conflict.theirs) {
+        (Some(content), Some(ours), _) => {
+            worktree::write_content(work_dir, &conflict.path, ours.mode, content)
+        }
+        // We deleted the file; leave their version on disk.
+        (_, None, Some(theirs)) => {
+            worktree::write_blob(odb, work_dir, &conflict.path, theirs.mode, &theirs.oid)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Refuse to continue if any path that differs between HEAD and `target`
+/// has staged or unstaged changes we'd lose.
+pub(crate) fn ensure_clean(
+    repo: &Repository,
+    index: &Index,
+    ours: &FlatTree,
+    target: &FlatTree,
+) -> GitResult<()> {
+    let work_dir = repo.require_work_dir()?;
+    let paths: BTreeSet<&String> = ours.keys().chain(target.keys()).collect();
+    let mut dirty = Vec::new();
+    for path in paths {
+        let head = ours.get(path).copied();
+        if head == target.get(path).copied() {
+            continue;
+        }
+        let staged = index.get(path, 0).map(|e| FlatEntry {
+            mode: e.mode,
+            oid: e.oid,
+        });
+        let on_disk = worktree::hash_file(work_dir, path)?;
+        if staged != head || on_disk != head {
+            dirty.push(PathBuf::from(path));
+        }
+    }
+    if dirty.is_empty() {
+        Ok(())
+    } else {
+        Err(GitError::LocalChanges(dirty))
+    }
+}
+
+/// The best common ancestor of two commits: a common ancestor that isn't
+/// itself an ancestor of another common ancestor.
+pub(crate) fn merge_base(odb: &ObjectDatabase, a: &Oid, b: &Oid) -> GitResult<Option<Oid>> {
+    Ok(revwalk::merge_bases(odb, a, b)?.into_iter().next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{checkout, init_repo, read_file, set_ref, write_commit};
+
+    #[test]
+    fn clean_merge_makes_a_commit() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n"), ("b.txt", "b\n")], "base");
+        let ours = write_commit(
+            &repo,
+            &[base],
+            &[("a.txt", "ours\n"), ("b.txt", "b\n")],
+            "ours",
+        );
+        let theirs = write_commit(
+            &repo,
+            &[base],
+            &[("a.txt", "a\n"), ("b.txt", "theirs\n")],
+            "theirs",
+        );
+        set_ref(&repo, "refs/heads/feature", &theirs);
+        checkout(&repo, "master", &ours);
+
+        let id = match merge(&repo, "feature").unwrap() {
+            MergeOutcome::MadeCommit(id) => id,
+            other => panic!("unexpected outcome {:?}", other),
+        };
+        let commit = repo.odb().read_commit(&id).unwrap();
+        assert_eq!(commit.parents, vec![ours, theirs]);
+        assert_eq!(repo.head_commit().unwrap(), Some(id));
+        assert_eq!(read_file(&repo, "a.txt"), "ours\n");
+        assert_eq!(read_file(&repo, "b.txt"), "theirs\n");
+        assert!(!repo.read_index().unwrap().has_conflicts());
+    }
+
+    #[test]
+    fn conflicting_merge_leaves_markers_and_stages() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n")], "base");
+        let ours = write_commit(&repo, &[base], &[("a.txt", "ours\n")], "ours");
+        let theirs = write_commit(&repo, &[base], &[("a.txt", "theirs\n")], "theirs");
+        set_ref(&repo, "refs/heads/feature", &theirs);
+        checkout(&repo, "master", &ours);
+
+        let outcome = merge(&repo, "feature").unwrap();
+        assert_eq!(
+            outcome,
+            MergeOutcome::Conflicts(vec![PathBuf::from("a.txt")])
+        );
+        assert_eq!(repo.head_commit().unwrap(), Some(ours));
+        assert_eq!(
+            read_file(&repo, "a.txt"),
+            "<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\n"
+        );
+        let index = repo.read_index().unwrap();
+        let stages: Vec<u8> = index.entries().iter().map(|e| e.stage()).collect();
+        assert_eq!(stages, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn merges_disjoint_edits_to_the_same_file() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "1\n2\n3\n4\n")], "base");
+        let ours = write_commit(&repo, &[base], &[("a.txt", "one\n2\n3\n4\n")], "ours");
+        let theirs = write_commit(&repo, &[base], &[("a.txt", "1\n2\n3\nfour\n")], "theirs");
+        set_ref(&repo, "refs/heads/feature", &theirs);
+        checkout(&repo, "master", &ours);
+
+        assert!(matches!(
+            merge(&repo, "feature").unwrap(),
+            MergeOutcome::MadeCommit(_)
+        ));
+        assert_eq!(read_file(&repo, "a.txt"), "one\n2\n3\nfour\n");
+    }
+
+    #[test]
+    fn fast_forwards_when_head_is_an_ancestor() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n")], "base");
+        let theirs = write_commit(
+            &repo,
+            &[base],
+            &[("a.txt", "a\n"), ("new.txt", "new\n")],
+            "theirs",
+        );
+        set_ref(&repo, "refs/heads/feature", &theirs);
+        checkout(&repo, "master", &base);
+
+        assert_eq!(
+            merge(&repo, "feature").unwrap(),
+            MergeOutcome::FastForward(theirs)
+        );
+        assert_eq!(read_file(&repo, "new.txt"), "new\n");
+        assert_eq!(merge(&repo, "feature").unwrap(), MergeOutcome::UpToDate);
+    }
+
+    #[test]
+    fn refuses_to_start_over_a_stopped_cherry_pick() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n")], "base");
+        let theirs = write_commit(&repo, &[base], &[("a.txt", "theirs\n")], "theirs");
+        set_ref(&repo, "refs/heads/feature", &theirs);
+        checkout(&repo, "master", &base);
+        let git_dir = repo.git_dir();
+        fs::write(git_dir.join("CHERRY_PICK_HEAD"), format!("{}\n", theirs)).unwrap();
+        fs::write(git_dir.join("MERGE_MSG"), "picked\n").unwrap();
+
+        assert!(matches!(
+            merge(&repo, "feature"),
+            Err(GitError::OperationInProgress(RepositoryState::CherryPick))
+        ));
+        assert_eq!(
+            fs::read_to_string(git_dir.join("MERGE_MSG")).unwrap(),
+            "picked\n"
+        );
+        assert!(!git_dir.join("MERGE_HEAD").exists());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::core::oid::Oid;
+use crate::core::revwalk;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// `git merge-base --all`: every best common ancestor of the commits `a`
+/// and `b` point at, newest first. There's usually one, more after a
+/// criss-cross merge, and none when the histories are unrelated.
+pub fn merge_base(repo: &Repository, a: Oid, b: Oid) -> GitResult<Vec<Oid>> {
+    let odb = repo.odb();
+    revwalk::merge_bases(odb, &odb.peel_to_commit(&a)?, &odb.peel_to_commit(&b)?)
+}
+
+/// `git merge-base --is-ancestor`: whether `a` is reachable from `b`,
+/// counting `b` itself. The walk ends as soon as `a` turns up.
+pub fn is_ancestor(repo: &Repository, a: Oid, b: Oid) -> GitResult<bool> {
+    let odb = repo.odb();
+    revwalk::is_ancestor(odb, &odb.peel_to_commit(&a)?, &odb.peel_to_commit(&b)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::commit_graph::{self, WriteOptions};
+    use crate::core::commit_graph::{serialize, CommitGraph, GraphCommit};
+    use crate::core::odb::ObjectDatabase;
+
+    use crate::core::revwalk::Painter;
+    use crate::test_utils::{git, init_repo, write_commit_at};
+    use sha1::{Digest, Sha1};
+    use std::collections::BTreeMap;
+
+    fn git_merge_base(repo: &Repository, args: &[&str]) -> Option<Vec<Oid>> {
+        let mut full = vec!["merge-base", "--all"];
+        full.extend_from_slice(args);
+        let output = git(repo, &full)?;
+        let mut ids: Vec<Oid> = output.lines().map(|l| l.parse::<Oid>().unwrap()).collect();
+        ids.sort();
+        Some(ids)
+    }
+
+    #[test]
+    fn finds_every_base_of_a_criss_cross() {
+        let (_dir, repo) = init_repo();
+        //   root - l1 - l2      (l2 merges r1)
+        //       \     X
+        //         r1 - r2       (r2 merges l1)
+        let root = write_commit_at(&repo, &[], &[("f", "root")], "root", 100);
+        let l1 = write_commit_at(&repo, &[root], &[("f", "l1")], "l1", 110);
+        let r1 = write_commit_at(&repo, &[root], &[("f", "r1")], "r1", 120);
+        let l2 = write_commit_at(&repo, &[l1, r1], &[("f", "l2")], "l2", 130);
+        let r2 = write_commit_at(&repo, &[r1, l1], &[("f", "r2")], "r2", 140);
+        let other = write_commit_at(&repo, &[], &[("g", "other")], "other", 150);
+
+        let mut bases = merge_base(&repo, l2, r2).unwrap();
+        assert_eq!(bases, vec![r1, l1]);
+        bases.sort();
+        if let Some(theirs) = git_merge_base(&repo, &[&l2.to_string(), &r2.to_string()]) {
+            assert_eq!(bases, theirs);
+        }
+        assert_eq!(merge_base(&repo, l2, l1).unwrap(), vec![l1]);
+        assert_eq!(merge_base(&repo, l2, l2).unwrap(), vec![l2]);
+        assert_eq!(merge_base(&repo, l2, other).unwrap(), Vec::<Oid>::new());
+
+        assert!(is_ancestor(&repo, root, r2).unwrap());
+        assert!(is_ancestor(&repo, l1, r2).unwrap());
+        assert!(is_ancestor(&repo, r2, r2).unwrap());
+        assert!(!is_ancestor(&repo, l2, r2).unwrap());
+        assert!(!is_ancestor(&repo, other, r2).unwrap());
+    }
+
+    #[test]
+    fn stops_walking_at_the_bases() {
+        let (_dir, repo) = init_repo();
+        let mut main = vec![write_commit_at(&repo, &[], &[("f", "0")], "0", 1000)];
+        for n in 1..200 {
+            let content = n.to_string();
+            let commit = write_commit_at(
+                &repo,
+                &[main[n - 1]],
+                &[("f", &content)],
+                &content,
+                1000 + n as i64 * 10,
+            );
+            main.push(commit);
+        }
+        let fork = main[190];
+        let side = write_commit_at(&repo, &[fork], &[("g", "side")], "side", 2905);
+        let tip = main[199];
+
+        let mut painter = Painter::new(repo.odb());
+        assert_eq!(painter.merge_bases(&tip, &side).unwrap(), vec![fork]);
+        // The nine commits on main past the fork, the side commit, the fork
+        // and its parent, which is painted stale before the walk gives up.
+        assert_eq!(painter.visited(), 12);
+
+        let mut painter = Painter::new(repo.odb());
+        assert!(painter.is_ancestor(&main[195], &tip).unwrap());
+        assert!(painter.visited() <= 5);
+        let mut painter = Painter::new(repo.odb());
+        assert!(!painter.is_ancestor(&side, &tip).unwrap());
+        assert!(painter.visited() < 20);
+    }
+
+    /// A commit-graph, and nothing else, for a main line of `main` commits
+    /// and a side branch of `side` commits forked from the tenth, returning
+    /// the tips of both and the fork. With `generations` unset the graph
+    /// has none, as graphs from old versions of git don't.
+    fn synthetic_graph(
+        main: usize,
+        side: usize,
+        generations: bool,
+    ) -> (CommitGraph, Oid, Oid, Oid) {
+        let id = |name: String| Oid::from_raw(Sha1::digest(name.as_bytes()).into());
+        let tree = id("tree".to_string());
+        let mut commits = BTreeMap::new();
+        let mut add = |this: Oid, parent: Option<Oid>, generation: usize| {
+            let commit = GraphCommit {
+                tree,
+                parents: parent.into_iter().collect(),
+                generation: if generations { generation as u32 } else { 0 },
+                time: 1_000_000 + generation as i64 * 60,
+            };
+            commits.insert(this, commit);
+        };
+        let mut parent = None;
+        for n in 0..main {
+            let this = id(format!("main {}", n));
+            add(this, parent, n + 1);
+            parent = Some(this);
+        }
+        let fork = id("main 9".to_string());
+        let mut side_parent = fork;
+        for n in 0..side {
+            let this = id(format!("side {}", n));
+            add(this, Some(side_parent), n + 11);
+            side_parent = this;
+        }
+        let graph = CommitGraph::parse(&serialize(&commits).unwrap()).unwrap();
+        (graph, parent.unwrap(), side_parent, fork)
+    }
+
+    #[test]
+    fn generation_numbers_cut_deep_walks_short() {
+        let dir = tempfile::tempdir().unwrap();
+        let visits = |generations: bool| {
+            let (graph, main, side, fork) = synthetic_graph(20_000, 19_000, generations);
+            let odb = ObjectDatabase::new(dir.path()).with_commit_graph(Some(graph));
+            let mut painter = Painter::new(&odb);
+            assert!(!painter.is_ancestor(&side, &main).unwrap());
+            let visited = painter.visited();
+            assert_eq!(
+                Painter::new(&odb).merge_bases(&side, &main).unwrap(),
+                [fork]
+            );
+            assert!(Painter::new(&odb).is_ancestor(&fork, &side).unwrap());
+            assert!(!Painter::new(&odb).is_ancestor(&main, &side).unwrap());
+            visited
+        };
+        let (with, without) = (visits(true), visits(false));
+        assert!(
+            with * 10 < without,
+            "{} commits read with generations, {} without",
+            with,
+            without
+        );
+    }
+
+    #[test]
+    fn stays_right_when_the_graph_covers_part_of_history() {
+        let (dir, repo) = init_repo();
+        let mut main = vec![write_commit_at(&repo, &[], &[("f", "0")], "0", 1000)];
+        let mut side = Vec::new();
+        for n in 1..30 {
+            let content = n.to_string();
+            let time = 1000 + n as i64 * 10;
+            main.push(write_commit_at(
+                &repo,
+                &[main[n - 1]],
+                &[("f", &content)],
+                &content,
+                time,
+            ));
+            if n >= 5 {
+                let parent = side.last().copied().unwrap_or(main[4]);
+                side.push(write_commit_at(
+                    &repo,
+                    &[parent],
+                    &[("g", &content)],
+                    &content,
+                    time + 5,
+                ));
+            }
+        }
+        let merge = write_commit_at(&repo, &[main[29], side[10]], &[("f", "m")], "m", 2000);
+        let candidates: Vec<Oid> = main
+            .iter()
+            .chain(&side)
+            .copied()
+            .chain(Some(merge))
+            .collect();
+        let answers = |repo: &Repository| {
+            let mut answers = Vec::new();
+            for a in candidates.iter().step_by(3) {
+                for b in candidates.iter().step_by(4) {
+                    answers.push((
+                        is_ancestor(repo, *a, *b).unwrap(),
+                        merge_base(repo, *a, *b).unwrap(),
+                    ));
+                }
+            }
+            answers
+        };
+        let expected = answers(&repo);
+        let options = WriteOptions {
+            commits: Some(vec![main[15], side[8]]),
+        };
+        commit_graph::write(&repo, &options).unwrap();
+        let graphed = Repository::open(dir.path()).unwrap();
+        assert!(graphed
+            .odb()
+            .commit_graph()
+            .unwrap()
+            .lookup(&main[20])
+            .is_none());
+        assert_eq!(answers(&graphed), expected);
+    }
+}
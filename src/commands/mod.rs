@@ -0,0 +1,47 @@
+pub mod add;
+pub mod am;
+pub mod apply;
+pub mod blame;
+pub mod branch;
+pub mod cat_file;
+pub mod check_ignore;
+pub mod checkout;
+pub mod cherry_pick;
+pub mod clean;
+pub mod clone;
+pub mod commit;
+pub mod commit_graph;
+pub mod config;
+pub mod describe;
+pub mod diff;
+pub mod diff_tree;
+pub mod fetch;
+pub mod for_each_ref;
+pub mod format_patch;
+pub mod fsck;
+pub mod index_pack;
+pub mod log;
+pub mod ls_files;
+pub mod merge;
+pub mod merge_base;
+pub mod mv;
+pub mod name_rev;
+pub mod pack_refs;
+pub mod prune;
+pub mod pull;
+pub mod read_tree;
+pub mod reflog;
+pub mod remote;
+pub mod repack;
+pub mod restore;
+pub mod rev_list;
+pub mod rev_parse;
+pub mod revert;
+pub mod shortlog;
+pub mod stash;
+pub mod status;
+pub mod symbolic_ref;
+pub mod tag;
+pub mod verify_commit;
+pub mod verify_pack;
+pub mod write_tree;
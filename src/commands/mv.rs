@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::worktree::relative_path;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// `git mv`: move the tracked file or directory `src` to `dst` on disk and
+/// in the index, keeping each entry's staged id and mode. A `dst` that is
+/// an existing directory receives `src` inside it. Anything already at the
+/// destination is only replaced when `force` is set.
+pub fn mv(repo: &Repository, src: &Path, dst: &Path, force: bool) -> GitResult<()> {
+    let work_dir = repo.require_work_dir()?;
+    let from = relative_path(work_dir, src)?;
+    let mut to = relative_path(work_dir, dst)?;
+    if from.is_empty() || to.is_empty() {
+        return Err(GitError::NotTracked(src.to_path_buf()));
+    }
+    if work_dir.join(&to).is_dir() {
+        let name = from.rsplit('/').next().unwrap_or(&from);
+        to = format!("{}/{}", to, name);
+    }
+
+    let mut index = repo.read_index()?;
+    let dir_prefix = format!("{}/", from);
+    let moving: Vec<_> = index
+        .entries()
+        .iter()
+        .filter(|entry| entry.path == from || entry.path.starts_with(&dir_prefix))
+        .cloned()
+        .collect();
+    if moving.is_empty() {
+        return Err(GitError::NotTracked(src.to_path_buf()));
+    }
+    if moving.iter().any(|entry| entry.stage() != 0) {
+        return Err(GitError::UnresolvedConflicts(vec![PathBuf::from(&from)]));
+    }
+    if to == from || to.starts_with(&dir_prefix) {
+        return Err(GitError::WouldOverwrite(dst.to_path_buf()));
+    }
+    let is_dir = moving.iter().all(|entry| entry.path != from);
+
+    let target = work_dir.join(&to);
+    let occupied = fs::symlink_metadata(&target).is_ok()
+        || index.entries().iter().any(|entry| entry.path == to);
+    if occupied {
+        // Only a file can be replaced, and only when asked to.
+        if !force || is_dir || target.is_dir() {
+            return Err(GitError::WouldOverwrite(PathBuf::from(&to)));
+        }
+        index.remove(&to);
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(work_dir.join(&from), &target)?;
+    for mut entry in moving {
+        index.remove(&entry.path);
+        entry.path = format!("{}{}", to, &entry.path[from.len()..]);
+        // A rename changes the ctime, so refresh the stat data along
+        // with the path.
+        if let Ok(meta) = fs::symlink_metadata(work_dir.join(&entry.path)) {
+            entry.update_stat(&meta);
+        }
+        index.add(entry);
+    }
+    repo.write_index(&index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{git, init_repo, read_file, stage_file, staged};
+
+    #[test]
+    fn renames_a_file_keeping_its_entry() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "old.txt", "content");
+        let before = staged(&repo);
+
+        mv(&repo, Path::new("old.txt"), Path::new("sub/new.txt"), false).unwrap();
+        assert_eq!(read_file(&repo, "sub/new.txt"), "content");
+        assert!(!repo.work_dir().unwrap().join("old.txt").exists());
+        let after = staged(&repo);
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].0, "sub/new.txt");
+        assert_eq!((after[0].1, after[0].2), (before[0].1, before[0].2));
+        // The refreshed stat data lets git see the file as unchanged.
+        let work_tree = repo.work_dir().unwrap().to_str().unwrap();
+        if let Some(output) = git(
+            &repo,
+            &["--work-tree", work_tree, "diff-files", "--name-only"],
+        ) {
+            assert_eq!(output, "");
+        }
+
+        assert!(matches!(
+            mv(&repo, Path::new("untracked"), Path::new("x"), false),
+            Err(GitError::NotTracked(_))
+        ));
+    }
+
+    #[test]
+    fn moves_into_an_existing_directory() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a.txt", "a");
+        stage_file(&repo, "lib/one.rs", "one");
+        stage_file(&repo, "lib/two.rs", "two");
+        fs::create_dir_all(repo.work_dir().unwrap().join("dest")).unwrap();
+
+        mv(&repo, Path::new("a.txt"), Path::new("dest"), false).unwrap();
+        mv(&repo, Path::new("lib"), Path::new("dest"), false).unwrap();
+        let paths: Vec<String> = staged(&repo).into_iter().map(|(path, ..)| path).collect();
+        assert_eq!(
+            paths,
+            vec!["dest/a.txt", "dest/lib/one.rs", "dest/lib/two.rs"]
+        );
+        assert_eq!(read_file(&repo, "dest/lib/two.rs"), "two");
+    }
+
+    #[test]
+    fn refuses_to_overwrite_without_force() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a", "a");
+        stage_file(&repo, "b", "b");
+
+        assert!(matches!(
+            mv(&repo, Path::new("a"), Path::new("b"), false),
+            Err(GitError::WouldOverwrite(_))
+        ));
+        assert_eq!(read_file(&repo, "b"), "b");
+
+        mv(&repo, Path::new("a"), Path::new("b"), true).unwrap();
+        assert_eq!(read_file(&repo, "b"), "a");
+        let paths: Vec<String> = staged(&repo).into_iter().map(|(path, ..)| path).collect();
+        assert_eq!(paths, vec!["b"]);
+    }
+}
@@ -0,0 +1,378 @@
+//! `git name-rev`: name commits by how they're reached from refs, as
+//! `tags/v1.0~3` or `master~2^2~5`.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::core::object::ObjectType;
+use crate::core::oid::Oid;
+use crate::core::refs;
+use crate::core::shallow;
+use crate::core::wildmatch::wildmatch;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// What crossing to a second or later parent costs, next to one step down
+/// a first parent. Matches git, which makes names that stay on one line of
+/// history win over shorter ones that hop across merges.
+const MERGE_TRAVERSAL_WEIGHT: u64 = 65535;
+/// How much older than the oldest commit asked about the walk goes before
+/// giving up, to allow for clock skew.
+const CUTOFF_DATE_SLOP: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameRevOptions {
+    /// Only name commits after tags, as `--tags` does.
+    pub tags_only: bool,
+    /// Only use refs matching this glob, as `--refs` does: against the
+    /// whole name, or against any tail of it after a `/`, in which case
+    /// names are shortened further as in `v1.0~2`.
+    pub refs: Option<String>,
+    /// Fall back to the abbreviated id for what no ref reaches.
+    pub always: bool,
+}
+
+/// `git name-rev`: each of `oids` with the name it's reached by from a
+/// ref, or `None` when no ref reaches it.
+///
+/// One walk from every ref tip, tags first and older tags before newer,
+/// names every commit the tips reach, a commit taking a new name only when
+/// it is better: from a tag rather than a branch, from an older tag, or
+/// fewer steps away. Other objects are named only when a ref points
+/// straight at them.
+pub fn name_rev(
+    repo: &Repository,
+    oids: &[Oid],
+    options: &NameRevOptions,
+) -> GitResult<Vec<(Oid, Option<String>)>> {
+    let odb = repo.odb();
+    let tips = tips(repo, options)?;
+
+    let mut cutoff = i64::MAX;
+    for id in oids {
+        if odb.read_header(id)?.0 == ObjectType::Commit {
+            cutoff = cutoff.min(odb.read_commit(id)?.committer.time);
+        }
+    }
+    let cutoff = cutoff.saturating_sub(CUTOFF_DATE_SLOP);
+    let shallow = shallow::shallow_commits(repo)?;
+
+    let mut names: HashMap<Oid, RevName> = HashMap::new();
+    for tip in tips.iter().filter(|tip| tip.commit.is_some()) {
+        let start = tip.commit.unwrap();
+        let name = RevName {
+            tip_name: Rc::from(if tip.deref {
+                format!("{}^0", tip.name)
+            } else {
+                tip.name.clone()
+            }),
+            tagger_date: tip.tagger_date,
+            generation: 0,
+            distance: 0,
+            from_tag: tip.from_tag,
+        };
+        if !update_name(&mut names, start, name) {
+            continue;
+        }
+        // Depth first, with a commit's first parent explored before its
+        // others, as git does.
+        let mut stack = vec![start];
+        while let Some(id) = stack.pop() {
+            let name = names[&id].clone();
+            let parents = if shallow.contains(&id) {
+                Vec::new()
+            } else {
+                odb.read_commit(&id)?.parents
+            };
+            let mut queued = Vec::new();
+            for (number, parent) in parents.into_iter().enumerate().map(|(i, p)| (i + 1, p)) {
+                if odb.read_commit(&parent)?.committer.time < cutoff {
+                    continue;
+                }
+                let candidate = if number > 1 {
+                    let base = strip_deref(&name.tip_name);
+                    let tip_name = if name.generation > 0 {
+                        format!("{}~{}^{}", base, name.generation, number)
+                    } else {
+                        format!("{}^{}", base, number)
+                    };
+                    RevName {
+                        tip_name: Rc::from(tip_name),
+                        generation: 0,
+                        distance: name.distance + MERGE_TRAVERSAL_WEIGHT,
+                        ..name.clone()
+                    }
+                } else {
+                    RevName {
+                        generation: name.generation + 1,
+                        distance: name.distance + 1,
+                        ..name.clone()
+                    }
+                };
+                if update_name(&mut names, parent, candidate) {
+                    queued.push(parent);
+                }
+            }
+            stack.extend(queued.into_iter().rev());
+        }
+    }
+
+    let mut answers = Vec::with_capacity(oids.len());
+    for id in oids {
+        let name = if odb.read_header(id)?.0 == ObjectType::Commit {
+            names.get(id).map(RevName::to_name)
+        } else {
+            tips.iter()
+                .find(|tip| tip.target == *id)
+                .map(|tip| tip.name.clone())
+        };
+        let name = match name {
+            None if options.always => Some(odb.abbreviate(id, 7)?),
+            name => name,
+        };
+        answers.push((*id, name));
+    }
+    Ok(answers)
+}
+
+/// A ref the walk starts from.
+struct Tip {
+    /// The ref's name as names are built from it, as `tags/v1.0`.
+    name: String,
+    target: Oid,
+    /// The commit the ref peels to, if it does.
+    commit: Option<Oid>,
+    /// For an annotated tag, when it was tagged; otherwise the commit's
+    /// date.
+    tagger_date: i64,
+    from_tag: bool,
+    /// Whether the ref is a tag object, whose commit is named `<tag>^0`.
+    deref: bool,
+}
+
+/// The refs `options` allows, in the order the walk visits them: tags
+/// before other refs, and older before newer.
+fn tips(repo: &Repository, options: &NameRevOptions) -> GitResult<Vec<Tip>> {
+    let odb = repo.odb();
+    let mut tips = Vec::new();
+    for reference in refs::list(repo)? {
+        let path = reference.name.as_str();
+        if options.tags_only && !path.starts_with("refs/tags/") {
+            continue;
+        }
+        let mut abbreviate = false;
+        if let Some(pattern) = &options.refs {
+            match subpath_matches(path, pattern) {
+                Some(0) => {}
+                Some(_) => abbreviate = true,
+                None => continue,
+            }
+        }
+        let mut id = reference.target;
+        let mut kind = odb.read_header(&id)?.0;
+        let mut tagger_date = None;
+        let mut deref = false;
+        while kind == ObjectType::Tag {
+            let tag = odb.read_tag(&id)?;
+            tagger_date = Some(tag.tagger.map_or(0, |tagger| tagger.time));
+            deref = true;
+            id = tag.object;
+            kind = tag.kind;
+        }
+        let commit = (kind == ObjectType::Commit).then_some(id);
+        let tagger_date = match (tagger_date, commit) {
+            (Some(date), _) => date,
+            (None, Some(commit)) => odb.read_commit(&commit)?.committer.time,
+            (None, None) => 0,
+        };
+        let name = if abbreviate {
+            refs::shorten(path)
+        } else {
+            path.strip_prefix("refs/heads/")
+                .or_else(|| path.strip_prefix("refs/"))
+                .unwrap_or(path)
+        };
+        tips.push(Tip {
+            name: name.to_string(),
+            target: reference.target,
+            commit,
+            tagger_date,
+            from_tag: path.starts_with("refs/tags/"),
+            deref,
+        });
+    }
+    tips.sort_by_key(|tip| (!tip.from_tag, tip.tagger_date));
+    Ok(tips)
+}
+
+/// Where in `path` `pattern` matches: `Some(0)` for the whole of it, or
+/// the offset of the first tail after a `/` it matches.
+fn subpath_matches(path: &str, pattern: &str) -> Option<usize> {
+    let mut offsets = std::iter::once(0).chain(path.match_indices('/').map(|(i, _)| i + 1));
+    offsets.find(|&at| wildmatch(pattern.as_bytes(), &path.as_bytes()[at..], false))
+}
+
+/// How a commit is reached: `generation` first-parent steps from
+/// `tip_name`, `distance` being the cost of the whole path.
+#[derive(Debug, Clone)]
+struct RevName {
+    tip_name: Rc<str>,
+    tagger_date: i64,
+    generation: u64,
+    distance: u64,
+    from_tag: bool,
+}
+
+impl RevName {
+    fn to_name(&self) -> String {
+        if self.generation == 0 {
+            self.tip_name.to_string()
+        } else {
+            format!("{}~{}", strip_deref(&self.tip_name), self.generation)
+        }
+    }
+
+    /// Whether `self` should replace `current`, as git weighs them.
+    fn is_better_than(&self, current: &RevName) -> bool {
+        let effective = |name: &RevName| {
+            name.distance
+                + if name.generation > 0 {
+                    MERGE_TRAVERSAL_WEIGHT
+                } else {
+                    0
+                }
+        };
+        if self.from_tag && current.from_tag {
+            // Going by tags, the older tag wins even when it's further.
+            return current.tagger_date > self.tagger_date
+                || (current.tagger_date == self.tagger_date
+                    && effective(current) > effective(self));
+        }
+        if self.from_tag != current.from_tag {
+            return self.from_tag;
+        }
+        if current.distance != self.distance {
+            return current.distance > self.distance;
+        }
+        current.tagger_date > self.tagger_date
+    }
+}
+
+/// Give `id` the name `candidate` unless it already has a better one,
+/// returning whether it took it.
+fn update_name(names: &mut HashMap<Oid, RevName>, id: Oid, candidate: RevName) -> bool {
+    match names.get(&id) {
+        Some(current) if !candidate.is_better_than(current) => false,
+        _ => {
+            names.insert(id, candidate);
+            true
+        }
+    }
+}
+
+fn strip_deref(tip_name: &str) -> &str {
+    match tip_name.strip_suffix("^0") {
+        Some(stripped) if !stripped.is_empty() => stripped,
+        _ => tip_name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tag;
+
+    use crate::core::signature::Signature;
+    use crate::test_utils::{git, init_repo, set_ref, write_commit_at};
+
+    #[test]
+    fn names_commits_like_git() {
+        let (_dir, repo) = init_repo();
+        let commit = |parents: &[Oid], name: &str, time: i64| {
+            write_commit_at(&repo, parents, &[(name, name)], name, time)
+        };
+        let c1 = commit(&[], "c1", 1000);
+        let c2 = commit(&[c1], "c2", 2000);
+        let s1 = commit(&[c2], "s1", 3000);
+        let s2 = commit(&[s1], "s2", 4000);
+        let c3 = commit(&[c2], "c3", 3500);
+        let merge = commit(&[c3, s2], "merge", 5000);
+        let c4 = commit(&[merge], "c4", 6000);
+        let lost = commit(&[c1], "lost", 7000);
+        set_ref(&repo, "refs/heads/master", &c4);
+        set_ref(&repo, "refs/heads/topic", &s1);
+        // Older than v2.0, so it names what both tags reach.
+        let tagger = Signature::new("A U Thor", "author@example.com", 2500, 0);
+        tag::create_annotated(&repo, "v1.0", &c2.to_string(), "one\n", tagger, false).unwrap();
+        tag::create_lightweight(&repo, "v2.0", "master~1", false).unwrap();
+
+        let ids = [c1, c2, s1, s2, c3, merge, c4, lost];
+        let named = |options: &NameRevOptions| -> Vec<Option<String>> {
+            name_rev(&repo, &ids, options)
+                .unwrap()
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect()
+        };
+        let default = named(&NameRevOptions::default());
+        let expected = [
+            "tags/v1.0~1",
+            "tags/v1.0^0",
+            "tags/v2.0^2~1",
+            "tags/v2.0^2",
+            "tags/v2.0~1",
+            "tags/v2.0",
+            "master",
+        ];
+        assert_eq!(default[..7], expected.map(|name| Some(name.to_string())));
+        assert_eq!(default[7], None);
+
+        let cases = [
+            ("", NameRevOptions::default()),
+            (
+                "--tags",
+                NameRevOptions {
+                    tags_only: true,
+                    ..NameRevOptions::default()
+                },
+            ),
+            (
+                "--refs=refs/heads/*",
+                NameRevOptions {
+                    refs: Some("refs/heads/*".to_string()),
+                    ..NameRevOptions::default()
+                },
+            ),
+            (
+                "--refs=v1*",
+                NameRevOptions {
+                    refs: Some("v1*".to_string()),
+                    ..NameRevOptions::default()
+                },
+            ),
+            (
+                // git prints `undefined` ahead of falling back unless told
+                // not to.
+                "--always --no-undefined",
+                NameRevOptions {
+                    always: true,
+                    ..NameRevOptions::default()
+                },
+            ),
+        ];
+        let hexes: Vec<String> = ids.iter().map(Oid::to_string).collect();
+        for (flag, options) in &cases {
+            let ours: String = hexes
+                .iter()
+                .zip(named(options))
+                .map(|(hex, name)| format!("{} {}\n", hex, name.as_deref().unwrap_or("undefined")))
+                .collect();
+            let mut args = vec!["name-rev"];
+            args.extend(flag.split_whitespace());
+            args.extend(hexes.iter().map(String::as_str));
+            if let Some(theirs) = git(&repo, &args) {
+                assert_eq!(ours, theirs, "{}", flag);
+            }
+        }
+    }
+}
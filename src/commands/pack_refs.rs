@@ -0,0 +1,128 @@
+use crate::core::refs;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// Move loose refs into `packed-refs`, returning the names packed. Like
+/// `git pack-refs`, only tags and refs that are already packed are moved
+/// unless `all` is set, and the loose files are only deleted with `prune`.
+pub fn pack_refs(repo: &Repository, all: bool, prune: bool) -> GitResult<Vec<String>> {
+    refs::pack(
+        repo,
+        |name, already_packed| all || already_packed || name.starts_with("refs/tags/"),
+        prune,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tag::{create_annotated, create_lightweight};
+    use crate::core::lockfile::LockFile;
+    use crate::core::oid::Oid;
+    use crate::test_utils::{git, init_repo, signature, write_commit};
+    use crate::Repository;
+
+    fn resolved(repo: &Repository) -> Vec<(String, Oid, Option<Oid>)> {
+        refs::list(repo)
+            .unwrap()
+            .into_iter()
+            .map(|r| {
+                let (target, peeled) = (r.target, repo.odb().peel(&r.target).unwrap().0);
+                (r.name, target, Some(peeled).filter(|p| *p != target))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packing_keeps_every_ref_resolving() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        let next = write_commit(&repo, &[base], &[("a", "b")], "next");
+        refs::update(&repo, "refs/heads/master", next, None, "").unwrap();
+        refs::update(&repo, "refs/heads/topic/one", base, None, "").unwrap();
+        create_lightweight(&repo, "light", "master", false).unwrap();
+        let v1 =
+            create_annotated(&repo, "v1.0", &base.to_string(), "one", signature(), false).unwrap();
+        create_annotated(&repo, "release/v2.0", "master", "two", signature(), false).unwrap();
+        let before = resolved(&repo);
+
+        let packed = pack_refs(&repo, false, true).unwrap();
+        assert_eq!(
+            packed,
+            vec![
+                "refs/tags/light",
+                "refs/tags/release/v2.0",
+                "refs/tags/v1.0"
+            ]
+        );
+        assert_eq!(resolved(&repo), before);
+        let tags = repo.git_dir().join("refs/tags");
+        assert!(!tags.join("v1.0").exists());
+        assert!(!tags.join("release").exists());
+        assert!(tags.is_dir());
+        assert!(repo.git_dir().join("refs/heads/master").is_file());
+
+        let text = std::fs::read_to_string(repo.git_dir().join("packed-refs")).unwrap();
+        assert!(text.starts_with("# pack-refs with: peeled fully-peeled sorted \n"));
+        assert!(text.contains(&format!(
+            "{} refs/tags/v1.0\n^{}\n",
+            v1,
+            repo.odb().peel(&v1).unwrap().0
+        )));
+
+        // Without pruning the loose files stay, and still win.
+        pack_refs(&repo, true, false).unwrap();
+        assert!(repo.git_dir().join("refs/heads/master").is_file());
+        assert_eq!(resolved(&repo), before);
+        pack_refs(&repo, true, true).unwrap();
+        assert!(!repo.git_dir().join("refs/heads/topic").exists());
+        assert_eq!(resolved(&repo), before);
+
+        if let Some(output) = git(&repo, &["show-ref", "--dereference"]) {
+            assert_eq!(output.lines().count(), before.len() + 2);
+        }
+    }
+
+    #[test]
+    fn leaves_refs_being_updated_loose() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        create_lightweight(&repo, "held", &base.to_string(), false).unwrap();
+        let path = repo.git_dir().join("refs/tags/held");
+        let _held = LockFile::acquire(&path).unwrap();
+
+        pack_refs(&repo, false, true).unwrap();
+        assert!(path.is_file());
+        assert_eq!(repo.resolve_rev("held").unwrap(), base);
+    }
+
+    #[test]
+    fn never_packs_symbolic_refs() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        refs::update(&repo, "refs/remotes/origin/main", base, None, "").unwrap();
+        refs::update_symbolic(
+            &repo,
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            None,
+        )
+        .unwrap();
+        refs::update(&repo, "HEAD", base, None, "").unwrap();
+
+        let packed = pack_refs(&repo, true, true).unwrap();
+        assert_eq!(packed, ["refs/heads/master", "refs/remotes/origin/main"]);
+        assert_eq!(
+            refs::read(&repo, "refs/remotes/origin/HEAD").unwrap(),
+            Some(refs::RefTarget::Symbolic(
+                "refs/remotes/origin/main".to_string()
+            ))
+        );
+        let text = std::fs::read_to_string(repo.git_dir().join("packed-refs")).unwrap();
+        assert!(!text.contains("HEAD"));
+        assert_eq!(
+            refs::read(&repo, "HEAD").unwrap(),
+            Some(refs::RefTarget::Symbolic("refs/heads/master".to_string()))
+        );
+    }
+}
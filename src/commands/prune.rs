@@ -0,0 +1,189 @@
+use std::collections::HashSet;
+use std::fs;
+use std::time::SystemTime;
+
+use crate::core::date;
+use crate::core::object::{GitObject, MODE_GITLINK};
+use crate::core::oid::Oid;
+use crate::core::reflog;
+use crate::core::refs;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// How old an unreachable object has to be before it's pruned, unless
+/// `gc.pruneExpire` says otherwise.
+pub const DEFAULT_EXPIRE: &str = "2.weeks.ago";
+
+/// Delete the loose objects that nothing reaches: not a ref, HEAD, an
+/// entry in any reflog, nor the index. Objects written more recently than
+/// `gc.pruneExpire` are left alone, since whoever wrote them may be about
+/// to point a ref at them. Returns what was pruned, or with `dry_run` what
+/// would have been, sorted.
+pub fn prune(repo: &Repository, dry_run: bool) -> GitResult<Vec<Oid>> {
+    let config = repo.config()?;
+    let expire = config
+        .get("gc", None, "pruneExpire")
+        .unwrap_or(DEFAULT_EXPIRE);
+    let cutoff = match parse_expire(expire) {
+        Some(cutoff) => cutoff,
+        None => {
+            return Err(GitError::InvalidConfigValue {
+                key: "gc.pruneExpire".to_string(),
+                value: expire.to_string(),
+            })
+        }
+    };
+    let cutoff = match cutoff {
+        Some(cutoff) => cutoff,
+        None => return Ok(Vec::new()),
+    };
+
+    let reachable = reachable_objects(repo)?;
+    let odb = repo.odb();
+    let mut pruned = Vec::new();
+    for id in odb.loose_objects()? {
+        if reachable.contains(&id) {
+            continue;
+        }
+        let modified = fs::metadata(odb.loose_path(&id))?.modified()?;
+        if modified > cutoff {
+            continue;
+        }
+        if !dry_run {
+            odb.remove_loose(&id)?;
+        }
+        pruned.push(id);
+    }
+    Ok(pruned)
+}
+
+/// `gc.pruneExpire`'s cutoff: `Some(None)` for `never`, otherwise the time
+/// objects must be older than, as [`date::parse_approxidate`] reads it.
+fn parse_expire(value: &str) -> Option<Option<SystemTime>> {
+    match value {
+        "never" | "false" => Some(None),
+        _ => date::parse_approxidate(value, SystemTime::now())
+            .ok()
+            .map(Some),
+    }
+}
+
+/// Every object reachable from the refs, HEAD, the reflogs and the index.
+/// Missing objects are skipped rather than treated as errors, so a
+/// damaged repository can still be pruned.
+pub(crate) fn reachable_objects(repo: &Repository) -> GitResult<HashSet<Oid>> {
+    let mut pending = Vec::new();
+    for reference in refs::iter(repo)? {
+        pending.push(reference?.target);
+    }
+    pending.extend(repo.head_commit()?);
+    for refname in reflog::logged_refs(repo)? {
+        for entry in reflog::read(repo, &refname)? {
+            pending.push(entry.new);
+            pending.extend(entry.old);
+        }
+    }
+    let mut reachable: HashSet<Oid> = repo
+        .read_index()?
+        .entries()
+        .iter()
+        .filter(|entry| entry.mode != MODE_GITLINK)
+        .map(|entry| entry.oid)
+        .collect();
+
+    let odb = repo.odb();
+    while let Some(id) = pending.pop() {
+        if !reachable.insert(id) || !odb.contains(&id) {
+            continue;
+        }
+        match odb.read(&id)? {
+            GitObject::Blob(_) => {}
+            GitObject::Tree(tree) => {
+                for entry in tree.entries {
+                    if entry.mode == MODE_GITLINK {
+                        continue;
+                    }
+                    if entry.is_tree() {
+                        pending.push(entry.oid);
+                    } else {
+                        // No need to read a blob to know it has no links.
+                        reachable.insert(entry.oid);
+                    }
+                }
+            }
+            GitObject::Commit(commit) => {
+                pending.push(commit.tree);
+                pending.extend(commit.parents);
+            }
+            GitObject::Tag(tag) => pending.push(tag.object),
+        }
+    }
+    Ok(reachable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tag;
+    use crate::core::object::ObjectType;
+
+    use crate::test_utils::{git, init_repo, signature, write_commit};
+
+    #[test]
+    fn prunes_only_old_unreachable_objects() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "kept")], "base");
+        repo.set_head_commit(&base, "").unwrap();
+        let odb = repo.odb();
+        let dangling = odb.write_raw(ObjectType::Blob, b"dangling").unwrap();
+        let tagged = odb.write_raw(ObjectType::Blob, b"tagged").unwrap();
+        tag::create_annotated(
+            &repo,
+            "blob",
+            &tagged.to_string(),
+            "a blob",
+            signature(),
+            false,
+        )
+        .unwrap();
+        let kept = odb
+            .read_tree(&odb.read_commit(&base).unwrap().tree)
+            .unwrap();
+        let kept = kept.get("a").unwrap().oid;
+
+        // Too new to go under the default grace period.
+        assert_eq!(prune(&repo, false).unwrap(), Vec::<Oid>::new());
+
+        let mut config = fs::read_to_string(repo.config_path()).unwrap();
+        config.push_str("[gc]\n\tpruneExpire = now\n");
+        fs::write(repo.config_path(), &config).unwrap();
+        if let Some(output) = git(&repo, &["prune", "--dry-run"]) {
+            assert_eq!(output, format!("{} blob\n", dangling));
+        }
+        assert_eq!(prune(&repo, true).unwrap(), vec![dangling]);
+        assert!(odb.contains(&dangling));
+
+        assert_eq!(prune(&repo, false).unwrap(), vec![dangling]);
+        assert!(!odb.contains(&dangling));
+        assert!(odb.contains(&kept) && odb.contains(&tagged));
+        assert_eq!(prune(&repo, false).unwrap(), Vec::<Oid>::new());
+
+        config.push_str("[gc]\n\tpruneExpire = someday\n");
+        fs::write(repo.config_path(), &config).unwrap();
+        assert!(matches!(
+            prune(&repo, false),
+            Err(GitError::InvalidConfigValue { .. })
+        ));
+    }
+
+    #[test]
+    fn parses_expiry_times() {
+        let now = SystemTime::now();
+        let two_weeks = parse_expire("2.weeks.ago").unwrap().unwrap();
+        let age = now.duration_since(two_weeks).unwrap().as_secs();
+        assert!((14 * 24 * 3600 - 5..=14 * 24 * 3600).contains(&age));
+        assert!(parse_expire("1 hour ago").unwrap().is_some());
+        assert_eq!(parse_expire("never"), Some(None));
+        assert_eq!(parse_expire("2.fortnights.ago"), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! `git pull`: a fetch, then the current branch's upstream merged in.
+
+use crate::commands::branch;
+use crate::commands::config::config_get;
+use crate::commands::fetch::{fetch, RefUpdate};
+use crate::commands::merge::{merge, MergeOutcome};
+use crate::core::refs;
+use crate::core::revwalk;
+use crate::error::{GitError, GitResult};
+use crate::repository::{Head, Repository};
+
+/// Options for [`pull_with`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PullOptions {
+    /// Only move the branch if it fast-forwards, like `--ff-only`, rather
+    /// than merging diverged history.
+    pub ff_only: bool,
+}
+
+/// What a pull fetched and how it was integrated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullOutcome {
+    pub fetched: Vec<RefUpdate>,
+    pub merge: MergeOutcome,
+}
+
+/// [`pull_with`] with the default options.
+pub fn pull(repo: &Repository, remote: &str) -> GitResult<PullOutcome> {
+    pull_with(repo, remote, &PullOptions::default())
+}
+
+/// Fetch from `remote`, then merge the current branch's upstream on it
+/// into the branch: a fast-forward when the branch has nothing of its
+/// own, otherwise a merge commit, or conflicts left to resolve as
+/// [`merge`] leaves them. The branch has to track a branch of `remote`.
+pub fn pull_with(repo: &Repository, remote: &str, options: &PullOptions) -> GitResult<PullOutcome> {
+    let name = match repo.head()? {
+        Head::Branch(name, _) | Head::Unborn(name) => refs::shorten(&name).to_string(),
+        Head::Detached(_) => return Err(GitError::NoUpstream("HEAD".to_string())),
+    };
+    let tracks = config_get(repo, &format!("branch.{}.remote", name))?;
+    let upstream = match branch::upstream_of(repo, &name)? {
+        Some(upstream) if tracks.as_deref() == Some(remote) => upstream,
+        _ => return Err(GitError::NoUpstream(name)),
+    };
+
+    let fetched = fetch(repo, remote)?;
+    let theirs = refs::resolve(repo, &upstream)?;
+    let short = refs::shorten(&upstream);
+    if options.ff_only {
+        if let Some(ours) = repo.head_commit()? {
+            let odb = repo.odb();
+            if !revwalk::is_ancestor(odb, &ours, &theirs)?
+                && !revwalk::is_ancestor(odb, &theirs, &ours)?
+            {
+                return Err(GitError::NotFastForward(short.to_string()));
+            }
+        }
+    }
+    Ok(PullOutcome {
+        fetched,
+        merge: merge(repo, short)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::clone::clone_local;
+    use crate::commands::config::config_set;
+    use crate::core::oid::Oid;
+    use crate::test_utils::{checkout, init_repo, read_file, set_ref, write_commit};
+
+    fn cloned() -> (
+        tempfile::TempDir,
+        Repository,
+        tempfile::TempDir,
+        Repository,
+        Oid,
+    ) {
+        let (src_dir, src) = init_repo();
+        let base = write_commit(&src, &[], &[("a", "a\n")], "base");
+        checkout(&src, "main", &base);
+        let dst_dir = tempfile::tempdir().unwrap();
+        let repo = clone_local(src_dir.path(), &dst_dir.path().join("clone")).unwrap();
+        (src_dir, src, dst_dir, repo, base)
+    }
+
+    #[test]
+    fn fast_forwards_the_branch_and_working_tree() {
+        let (_src_dir, src, _dst_dir, repo, base) = cloned();
+        let next = write_commit(&src, &[base], &[("a", "a\n"), ("b", "b\n")], "next");
+        set_ref(&src, "refs/heads/main", &next);
+
+        let outcome = pull(&repo, "origin").unwrap();
+        assert_eq!(outcome.fetched.len(), 1);
+        assert_eq!(outcome.merge, MergeOutcome::FastForward(next));
+        assert_eq!(
+            repo.head().unwrap(),
+            Head::Branch("refs/heads/main".to_string(), Some(next))
+        );
+        assert_eq!(read_file(&repo, "b"), "b\n");
+        assert_eq!(pull(&repo, "origin").unwrap().merge, MergeOutcome::UpToDate);
+        assert!(matches!(
+            pull(&repo, "upstream"),
+            Err(GitError::NoUpstream(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_diverged_history_with_ff_only() {
+        let (_src_dir, src, _dst_dir, repo, base) = cloned();
+        let theirs = write_commit(&src, &[base], &[("a", "a\n"), ("b", "b\n")], "theirs");
+        set_ref(&src, "refs/heads/main", &theirs);
+        let ours = write_commit(&repo, &[base], &[("a", "a\n"), ("c", "c\n")], "ours");
+        checkout(&repo, "main", &ours);
+
+        let ff_only = PullOptions { ff_only: true };
+        assert!(matches!(
+            pull_with(&repo, "origin", &ff_only),
+            Err(GitError::NotFastForward(_))
+        ));
+        // The fetch still happened; only the branch stayed put.
+        assert_eq!(
+            refs::resolve(&repo, "refs/remotes/origin/main").unwrap(),
+            theirs
+        );
+        assert_eq!(repo.head_commit().unwrap(), Some(ours));
+
+        config_set(&repo, "user.name", "A U Thor").unwrap();
+        config_set(&repo, "user.email", "author@example.com").unwrap();
+        let merged = match pull(&repo, "origin").unwrap().merge {
+            MergeOutcome::MadeCommit(id) => id,
+            other => panic!("expected a merge commit, got {:?}", other),
+        };
+        assert_eq!(
+            repo.odb().read_commit(&merged).unwrap().parents,
+            [ours, theirs]
+        );
+        assert_eq!(read_file(&repo, "b"), "b\n");
+        assert_eq!(read_file(&repo, "c"), "c\n");
+    }
+}
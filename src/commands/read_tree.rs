@@ -0,0 +1,144 @@
+//! `git read-tree`.
+
+use std::path::PathBuf;
+
+use crate::core::index::IndexEntry;
+use crate::core::tree;
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadTreeOptions {
+    /// Bring the working tree in line with the new index, as `-u` does,
+    /// and record the files' stat information. Local edits to files the
+    /// index changes are overwritten.
+    pub update: bool,
+    /// Keep the index and read the tree in under this directory instead,
+    /// as `--prefix` does, refusing if anything is there already.
+    pub prefix: Option<String>,
+}
+
+/// Replace the index with the entries of the tree `tree_ish` names, each
+/// with no stat information, so the next look at the working tree
+/// re-hashes the files.
+pub fn read_tree(repo: &Repository, tree_ish: &str) -> GitResult<()> {
+    read_tree_with(repo, tree_ish, &ReadTreeOptions::default())
+}
+
+/// [`read_tree`] with every option.
+pub fn read_tree_with(
+    repo: &Repository,
+    tree_ish: &str,
+    options: &ReadTreeOptions,
+) -> GitResult<()> {
+    let odb = repo.odb();
+    let tree = repo.resolve_rev(&format!("{}^{{tree}}", tree_ish))?;
+    let mut entries = tree::flatten(odb, &tree)?;
+    let mut index = repo.read_index()?;
+    let before = tree::from_index(&index);
+
+    match options.prefix.as_deref().map(|p| p.trim_end_matches('/')) {
+        Some(prefix) if !prefix.is_empty() => {
+            let under = format!("{}/", prefix);
+            if let Some(existing) = index
+                .entries()
+                .iter()
+                .find(|e| e.path == prefix || e.path.starts_with(&under))
+            {
+                return Err(GitError::WouldOverwrite(PathBuf::from(&existing.path)));
+            }
+            entries = entries
+                .into_iter()
+                .map(|(path, entry)| (format!("{}{}", under, path), entry))
+                .collect();
+        }
+        _ => index.clear(),
+    }
+    for (path, entry) in &entries {
+        index.add(IndexEntry::new(path, entry.oid, entry.mode));
+    }
+
+    if options.update {
+        let work_dir = repo.require_work_dir()?;
+        let after = tree::from_index(&index);
+        worktree::update(odb, work_dir, &before, &after)?;
+        index = worktree::index_from_tree(work_dir, &after)?;
+    }
+    repo.write_index(&index)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::core::object::{ObjectType, MODE_FILE};
+    use crate::core::oid::Oid;
+    use crate::test_utils::{git, init_repo, read_file, set_ref, stage_file, staged, write_commit};
+
+    #[test]
+    fn replaces_the_index_with_a_tree() {
+        let (_dir, repo) = init_repo();
+        let files = [("a.txt", "a\n"), ("dir/b.txt", "b\n"), ("dir/sub/c", "c\n")];
+        let commit = write_commit(&repo, &[], &files, "base");
+        set_ref(&repo, "refs/heads/master", &commit);
+        stage_file(&repo, "stale", "gone after reading\n");
+
+        read_tree(&repo, "master").unwrap();
+        let odb = repo.odb();
+        let expected: Vec<(String, Oid, u32)> = files
+            .iter()
+            .map(|(path, content)| {
+                let blob = odb.write_raw(ObjectType::Blob, content.as_bytes());
+                (path.to_string(), blob.unwrap(), MODE_FILE)
+            })
+            .collect();
+        assert_eq!(staged(&repo), expected);
+        // Nothing was read from the working tree, so there's no stat data.
+        assert!(repo
+            .read_index()
+            .unwrap()
+            .entries()
+            .iter()
+            .all(|e| e.mtime == 0));
+        if let Some(theirs) = git(&repo, &["ls-files", "--stage"]) {
+            let ours: String = expected
+                .iter()
+                .map(|(path, blob, _)| format!("100644 {} 0\t{}\n", blob, path))
+                .collect();
+            assert_eq!(ours, theirs);
+        }
+    }
+
+    #[test]
+    fn updates_the_working_tree_and_reads_under_a_prefix() {
+        let (_dir, repo) = init_repo();
+        let commit = write_commit(&repo, &[], &[("a.txt", "a\n")], "base");
+        set_ref(&repo, "refs/heads/master", &commit);
+        let update = ReadTreeOptions {
+            update: true,
+            prefix: None,
+        };
+        read_tree_with(&repo, "master", &update).unwrap();
+        assert_eq!(read_file(&repo, "a.txt"), "a\n");
+        assert!(repo
+            .read_index()
+            .unwrap()
+            .entries()
+            .iter()
+            .all(|e| e.mtime != 0));
+
+        let prefixed = ReadTreeOptions {
+            update: false,
+            prefix: Some("vendor/".to_string()),
+        };
+        read_tree_with(&repo, "master", &prefixed).unwrap();
+        let paths: Vec<String> = staged(&repo).into_iter().map(|(path, _, _)| path).collect();
+        assert_eq!(paths, ["a.txt", "vendor/a.txt"]);
+        assert!(matches!(
+            read_tree_with(&repo, "master", &prefixed),
+            Err(GitError::WouldOverwrite(path)) if path == Path::new("vendor/a.txt")
+        ));
+    }
+}
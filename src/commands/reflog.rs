@@ -0,0 +1,82 @@
+use crate::core::reflog;
+use crate::core::refs;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// `refname`'s log as `git reflog show` prints it, newest first: the short
+/// id, the `refname@{n}` selector for it, and the message.
+pub fn show(repo: &Repository, refname: &str) -> GitResult<Vec<String>> {
+    let full =
+        refs::expand(repo, refname)?.ok_or_else(|| GitError::RefNotFound(refname.to_string()))?;
+    let entries = reflog::read(repo, &full)?;
+    Ok(entries
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(n, entry)| {
+            format!(
+                "{} {}@{{{}}}: {}",
+                entry.new.abbrev(7),
+                refname,
+                n,
+                entry.message
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::checkout::checkout_detached;
+    use crate::commands::commit::{commit, CommitOptions};
+    use crate::core::oid::Oid;
+    use crate::test_utils::{init_repo, stage_file};
+
+    #[test]
+    fn shows_and_resolves_log_entries() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a", "one");
+        let first = commit(&repo, Some("first"), &CommitOptions::default()).unwrap();
+        stage_file(&repo, "a", "two");
+        let second = commit(
+            &repo,
+            Some("second\n\nWith a body."),
+            &CommitOptions::default(),
+        )
+        .unwrap();
+        let short = |id: &Oid| id.abbrev(7);
+
+        assert_eq!(
+            show(&repo, "HEAD").unwrap(),
+            vec![
+                format!("{} HEAD@{{0}}: commit: second", short(&second)),
+                format!("{} HEAD@{{1}}: commit (initial): first", short(&first)),
+            ]
+        );
+        assert_eq!(repo.resolve_rev("HEAD@{0}").unwrap(), second);
+        assert_eq!(repo.resolve_rev("HEAD@{1}").unwrap(), first);
+        assert_eq!(repo.resolve_rev("master@{1}").unwrap(), first);
+        assert_eq!(repo.resolve_rev("@{1}").unwrap(), first);
+        assert!(matches!(
+            repo.resolve_rev("master@{2}"),
+            Err(GitError::ReflogTooShort { entries: 2, .. })
+        ));
+        assert!(matches!(
+            repo.resolve_rev("@{-1}"),
+            Err(GitError::UnknownRevision(_))
+        ));
+
+        checkout_detached(&repo, "HEAD@{1}").unwrap();
+        assert_eq!(repo.resolve_rev("@{-1}").unwrap(), second);
+        assert_eq!(
+            show(&repo, "HEAD").unwrap()[0],
+            format!(
+                "{} HEAD@{{0}}: checkout: moving from master to HEAD@{{1}}",
+                short(&first)
+            )
+        );
+        // The branch itself didn't move.
+        assert_eq!(show(&repo, "master").unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,177 @@
+//! `git remote`: the `[remote "<name>"]` sections of the config, naming
+//! the repositories that are fetched from and where their refs go.
+
+use crate::commands::config::{config_remove_section, config_set, config_unset};
+use crate::core::refs::{self, Transaction};
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// A configured remote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub name: String,
+    pub url: Option<String>,
+    /// Its `fetch` refspecs, in the order they're configured.
+    pub fetch: Vec<String>,
+}
+
+/// The fetch refspec a remote named `name` is given when it's added:
+/// every branch, to `refs/remotes/<name>/`, forced.
+pub fn default_fetch_refspec(name: &str) -> String {
+    format!("+refs/heads/*:refs/remotes/{}/*", name)
+}
+
+/// Add a remote called `name` at `url`, fetched with the default refspec,
+/// as `git remote add` does.
+pub fn add_remote(repo: &Repository, name: &str, url: &str) -> GitResult<Remote> {
+    refs::validate_name(name, true)?;
+    if find_remote(repo, name)?.is_some() {
+        return Err(GitError::RemoteExists(name.to_string()));
+    }
+    let fetch = default_fetch_refspec(name);
+    config_set(repo, &format!("remote.{}.url", name), url)?;
+    config_set(repo, &format!("remote.{}.fetch", name), &fetch)?;
+    Ok(Remote {
+        name: name.to_string(),
+        url: Some(url.to_string()),
+        fetch: vec![fetch],
+    })
+}
+
+/// Remove the remote `name`: its config section, its remote-tracking
+/// refs, and the upstream of any branch that tracked it.
+pub fn remove_remote(repo: &Repository, name: &str) -> GitResult<()> {
+    if !config_remove_section(repo, &format!("remote.{}", name))? {
+        return Err(GitError::RemoteNotFound(name.to_string()));
+    }
+    let config = repo.config()?;
+    let tracking: Vec<String> = config
+        .entries()
+        .iter()
+        .filter(|e| e.section == "branch" && e.key == "remote" && e.value == name)
+        .filter_map(|e| e.subsection.clone())
+        .collect();
+    for branch in tracking {
+        config_unset(repo, &format!("branch.{}.remote", branch), true)?;
+        config_unset(repo, &format!("branch.{}.merge", branch), true)?;
+    }
+
+    let prefix = format!("refs/remotes/{}/", name);
+    let mut transaction = Transaction::new(repo);
+    for reference in refs::iter_prefixed(repo, &prefix)? {
+        transaction.delete(&reference?.name, None);
+    }
+    transaction.commit(&format!("remote: remove {}", name))
+}
+
+/// Every configured remote, in the order they first appear in the config.
+pub fn list_remotes(repo: &Repository) -> GitResult<Vec<Remote>> {
+    let config = repo.config()?;
+    let mut names: Vec<&str> = Vec::new();
+    for entry in config.entries().iter().filter(|e| e.section == "remote") {
+        if let Some(name) = entry.subsection.as_deref() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    Ok(names
+        .into_iter()
+        .map(|name| Remote {
+            name: name.to_string(),
+            url: config.get("remote", Some(name), "url").map(str::to_string),
+            fetch: config
+                .get_all("remote", Some(name), "fetch")
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        })
+        .collect())
+}
+
+/// The remote called `name`, if there is one.
+pub fn find_remote(repo: &Repository, name: &str) -> GitResult<Option<Remote>> {
+    Ok(list_remotes(repo)?.into_iter().find(|r| r.name == name))
+}
+
+/// Point the remote `name` at `url`, as `git remote set-url` does.
+pub fn set_remote_url(repo: &Repository, name: &str, url: &str) -> GitResult<()> {
+    if find_remote(repo, name)?.is_none() {
+        return Err(GitError::RemoteNotFound(name.to_string()));
+    }
+    config_set(repo, &format!("remote.{}.url", name), url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::branch;
+    use crate::commands::config::config_get;
+    use crate::test_utils::{checkout, git, init_repo, set_ref, write_commit};
+
+    #[test]
+    fn adds_and_removes_remotes_with_their_refs() {
+        let (_dir, repo) = init_repo();
+        let id = write_commit(&repo, &[], &[("a", "a\n")], "base");
+        checkout(&repo, "main", &id);
+
+        let origin = add_remote(&repo, "origin", "/srv/origin.git").unwrap();
+        assert_eq!(origin.fetch, ["+refs/heads/*:refs/remotes/origin/*"]);
+        add_remote(&repo, "upstream", "/srv/upstream.git").unwrap();
+        assert!(matches!(
+            add_remote(&repo, "origin", "/elsewhere"),
+            Err(GitError::RemoteExists(_))
+        ));
+        assert!(matches!(
+            add_remote(&repo, "bad..name", "/elsewhere"),
+            Err(GitError::InvalidRefName { .. })
+        ));
+        assert_eq!(
+            config_get(&repo, "remote.origin.url").unwrap().as_deref(),
+            Some("/srv/origin.git")
+        );
+        if let Some(out) = git(&repo, &["config", "--get", "remote.upstream.fetch"]) {
+            assert_eq!(out, "+refs/heads/*:refs/remotes/upstream/*\n");
+        }
+        set_remote_url(&repo, "origin", "/srv/moved.git").unwrap();
+        let names: Vec<(String, Option<String>)> = list_remotes(&repo)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.name, r.url))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("origin".to_string(), Some("/srv/moved.git".to_string())),
+                (
+                    "upstream".to_string(),
+                    Some("/srv/upstream.git".to_string())
+                ),
+            ]
+        );
+        assert!(matches!(
+            set_remote_url(&repo, "missing", "/x"),
+            Err(GitError::RemoteNotFound(_))
+        ));
+
+        set_ref(&repo, "refs/remotes/origin/main", &id);
+        set_ref(&repo, "refs/remotes/origin/topic", &id);
+        set_ref(&repo, "refs/remotes/upstream/main", &id);
+        branch::set_upstream(&repo, "main", "origin/main").unwrap();
+        remove_remote(&repo, "origin").unwrap();
+        assert_eq!(find_remote(&repo, "origin").unwrap(), None);
+        assert!(!std::fs::read_to_string(repo.config_path())
+            .unwrap()
+            .contains("origin"));
+        let left: Vec<String> = refs::iter_prefixed(&repo, "refs/remotes/")
+            .unwrap()
+            .map(|r| r.unwrap().name)
+            .collect();
+        assert_eq!(left, ["refs/remotes/upstream/main"]);
+        assert_eq!(branch::upstream_of(&repo, "main").unwrap(), None);
+        assert!(matches!(
+            remove_remote(&repo, "origin"),
+            Err(GitError::RemoteNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,296 @@
+//! `git repack`: gather loose objects into a pack.
+//!
+//! Every loose object reachable from the refs, HEAD, the reflogs or the
+//! index goes into one new pack of whole objects, written to
+//! `objects/pack` with its index and named after its checksum, as git
+//! names packs. Objects already packed, and unreachable loose objects,
+//! are left where they are.
+//!
+//! With a delta window, as `git repack -f --window=<n>` has, each object
+//! is compared with the few before it of the same type, and stored as a
+//! `REF_DELTA` against whichever gives the smallest delta, if any gives
+//! one less than half its size.
+
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use crate::commands::prune::reachable_objects;
+use crate::core::object::ObjectType;
+use crate::core::oid::Oid;
+use crate::core::pack::{create_delta, PackWriter};
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// `pack.depth`'s default.
+pub const DEFAULT_DEPTH: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepackOptions {
+    /// Delete the loose copies of the objects once they're packed, as
+    /// `git repack -d` does; the packed copies are read in their place.
+    pub remove_loose: bool,
+    /// How many of the objects before each one to try as its delta base;
+    /// 0 packs whole objects only.
+    pub window: usize,
+    /// The longest chain of deltas an object may take to read.
+    pub max_depth: usize,
+}
+
+impl Default for RepackOptions {
+    fn default() -> RepackOptions {
+        RepackOptions {
+            remove_loose: false,
+            window: 0,
+            max_depth: DEFAULT_DEPTH,
+        }
+    }
+}
+
+/// Pack the reachable loose objects, keeping the loose copies, and return
+/// the path of the new pack.
+pub fn repack(repo: &Repository) -> GitResult<PathBuf> {
+    repack_with(repo, &RepackOptions::default())
+}
+
+/// [`repack`], storing objects as deltas against the best of the `window`
+/// objects before them where that's smaller.
+pub fn repack_delta(repo: &Repository, window: usize) -> GitResult<PathBuf> {
+    let options = RepackOptions {
+        window,
+        ..RepackOptions::default()
+    };
+    repack_with(repo, &options)
+}
+
+/// [`repack`] with `options`.
+pub fn repack_with(repo: &Repository, options: &RepackOptions) -> GitResult<PathBuf> {
+    let odb = repo.odb();
+    let reachable = reachable_objects(repo)?;
+    let mut objects = Vec::new();
+    for id in odb.loose_objects()? {
+        if reachable.contains(&id) {
+            let (kind, data) = odb.read_raw(&id)?;
+            objects.push((kind, id, data));
+        }
+    }
+    // Commits first, then tags, trees and blobs, as git orders a pack, and
+    // the largest of each first, as deltas that remove are the smaller.
+    objects.sort_by_key(|(kind, id, data)| {
+        let rank = match kind {
+            ObjectType::Commit => 0,
+            ObjectType::Tag => 1,
+            ObjectType::Tree => 2,
+            ObjectType::Blob => 3,
+        };
+        (rank, Reverse(data.len()), *id)
+    });
+
+    let mut writer = PackWriter::new();
+    // The objects last written, with the deltas it takes to read each.
+    let mut window: VecDeque<(ObjectType, Oid, &[u8], usize)> = VecDeque::new();
+    for (kind, id, data) in &objects {
+        // (base, delta, depth)
+        let mut best: Option<(Oid, Vec<u8>, usize)> = None;
+        for (base_kind, base_id, base, depth) in &window {
+            if base_kind != kind || *depth >= options.max_depth {
+                continue;
+            }
+            let delta = create_delta(base, data);
+            // The base's id is stored with the delta.
+            let size = delta.len() + 20;
+            if size
+                < best
+                    .as_ref()
+                    .map_or(data.len() / 2, |(_, best, _)| best.len() + 20)
+            {
+                best = Some((*base_id, delta, depth + 1));
+            }
+        }
+        let depth = match best {
+            Some((base, delta, depth)) => {
+                writer.add_delta(*kind, data, &base, &delta)?;
+                depth
+            }
+            None => {
+                writer.add_object(*kind, data)?;
+                0
+            }
+        };
+        if options.window > 0 {
+            if window.len() == options.window {
+                window.pop_front();
+            }
+            window.push_back((*kind, *id, data, depth));
+        }
+    }
+    let (pack, index, checksum) = writer.finish();
+    let pack_path = odb.install_pack(&pack, &index, &checksum)?;
+
+    if options.remove_loose {
+        for (_, id, _) in &objects {
+            odb.remove_loose(id)?;
+        }
+    }
+    Ok(pack_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::core::object::hash_object;
+
+    use crate::core::pack::Pack;
+    use crate::core::tree;
+    use crate::test_utils::{git, init_repo, set_ref, write_commit};
+    use std::collections::HashMap;
+
+    #[test]
+    fn packs_reachable_loose_objects() {
+        let (_dir, repo) = init_repo();
+        let big = "a line of text\n".repeat(2000);
+        let base = write_commit(&repo, &[], &[("a", "a\n"), ("dir/big", &big)], "base");
+        let tip = write_commit(&repo, &[base], &[("a", "b\n"), ("dir/big", &big)], "tip");
+        set_ref(&repo, "refs/heads/master", &tip);
+        let odb = repo.odb();
+        let dangling = odb.write_raw(ObjectType::Blob, b"dangling").unwrap();
+        let loose = odb.loose_objects().unwrap();
+
+        let pack_path = repack(&repo).unwrap();
+        assert_eq!(pack_path.extension().unwrap(), "pack");
+        let pack = Pack::open(&pack_path.with_extension("idx")).unwrap();
+        // Two commits, two root trees, one subtree and three blobs.
+        assert_eq!(pack.index.len(), 8);
+        assert_eq!(pack.index.position(&dangling), None);
+        let mut cache = HashMap::new();
+        for (position, id) in pack.index.ids().iter().enumerate() {
+            let object = pack
+                .read_at(pack.index.offset(position), &mut cache)
+                .unwrap();
+            assert_eq!(hash_object(object.kind, &object.data), *id);
+            assert_eq!((object.kind, object.data), odb.read_raw(id).unwrap());
+        }
+        assert_eq!(odb.loose_objects().unwrap(), loose);
+        let idx = pack_path.with_extension("idx");
+        if let Some(out) = git(&repo, &["verify-pack", "-v", idx.to_str().unwrap()]) {
+            assert!(out.ends_with(": ok\n"), "{}", out);
+        }
+    }
+
+    #[test]
+    fn reads_objects_back_once_their_loose_copies_are_gone() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a\n"), ("b/c", "c\n")], "base");
+        set_ref(&repo, "refs/heads/master", &base);
+        let odb = repo.odb();
+        let dangling = odb.write_raw(ObjectType::Blob, b"dangling").unwrap();
+
+        let options = RepackOptions {
+            remove_loose: true,
+            ..RepackOptions::default()
+        };
+        repack_with(&repo, &options).unwrap();
+        assert_eq!(odb.loose_objects().unwrap(), [dangling]);
+        let fresh = Repository::open(repo.git_dir()).unwrap();
+        let tree = fresh.odb().read_commit(&base).unwrap().tree;
+        let flat = tree::flatten(fresh.odb(), &tree).unwrap();
+        assert_eq!(fresh.odb().read_blob(&flat["b/c"].oid).unwrap(), b"c\n");
+        assert!(fresh.odb().contains(&base));
+        let hex = base.to_string();
+        assert_eq!(fresh.odb().find_prefix(&hex[..6]).unwrap(), [base]);
+        if let Some(out) = git(&repo, &["cat-file", "-p", "HEAD:b/c"]) {
+            assert_eq!(out, "c\n");
+        }
+    }
+
+    /// Every object in the pack at `path`, read back and checked against
+    /// its id, with the deltas it took to read.
+    fn read_back(path: &Path) -> Vec<(Oid, ObjectType, Vec<u8>, usize)> {
+        let pack = Pack::open(&path.with_extension("idx")).unwrap();
+        let mut cache = HashMap::new();
+        let mut objects = Vec::new();
+        for (position, id) in pack.index.ids().iter().enumerate() {
+            let object = pack
+                .read_at(pack.index.offset(position), &mut cache)
+                .unwrap();
+            assert_eq!(hash_object(object.kind, &object.data), *id);
+            objects.push((*id, object.kind, object.data, object.depth));
+        }
+        objects
+    }
+
+    #[test]
+    fn packs_similar_objects_as_deltas() {
+        let (_dir, repo) = init_repo();
+        let big: String = (0..5000).map(|n| format!("line {}\n", n)).collect();
+        let edited = big.replace("line 2500\n", "line two thousand five hundred\n");
+        let base = write_commit(&repo, &[], &[("big", &big)], "base");
+        let tip = write_commit(&repo, &[base], &[("big", &edited)], "tip");
+        set_ref(&repo, "refs/heads/master", &tip);
+
+        let whole = repack(&repo).unwrap();
+        let whole_objects = read_back(&whole);
+        let whole_size = fs::metadata(&whole).unwrap().len();
+        fs::remove_file(whole.with_extension("idx")).unwrap();
+        fs::remove_file(&whole).unwrap();
+        let delta = repack_delta(&repo, 10).unwrap();
+        let delta_objects = read_back(&delta);
+        let delta_size = fs::metadata(&delta).unwrap().len();
+
+        assert!(delta_size < whole_size, "{} >= {}", delta_size, whole_size);
+        assert_eq!(delta_objects.len(), whole_objects.len());
+        for (whole, delta) in whole_objects.iter().zip(&delta_objects) {
+            assert_eq!(whole.0, delta.0);
+            assert_eq!((whole.1, &whole.2), (delta.1, &delta.2));
+        }
+        // The smaller blob is the one stored as a delta.
+        let blob = big_blob(&repo, &tip);
+        let depth = |objects: &[(Oid, ObjectType, Vec<u8>, usize)], id| {
+            objects.iter().find(|o| o.0 == id).unwrap().3
+        };
+        assert_eq!(depth(&whole_objects, blob), 0);
+        assert_eq!(depth(&delta_objects, big_blob(&repo, &base)), 1);
+        assert_eq!(depth(&delta_objects, blob), 0);
+        let idx = delta.with_extension("idx");
+        if let Some(out) = git(&repo, &["verify-pack", "-v", idx.to_str().unwrap()]) {
+            assert!(out.contains("chain length = 1: "), "{}", out);
+            assert!(out.ends_with(": ok\n"), "{}", out);
+        }
+    }
+
+    fn big_blob(repo: &Repository, commit: &Oid) -> Oid {
+        let tree = repo.odb().read_commit(commit).unwrap().tree;
+        tree::flatten(repo.odb(), &tree).unwrap()["big"].oid
+    }
+
+    #[test]
+    fn keeps_delta_chains_to_the_max_depth() {
+        let (_dir, repo) = init_repo();
+        let mut content: String = (0..2000).map(|n| format!("line {}\n", n)).collect();
+        let mut parents = Vec::new();
+        for n in 0..6 {
+            content.push_str(&format!("more {}\n", n));
+            let id = write_commit(&repo, &parents, &[("big", &content)], "step");
+            parents = vec![id];
+        }
+        set_ref(&repo, "refs/heads/master", &parents[0]);
+
+        // Each blob can only be a delta against the one before it.
+        let options = RepackOptions {
+            window: 1,
+            max_depth: 2,
+            ..RepackOptions::default()
+        };
+        let path = repack_with(&repo, &options).unwrap();
+        let mut depths: Vec<usize> = read_back(&path)
+            .into_iter()
+            .filter(|o| o.1 == ObjectType::Blob)
+            .map(|o| o.3)
+            .collect();
+        depths.sort_unstable();
+        assert_eq!(depths, [0, 0, 1, 1, 2, 2]);
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use crate::core::index::IndexEntry;
+use crate::core::object::ObjectType;
+use crate::core::oid::Oid;
+use crate::core::tree::{self, FlatEntry, FlatTree};
+use crate::core::worktree::{self, relative_path};
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// Where `restore` takes content from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum RestoreSource {
+    /// What `git restore` uses without `--source`: the index when
+    /// restoring the working tree, HEAD when restoring the index.
+    #[default]
+    Default,
+    /// The tree of the commit a revision names.
+    Revision(String),
+    /// A tree, or the tree of a commit, by id.
+    Tree(Oid),
+}
+
+/// Which side of a conflict to restore an unmerged path to, as `--ours`
+/// and `--theirs` pick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictSide {
+    /// Stage 2, HEAD's version.
+    Ours,
+    /// Stage 3, the version being merged in.
+    Theirs,
+}
+
+/// Options for [`restore_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestoreOptions {
+    /// Reset the index entries, like `--staged`.
+    pub staged: bool,
+    /// Overwrite the working tree files, like `--worktree`.
+    pub worktree: bool,
+    /// Leave paths the source doesn't have alone; without it, as with
+    /// `--no-overlay`, they're removed.
+    pub overlay: bool,
+    /// Restore unmerged paths in the working tree from this side of the
+    /// conflict rather than refusing to.
+    pub conflict_side: Option<ConflictSide>,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> RestoreOptions {
+        RestoreOptions {
+            staged: false,
+            worktree: true,
+            overlay: true,
+            conflict_side: None,
+        }
+    }
+}
+
+/// `git restore`: put each of `paths` (files, or directories standing for
+/// every tracked path under them) back the way `source` has it. With
+/// `staged` only the index is reset; otherwise only the working tree is
+/// overwritten, discarding local edits. Paths the source doesn't have are
+/// removed.
+pub fn restore(
+    repo: &Repository,
+    paths: &[PathBuf],
+    source: RestoreSource,
+    staged: bool,
+) -> GitResult<()> {
+    let options = RestoreOptions {
+        staged,
+        worktree: !staged,
+        overlay: false,
+        conflict_side: None,
+    };
+    restore_with(repo, paths, source, &options)
+}
+
+/// Overwrite the working tree files at `paths` with `source`'s content,
+/// like `git checkout <source> -- <paths>`, leaving any path the source
+/// doesn't have as it is.
+pub fn restore_worktree(
+    repo: &Repository,
+    paths: &[PathBuf],
+    source: RestoreSource,
+) -> GitResult<()> {
+    restore_with(repo, paths, source, &RestoreOptions::default())
+}
+
+/// [`restore`] with every option: the index, the working tree or both can
+/// be restored at once, from HEAD by default when the index is.
+pub fn restore_with(
+    repo: &Repository,
+    paths: &[PathBuf],
+    source: RestoreSource,
+    options: &RestoreOptions,
+) -> GitResult<()> {
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let mut index = repo.read_index()?;
+    let from_index = !options.staged && source == RestoreSource::Default;
+    let contents = match source {
+        _ if from_index => tree::from_index(&index),
+        RestoreSource::Default => match repo.head_commit()? {
+            Some(id) => tree::flatten(odb, &odb.read_commit(&id)?.tree)?,
+            None => FlatTree::new(),
+        },
+        RestoreSource::Revision(rev) => {
+            let commit = odb.peel_to_commit(&repo.resolve_rev(&rev)?)?;
+            tree::flatten(odb, &odb.read_commit(&commit)?.tree)?
+        }
+        RestoreSource::Tree(id) => {
+            let tree = match odb.peel(&id)? {
+                (tree, ObjectType::Tree) => tree,
+                (other, _) => odb.read_commit(&odb.peel_to_commit(&other)?)?.tree,
+            };
+            tree::flatten(odb, &tree)?
+        }
+    };
+
+    // The paths to restore: whatever matches in the source, plus tracked
+    // paths that match and will be removed because the source lacks them.
+    let mut selected = BTreeSet::new();
+    let mut unmerged = BTreeSet::new();
+    for path in paths {
+        let spec = relative_path(work_dir, path)?;
+        let dir_prefix = format!("{}/", spec);
+        let matches = |candidate: &str| {
+            spec.is_empty() || candidate == spec || candidate.starts_with(&dir_prefix)
+        };
+        let sourced: Vec<&String> = contents.keys().filter(|p| matches(p)).collect();
+        let tracked: Vec<&IndexEntry> = index
+            .entries()
+            .iter()
+            .filter(|e| matches(&e.path))
+            .collect();
+        if sourced.is_empty() && tracked.is_empty() {
+            return Err(GitError::NotTracked(path.clone()));
+        }
+        if from_index {
+            let conflicted: BTreeSet<String> = tracked
+                .iter()
+                .filter(|e| e.stage() != 0)
+                .map(|e| e.path.clone())
+                .collect();
+            if !conflicted.is_empty() && options.conflict_side.is_none() {
+                return Err(GitError::UnresolvedConflicts(
+                    conflicted.into_iter().map(PathBuf::from).collect(),
+                ));
+            }
+            unmerged.extend(conflicted);
+        }
+        selected.extend(sourced.into_iter().cloned());
+        selected.extend(tracked.into_iter().map(|e| e.path.clone()));
+    }
+
+    for path in &selected {
+        let conflicted = unmerged.contains(path);
+        let entry = match options.conflict_side {
+            Some(side) if conflicted => {
+                let stage = match side {
+                    ConflictSide::Ours => 2,
+                    ConflictSide::Theirs => 3,
+                };
+                let entry = index
+                    .get(path, stage)
+                    .ok_or_else(|| GitError::UnresolvedConflicts(vec![PathBuf::from(path)]))?;
+                Some(FlatEntry {
+                    mode: entry.mode,
+                    oid: entry.oid,
+                })
+            }
+            _ => contents.get(path).copied(),
+        };
+        if entry.is_none() && options.overlay {
+            continue;
+        }
+        if options.staged {
+            index.remove(path);
+            if let Some(entry) = entry {
+                index.add(IndexEntry::new(path, entry.oid, entry.mode));
+            }
+        }
+        if !options.worktree {
+            continue;
+        }
+        match entry {
+            Some(entry) => {
+                worktree::write_blob(odb, work_dir, path, entry.mode, &entry.oid)?;
+                // The file now matches its entry again, so let the entry's
+                // stat data say so; an unmerged path stays unmerged.
+                if options.staged || (from_index && !conflicted) {
+                    index.add(worktree::stat_entry(work_dir, path, entry.oid, entry.mode)?);
+                }
+            }
+            None => worktree::remove_file(work_dir, path)?,
+        }
+    }
+    repo.write_index(&index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::commands::merge::{merge, MergeOutcome};
+    use crate::test_utils::{
+        checkout, git, init_repo, read_file, stage_file, staged_oid, write_commit,
+    };
+
+    #[test]
+    fn restores_a_modified_file_to_its_staged_version() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a.txt", "staged");
+        stage_file(&repo, "dir/b.txt", "b");
+        let work_dir = repo.work_dir().unwrap().to_path_buf();
+        fs::write(work_dir.join("a.txt"), "edited").unwrap();
+        fs::remove_file(work_dir.join("dir/b.txt")).unwrap();
+
+        restore(
+            &repo,
+            &[PathBuf::from("a.txt"), PathBuf::from("dir")],
+            RestoreSource::Default,
+            false,
+        )
+        .unwrap();
+        assert_eq!(read_file(&repo, "a.txt"), "staged");
+        assert_eq!(read_file(&repo, "dir/b.txt"), "b");
+        let work_tree = work_dir.to_str().unwrap();
+        if let Some(output) = git(
+            &repo,
+            &["--work-tree", work_tree, "diff-files", "--name-only"],
+        ) {
+            assert_eq!(output, "");
+        }
+
+        assert!(matches!(
+            restore(
+                &repo,
+                &[PathBuf::from("nope")],
+                RestoreSource::Default,
+                false
+            ),
+            Err(GitError::NotTracked(_))
+        ));
+    }
+
+    #[test]
+    fn staged_reverts_the_index_to_head() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "base")], "base");
+        repo.set_head_commit(&base, "").unwrap();
+        stage_file(&repo, "a.txt", "base");
+        let committed = staged_oid(&repo, "a.txt");
+        stage_file(&repo, "a.txt", "changed");
+        stage_file(&repo, "new.txt", "new");
+
+        let paths = [PathBuf::from("a.txt"), PathBuf::from("new.txt")];
+        restore(&repo, &paths, RestoreSource::Default, true).unwrap();
+        assert_eq!(staged_oid(&repo, "a.txt"), committed);
+        assert_eq!(staged_oid(&repo, "new.txt"), None);
+        // The working tree keeps its edits.
+        assert_eq!(read_file(&repo, "a.txt"), "changed");
+        assert_eq!(read_file(&repo, "new.txt"), "new");
+
+        // Restoring the working tree from a revision deletes what it lacks.
+        stage_file(&repo, "new.txt", "new");
+        restore(
+            &repo,
+            &[PathBuf::from(".")],
+            RestoreSource::Revision("HEAD".to_string()),
+            false,
+        )
+        .unwrap();
+        assert_eq!(read_file(&repo, "a.txt"), "base");
+        assert!(!repo.work_dir().unwrap().join("new.txt").exists());
+    }
+
+    #[test]
+    fn restores_deleted_files_and_directories() {
+        let (_dir, repo) = init_repo();
+        let files = [("a", "a\n"), ("dir/b", "b\n"), ("dir/sub/c", "c\n")];
+        let base = write_commit(&repo, &[], &files, "base");
+        checkout(&repo, "master", &base);
+        let work_dir = repo.work_dir().unwrap();
+        fs::remove_file(work_dir.join("a")).unwrap();
+        fs::remove_dir_all(work_dir.join("dir")).unwrap();
+
+        restore_worktree(&repo, &[PathBuf::from("a")], RestoreSource::Default).unwrap();
+        assert_eq!(read_file(&repo, "a"), "a\n");
+        restore_worktree(&repo, &[PathBuf::from("dir")], RestoreSource::Tree(base)).unwrap();
+        assert_eq!(read_file(&repo, "dir/b"), "b\n");
+        assert_eq!(read_file(&repo, "dir/sub/c"), "c\n");
+        if let Some(out) = git(&repo, &["status", "--porcelain"]) {
+            assert_eq!(out, "");
+        }
+    }
+
+    #[test]
+    fn removes_what_the_source_lacks_only_without_overlay() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a\n"), ("dir/old", "old\n")], "base");
+        let next = write_commit(
+            &repo,
+            &[base],
+            &[("a", "next\n"), ("dir/old", "old\n"), ("dir/new", "new\n")],
+            "next",
+        );
+        checkout(&repo, "master", &next);
+        let new = repo.work_dir().unwrap().join("dir/new");
+
+        restore_worktree(&repo, &[PathBuf::from("dir")], RestoreSource::Tree(base)).unwrap();
+        assert!(new.exists());
+        let no_overlay = RestoreOptions {
+            overlay: false,
+            ..RestoreOptions::default()
+        };
+        let dir = [PathBuf::from("dir")];
+        restore_with(&repo, &dir, RestoreSource::Tree(base), &no_overlay).unwrap();
+        assert!(!new.exists());
+        // The index still has it; only the working tree was restored.
+        assert!(staged_oid(&repo, "dir/new").is_some());
+
+        // The index and working tree together, in one go.
+        let both = RestoreOptions {
+            staged: true,
+            ..no_overlay
+        };
+        let all = [PathBuf::from(".")];
+        restore_with(&repo, &all, RestoreSource::Tree(base), &both).unwrap();
+        assert_eq!(read_file(&repo, "a"), "a\n");
+        assert_eq!(staged_oid(&repo, "dir/new"), None);
+        if let Some(out) = git(&repo, &["status", "--porcelain"]) {
+            assert_eq!(out, "M  a\nD  dir/new\n");
+        }
+    }
+
+    #[test]
+    fn restores_unmerged_paths_only_from_a_chosen_side() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "base\n")], "base");
+        let theirs = write_commit(&repo, &[base], &[("a", "theirs\n")], "theirs");
+        let ours = write_commit(&repo, &[base], &[("a", "ours\n")], "ours");
+        checkout(&repo, "topic", &theirs);
+        checkout(&repo, "master", &ours);
+        assert!(matches!(
+            merge(&repo, "topic").unwrap(),
+            MergeOutcome::Conflicts(_)
+        ));
+
+        let a = [PathBuf::from("a")];
+        assert!(matches!(
+            restore_worktree(&repo, &a, RestoreSource::Default),
+            Err(GitError::UnresolvedConflicts(_))
+        ));
+        let theirs_side = RestoreOptions {
+            conflict_side: Some(ConflictSide::Theirs),
+            ..RestoreOptions::default()
+        };
+        restore_with(&repo, &a, RestoreSource::Default, &theirs_side).unwrap();
+        assert_eq!(read_file(&repo, "a"), "theirs\n");
+        // Still for the user to resolve.
+        let index = repo.read_index().unwrap();
+        assert!(index.get("a", 0).is_none() && index.get("a", 3).is_some());
+    }
+}
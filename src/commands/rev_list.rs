@@ -0,0 +1,131 @@
+//! `git rev-list`.
+
+use crate::core::oid::Oid;
+use crate::core::revparse;
+use crate::core::revwalk::RevWalk;
+use crate::core::shallow;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// The commits reachable from `includes` and from none of `excludes`,
+/// newest first, at most `limit` of them. `includes` are revision
+/// arguments as [`revparse::parse_range`] takes them, so `A..B` and `^A`
+/// work there too; `excludes` are plain revisions to leave out along with
+/// their history.
+pub fn rev_list(
+    repo: &Repository,
+    includes: &[String],
+    excludes: &[String],
+    limit: Option<usize>,
+) -> GitResult<Vec<Oid>> {
+    let mut specs = revparse::parse_range(repo, includes)?;
+    for rev in excludes {
+        specs.exclude.push(repo.resolve_rev(rev)?);
+    }
+    // Every exclusion is marked before the first commit comes out, so
+    // nothing reachable from one is ever listed.
+    let mut walk = RevWalk::new(repo.odb());
+    walk.push_specs(&specs)?
+        .shallow(shallow::shallow_commits(repo)?);
+    let mut ids = Vec::new();
+    for commit in walk.take(limit.unwrap_or(usize::MAX)) {
+        ids.push(commit?.0);
+    }
+    Ok(ids)
+}
+
+/// How many commits [`rev_list`] would list, as `--count` prints it.
+pub fn rev_list_count(
+    repo: &Repository,
+    includes: &[String],
+    excludes: &[String],
+    limit: Option<usize>,
+) -> GitResult<usize> {
+    rev_list(repo, includes, excludes, limit).map(|ids| ids.len())
+}
+
+/// How many commits `local` has that `upstream` doesn't, and the other
+/// way round: the "ahead 2, behind 1" of a branch against the one it
+/// tracks. Histories with nothing in common count everything each side
+/// reaches.
+pub fn ahead_behind(repo: &Repository, local: &str, upstream: &str) -> GitResult<(usize, usize)> {
+    let (local, upstream) = ([local.to_string()], [upstream.to_string()]);
+    let ahead = rev_list_count(repo, &local, &upstream, None)?;
+    let behind = rev_list_count(repo, &upstream, &local, None)?;
+    Ok((ahead, behind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{git, init_repo, set_ref, write_commit_at};
+
+    fn args(revs: &[&str]) -> Vec<String> {
+        revs.iter().map(|rev| rev.to_string()).collect()
+    }
+
+    #[test]
+    fn lists_what_a_branch_has_that_another_lacks() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit_at(&repo, &[], &[("f", "base")], "base", 1000);
+        let main1 = write_commit_at(&repo, &[base], &[("f", "m1")], "m1", 2000);
+        let feature1 = write_commit_at(&repo, &[base], &[("f", "f1")], "f1", 3000);
+        let main2 = write_commit_at(&repo, &[main1], &[("f", "m2")], "m2", 4000);
+        let feature2 = write_commit_at(&repo, &[feature1, main1], &[("f", "f2")], "f2", 5000);
+        set_ref(&repo, "refs/heads/main", &main2);
+        set_ref(&repo, "refs/heads/feature", &feature2);
+
+        let ids = rev_list(&repo, &args(&["main..feature"]), &[], None).unwrap();
+        assert_eq!(ids, [feature2, feature1]);
+        let same = rev_list(&repo, &args(&["feature"]), &args(&["main"]), None).unwrap();
+        assert_eq!(same, ids);
+        if let Some(theirs) = git(&repo, &["rev-list", "main..feature"]) {
+            let ours: String = ids.iter().map(|id| id.to_string() + "\n").collect();
+            assert_eq!(ours, theirs);
+        }
+        let limited = rev_list(&repo, &args(&["feature"]), &[], Some(3)).unwrap();
+        assert_eq!(limited, [feature2, feature1, main1]);
+    }
+
+    #[test]
+    fn counts_ahead_and_behind() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit_at(&repo, &[], &[("f", "base")], "base", 1000);
+        let theirs = write_commit_at(&repo, &[base], &[("f", "theirs")], "theirs", 2000);
+        let ours1 = write_commit_at(&repo, &[base], &[("f", "ours1")], "ours1", 3000);
+        let ours2 = write_commit_at(&repo, &[ours1], &[("f", "ours2")], "ours2", 4000);
+        let other = write_commit_at(&repo, &[], &[("g", "other")], "other", 5000);
+        set_ref(&repo, "refs/heads/main", &ours2);
+        set_ref(&repo, "refs/remotes/origin/main", &theirs);
+        set_ref(&repo, "refs/heads/unrelated", &other);
+
+        assert_eq!(ahead_behind(&repo, "main", "origin/main").unwrap(), (2, 1));
+        assert_eq!(ahead_behind(&repo, "main", "main").unwrap(), (0, 0));
+        assert_eq!(ahead_behind(&repo, "unrelated", "main").unwrap(), (1, 3));
+        let args = ["rev-list", "--left-right", "--count", "main...origin/main"];
+        if let Some(theirs) = git(&repo, &args) {
+            assert_eq!(theirs, "2\t1\n");
+        }
+    }
+
+    #[test]
+    fn counts_a_linear_history() {
+        let (_dir, repo) = init_repo();
+        let mut tip = write_commit_at(&repo, &[], &[("f", "0")], "0", 1000);
+        for n in 1..10 {
+            let content = n.to_string();
+            tip = write_commit_at(&repo, &[tip], &[("f", &content)], &content, 1000 + n);
+        }
+        set_ref(&repo, "refs/heads/master", &tip);
+        assert_eq!(rev_list_count(&repo, &[], &[], None).unwrap(), 10);
+        assert_eq!(
+            rev_list_count(&repo, &args(&["master"]), &args(&["master~4"]), None).unwrap(),
+            4
+        );
+        assert_eq!(rev_list_count(&repo, &[], &[], Some(6)).unwrap(), 6);
+        if let Some(theirs) = git(&repo, &["rev-list", "--count", "master~4..master"]) {
+            assert_eq!(theirs.trim_end(), "4");
+        }
+    }
+}
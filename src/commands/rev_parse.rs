@@ -0,0 +1,92 @@
+use crate::core::revparse;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// How many hex digits `--short` shows when no length is given.
+pub const DEFAULT_ABBREV: usize = 7;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RevParseOptions {
+    /// Like `--verify`: there must be exactly one revision, and it must
+    /// name an object that exists.
+    pub verify: bool,
+    /// Like `--short=<n>`: abbreviate ids to at least `n` digits, more if
+    /// that's needed to keep them unambiguous.
+    pub short: Option<usize>,
+}
+
+/// The object id each of `revs` names, one line per revision.
+pub fn rev_parse(
+    repo: &Repository,
+    revs: &[&str],
+    options: &RevParseOptions,
+) -> GitResult<Vec<String>> {
+    if options.verify && revs.len() != 1 {
+        return Err(GitError::BadRevision {
+            rev: revs.join(" "),
+            reason: "needed a single revision".to_string(),
+        });
+    }
+    let mut lines = Vec::new();
+    for rev in revs {
+        let id = revparse::parse(repo, rev)?;
+        if options.verify && !repo.odb().contains(&id) {
+            return Err(GitError::ObjectNotFound(id));
+        }
+        lines.push(match options.short {
+            Some(len) => repo.odb().abbreviate(&id, len)?,
+            None => id.to_string(),
+        });
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{git, init_repo, write_commit};
+
+    #[test]
+    fn verifies_and_abbreviates() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        let next = write_commit(&repo, &[base], &[("a", "b")], "next");
+        repo.set_head_commit(&next, "").unwrap();
+
+        let both = rev_parse(&repo, &["HEAD", "HEAD~"], &RevParseOptions::default()).unwrap();
+        assert_eq!(both, vec![next.to_string(), base.to_string()]);
+
+        let verify = RevParseOptions {
+            verify: true,
+            ..RevParseOptions::default()
+        };
+        assert!(matches!(
+            rev_parse(&repo, &["HEAD", "HEAD~"], &verify),
+            Err(GitError::BadRevision { .. })
+        ));
+        let missing = "1234567890123456789012345678901234567890";
+        assert!(rev_parse(&repo, &[missing], &RevParseOptions::default()).is_ok());
+        assert!(matches!(
+            rev_parse(&repo, &[missing], &verify),
+            Err(GitError::ObjectNotFound(_))
+        ));
+
+        let short = RevParseOptions {
+            verify: true,
+            short: Some(DEFAULT_ABBREV),
+        };
+        let abbreviated = rev_parse(&repo, &["HEAD^{tree}"], &short).unwrap();
+        assert_eq!(abbreviated[0].len(), DEFAULT_ABBREV);
+        if let Some(output) = git(&repo, &["rev-parse", "--verify", "--short", "HEAD^{tree}"]) {
+            assert_eq!(output.trim(), abbreviated[0]);
+        }
+        // Short lengths grow until the prefix is unique.
+        let tiny = RevParseOptions {
+            short: Some(2),
+            ..RevParseOptions::default()
+        };
+        let prefix = &rev_parse(&repo, &["HEAD"], &tiny).unwrap()[0];
+        assert!(next.to_string().starts_with(prefix.as_str()));
+        assert_eq!(repo.odb().find_prefix(prefix).unwrap(), vec![next]);
+    }
+}
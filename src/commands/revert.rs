@@ -0,0 +1,239 @@
+//! `git revert`: undo the change one commit made, with a new commit on top
+//! of HEAD.
+//!
+//! This is a [`cherry_pick`](super::cherry_pick) the other way round, the
+//! reverted commit's tree as the base and its parent's as theirs, and
+//! stops on conflicts the same way, with `REVERT_HEAD` in place of
+//! `CHERRY_PICK_HEAD`.
+
+use crate::commands::cherry_pick::{self, Action, CherryPickOutcome, Pick};
+use crate::core::oid::Oid;
+use crate::core::signature;
+use crate::core::tree::{self, FlatTree};
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RevertOptions {
+    /// Leave the commit's change undone in the index and working tree for
+    /// the next commit, rather than committing the revert, as
+    /// `--no-commit` does; see [`CherryPickOptions::no_commit`].
+    ///
+    /// [`CherryPickOptions::no_commit`]: cherry_pick::CherryPickOptions::no_commit
+    pub no_commit: bool,
+    /// For a merge commit, which parent, counting from 1, to go back to,
+    /// as `--mainline` does. Reverting a merge needs one.
+    pub mainline: Option<usize>,
+}
+
+/// What [`revert`] did; the same as what a cherry-pick can do.
+pub type RevertOutcome = CherryPickOutcome;
+
+/// Undo the change the commit `rev` names made, committing that as the
+/// current identity with the message `Revert "<subject>"`.
+pub fn revert(repo: &Repository, rev: &str, options: &RevertOptions) -> GitResult<RevertOutcome> {
+    let odb = repo.odb();
+    let id = &odb.peel_to_commit(&repo.resolve_rev(rev)?)?;
+    let reverted = odb.read_commit(id)?;
+    let parent = cherry_pick::mainline_parent(id, &reverted, options.mainline)?;
+    let theirs = match parent {
+        Some(parent) => tree::flatten(odb, &odb.read_commit(&parent)?.tree)?,
+        None => FlatTree::new(),
+    };
+    let mut message = format!(
+        "Revert \"{}\"\n\nThis reverts commit {}",
+        reverted.summary(),
+        id
+    );
+    match parent {
+        Some(parent) if reverted.parents.len() > 1 => {
+            message.push_str(&format!(", reversing\nchanges made to {}.\n", parent))
+        }
+        _ => message.push_str(".\n"),
+    }
+    let label = cherry_pick::label(odb, id, &reverted)?;
+    cherry_pick::pick(
+        repo,
+        Pick {
+            action: Action::Revert,
+            id: *id,
+            base: tree::flatten(odb, &reverted.tree)?,
+            theirs,
+            their_label: format!("parent of {}", label),
+            message,
+            author: signature::default_signatures(repo)?.0,
+            no_commit: options.no_commit,
+        },
+    )
+}
+
+/// Commit the resolved index, once every conflict of a revert is staged,
+/// with the message in `MERGE_MSG` cleaned of its comments.
+pub fn revert_continue(repo: &Repository) -> GitResult<Oid> {
+    cherry_pick::continue_pick(repo, Action::Revert)
+}
+
+/// Give up on a conflicted revert: put the index and working tree back as
+/// HEAD has them and forget the saved state.
+pub fn revert_abort(repo: &Repository) -> GitResult<()> {
+    cherry_pick::abort_pick(repo, Action::Revert)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::GitError;
+    use crate::repository::RepositoryState;
+    use crate::test_utils::{checkout, init_repo, read_file, stage_file, write_commit};
+    use std::path::PathBuf;
+
+    #[test]
+    fn reverts_and_reapplies_a_commit() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n"), ("b.txt", "b\n")], "base");
+        let change = write_commit(
+            &repo,
+            &[base],
+            &[("a.txt", "changed\n"), ("c.txt", "c\n")],
+            "change a",
+        );
+        let later = write_commit(
+            &repo,
+            &[change],
+            &[("a.txt", "changed\n"), ("c.txt", "c\n"), ("d.txt", "d\n")],
+            "add d",
+        );
+        checkout(&repo, "master", &later);
+
+        let reverted = match revert(&repo, &change.to_string(), &RevertOptions::default()).unwrap()
+        {
+            RevertOutcome::MadeCommit(id) => id,
+            other => panic!("unexpected outcome {:?}", other),
+        };
+        let odb = repo.odb();
+        let commit = odb.read_commit(&reverted).unwrap();
+        assert_eq!(commit.parents, vec![later]);
+        assert_eq!(commit.author.name, "A U Thor");
+        assert_eq!(
+            commit.message,
+            format!("Revert \"change a\"\n\nThis reverts commit {}.\n", change)
+        );
+        let flat = tree::flatten(odb, &commit.tree).unwrap();
+        let base_tree = tree::flatten(odb, &odb.read_commit(&base).unwrap().tree).unwrap();
+        assert_eq!(flat.get("a.txt"), base_tree.get("a.txt"));
+        assert_eq!(flat.get("b.txt"), base_tree.get("b.txt"));
+        assert!(!flat.contains_key("c.txt"));
+        assert!(flat.contains_key("d.txt"));
+        assert_eq!(read_file(&repo, "a.txt"), "a\n");
+
+        let again = match revert(&repo, &reverted.to_string(), &RevertOptions::default()).unwrap() {
+            RevertOutcome::MadeCommit(id) => id,
+            other => panic!("unexpected outcome {:?}", other),
+        };
+        let again = odb.read_commit(&again).unwrap();
+        assert_eq!(again.tree, odb.read_commit(&later).unwrap().tree);
+        assert!(again
+            .message
+            .starts_with("Revert \"Revert \"change a\"\"\n"));
+    }
+
+    #[test]
+    fn reverts_the_last_commit() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n"), ("b.txt", "b\n")], "base");
+        let last = write_commit(
+            &repo,
+            &[base],
+            &[("a.txt", "a\nmore\n"), ("c.txt", "c\n")],
+            "last",
+        );
+        checkout(&repo, "master", &last);
+
+        let id = match revert(&repo, "HEAD", &RevertOptions::default()).unwrap() {
+            RevertOutcome::MadeCommit(id) => id,
+            other => panic!("unexpected outcome {:?}", other),
+        };
+        let odb = repo.odb();
+        assert_eq!(
+            odb.read_commit(&id).unwrap().tree,
+            odb.read_commit(&base).unwrap().tree
+        );
+        assert_eq!(read_file(&repo, "b.txt"), "b\n");
+        assert!(!repo.work_dir().unwrap().join("c.txt").exists());
+        assert_eq!(
+            revert(&repo, &last.to_string(), &RevertOptions::default()).unwrap(),
+            RevertOutcome::Empty
+        );
+    }
+
+    #[test]
+    fn conflicts_and_merges() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a.txt", "a\n")], "base");
+        let change = write_commit(&repo, &[base], &[("a.txt", "changed\n")], "change a");
+        let later = write_commit(&repo, &[change], &[("a.txt", "later\n")], "again");
+        checkout(&repo, "master", &later);
+
+        let outcome = revert(&repo, &change.to_string(), &RevertOptions::default()).unwrap();
+        assert_eq!(
+            outcome,
+            RevertOutcome::Conflicts(vec![PathBuf::from("a.txt")])
+        );
+        assert_eq!(repo.state(), RepositoryState::Revert);
+        let label = cherry_pick::label(
+            repo.odb(),
+            &change,
+            &repo.odb().read_commit(&change).unwrap(),
+        )
+        .unwrap();
+        assert!(read_file(&repo, "a.txt").ends_with(&format!(">>>>>>> parent of {}\n", label)));
+        assert!(matches!(
+            cherry_pick::cherry_pick_continue(&repo),
+            Err(GitError::NoOperationInProgress(RepositoryState::CherryPick))
+        ));
+        revert_abort(&repo).unwrap();
+        assert_eq!(read_file(&repo, "a.txt"), "later\n");
+        assert_eq!(repo.state(), RepositoryState::Clean);
+
+        revert(&repo, &change.to_string(), &RevertOptions::default()).unwrap();
+        stage_file(&repo, "a.txt", "a\n");
+        let id = revert_continue(&repo).unwrap();
+        let commit = repo.odb().read_commit(&id).unwrap();
+        assert!(commit.message.starts_with("Revert \"change a\"\n"));
+        assert_eq!(repo.state(), RepositoryState::Clean);
+
+        // A merge needs to be told which side to go back to.
+        let odb = repo.odb();
+        let side = write_commit(
+            &repo,
+            &[base],
+            &[("a.txt", "a\n"), ("s.txt", "s\n")],
+            "side",
+        );
+        let merge = write_commit(
+            &repo,
+            &[id, side],
+            &[("a.txt", "a\n"), ("s.txt", "s\n")],
+            "merge",
+        );
+        checkout(&repo, "master", &merge);
+        assert!(matches!(
+            revert(&repo, &merge.to_string(), &RevertOptions::default()),
+            Err(GitError::MainlineRequired(m)) if m == merge
+        ));
+        let options = RevertOptions {
+            mainline: Some(1),
+            ..Default::default()
+        };
+        let reverted = match revert(&repo, &merge.to_string(), &options).unwrap() {
+            RevertOutcome::MadeCommit(reverted) => reverted,
+            other => panic!("unexpected outcome {:?}", other),
+        };
+        let reverted = odb.read_commit(&reverted).unwrap();
+        assert_eq!(reverted.tree, odb.read_commit(&id).unwrap().tree);
+        assert!(reverted.message.ends_with(&format!(
+            "This reverts commit {}, reversing\nchanges made to {}.\n",
+            merge, id
+        )));
+    }
+}
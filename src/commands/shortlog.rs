@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::core::pretty;
+use crate::core::revparse;
+use crate::core::revwalk::RevWalk;
+use crate::core::shallow;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShortlogOptions {
+    /// Sort by commit count, highest first, rather than by name.
+    pub numbered: bool,
+    /// Only write the counts, as `-s` does.
+    pub summary: bool,
+    /// Group by name and email rather than name alone, as `-e` does.
+    pub email: bool,
+    /// Group by committer rather than author, as `-c` does.
+    pub committer: bool,
+}
+
+/// One person's commits in a shortlog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorSummary {
+    pub name: String,
+    /// Set when grouping by email.
+    pub email: Option<String>,
+    /// The subject of each commit, oldest first.
+    pub subjects: Vec<String>,
+}
+
+impl AuthorSummary {
+    pub fn count(&self) -> usize {
+        self.subjects.len()
+    }
+
+    /// The name as the groups are keyed and sorted: `Name <email>` when
+    /// grouping by email.
+    fn key(&self) -> String {
+        match &self.email {
+            Some(email) => format!("{} <{}>", self.name, email),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// `git shortlog`: the commits in `range`, as [`revparse::parse_range`]
+/// takes it, grouped by who wrote them. Groups are sorted by name, byte by
+/// byte like git, or with `numbered` by count and then name.
+pub fn shortlog(
+    repo: &Repository,
+    range: &[String],
+    options: &ShortlogOptions,
+) -> GitResult<Vec<AuthorSummary>> {
+    let mut walk = RevWalk::new(repo.odb());
+    walk.push_specs(&revparse::parse_range(repo, range)?)?
+        .shallow(shallow::shallow_commits(repo)?);
+    let mut groups: Vec<AuthorSummary> = Vec::new();
+    let mut by_key: HashMap<(String, Option<String>), usize> = HashMap::new();
+    for commit in walk {
+        let (_, commit) = commit?;
+        let person = if options.committer {
+            &commit.committer
+        } else {
+            &commit.author
+        };
+        let email = options.email.then(|| person.email.clone());
+        let group = *by_key
+            .entry((person.name.clone(), email.clone()))
+            .or_insert_with(|| {
+                groups.push(AuthorSummary {
+                    name: person.name.clone(),
+                    email,
+                    subjects: Vec::new(),
+                });
+                groups.len() - 1
+            });
+        groups[group].subjects.push(subject(&commit.message));
+    }
+    for group in &mut groups {
+        group.subjects.reverse();
+    }
+    if options.numbered {
+        groups.sort_by_cached_key(|group| (std::cmp::Reverse(group.count()), group.key()));
+    } else {
+        groups.sort_by_cached_key(AuthorSummary::key);
+    }
+    Ok(groups)
+}
+
+/// Write a shortlog as git lays it out: each name and count followed by
+/// its indented subjects, or with `summary` one `count<TAB>name` line each.
+pub fn write_shortlog<W: Write>(
+    mut out: W,
+    groups: &[AuthorSummary],
+    options: &ShortlogOptions,
+) -> GitResult<()> {
+    for group in groups {
+        if options.summary {
+            writeln!(out, "{:6}\t{}", group.count(), group.key())?;
+            continue;
+        }
+        writeln!(out, "{} ({}):", group.key(), group.count())?;
+        for subject in &group.subjects {
+            writeln!(out, "      {}", subject)?;
+        }
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// A commit's subject as shortlog shows it, without any `[PATCH]` prefix.
+fn subject(message: &str) -> String {
+    let subject = pretty::subject(message);
+    let subject = match subject.strip_prefix("[PATCH") {
+        Some(rest) => rest
+            .split_once(']')
+            .map_or(subject.as_str(), |(_, rest)| rest),
+        None => &subject,
+    };
+    match subject.trim_start() {
+        "" => "<none>".to_string(),
+        subject => subject.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::object::{Commit, GitObject};
+    use crate::core::oid::Oid;
+    use crate::core::signature::Signature;
+    use crate::test_utils::{git, init_repo, set_ref};
+
+    fn commit_by(
+        repo: &Repository,
+        parent: Option<Oid>,
+        author: (&str, &str),
+        committer: &str,
+        message: &str,
+    ) -> Oid {
+        let odb = repo.odb();
+        let tree = odb.write(&GitObject::Tree(Default::default())).unwrap();
+        let commit = Commit {
+            tree,
+            parents: parent.into_iter().collect(),
+            author: Signature::new(author.0, author.1, 1_700_000_000, 0),
+            committer: Signature::new(committer, "c@example.com", 1_700_000_000, 0),
+            extra_headers: Vec::new(),
+            message: message.to_string(),
+        };
+        odb.write(&GitObject::Commit(commit)).unwrap()
+    }
+
+    fn render(groups: &[AuthorSummary], options: &ShortlogOptions) -> String {
+        let mut out = Vec::new();
+        write_shortlog(&mut out, groups, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn groups_and_sorts_like_git() {
+        let (_dir, repo) = init_repo();
+        let bob = ("Bob", "bob@example.com");
+        let mut tip = None;
+        for (author, committer, message) in [
+            (bob, "Carol", "bob 1\nwrapped\n\nbody\n"),
+            (("alice", "alice@example.com"), "Carol", "[PATCH] alice\n"),
+            (bob, "Dave", "bob 2\n"),
+            (("Bob", "bob@work.example.com"), "Carol", "bob at work\n"),
+            (("Zed", "zed@example.com"), "Carol", "\n"),
+            (("Zed", "zed@example.com"), "Carol", "[PATCH v2] zed\n"),
+        ] {
+            tip = Some(commit_by(&repo, tip, author, committer, message));
+        }
+        set_ref(&repo, "refs/heads/master", &tip.unwrap());
+        let range = ["master".to_string()];
+
+        let default = ShortlogOptions::default();
+        let groups = shortlog(&repo, &range, &default).unwrap();
+        assert_eq!(groups[0].name, "Bob");
+        assert_eq!(
+            groups[0].subjects,
+            ["bob 1 wrapped", "bob 2", "bob at work"]
+        );
+        let counted = |numbered, email, committer| ShortlogOptions {
+            numbered,
+            summary: true,
+            email,
+            committer,
+        };
+        let cases = [
+            (vec![], default),
+            (vec!["-s"], counted(false, false, false)),
+            (vec!["-sn"], counted(true, false, false)),
+            (vec!["-se"], counted(false, true, false)),
+            (vec!["-sne"], counted(true, true, false)),
+            (vec!["-snc"], counted(true, false, true)),
+        ];
+        for (flags, options) in &cases {
+            let ours = render(&shortlog(&repo, &range, options).unwrap(), options);
+            let mut args = vec!["shortlog"];
+            args.extend(flags);
+            args.push("master");
+            if let Some(theirs) = git(&repo, &args) {
+                assert_eq!(ours, theirs, "{:?}", flags);
+            }
+        }
+        assert_eq!(
+            render(&shortlog(&repo, &range, &cases[2].1).unwrap(), &cases[2].1),
+            "     3\tBob\n     2\tZed\n     1\talice\n"
+        );
+        let earlier = ["master~3".to_string()];
+        let groups = shortlog(&repo, &earlier, &counted(false, false, true)).unwrap();
+        let counts: Vec<_> = groups.iter().map(|g| (g.key(), g.count())).collect();
+        assert_eq!(counts, [("Carol".to_string(), 2), ("Dave".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+use crate::commands::merge::{ensure_clean, merge_clean};
+use crate::core::index::IndexEntry;
+use crate::core::object::{Commit, GitObject, ObjectType};
+use crate::core::odb::LooseObjectWriter;
+use crate::core::oid::Oid;
+use crate::core::reflog;
+use crate::core::refs;
+use crate::core::signature;
+use crate::core::tree::{self, FlatEntry, FlatTree};
+use crate::core::worktree;
+use crate::error::{GitError, GitResult};
+use crate::repository::{Head, Repository};
+
+pub const STASH_REF: &str = "refs/stash";
+
+/// `git stash push`: save the index and the tracked files' working tree
+/// changes, then reset both to HEAD. Like git, the stash is a commit of
+/// the working tree whose parents are HEAD and a commit of the index, and
+/// `refs/stash`'s log is the stack of saved stashes. Returns the new
+/// stash commit.
+pub fn stash_push(repo: &Repository, message: Option<&str>) -> GitResult<Oid> {
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let (branch, head) = match repo.head()? {
+        Head::Branch(name, Some(id)) => (refs::shorten(&name).to_string(), id),
+        Head::Detached(id) => ("(no branch)".to_string(), id),
+        Head::Branch(name, None) | Head::Unborn(name) => {
+            return Err(GitError::UnbornBranch(refs::shorten(&name).to_string()))
+        }
+    };
+    let head_commit = odb.read_commit(&head)?;
+    let head_tree = tree::flatten(odb, &head_commit.tree)?;
+    let index = repo.read_index()?;
+    if index.has_conflicts() {
+        return Err(GitError::UnresolvedConflicts(
+            index
+                .conflicts()
+                .into_iter()
+                .map(|c| PathBuf::from(c.path))
+                .collect(),
+        ));
+    }
+
+    let staged = tree::from_index(&index);
+    let mut work = FlatTree::new();
+    for (path, entry) in &staged {
+        match worktree::hash_file(work_dir, path)? {
+            Some(on_disk) if on_disk == *entry => {
+                work.insert(path.clone(), on_disk);
+            }
+            Some(on_disk) => {
+                let full = work_dir.join(path);
+                let content = worktree::read_content(&full, &fs::symlink_metadata(&full)?)?;
+                let oid = odb.write_raw(ObjectType::Blob, &content)?;
+                work.insert(
+                    path.clone(),
+                    FlatEntry {
+                        mode: on_disk.mode,
+                        oid,
+                    },
+                );
+            }
+            None => {}
+        }
+    }
+    if staged == head_tree && work == head_tree {
+        return Err(GitError::NoLocalChanges);
+    }
+
+    let summary = format!("{} {}", odb.abbreviate(&head, 7)?, head_commit.summary());
+    let (author, committer) = signature::default_signatures(repo)?;
+    let mut writer = LooseObjectWriter::new(odb);
+    let index_commit = Commit {
+        tree: tree::build(&mut writer, &staged)?,
+        parents: vec![head],
+        author: author.clone(),
+        committer: committer.clone(),
+        extra_headers: Vec::new(),
+        message: format!("index on {}: {}\n", branch, summary),
+    };
+    let index_commit = odb.write(&GitObject::Commit(index_commit))?;
+    let message = match message {
+        Some(message) => format!("On {}: {}\n", branch, message),
+        None => format!("WIP on {}: {}\n", branch, summary),
+    };
+    let stash = Commit {
+        tree: tree::build(&mut writer, &work)?,
+        parents: vec![head, index_commit],
+        author,
+        committer,
+        extra_headers: Vec::new(),
+        message: message.clone(),
+    };
+    let stash = odb.write(&GitObject::Commit(stash))?;
+
+    // The stash is always logged, whatever `core.logAllRefUpdates` says:
+    // its log is what remembers the older stashes.
+    let log = reflog::path(repo, STASH_REF);
+    if !log.is_file() {
+        if let Some(parent) = log.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        File::create(&log)?;
+    }
+    refs::update_no_deref(repo, STASH_REF, stash, None, &message)?;
+
+    worktree::update(odb, work_dir, &work, &head_tree)?;
+    repo.write_index(&worktree::index_from_tree(work_dir, &head_tree)?)?;
+    Ok(stash)
+}
+
+/// `git stash pop --index`: merge the newest stash's working tree and
+/// index changes into the current ones, then drop it. Nothing is touched
+/// if either would conflict, or if a path the stash changes has local
+/// changes of its own.
+pub fn stash_pop(repo: &Repository) -> GitResult<()> {
+    let work_dir = repo.require_work_dir()?;
+    let odb = repo.odb();
+    let stash = match refs::read(repo, STASH_REF)? {
+        Some(_) => refs::resolve(repo, STASH_REF)?,
+        None => return Err(GitError::RefNotFound(STASH_REF.to_string())),
+    };
+    let commit = odb.read_commit(&stash)?;
+    let (base, index_commit) = match commit.parents.as_slice() {
+        [base, index_commit, ..] => (*base, *index_commit),
+        _ => return Err(GitError::Corrupt(format!("{} is not a stash", STASH_REF))),
+    };
+    let base = tree::flatten(odb, &odb.read_commit(&base)?.tree)?;
+    let stashed_index = tree::flatten(odb, &odb.read_commit(&index_commit)?.tree)?;
+    let stashed_work = tree::flatten(odb, &commit.tree)?;
+    let head = repo
+        .head_commit()?
+        .ok_or_else(|| GitError::UnknownRevision("HEAD".to_string()))?;
+    let ours = tree::flatten(odb, &odb.read_commit(&head)?.tree)?;
+
+    let mut index = repo.read_index()?;
+    let work = merge_clean(odb, &base, &ours, &stashed_work, "Stashed changes")?;
+    let staged = merge_clean(odb, &base, &ours, &stashed_index, "Stashed changes")?;
+    ensure_clean(repo, &index, &ours, &work)?;
+    ensure_clean(repo, &index, &ours, &staged)?;
+
+    worktree::update(odb, work_dir, &ours, &work)?;
+    let paths: BTreeSet<&String> = ours.keys().chain(staged.keys()).collect();
+    for path in paths {
+        let entry = staged.get(path);
+        if ours.get(path) == entry {
+            continue;
+        }
+        match entry {
+            // Only a file that matches its entry can have its stat data
+            // recorded, or the unstaged changes would look clean.
+            Some(entry) if work.get(path) == Some(entry) => {
+                index.add(worktree::stat_entry(work_dir, path, entry.oid, entry.mode)?)
+            }
+            Some(entry) => index.add(IndexEntry::new(path, entry.oid, entry.mode)),
+            None => {
+                index.remove(path);
+            }
+        }
+    }
+    repo.write_index(&index)?;
+    drop_newest(repo, stash)
+}
+
+/// Remove the newest stash, making the one before it (if any) the top of
+/// the stack.
+fn drop_newest(repo: &Repository, stash: Oid) -> GitResult<()> {
+    let mut entries = reflog::read(repo, STASH_REF)?;
+    entries.pop();
+    match entries.last() {
+        Some(previous) => {
+            refs::update_no_deref(repo, STASH_REF, previous.new, Some(Some(stash)), "")?;
+            // Updating the ref logged it again; put the log back the way it
+            // was, less the dropped entry.
+            reflog::rewrite(repo, STASH_REF, &entries)
+        }
+        None => refs::delete(repo, STASH_REF, Some(stash)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{git, init_repo, read_file, stage_file, staged_oid, write_commit};
+
+    /// HEAD with `a` and `b` checked out and staged.
+    fn checked_out(repo: &Repository) -> Oid {
+        let base = write_commit(repo, &[], &[("a", "a\n"), ("b", "b\n")], "base");
+        repo.set_head_commit(&base, "").unwrap();
+        stage_file(repo, "a", "a\n");
+        stage_file(repo, "b", "b\n");
+        base
+    }
+
+    #[test]
+    fn push_cleans_the_tree_and_pop_brings_changes_back() {
+        let (_dir, repo) = init_repo();
+        let base = checked_out(&repo);
+        let work_dir = repo.work_dir().unwrap().to_path_buf();
+        stage_file(&repo, "a", "staged\n");
+        stage_file(&repo, "new", "new\n");
+        fs::write(work_dir.join("b"), "unstaged\n").unwrap();
+        let (staged_a, staged_new) = (staged_oid(&repo, "a"), staged_oid(&repo, "new"));
+        stash_push(&repo, None).unwrap();
+
+        assert_eq!(read_file(&repo, "a"), "a\n");
+        assert_eq!(read_file(&repo, "b"), "b\n");
+        assert!(!work_dir.join("new").exists());
+        assert_eq!(staged_oid(&repo, "new"), None);
+        assert_eq!(reflog::read(&repo, STASH_REF).unwrap().len(), 1);
+        let work_tree = work_dir.to_str().unwrap();
+        if let Some(status) = git(&repo, &["--work-tree", work_tree, "status", "--porcelain"]) {
+            assert_eq!(status, "");
+        }
+        if let Some(list) = git(&repo, &["stash", "list"]) {
+            let abbrev = base.abbrev(7);
+            assert_eq!(
+                list,
+                format!("stash@{{0}}: WIP on master: {} base\n", abbrev)
+            );
+        }
+        assert!(matches!(
+            stash_push(&repo, None),
+            Err(GitError::NoLocalChanges)
+        ));
+
+        stash_pop(&repo).unwrap();
+        assert_eq!(read_file(&repo, "a"), "staged\n");
+        assert_eq!(read_file(&repo, "b"), "unstaged\n");
+        assert_eq!(read_file(&repo, "new"), "new\n");
+        assert_eq!(staged_oid(&repo, "a"), staged_a);
+        assert_eq!(staged_oid(&repo, "new"), staged_new);
+        assert!(refs::read(&repo, STASH_REF).unwrap().is_none());
+        if let Some(status) = git(&repo, &["--work-tree", work_tree, "status", "--porcelain"]) {
+            assert_eq!(status, "M  a\n M b\nA  new\n");
+        }
+    }
+
+    #[test]
+    fn pop_keeps_older_stashes_and_refuses_conflicts() {
+        let (_dir, repo) = init_repo();
+        checked_out(&repo);
+        let work_dir = repo.work_dir().unwrap().to_path_buf();
+        fs::write(work_dir.join("a"), "first\n").unwrap();
+        let first = stash_push(&repo, Some("first")).unwrap();
+        fs::write(work_dir.join("a"), "second\n").unwrap();
+        stash_push(&repo, Some("second")).unwrap();
+
+        // A local edit to the same line keeps the stash where it is.
+        fs::write(work_dir.join("a"), "local\n").unwrap();
+        assert!(matches!(stash_pop(&repo), Err(GitError::LocalChanges(_))));
+        fs::write(work_dir.join("a"), "a\n").unwrap();
+        stash_pop(&repo).unwrap();
+        assert_eq!(read_file(&repo, "a"), "second\n");
+        assert_eq!(refs::resolve(&repo, STASH_REF).unwrap(), first);
+        let entries = reflog::read(&repo, STASH_REF).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].message, "On master: first");
+
+        // With `second` popped and committed the first stash conflicts.
+        let head = repo.head_commit().unwrap().unwrap();
+        let second = write_commit(&repo, &[head], &[("a", "second\n"), ("b", "b\n")], "second");
+        repo.set_head_commit(&second, "").unwrap();
+        stage_file(&repo, "a", "second\n");
+        assert!(matches!(stash_pop(&repo), Err(GitError::WouldConflict(_))));
+        assert_eq!(read_file(&repo, "a"), "second\n");
+        assert_eq!(refs::resolve(&repo, STASH_REF).unwrap(), first);
+    }
+}
@@ -0,0 +1,1177 @@
+//! `git status`: what's staged, what's changed since, and what's
+//! untracked, along with the branch and any operation in progress.
+//!
+//! Staged changes are HEAD's tree against the index, with renames found
+//! as git finds them. Changes in the working tree are found file by file
+//! from the index's stat data: a file whose times, inode and size still
+//! match its entry isn't read, unless it was written no earlier than the
+//! index and so could have changed without its times showing it, git's
+//! "racily clean" case. Conflicted paths are reported on their own.
+//!
+//! The result can be written as `git status` writes it for people, or in
+//! either of the porcelain formats scripts read.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::commands::branch;
+use crate::commands::diff::{self, DiffOptions};
+use crate::commands::rev_list::ahead_behind;
+use crate::core::diff::{DeltaStatus, FileDelta};
+use crate::core::ignore::IgnoreStack;
+use crate::core::index::Index;
+use crate::core::object::MODE_GITLINK;
+use crate::core::oid::{Oid, NULL_OID};
+use crate::core::quote::{quote_path, quote_path_with_spaces};
+use crate::core::refs;
+use crate::core::rename::RenameOptions;
+use crate::core::tree::FlatEntry;
+use crate::core::worktree;
+use crate::error::GitResult;
+use crate::repository::{Head, Repository, RepositoryState};
+
+/// Which untracked files are listed, as `--untracked-files` picks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UntrackedFiles {
+    No,
+    /// Files, and untracked directories as a whole, as `dir/`.
+    #[default]
+    Normal,
+    /// Every file, however deep in an untracked directory.
+    All,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatusOptions {
+    pub untracked: UntrackedFiles,
+    /// List ignored paths too, as `--ignored` does: directories wholly
+    /// ignored as `dir/` unless every file is asked for.
+    pub ignored: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub head: Head,
+    /// What the branch tracks, when it's configured to.
+    pub upstream: Option<Upstream>,
+    pub state: RepositoryState,
+    /// The commit being cherry-picked or reverted, when one is.
+    pub picking: Option<Oid>,
+    /// HEAD's tree against the index.
+    pub staged: Vec<FileDelta>,
+    /// The index against the working tree.
+    pub unstaged: Vec<FileDelta>,
+    pub unmerged: Vec<UnmergedPath>,
+    /// Paths neither tracked nor ignored, sorted.
+    pub untracked: Vec<String>,
+    /// Ignored paths, sorted, when they're asked for.
+    pub ignored: Vec<String>,
+}
+
+/// The branch a branch tracks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    /// Its full name, as `refs/remotes/origin/main`.
+    pub name: String,
+    /// How many commits the branch has that it doesn't, and the other way
+    /// round; `None` when it's gone.
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+/// A path with conflicts, as the index and working tree have it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnmergedPath {
+    pub path: String,
+    /// Stages 1 to 3: the merge base's version, ours and theirs.
+    pub stages: [Option<FlatEntry>; 3],
+    /// The working tree file's mode, 0 if there isn't one.
+    pub worktree_mode: u32,
+}
+
+impl UnmergedPath {
+    /// The conflict's two letters in `git status --short`: `U` for a side
+    /// that changed the path, `A` for one that added it and `D` for one
+    /// that deleted it, ours first.
+    pub fn code(&self) -> &'static str {
+        match [0, 1, 2].map(|i| self.stages[i].is_some()) {
+            [true, false, false] => "DD",
+            [false, true, false] => "AU",
+            [true, true, false] => "UD",
+            [false, false, true] => "UA",
+            [true, false, true] => "DU",
+            [false, true, true] => "AA",
+            _ => "UU",
+        }
+    }
+}
+
+impl Status {
+    /// Whether there's nothing to report but the branch.
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty()
+            && self.unstaged.is_empty()
+            && self.unmerged.is_empty()
+            && self.untracked.is_empty()
+    }
+}
+
+/// The repository's status, with untracked directories shown whole.
+pub fn status(repo: &Repository) -> GitResult<Status> {
+    status_with(repo, &StatusOptions::default())
+}
+
+/// [`status`] with `options`.
+pub fn status_with(repo: &Repository, options: &StatusOptions) -> GitResult<Status> {
+    let work_dir = repo.require_work_dir()?;
+    let index = repo.read_index()?;
+    let head = repo.head()?;
+    let upstream = match &head {
+        Head::Branch(name, Some(_)) => match branch::upstream_of(repo, refs::shorten(name))? {
+            Some(upstream) => {
+                let ahead_behind = match refs::read(repo, &upstream)? {
+                    Some(_) => Some(ahead_behind(repo, name, &upstream)?),
+                    None => None,
+                };
+                Some(Upstream {
+                    name: upstream,
+                    ahead_behind,
+                })
+            }
+            None => None,
+        },
+        _ => None,
+    };
+    let state = repo.state();
+    let picking = match state {
+        RepositoryState::CherryPick => Some("CHERRY_PICK_HEAD"),
+        RepositoryState::Revert => Some("REVERT_HEAD"),
+        _ => None,
+    };
+    let picking = match picking {
+        Some(name) => {
+            let text = fs::read_to_string(repo.git_dir().join(name))?;
+            Some(text.trim().parse::<Oid>()?)
+        }
+        None => None,
+    };
+    let staged_options = DiffOptions {
+        cached: true,
+        renames: Some(RenameOptions::default()),
+        ..DiffOptions::default()
+    };
+    let mut found = Found::default();
+    if options.untracked != UntrackedFiles::No {
+        let mut walker = Walker::new(repo, work_dir, &index, options)?;
+        walker.walk("", false, &mut found)?;
+        found.untracked.sort();
+        found.ignored.sort();
+        if !options.ignored {
+            found.ignored.clear();
+        }
+    }
+    Ok(Status {
+        head,
+        upstream,
+        state,
+        picking,
+        staged: diff::changes(repo, &staged_options)?,
+        unstaged: unstaged(repo, work_dir, &index)?,
+        unmerged: unmerged(work_dir, &index)?,
+        untracked: found.untracked,
+        ignored: found.ignored,
+    })
+}
+
+/// The index's merged entries against the working tree, reading only the
+/// files their stat data doesn't vouch for.
+fn unstaged(repo: &Repository, work_dir: &Path, index: &Index) -> GitResult<Vec<FileDelta>> {
+    // Entries stat'ed no earlier than the index was written may be of
+    // files changed again within the same tick.
+    let index_time = fs::metadata(repo.index_path())?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or((0, 0), |time| (time.as_secs() as u32, time.subsec_nanos()));
+    let mut deltas = Vec::new();
+    for entry in index.entries() {
+        // Submodules' checkouts aren't compared.
+        if entry.stage() != 0 || entry.mode == MODE_GITLINK {
+            continue;
+        }
+        let meta = worktree_metadata(work_dir, &entry.path)?;
+        if let Some(meta) = &meta {
+            let racy = (entry.mtime, entry.mtime_nsec) >= index_time;
+            if !racy && worktree::mode_of(meta) == entry.mode && entry.stat_matches(meta) {
+                continue;
+            }
+        }
+        let new = match meta {
+            Some(_) => worktree::hash_file(work_dir, &entry.path)?,
+            None => None,
+        };
+        let old = FlatEntry {
+            mode: entry.mode,
+            oid: entry.oid,
+        };
+        if let Some(status) = DeltaStatus::between(Some(&old), new.as_ref()) {
+            deltas.push(FileDelta {
+                path: entry.path.clone(),
+                from: None,
+                status,
+                old: Some(old),
+                new,
+            });
+        }
+    }
+    Ok(deltas)
+}
+
+/// The index's conflicted paths, with each stage's mode and id.
+fn unmerged(work_dir: &Path, index: &Index) -> GitResult<Vec<UnmergedPath>> {
+    let mut paths: Vec<UnmergedPath> = Vec::new();
+    for entry in index.entries().iter().filter(|e| e.stage() != 0) {
+        if paths.last().is_none_or(|p| p.path != entry.path) {
+            let worktree_mode = worktree_metadata(work_dir, &entry.path)?
+                .map_or(0, |meta| worktree::mode_of(&meta));
+            paths.push(UnmergedPath {
+                path: entry.path.clone(),
+                stages: [None, None, None],
+                worktree_mode,
+            });
+        }
+        let path = paths.last_mut().unwrap();
+        path.stages[entry.stage() as usize - 1] = Some(FlatEntry {
+            mode: entry.mode,
+            oid: entry.oid,
+        });
+    }
+    Ok(paths)
+}
+
+/// The working tree file's metadata, or `None` if it's missing.
+fn worktree_metadata(work_dir: &Path, path: &str) -> GitResult<Option<fs::Metadata>> {
+    match fs::symlink_metadata(work_dir.join(path)) {
+        Ok(meta) => Ok(Some(meta)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The untracked and ignored paths a [`Walker`] finds.
+#[derive(Debug, Default)]
+struct Found {
+    untracked: Vec<String>,
+    ignored: Vec<String>,
+}
+
+/// Finds the untracked and ignored files in the working tree.
+struct Walker<'a> {
+    work_dir: &'a Path,
+    rules: IgnoreStack,
+    tracked: HashSet<&'a str>,
+    /// See [`Index::directories`].
+    tracked_dirs: HashSet<&'a str>,
+    /// List the files in untracked directories rather than the
+    /// directories.
+    all: bool,
+}
+
+impl<'a> Walker<'a> {
+    fn new(
+        repo: &Repository,
+        work_dir: &'a Path,
+        index: &'a Index,
+        options: &StatusOptions,
+    ) -> GitResult<Walker<'a>> {
+        Ok(Walker {
+            work_dir,
+            rules: IgnoreStack::new(repo)?,
+            tracked: index.entries().iter().map(|e| e.path.as_str()).collect(),
+            tracked_dirs: index.directories(),
+            all: options.untracked == UntrackedFiles::All,
+        })
+    }
+
+    /// Add what's untracked and what's ignored under `dir`, everything
+    /// there being ignored if `ignored` is, to `found`.
+    fn walk(&mut self, dir: &str, ignored: bool, found: &mut Found) -> GitResult<()> {
+        self.rules.push(dir)?;
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.work_dir.join(dir))? {
+            let entry = entry?;
+            names.push((
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.file_type()?.is_dir(),
+            ));
+        }
+        names.sort();
+        for (name, is_dir) in names {
+            if name == ".git" {
+                continue;
+            }
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            if !is_dir {
+                if self.tracked.contains(path.as_str()) {
+                    continue;
+                }
+                if ignored || self.rules.is_ignored(&path, false) {
+                    found.ignored.push(path);
+                } else {
+                    found.untracked.push(path);
+                }
+                continue;
+            }
+            if self.tracked_dirs.contains(path.as_str()) {
+                self.walk(&path, ignored, found)?;
+                continue;
+            }
+            let ignored = ignored || self.rules.is_ignored(&path, true);
+            // A nested repository is shown whole, and an untracked
+            // directory too unless every file is asked for, so long as
+            // there's something in it to show. One with nothing in it but
+            // ignored files counts as ignored itself.
+            if self.work_dir.join(&path).join(".git").exists() {
+                let list = if ignored {
+                    &mut found.ignored
+                } else {
+                    &mut found.untracked
+                };
+                list.push(format!("{}/", path));
+            } else if self.all {
+                self.walk(&path, ignored, found)?;
+            } else {
+                let mut inside = Found::default();
+                self.walk(&path, ignored, &mut inside)?;
+                if !inside.untracked.is_empty() {
+                    found.untracked.push(format!("{}/", path));
+                    found.ignored.append(&mut inside.ignored);
+                } else if !inside.ignored.is_empty() {
+                    found.ignored.push(format!("{}/", path));
+                }
+            }
+        }
+        self.rules.pop();
+        Ok(())
+    }
+}
+
+/// `status` as `git status` shows it by default, paths relative to the
+/// top of the working tree.
+pub fn format_long(status: &Status) -> String {
+    let mut out = String::new();
+    match &status.head {
+        Head::Branch(name, _) => out.push_str(&format!("On branch {}\n", refs::shorten(name))),
+        Head::Unborn(name) => out.push_str(&format!(
+            "On branch {}\n\nNo commits yet\n\n",
+            refs::shorten(name)
+        )),
+        Head::Detached(id) => out.push_str(&format!("HEAD detached at {}\n", id.abbrev(7))),
+    }
+    if let Some(upstream) = &status.upstream {
+        write_tracking(&mut out, upstream);
+    }
+    let conflicted = !status.unmerged.is_empty();
+    let picked = status.picking.map_or_else(String::new, |id| id.abbrev(7));
+    match status.state {
+        RepositoryState::Clean => {}
+        RepositoryState::Merge if conflicted => out.push_str(
+            "You have unmerged paths.\n  (fix conflicts and run \"git commit\")\n  \
+             (use \"git merge --abort\" to abort the merge)\n\n",
+        ),
+        RepositoryState::Merge => out.push_str(
+            "All conflicts fixed but you are still merging.\n  \
+             (use \"git commit\" to conclude merge)\n\n",
+        ),
+        RepositoryState::CherryPick | RepositoryState::Revert => {
+            let (doing, command) = match status.state {
+                RepositoryState::CherryPick => ("cherry-picking", "cherry-pick"),
+                _ => ("reverting", "revert"),
+            };
+            out.push_str(&format!("You are currently {} commit {}.\n", doing, picked));
+            if conflicted {
+                out.push_str(&format!(
+                    "  (fix conflicts and run \"git {} --continue\")\n",
+                    command
+                ));
+            } else {
+                out.push_str(&format!(
+                    "  (all conflicts fixed: run \"git {} --continue\")\n",
+                    command
+                ));
+            }
+            out.push_str(&format!(
+                "  (use \"git {0} --skip\" to skip this patch)\n  \
+                 (use \"git {0} --abort\" to cancel the {0} operation)\n\n",
+                command
+            ));
+        }
+        RepositoryState::Rebase => out.push_str(
+            "You are currently rebasing.\n  \
+             (use \"git rebase --abort\" to check out the original branch)\n\n",
+        ),
+        RepositoryState::ApplyMailbox => out.push_str(
+            "You are in the middle of an am session.\n  \
+             (fix conflicts and then run \"git am --continue\")\n  \
+             (use \"git am --skip\" to skip this patch)\n  \
+             (use \"git am --abort\" to restore the original branch)\n\n",
+        ),
+    }
+    // Mid-merge, unstaging isn't offered.
+    let merging = matches!(
+        status.state,
+        RepositoryState::Merge | RepositoryState::CherryPick
+    );
+
+    if !status.staged.is_empty() {
+        out.push_str("Changes to be committed:\n");
+        if matches!(status.head, Head::Unborn(_)) {
+            out.push_str("  (use \"git rm --cached <file>...\" to unstage)\n");
+        } else if !merging {
+            out.push_str("  (use \"git restore --staged <file>...\" to unstage)\n");
+        }
+        for delta in &status.staged {
+            write_change(&mut out, delta);
+        }
+        out.push('\n');
+    }
+    if conflicted {
+        out.push_str("Unmerged paths:\n");
+        if !merging {
+            out.push_str("  (use \"git restore --staged <file>...\" to unstage)\n");
+        }
+        // A path deleted on one side and kept on the other may be resolved
+        // either way; one deleted on both is resolved by removing it.
+        let codes: Vec<&str> = status.unmerged.iter().map(UnmergedPath::code).collect();
+        let one_sided = codes.iter().any(|&code| code == "UD" || code == "DU");
+        let both_deleted = codes.contains(&"DD");
+        out.push_str(if one_sided {
+            "  (use \"git add/rm <file>...\" as appropriate to mark resolution)\n"
+        } else if both_deleted {
+            "  (use \"git rm <file>...\" to mark resolution)\n"
+        } else {
+            "  (use \"git add <file>...\" to mark resolution)\n"
+        });
+        for (conflict, code) in status.unmerged.iter().zip(codes) {
+            let how = match code {
+                "UU" => "both modified:",
+                "AA" => "both added:",
+                "UD" => "deleted by them:",
+                "DU" => "deleted by us:",
+                "AU" => "added by us:",
+                "UA" => "added by them:",
+                _ => "both deleted:",
+            };
+            out.push_str(&format!("\t{:<17}{}\n", how, quote_path(&conflict.path)));
+        }
+        out.push('\n');
+    }
+    if !status.unstaged.is_empty() {
+        out.push_str("Changes not staged for commit:\n");
+        if status
+            .unstaged
+            .iter()
+            .any(|d| d.status == DeltaStatus::Deleted)
+        {
+            out.push_str("  (use \"git add/rm <file>...\" to update what will be committed)\n");
+        } else {
+            out.push_str("  (use \"git add <file>...\" to update what will be committed)\n");
+        }
+        out.push_str("  (use \"git restore <file>...\" to discard changes in working directory)\n");
+        for delta in &status.unstaged {
+            write_change(&mut out, delta);
+        }
+        out.push('\n');
+    }
+    if !status.untracked.is_empty() {
+        out.push_str(
+            "Untracked files:\n  (use \"git add <file>...\" to include in what will be committed)\n",
+        );
+        for path in &status.untracked {
+            out.push_str(&format!("\t{}\n", quote_path(path)));
+        }
+        out.push('\n');
+    }
+    if !status.ignored.is_empty() {
+        out.push_str(
+            "Ignored files:\n  (use \"git add -f <file>...\" to include in what will be committed)\n",
+        );
+        for path in &status.ignored {
+            out.push_str(&format!("\t{}\n", quote_path(path)));
+        }
+        out.push('\n');
+    }
+
+    if status.staged.is_empty() {
+        out.push_str(if !status.unstaged.is_empty() || conflicted {
+            "no changes added to commit (use \"git add\" and/or \"git commit -a\")\n"
+        } else if !status.untracked.is_empty() {
+            "nothing added to commit but untracked files present (use \"git add\" to track)\n"
+        } else if matches!(status.head, Head::Unborn(_)) {
+            "nothing to commit (create/copy files and use \"git add\" to track)\n"
+        } else {
+            "nothing to commit, working tree clean\n"
+        });
+    }
+    out
+}
+
+/// How the branch compares with its upstream, and the blank line after.
+fn write_tracking(out: &mut String, upstream: &Upstream) {
+    let name = refs::shorten(&upstream.name);
+    let commits = |n: usize| format!("{} commit{}", n, if n == 1 { "" } else { "s" });
+    match upstream.ahead_behind {
+        None => out.push_str(&format!(
+            "Your branch is based on '{}', but the upstream is gone.\n  \
+             (use \"git branch --unset-upstream\" to fixup)\n",
+            name
+        )),
+        Some((0, 0)) => out.push_str(&format!("Your branch is up to date with '{}'.\n", name)),
+        Some((ahead, 0)) => out.push_str(&format!(
+            "Your branch is ahead of '{}' by {}.\n  \
+             (use \"git push\" to publish your local commits)\n",
+            name,
+            commits(ahead)
+        )),
+        Some((0, behind)) => out.push_str(&format!(
+            "Your branch is behind '{}' by {}, and can be fast-forwarded.\n  \
+             (use \"git pull\" to update your local branch)\n",
+            name,
+            commits(behind)
+        )),
+        Some((ahead, behind)) => out.push_str(&format!(
+            "Your branch and '{}' have diverged,\n\
+             and have {} and {} different commits each, respectively.\n  \
+             (use \"git pull\" to merge the remote branch into yours)\n",
+            name, ahead, behind
+        )),
+    }
+    out.push('\n');
+}
+
+/// The options `git status --porcelain` takes, for either version.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PorcelainOptions {
+    /// Start with the branch and how it compares with its upstream, as
+    /// `--branch` does.
+    pub branch: bool,
+    /// End each record with a NUL and leave paths unquoted, as `-z` does.
+    pub nul_terminated: bool,
+}
+
+/// What's reported of one path with changes in the index or working
+/// tree.
+enum Record<'a> {
+    Changed {
+        staged: Option<&'a FileDelta>,
+        unstaged: Option<&'a FileDelta>,
+    },
+    Unmerged(&'a UnmergedPath),
+}
+
+/// The paths with changes, in path order, a rename under its new name.
+fn records(status: &Status) -> BTreeMap<&str, Record<'_>> {
+    let mut records = BTreeMap::new();
+    for delta in &status.staged {
+        records.insert(
+            delta.path.as_str(),
+            Record::Changed {
+                staged: Some(delta),
+                unstaged: None,
+            },
+        );
+    }
+    for delta in &status.unstaged {
+        match records
+            .entry(delta.path.as_str())
+            .or_insert(Record::Changed {
+                staged: None,
+                unstaged: None,
+            }) {
+            Record::Changed { unstaged, .. } => *unstaged = Some(delta),
+            Record::Unmerged(_) => {}
+        }
+    }
+    for conflict in &status.unmerged {
+        records.insert(conflict.path.as_str(), Record::Unmerged(conflict));
+    }
+    records
+}
+
+/// The one-letter change a delta makes, or `unchanged` without one.
+fn letter(delta: Option<&FileDelta>, unchanged: char) -> char {
+    delta.map_or(unchanged, |delta| delta.status.letter())
+}
+
+/// `status` as `git status --porcelain` (version 1) shows it: each path
+/// with its index and working tree changes in two letters, staged first,
+/// then `??` for what's untracked and `!!` for what's ignored.
+pub fn format_porcelain(status: &Status, options: &PorcelainOptions) -> String {
+    let end = if options.nul_terminated { '\0' } else { '\n' };
+    let quote = |path: &str| {
+        if options.nul_terminated {
+            path.to_string()
+        } else {
+            quote_path_with_spaces(path)
+        }
+    };
+    let mut out = String::new();
+    if options.branch {
+        out.push_str("## ");
+        match &status.head {
+            Head::Branch(name, _) => out.push_str(refs::shorten(name)),
+            Head::Unborn(name) => {
+                out.push_str(&format!("No commits yet on {}", refs::shorten(name)))
+            }
+            Head::Detached(_) => out.push_str("HEAD (no branch)"),
+        }
+        if let Some(upstream) = &status.upstream {
+            out.push_str(&format!("...{}", refs::shorten(&upstream.name)));
+            match upstream.ahead_behind {
+                None => out.push_str(" [gone]"),
+                Some((0, 0)) => {}
+                Some((ahead, 0)) => out.push_str(&format!(" [ahead {}]", ahead)),
+                Some((0, behind)) => out.push_str(&format!(" [behind {}]", behind)),
+                Some((ahead, behind)) => {
+                    out.push_str(&format!(" [ahead {}, behind {}]", ahead, behind))
+                }
+            }
+        }
+        out.push(end);
+    }
+    for (path, record) in records(status) {
+        let (code, from) = match record {
+            Record::Changed { staged, unstaged } => (
+                format!("{}{}", letter(staged, ' '), letter(unstaged, ' ')),
+                staged.and_then(|delta| delta.from.as_deref()),
+            ),
+            Record::Unmerged(conflict) => (conflict.code().to_string(), None),
+        };
+        match from {
+            // With `-z` the new name comes first, as the paths in a
+            // rename are written everywhere else.
+            Some(from) if options.nul_terminated => {
+                out.push_str(&format!("{} {}\0{}\0", code, path, from))
+            }
+            Some(from) => out.push_str(&format!("{} {} -> {}\n", code, quote(from), quote(path))),
+            None => out.push_str(&format!("{} {}{}", code, quote(path), end)),
+        }
+    }
+    for path in &status.untracked {
+        out.push_str(&format!("?? {}{}", quote(path), end));
+    }
+    for path in &status.ignored {
+        out.push_str(&format!("!! {}{}", quote(path), end));
+    }
+    out
+}
+
+/// `status` as `git status --porcelain=v2` shows it, with each changed
+/// path's modes and ids in HEAD, the index and the working tree: `1` for
+/// an ordinary change, `2` for a rename or copy, `u` for a conflict, and
+/// `?` and `!` for untracked and ignored paths. Submodules aren't looked
+/// into, so their state is always `S...`.
+pub fn format_porcelain_v2(status: &Status, options: &PorcelainOptions) -> String {
+    let end = if options.nul_terminated { '\0' } else { '\n' };
+    let quote = |path: &str| {
+        if options.nul_terminated {
+            path.to_string()
+        } else {
+            quote_path(path)
+        }
+    };
+    let mode = |entry: Option<&FlatEntry>| format!("{:06o}", entry.map_or(0, |e| e.mode));
+    let id = |entry: Option<&FlatEntry>| entry.map_or(NULL_OID, |e| e.oid).to_string();
+    let submodule = |modes: &[u32]| {
+        if modes.contains(&MODE_GITLINK) {
+            "S..."
+        } else {
+            "N..."
+        }
+    };
+    let mut out = String::new();
+    if options.branch {
+        match &status.head {
+            Head::Branch(name, Some(id)) => out.push_str(&format!(
+                "# branch.oid {}{}# branch.head {}{}",
+                id,
+                end,
+                refs::shorten(name),
+                end
+            )),
+            Head::Branch(name, None) | Head::Unborn(name) => out.push_str(&format!(
+                "# branch.oid (initial){}# branch.head {}{}",
+                end,
+                refs::shorten(name),
+                end
+            )),
+            Head::Detached(id) => out.push_str(&format!(
+                "# branch.oid {}{}# branch.head (detached){}",
+                id, end, end
+            )),
+        }
+        if let Some(upstream) = &status.upstream {
+            out.push_str(&format!(
+                "# branch.upstream {}{}",
+                refs::shorten(&upstream.name),
+                end
+            ));
+            if let Some((ahead, behind)) = upstream.ahead_behind {
+                out.push_str(&format!("# branch.ab +{} -{}{}", ahead, behind, end));
+            }
+        }
+    }
+    for (path, record) in records(status) {
+        match record {
+            Record::Changed { staged, unstaged } => {
+                let code = format!("{}{}", letter(staged, '.'), letter(unstaged, '.'));
+                // Without a staged change the index has what HEAD has.
+                let (head, index) = match staged {
+                    Some(delta) => (delta.old.as_ref(), delta.new.as_ref()),
+                    None => {
+                        let index = unstaged.and_then(|delta| delta.old.as_ref());
+                        (index, index)
+                    }
+                };
+                let worktree = match unstaged {
+                    Some(delta) => delta.new.as_ref(),
+                    None => index,
+                };
+                let modes = [head, index, worktree].map(|e| e.map_or(0, |e| e.mode));
+                let fields = format!(
+                    "{} {} {} {} {} {} {}",
+                    code,
+                    submodule(&modes),
+                    mode(head),
+                    mode(index),
+                    mode(worktree),
+                    id(head),
+                    id(index)
+                );
+                let pairing = staged.and_then(|delta| match delta.status {
+                    DeltaStatus::Renamed { similarity } => Some(('R', similarity)),
+                    DeltaStatus::Copied { similarity } => Some(('C', similarity)),
+                    _ => None,
+                });
+                match (pairing, staged.and_then(|delta| delta.from.as_deref())) {
+                    (Some((kind, score)), Some(from)) => out.push_str(&format!(
+                        "2 {} {}{} {}{}{}{}",
+                        fields,
+                        kind,
+                        score,
+                        quote(path),
+                        if options.nul_terminated { '\0' } else { '\t' },
+                        quote(from),
+                        end
+                    )),
+                    _ => out.push_str(&format!("1 {} {}{}", fields, quote(path), end)),
+                }
+            }
+            Record::Unmerged(conflict) => {
+                let [base, ours, theirs] = conflict.stages.each_ref().map(Option::as_ref);
+                let modes = [base, ours, theirs].map(|e| e.map_or(0, |e| e.mode));
+                out.push_str(&format!(
+                    "u {} {} {} {} {} {:06o} {} {} {} {}{}",
+                    conflict.code(),
+                    submodule(&modes),
+                    mode(base),
+                    mode(ours),
+                    mode(theirs),
+                    conflict.worktree_mode,
+                    id(base),
+                    id(ours),
+                    id(theirs),
+                    quote(path),
+                    end
+                ));
+            }
+        }
+    }
+    for path in &status.untracked {
+        out.push_str(&format!("? {}{}", quote(path), end));
+    }
+    for path in &status.ignored {
+        out.push_str(&format!("! {}{}", quote(path), end));
+    }
+    out
+}
+
+/// A line of the staged or unstaged list.
+fn write_change(out: &mut String, delta: &FileDelta) {
+    let how = match delta.status {
+        DeltaStatus::Added => "new file:",
+        DeltaStatus::Deleted => "deleted:",
+        DeltaStatus::Modified => "modified:",
+        DeltaStatus::TypeChanged => "typechange:",
+        DeltaStatus::Renamed { .. } => "renamed:",
+        DeltaStatus::Copied { .. } => "copied:",
+    };
+    let path = match &delta.from {
+        Some(from) => format!("{} -> {}", quote_path(from), quote_path(&delta.path)),
+        None => quote_path(&delta.path),
+    };
+    out.push_str(&format!("\t{:<12}{}\n", how, path));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config::config_set;
+    use crate::core::index::IndexEntry;
+    use crate::core::object::{GitObject, MODE_FILE};
+    use crate::test_utils::{checkout, git, init_repo, set_ref, stage_file, write_commit};
+
+    fn paths(deltas: &[FileDelta]) -> Vec<(char, &str)> {
+        deltas
+            .iter()
+            .map(|d| (d.status.letter(), d.path.as_str()))
+            .collect()
+    }
+
+    /// `ours` after checking it against what `git status` says of the same
+    /// repository.
+    fn long_like_git(repo: &Repository, ours: &Status) -> String {
+        let ours = format_long(ours);
+        if let Some(theirs) = git(repo, &["status"]) {
+            assert_eq!(ours, theirs);
+        }
+        ours
+    }
+
+    #[test]
+    fn sorts_changes_into_staged_unstaged_and_untracked() {
+        let (_dir, repo) = init_repo();
+        let work_dir = repo.work_dir().unwrap();
+        let base = write_commit(
+            &repo,
+            &[],
+            &[("kept", "k\n"), ("gone", "g\n"), ("edited", "e\n")],
+            "base",
+        );
+        checkout(&repo, "master", &base);
+        // A new file, staged; a file staged then modified again; a file
+        // deleted and the deletion staged; and a file deleted but not.
+        stage_file(&repo, "new", "n\n");
+        stage_file(&repo, "edited", "e2\n");
+        fs::write(work_dir.join("edited"), "e3\n").unwrap();
+        let mut index = repo.read_index().unwrap();
+        index.remove("gone");
+        repo.write_index(&index).unwrap();
+        fs::remove_file(work_dir.join("gone")).unwrap();
+        fs::remove_file(work_dir.join("kept")).unwrap();
+        // An ignored file, an untracked one, and untracked directories
+        // with and without anything that isn't ignored.
+        fs::write(work_dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(work_dir.join("debug.log"), "x\n").unwrap();
+        fs::write(work_dir.join("untracked"), "u\n").unwrap();
+        fs::create_dir_all(work_dir.join("newdir/sub")).unwrap();
+        fs::write(work_dir.join("newdir/sub/f"), "f\n").unwrap();
+        fs::create_dir(work_dir.join("logs")).unwrap();
+        fs::write(work_dir.join("logs/a.log"), "a\n").unwrap();
+
+        let status = status(&repo).unwrap();
+        assert_eq!(
+            status.head,
+            Head::Branch("refs/heads/master".to_string(), Some(base))
+        );
+        assert_eq!(
+            paths(&status.staged),
+            [('M', "edited"), ('D', "gone"), ('A', "new")]
+        );
+        assert_eq!(paths(&status.unstaged), [('M', "edited"), ('D', "kept")]);
+        assert_eq!(status.untracked, [".gitignore", "newdir/", "untracked"]);
+        let all = StatusOptions {
+            untracked: UntrackedFiles::All,
+            ..StatusOptions::default()
+        };
+        let every = status_with(&repo, &all).unwrap();
+        assert_eq!(every.untracked, [".gitignore", "newdir/sub/f", "untracked"]);
+        assert!(long_like_git(&repo, &status).contains(
+            "Changes not staged for commit:\n  \
+             (use \"git add/rm <file>...\" to update what will be committed)\n"
+        ));
+    }
+
+    #[test]
+    fn reads_only_files_whose_stat_data_changed() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "same size\n")], "base");
+        checkout(&repo, "master", &base);
+        // Make the index newer than the file, so the entry isn't racy.
+        let index = repo.read_index().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        repo.write_index(&index).unwrap();
+        assert!(status(&repo).unwrap().is_clean());
+        assert_eq!(
+            long_like_git(&repo, &status(&repo).unwrap()),
+            "On branch master\nnothing to commit, working tree clean\n"
+        );
+
+        // An entry that records the content wrongly goes unnoticed while
+        // the stat data matches; once the file is touched it's re-read.
+        let mut index = repo.read_index().unwrap();
+        let mut entry = index.get("a", 0).unwrap().clone();
+        let stale = repo.odb().write(&GitObject::Blob(b"other text\n".to_vec()));
+        entry.oid = stale.unwrap();
+        index.add(entry);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        repo.write_index(&index).unwrap();
+        assert!(status(&repo).unwrap().unstaged.is_empty());
+        let mut touched = index.get("a", 0).unwrap().clone();
+        touched.mtime_nsec = touched.mtime_nsec.wrapping_add(1);
+        index.add(touched);
+        repo.write_index(&index).unwrap();
+        assert_eq!(paths(&status(&repo).unwrap().unstaged), [('M', "a")]);
+    }
+
+    #[test]
+    fn reports_conflicts_and_the_merge_in_progress() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("f", "base\n"), ("g", "g\n")], "base");
+        checkout(&repo, "master", &base);
+        let work_dir = repo.work_dir().unwrap();
+        let blob = |text: &str| {
+            repo.odb()
+                .write(&GitObject::Blob(text.as_bytes().to_vec()))
+                .unwrap()
+        };
+        let mut index = repo.read_index().unwrap();
+        index.remove("f");
+        for (stage, text) in [(1, "base\n"), (2, "ours\n"), (3, "theirs\n")] {
+            index.add(IndexEntry::new("f", blob(text), MODE_FILE).with_stage(stage));
+        }
+        // Deleted on their side, and added only on their side.
+        index.remove("g");
+        for stage in [1, 2] {
+            index.add(IndexEntry::new("g", blob("g\n"), MODE_FILE).with_stage(stage));
+        }
+        index.add(IndexEntry::new("only-theirs", blob("t\n"), MODE_FILE).with_stage(3));
+        repo.write_index(&index).unwrap();
+        fs::write(work_dir.join("f"), "<<<<<<< conflicted\n").unwrap();
+        fs::write(work_dir.join("only-theirs"), "t\n").unwrap();
+        fs::write(repo.git_dir().join("MERGE_HEAD"), format!("{}\n", base)).unwrap();
+
+        let status = status(&repo).unwrap();
+        assert_eq!(status.state, RepositoryState::Merge);
+        let conflicted: Vec<&str> = status.unmerged.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(conflicted, ["f", "g", "only-theirs"]);
+        assert!(status.staged.is_empty() && status.unstaged.is_empty());
+        assert_eq!(
+            long_like_git(&repo, &status),
+            "On branch master\nYou have unmerged paths.\n  \
+             (fix conflicts and run \"git commit\")\n  \
+             (use \"git merge --abort\" to abort the merge)\n\n\
+             Unmerged paths:\n  \
+             (use \"git add/rm <file>...\" as appropriate to mark resolution)\n\
+             \tboth modified:   f\n\
+             \tdeleted by them: g\n\
+             \tadded by them:   only-theirs\n\n\
+             no changes added to commit (use \"git add\" and/or \"git commit -a\")\n"
+        );
+    }
+
+    #[test]
+    fn describes_an_unborn_branch() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "first", "1\n");
+        assert_eq!(
+            long_like_git(&repo, &status(&repo).unwrap()),
+            "On branch master\n\nNo commits yet\n\n\
+             Changes to be committed:\n  (use \"git rm --cached <file>...\" to unstage)\n\
+             \tnew file:   first\n\n"
+        );
+    }
+
+    /// The porcelain `ours` in each format, each checked against what
+    /// `git status` prints with the same `args` plus the format's own.
+    fn porcelain_like_git(
+        repo: &Repository,
+        ours: &Status,
+        branch: bool,
+        args: &[&str],
+    ) -> [String; 4] {
+        let formats: [(&str, bool); 4] = [
+            ("--porcelain", false),
+            ("--porcelain", true),
+            ("--porcelain=v2", false),
+            ("--porcelain=v2", true),
+        ];
+        formats.map(|(format, nul_terminated)| {
+            let options = PorcelainOptions {
+                branch,
+                nul_terminated,
+            };
+            let text = match format {
+                "--porcelain" => format_porcelain(ours, &options),
+                _ => format_porcelain_v2(ours, &options),
+            };
+            let mut git_args = vec!["status", format];
+            git_args.extend(nul_terminated.then_some("-z"));
+            git_args.extend(branch.then_some("--branch"));
+            git_args.extend(args);
+            if let Some(theirs) = git(repo, &git_args) {
+                assert_eq!(text, theirs, "{:?}", git_args);
+            }
+            text
+        })
+    }
+
+    #[test]
+    fn writes_porcelain_codes_like_git() {
+        let (_dir, repo) = init_repo();
+        let work_dir = repo.work_dir().unwrap();
+        let lines = "one\ntwo\nthree\nfour\nfive\n";
+        let base = write_commit(
+            &repo,
+            &[],
+            &[
+                ("del", "d\n"),
+                ("exe", "x\n"),
+                ("mod", "m\n"),
+                ("ren", lines),
+                ("typ", "t\n"),
+            ],
+            "base",
+        );
+        checkout(&repo, "master", &base);
+        // Renamed in the index, then modified.
+        let mut index = repo.read_index().unwrap();
+        index.remove("ren");
+        repo.write_index(&index).unwrap();
+        fs::remove_file(work_dir.join("ren")).unwrap();
+        stage_file(&repo, "renamed", lines);
+        fs::write(work_dir.join("renamed"), format!("{}six\n", lines)).unwrap();
+        // Added, then deleted from the working tree.
+        stage_file(&repo, "added", "new\n");
+        fs::remove_file(work_dir.join("added")).unwrap();
+        // Modified in the index and again since.
+        stage_file(&repo, "mod", "m2\n");
+        fs::write(work_dir.join("mod"), "m3\n").unwrap();
+        // Deleted from the index but not the working tree.
+        let mut index = repo.read_index().unwrap();
+        index.remove("del");
+        repo.write_index(&index).unwrap();
+        // A symlink in place of a file, and a file made executable.
+        fs::remove_file(work_dir.join("typ")).unwrap();
+        std::os::unix::fs::symlink("mod", work_dir.join("typ")).unwrap();
+        let exe = work_dir.join("exe");
+        let mut permissions = fs::metadata(&exe).unwrap().permissions();
+        std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o755);
+        fs::set_permissions(&exe, permissions).unwrap();
+        // What's untracked and what's ignored, alone and side by side.
+        fs::create_dir_all(repo.git_dir().join("info")).unwrap();
+        fs::write(repo.git_dir().join("info/exclude"), "*.log\n").unwrap();
+        fs::write(work_dir.join("sp ace"), "u\n").unwrap();
+        fs::write(work_dir.join("x.log"), "i\n").unwrap();
+        for dir in ["ig", "nd", "tracked"] {
+            fs::create_dir(work_dir.join(dir)).unwrap();
+        }
+        fs::write(work_dir.join("ig/a.log"), "a\n").unwrap();
+        fs::write(work_dir.join("nd/f"), "f\n").unwrap();
+        fs::write(work_dir.join("nd/x.log"), "l\n").unwrap();
+        stage_file(&repo, "tracked/t", "t\n");
+        fs::write(work_dir.join("tracked/y.log"), "l\n").unwrap();
+
+        let plain = status(&repo).unwrap();
+        let [short, ..] = porcelain_like_git(&repo, &plain, false, &[]);
+        assert_eq!(
+            short,
+            "AD added\nD  del\n M exe\nMM mod\nRM ren -> renamed\n\
+             A  tracked/t\n T typ\n?? del\n?? nd/\n?? \"sp ace\"\n"
+        );
+        let ignored = StatusOptions {
+            ignored: true,
+            ..StatusOptions::default()
+        };
+        let with_ignored = status_with(&repo, &ignored).unwrap();
+        assert_eq!(
+            with_ignored.ignored,
+            ["ig/", "nd/x.log", "tracked/y.log", "x.log"]
+        );
+        let [_, _, v2, v2_z] = porcelain_like_git(&repo, &with_ignored, false, &["--ignored"]);
+        assert!(v2.contains(
+            "2 RM N... 100644 100644 100644 \
+             b2f931a67315c95c5daab3aac6de62e534808476 \
+             b2f931a67315c95c5daab3aac6de62e534808476 R100 renamed\tren\n"
+        ));
+        assert!(v2_z.contains(" R100 renamed\0ren\0"));
+        let every = StatusOptions {
+            untracked: UntrackedFiles::All,
+            ignored: true,
+        };
+        let all = status_with(&repo, &every).unwrap();
+        porcelain_like_git(&repo, &all, false, &["--ignored", "-uall"]);
+        long_like_git(&repo, &plain);
+    }
+
+    #[test]
+    fn writes_conflicts_and_branch_headers_like_git() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("f", "base\n"), ("g", "g\n")], "base");
+        let ahead = write_commit(&repo, &[base], &[("f", "base\n"), ("g", "g\n")], "ahead");
+        checkout(&repo, "master", &ahead);
+        // Without an upstream, then an upstream a commit behind, then one
+        // that's gone.
+        assert_eq!(
+            porcelain_like_git(&repo, &status(&repo).unwrap(), true, &[])[0],
+            "## master\n"
+        );
+        set_ref(&repo, "refs/remotes/origin/master", &base);
+        config_set(&repo, "remote.origin.url", "/nowhere").unwrap();
+        let fetch = "+refs/heads/*:refs/remotes/origin/*";
+        config_set(&repo, "remote.origin.fetch", fetch).unwrap();
+        config_set(&repo, "branch.master.remote", "origin").unwrap();
+        config_set(&repo, "branch.master.merge", "refs/heads/master").unwrap();
+        let tracking = status(&repo).unwrap();
+        let [short, _, v2, _] = porcelain_like_git(&repo, &tracking, true, &[]);
+        assert_eq!(short, "## master...origin/master [ahead 1]\n");
+        assert!(v2.ends_with("# branch.upstream origin/master\n# branch.ab +1 -0\n"));
+        assert!(long_like_git(&repo, &tracking)
+            .contains("Your branch is ahead of 'origin/master' by 1 commit.\n"));
+        config_set(&repo, "branch.master.merge", "refs/heads/gone").unwrap();
+        let gone = status(&repo).unwrap();
+        assert_eq!(
+            porcelain_like_git(&repo, &gone, true, &[])[0],
+            "## master...origin/gone [gone]\n"
+        );
+        long_like_git(&repo, &gone);
+
+        // Both modified, and deleted by them.
+        let blob = |text: &str| {
+            repo.odb()
+                .write(&GitObject::Blob(text.as_bytes().to_vec()))
+                .unwrap()
+        };
+        let mut index = repo.read_index().unwrap();
+        for path in ["f", "g"] {
+            index.remove(path);
+        }
+        for (stage, text) in [(1, "base\n"), (2, "ours\n"), (3, "theirs\n")] {
+            index.add(IndexEntry::new("f", blob(text), MODE_FILE).with_stage(stage));
+        }
+        for stage in [1, 2] {
+            index.add(IndexEntry::new("g", blob("g\n"), MODE_FILE).with_stage(stage));
+        }
+        repo.write_index(&index).unwrap();
+        fs::write(repo.git_dir().join("MERGE_HEAD"), format!("{}\n", base)).unwrap();
+        let [short, ..] = porcelain_like_git(&repo, &status(&repo).unwrap(), false, &[]);
+        assert_eq!(short, "UU f\nUD g\n");
+
+        refs::update_no_deref(&repo, "HEAD", ahead, None, "detach").unwrap();
+        let detached = status(&repo).unwrap();
+        assert!(format_porcelain(
+            &detached,
+            &PorcelainOptions {
+                branch: true,
+                ..PorcelainOptions::default()
+            }
+        )
+        .starts_with("## HEAD (no branch)\n"));
+        porcelain_like_git(&repo, &detached, true, &[]);
+    }
+}
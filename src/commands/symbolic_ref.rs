@@ -0,0 +1,78 @@
+use crate::core::refs::{self, RefTarget};
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// The ref that the symbolic ref `name` points at.
+pub fn read(repo: &Repository, name: &str) -> GitResult<String> {
+    match refs::resolve_symbolic(repo, name)? {
+        RefTarget::Symbolic(target) => Ok(target),
+        RefTarget::Direct(_) => Err(GitError::NotASymbolicRef(name.to_string())),
+    }
+}
+
+/// Like [`read`], but shortened the way `git symbolic-ref --short` does,
+/// e.g. `main` rather than `refs/heads/main`.
+pub fn read_short(repo: &Repository, name: &str) -> GitResult<String> {
+    read(repo, name).map(|target| refs::shorten(&target).to_string())
+}
+
+/// Point the symbolic ref `name` at `target`, which has to be a valid ref
+/// name under `refs/`. The target doesn't need to exist yet.
+pub fn write(
+    repo: &Repository,
+    name: &str,
+    target: &str,
+    reflog_msg: Option<&str>,
+) -> GitResult<()> {
+    if !target.starts_with("refs/") {
+        return Err(GitError::InvalidRefName {
+            name: target.to_string(),
+            reason: "it is not under refs/".to_string(),
+        });
+    }
+    refs::validate_name(target, false)?;
+    refs::update_symbolic(repo, name, target, reflog_msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Repository;
+    use crate::test_utils::write_commit;
+
+    #[test]
+    fn switches_the_default_branch_of_a_bare_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        assert_eq!(read(&repo, "HEAD").unwrap(), "refs/heads/master");
+
+        let develop = write_commit(&repo, &[], &[("a", "a")], "develop");
+        refs::update(&repo, "refs/heads/develop", develop, Some(None), "").unwrap();
+        write(&repo, "HEAD", "refs/heads/develop", Some("switch")).unwrap();
+
+        assert_eq!(read(&repo, "HEAD").unwrap(), "refs/heads/develop");
+        assert_eq!(read_short(&repo, "HEAD").unwrap(), "develop");
+        assert_eq!(refs::resolve(&repo, "HEAD").unwrap(), develop);
+        assert!(!repo.git_dir().join("HEAD.lock").exists());
+    }
+
+    #[test]
+    fn rejects_direct_refs_and_bad_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        let id = write_commit(&repo, &[], &[("a", "a")], "a");
+        refs::update(&repo, "refs/heads/master", id, None, "").unwrap();
+
+        assert!(matches!(
+            read(&repo, "refs/heads/master"),
+            Err(GitError::NotASymbolicRef(_))
+        ));
+        for target in &["master", "refs/heads/a..b", "refs/heads/"] {
+            assert!(matches!(
+                write(&repo, "HEAD", target, None),
+                Err(GitError::InvalidRefName { .. })
+            ));
+        }
+        assert_eq!(read(&repo, "HEAD").unwrap(), "refs/heads/master");
+    }
+}
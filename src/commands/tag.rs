@@ -0,0 +1,257 @@
+use crate::core::object::{GitObject, Tag};
+use crate::core::oid::Oid;
+use crate::core::refs;
+use crate::core::signature::Signature;
+use crate::core::signing;
+use crate::core::wildmatch::wildmatch;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// A tag, as `git tag -l` lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagInfo {
+    /// The short name, e.g. `v1.0` for `refs/tags/v1.0`.
+    pub name: String,
+    /// What the ref points at: the tag object for an annotated tag.
+    pub target: Oid,
+    /// For an annotated tag, the object it ultimately tags.
+    pub peeled: Option<Oid>,
+}
+
+fn full_name(name: &str) -> String {
+    format!("refs/tags/{}", name)
+}
+
+/// Point `refs/tags/<name>` at `id`, refusing to replace an existing tag
+/// unless `force` is set.
+fn write_tag_ref(repo: &Repository, name: &str, id: Oid, force: bool) -> GitResult<()> {
+    let refname = full_name(name);
+    if !force && refs::read(repo, &refname)?.is_some() {
+        return Err(GitError::RefExists(refname));
+    }
+    let expected = if force { None } else { Some(None) };
+    refs::update(repo, &refname, id, expected, "")
+}
+
+/// Create a lightweight tag: a ref straight to whatever `target` names.
+pub fn create_lightweight(
+    repo: &Repository,
+    name: &str,
+    target: &str,
+    force: bool,
+) -> GitResult<Oid> {
+    refs::validate_name(name, true)?;
+    let id = repo.resolve_rev(target)?;
+    write_tag_ref(repo, name, id, force)?;
+    Ok(id)
+}
+
+/// Create an annotated tag object for `target` and point the tag ref at
+/// it, returning the tag object's id. With `tag.gpgSign` set the tag is
+/// signed, as [`create_signed`] does.
+pub fn create_annotated(
+    repo: &Repository,
+    name: &str,
+    target: &str,
+    message: &str,
+    tagger: Signature,
+    force: bool,
+) -> GitResult<Oid> {
+    let sign = repo
+        .config()?
+        .get_bool("tag", None, "gpgsign")?
+        .unwrap_or(false);
+    write_annotated(repo, name, target, message, tagger, force, sign)
+}
+
+/// [`create_annotated`], always signing the tag as `git tag -s` does,
+/// with the program and key [`signing`] describes.
+pub fn create_signed(
+    repo: &Repository,
+    name: &str,
+    target: &str,
+    message: &str,
+    tagger: Signature,
+    force: bool,
+) -> GitResult<Oid> {
+    write_annotated(repo, name, target, message, tagger, force, true)
+}
+
+fn write_annotated(
+    repo: &Repository,
+    name: &str,
+    target: &str,
+    message: &str,
+    tagger: Signature,
+    force: bool,
+    sign: bool,
+) -> GitResult<Oid> {
+    refs::validate_name(name, true)?;
+    let object = repo.resolve_rev(target)?;
+    let (kind, _) = repo.odb().read_raw(&object)?;
+    let mut message = message.to_string();
+    if !message.ends_with('\n') {
+        message.push('\n');
+    }
+    let mut tag = Tag {
+        object,
+        kind,
+        name: name.to_string(),
+        tagger: Some(tagger),
+        extra_headers: Vec::new(),
+        message,
+    };
+    if sign {
+        signing::sign_tag(repo, &mut tag)?;
+    }
+    let id = repo.odb().write(&GitObject::Tag(tag))?;
+    write_tag_ref(repo, name, id, force)?;
+    Ok(id)
+}
+
+/// Every tag whose name matches the glob `pattern`, sorted by name.
+pub fn list(repo: &Repository, pattern: Option<&str>) -> GitResult<Vec<TagInfo>> {
+    let mut tags = Vec::new();
+    for reference in refs::iter_prefixed(repo, "refs/tags/")? {
+        let reference = reference?;
+        let name = &reference.name["refs/tags/".len()..];
+        if let Some(pattern) = pattern {
+            if !wildmatch(pattern.as_bytes(), name.as_bytes(), false) {
+                continue;
+            }
+        }
+        // packed-refs may already know the peeled value.
+        let peeled = match reference.peeled() {
+            Some(peeled) => Some(peeled),
+            None => match repo.odb().peel(&reference.target)? {
+                (peeled, _) if peeled != reference.target => Some(peeled),
+                _ => None,
+            },
+        };
+        tags.push(TagInfo {
+            name: name.to_string(),
+            target: reference.target,
+            peeled,
+        });
+    }
+    Ok(tags)
+}
+
+pub fn delete(repo: &Repository, name: &str) -> GitResult<()> {
+    refs::delete(repo, &full_name(name), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config::config_set;
+
+    use crate::test_utils::{fake_gpg, git, init_repo, signature, write_commit};
+
+    #[test]
+    fn creates_lists_and_deletes_tags() {
+        let (_dir, repo) = init_repo();
+        let first = write_commit(&repo, &[], &[("a", "a")], "first");
+        repo.set_head_commit(&first, "").unwrap();
+
+        create_lightweight(&repo, "v1.0", "HEAD", false).unwrap();
+        let annotated =
+            create_annotated(&repo, "v1.1", "HEAD", "Release 1.1", signature(), false).unwrap();
+        create_lightweight(&repo, "other", "HEAD", false).unwrap();
+        assert!(matches!(
+            create_lightweight(&repo, "v1.0", "HEAD", false),
+            Err(GitError::RefExists(_))
+        ));
+        assert!(matches!(
+            create_lightweight(&repo, "bad name", "HEAD", false),
+            Err(GitError::InvalidRefName { .. })
+        ));
+
+        let tag = repo.odb().read_tag(&annotated).unwrap();
+        assert_eq!(tag.object, first);
+        assert_eq!(tag.message, "Release 1.1\n");
+
+        assert_eq!(
+            list(&repo, Some("v1.*")).unwrap(),
+            vec![
+                TagInfo {
+                    name: "v1.0".to_string(),
+                    target: first,
+                    peeled: None,
+                },
+                TagInfo {
+                    name: "v1.1".to_string(),
+                    target: annotated,
+                    peeled: Some(first),
+                },
+            ]
+        );
+
+        delete(&repo, "v1.0").unwrap();
+        let names: Vec<String> = list(&repo, None)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(names, vec!["other", "v1.1"]);
+    }
+
+    #[test]
+    fn git_reads_our_tags() {
+        let (_dir, repo) = init_repo();
+        let first = write_commit(&repo, &[], &[("a", "a")], "first");
+        repo.set_head_commit(&first, "").unwrap();
+        create_lightweight(&repo, "light", "HEAD", false).unwrap();
+        let annotated =
+            create_annotated(&repo, "heavy", "HEAD", "A heavy tag", signature(), false).unwrap();
+
+        let output = match git(
+            &repo,
+            &[
+                "for-each-ref",
+                "--format=%(refname) %(objecttype) %(objectname) %(*objectname)",
+                "refs/tags",
+            ],
+        ) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            format!(
+                "refs/tags/heavy tag {} {}\nrefs/tags/light commit {} \n",
+                annotated, first, first
+            )
+        );
+        let verified = git(&repo, &["cat-file", "-t", "heavy"]).unwrap();
+        assert_eq!(verified, "tag\n");
+    }
+
+    #[test]
+    fn signs_annotated_tags() {
+        let (dir, repo) = init_repo();
+        let gpg = fake_gpg(dir.path());
+        config_set(&repo, "gpg.program", gpg.to_str().unwrap()).unwrap();
+        let first = write_commit(&repo, &[], &[("a", "a")], "first");
+        repo.set_head_commit(&first, "").unwrap();
+
+        let signed = create_signed(&repo, "v1.0", "HEAD", "Release\n", signature(), false).unwrap();
+        let tag = repo.odb().read_tag(&signed).unwrap();
+        assert!(tag
+            .message
+            .starts_with("Release\n-----BEGIN PGP SIGNATURE-----\n"));
+        git(&repo, &["verify-tag", "v1.0"]);
+
+        let plain = create_annotated(&repo, "v1.1", "HEAD", "Plain\n", signature(), false).unwrap();
+        assert_eq!(repo.odb().read_tag(&plain).unwrap().message, "Plain\n");
+        config_set(&repo, "tag.gpgSign", "true").unwrap();
+        let by_config =
+            create_annotated(&repo, "v1.2", "HEAD", "Signed\n", signature(), false).unwrap();
+        assert!(repo
+            .odb()
+            .read_tag(&by_config)
+            .unwrap()
+            .message
+            .contains("-----END PGP SIGNATURE-----\n"));
+    }
+}
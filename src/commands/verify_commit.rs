@@ -0,0 +1,72 @@
+//! `git verify-commit`.
+
+use crate::core::oid::Oid;
+use crate::core::signing::{self, SignatureStatus, Signer};
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// Check the signature on the commit `id` with the configured verifier,
+/// the same program [`Signer`] signs with. `None` when it isn't signed.
+pub fn verify_commit(repo: &Repository, id: &Oid) -> GitResult<Option<SignatureStatus>> {
+    let commit = repo.odb().read_commit(id)?;
+    let (payload, signature) = match signing::commit_signature(&commit) {
+        Some(signed) => signed,
+        None => return Ok(None),
+    };
+    let signer = Signer::from_config(&repo.config()?)?;
+    signer.verify(repo, &payload, &signature).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::commit::{commit, CommitOptions};
+    use crate::commands::config::config_set;
+    use crate::core::object::GitObject;
+
+    use crate::test_utils::{fake_gpg, git, init_repo, stage_file};
+
+    #[test]
+    fn verifies_signed_commits() {
+        let (dir, repo) = init_repo();
+        let gpg = fake_gpg(dir.path());
+        config_set(&repo, "gpg.program", gpg.to_str().unwrap()).unwrap();
+        stage_file(&repo, "a.txt", "a\n");
+        let unsigned = commit(&repo, Some("unsigned"), &CommitOptions::default()).unwrap();
+        assert_eq!(verify_commit(&repo, &unsigned).unwrap(), None);
+
+        stage_file(&repo, "a.txt", "b\n");
+        let sign = CommitOptions {
+            sign: true,
+            ..CommitOptions::default()
+        };
+        let signed = commit(&repo, Some("signed"), &sign).unwrap();
+        let odb = repo.odb();
+        let object = odb.read_commit(&signed).unwrap();
+        let (key, value) = &object.extra_headers[0];
+        assert_eq!(key, "gpgsig");
+        assert!(value.starts_with("-----BEGIN PGP SIGNATURE-----\nComment: "));
+        assert!(value.ends_with("\n-----END PGP SIGNATURE-----"));
+        assert_eq!(
+            verify_commit(&repo, &signed).unwrap(),
+            Some(SignatureStatus::Good)
+        );
+        git(&repo, &["verify-commit", &signed.to_string()]);
+
+        let mut tampered = object;
+        tampered.message = "not what was signed\n".to_string();
+        let tampered = odb.write(&GitObject::Commit(tampered)).unwrap();
+        assert_eq!(
+            verify_commit(&repo, &tampered).unwrap(),
+            Some(SignatureStatus::Bad)
+        );
+
+        config_set(&repo, "commit.gpgSign", "true").unwrap();
+        stage_file(&repo, "a.txt", "c\n");
+        let by_config = commit(&repo, Some("by config"), &CommitOptions::default()).unwrap();
+        assert_eq!(
+            verify_commit(&repo, &by_config).unwrap(),
+            Some(SignatureStatus::Good)
+        );
+    }
+}
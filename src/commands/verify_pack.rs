@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::core::object::{hash_object, ObjectType};
+use crate::core::oid::Oid;
+use crate::core::pack::{self, EntryKind, Pack};
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// One entry of a verified pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackObjectInfo {
+    pub id: Oid,
+    pub kind: ObjectType,
+    /// The size of the entry's data once inflated: the object itself, or
+    /// for a delta the delta, as git reports it.
+    pub size: usize,
+    /// How many bytes the entry takes up in the pack.
+    pub packed_size: u64,
+    pub offset: u64,
+    /// How many deltas have to be applied to rebuild the object.
+    pub depth: usize,
+    /// The object a delta applies to.
+    pub base: Option<Oid>,
+}
+
+/// Formats like a line of `git verify-pack -v`.
+impl fmt::Display for PackObjectInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:<6} {} {} {}",
+            self.id,
+            self.kind.as_str(),
+            self.size,
+            self.packed_size,
+            self.offset
+        )?;
+        if let Some(base) = &self.base {
+            write!(f, " {} {}", self.depth, base)?;
+        }
+        Ok(())
+    }
+}
+
+/// `git verify-pack`: check every object in the pack that `idx_path`
+/// indexes. Each entry's raw bytes must match the CRC32 in the index and
+/// the object they rebuild to must hash to its id. A relative path is
+/// looked up in the repository's `objects/pack`. Returns the objects in
+/// pack order.
+pub fn verify_pack(repo: &Repository, idx_path: &Path) -> GitResult<Vec<PackObjectInfo>> {
+    let idx_path = repo.git_dir().join("objects/pack").join(idx_path);
+    let pack = Pack::open(&idx_path)?;
+    let index = &pack.index;
+
+    let mut by_offset: Vec<(u64, usize)> = (0..index.len())
+        .map(|position| (index.offset(position), position))
+        .collect();
+    by_offset.sort_unstable();
+    let ids_at: HashMap<u64, Oid> = by_offset
+        .iter()
+        .map(|&(offset, position)| (offset, index.ids()[position]))
+        .collect();
+
+    let mut cache = HashMap::new();
+    let mut objects = Vec::with_capacity(by_offset.len());
+    for (i, &(offset, position)) in by_offset.iter().enumerate() {
+        let id = index.ids()[position];
+        let hex = id.to_string();
+        let end = by_offset
+            .get(i + 1)
+            .map_or(pack.file.entries_end(), |&(next, _)| next);
+        if pack::crc32(pack.file.raw(offset, end)?) != index.crc32(position) {
+            return Err(GitError::Corrupt(format!(
+                "CRC mismatch for object {}",
+                hex
+            )));
+        }
+        let header = pack.file.entry_header(offset)?;
+        let base = match header.kind {
+            EntryKind::Object(_) => None,
+            EntryKind::OfsDelta(base) => Some(*ids_at.get(&base).ok_or_else(|| {
+                GitError::Corrupt(format!("delta base of {} is not an indexed entry", hex))
+            })?),
+            EntryKind::RefDelta(base) => Some(base),
+        };
+        let object = pack
+            .read_at(offset, &mut cache)
+            .map_err(|err| GitError::Corrupt(format!("cannot read object {}: {}", hex, err)))?;
+        if hash_object(object.kind, &object.data) != id {
+            return Err(GitError::Corrupt(format!(
+                "SHA-1 mismatch for object {}",
+                hex
+            )));
+        }
+        objects.push(PackObjectInfo {
+            id,
+            kind: object.kind,
+            size: header.size,
+            packed_size: end - offset,
+            offset,
+            depth: object.depth,
+            base,
+        });
+    }
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_repo;
+    use sha1::{Digest, Sha1};
+    use std::fs;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    const OBJECTS: &str = "\
+75c2d88426fe17e34cd7f0422b03b01115515ee8 commit 221 160 12
+6bd9898988ad7f8c768df43fb94e74a92f8f06c8 tag    141 131 172
+633e9bcabc47434c0e80f9bcdd3759e89a0095e7 commit 221 160 303
+007b2748fe95334981c7bdcd38a35bc560da1006 commit 221 160 463
+d51f0ae49c9b807db81d150d461a52e191221ecd commit 173 130 623
+c3398e3db8261574df2149c22ba56d89f9567076 tree   36 47 753
+014301c8f68cc780c08e96e11493716ee1d9ef0e tree   36 47 800
+9b820788aa060499eb97473ce13fbb2a943f753d tree   36 47 847
+32578bac4a4e9ee4479b3798a9758e9573205385 tree   36 47 894
+8b1790349890681f4fb68e0c3711fa20b0709b3e blob   5576 425 941
+";
+
+    fn lines(objects: &[PackObjectInfo]) -> String {
+        objects.iter().map(|o| format!("{}\n", o)).collect()
+    }
+
+    #[test]
+    fn reports_objects_like_git() {
+        let (_dir, repo) = init_repo();
+        let ofs = verify_pack(&repo, &Path::new(FIXTURES).join("ofs-delta.idx")).unwrap();
+        let expected = format!(
+            "{}{}{}{}",
+            OBJECTS,
+            "e9ff041dfb09a2a6f5a4a3d1a570e0be8ddbd122 blob   37 50 1366 1 8b1790349890681f4fb68e0c3711fa20b0709b3e\n",
+            "ea16f20a5bb2b8fda0c758617128be1d62b0c8ce blob   38 49 1416 2 e9ff041dfb09a2a6f5a4a3d1a570e0be8ddbd122\n",
+            "31c5e7d6d113c9a3db885d34f7be27a598a8cb0a blob   38 49 1465 3 ea16f20a5bb2b8fda0c758617128be1d62b0c8ce\n",
+        );
+        assert_eq!(lines(&ofs), expected);
+
+        // The same objects with their bases named by id.
+        let by_ref = verify_pack(&repo, &Path::new(FIXTURES).join("ref-delta.idx")).unwrap();
+        assert_eq!(lines(&by_ref[..10]), OBJECTS);
+        for (ours, theirs) in by_ref[10..].iter().zip(&ofs[10..]) {
+            assert_eq!(
+                (ours.id, ours.depth, ours.base),
+                (theirs.id, theirs.depth, theirs.base)
+            );
+        }
+        let offsets: Vec<_> = by_ref[10..]
+            .iter()
+            .map(|o| (o.offset, o.packed_size))
+            .collect();
+        assert_eq!(offsets, [(1366, 68), (1434, 68), (1502, 68)]);
+    }
+
+    /// Recompute the SHA-1 trailing `data`, returning it.
+    fn rehash(data: &mut [u8]) -> Oid {
+        let body = data.len() - 20;
+        let checksum: [u8; 20] = Sha1::digest(&data[..body]).into();
+        data[body..].copy_from_slice(&checksum);
+        Oid::from_raw(checksum)
+    }
+
+    #[test]
+    fn names_the_object_with_a_bad_entry() {
+        let (_dir, repo) = init_repo();
+        let pack_dir = repo.git_dir().join("objects/pack");
+        fs::create_dir_all(&pack_dir).unwrap();
+        let mut data = fs::read(Path::new(FIXTURES).join("ofs-delta.pack")).unwrap();
+        fs::copy(
+            Path::new(FIXTURES).join("ofs-delta.idx"),
+            pack_dir.join("pack-test.idx"),
+        )
+        .unwrap();
+        fs::write(pack_dir.join("pack-test.pack"), &data).unwrap();
+        assert_eq!(
+            verify_pack(&repo, Path::new("pack-test.idx"))
+                .unwrap()
+                .len(),
+            13
+        );
+
+        // Flip a byte inside the second commit, then fix up the checksums
+        // so that only the entry is wrong.
+        data[320] ^= 0xff;
+        let checksum = rehash(&mut data);
+        let mut idx = fs::read(pack_dir.join("pack-test.idx")).unwrap();
+        let at = idx.len() - 40;
+        idx[at..at + 20].copy_from_slice(checksum.as_bytes());
+        rehash(&mut idx);
+        fs::write(pack_dir.join("pack-test.pack"), &data).unwrap();
+        fs::write(pack_dir.join("pack-test.idx"), &idx).unwrap();
+        match verify_pack(&repo, Path::new("pack-test.idx")) {
+            Err(GitError::Corrupt(message)) => assert_eq!(
+                message,
+                "CRC mismatch for object 633e9bcabc47434c0e80f9bcdd3759e89a0095e7"
+            ),
+            other => panic!("expected a CRC mismatch, got {:?}", other),
+        }
+    }
+}
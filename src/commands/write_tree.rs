@@ -0,0 +1,91 @@
+//! `git write-tree`.
+
+use std::path::PathBuf;
+
+use crate::core::index::Index;
+use crate::core::odb::{LooseObjectWriter, ObjectWriter};
+use crate::core::oid::Oid;
+use crate::core::tree;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// Write the tree the index describes, and the subtrees under it, and
+/// return its id. Only what's staged goes in; the working tree isn't
+/// looked at.
+pub fn write_tree_from_index(repo: &Repository) -> GitResult<Oid> {
+    write_tree_with(repo, &mut LooseObjectWriter::new(repo.odb()))
+}
+
+/// [`write_tree_from_index`], handing the trees to `writer`; with a
+/// [`NullObjectWriter`](crate::core::odb::NullObjectWriter) nothing is
+/// stored and only the id is worked out.
+pub fn write_tree_with(repo: &Repository, writer: &mut dyn ObjectWriter) -> GitResult<Oid> {
+    write_index_tree(&repo.read_index()?, writer)
+}
+
+/// Hand the trees for `index` to `writer`, refusing while any path is
+/// conflicted since there's no one blob to put in the tree for it.
+pub(crate) fn write_index_tree(index: &Index, writer: &mut dyn ObjectWriter) -> GitResult<Oid> {
+    let conflicts = index.conflicts();
+    if !conflicts.is_empty() {
+        return Err(GitError::UnresolvedConflicts(
+            conflicts
+                .into_iter()
+                .map(|c| PathBuf::from(c.path))
+                .collect(),
+        ));
+    }
+    tree::build(writer, &tree::from_index(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::index::IndexEntry;
+    use crate::core::object::MODE_FILE;
+    use crate::core::odb::NullObjectWriter;
+
+    use crate::test_utils::{git, init_repo, stage_file};
+
+    #[test]
+    fn writes_only_what_is_staged() {
+        let (_dir, repo) = init_repo();
+        for (path, content) in [
+            ("top.txt", "top\n"),
+            ("src/main.rs", "fn main() {}\n"),
+            ("src/bin/tool.rs", "tool\n"),
+            ("src.txt", "not the dir\n"),
+            ("docs/a/b/c.md", "deep\n"),
+        ] {
+            stage_file(&repo, path, content);
+        }
+        std::fs::write(repo.work_dir().unwrap().join("top.txt"), "unstaged\n").unwrap();
+        std::fs::write(repo.work_dir().unwrap().join("untracked"), "new\n").unwrap();
+
+        let mut null = NullObjectWriter::default();
+        let preview = write_tree_with(&repo, &mut null).unwrap();
+        // The top, src, src/bin, docs, docs/a and docs/a/b.
+        assert_eq!(null.count, 6);
+        assert!(!repo.odb().contains(&preview));
+        let tree = write_tree_from_index(&repo).unwrap();
+        assert_eq!(tree, preview);
+        assert!(repo.odb().contains(&tree));
+        if let Some(theirs) = git(&repo, &["write-tree"]) {
+            assert_eq!(tree.to_string(), theirs.trim_end());
+        }
+    }
+
+    #[test]
+    fn refuses_conflicted_indexes() {
+        let (_dir, repo) = init_repo();
+        stage_file(&repo, "a", "a\n");
+        let mut index = repo.read_index().unwrap();
+        index.add(IndexEntry::new("b", Oid::from_raw([1; 20]), MODE_FILE).with_stage(2));
+        index.add(IndexEntry::new("b", Oid::from_raw([2; 20]), MODE_FILE).with_stage(3));
+        repo.write_index(&index).unwrap();
+        assert!(matches!(
+            write_tree_from_index(&repo),
+            Err(GitError::UnresolvedConflicts(paths)) if paths == [PathBuf::from("b")]
+        ));
+    }
+}
@@ -0,0 +1,504 @@
+//! Commit-graph files, which `git commit-graph write` and `git gc` leave
+//! in `objects/info/commit-graph`, or as a chain of layers listed in
+//! `objects/info/commit-graphs/commit-graph-chain`.
+//!
+//! A graph lists commits by id with their root tree, parents, generation
+//! number and committer date, so a history walk can read those without
+//! inflating and parsing each commit object. The file is a `CGPH` header,
+//! a table of chunks and the chunks themselves:
+//!
+//! - `OIDF`, a fanout table counting the ids up to each first byte
+//! - `OIDL`, the sorted ids
+//! - `CDAT`, each commit's tree, first two parents as positions in the
+//!   graph, generation number and date
+//! - `EDGE`, the third and later parents of octopus merges
+//!
+//! Other chunks, such as the corrected commit dates of newer graphs, are
+//! skipped. In a chain each layer's positions carry on from the layers
+//! below it, so a parent can live in an earlier layer. Like git, the
+//! trailing checksum isn't checked on read. [`serialize`] writes a single
+//! layer with just the chunks above.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use sha1::{Digest, Sha1};
+
+use crate::core::oid::Oid;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+const SIGNATURE: &[u8; 4] = b"CGPH";
+const CHUNK_OIDF: u32 = u32::from_be_bytes(*b"OIDF");
+const CHUNK_OIDL: u32 = u32::from_be_bytes(*b"OIDL");
+const CHUNK_CDAT: u32 = u32::from_be_bytes(*b"CDAT");
+const CHUNK_EDGE: u32 = u32::from_be_bytes(*b"EDGE");
+const HEADER_LEN: usize = 8;
+const CHUNK_ENTRY_LEN: usize = 12;
+/// A `CDAT` entry: tree, two parents and the generation and date.
+const COMMIT_DATA_LEN: usize = 36;
+const PARENT_NONE: u32 = 0x7000_0000;
+/// Set on the second parent when the parents after the first are in
+/// `EDGE`, and on the last of them there.
+const EXTRA_EDGES: u32 = 0x8000_0000;
+/// The highest generation number the format has room for; deeper commits
+/// are all given this one.
+pub const GENERATION_MAX: u32 = 0x3fff_ffff;
+
+fn corrupt(what: impl Into<String>) -> GitError {
+    GitError::Corrupt(what.into())
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().expect("four bytes"))
+}
+
+fn read_id(data: &[u8], at: usize) -> Oid {
+    data[at..at + 20].try_into().expect("twenty bytes")
+}
+
+/// Where a commit sits in a graph, counting across every layer from the
+/// base up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GraphPosition(pub u32);
+
+/// What a graph records about a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphCommit {
+    pub tree: Oid,
+    pub parents: Vec<Oid>,
+    /// One more than the highest generation among the parents, starting
+    /// from 1 for a root commit.
+    pub generation: u32,
+    /// The committer date, in seconds since the epoch.
+    pub time: i64,
+}
+
+/// One commit-graph file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Layer {
+    fanout: Vec<u32>,
+    ids: Vec<Oid>,
+    /// The `CDAT` chunk.
+    commit_data: Vec<u8>,
+    /// The `EDGE` chunk, empty without octopus merges.
+    edges: Vec<u32>,
+}
+
+impl Layer {
+    fn parse(data: &[u8]) -> GitResult<Layer> {
+        if data.len() < HEADER_LEN + 20 || &data[..4] != SIGNATURE {
+            return Err(corrupt("not a commit-graph file"));
+        }
+        if data[4] != 1 {
+            return Err(corrupt(format!(
+                "unsupported commit-graph version {}",
+                data[4]
+            )));
+        }
+        if data[5] != 1 {
+            return Err(corrupt(format!(
+                "unsupported commit-graph hash version {}",
+                data[5]
+            )));
+        }
+        let chunk_count = usize::from(data[6]);
+        let table_end = HEADER_LEN + (chunk_count + 1) * CHUNK_ENTRY_LEN;
+        if data.len() < table_end + 20 {
+            return Err(corrupt("commit-graph chunk table is truncated"));
+        }
+        let mut chunks = Vec::with_capacity(chunk_count);
+        for i in 0..chunk_count {
+            let at = HEADER_LEN + i * CHUNK_ENTRY_LEN;
+            let start = u64::from_be_bytes(data[at + 4..at + 12].try_into().expect("eight bytes"));
+            let end = u64::from_be_bytes(data[at + 16..at + 24].try_into().expect("eight bytes"));
+            if start > end || end > (data.len() - 20) as u64 {
+                return Err(corrupt("commit-graph chunk out of bounds"));
+            }
+            chunks.push((read_u32(data, at), start as usize..end as usize));
+        }
+        let chunk = |id: u32| {
+            chunks
+                .iter()
+                .find(|(chunk, _)| *chunk == id)
+                .map(|(_, range)| &data[range.clone()])
+        };
+        let missing = |name: &str| corrupt(format!("commit-graph has no {} chunk", name));
+
+        let oidf = chunk(CHUNK_OIDF).ok_or_else(|| missing("OIDF"))?;
+        if oidf.len() != 256 * 4 {
+            return Err(corrupt("commit-graph fanout has the wrong size"));
+        }
+        let fanout: Vec<u32> = (0..256).map(|i| read_u32(oidf, i * 4)).collect();
+        if fanout.windows(2).any(|w| w[0] > w[1]) {
+            return Err(corrupt("commit-graph fanout is not sorted"));
+        }
+        let count = fanout[255] as usize;
+        let oidl = chunk(CHUNK_OIDL).ok_or_else(|| missing("OIDL"))?;
+        let cdat = chunk(CHUNK_CDAT).ok_or_else(|| missing("CDAT"))?;
+        if oidl.len() != count * 20 || cdat.len() != count * COMMIT_DATA_LEN {
+            return Err(corrupt("commit-graph chunks disagree on the commit count"));
+        }
+        let ids = (0..count).map(|i| read_id(oidl, i * 20)).collect();
+        let edges = chunk(CHUNK_EDGE)
+            .map(|edge| (0..edge.len() / 4).map(|i| read_u32(edge, i * 4)).collect())
+            .unwrap_or_default();
+        Ok(Layer {
+            fanout,
+            ids,
+            commit_data: cdat.to_vec(),
+            edges,
+        })
+    }
+
+    fn find(&self, id: &Oid) -> Option<usize> {
+        let first = usize::from(id.as_bytes()[0]);
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1] as usize
+        };
+        let end = self.fanout[first] as usize;
+        self.ids[start..end]
+            .binary_search(id)
+            .ok()
+            .map(|found| start + found)
+    }
+}
+
+/// A commit-graph, made of one or more layers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitGraph {
+    layers: Vec<Layer>,
+}
+
+impl CommitGraph {
+    /// The repository's commit-graph, if it has one: the single file, or
+    /// failing that the chain.
+    pub fn open(repo: &Repository) -> GitResult<Option<CommitGraph>> {
+        CommitGraph::open_in(&repo.odb().objects_dir().join("info"))
+    }
+
+    /// The commit-graph kept in `info_dir`, an `objects/info` directory.
+    pub fn open_in(info_dir: &Path) -> GitResult<Option<CommitGraph>> {
+        match fs::read(info_dir.join("commit-graph")) {
+            Ok(data) => return CommitGraph::parse(&data).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        let graphs = info_dir.join("commit-graphs");
+        let chain = match fs::read_to_string(graphs.join("commit-graph-chain")) {
+            Ok(chain) => chain,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut layers = Vec::new();
+        for hash in chain.lines().filter(|line| !line.is_empty()) {
+            let data = fs::read(graphs.join(format!("graph-{}.graph", hash)))?;
+            layers.push(Layer::parse(&data)?);
+        }
+        Ok(Some(CommitGraph { layers }))
+    }
+
+    /// Parse a single commit-graph file.
+    pub fn parse(data: &[u8]) -> GitResult<CommitGraph> {
+        Ok(CommitGraph {
+            layers: vec![Layer::parse(data)?],
+        })
+    }
+
+    /// How many commits the graph has.
+    pub fn len(&self) -> usize {
+        self.layers.iter().map(|layer| layer.ids.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Where `id` is in the graph, if it's there.
+    pub fn lookup(&self, id: &Oid) -> Option<GraphPosition> {
+        let mut base = 0;
+        for layer in &self.layers {
+            if let Some(found) = layer.find(id) {
+                return Some(GraphPosition((base + found) as u32));
+            }
+            base += layer.ids.len();
+        }
+        None
+    }
+
+    /// The layer holding `position` and the position within it.
+    fn layer(&self, position: GraphPosition) -> GitResult<(&Layer, usize)> {
+        let mut local = position.0 as usize;
+        for layer in &self.layers {
+            if local < layer.ids.len() {
+                return Ok((layer, local));
+            }
+            local -= layer.ids.len();
+        }
+        Err(corrupt(format!(
+            "commit-graph position {} out of range",
+            position.0
+        )))
+    }
+
+    /// The id of the commit at `position`.
+    pub fn id(&self, position: GraphPosition) -> GitResult<Oid> {
+        let (layer, local) = self.layer(position)?;
+        Ok(layer.ids[local])
+    }
+
+    /// Everything the graph has on the commit at `position`.
+    pub fn commit(&self, position: GraphPosition) -> GitResult<GraphCommit> {
+        let (layer, local) = self.layer(position)?;
+        let data = &layer.commit_data[local * COMMIT_DATA_LEN..(local + 1) * COMMIT_DATA_LEN];
+        let mut parents = Vec::new();
+        let first = read_u32(data, 20);
+        if first != PARENT_NONE {
+            parents.push(self.id(GraphPosition(first))?);
+        }
+        let second = read_u32(data, 24);
+        if second & EXTRA_EDGES == 0 {
+            if second != PARENT_NONE {
+                parents.push(self.id(GraphPosition(second))?);
+            }
+        } else {
+            let mut edge = (second & !EXTRA_EDGES) as usize;
+            loop {
+                let entry = *layer
+                    .edges
+                    .get(edge)
+                    .ok_or_else(|| corrupt("commit-graph edge out of range"))?;
+                parents.push(self.id(GraphPosition(entry & !EXTRA_EDGES))?);
+                if entry & EXTRA_EDGES != 0 {
+                    break;
+                }
+                edge += 1;
+            }
+        }
+        let high = read_u32(data, 28);
+        Ok(GraphCommit {
+            tree: read_id(data, 0),
+            parents,
+            generation: high >> 2,
+            time: (i64::from(high & 3) << 32) | i64::from(read_u32(data, 32)),
+        })
+    }
+
+    /// Everything the graph has on `id`, if it has the commit.
+    pub fn get(&self, id: &Oid) -> GitResult<Option<GraphCommit>> {
+        self.lookup(id)
+            .map(|position| self.commit(position))
+            .transpose()
+    }
+}
+
+/// Lay out a single-layer commit-graph file for `commits`, which must
+/// include every parent of each, trailing checksum and all. Generation
+/// numbers and dates are written as given, so the generations should
+/// already be worked out.
+pub fn serialize(commits: &BTreeMap<Oid, GraphCommit>) -> GitResult<Vec<u8>> {
+    let positions: BTreeMap<&Oid, u32> = commits
+        .keys()
+        .enumerate()
+        .map(|(position, id)| (id, position as u32))
+        .collect();
+    let position = |id: &Oid| {
+        positions
+            .get(id)
+            .copied()
+            .ok_or_else(|| corrupt(format!("commit-graph is missing parent {}", id)))
+    };
+
+    let mut fanout = vec![0u32; 256];
+    for id in commits.keys() {
+        fanout[usize::from(id.as_bytes()[0])] += 1;
+    }
+    for i in 1..256 {
+        fanout[i] += fanout[i - 1];
+    }
+    let mut oidf = Vec::with_capacity(256 * 4);
+    for count in fanout {
+        oidf.extend_from_slice(&count.to_be_bytes());
+    }
+    let mut oidl = Vec::with_capacity(commits.len() * 20);
+    let mut cdat = Vec::with_capacity(commits.len() * COMMIT_DATA_LEN);
+    let mut edge = Vec::new();
+    for (id, commit) in commits {
+        oidl.extend_from_slice(id.as_bytes());
+        cdat.extend_from_slice(commit.tree.as_bytes());
+        let first = match commit.parents.first() {
+            Some(parent) => position(parent)?,
+            None => PARENT_NONE,
+        };
+        let second = match commit.parents.len() {
+            0 | 1 => PARENT_NONE,
+            2 => position(&commit.parents[1])?,
+            _ => {
+                let start = (edge.len() / 4) as u32 | EXTRA_EDGES;
+                let rest = &commit.parents[1..];
+                for (i, parent) in rest.iter().enumerate() {
+                    let last = if i + 1 == rest.len() { EXTRA_EDGES } else { 0 };
+                    edge.extend_from_slice(&(position(parent)? | last).to_be_bytes());
+                }
+                start
+            }
+        };
+        cdat.extend_from_slice(&first.to_be_bytes());
+        cdat.extend_from_slice(&second.to_be_bytes());
+        let time = commit.time.max(0) as u64;
+        let high = (commit.generation.min(GENERATION_MAX) << 2) | ((time >> 32) & 3) as u32;
+        cdat.extend_from_slice(&high.to_be_bytes());
+        cdat.extend_from_slice(&(time as u32).to_be_bytes());
+    }
+
+    let mut chunks = vec![(CHUNK_OIDF, oidf), (CHUNK_OIDL, oidl), (CHUNK_CDAT, cdat)];
+    if !edge.is_empty() {
+        chunks.push((CHUNK_EDGE, edge));
+    }
+    let mut data = Vec::new();
+    data.extend_from_slice(SIGNATURE);
+    data.extend_from_slice(&[1, 1, chunks.len() as u8, 0]);
+    let mut offset = (HEADER_LEN + (chunks.len() + 1) * CHUNK_ENTRY_LEN) as u64;
+    for (id, chunk) in &chunks {
+        data.extend_from_slice(&id.to_be_bytes());
+        data.extend_from_slice(&offset.to_be_bytes());
+        offset += chunk.len() as u64;
+    }
+    data.extend_from_slice(&0u32.to_be_bytes());
+    data.extend_from_slice(&offset.to_be_bytes());
+    for (_, chunk) in &chunks {
+        data.extend_from_slice(chunk);
+    }
+    let checksum = Sha1::digest(&data);
+    data.extend_from_slice(&checksum);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::revwalk::{self, RevWalk};
+    use crate::test_utils::{init_repo, set_ref, write_commit_at};
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/commit-graph");
+
+    /// A history with a merge, an octopus merge and a skewed clock, which
+    /// `tests/fixtures/commit-graph` was written for by `git commit-graph
+    /// write --reachable`. Returns the commits in the order they were made.
+    fn history(repo: &Repository) -> Vec<Oid> {
+        let mut ids: Vec<Oid> = Vec::new();
+        let mut commit = |parents: &[usize], name: &str, time: i64| {
+            let parents: Vec<Oid> = parents.iter().map(|&p| ids[p]).collect();
+            let id = write_commit_at(repo, &parents, &[(name, name)], name, time);
+            ids.push(id);
+        };
+        commit(&[], "root", 1_000);
+        commit(&[0], "a1", 2_000);
+        commit(&[1], "a2", 3_000);
+        commit(&[0], "b1", 2_500);
+        commit(&[3], "b2", 1_500);
+        commit(&[2, 4], "merge", 4_000);
+        commit(&[1], "c1", 3_500);
+        commit(&[1], "d1", 3_600);
+        commit(&[5, 6, 7], "octopus", 5_000);
+        commit(&[8], "tip", 6_000);
+        set_ref(repo, "refs/heads/master", &ids[9]);
+        ids
+    }
+
+    fn install_fixture(repo: &Repository) -> Repository {
+        let info = repo.odb().objects_dir().join("info");
+        fs::create_dir_all(&info).unwrap();
+        fs::copy(FIXTURE, info.join("commit-graph")).unwrap();
+        Repository::open(repo.git_dir()).unwrap()
+    }
+
+    #[test]
+    fn reads_what_git_wrote() {
+        let (_dir, repo) = init_repo();
+        let ids = history(&repo);
+        let graph = CommitGraph::parse(&fs::read(FIXTURE).unwrap()).unwrap();
+        assert_eq!(graph.len(), ids.len());
+        for id in &ids {
+            let graphed = graph
+                .get(id)
+                .unwrap()
+                .expect("every commit is in the graph");
+            let commit = repo.odb().read_commit(id).unwrap();
+            assert_eq!(graphed.tree, commit.tree);
+            assert_eq!(graphed.parents, commit.parents);
+            assert_eq!(graphed.time, commit.committer.time);
+            let position = graph.lookup(id).unwrap();
+            assert_eq!(graph.id(position).unwrap(), *id);
+        }
+        let generations: Vec<u32> = ids
+            .iter()
+            .map(|id| graph.get(id).unwrap().unwrap().generation)
+            .collect();
+        assert_eq!(generations, [1, 2, 3, 2, 3, 4, 3, 3, 5, 6]);
+        assert_eq!(graph.lookup(&Oid::from_raw([0; 20])), None);
+
+        // The same file as the only layer of a chain.
+        let info = repo.odb().objects_dir().join("info");
+        fs::create_dir_all(info.join("commit-graphs")).unwrap();
+        let data = fs::read(FIXTURE).unwrap();
+        let hash: Oid = data[data.len() - 20..].try_into().unwrap();
+        fs::write(
+            info.join("commit-graphs/commit-graph-chain"),
+            format!("{}\n", hash),
+        )
+        .unwrap();
+        fs::write(
+            info.join(format!("commit-graphs/graph-{}.graph", hash)),
+            &data,
+        )
+        .unwrap();
+        let chained = CommitGraph::open(&repo).unwrap().unwrap();
+        assert_eq!(chained, graph);
+
+        let mut data = data;
+        data[0] = b'X';
+        assert!(matches!(
+            CommitGraph::parse(&data),
+            Err(GitError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn walks_the_same_with_and_without_the_graph() {
+        let (_dir, repo) = init_repo();
+        let ids = history(&repo);
+        let walk = |repo: &Repository, hide: Option<Oid>| {
+            let mut walk = RevWalk::new(repo.odb());
+            walk.push(&ids[9]).unwrap();
+            if let Some(hide) = hide {
+                walk.hide(&hide).unwrap();
+            }
+            walk.map(|commit| commit.unwrap().0).collect::<Vec<_>>()
+        };
+        let without = (walk(&repo, None), walk(&repo, Some(ids[5])));
+        let merge_bases = revwalk::merge_bases(repo.odb(), &ids[4], &ids[7]).unwrap();
+        let graphed = install_fixture(&repo);
+        assert!(graphed.odb().commit_graph().is_some());
+        assert_eq!(
+            (walk(&graphed, None), walk(&graphed, Some(ids[5]))),
+            without
+        );
+
+        // Nothing below the listed commits is read from the object
+        // database any more.
+        for id in &ids[..5] {
+            graphed.odb().remove_loose(id).unwrap();
+        }
+        assert_eq!(walk(&graphed, Some(ids[5])), without.1);
+        assert_eq!(
+            revwalk::merge_bases(graphed.odb(), &ids[4], &ids[7]).unwrap(),
+            merge_bases
+        );
+    }
+}
@@ -0,0 +1,798 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::wildmatch::wildmatch;
+use crate::error::{GitError, GitResult};
+
+/// How deeply includes may nest, as in git.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// A single `key = value` line, qualified by the section it appeared in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigEntry {
+    pub section: String,
+    pub subsection: Option<String>,
+    pub key: String,
+    pub value: String,
+}
+
+/// The contents of a git config file.
+///
+/// Section and key names are case-insensitive and stored lowercased;
+/// subsection names are case-sensitive.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    entries: Vec<ConfigEntry>,
+}
+
+impl Config {
+    /// Load a config file; a missing file is an empty config.
+    ///
+    /// `include.path` pulls in another file, its entries taking the place
+    /// of the line that names it, so later lines still override them.
+    /// Without a repository to match against, `includeIf` sections are
+    /// ignored; see [`Config::load_for_repo`].
+    pub fn load(path: &Path) -> GitResult<Config> {
+        let entries = load_entries(path, None, &mut Vec::new())?;
+        Ok(Config { entries })
+    }
+
+    /// [`Config::load`] for a repository's config, also following
+    /// `includeIf "gitdir:<pattern>".path` when `git_dir` matches the
+    /// pattern. `gitdir/i:` matches case-insensitively.
+    pub fn load_for_repo(path: &Path, git_dir: &Path) -> GitResult<Config> {
+        let entries = load_entries(path, Some(git_dir), &mut Vec::new())?;
+        Ok(Config { entries })
+    }
+
+    pub fn parse(text: &str) -> GitResult<Config> {
+        let mut entries = Vec::new();
+        let mut section: Option<(String, Option<String>)> = None;
+        for (number, raw) in text.lines().enumerate() {
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                section = Some(parse_section_header(line).ok_or_else(|| {
+                    GitError::Corrupt(format!("bad config section on line {}", number + 1))
+                })?);
+                continue;
+            }
+            let (name, subsection) = section.clone().ok_or_else(|| {
+                GitError::Corrupt(format!(
+                    "config key outside a section on line {}",
+                    number + 1
+                ))
+            })?;
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), parse_value(line[i + 1..].trim())),
+                // A bare key is shorthand for `key = true`.
+                None => (line, "true".to_string()),
+            };
+            entries.push(ConfigEntry {
+                section: name,
+                subsection,
+                key: key.to_ascii_lowercase(),
+                value,
+            });
+        }
+        Ok(Config { entries })
+    }
+
+    /// Look up a value. When a key is repeated the last value wins.
+    pub fn get(&self, section: &str, subsection: Option<&str>, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.matches(section, subsection, key))
+            .map(|e| e.value.as_str())
+    }
+
+    /// Every value of a key that may be repeated, such as
+    /// `remote.<name>.fetch`, in the order they appear.
+    pub fn get_all(&self, section: &str, subsection: Option<&str>, key: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|e| e.matches(section, subsection, key))
+            .map(|e| e.value.as_str())
+            .collect()
+    }
+
+    /// Add another value for a key, after any it already has. Nothing is
+    /// written to disk; see [`add_value`] for editing a config file.
+    pub fn add_value(&mut self, section: &str, subsection: Option<&str>, key: &str, value: &str) {
+        self.entries.push(ConfigEntry {
+            section: section.to_ascii_lowercase(),
+            subsection: subsection.map(str::to_string),
+            key: key.to_ascii_lowercase(),
+            value: value.to_string(),
+        });
+    }
+
+    /// A boolean value, spelled any way git accepts: `true`/`yes`/`on`/`1`
+    /// or `false`/`no`/`off`/`0`/empty, case-insensitively.
+    pub fn get_bool(
+        &self,
+        section: &str,
+        subsection: Option<&str>,
+        key: &str,
+    ) -> GitResult<Option<bool>> {
+        let value = match self.get(section, subsection, key) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(Some(true)),
+            "false" | "no" | "off" | "0" | "" => Ok(Some(false)),
+            _ => Err(invalid_value(section, subsection, key, value)),
+        }
+    }
+
+    /// An integer value, which may have a `k`, `m` or `g` suffix scaling it
+    /// by powers of 1024.
+    pub fn get_int(
+        &self,
+        section: &str,
+        subsection: Option<&str>,
+        key: &str,
+    ) -> GitResult<Option<i64>> {
+        let value = match self.get(section, subsection, key) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let trimmed = value.trim();
+        let (digits, scale) = match trimmed.chars().last().map(|c| c.to_ascii_lowercase()) {
+            Some('k') => (&trimmed[..trimmed.len() - 1], 1 << 10),
+            Some('m') => (&trimmed[..trimmed.len() - 1], 1 << 20),
+            Some('g') => (&trimmed[..trimmed.len() - 1], 1 << 30),
+            _ => (trimmed, 1),
+        };
+        digits
+            .parse::<i64>()
+            .ok()
+            .and_then(|n| n.checked_mul(scale))
+            .map(Some)
+            .ok_or_else(|| invalid_value(section, subsection, key, value))
+    }
+
+    pub fn entries(&self) -> &[ConfigEntry] {
+        &self.entries
+    }
+}
+
+/// The entries of the config file at `path` with its includes spliced in,
+/// `including` being the files already being read, outermost first.
+fn load_entries(
+    path: &Path,
+    git_dir: Option<&Path>,
+    including: &mut Vec<PathBuf>,
+) -> GitResult<Vec<ConfigEntry>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let canonical = fs::canonicalize(path)?;
+    if including.contains(&canonical) {
+        return Err(GitError::Corrupt(format!(
+            "config include cycle through {}",
+            path.display()
+        )));
+    }
+    if including.len() > MAX_INCLUDE_DEPTH {
+        return Err(GitError::Corrupt(format!(
+            "exceeded maximum include depth ({}) while including {}",
+            MAX_INCLUDE_DEPTH,
+            path.display()
+        )));
+    }
+    including.push(canonical);
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut entries = Vec::new();
+    for entry in Config::parse(&text)?.entries {
+        let included = entry.key == "path"
+            && match (entry.section.as_str(), &entry.subsection) {
+                ("include", None) => true,
+                ("includeif", Some(condition)) => {
+                    git_dir.is_some_and(|git_dir| condition_holds(condition, dir, git_dir))
+                }
+                _ => false,
+            };
+        let target = included.then(|| resolve_path(&entry.value, dir));
+        entries.push(entry);
+        if let Some(Some(target)) = target {
+            entries.extend(load_entries(&target, git_dir, including)?);
+        }
+    }
+    including.pop();
+    Ok(entries)
+}
+
+/// An include path, with `~/` meaning the home directory and a relative
+/// path taken from the including file's directory. `None` without a home
+/// to expand into.
+fn resolve_path(value: &str, dir: &Path) -> Option<PathBuf> {
+    match value.strip_prefix("~/") {
+        Some(rest) => env::var_os("HOME").map(|home| PathBuf::from(home).join(rest)),
+        None => Some(dir.join(value)),
+    }
+}
+
+/// Whether an `includeIf` condition holds for the repository at `git_dir`.
+/// Only `gitdir:` and `gitdir/i:` are understood; anything else is false.
+///
+/// As in git, `~/` and `./` at the start of the pattern expand to the home
+/// directory and the including file's directory, any other relative
+/// pattern can match at any depth, and a trailing `/` matches everything
+/// below it.
+fn condition_holds(condition: &str, dir: &Path, git_dir: &Path) -> bool {
+    let (pattern, fold_case) = match condition.strip_prefix("gitdir:") {
+        Some(pattern) => (pattern, false),
+        None => match condition.strip_prefix("gitdir/i:") {
+            Some(pattern) => (pattern, true),
+            None => return false,
+        },
+    };
+    let mut pattern = if let Some(rest) = pattern.strip_prefix("~/") {
+        match env::var_os("HOME") {
+            Some(home) => format!("{}/{}", PathBuf::from(home).display(), rest),
+            None => return false,
+        }
+    } else if let Some(rest) = pattern.strip_prefix("./") {
+        let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        format!("{}/{}", dir.display(), rest)
+    } else if pattern.starts_with('/') {
+        pattern.to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+    let canonical = fs::canonicalize(git_dir).unwrap_or_else(|_| git_dir.to_path_buf());
+    [git_dir, canonical.as_path()].iter().any(|path| {
+        let path = path.to_string_lossy();
+        if fold_case {
+            wildmatch(
+                pattern.to_lowercase().as_bytes(),
+                path.to_lowercase().as_bytes(),
+                true,
+            )
+        } else {
+            wildmatch(pattern.as_bytes(), path.as_bytes(), true)
+        }
+    })
+}
+
+fn invalid_value(section: &str, subsection: Option<&str>, key: &str, value: &str) -> GitError {
+    GitError::InvalidConfigValue {
+        key: format_key(section, subsection, key),
+        value: value.to_string(),
+    }
+}
+
+/// `section.subsection.key`, the way keys are named on the command line.
+pub fn format_key(section: &str, subsection: Option<&str>, key: &str) -> String {
+    match subsection {
+        Some(subsection) => format!("{}.{}.{}", section, subsection, key),
+        None => format!("{}.{}", section, key),
+    }
+}
+
+/// Split a command-line key like `remote.origin.url` into its section,
+/// subsection and key. The subsection is everything between the first and
+/// last dots, so it may itself contain dots.
+pub fn parse_key(name: &str) -> GitResult<(String, Option<String>, String)> {
+    let invalid = || GitError::InvalidConfigKey(name.to_string());
+    let first = name.find('.').ok_or_else(invalid)?;
+    let last = name.rfind('.').ok_or_else(invalid)?;
+    let section = &name[..first];
+    let key = &name[last + 1..];
+    let valid_name = |s: &str| {
+        !s.is_empty()
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && s.starts_with(|c: char| c.is_ascii_alphabetic())
+    };
+    if !valid_name(section) || !valid_name(key) {
+        return Err(invalid());
+    }
+    let subsection = if first == last {
+        None
+    } else {
+        Some(name[first + 1..last].to_string())
+    };
+    Ok((
+        section.to_ascii_lowercase(),
+        subsection,
+        key.to_ascii_lowercase(),
+    ))
+}
+
+/// Where one line of a config file sits: the section it's in, and the key
+/// it sets if it's a `key = value` line.
+struct Line<'a> {
+    text: &'a str,
+    section: Option<(String, Option<String>)>,
+    key: Option<String>,
+}
+
+fn scan(text: &str) -> Vec<Line<'_>> {
+    let mut section = None;
+    let mut lines = Vec::new();
+    for raw in text.split_inclusive('\n') {
+        let line = strip_comment(raw).trim();
+        let mut key = None;
+        if line.starts_with('[') {
+            section = parse_section_header(line);
+        } else if !line.is_empty() {
+            let name = line.split('=').next().unwrap_or(line).trim();
+            key = Some(name.to_ascii_lowercase());
+        }
+        lines.push(Line {
+            text: raw,
+            section: section.clone(),
+            key,
+        });
+    }
+    lines
+}
+
+impl Line<'_> {
+    fn in_section(&self, section: &str, subsection: Option<&str>) -> bool {
+        match &self.section {
+            Some((name, sub)) => name == section && sub.as_deref() == subsection,
+            None => false,
+        }
+    }
+
+    fn sets(&self, section: &str, subsection: Option<&str>, key: &str) -> bool {
+        self.in_section(section, subsection) && self.key.as_deref() == Some(key)
+    }
+}
+
+/// Set `key` in config file `text`, leaving every other line untouched.
+/// An existing value is replaced in place; otherwise the key is added at
+/// the end of the last matching section, which is created if needed.
+/// Replacing a key that has several values is an error.
+pub fn set_value(
+    text: &str,
+    section: &str,
+    subsection: Option<&str>,
+    key: &str,
+    value: &str,
+) -> GitResult<String> {
+    let (section, key) = (section.to_ascii_lowercase(), key.to_ascii_lowercase());
+    let lines = scan(text);
+    let existing: Vec<usize> = (0..lines.len())
+        .filter(|&i| lines[i].sets(&section, subsection, &key))
+        .collect();
+    if existing.len() > 1 {
+        return Err(GitError::MultipleConfigValues(format_key(
+            &section, subsection, &key,
+        )));
+    }
+
+    let mut out = String::new();
+    if let Some(&i) = existing.first() {
+        for (n, line) in lines.iter().enumerate() {
+            if n == i {
+                let indent: String = line
+                    .text
+                    .chars()
+                    .take_while(|c| c.is_whitespace())
+                    .collect();
+                let name = line
+                    .text
+                    .trim_start()
+                    .split(['=', ' ', '\t'])
+                    .next()
+                    .unwrap_or(&key);
+                out.push_str(&format!("{}{} = {}\n", indent, name, quote_value(value)));
+            } else {
+                out.push_str(line.text);
+            }
+        }
+        return Ok(out);
+    }
+
+    Ok(insert_value(
+        text, &lines, &section, subsection, &key, value,
+    ))
+}
+
+/// Add another value for `key` to config file `text`, after any values it
+/// already has, as `git config --add` does.
+pub fn add_value(
+    text: &str,
+    section: &str,
+    subsection: Option<&str>,
+    key: &str,
+    value: &str,
+) -> String {
+    let (section, key) = (section.to_ascii_lowercase(), key.to_ascii_lowercase());
+    insert_value(text, &scan(text), &section, subsection, &key, value)
+}
+
+/// Write a new `key = value` line at the end of the last matching section,
+/// which is created if needed.
+fn insert_value(
+    text: &str,
+    lines: &[Line<'_>],
+    section: &str,
+    subsection: Option<&str>,
+    key: &str,
+    value: &str,
+) -> String {
+    let mut out = String::new();
+    let new_line = format!("\t{} = {}\n", key, quote_value(value));
+    // After the section's last key, or its header if it has none, rather
+    // than after any blank lines or comments that follow.
+    let last_in_section = lines.iter().rposition(|line| {
+        line.in_section(section, subsection)
+            && (line.key.is_some() || line.text.trim_start().starts_with('['))
+    });
+    match last_in_section {
+        Some(i) => {
+            for (n, line) in lines.iter().enumerate() {
+                out.push_str(line.text);
+                if n == i {
+                    if !line.text.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    out.push_str(&new_line);
+                }
+            }
+        }
+        None => {
+            out.push_str(text);
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            match subsection {
+                Some(sub) => out.push_str(&format!(
+                    "[{} \"{}\"]\n",
+                    section,
+                    sub.replace('\\', "\\\\").replace('"', "\\\"")
+                )),
+                None => out.push_str(&format!("[{}]\n", section)),
+            }
+            out.push_str(&new_line);
+        }
+    }
+    out
+}
+
+/// Remove `key` from config file `text`. A key with several values is
+/// only removed when `all` is set. Returns how many lines were removed.
+pub fn unset_value(
+    text: &str,
+    section: &str,
+    subsection: Option<&str>,
+    key: &str,
+    all: bool,
+) -> GitResult<(String, usize)> {
+    let (section, key) = (section.to_ascii_lowercase(), key.to_ascii_lowercase());
+    let lines = scan(text);
+    let matching = lines
+        .iter()
+        .filter(|line| line.sets(&section, subsection, &key))
+        .count();
+    if matching > 1 && !all {
+        return Err(GitError::MultipleConfigValues(format_key(
+            &section, subsection, &key,
+        )));
+    }
+    let out = lines
+        .iter()
+        .filter(|line| !line.sets(&section, subsection, &key))
+        .map(|line| line.text)
+        .collect();
+    Ok((out, matching))
+}
+
+/// Remove the section `[section "subsection"]` from config file `text`,
+/// every line of it from its header on, wherever it appears. Returns
+/// whether there was one.
+pub fn remove_section(text: &str, section: &str, subsection: Option<&str>) -> (String, bool) {
+    let section = section.to_ascii_lowercase();
+    let lines = scan(text);
+    let kept: String = lines
+        .iter()
+        .filter(|line| !line.in_section(&section, subsection))
+        .map(|line| line.text)
+        .collect();
+    let removed = kept.len() != text.len();
+    (kept, removed)
+}
+
+/// Quote a value if writing it bare would change its meaning.
+fn quote_value(value: &str) -> String {
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    if needs_quotes {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+impl ConfigEntry {
+    fn matches(&self, section: &str, subsection: Option<&str>, key: &str) -> bool {
+        self.section.eq_ignore_ascii_case(section)
+            && self.subsection.as_deref() == subsection
+            && self.key.eq_ignore_ascii_case(key)
+    }
+}
+
+/// Parse `[section]`, `[section "subsection"]` or the legacy `[section.subsection]`.
+fn parse_section_header(line: &str) -> Option<(String, Option<String>)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    if let Some(quote) = inner.find('"') {
+        let name = inner[..quote].trim().to_ascii_lowercase();
+        let sub = inner[quote + 1..].strip_suffix('"')?;
+        return Some((name, Some(sub.replace("\\\"", "\"").replace("\\\\", "\\"))));
+    }
+    match inner.find('.') {
+        Some(dot) => Some((
+            inner[..dot].to_ascii_lowercase(),
+            Some(inner[dot + 1..].to_ascii_lowercase()),
+        )),
+        None => Some((inner.to_ascii_lowercase(), None)),
+    }
+}
+
+/// Strip a trailing `#` or `;` comment that isn't inside quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '#' | ';' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Unquote a value and process its escape sequences.
+fn parse_value(raw: &str) -> String {
+    let mut value = String::new();
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {}
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('b') => {
+                    value.pop();
+                }
+                Some(other) => value.push(other),
+                None => {}
+            },
+            _ => value.push(c),
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sections_and_subsections() {
+        let config = Config::parse(
+            "[core]\n\tbare = false ; trailing comment\n\
+             [remote \"origin\"]\n\turl = \"/tmp/some # path\"\n\
+             [User]\n\tName = A U Thor\n",
+        )
+        .unwrap();
+        assert_eq!(config.get("core", None, "bare"), Some("false"));
+        assert_eq!(
+            config.get("remote", Some("origin"), "url"),
+            Some("/tmp/some # path")
+        );
+        assert_eq!(config.get("user", None, "name"), Some("A U Thor"));
+        assert_eq!(config.get("remote", Some("ORIGIN"), "url"), None);
+    }
+
+    #[test]
+    fn coerces_bools_and_ints() {
+        let config = Config::parse(
+            "[t]\n\ta = yes\n\tb = Off\n\tc\n\td = 1\n\te =\n\tf = maybe\n\
+             \tsmall = 42\n\tkilo = 2k\n\tmega = 3M\n\tgiga = 1g\n\tneg = -8\n\tbad = 12x\n",
+        )
+        .unwrap();
+        let get_bool = |key| config.get_bool("t", None, key).unwrap();
+        assert_eq!(get_bool("a"), Some(true));
+        assert_eq!(get_bool("b"), Some(false));
+        assert_eq!(get_bool("c"), Some(true));
+        assert_eq!(get_bool("d"), Some(true));
+        assert_eq!(get_bool("e"), Some(false));
+        assert_eq!(get_bool("missing"), None);
+        assert!(matches!(
+            config.get_bool("t", None, "f"),
+            Err(GitError::InvalidConfigValue { .. })
+        ));
+
+        let get_int = |key| config.get_int("t", None, key).unwrap();
+        assert_eq!(get_int("small"), Some(42));
+        assert_eq!(get_int("kilo"), Some(2048));
+        assert_eq!(get_int("mega"), Some(3 * 1024 * 1024));
+        assert_eq!(get_int("giga"), Some(1 << 30));
+        assert_eq!(get_int("neg"), Some(-8));
+        assert!(config.get_int("t", None, "bad").is_err());
+    }
+
+    #[test]
+    fn edits_keep_the_rest_of_the_file() {
+        let text = "# top comment\n[core]\n\tbare = false ; keep me\n\n[user]\n    name = Old\n";
+        let text = set_value(text, "user", None, "name", "New Name").unwrap();
+        let text = set_value(&text, "core", None, "editor", "vim # fast").unwrap();
+        let text = set_value(&text, "remote", Some("origin"), "url", "/srv/repo").unwrap();
+        assert_eq!(
+            text,
+            "# top comment\n[core]\n\tbare = false ; keep me\n\teditor = \"vim # fast\"\n\n\
+             [user]\n    name = New Name\n[remote \"origin\"]\n\turl = /srv/repo\n"
+        );
+        let config = Config::parse(&text).unwrap();
+        assert_eq!(config.get("core", None, "editor"), Some("vim # fast"));
+        assert_eq!(
+            config.get("remote", Some("origin"), "url"),
+            Some("/srv/repo")
+        );
+
+        let (text, removed) = unset_value(&text, "core", None, "editor", false).unwrap();
+        assert_eq!(removed, 1);
+        assert!(text.starts_with("# top comment\n[core]\n\tbare = false ; keep me\n\n"));
+    }
+
+    #[test]
+    fn keeps_every_value_of_a_repeated_key() {
+        let text = "[remote \"origin\"]\n\turl = /srv/repo\n\
+                    \tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+                    \tfetch = +refs/tags/*:refs/tags/*\n";
+        let mut config = Config::parse(text).unwrap();
+        assert_eq!(
+            config.get_all("remote", Some("origin"), "fetch"),
+            vec![
+                "+refs/heads/*:refs/remotes/origin/*",
+                "+refs/tags/*:refs/tags/*"
+            ]
+        );
+        assert_eq!(
+            config.get("remote", Some("origin"), "fetch"),
+            Some("+refs/tags/*:refs/tags/*")
+        );
+        config.add_value(
+            "remote",
+            Some("origin"),
+            "fetch",
+            "+refs/notes/*:refs/notes/*",
+        );
+        assert_eq!(config.get_all("remote", Some("origin"), "fetch").len(), 3);
+
+        let added = add_value(
+            text,
+            "remote",
+            Some("origin"),
+            "fetch",
+            "+refs/notes/*:refs/notes/*",
+        );
+        assert_eq!(
+            added,
+            format!("{}\tfetch = +refs/notes/*:refs/notes/*\n", text)
+        );
+        let reparsed = Config::parse(&added).unwrap();
+        assert_eq!(reparsed.get_all("remote", Some("origin"), "fetch").len(), 3);
+        assert!(set_value(&added, "remote", Some("origin"), "fetch", "x").is_err());
+    }
+
+    #[test]
+    fn parses_command_line_keys() {
+        assert_eq!(
+            parse_key("remote.my.fork.url").unwrap(),
+            (
+                "remote".to_string(),
+                Some("my.fork".to_string()),
+                "url".to_string()
+            )
+        );
+        assert_eq!(
+            parse_key("Core.Bare").unwrap(),
+            ("core".to_string(), None, "bare".to_string())
+        );
+        assert!(parse_key("nodot").is_err());
+        assert!(parse_key("core.").is_err());
+    }
+
+    #[test]
+    fn follows_includes_where_they_appear() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("conf")).unwrap();
+        fs::write(
+            dir.path().join("conf/identity"),
+            "[user]\n\temail = included@example.com\n\tname = Included\n\
+             [include]\n\tpath = ../missing\n",
+        )
+        .unwrap();
+        let main = dir.path().join("config");
+        fs::write(
+            &main,
+            "[user]\n\tname = Before\n[include]\n\tpath = conf/identity\n\
+             [user]\n\tname = After\n",
+        )
+        .unwrap();
+        let config = Config::load(&main).unwrap();
+        assert_eq!(
+            config.get("user", None, "email"),
+            Some("included@example.com")
+        );
+        assert_eq!(
+            config.get_all("user", None, "name"),
+            ["Before", "Included", "After"]
+        );
+
+        fs::write(
+            dir.path().join("conf/identity"),
+            "[include]\n\tpath = ../config\n",
+        )
+        .unwrap();
+        assert!(matches!(Config::load(&main), Err(GitError::Corrupt(_))));
+    }
+
+    #[test]
+    fn includes_by_gitdir_only_when_it_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let git_dir = dir.path().join("work/project/.git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(
+            dir.path().join("work.inc"),
+            "[user]\n\temail = work@example.com\n",
+        )
+        .unwrap();
+        let config_path = git_dir.join("config");
+        let email = |condition: &str| {
+            fs::write(
+                &config_path,
+                format!(
+                    "[user]\n\temail = home@example.com\n\
+                     [includeIf \"{}\"]\n\tpath = ../../../work.inc\n",
+                    condition
+                ),
+            )
+            .unwrap();
+            let config = Config::load_for_repo(&config_path, &git_dir).unwrap();
+            config.get("user", None, "email").unwrap().to_string()
+        };
+        let work = format!("gitdir:{}/work/", dir.path().display());
+        assert_eq!(email(&work), "work@example.com");
+        assert_eq!(email("gitdir:project/.git"), "work@example.com");
+        assert_eq!(email("gitdir/i:PROJECT/"), "work@example.com");
+        assert_eq!(email("gitdir:PROJECT/"), "home@example.com");
+        assert_eq!(email("gitdir:/elsewhere/"), "home@example.com");
+        assert_eq!(email("onbranch:main"), "home@example.com");
+        // Without a repository there's nothing to match.
+        email(&work);
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(config.get("user", None, "email"), Some("home@example.com"));
+    }
+}
@@ -0,0 +1,316 @@
+//! Dates as people type them, for `--since`, `--until` and expiry
+//! settings.
+//!
+//! [`parse_approxidate`] understands:
+//!
+//! - `now` and `yesterday`
+//! - `@<seconds>`, a unix timestamp
+//! - ISO dates and datetimes: `2024-01-01`, `2024-01-01 10:20`,
+//!   `2024-01-01T10:20:30Z`, `2024-01-01 10:20:30 +0200`. Without a zone
+//!   the local one is used, and a date without a time keeps the current
+//!   time of day, as git does.
+//! - `<n> <unit> ago`, with spaces or dots between the words as in
+//!   `2.weeks.ago`, and `ago` optional. Units run from seconds to years;
+//!   months and years step through the calendar rather than counting a
+//!   fixed number of days.
+//!
+//! Unlike git, which takes whatever it can from anything, other input is
+//! an error. Times of day by name (`noon`, `tea`) and weekdays (`last
+//! tuesday`) aren't supported.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::signature::{self, civil_from_days, local_offset, MONTHS};
+use crate::error::{GitError, GitResult};
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Seconds since the epoch, negative before it.
+pub fn to_unix(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+pub fn from_unix(seconds: i64) -> SystemTime {
+    if seconds < 0 {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    } else {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    }
+}
+
+/// Read `input` as a point in time, with relative dates counted back from
+/// `now`. See the [module docs](self) for what's understood.
+pub fn parse_approxidate(input: &str, now: SystemTime) -> GitResult<SystemTime> {
+    let invalid = || GitError::InvalidDate(input.to_string());
+    let text = input.trim().to_ascii_lowercase();
+    if text == "now" {
+        return Ok(now);
+    }
+    if let Some(seconds) = text.strip_prefix('@') {
+        return seconds.parse().map(from_unix).map_err(|_| invalid());
+    }
+    if let Some(time) = parse_iso(&text, to_unix(now)) {
+        return Ok(from_unix(time));
+    }
+    let words: Vec<&str> = text.split([' ', '.']).filter(|w| !w.is_empty()).collect();
+    let (count, unit) = match words.as_slice() {
+        ["yesterday"] => (1, "day"),
+        [count, unit] | [count, unit, "ago"] => {
+            (count.parse::<u32>().map_err(|_| invalid())?, *unit)
+        }
+        _ => return Err(invalid()),
+    };
+    let seconds = match unit.strip_suffix('s').unwrap_or(unit) {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => DAY as u64,
+        "week" => 7 * DAY as u64,
+        "month" => return Ok(from_unix(months_before(to_unix(now), count.into()))),
+        "year" => {
+            return Ok(from_unix(months_before(
+                to_unix(now),
+                i64::from(count) * 12,
+            )))
+        }
+        _ => return Err(invalid()),
+    };
+    now.checked_sub(Duration::from_secs(u64::from(count) * seconds))
+        .ok_or_else(invalid)
+}
+
+/// A date as `GIT_AUTHOR_DATE` and `GIT_COMMITTER_DATE` take it, as
+/// seconds since the epoch and a UTC offset in minutes: git's own
+/// `[@]<seconds> [<±HHMM>]`, or RFC 2822 as in `Thu, 7 Apr 2005 22:13:13
+/// +0200`.
+pub fn parse_git_date(input: &str) -> GitResult<(i64, i32)> {
+    let invalid = || GitError::InvalidDate(input.to_string());
+    let text = input.trim();
+    let words: Vec<&str> = text.trim_start_matches('@').split_whitespace().collect();
+    if let Some(seconds) = words.first().and_then(|w| w.parse::<i64>().ok()) {
+        // Like git, a bare number is only taken for seconds when it has
+        // the nine digits of a date since 1973.
+        if text.starts_with('@') || seconds >= 100_000_000 {
+            let offset = match words[1..] {
+                [] => 0,
+                [zone] => signature::parse_offset(zone).map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            };
+            return Ok((seconds, offset));
+        }
+    }
+
+    // RFC 2822, its day of the week optional.
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let words = match words.first() {
+        Some(day) if day.ends_with(',') => &words[1..],
+        _ => &words[..],
+    };
+    let (day, month, year, clock, zone) = match *words {
+        [day, month, year, clock, zone] => (day, month, year, clock, zone),
+        _ => return Err(invalid()),
+    };
+    let day: i64 = day.parse().map_err(|_| invalid())?;
+    let month = MONTHS
+        .iter()
+        .position(|m| m.eq_ignore_ascii_case(month))
+        .ok_or_else(invalid)? as i64
+        + 1;
+    let year: i64 = number(year, 4).ok_or_else(invalid)?;
+    let mut fields = clock.splitn(3, ':');
+    let mut field = || fields.next().and_then(|f| number(f, 2));
+    let (hour, minute) = (field().ok_or_else(invalid)?, field().ok_or_else(invalid)?);
+    let second = field().unwrap_or(0);
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid());
+    }
+    let offset = signature::parse_offset(zone).map_err(|_| invalid())?;
+    let naive = days_from_civil(year, month, day) * DAY + hour * 3600 + minute * 60 + second;
+    Ok((naive - i64::from(offset) * 60, offset))
+}
+
+/// `now` moved back `months` calendar months in the local timezone, the
+/// day overflowing into the next month where the earlier one is shorter,
+/// as `mktime` does it.
+fn months_before(now: i64, months: i64) -> i64 {
+    let local = now + i64::from(local_offset(now)) * 60;
+    let (year, month, day) = civil_from_days(local.div_euclid(DAY));
+    let total = year * 12 + (month as i64 - 1) - months;
+    let days =
+        days_from_civil(total.div_euclid(12), total.rem_euclid(12) + 1, 1) + i64::from(day) - 1;
+    from_local(days * DAY + local.rem_euclid(DAY))
+}
+
+/// The time at which local clocks show `naive`, using the offset in force
+/// then, found from a first guess.
+fn from_local(naive: i64) -> i64 {
+    naive - i64::from(local_offset(naive - i64::from(local_offset(naive)) * 60)) * 60
+}
+
+/// `YYYY-MM-DD`, then optionally a `HH:MM[:SS]` time after a space or
+/// `T`, then optionally a zone: `Z`, `±HHMM`, `±HH:MM` or `±HH`.
+fn parse_iso(text: &str, now: i64) -> Option<i64> {
+    let (date, rest) = match text.find(['t', ' ']) {
+        Some(at) => (&text[..at], text[at + 1..].trim()),
+        None => (text, ""),
+    };
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = number(parts.next()?, 4)?;
+    let month: i64 = number(parts.next()?, 2)?;
+    let day: i64 = number(parts.next()?, 2)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+
+    let (clock, zone) = match rest.strip_suffix('z') {
+        Some(clock) => (clock.trim_end(), Some(0)),
+        None => match rest.find(['+', '-']) {
+            Some(at) => (rest[..at].trim_end(), Some(parse_zone(&rest[at..])?)),
+            None => (rest, None),
+        },
+    };
+    let seconds_of_day = if clock.is_empty() {
+        if zone.is_some() {
+            return None;
+        }
+        // Keep the current local time of day.
+        (now + i64::from(local_offset(now)) * 60).rem_euclid(DAY)
+    } else {
+        let mut fields = clock.splitn(3, ':');
+        let hour: i64 = number(fields.next()?, 2)?;
+        let minute: i64 = number(fields.next()?, 2)?;
+        let second: i64 = match fields.next() {
+            Some(second) => number(second, 2)?,
+            None => 0,
+        };
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        hour * 3600 + minute * 60 + second
+    };
+    let naive = days * DAY + seconds_of_day;
+    Some(match zone {
+        Some(offset) => naive - offset,
+        None => from_local(naive),
+    })
+}
+
+/// A `±HHMM`, `±HH:MM` or `±HH` zone as seconds east of UTC.
+fn parse_zone(zone: &str) -> Option<i64> {
+    let sign = if zone.starts_with('-') { -1 } else { 1 };
+    let digits: String = zone[1..].chars().filter(|&c| c != ':').collect();
+    let (hours, minutes) = match digits.len() {
+        2 => (number(&digits, 2)?, 0),
+        4 => (number(&digits[..2], 2)?, number(&digits[2..], 2)?),
+        _ => return None,
+    };
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// A run of exactly `len` ASCII digits.
+fn number(text: &str, len: usize) -> Option<i64> {
+    if text.len() != len || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+/// Days from 1970-01-01 to a proleptic Gregorian date, the inverse of
+/// [`civil_from_days`].
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = (month + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str, now: i64) -> GitResult<i64> {
+        parse_approxidate(input, from_unix(now)).map(to_unix)
+    }
+
+    #[test]
+    fn parses_absolute_and_relative_dates() {
+        // 2026-10-14 06:18:18 UTC.
+        let now = 1_791_958_698;
+        for (input, expected) in [
+            ("now", now),
+            ("@1700000000", 1_700_000_000),
+            ("2024-01-01T10:20:30Z", 1_704_104_430),
+            ("2024-01-01 10:20:30 +0200", 1_704_097_230),
+            ("2024-01-01 10:20:30 +02:00", 1_704_097_230),
+            ("2024-02-29 00:00 -05", 1_709_182_800),
+            ("2.days.ago", now - 2 * DAY),
+            ("2 weeks ago", now - 14 * DAY),
+            ("2 days", now - 2 * DAY),
+            ("1 hour ago", now - 3600),
+            ("yesterday", now - DAY),
+        ] {
+            assert_eq!(parse(input, now).unwrap(), expected, "{}", input);
+        }
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+        for input in [
+            "garbage",
+            "2.fortnights.ago",
+            "2024-13-01",
+            "@x",
+            "2024-01-01 25:00",
+        ] {
+            assert!(
+                matches!(parse(input, now), Err(GitError::InvalidDate(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parses_dates_from_the_environment() {
+        for (input, expected) in [
+            ("@1112911993 -0700", (1_112_911_993, -420)),
+            ("1112911993 +0130", (1_112_911_993, 90)),
+            ("@0", (0, 0)),
+            ("Thu, 7 Apr 2005 22:13:13 +0200", (1_112_904_793, 120)),
+            ("7 Apr 2005 22:13 -0000", (1_112_911_980, 0)),
+        ] {
+            assert_eq!(parse_git_date(input).unwrap(), expected, "{}", input);
+        }
+        for input in ["", "tomorrow", "Thu, 7 Foo 2005 22:13:13 +0200", "@1 2 3"] {
+            assert!(
+                matches!(parse_git_date(input), Err(GitError::InvalidDate(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn steps_through_the_calendar_for_months_and_years() {
+        // Whatever the local zone, months and years keep the time of day
+        // and land on the same day of the month when there is one.
+        let now = 1_791_958_698;
+        let local = |time: i64| {
+            let local = time + i64::from(local_offset(time)) * 60;
+            (
+                civil_from_days(local.div_euclid(DAY)),
+                local.rem_euclid(DAY),
+            )
+        };
+        let ((year, month, day), time_of_day) = local(now);
+        let three_months = local(parse("3 months ago", now).unwrap());
+        assert_eq!(three_months, ((year, month - 3, day), time_of_day));
+        let a_year = local(parse("1.year.ago", now).unwrap());
+        assert_eq!(a_year, ((year - 1, month, day), time_of_day));
+    }
+}
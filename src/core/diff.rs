@@ -0,0 +1,1102 @@
+//! Line-based diffing, and the changes between two trees.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::core::object::TreeEntry;
+use crate::core::odb::ObjectDatabase;
+use crate::core::oid::Oid;
+use crate::core::pathspec::Pathspec;
+use crate::core::tree::FlatEntry;
+use crate::error::GitResult;
+
+/// One run of a diff script. `old` and `new` are the positions in each
+/// sequence where the run starts; `len` counts elements of the sequence
+/// the run consumes (old for deletes, new for inserts).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal { old: usize, new: usize, len: usize },
+    Delete { old: usize, new: usize, len: usize },
+    Insert { old: usize, new: usize, len: usize },
+}
+
+/// Split content into lines, each keeping its `\n` terminator. A final line
+/// without one is kept as is.
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, &b) in data.iter().enumerate() {
+        if b == b'\n' {
+            lines.push(&data[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < data.len() {
+        lines.push(&data[start..]);
+    }
+    lines
+}
+
+/// Git's heuristic: content with a NUL in its first 8000 bytes is binary.
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|&b| b == 0)
+}
+
+/// How alike two files are, as a percentage: the lines they have in
+/// common, counting repeats, out of the lines in the longer one. Two empty
+/// files are identical.
+pub fn similarity(old: &[u8], new: &[u8]) -> u32 {
+    let (old, new) = (split_lines(old), split_lines(new));
+    let longer = old.len().max(new.len());
+    if longer == 0 {
+        return 100;
+    }
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for line in &old {
+        *counts.entry(line).or_insert(0) += 1;
+    }
+    let mut common = 0;
+    for line in &new {
+        if let Some(count) = counts.get_mut(line).filter(|count| **count > 0) {
+            *count -= 1;
+            common += 1;
+        }
+    }
+    (common * 100 / longer) as u32
+}
+
+/// Diff two sequences with Myers' O(ND) algorithm. Within each changed
+/// region the deletions come before the insertions.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    let edits = shortest_edit(old, new, None).expect("an unlimited search finishes");
+    ops_from_edits(&edits)
+}
+
+/// How the edits between two files are found, as `--diff-algorithm`
+/// picks it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    /// The fewest edits, which on code can pair up the wrong closing
+    /// braces and blank lines.
+    #[default]
+    Myers,
+    /// Anchor on the lines that appear once on each side, such as a
+    /// function's signature, and diff between them.
+    Patience,
+    /// Anchor on the lines that appear least often on the old side, which
+    /// lines unique to both are the rarest case of.
+    Histogram,
+}
+
+impl DiffAlgorithm {
+    /// The algorithm `diff.algorithm` names. `default` and `minimal` are
+    /// both Myers, which here is always minimal.
+    pub fn from_name(name: &str) -> Option<DiffAlgorithm> {
+        match name.to_ascii_lowercase().as_str() {
+            "myers" | "default" | "minimal" => Some(DiffAlgorithm::Myers),
+            "patience" => Some(DiffAlgorithm::Patience),
+            "histogram" => Some(DiffAlgorithm::Histogram),
+            _ => None,
+        }
+    }
+}
+
+/// Old lines seen more often than this aren't histogram anchors.
+const MAX_CHAIN: usize = 64;
+
+/// [`diff`] with `algorithm`. Patience and histogram split the sequences
+/// at their anchors and diff between them the same way, each region
+/// without an anchor being left to Myers.
+pub fn diff_with<T: Eq + Hash>(old: &[T], new: &[T], algorithm: DiffAlgorithm) -> Vec<DiffOp> {
+    if algorithm == DiffAlgorithm::Myers {
+        return diff(old, new);
+    }
+    let mut ids = HashMap::new();
+    let (old, new) = (number(old, &mut ids), number(new, &mut ids));
+    let mut edits = Vec::with_capacity(old.len() + new.len());
+    anchored_edits(&old, &new, algorithm, &mut edits);
+    slide_down(&mut edits, &old, &new);
+    ops_from_edits(&edits)
+}
+
+/// Move each run of only insertions or only deletions as far down as
+/// the lines after it allow, as git does after any algorithm, so that an
+/// added block is shown ending where it ends rather than with lines the
+/// block before ended with too.
+fn slide_down(edits: &mut [Edit], old: &[usize], new: &[usize]) {
+    let (mut i, mut x, mut y) = (0, 0, 0);
+    while i < edits.len() {
+        let kind = edits[i];
+        if kind == Edit::Equal {
+            i += 1;
+            x += 1;
+            y += 1;
+            continue;
+        }
+        let mut start = i;
+        while i < edits.len() && edits[i] == kind {
+            i += 1;
+        }
+        let len = i - start;
+        let alone = start == 0 || edits[start - 1] == Edit::Equal;
+        let (lines, mut at) = if kind == Edit::Insert {
+            (new, y)
+        } else {
+            (old, x)
+        };
+        let mut slid = 0;
+        while alone && i < edits.len() && edits[i] == Edit::Equal && lines[at] == lines[at + len] {
+            edits.swap(start, i);
+            start += 1;
+            i += 1;
+            at += 1;
+            slid += 1;
+        }
+        x += slid;
+        y += slid;
+        if kind == Edit::Insert {
+            y += len;
+        } else {
+            x += len;
+        }
+    }
+}
+
+/// A number for each of `items`, the same for equal items, from the
+/// numbers already handed out in `ids`.
+fn number<'a, T: Eq + Hash>(items: &'a [T], ids: &mut HashMap<&'a T, usize>) -> Vec<usize> {
+    items
+        .iter()
+        .map(|item| {
+            let next = ids.len();
+            *ids.entry(item).or_insert(next)
+        })
+        .collect()
+}
+
+/// The edits from `old` to `new` by patience or histogram, appended to
+/// `edits`.
+fn anchored_edits(old: &[usize], new: &[usize], algorithm: DiffAlgorithm, edits: &mut Vec<Edit>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    edits.extend(std::iter::repeat_n(Edit::Equal, prefix));
+    let old_rest = &old[prefix..old.len() - suffix];
+    let new_rest = &new[prefix..new.len() - suffix];
+    if old_rest.is_empty() || new_rest.is_empty() {
+        edits.extend(std::iter::repeat_n(Edit::Delete, old_rest.len()));
+        edits.extend(std::iter::repeat_n(Edit::Insert, new_rest.len()));
+    } else {
+        let anchors = match algorithm {
+            DiffAlgorithm::Histogram => histogram_anchor(old_rest, new_rest),
+            _ => patience_anchors(old_rest, new_rest),
+        };
+        if anchors.is_empty() {
+            edits.extend(
+                shortest_edit(old_rest, new_rest, None).expect("an unlimited search finishes"),
+            );
+        } else {
+            let (mut x, mut y) = (0, 0);
+            for (a, b, len) in anchors {
+                anchored_edits(&old_rest[x..a], &new_rest[y..b], algorithm, edits);
+                edits.extend(std::iter::repeat_n(Edit::Equal, len));
+                x = a + len;
+                y = b + len;
+            }
+            anchored_edits(&old_rest[x..], &new_rest[y..], algorithm, edits);
+        }
+    }
+    edits.extend(std::iter::repeat_n(Edit::Equal, suffix));
+}
+
+/// The lines found once on each side, as `(old, new, 1)` runs, keeping
+/// the longest series of them in the same order on both sides.
+fn patience_anchors(old: &[usize], new: &[usize]) -> Vec<(usize, usize, usize)> {
+    // (count in old, count in new, index in old, index in new)
+    let mut counts: HashMap<usize, (usize, usize, usize, usize)> = HashMap::new();
+    for (i, line) in old.iter().enumerate() {
+        let count = counts.entry(*line).or_insert((0, 0, i, 0));
+        count.0 += 1;
+    }
+    for (j, line) in new.iter().enumerate() {
+        if let Some(count) = counts.get_mut(line) {
+            count.1 += 1;
+            count.3 = j;
+        }
+    }
+    let mut unique: Vec<(usize, usize)> = counts
+        .values()
+        .filter(|count| count.0 == 1 && count.1 == 1)
+        .map(|count| (count.2, count.3))
+        .collect();
+    unique.sort_unstable();
+
+    // Patience sorting: `tails[k]` ends the best series of k + 1 pairs
+    // found so far, and each pair remembers the one before it.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut before = vec![None; unique.len()];
+    for (n, &(_, j)) in unique.iter().enumerate() {
+        let k = tails.partition_point(|&t| unique[t].1 < j);
+        if k > 0 {
+            before[n] = Some(tails[k - 1]);
+        }
+        if k == tails.len() {
+            tails.push(n);
+        } else {
+            tails[k] = n;
+        }
+    }
+    let mut anchors = Vec::with_capacity(tails.len());
+    let mut at = tails.last().copied();
+    while let Some(n) = at {
+        anchors.push((unique[n].0, unique[n].1, 1));
+        at = before[n];
+    }
+    anchors.reverse();
+    anchors
+}
+
+/// The common run around the rarest line of `old` that's also in `new`,
+/// the longest of those as rare, as a single `(old, new, len)` anchor.
+/// Lines repeated more than [`MAX_CHAIN`] times aren't considered.
+fn histogram_anchor(old: &[usize], new: &[usize]) -> Vec<(usize, usize, usize)> {
+    let mut positions: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, line) in old.iter().enumerate() {
+        positions.entry(*line).or_default().push(i);
+    }
+    // (count, len, old start, new start)
+    let mut best: Option<(usize, usize, usize, usize)> = None;
+    let mut j = 0;
+    while j < new.len() {
+        let mut next = j + 1;
+        let starts = match positions.get(&new[j]) {
+            Some(starts) if starts.len() <= MAX_CHAIN => starts,
+            _ => {
+                j = next;
+                continue;
+            }
+        };
+        if best.is_some_and(|best| starts.len() > best.0) {
+            j = next;
+            continue;
+        }
+        for &i in starts {
+            let (mut a, mut b) = (i, j);
+            while a > 0 && b > 0 && old[a - 1] == new[b - 1] {
+                a -= 1;
+                b -= 1;
+            }
+            let mut len = j - b + 1;
+            while a + len < old.len() && b + len < new.len() && old[a + len] == new[b + len] {
+                len += 1;
+            }
+            let count = old[a..a + len]
+                .iter()
+                .map(|line| positions[line].len())
+                .min()
+                .expect("a run of at least one line");
+            if best.is_none_or(|best| count < best.0 || (count == best.0 && len > best.1)) {
+                best = Some((count, len, a, b));
+            }
+            next = next.max(b + len);
+        }
+        j = next;
+    }
+    best.map(|(_, len, a, b)| vec![(a, b, len)])
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MyersOptions {
+    /// Compare lines as if their trailing spaces, tabs and carriage returns
+    /// weren't there, as `--ignore-space-at-eol` does.
+    pub ignore_trailing_whitespace: bool,
+    /// The most bytes the search may keep to trace its way back. Past
+    /// that the diff is given up on and the whole of `old` replaced with
+    /// the whole of `new`, which is still correct, only not minimal.
+    pub budget: Option<usize>,
+}
+
+/// What [`myers`] made of two files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineDiff {
+    /// One side [`is_binary`], so there are no lines to compare.
+    Binary,
+    /// The runs of lines turning the old file into the new.
+    Lines(Vec<DiffOp>),
+}
+
+/// Diff the lines of two files with Myers' algorithm, as [`diff`] does,
+/// comparing each line as a number standing for its content rather than
+/// byte by byte.
+pub fn myers(old: &[u8], new: &[u8], options: &MyersOptions) -> LineDiff {
+    if is_binary(old) || is_binary(new) {
+        return LineDiff::Binary;
+    }
+    let (mut old, mut new) = (split_lines(old), split_lines(new));
+    if options.ignore_trailing_whitespace {
+        for line in old.iter_mut().chain(new.iter_mut()) {
+            *line = trim_trailing_whitespace(line);
+        }
+    }
+    let mut ids = HashMap::new();
+    let (old_ids, new_ids) = (number(&old, &mut ids), number(&new, &mut ids));
+    let edits = match shortest_edit(&old_ids, &new_ids, options.budget) {
+        Some(edits) => edits,
+        None => {
+            let mut edits = vec![Edit::Delete; old.len()];
+            edits.extend(std::iter::repeat_n(Edit::Insert, new.len()));
+            edits
+        }
+    };
+    LineDiff::Lines(ops_from_edits(&edits))
+}
+
+/// `line` without its terminator and the whitespace before it.
+fn trim_trailing_whitespace(line: &[u8]) -> &[u8] {
+    let end = line
+        .iter()
+        .rposition(|b| !matches!(b, b' ' | b'\t' | b'\r' | b'\n'))
+        .map_or(0, |i| i + 1);
+    &line[..end]
+}
+
+/// Runs of the same edit, each delete run before the insert run next to
+/// it.
+fn ops_from_edits(edits: &[Edit]) -> Vec<DiffOp> {
+    let mut ops: Vec<DiffOp> = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    let mut i = 0;
+    while i < edits.len() {
+        if edits[i] == Edit::Equal {
+            let start = i;
+            while i < edits.len() && edits[i] == Edit::Equal {
+                i += 1;
+            }
+            let len = i - start;
+            ops.push(DiffOp::Equal {
+                old: x,
+                new: y,
+                len,
+            });
+            x += len;
+            y += len;
+            continue;
+        }
+        let (mut deleted, mut inserted) = (0, 0);
+        while i < edits.len() && edits[i] != Edit::Equal {
+            match edits[i] {
+                Edit::Delete => deleted += 1,
+                _ => inserted += 1,
+            }
+            i += 1;
+        }
+        if deleted > 0 {
+            ops.push(DiffOp::Delete {
+                old: x,
+                new: y,
+                len: deleted,
+            });
+        }
+        if inserted > 0 {
+            ops.push(DiffOp::Insert {
+                old: x + deleted,
+                new: y,
+                len: inserted,
+            });
+        }
+        x += deleted;
+        y += inserted;
+    }
+    ops
+}
+
+/// The hunks of a unified diff from `old` to `new`, each with up to
+/// `context` unchanged lines around its changes, as they follow a file's
+/// `---` and `+++` lines. Changes no more than twice `context` lines apart
+/// share a hunk, as they do in git's output. Each header ends with the
+/// nearest line above the hunk that starts with a letter, `_` or `$`,
+/// git's default guess at the enclosing function. Identical contents
+/// give nothing.
+pub fn unified_hunks(old: &[u8], new: &[u8], context: usize) -> Vec<u8> {
+    unified_hunks_with(old, new, context, DiffAlgorithm::Myers)
+}
+
+/// [`unified_hunks`] with the lines diffed by `algorithm`.
+pub fn unified_hunks_with(
+    old: &[u8],
+    new: &[u8],
+    context: usize,
+    algorithm: DiffAlgorithm,
+) -> Vec<u8> {
+    let (old, new) = (split_lines(old), split_lines(new));
+    // One entry per line of the diff: its prefix and the line.
+    let mut lines: Vec<(u8, &[u8])> = Vec::new();
+    for op in diff_with(&old, &new, algorithm) {
+        match op {
+            DiffOp::Equal { old: o, len, .. } => {
+                lines.extend(old[o..o + len].iter().map(|l| (b' ', *l)))
+            }
+            DiffOp::Delete { old: o, len, .. } => {
+                lines.extend(old[o..o + len].iter().map(|l| (b'-', *l)))
+            }
+            DiffOp::Insert { new: n, len, .. } => {
+                lines.extend(new[n..n + len].iter().map(|l| (b'+', *l)))
+            }
+        }
+    }
+    let changes: Vec<usize> = (0..lines.len()).filter(|&i| lines[i].0 != b' ').collect();
+    let mut out = Vec::new();
+    let mut next = 0;
+    // Like git, a hunk with no function line of its own above it, back to
+    // where the previous hunk's search began, repeats that one's.
+    let (mut function, mut searched_to): (&[u8], usize) = (b"", 0);
+    while next < changes.len() {
+        let start = changes[next].saturating_sub(context);
+        let mut last = changes[next];
+        next += 1;
+        while next < changes.len() && changes[next] - last - 1 <= 2 * context {
+            last = changes[next];
+            next += 1;
+        }
+        let end = (last + context + 1).min(lines.len());
+        // Line numbers are counted from the lines of each side before the
+        // hunk.
+        let before = &lines[..start];
+        let old_start = before.iter().filter(|(tag, _)| *tag != b'+').count();
+        let new_start = before.iter().filter(|(tag, _)| *tag != b'-').count();
+        let hunk = &lines[start..end];
+        let old_len = hunk.iter().filter(|(tag, _)| *tag != b'+').count();
+        let new_len = hunk.iter().filter(|(tag, _)| *tag != b'-').count();
+        if let Some(line) = old[searched_to..old_start]
+            .iter()
+            .rev()
+            .find(|line| matches!(line.first(), Some(c) if c.is_ascii_alphabetic() || *c == b'_' || *c == b'$'))
+        {
+            function = line;
+        }
+        searched_to = old_start;
+        out.extend_from_slice(
+            format!(
+                "@@ -{} +{} @@",
+                hunk_range(old_start, old_len),
+                hunk_range(new_start, new_len)
+            )
+            .as_bytes(),
+        );
+        let function = &function[..function.len().min(80)];
+        let end_of_text = function
+            .iter()
+            .rposition(|c| !c.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        if end_of_text > 0 {
+            out.push(b' ');
+            out.extend_from_slice(&function[..end_of_text]);
+        }
+        out.push(b'\n');
+        for (tag, line) in hunk {
+            out.push(*tag);
+            out.extend_from_slice(line);
+            if !line.ends_with(b"\n") {
+                out.extend_from_slice(b"\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+/// `start,len` for a hunk header, 1-based, or just `start` for one line.
+/// An empty range names the line before it.
+fn hunk_range(before: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, len),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// The single-element edits turning `old` into `new`, in order, or `None`
+/// if tracing the search would take more than `budget` bytes.
+fn shortest_edit<T: PartialEq>(old: &[T], new: &[T], budget: Option<usize>) -> Option<Vec<Edit>> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = n + m;
+    // `v[k + max]` is the furthest x reached on diagonal k. We snapshot the
+    // live part of it (diagonals -d..=d) after every round to backtrack.
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let idx = |k: isize| (k + max) as usize;
+    let mut traced = 0;
+
+    'search: for d in 0..=max {
+        traced += (2 * d as usize + 1) * std::mem::size_of::<isize>();
+        if budget.is_some_and(|budget| traced > budget) {
+            return None;
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+                v[idx(k + 1)]
+            } else {
+                v[idx(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx(k)] = x;
+            if x >= n && y >= m {
+                trace.push(v[idx(-d)..=idx(d)].to_vec());
+                break 'search;
+            }
+        }
+        trace.push(v[idx(-d)..=idx(d)].to_vec());
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        // Round d - 1 covered diagonals -(d-1)..=(d-1).
+        let prev = &trace[d as usize - 1];
+        let at = |k: isize| prev[(k + d - 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        edits.push(if x == prev_x {
+            Edit::Insert
+        } else {
+            Edit::Delete
+        });
+        x = prev_x;
+        y = prev_y;
+    }
+    for _ in 0..x {
+        edits.push(Edit::Equal);
+    }
+    edits.reverse();
+    Some(edits)
+}
+
+/// How a path changed, with the letters `--name-status` shows for each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaStatus {
+    /// `A`
+    Added,
+    /// `D`
+    Deleted,
+    /// `M`: the content, the executable bit, or both.
+    Modified,
+    /// `T`: a file turned into a symlink or submodule, or back.
+    TypeChanged,
+    /// `R`: a deleted file paired with an added one, `similarity` being
+    /// how alike they are as a percentage.
+    Renamed { similarity: u32 },
+    /// `C`: an added file paired with a modified one it was copied from.
+    Copied { similarity: u32 },
+}
+
+impl DeltaStatus {
+    /// How `old` became `new`, `None` on the side a path doesn't exist
+    /// on, or `None` if nothing changed.
+    pub fn between(old: Option<&FlatEntry>, new: Option<&FlatEntry>) -> Option<DeltaStatus> {
+        Some(match (old, new) {
+            (None, None) => return None,
+            (None, Some(_)) => DeltaStatus::Added,
+            (Some(_), None) => DeltaStatus::Deleted,
+            (Some(o), Some(n)) if o == n => return None,
+            (Some(o), Some(n)) if o.mode & 0o170000 != n.mode & 0o170000 => {
+                DeltaStatus::TypeChanged
+            }
+            (Some(_), Some(_)) => DeltaStatus::Modified,
+        })
+    }
+
+    pub fn letter(self) -> char {
+        match self {
+            DeltaStatus::Added => 'A',
+            DeltaStatus::Deleted => 'D',
+            DeltaStatus::Modified => 'M',
+            DeltaStatus::TypeChanged => 'T',
+            DeltaStatus::Renamed { .. } => 'R',
+            DeltaStatus::Copied { .. } => 'C',
+        }
+    }
+}
+
+/// One changed path, `None` on the side it doesn't exist on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDelta {
+    pub path: String,
+    /// Where a renamed file was, or a copied one was copied from, `path`
+    /// being where it is now.
+    pub from: Option<String>,
+    pub status: DeltaStatus,
+    pub old: Option<FlatEntry>,
+    pub new: Option<FlatEntry>,
+}
+
+/// The changed files between two trees, `None` standing for the empty
+/// tree, at the paths `pathspec` selects. Both trees are walked together
+/// in their sorted order, and only subtrees whose ids differ are read, so
+/// an unchanged directory costs nothing however big it is. A file that
+/// turns into a directory, or back, is deleted on one side and its
+/// contents added on the other, as git reports it. Deltas come in the
+/// order git sorts tree entries.
+pub fn tree_diff(
+    odb: &ObjectDatabase,
+    old: Option<&Oid>,
+    new: Option<&Oid>,
+    pathspec: &Pathspec,
+) -> GitResult<Vec<FileDelta>> {
+    let mut deltas = Vec::new();
+    let mut path = String::new();
+    diff_trees_into(odb, old, new, pathspec, &mut path, &mut deltas)?;
+    Ok(deltas)
+}
+
+/// The deltas between two trees at `prefix`, which ends in `/` unless
+/// it's the root. `prefix` is built on and restored as the walk goes, so
+/// a path is only allocated for an entry that changed.
+fn diff_trees_into(
+    odb: &ObjectDatabase,
+    old: Option<&Oid>,
+    new: Option<&Oid>,
+    pathspec: &Pathspec,
+    prefix: &mut String,
+    deltas: &mut Vec<FileDelta>,
+) -> GitResult<()> {
+    if old == new {
+        return Ok(());
+    }
+    let entries = |tree: Option<&Oid>| -> GitResult<Vec<TreeEntry>> {
+        Ok(match tree {
+            Some(tree) => odb.read_tree(tree)?.entries,
+            None => Vec::new(),
+        })
+    };
+    let (old, new) = (entries(old)?, entries(new)?);
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        let order = match (old.get(i), new.get(j)) {
+            (Some(a), Some(b)) => entry_order(a, b),
+            (Some(_), None) => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+        let (a, b) = match order {
+            Ordering::Less => (old.get(i), None),
+            Ordering::Greater => (None, new.get(j)),
+            Ordering::Equal => (old.get(i), new.get(j)),
+        };
+        i += a.is_some() as usize;
+        j += b.is_some() as usize;
+
+        let len = prefix.len();
+        prefix.push_str(&a.or(b).expect("one side has an entry").name);
+        // Entries sorting the same are both trees or both not.
+        if a.or(b).is_some_and(TreeEntry::is_tree) {
+            let (a, b) = (a.map(|e| &e.oid), b.map(|e| &e.oid));
+            if a != b && pathspec.may_match_within(prefix) {
+                prefix.push('/');
+                diff_trees_into(odb, a, b, pathspec, prefix, deltas)?;
+            }
+        } else {
+            let flat = |e: &TreeEntry| FlatEntry {
+                mode: e.mode,
+                oid: e.oid,
+            };
+            let (a, b) = (a.map(flat), b.map(flat));
+            if let Some(status) = DeltaStatus::between(a.as_ref(), b.as_ref()) {
+                if pathspec.matches(prefix) {
+                    deltas.push(FileDelta {
+                        path: prefix.clone(),
+                        from: None,
+                        status,
+                        old: a,
+                        new: b,
+                    });
+                }
+            }
+        }
+        prefix.truncate(len);
+    }
+    Ok(())
+}
+
+/// Tree entry order: names compared as bytes, with a tree's name taken
+/// to end in `/`.
+fn entry_order(a: &TreeEntry, b: &TreeEntry) -> Ordering {
+    fn key(e: &TreeEntry) -> impl Iterator<Item = u8> + '_ {
+        let slash = if e.is_tree() { Some(b'/') } else { None };
+        e.name.bytes().chain(slash)
+    }
+    key(a).cmp(key(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply<T: Copy>(old: &[T], new: &[T], ops: &[DiffOp]) -> Vec<T> {
+        let mut out = Vec::new();
+        for op in ops {
+            match *op {
+                DiffOp::Equal { old: o, len, .. } => out.extend_from_slice(&old[o..o + len]),
+                DiffOp::Insert { new: n, len, .. } => out.extend_from_slice(&new[n..n + len]),
+                DiffOp::Delete { .. } => {}
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn diffs_the_classic_example() {
+        let old: Vec<&str> = "ABCABBA".split("").filter(|s| !s.is_empty()).collect();
+        let new: Vec<&str> = "CBABAC".split("").filter(|s| !s.is_empty()).collect();
+        let ops = diff(&old, &new);
+        assert_eq!(apply(&old, &new, &ops), new);
+        let edits: usize = ops
+            .iter()
+            .map(|op| match op {
+                DiffOp::Equal { .. } => 0,
+                DiffOp::Delete { len, .. } | DiffOp::Insert { len, .. } => *len,
+            })
+            .sum();
+        assert_eq!(edits, 5);
+    }
+
+    #[test]
+    fn handles_empty_sides() {
+        let lines = ["a\n", "b\n"];
+        assert_eq!(diff::<&str>(&[], &[]), vec![]);
+        assert_eq!(
+            diff(&lines, &[]),
+            vec![DiffOp::Delete {
+                old: 0,
+                new: 0,
+                len: 2
+            }]
+        );
+        assert_eq!(
+            diff(&[], &lines),
+            vec![DiffOp::Insert {
+                old: 0,
+                new: 0,
+                len: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn myers_diffs_interned_lines() {
+        let options = MyersOptions::default();
+        assert_eq!(
+            myers(b"a\nb\nc\nd\n", b"a\nc\nd\ne\n", &options),
+            LineDiff::Lines(vec![
+                DiffOp::Equal {
+                    old: 0,
+                    new: 0,
+                    len: 1
+                },
+                DiffOp::Delete {
+                    old: 1,
+                    new: 1,
+                    len: 1
+                },
+                DiffOp::Equal {
+                    old: 2,
+                    new: 1,
+                    len: 2
+                },
+                DiffOp::Insert {
+                    old: 4,
+                    new: 3,
+                    len: 1
+                },
+            ])
+        );
+        let (old, new) = (b"x\na \r\nb\t\n", b"x\na\nb\n");
+        assert_eq!(
+            myers(old, new, &options),
+            LineDiff::Lines(diff(&split_lines(old), &split_lines(new)))
+        );
+        let ignoring = MyersOptions {
+            ignore_trailing_whitespace: true,
+            ..options
+        };
+        assert_eq!(
+            myers(old, new, &ignoring),
+            LineDiff::Lines(vec![DiffOp::Equal {
+                old: 0,
+                new: 0,
+                len: 3
+            }])
+        );
+        assert_eq!(myers(b"text\n", b"bin\0ary", &options), LineDiff::Binary);
+        assert_eq!(myers(b"", b"", &options), LineDiff::Lines(vec![]));
+    }
+
+    #[test]
+    fn myers_gives_up_past_its_budget() {
+        let lines = |prefix: &str| -> Vec<u8> {
+            (0..2000)
+                .flat_map(|n| format!("{} {}\n", prefix, n).into_bytes())
+                .collect()
+        };
+        let (old, new) = (lines("old"), lines("new"));
+        let options = MyersOptions {
+            budget: Some(64 * 1024),
+            ..Default::default()
+        };
+        assert_eq!(
+            myers(&old, &new, &options),
+            LineDiff::Lines(vec![
+                DiffOp::Delete {
+                    old: 0,
+                    new: 0,
+                    len: 2000
+                },
+                DiffOp::Insert {
+                    old: 2000,
+                    new: 0,
+                    len: 2000
+                },
+            ])
+        );
+
+        // A small change to a big file stays well within it.
+        let mut edited = old.clone();
+        edited.splice(0..3, b"new".iter().copied());
+        match myers(&old, &edited, &options) {
+            LineDiff::Lines(ops) => assert_eq!(ops.len(), 3),
+            LineDiff::Binary => panic!("text taken for binary"),
+        }
+    }
+
+    /// Bram Cohen's example: one function replaced by another above a
+    /// function that changes a little.
+    const FROBNITZ: (&str, &str) = (
+        "#include <stdio.h>\n\n// Frobs foo heartily\nint frobnitz(int foo)\n{\n    int i;\n\
+         \x20   for(i = 0; i < 10; i++)\n    {\n        printf(\"Your answer is: \");\n\
+         \x20       printf(\"%d\\n\", foo);\n    }\n}\n\nint fact(int n)\n{\n    if(n > 1)\n\
+         \x20   {\n        return fact(n-1) * n;\n    }\n    return 1;\n}\n\n\
+         int main(int argc, char **argv)\n{\n    frobnitz(fact(10));\n}\n",
+        "#include <stdio.h>\n\nint fib(int n)\n{\n    if(n > 2)\n    {\n\
+         \x20       return fib(n-1) + fib(n-2);\n    }\n    return 1;\n}\n\n\
+         // Frobs foo heartily\nint frobnitz(int foo)\n{\n    int i;\n\
+         \x20   for(i = 0; i < 10; i++)\n    {\n        printf(\"%d\\n\", foo);\n    }\n}\n\n\
+         int main(int argc, char **argv)\n{\n    frobnitz(fib(10));\n}\n",
+    );
+
+    /// The hunk headers of the `-U0` diff of `old` against `new`.
+    fn headers(old: &str, new: &str, algorithm: DiffAlgorithm) -> Vec<String> {
+        let hunks = unified_hunks_with(old.as_bytes(), new.as_bytes(), 0, algorithm);
+        String::from_utf8(hunks)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with("@@"))
+            .map(|line| line[..line.rfind("@@").unwrap() + 2].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn anchors_on_rare_lines() {
+        // One function dropped from the top and another added at the end.
+        // Myers pairs up the shared `return;` lines, braces and blanks and
+        // edits the survivor piece by piece, as git's Myers does too.
+        let old = "fn a() {\n    return;\n}\n\nfn b() {\n    return;\n    b1();\n}\n\n";
+        let new = "fn b() {\n    return;\n    b1();\n}\n\nfn z() {\n    return;\n}\n\n";
+        assert_eq!(
+            headers(old, new, DiffAlgorithm::Myers),
+            [
+                "@@ -1 +1 @@",
+                "@@ -2,0 +3 @@",
+                "@@ -5 +6 @@",
+                "@@ -7 +7,0 @@"
+            ]
+        );
+        let whole = ["@@ -1,4 +0,0 @@", "@@ -9,0 +6,4 @@"];
+        assert_eq!(headers(old, new, DiffAlgorithm::Patience), whole);
+        assert_eq!(headers(old, new, DiffAlgorithm::Histogram), whole);
+
+        // The new function is added whole, above the one that stays, and
+        // the old one removed whole.
+        let (old, new) = FROBNITZ;
+        let anchored = [
+            "@@ -2,0 +3,9 @@",
+            "@@ -9 +17,0 @@",
+            "@@ -14,9 +21,0 @@",
+            "@@ -25 +24 @@",
+        ];
+        assert_eq!(headers(old, new, DiffAlgorithm::Patience), anchored);
+        assert_eq!(headers(old, new, DiffAlgorithm::Histogram), anchored);
+    }
+
+    #[test]
+    fn every_algorithm_gives_a_valid_edit_script() {
+        let cases = [
+            ("abcabba", "cbabac"),
+            // Nothing unique, so patience is left to Myers.
+            ("aabb", "bbaa"),
+            ("xyzxyz", "zyxzyx"),
+            ("}}}a}}}", "}}b}}}}"),
+            ("", "abc"),
+            ("abc", ""),
+            ("abcdef", "abcdef"),
+        ];
+        let chars = |s: &'static str| -> Vec<&'static str> {
+            s.split("").filter(|s| !s.is_empty()).collect()
+        };
+        for algorithm in [
+            DiffAlgorithm::Myers,
+            DiffAlgorithm::Patience,
+            DiffAlgorithm::Histogram,
+        ] {
+            for (old, new) in cases {
+                let (old, new) = (chars(old), chars(new));
+                let ops = diff_with(&old, &new, algorithm);
+                assert_eq!(apply(&old, &new, &ops), new, "{:?}", algorithm);
+            }
+            let (old, new) = FROBNITZ;
+            let (old, new) = (split_lines(old.as_bytes()), split_lines(new.as_bytes()));
+            let ops = diff_with(&old, &new, algorithm);
+            assert_eq!(apply(&old, &new, &ops), new, "{:?}", algorithm);
+        }
+        assert_eq!(
+            DiffAlgorithm::from_name("Patience"),
+            Some(DiffAlgorithm::Patience)
+        );
+        assert_eq!(
+            DiffAlgorithm::from_name("minimal"),
+            Some(DiffAlgorithm::Myers)
+        );
+        assert_eq!(DiffAlgorithm::from_name("fastest"), None);
+    }
+
+    #[test]
+    fn scores_similarity_by_shared_lines() {
+        assert_eq!(similarity(b"", b""), 100);
+        assert_eq!(similarity(b"a\nb\nc\nd\n", b"a\nb\nc\nd\n"), 100);
+        assert_eq!(similarity(b"a\nb\nc\nd\n", b"a\nx\nc\n"), 50);
+        assert_eq!(similarity(b"a\na\n", b"a\nb\n"), 50);
+        assert_eq!(similarity(b"a\n", b""), 0);
+    }
+
+    #[test]
+    fn writes_unified_hunks_like_git() {
+        let old = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16";
+        let new = b"1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\n17\n";
+        assert_eq!(
+            String::from_utf8(unified_hunks(old, new, 3)).unwrap(),
+            "@@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+             @@ -13,4 +13,5 @@\n 13\n 14\n 15\n-16\n\\ No newline at end of file\n+16\n+17\n"
+        );
+        let functions = b"fn one() {\n  a\n  b\n  c\n  d\n  e\n}\n  f\n  g\n  h\n  i\n";
+        let changed = b"fn one() {\n  a\n  b\n  c\n  D\n  e\n}\n  f\n  g\n  h\n  I\n";
+        assert_eq!(
+            String::from_utf8(unified_hunks(functions, changed, 1)).unwrap(),
+            "@@ -4,3 +4,3 @@ fn one() {\n   c\n-  d\n+  D\n   e\n\
+             @@ -10,2 +10,2 @@ fn one() {\n   h\n-  i\n+  I\n"
+        );
+        assert_eq!(
+            String::from_utf8(unified_hunks(b"a\nb\n", b"a\nc\n", 0)).unwrap(),
+            "@@ -2 +2 @@ a\n-b\n+c\n"
+        );
+        assert_eq!(
+            String::from_utf8(unified_hunks(b"", b"new\n", 3)).unwrap(),
+            "@@ -0,0 +1 @@\n+new\n"
+        );
+        assert!(unified_hunks(old, old, 3).is_empty());
+    }
+
+    #[test]
+    fn splits_lines_keeping_terminators() {
+        assert_eq!(split_lines(b"a\nb"), vec![&b"a\n"[..], &b"b"[..]]);
+        assert!(split_lines(b"").is_empty());
+    }
+
+    fn tree_changes(old: &[(&str, &str)], new: &[(&str, &str)], spec: &[&str]) -> Vec<String> {
+        let (_dir, repo) = crate::test_utils::init_repo();
+        let odb = repo.odb();
+        let tree = |files: &[(&str, &str)]| {
+            let commit = crate::test_utils::write_commit(&repo, &[], files, "c");
+            odb.read_commit(&commit).unwrap().tree
+        };
+        let (old, new) = (tree(old), tree(new));
+        let changes: Vec<String> = tree_diff(odb, Some(&old), Some(&new), &Pathspec::parse(spec))
+            .unwrap()
+            .iter()
+            .map(|d| format!("{} {}", d.status.letter(), d.path))
+            .collect();
+        let mut args = vec!["diff-tree", "-r", "--name-status"];
+        let (old, new) = (old.to_string(), new.to_string());
+        args.extend([old.as_str(), new.as_str(), "--"]);
+        args.extend(spec);
+        if let Some(theirs) = crate::test_utils::git(&repo, &args) {
+            assert_eq!(changes.join("\n"), theirs.replace('\t', " ").trim_end());
+        }
+        changes
+    }
+
+    #[test]
+    fn diffs_nested_trees() {
+        let old = [
+            ("a/b/c.txt", "c\n"),
+            ("a/b/d.txt", "d\n"),
+            ("a/keep.txt", "k\n"),
+            ("gone/deep/x", "x\n"),
+            ("gone/y", "y\n"),
+            ("top", "t\n"),
+        ];
+        let new = [
+            ("a/b/c.txt", "C\n"),
+            ("a/b/d.txt", "d\n"),
+            ("a/keep.txt", "k\n"),
+            ("a/new/deeper/z", "z\n"),
+            ("top", "t\n"),
+        ];
+        assert_eq!(
+            tree_changes(&old, &new, &[]),
+            [
+                "M a/b/c.txt",
+                "A a/new/deeper/z",
+                "D gone/deep/x",
+                "D gone/y"
+            ]
+        );
+        assert_eq!(tree_changes(&old, &new, &["gone/deep"]), ["D gone/deep/x"]);
+        assert_eq!(tree_changes(&old, &new, &["*.txt"]), ["M a/b/c.txt"]);
+        assert!(tree_changes(&old, &old, &[]).is_empty());
+    }
+
+    #[test]
+    fn diffs_a_file_turning_into_a_directory() {
+        let old = [("foo", "file\n"), ("foo.txt", "1\n"), ("bar/x", "x\n")];
+        let new = [("foo/inner", "i\n"), ("foo.txt", "1\n"), ("bar", "file\n")];
+        assert_eq!(
+            tree_changes(&old, &new, &[]),
+            ["A bar", "D bar/x", "D foo", "A foo/inner"]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! `--stat` and `--numstat` summaries of a diff, laid out as git lays
+//! them out.
+//!
+//! `--stat` gives each file a line with its name, how many lines changed
+//! and a bar of `+` and `-`, scaled so the longest bar fits the width,
+//! then a line totting up the files and lines. Names too long for their
+//! share of the width lose their start to `...`.
+
+/// What changed in one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatChange {
+    Lines {
+        added: usize,
+        deleted: usize,
+    },
+    /// Binary content, by size in bytes.
+    Binary {
+        old_size: usize,
+        new_size: usize,
+    },
+    /// A conflicted path, which has no single change to count.
+    Unmerged,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    /// The path as shown, which for a rename is from [`rename_name`].
+    pub name: String,
+    pub change: StatChange,
+}
+
+/// How a rename from `old` to `new` is shown: `old => new`, with any
+/// leading directories and trailing path components the two share kept
+/// outside braces, as in `src/{a => b}/lib.rs`.
+pub fn rename_name(old: &str, new: &str) -> String {
+    let (a, b) = (old.as_bytes(), new.as_bytes());
+    let mut prefix = 0;
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y {
+            break;
+        }
+        if *x == b'/' {
+            prefix = i + 1;
+        }
+    }
+    // Walk back from the ends, which match as terminators. A shared
+    // prefix ends in a slash, which the walk may run onto, so that
+    // `a/b` and `a/c/b` share `/b`.
+    let at = |s: &[u8], i: isize| {
+        if i as usize == s.len() {
+            0
+        } else {
+            s[i as usize]
+        }
+    };
+    let floor = prefix as isize - if prefix > 0 { 1 } else { 0 };
+    let (mut i, mut j) = (a.len() as isize, b.len() as isize);
+    let mut suffix = 0;
+    while floor <= i && floor <= j && at(a, i) == at(b, j) {
+        if at(a, i) == b'/' {
+            suffix = a.len() - i as usize;
+        }
+        i -= 1;
+        j -= 1;
+    }
+    let a_mid = a.len().saturating_sub(prefix + suffix);
+    let b_mid = b.len().saturating_sub(prefix + suffix);
+    let part = |s: &str, from: usize, len: usize| s[from..from + len].to_string();
+    if prefix + suffix == 0 {
+        return format!("{} => {}", old, new);
+    }
+    format!(
+        "{}{{{} => {}}}{}",
+        &old[..prefix],
+        part(old, prefix, a_mid),
+        part(new, prefix, b_mid),
+        &old[old.len() - suffix..]
+    )
+}
+
+/// The `--stat` lines for `files`, fitted to `width` columns, with the
+/// summary line; nothing if there are no files.
+pub fn format_stat(files: &[FileStat], width: usize) -> String {
+    if files.is_empty() {
+        return String::new();
+    }
+    let (mut max_len, mut max_change) = (0, 0);
+    let (mut number_width, mut bin_width) = (0, 0);
+    for file in files {
+        max_len = max_len.max(display_width(&file.name));
+        match file.change {
+            StatChange::Unmerged => bin_width = bin_width.max(8),
+            StatChange::Binary { old_size, new_size } => {
+                // "Bin XXX -> YYY bytes", with counts aligned with "Bin".
+                bin_width = bin_width.max(14 + decimal_width(old_size) + decimal_width(new_size));
+                number_width = 3;
+            }
+            StatChange::Lines { added, deleted } => max_change = max_change.max(added + deleted),
+        }
+    }
+    let number_width = decimal_width(max_change).max(number_width) as isize;
+    // At least 10 columns for names and 6 for the bar.
+    let width = (width as isize).max(16 + 6 + number_width);
+
+    let mut graph_width = if max_change + 4 > bin_width {
+        max_change
+    } else {
+        bin_width - 4
+    } as isize;
+    let mut name_width = max_len as isize;
+    if name_width + number_width + 6 + graph_width > width {
+        let most = width * 3 / 8 - number_width - 6;
+        if graph_width > most {
+            graph_width = most.max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let mut out = String::new();
+    let number_width = number_width as usize;
+    let (mut changed, mut insertions, mut deletions) = (0, 0, 0);
+    for file in files {
+        let mut name = file.name.as_str();
+        let mut prefix = "";
+        let mut len = name_width;
+        if name_width < display_width(name) as isize {
+            prefix = "...";
+            len = (len - 3).max(0);
+            while display_width(name) as isize > len {
+                let mut chars = name.chars();
+                chars.next();
+                name = chars.as_str();
+            }
+            if let Some(slash) = name.find('/') {
+                name = &name[slash..];
+            }
+        }
+        let padding = (len - display_width(name) as isize).max(0) as usize;
+        out.push_str(&format!(
+            " {}{}{:padding$} | ",
+            prefix,
+            name,
+            "",
+            padding = padding
+        ));
+        match file.change {
+            StatChange::Unmerged => {
+                out.push_str(&format!("{:>1$}", "Unmerged\n", number_width));
+                continue;
+            }
+            StatChange::Binary { old_size, new_size } => {
+                out.push_str(&format!("{:>1$}", "Bin", number_width));
+                if old_size != 0 || new_size != 0 {
+                    out.push_str(&format!(" {} -> {} bytes", old_size, new_size));
+                }
+                out.push('\n');
+            }
+            StatChange::Lines { added, deleted } => {
+                let (mut add, mut del) = (added, deleted);
+                let graph_width = graph_width as usize;
+                if graph_width <= max_change {
+                    let scale = |n: usize| {
+                        if n == 0 {
+                            0
+                        } else {
+                            1 + n * (graph_width - 1) / max_change
+                        }
+                    };
+                    let mut total = scale(added + deleted);
+                    if total < 2 && added > 0 && deleted > 0 {
+                        total = 2;
+                    }
+                    if added < deleted {
+                        add = scale(added);
+                        del = total - add;
+                    } else {
+                        del = scale(deleted);
+                        add = total - del;
+                    }
+                }
+                out.push_str(&format!("{:>1$}", added + deleted, number_width));
+                if added + deleted > 0 {
+                    out.push(' ');
+                }
+                out.push_str(&"+".repeat(add));
+                out.push_str(&"-".repeat(del));
+                out.push('\n');
+                insertions += added;
+                deletions += deleted;
+            }
+        }
+        changed += 1;
+    }
+    out.push_str(&summary(changed, insertions, deletions));
+    out
+}
+
+/// ` N files changed, X insertions(+), Y deletions(-)`, leaving out
+/// whichever count is zero unless both are.
+pub fn summary(files: usize, insertions: usize, deletions: usize) -> String {
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+    let mut out = format!(" {}", plural(files, "file changed", "files changed"));
+    if insertions > 0 || deletions == 0 {
+        out.push_str(&format!(
+            ", {}",
+            plural(insertions, "insertion(+)", "insertions(+)")
+        ));
+    }
+    if deletions > 0 || insertions == 0 {
+        out.push_str(&format!(
+            ", {}",
+            plural(deletions, "deletion(-)", "deletions(-)")
+        ));
+    }
+    out.push('\n');
+    out
+}
+
+/// The `--numstat` lines for `files`: added and deleted line counts and
+/// the name, tab-separated, with `-` for counts a binary file hasn't got.
+pub fn format_numstat(files: &[FileStat]) -> String {
+    files
+        .iter()
+        .map(|file| match file.change {
+            StatChange::Lines { added, deleted } => {
+                format!("{}\t{}\t{}\n", added, deleted, file.name)
+            }
+            StatChange::Binary { .. } => format!("-\t-\t{}\n", file.name),
+            StatChange::Unmerged => format!("0\t0\t{}\n", file.name),
+        })
+        .collect()
+}
+
+fn decimal_width(n: usize) -> usize {
+    n.to_string().len()
+}
+
+/// Columns `s` takes up, counting a character as one.
+fn display_width(s: &str) -> usize {
+    s.chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(name: &str, added: usize, deleted: usize) -> FileStat {
+        FileStat {
+            name: name.to_string(),
+            change: StatChange::Lines { added, deleted },
+        }
+    }
+
+    #[test]
+    fn compresses_rename_names() {
+        let cases = [
+            ("a.txt", "b.txt", "a.txt => b.txt"),
+            ("src/a.rs", "src/b.rs", "src/{a.rs => b.rs}"),
+            ("a/x/file", "a/y/file", "a/{x => y}/file"),
+            ("dir/file", "file", "dir/file => file"),
+            ("x/dir/file", "x/file", "x/{dir => }/file"),
+            ("a/b", "a/c/b", "a/{ => c}/b"),
+            ("lib/one/x.rs", "src/one/x.rs", "{lib => src}/one/x.rs"),
+        ];
+        for (old, new, want) in cases {
+            assert_eq!(rename_name(old, new), want, "{} => {}", old, new);
+        }
+    }
+
+    #[test]
+    fn scales_bars_and_names_to_the_width() {
+        let files = [
+            lines("short", 1, 1),
+            lines("a/rather/long/path/that/will/not/fit.txt", 300, 100),
+            lines("mode-only", 0, 0),
+        ];
+        assert_eq!(
+            format_stat(&files, 60),
+            concat!(
+                " short                                  |   2 +-\n",
+                " .../long/path/that/will/not/fit.txt    | 400 +++++++++----\n",
+                " mode-only                              |   0\n",
+                " 3 files changed, 301 insertions(+), 101 deletions(-)\n",
+            )
+        );
+        assert_eq!(
+            format_stat(&[lines("one", 1, 0)], 80),
+            " one | 1 +\n 1 file changed, 1 insertion(+)\n"
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! Ignore rules from `.gitignore` files, `.git/info/exclude` and
+//! `core.excludesFile`.
+//!
+//! Rules are kept in one list ordered from lowest to highest precedence and
+//! the last pattern matching a path decides, so a `!negation` only needs to
+//! come later in the list than the pattern it overrides.
+//!
+//! A walk of the working tree reads the `.gitignore` files as it goes with
+//! an [`IgnoreStack`], rather than loading them all up front as
+//! [`load_all_ignores`] does.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::wildmatch::wildmatch;
+use crate::error::GitResult;
+use crate::repository::Repository;
+
+/// One line of an ignore file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    /// The line as written, minus trailing whitespace.
+    pub text: String,
+    /// The glob left once `!`, a leading `/` and a trailing `/` are removed.
+    glob: String,
+    pub negated: bool,
+    /// A trailing `/` restricts the pattern to directories.
+    pub dir_only: bool,
+    /// Patterns with a `/` other than a trailing one match the whole path
+    /// relative to `base`; the rest match just the last component.
+    anchored: bool,
+    /// The directory the pattern applies under, relative to the top of the
+    /// working tree; empty for the top itself.
+    base: String,
+    /// Where the pattern came from, if it was read from a file.
+    pub source: Option<PathBuf>,
+    /// The 1-based line number within `source`.
+    pub line: usize,
+}
+
+impl Pattern {
+    fn parse(line: &str, base: &str, source: Option<&Path>, number: usize) -> Option<Pattern> {
+        let text = trim_trailing_spaces(line);
+        if text.is_empty() || text.starts_with('#') {
+            return None;
+        }
+        let (negated, mut glob) = match text.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        if glob.starts_with("\\!") || glob.starts_with("\\#") {
+            glob = &glob[1..];
+        }
+        let dir_only = glob.ends_with('/');
+        let glob = glob.trim_end_matches('/');
+        let anchored = glob.contains('/');
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+        if glob.is_empty() {
+            return None;
+        }
+        Some(Pattern {
+            text: text.to_string(),
+            glob: glob.to_string(),
+            negated,
+            dir_only,
+            anchored,
+            base: base.to_string(),
+            source: source.map(Path::to_path_buf),
+            line: number,
+        })
+    }
+
+    /// Whether the pattern matches `path`, a `/`-separated path relative to
+    /// the top of the working tree.
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(self.base.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(relative) => relative,
+                None => return false,
+            }
+        };
+        let subject = if self.anchored {
+            relative
+        } else {
+            relative.rsplit('/').next().unwrap_or(relative)
+        };
+        wildmatch(self.glob.as_bytes(), subject.as_bytes(), true)
+    }
+}
+
+/// Trailing spaces are ignored unless escaped with a backslash.
+fn trim_trailing_spaces(line: &str) -> &str {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let trimmed = line.trim_end_matches(' ');
+    if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        &line[..trimmed.len() + 1]
+    } else {
+        trimmed
+    }
+}
+
+/// An ordered set of ignore patterns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IgnoreRules {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreRules {
+    pub fn new() -> IgnoreRules {
+        IgnoreRules::default()
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    /// Add the patterns in `text`, which apply to paths under `base`. They
+    /// take precedence over everything added before them, so command-line
+    /// patterns go last.
+    pub fn add_patterns(&mut self, text: &str, base: &str, source: Option<&Path>) {
+        for (number, line) in text.lines().enumerate() {
+            if let Some(pattern) = Pattern::parse(line, base, source, number + 1) {
+                self.patterns.push(pattern);
+            }
+        }
+    }
+
+    /// Add the patterns from the file at `path`, if it exists. `source` is
+    /// how the file is named in [`Pattern::source`].
+    pub fn add_file(&mut self, path: &Path, base: &str, source: &Path) -> GitResult<()> {
+        match fs::read(path) {
+            Ok(data) => {
+                self.add_patterns(&String::from_utf8_lossy(&data), base, Some(source));
+                Ok(())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(_) if path.is_dir() => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The pattern that decides whether `path` is ignored: the last one to
+    /// match it, which may be a negation. A path inside an ignored directory
+    /// is decided by the pattern ignoring that directory, since git never
+    /// looks inside it.
+    pub fn matching(&self, path: &str, is_dir: bool) -> Option<&Pattern> {
+        let mut end = 0;
+        while let Some(slash) = path[end..].find('/') {
+            end += slash;
+            if let Some(pattern) = self.last_match(&path[..end], true) {
+                if !pattern.negated {
+                    return Some(pattern);
+                }
+            }
+            end += 1;
+        }
+        self.last_match(path, is_dir)
+    }
+
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.matching(path, is_dir).is_some_and(|p| !p.negated)
+    }
+
+    fn last_match(&self, path: &str, is_dir: bool) -> Option<&Pattern> {
+        self.patterns.iter().rev().find(|p| p.matches(path, is_dir))
+    }
+}
+
+/// Every ignore rule that applies to the repository, lowest precedence
+/// first: `core.excludesFile`, `.git/info/exclude`, then each `.gitignore`
+/// in the working tree with deeper files after shallower ones. Callers add
+/// command-line patterns on top with [`IgnoreRules::add_patterns`].
+pub fn load_all_ignores(repo: &Repository) -> GitResult<IgnoreRules> {
+    let mut rules = load_repo_excludes(repo)?;
+    if let Some(work_dir) = repo.work_dir() {
+        add_gitignores(&mut rules, work_dir, "")?;
+    }
+    Ok(rules)
+}
+
+/// The rules that don't come from the working tree: `core.excludesFile`,
+/// then `.git/info/exclude`.
+fn load_repo_excludes(repo: &Repository) -> GitResult<IgnoreRules> {
+    let mut rules = IgnoreRules::new();
+    let config = repo.config()?;
+    if let Some(global) = global_excludes_file(config.get("core", None, "excludesFile")) {
+        rules.add_file(&global, "", &global)?;
+    }
+    let exclude = repo.git_dir().join("info").join("exclude");
+    let exclude_source = match repo.work_dir() {
+        Some(work_dir) => exclude.strip_prefix(work_dir).unwrap_or(&exclude),
+        None => &exclude,
+    };
+    rules.add_file(&exclude, "", exclude_source)?;
+    Ok(rules)
+}
+
+/// The ignore rules in force at one point of a walk down the working tree.
+///
+/// The walk [`push`](IgnoreStack::push)es each directory as it enters it,
+/// which reads that directory's `.gitignore` on top of its parents', and
+/// [`pop`](IgnoreStack::pop)s it on the way out, dropping them again, so
+/// each file is read once and only while it applies. As in git, the
+/// `.gitignore` of a directory that's itself ignored isn't read.
+#[derive(Debug, Clone)]
+pub struct IgnoreStack {
+    work_dir: PathBuf,
+    rules: IgnoreRules,
+    /// How many patterns there were before each directory pushed added
+    /// its own, innermost last.
+    marks: Vec<usize>,
+}
+
+impl IgnoreStack {
+    /// The repository's rules from outside the working tree, with no
+    /// directory pushed yet; push `""` for the top's `.gitignore`.
+    pub fn new(repo: &Repository) -> GitResult<IgnoreStack> {
+        Ok(IgnoreStack {
+            work_dir: repo.require_work_dir()?.to_path_buf(),
+            rules: load_repo_excludes(repo)?,
+            marks: Vec::new(),
+        })
+    }
+
+    /// Enter `dir`, relative to the top of the working tree, which should
+    /// be a child of the directory last pushed.
+    pub fn push(&mut self, dir: &str) -> GitResult<()> {
+        self.marks.push(self.rules.patterns.len());
+        if dir.is_empty() || !self.rules.is_ignored(dir, true) {
+            let source = Path::new(dir).join(".gitignore");
+            self.rules
+                .add_file(&self.work_dir.join(&source), dir, &source)?;
+        }
+        Ok(())
+    }
+
+    /// Leave the directory last pushed.
+    pub fn pop(&mut self) {
+        if let Some(mark) = self.marks.pop() {
+            self.rules.patterns.truncate(mark);
+        }
+    }
+
+    /// The rules for paths in the directory last pushed.
+    pub fn rules(&self) -> &IgnoreRules {
+        &self.rules
+    }
+
+    pub fn matching(&self, path: &str, is_dir: bool) -> Option<&Pattern> {
+        self.rules.matching(path, is_dir)
+    }
+
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.rules.is_ignored(path, is_dir)
+    }
+}
+
+/// `core.excludesFile` with `~/` expanded, defaulting to
+/// `$XDG_CONFIG_HOME/git/ignore` or `~/.config/git/ignore` like git.
+fn global_excludes_file(configured: Option<&str>) -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    match configured {
+        Some(path) => match path.strip_prefix("~/") {
+            Some(rest) => home.map(|home| home.join(rest)),
+            None => Some(PathBuf::from(path)),
+        },
+        None => match env::var_os("XDG_CONFIG_HOME") {
+            Some(config) if !config.is_empty() => Some(PathBuf::from(config).join("git/ignore")),
+            _ => home.map(|home| home.join(".config/git/ignore")),
+        },
+    }
+}
+
+/// Read `dir`'s `.gitignore` and those below it, skipping `.git` and any
+/// directory its parents' rules already ignore.
+fn add_gitignores(rules: &mut IgnoreRules, work_dir: &Path, dir: &str) -> GitResult<()> {
+    let fs_dir = work_dir.join(dir);
+    let source = Path::new(dir).join(".gitignore");
+    rules.add_file(&fs_dir.join(".gitignore"), dir, &source)?;
+
+    let mut subdirs = Vec::new();
+    for entry in fs::read_dir(&fs_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == ".git" {
+            continue;
+        }
+        let path = if dir.is_empty() {
+            name
+        } else {
+            format!("{}/{}", dir, name)
+        };
+        if !rules.is_ignored(&path, true) {
+            subdirs.push(path);
+        }
+    }
+    subdirs.sort();
+    for subdir in subdirs {
+        add_gitignores(rules, work_dir, &subdir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{git_with_input, init_repo};
+
+    fn rules(text: &str) -> IgnoreRules {
+        let mut rules = IgnoreRules::new();
+        rules.add_patterns(text, "", None);
+        rules
+    }
+
+    #[test]
+    fn applies_directory_and_anchoring_rules() {
+        let rules = rules("/target\nlogs/\n*.log\n!keep.log\n");
+        assert!(rules.is_ignored("target", true));
+        assert!(!rules.is_ignored("sub/target", true));
+        assert!(rules.is_ignored("logs", true));
+        assert!(!rules.is_ignored("logs", false));
+        assert!(rules.is_ignored("a/debug.log", false));
+        assert!(!rules.is_ignored("a/keep.log", false));
+        // A negation can't reach inside an ignored directory.
+        assert!(rules.is_ignored("logs/keep.log", false));
+    }
+
+    #[test]
+    fn gitignore_overrides_info_exclude() {
+        let (_dir, repo) = init_repo();
+        let work_dir = repo.work_dir().unwrap();
+        fs::create_dir_all(repo.git_dir().join("info")).unwrap();
+        fs::write(repo.git_dir().join("info/exclude"), "# local\n*.tmp\n").unwrap();
+        fs::create_dir_all(work_dir.join("keep")).unwrap();
+        fs::write(work_dir.join("keep/.gitignore"), "!wanted.tmp\n").unwrap();
+
+        let rules = load_all_ignores(&repo).unwrap();
+        let decided = rules.matching("scratch.tmp", false).unwrap();
+        assert!(!decided.negated);
+        assert_eq!(
+            decided.source.as_deref(),
+            Some(Path::new(".git/info/exclude"))
+        );
+        assert_eq!(decided.line, 2);
+        assert!(rules.is_ignored("keep/other.tmp", false));
+        assert!(!rules.is_ignored("keep/wanted.tmp", false));
+        assert!(rules.is_ignored("wanted.tmp", false));
+    }
+
+    /// The layout of git's own t0008-ignores.sh: ignore files at three
+    /// levels, info/exclude and a global file.
+    #[test]
+    fn walks_the_t0008_layout_like_git() {
+        let (_dir, repo) = init_repo();
+        let work_dir = repo.work_dir().unwrap();
+        let global = work_dir.join("global-excludes");
+        fs::write(&global, "globalone\n!globaltwo\nglobalthree\n").unwrap();
+        crate::commands::config::config_set(&repo, "core.excludesFile", &global.to_string_lossy())
+            .unwrap();
+        fs::create_dir_all(repo.git_dir().join("info")).unwrap();
+        fs::write(repo.git_dir().join("info/exclude"), "per-repo\n").unwrap();
+        for dir in ["a/b/ignored-dir", "top-level-dir"] {
+            fs::create_dir_all(work_dir.join(dir)).unwrap();
+        }
+        fs::write(
+            work_dir.join(".gitignore"),
+            "one\nignored-*\ntop-level-dir/\n",
+        )
+        .unwrap();
+        fs::write(work_dir.join("a/.gitignore"), "two*\n*three\n").unwrap();
+        fs::write(
+            work_dir.join("a/b/.gitignore"),
+            "four\nfive\n# this comment should affect the line numbers\nsix\n\
+             ignored-dir/\n# and so should this blank line:\n\n!on*\n!two\n",
+        )
+        .unwrap();
+        // Never read: its directory is ignored.
+        fs::write(work_dir.join("a/b/ignored-dir/.gitignore"), "!seven\n").unwrap();
+        // A file named like a directory-only pattern.
+        fs::write(work_dir.join("a/top-level-dir"), "").unwrap();
+
+        let mut stack = IgnoreStack::new(&repo).unwrap();
+        for dir in ["", "a", "a/b", "a/b/ignored-dir"] {
+            stack.push(dir).unwrap();
+        }
+        let paths = [
+            "one",
+            "not-ignored",
+            "a/one",
+            "a/not-ignored",
+            "a/b/on",
+            "a/b/one",
+            "a/b/one one",
+            "a/b/two",
+            "a/b/twooo",
+            "a/3-three",
+            "a/b/3-three",
+            "a/b/four",
+            "a/b/five",
+            "a/b/six",
+            "a/b/ignored-dir",
+            "a/b/ignored-dir/foo",
+            "a/b/ignored-dir/twoooo",
+            "a/b/ignored-dir/seven",
+            "top-level-dir",
+            "a/top-level-dir",
+            "ignored-and-untracked",
+            "globalone",
+            "a/globalthree",
+            "globaltwo",
+            "per-repo",
+            "a/per-repo",
+        ];
+        let mut ours = String::new();
+        for path in paths {
+            let is_dir = work_dir.join(path).is_dir();
+            match stack.matching(path, is_dir) {
+                Some(p) => ours.push_str(&format!(
+                    "{}:{}:{}\t{}\n",
+                    p.source.as_deref().unwrap().display(),
+                    p.line,
+                    p.text,
+                    path
+                )),
+                None => ours.push_str(&format!("::\t{}\n", path)),
+            }
+        }
+        let input = paths.join("\n") + "\n";
+        if let Some(theirs) =
+            git_with_input(&repo, &["check-ignore", "-v", "-n", "--stdin"], &input)
+        {
+            // Given `--git-dir`, git names info/exclude in full.
+            let exclude = repo.git_dir().join("info/exclude");
+            let theirs = theirs.replace(&exclude.display().to_string(), ".git/info/exclude");
+            assert_eq!(ours, theirs);
+        }
+        assert!(ours.contains("a/b/.gitignore:5:ignored-dir/\ta/b/ignored-dir/seven\n"));
+        assert!(ours.contains("::\ta/top-level-dir\n"));
+
+        // Each directory's rules go with it.
+        stack.pop();
+        stack.pop();
+        assert!(stack.is_ignored("a/twooo", false));
+        assert!(!stack.is_ignored("a/b/four", false));
+        stack.pop();
+        assert!(!stack.is_ignored("a/twooo", false));
+        assert!(stack.is_ignored("one", false));
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use sha1::{Digest, Sha1};
+
+use crate::core::oid::{self, Oid};
+use crate::error::{GitError, GitResult};
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const HEADER_LEN: usize = 12;
+/// Length of an entry up to (but not including) its path.
+const ENTRY_FIXED_LEN: usize = 62;
+const STAGE_MASK: u16 = 0x3000;
+const STAGE_SHIFT: u16 = 12;
+const NAME_MASK: u16 = 0x0fff;
+const EXTENDED_FLAG: u16 = 0x4000;
+
+/// One entry in `.git/index`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IndexEntry {
+    pub ctime: u32,
+    pub ctime_nsec: u32,
+    pub mtime: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub oid: Oid,
+    pub flags: u16,
+    pub path: String,
+}
+
+impl IndexEntry {
+    /// An entry with no stat information, which forces the next status
+    /// check to re-hash the file.
+    pub fn new(path: &str, oid: Oid, mode: u32) -> IndexEntry {
+        let mut entry = IndexEntry {
+            path: path.to_string(),
+            oid,
+            mode,
+            ..IndexEntry::default()
+        };
+        entry.set_name_len();
+        entry
+    }
+
+    /// The merge stage: 0 for a normal entry, 1-3 for base/ours/theirs
+    /// during a conflicted merge.
+    pub fn stage(&self) -> u8 {
+        ((self.flags & STAGE_MASK) >> STAGE_SHIFT) as u8
+    }
+
+    pub fn set_stage(&mut self, stage: u8) {
+        self.flags = (self.flags & !STAGE_MASK) | ((u16::from(stage) << STAGE_SHIFT) & STAGE_MASK);
+    }
+
+    pub fn with_stage(mut self, stage: u8) -> IndexEntry {
+        self.set_stage(stage);
+        self
+    }
+
+    fn set_name_len(&mut self) {
+        let len = self.path.len().min(NAME_MASK as usize) as u16;
+        self.flags = (self.flags & !NAME_MASK) | len;
+    }
+
+    /// Copy stat information from the file's metadata.
+    #[cfg(unix)]
+    pub fn update_stat(&mut self, meta: &fs::Metadata) {
+        use std::os::unix::fs::MetadataExt;
+        self.ctime = meta.ctime() as u32;
+        self.ctime_nsec = meta.ctime_nsec() as u32;
+        self.mtime = meta.mtime() as u32;
+        self.mtime_nsec = meta.mtime_nsec() as u32;
+        self.dev = meta.dev() as u32;
+        self.ino = meta.ino() as u32;
+        self.uid = meta.uid();
+        self.gid = meta.gid();
+        self.size = meta.len() as u32;
+    }
+
+    #[cfg(not(unix))]
+    pub fn update_stat(&mut self, meta: &fs::Metadata) {
+        self.size = meta.len() as u32;
+    }
+
+    /// Whether a working tree file's metadata still matches the stat
+    /// information recorded, so that the file can be taken as unchanged
+    /// without reading it.
+    #[cfg(unix)]
+    pub fn stat_matches(&self, meta: &fs::Metadata) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        self.mtime == meta.mtime() as u32
+            && self.mtime_nsec == meta.mtime_nsec() as u32
+            && self.ctime == meta.ctime() as u32
+            && self.ctime_nsec == meta.ctime_nsec() as u32
+            && self.ino == meta.ino() as u32
+            && self.uid == meta.uid()
+            && self.gid == meta.gid()
+            && self.size == meta.len() as u32
+    }
+
+    /// Without the times and inode to go on, every file has to be read.
+    #[cfg(not(unix))]
+    pub fn stat_matches(&self, _meta: &fs::Metadata) -> bool {
+        false
+    }
+}
+
+/// A path left conflicted by a merge, with the blob each side of it had.
+/// A side is `None` when that side didn't have the path, as when one side
+/// deleted a file the other changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictedPath {
+    pub path: String,
+    /// Stage 1, the merge base's version.
+    pub base: Option<Oid>,
+    /// Stage 2, the version on the branch being merged into.
+    pub ours: Option<Oid>,
+    /// Stage 3, the version being merged in.
+    pub theirs: Option<Oid>,
+}
+
+/// The staging area: entries sorted by path and then stage.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Index {
+    entries: Vec<IndexEntry>,
+}
+
+impl Index {
+    pub fn new() -> Index {
+        Index::default()
+    }
+
+    /// Load an index file; a missing file is an empty index.
+    pub fn load(path: &Path) -> GitResult<Index> {
+        match fs::read(path) {
+            Ok(data) => Index::parse(&data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Index::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> GitResult<Index> {
+        let corrupt = |msg: &str| GitError::Corrupt(format!("index: {}", msg));
+        if data.len() < HEADER_LEN + 20 || &data[..4] != SIGNATURE {
+            return Err(corrupt("bad signature"));
+        }
+        let (body, checksum) = data.split_at(data.len() - 20);
+        let actual: [u8; 20] = Sha1::digest(body).into();
+        if actual[..] != checksum[..] {
+            return Err(corrupt("checksum mismatch"));
+        }
+        let version = read_u32(data, 4);
+        if version != 2 && version != 3 {
+            return Err(corrupt(&format!("unsupported version {}", version)));
+        }
+        let count = read_u32(data, 8) as usize;
+
+        let mut entries = Vec::with_capacity(count);
+        let mut pos = HEADER_LEN;
+        for _ in 0..count {
+            if pos + ENTRY_FIXED_LEN > body.len() {
+                return Err(corrupt("truncated entry"));
+            }
+            let field = |i: usize| read_u32(body, pos + i * 4);
+            let flags = u16::from_be_bytes([body[pos + 60], body[pos + 61]]);
+            let mut path_start = pos + ENTRY_FIXED_LEN;
+            if flags & EXTENDED_FLAG != 0 {
+                path_start += 2;
+            }
+            let nul = body[path_start..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| corrupt("unterminated path"))?;
+            let path = String::from_utf8_lossy(&body[path_start..path_start + nul]).into_owned();
+            entries.push(IndexEntry {
+                ctime: field(0),
+                ctime_nsec: field(1),
+                mtime: field(2),
+                mtime_nsec: field(3),
+                dev: field(4),
+                ino: field(5),
+                mode: field(6),
+                uid: field(7),
+                gid: field(8),
+                size: field(9),
+                oid: oid::from_bytes(&body[pos + 40..pos + 60])?,
+                flags: flags & !EXTENDED_FLAG,
+                path,
+            });
+            // Entries are NUL padded to a multiple of eight bytes.
+            let entry_len = path_start - pos + nul;
+            pos += (entry_len + 8) & !7;
+        }
+        // Anything between the entries and the checksum is extensions,
+        // which are caches we're free to drop.
+        let mut index = Index { entries };
+        index.sort();
+        Ok(index)
+    }
+
+    pub fn save(&self, path: &Path) -> GitResult<()> {
+        let lock = path.with_extension("lock");
+        fs::write(&lock, self.serialize())?;
+        fs::rename(&lock, path)?;
+        Ok(())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(SIGNATURE);
+        out.extend_from_slice(&2u32.to_be_bytes());
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries {
+            let start = out.len();
+            for field in &[
+                entry.ctime,
+                entry.ctime_nsec,
+                entry.mtime,
+                entry.mtime_nsec,
+                entry.dev,
+                entry.ino,
+                entry.mode,
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ] {
+                out.extend_from_slice(&field.to_be_bytes());
+            }
+            out.extend_from_slice(entry.oid.as_bytes());
+            let name_len = entry.path.len().min(NAME_MASK as usize) as u16;
+            let flags = (entry.flags & STAGE_MASK) | name_len;
+            out.extend_from_slice(&flags.to_be_bytes());
+            out.extend_from_slice(entry.path.as_bytes());
+            let padded = (out.len() - start + 8) & !7;
+            out.resize(start + padded, 0);
+        }
+        let checksum: [u8; 20] = Sha1::digest(&out).into();
+        out.extend_from_slice(&checksum);
+        out
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, path: &str, stage: u8) -> Option<&IndexEntry> {
+        self.find(path, stage).ok().map(|i| &self.entries[i])
+    }
+
+    /// Insert or replace an entry. Staging a path at stage 0 resolves any
+    /// conflict on it, and adding a conflict stage drops the stage 0 entry.
+    pub fn add(&mut self, mut entry: IndexEntry) {
+        entry.set_name_len();
+        if entry.stage() == 0 {
+            self.entries.retain(|e| e.path != entry.path);
+        } else {
+            self.entries
+                .retain(|e| !(e.path == entry.path && e.stage() == 0));
+        }
+        match self.find(&entry.path, entry.stage()) {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    /// Remove every stage of `path`, returning whether anything was removed.
+    pub fn remove(&mut self, path: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.path != path);
+        self.entries.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|e| e.stage() != 0)
+    }
+
+    /// Every directory with a tracked path somewhere under it, as
+    /// `/`-separated paths without a trailing slash.
+    pub fn directories(&self) -> HashSet<&str> {
+        let mut dirs = HashSet::new();
+        for entry in &self.entries {
+            let mut path = entry.path.as_str();
+            while let Some((dir, _)) = path.rsplit_once('/') {
+                if !dirs.insert(dir) {
+                    break;
+                }
+                path = dir;
+            }
+        }
+        dirs
+    }
+
+    /// Every conflicted path, sorted, with its stage 1-3 entries gathered
+    /// together.
+    pub fn conflicts(&self) -> Vec<ConflictedPath> {
+        let mut conflicts: Vec<ConflictedPath> = Vec::new();
+        for entry in self.entries.iter().filter(|e| e.stage() != 0) {
+            if conflicts.last().is_none_or(|c| c.path != entry.path) {
+                conflicts.push(ConflictedPath {
+                    path: entry.path.clone(),
+                    base: None,
+                    ours: None,
+                    theirs: None,
+                });
+            }
+            let conflict = conflicts.last_mut().unwrap();
+            match entry.stage() {
+                1 => conflict.base = Some(entry.oid),
+                2 => conflict.ours = Some(entry.oid),
+                _ => conflict.theirs = Some(entry.oid),
+            }
+        }
+        conflicts
+    }
+
+    fn find(&self, path: &str, stage: u8) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|e| (e.path.as_bytes(), e.stage()).cmp(&(path.as_bytes(), stage)))
+    }
+
+    fn sort(&mut self) {
+        self.entries
+            .sort_by(|a, b| (a.path.as_bytes(), a.stage()).cmp(&(b.path.as_bytes(), b.stage())));
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::object::MODE_FILE;
+
+    #[test]
+    fn round_trips_entries_and_stages() {
+        let blob = Oid::from_raw([7; 20]);
+        let mut index = Index::new();
+        index.add(IndexEntry::new("b.txt", blob, MODE_FILE));
+        index.add(IndexEntry::new("a/long-name.txt", blob, MODE_FILE));
+        index.add(IndexEntry::new("c", blob, MODE_FILE).with_stage(2));
+        index.add(IndexEntry::new("c", blob, MODE_FILE).with_stage(3));
+
+        let parsed = Index::parse(&index.serialize()).unwrap();
+        assert_eq!(parsed, index);
+        let paths: Vec<_> = parsed
+            .entries()
+            .iter()
+            .map(|e| (e.path.as_str(), e.stage()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![("a/long-name.txt", 0), ("b.txt", 0), ("c", 2), ("c", 3)]
+        );
+        assert!(parsed.has_conflicts());
+    }
+
+    #[test]
+    fn gathers_the_stages_of_each_conflict() {
+        let mut index = Index::new();
+        index.add(IndexEntry::new("a", Oid::from_raw([9; 20]), MODE_FILE));
+        index.add(IndexEntry::new("c", Oid::from_raw([3; 20]), MODE_FILE).with_stage(3));
+        index.add(IndexEntry::new("c", Oid::from_raw([1; 20]), MODE_FILE).with_stage(1));
+        index.add(IndexEntry::new("c", Oid::from_raw([2; 20]), MODE_FILE).with_stage(2));
+        index.add(IndexEntry::new("d", Oid::from_raw([4; 20]), MODE_FILE).with_stage(2));
+
+        let parsed = Index::parse(&index.serialize()).unwrap();
+        assert_eq!(
+            parsed.conflicts(),
+            [
+                ConflictedPath {
+                    path: "c".to_string(),
+                    base: Some(Oid::from_raw([1; 20])),
+                    ours: Some(Oid::from_raw([2; 20])),
+                    theirs: Some(Oid::from_raw([3; 20])),
+                },
+                ConflictedPath {
+                    path: "d".to_string(),
+                    base: None,
+                    ours: Some(Oid::from_raw([4; 20])),
+                    theirs: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn staging_resolves_conflicts() {
+        let mut index = Index::new();
+        index.add(IndexEntry::new("c", Oid::from_raw([1; 20]), MODE_FILE).with_stage(1));
+        index.add(IndexEntry::new("c", Oid::from_raw([2; 20]), MODE_FILE).with_stage(2));
+        index.add(IndexEntry::new("c", Oid::from_raw([3; 20]), MODE_FILE));
+        assert_eq!(index.entries().len(), 1);
+        assert!(!index.has_conflicts());
+    }
+}
@@ -0,0 +1,105 @@
+//! Git-style `<file>.lock` files: create the lock exclusively, write the
+//! new contents into it, then rename it over the original.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// An exclusively held lock on `path`. Dropping it without calling
+/// [`LockFile::commit`] removes the lock file and leaves `path` untouched.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    /// Take the lock for `path`. Fails with `io::ErrorKind::AlreadyExists`
+    /// if someone else holds it.
+    pub fn acquire(path: &Path) -> io::Result<LockFile> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        if let Some(parent) = lock_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)?;
+        Ok(LockFile {
+            path: path.to_path_buf(),
+            lock_path,
+            file: Some(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flush the written contents to disk and rename them into place.
+    pub fn commit(mut self) -> io::Result<()> {
+        let result = match self.file.take() {
+            Some(file) => file
+                .sync_all()
+                .and_then(|_| fs::rename(&self.lock_path, &self.path)),
+            None => Ok(()),
+        };
+        if result.is_err() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+        result
+    }
+}
+
+impl Write for LockFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.file {
+            Some(file) => file.write(buf),
+            None => Err(io::Error::other("lock already committed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commits_or_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, "old").unwrap();
+
+        let mut lock = LockFile::acquire(&path).unwrap();
+        lock.write_all(b"new").unwrap();
+        let err = LockFile::acquire(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        drop(lock);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(!dir.path().join("file.lock").exists());
+
+        let mut lock = LockFile::acquire(&path).unwrap();
+        lock.write_all(b"new").unwrap();
+        lock.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!dir.path().join("file.lock").exists());
+    }
+}
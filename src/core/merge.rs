@@ -0,0 +1,230 @@
+//! Three-way merging of file contents.
+
+use crate::core::diff::{self, DiffOp};
+
+/// The outcome of merging one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    /// The merged content, including conflict markers for any region that
+    /// couldn't be merged. For binary files this is our side unchanged.
+    pub content: Vec<u8>,
+    pub conflicted: bool,
+}
+
+/// Merge `ours` and `theirs`, both descended from `base`, line by line.
+pub fn merge_blobs(base: &[u8], ours: &[u8], theirs: &[u8]) -> MergeResult {
+    merge_blobs_with_labels(base, ours, theirs, "ours", "theirs")
+}
+
+/// Like [`merge_blobs`], naming each side on its conflict markers.
+pub fn merge_blobs_with_labels(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    our_label: &str,
+    their_label: &str,
+) -> MergeResult {
+    if ours == theirs || base == theirs {
+        return clean(ours);
+    }
+    if base == ours {
+        return clean(theirs);
+    }
+    if diff::is_binary(base) || diff::is_binary(ours) || diff::is_binary(theirs) {
+        return MergeResult {
+            content: ours.to_vec(),
+            conflicted: true,
+        };
+    }
+
+    let base_lines = diff::split_lines(base);
+    let our_lines = diff::split_lines(ours);
+    let their_lines = diff::split_lines(theirs);
+    let our_hunks = hunks(&base_lines, &our_lines);
+    let their_hunks = hunks(&base_lines, &their_lines);
+
+    let mut out = Vec::new();
+    let mut conflicted = false;
+    let mut pos = 0;
+    let (mut i, mut j) = (0, 0);
+    while i < our_hunks.len() || j < their_hunks.len() {
+        // Gather a run of hunks that overlap or touch, starting from
+        // whichever side changes the base first.
+        let take_ours = j >= their_hunks.len()
+            || (i < our_hunks.len() && our_hunks[i].start <= their_hunks[j].start);
+        let first = if take_ours {
+            &our_hunks[i]
+        } else {
+            &their_hunks[j]
+        };
+        let (start, mut end) = (first.start, first.end);
+        let (i0, j0) = (i, j);
+        loop {
+            if i < our_hunks.len() && our_hunks[i].start <= end {
+                end = end.max(our_hunks[i].end);
+                i += 1;
+            } else if j < their_hunks.len() && their_hunks[j].start <= end {
+                end = end.max(their_hunks[j].end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        push_all(&mut out, &base_lines[pos..start]);
+        pos = end;
+        let ours_region = apply(&base_lines, start, end, &our_hunks[i0..i]);
+        let theirs_region = apply(&base_lines, start, end, &their_hunks[j0..j]);
+        if i0 == i {
+            push_all(&mut out, &theirs_region);
+        } else if j0 == j || ours_region == theirs_region {
+            push_all(&mut out, &ours_region);
+        } else {
+            conflicted = true;
+            write_conflict(
+                &mut out,
+                &ours_region,
+                &theirs_region,
+                our_label,
+                their_label,
+            );
+        }
+    }
+    push_all(&mut out, &base_lines[pos..]);
+    MergeResult {
+        content: out,
+        conflicted,
+    }
+}
+
+fn clean(content: &[u8]) -> MergeResult {
+    MergeResult {
+        content: content.to_vec(),
+        conflicted: false,
+    }
+}
+
+/// A change to the base: lines `start..end` are replaced by `lines`.
+struct Hunk<'a> {
+    start: usize,
+    end: usize,
+    lines: Vec<&'a [u8]>,
+}
+
+fn hunks<'a>(base: &[&'a [u8]], side: &[&'a [u8]]) -> Vec<Hunk<'a>> {
+    let mut hunks: Vec<Hunk<'a>> = Vec::new();
+    for op in diff::diff(base, side) {
+        let (start, end, lines) = match op {
+            DiffOp::Equal { .. } => continue,
+            DiffOp::Delete { old, len, .. } => (old, old + len, Vec::new()),
+            DiffOp::Insert { old, new, len } => (old, old, side[new..new + len].to_vec()),
+        };
+        // An insert straight after a delete belongs to the same hunk.
+        match hunks.last_mut() {
+            Some(last) if last.end == start => {
+                last.end = end;
+                last.lines.extend(lines);
+            }
+            _ => hunks.push(Hunk { start, end, lines }),
+        }
+    }
+    hunks
+}
+
+/// One side's version of base lines `start..end`.
+fn apply<'a>(base: &[&'a [u8]], start: usize, end: usize, hunks: &[Hunk<'a>]) -> Vec<&'a [u8]> {
+    let mut out = Vec::new();
+    let mut pos = start;
+    for hunk in hunks {
+        out.extend_from_slice(&base[pos..hunk.start]);
+        out.extend_from_slice(&hunk.lines);
+        pos = hunk.end;
+    }
+    out.extend_from_slice(&base[pos..end]);
+    out
+}
+
+/// Emit a conflict, keeping lines both sides agree on at either end of
+/// the region outside the markers.
+fn write_conflict(
+    out: &mut Vec<u8>,
+    ours: &[&[u8]],
+    theirs: &[&[u8]],
+    our_label: &str,
+    their_label: &str,
+) {
+    let prefix = ours.iter().zip(theirs).take_while(|(a, b)| a == b).count();
+    let suffix = ours[prefix..]
+        .iter()
+        .rev()
+        .zip(theirs[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    push_all(out, &ours[..prefix]);
+    out.extend_from_slice(format!("<<<<<<< {}\n", our_label).as_bytes());
+    push_lines(out, &ours[prefix..ours.len() - suffix]);
+    out.extend_from_slice(b"=======\n");
+    push_lines(out, &theirs[prefix..theirs.len() - suffix]);
+    out.extend_from_slice(format!(">>>>>>> {}\n", their_label).as_bytes());
+    push_all(out, &ours[ours.len() - suffix..]);
+}
+
+fn push_all(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        out.extend_from_slice(line);
+    }
+}
+
+/// Like `push_all`, but terminates an unterminated last line so a marker
+/// can follow it.
+fn push_lines(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    push_all(out, lines);
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_disjoint_edits() {
+        let base = b"one\ntwo\nthree\nfour\nfive\n";
+        let ours = b"ONE\ntwo\nthree\nfour\nfive\n";
+        let theirs = b"one\ntwo\nthree\nfour\nFIVE\nsix\n";
+        let result = merge_blobs(base, ours, theirs);
+        assert!(!result.conflicted);
+        assert_eq!(result.content, b"ONE\ntwo\nthree\nfour\nFIVE\nsix\n");
+    }
+
+    #[test]
+    fn identical_changes_do_not_conflict() {
+        let base = b"a\nb\nc\n";
+        let ours = b"a\nB\nc\nd\n";
+        let theirs = b"a\nB\nc\n";
+        let result = merge_blobs(base, ours, theirs);
+        assert!(!result.conflicted);
+        assert_eq!(result.content, b"a\nB\nc\nd\n");
+    }
+
+    #[test]
+    fn overlapping_edits_conflict_with_markers() {
+        let base = b"a\nb\nc\nd\ne\n";
+        let ours = b"a\nb\nours\nd\ne\n";
+        let theirs = b"a\nb\ntheirs\nd\nE\n";
+        let result = merge_blobs_with_labels(base, ours, theirs, "HEAD", "topic");
+        assert!(result.conflicted);
+        assert_eq!(
+            String::from_utf8(result.content).unwrap(),
+            "a\nb\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\nd\nE\n"
+        );
+    }
+
+    #[test]
+    fn binary_content_is_a_whole_file_conflict() {
+        let result = merge_blobs(b"\0base", b"\0ours", b"\0theirs");
+        assert!(result.conflicted);
+        assert_eq!(result.content, b"\0ours");
+    }
+}
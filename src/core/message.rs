@@ -0,0 +1,318 @@
+//! Commit messages: the cleanup git applies before a message is stored,
+//! and the trailer block (`Signed-off-by:` and the like) at their end.
+
+use crate::core::config::Config;
+
+/// What [`cleanup`] does to a message, as `git commit --cleanup` names
+/// the modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupMode {
+    /// Leave the message exactly as it is.
+    Verbatim,
+    /// Strip trailing whitespace from every line, collapse runs of blank
+    /// lines into one and drop blank lines at the start and end.
+    Whitespace,
+    /// [`CleanupMode::Whitespace`], and also drop comment lines.
+    Strip,
+}
+
+/// `git commit -m` uses `whitespace`; only a message from the editor is
+/// stripped of comments by default.
+impl Default for CleanupMode {
+    fn default() -> CleanupMode {
+        CleanupMode::Whitespace
+    }
+}
+
+/// The character that starts a comment line: `core.commentChar`, or `#`.
+/// Values other than a single character, such as `auto`, keep `#`.
+pub fn comment_char(config: &Config) -> char {
+    let mut chars = config
+        .get("core", None, "commentchar")
+        .unwrap_or("")
+        .chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => '#',
+    }
+}
+
+/// `message` cleaned up as `mode` says, with `#` starting comments. A
+/// message left with any text ends in exactly one newline; one without
+/// any comes back empty.
+pub fn cleanup(message: &str, mode: CleanupMode) -> String {
+    cleanup_with(message, mode, '#')
+}
+
+/// [`cleanup`] with comments starting at `comment_char` instead.
+pub fn cleanup_with(message: &str, mode: CleanupMode, comment_char: char) -> String {
+    if mode == CleanupMode::Verbatim {
+        return message.to_string();
+    }
+    let mut out = String::with_capacity(message.len());
+    let mut blank = false;
+    for line in message.split('\n') {
+        if mode == CleanupMode::Strip && line.starts_with(comment_char) {
+            continue;
+        }
+        // `\r` counts as trailing space, which is what undoes CRLF endings.
+        let line = line.trim_end_matches(is_space);
+        if line.is_empty() {
+            blank = true;
+            continue;
+        }
+        if blank && !out.is_empty() {
+            out.push('\n');
+        }
+        blank = false;
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0b' | '\x0c')
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trailer {
+    pub key: String,
+    pub value: String,
+}
+
+/// The trailer block of a message: its last paragraph, when that isn't
+/// also its first and reads as `Key: value` lines. Like git, a block is
+/// accepted when every line is a trailer or a continuation of one, or
+/// when a quarter of them are and one is a `Signed-off-by:`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trailers {
+    trailers: Vec<Trailer>,
+}
+
+impl Trailers {
+    pub fn parse(message: &str) -> Trailers {
+        Trailers {
+            trailers: trailer_block(message).unwrap_or_default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trailers.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Trailer> {
+        self.trailers.iter()
+    }
+
+    /// The values of every trailer named `key`, compared without regard
+    /// to case, in order.
+    pub fn get<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.trailers
+            .iter()
+            .filter(move |t| t.key.eq_ignore_ascii_case(key))
+            .map(|t| t.value.as_str())
+    }
+}
+
+/// `message` with `key: value` added to its trailer block, starting one
+/// after a blank line if it has none. Like `git commit --signoff`, nothing
+/// is added when the block already ends with that same trailer, so
+/// appending twice leaves one.
+pub fn append_trailer(message: &str, key: &str, value: &str) -> String {
+    let mut out = message.trim_end_matches(is_space).to_string();
+    match trailer_block(&out) {
+        Some(trailers) => {
+            let last = trailers.last();
+            if last.is_some_and(|t| t.key.eq_ignore_ascii_case(key) && t.value == value) {
+                out.push('\n');
+                return out;
+            }
+            out.push('\n');
+        }
+        None if out.is_empty() => {}
+        None => out.push_str("\n\n"),
+    }
+    out.push_str(&format!("{}: {}\n", key, value));
+    out
+}
+
+/// `message` with `line` added at the end of its trailer block, or after
+/// a blank line if it has none, as `git cherry-pick -x` adds its
+/// [`CHERRY_PICKED_PREFIX`] line.
+pub fn append_to_trailers(message: &str, line: &str) -> String {
+    let mut out = message.trim_end_matches(is_space).to_string();
+    if trailer_block(&out).is_none() && !out.is_empty() {
+        out.push('\n');
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(line);
+    out.push('\n');
+    out
+}
+
+/// What the line `git cherry-pick -x` adds starts with. Git counts these
+/// lines as part of a trailer block, though they aren't trailers.
+pub const CHERRY_PICKED_PREFIX: &str = "(cherry picked from commit ";
+
+/// The trailers in the trailer block of `message`, if it has one. A block
+/// of only [`CHERRY_PICKED_PREFIX`] lines has no trailers.
+fn trailer_block(message: &str) -> Option<Vec<Trailer>> {
+    let message = message.trim_end_matches(is_space);
+    // The first paragraph is the subject, never trailers.
+    let start = message.rfind("\n\n")? + 2;
+    let block = &message[start..];
+    let (mut lines, mut trailer_lines, mut recognised) = (0, 0, false);
+    let mut trailers: Vec<Trailer> = Vec::new();
+    for line in block.lines() {
+        lines += 1;
+        if line.starts_with([' ', '\t']) {
+            // A continuation of the previous trailer's value.
+            match trailers.last_mut() {
+                Some(last) => {
+                    last.value.push(' ');
+                    last.value.push_str(line.trim());
+                }
+                None => return None,
+            }
+            trailer_lines += 1;
+            continue;
+        }
+        if line.starts_with(CHERRY_PICKED_PREFIX) {
+            recognised = true;
+            trailer_lines += 1;
+            continue;
+        }
+        if let Some(trailer) = parse_trailer(line) {
+            recognised |= trailer.key == "Signed-off-by";
+            trailers.push(trailer);
+            trailer_lines += 1;
+        }
+    }
+    let accepted = trailer_lines == lines || (recognised && trailer_lines * 4 >= lines);
+    if accepted && trailer_lines > 0 {
+        Some(trailers)
+    } else {
+        None
+    }
+}
+
+/// `Key: value`, where the key is letters, digits and dashes.
+fn parse_trailer(line: &str) -> Option<Trailer> {
+    let (key, value) = line.split_once(':')?;
+    let key = key.trim_end();
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    Some(Trailer {
+        key: key.to_string(),
+        value: value.trim().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{git_with_input, init_repo};
+
+    #[test]
+    fn cleans_up_messy_messages() {
+        let messy = "\r\n\n  Subject line  \r\n\r\n\r\n# a comment\r\nbody\twith tab \r\n\
+                     ;not a comment\n\n\n# trailing comment\n\n\n";
+        assert_eq!(
+            cleanup(messy, CleanupMode::Whitespace),
+            "  Subject line\n\n# a comment\nbody\twith tab\n;not a comment\n\n# trailing comment\n"
+        );
+        assert_eq!(
+            cleanup(messy, CleanupMode::Strip),
+            "  Subject line\n\nbody\twith tab\n;not a comment\n"
+        );
+        assert_eq!(
+            cleanup_with(messy, CleanupMode::Strip, ';'),
+            "  Subject line\n\n# a comment\nbody\twith tab\n\n# trailing comment\n"
+        );
+        assert_eq!(cleanup(messy, CleanupMode::Verbatim), messy);
+        assert_eq!(cleanup("# only\n\n  \n", CleanupMode::Strip), "");
+
+        let (_dir, repo) = init_repo();
+        if let Some(theirs) = git_with_input(&repo, &["stripspace"], messy) {
+            assert_eq!(cleanup(messy, CleanupMode::Whitespace), theirs);
+        }
+        if let Some(theirs) = git_with_input(&repo, &["stripspace", "-s"], messy) {
+            assert_eq!(cleanup(messy, CleanupMode::Strip), theirs);
+        }
+        let config = Config::parse("[core]\n\tcommentChar = \";\"\n").unwrap();
+        assert_eq!(comment_char(&config), ';');
+        assert_eq!(comment_char(&Config::default()), '#');
+    }
+
+    #[test]
+    fn parses_and_appends_trailers() {
+        let message = "Subject\n\nBody text.\n\nSigned-off-by: A <a@example.com>\n\
+                       Co-authored-by: B\n  continued <b@example.com>\n";
+        let trailers = Trailers::parse(message);
+        let parsed: Vec<(&str, &str)> = trailers
+            .iter()
+            .map(|t| (t.key.as_str(), t.value.as_str()))
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                ("Signed-off-by", "A <a@example.com>"),
+                ("Co-authored-by", "B continued <b@example.com>"),
+            ]
+        );
+        assert_eq!(
+            trailers.get("co-authored-by").collect::<Vec<_>>(),
+            ["B continued <b@example.com>"]
+        );
+        assert!(Trailers::parse("Key: value\n").is_empty());
+        assert!(Trailers::parse("Subject\n\nJust prose: here\nand more\n").is_empty());
+        let mostly_prose = "Subject\n\nnot a trailer\nstill not\nnor this\n\
+                            Signed-off-by: A <a@example.com>\n";
+        assert_eq!(
+            Trailers::parse(mostly_prose).get("Signed-off-by").count(),
+            1
+        );
+
+        let sob = "C <c@example.com>";
+        let signed = append_trailer(message, "Signed-off-by", sob);
+        assert_eq!(signed, format!("{}Signed-off-by: {}\n", message, sob));
+        assert_eq!(append_trailer(&signed, "Signed-off-by", sob), signed);
+        assert_eq!(
+            append_trailer("Subject\n\nBody.\n\n", "Signed-off-by", sob),
+            format!("Subject\n\nBody.\n\nSigned-off-by: {}\n", sob)
+        );
+        assert_eq!(
+            append_trailer("Signed-off-by: C <c@example.com>", "Signed-off-by", sob),
+            format!(
+                "Signed-off-by: C <c@example.com>\n\nSigned-off-by: {}\n",
+                sob
+            )
+        );
+
+        let origin = format!("{}1234567)", CHERRY_PICKED_PREFIX);
+        assert_eq!(
+            append_to_trailers("Subject\n", &origin),
+            format!("Subject\n\n{}\n", origin)
+        );
+        let picked = append_to_trailers(message, &origin);
+        assert_eq!(picked, format!("{}{}\n", message, origin));
+        assert_eq!(
+            append_trailer(&picked, "Signed-off-by", sob),
+            format!("{}Signed-off-by: {}\n", picked, sob)
+        );
+
+        let (_dir, repo) = init_repo();
+        let args = [
+            "interpret-trailers",
+            "--trailer",
+            "Signed-off-by: C <c@example.com>",
+        ];
+        if let Some(theirs) = git_with_input(&repo, &args, message) {
+            assert_eq!(signed, theirs);
+        }
+    }
+}
@@ -0,0 +1,34 @@
+pub mod commit_graph;
+pub mod config;
+pub mod date;
+pub mod diff;
+pub mod diffstat;
+pub mod ignore;
+pub mod index;
+pub mod lockfile;
+pub mod merge;
+pub mod message;
+pub mod object;
+pub mod object_cache;
+pub mod odb;
+pub mod oid;
+pub mod pack;
+pub mod packed_refs;
+pub mod patch;
+pub mod pathspec;
+pub mod pretty;
+pub mod quote;
+pub mod reflog;
+pub mod refs;
+pub mod refspec;
+pub mod regex;
+pub mod rename;
+pub mod revparse;
+pub mod revwalk;
+pub mod shallow;
+pub mod signature;
+pub mod signing;
+pub mod tree;
+pub mod wildmatch;
+pub mod word_diff;
+pub mod worktree;
@@ -0,0 +1,376 @@
+use std::fmt;
+
+use sha1::{Digest, Sha1};
+
+use crate::core::oid::{self, Oid};
+use crate::core::signature::Signature;
+use crate::error::{GitError, GitResult};
+
+pub const MODE_FILE: u32 = 0o100644;
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+pub const MODE_SYMLINK: u32 = 0o120000;
+pub const MODE_TREE: u32 = 0o040000;
+pub const MODE_GITLINK: u32 = 0o160000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ObjectType {
+    Blob,
+    Tree,
+    Commit,
+    Tag,
+}
+
+impl ObjectType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ObjectType::Blob => "blob",
+            ObjectType::Tree => "tree",
+            ObjectType::Commit => "commit",
+            ObjectType::Tag => "tag",
+        }
+    }
+
+    pub fn parse(s: &str) -> GitResult<ObjectType> {
+        match s {
+            "blob" => Ok(ObjectType::Blob),
+            "tree" => Ok(ObjectType::Tree),
+            "commit" => Ok(ObjectType::Commit),
+            "tag" => Ok(ObjectType::Tag),
+            _ => Err(GitError::Corrupt(format!("unknown object type: {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Hash an object body the way git does: `sha1("<type> <len>\0<body>")`.
+pub fn hash_object(kind: ObjectType, body: &[u8]) -> Oid {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{} {}\0", kind, body.len()).as_bytes());
+    hasher.update(body);
+    Oid::from_raw(hasher.finalize().into())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: u32,
+    pub name: String,
+    pub oid: Oid,
+}
+
+impl TreeEntry {
+    pub fn is_tree(&self) -> bool {
+        self.mode == MODE_TREE
+    }
+
+    /// Trees sort as though their name had a trailing `/`.
+    fn sort_key(&self) -> Vec<u8> {
+        let mut key = self.name.as_bytes().to_vec();
+        if self.is_tree() {
+            key.push(b'/');
+        }
+        key
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tree {
+    pub entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    pub fn parse(data: &[u8]) -> GitResult<Tree> {
+        let corrupt = || GitError::Corrupt("malformed tree entry".to_string());
+        let mut entries = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let space = rest.iter().position(|&b| b == b' ').ok_or_else(corrupt)?;
+            let mode = std::str::from_utf8(&rest[..space])
+                .ok()
+                .and_then(|m| u32::from_str_radix(m, 8).ok())
+                .ok_or_else(corrupt)?;
+            rest = &rest[space + 1..];
+            let nul = rest.iter().position(|&b| b == 0).ok_or_else(corrupt)?;
+            let name = String::from_utf8_lossy(&rest[..nul]).into_owned();
+            rest = &rest[nul + 1..];
+            if rest.len() < 20 {
+                return Err(corrupt());
+            }
+            let oid = oid::from_bytes(&rest[..20])?;
+            rest = &rest[20..];
+            entries.push(TreeEntry { mode, name, oid });
+        }
+        Ok(Tree { entries })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut entries: Vec<&TreeEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|e| e.sort_key());
+        let mut out = Vec::new();
+        for entry in entries {
+            out.extend_from_slice(format!("{:o} {}\0", entry.mode, entry.name).as_bytes());
+            out.extend_from_slice(entry.oid.as_bytes());
+        }
+        out
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TreeEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: Oid,
+    pub parents: Vec<Oid>,
+    pub author: Signature,
+    pub committer: Signature,
+    /// Headers we don't interpret (`encoding`, `gpgsig`, ...), kept so
+    /// re-serializing a parsed commit reproduces the same id.
+    pub extra_headers: Vec<(String, String)>,
+    pub message: String,
+}
+
+impl Commit {
+    pub fn parse(data: &[u8]) -> GitResult<Commit> {
+        let text = String::from_utf8_lossy(data);
+        let (headers, message) = split_headers(&text);
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers = Vec::new();
+        for (key, value) in headers {
+            match key.as_str() {
+                "tree" => tree = Some(value.parse::<Oid>()?),
+                "parent" => parents.push(value.parse::<Oid>()?),
+                "author" => author = Some(Signature::parse(&value)?),
+                "committer" => committer = Some(Signature::parse(&value)?),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+        let missing = |field: &str| GitError::Corrupt(format!("commit is missing {}", field));
+        Ok(Commit {
+            tree: tree.ok_or_else(|| missing("tree"))?,
+            parents,
+            author: author.ok_or_else(|| missing("author"))?,
+            committer: committer.ok_or_else(|| missing("committer"))?,
+            extra_headers,
+            message,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = String::new();
+        out.push_str(&format!("tree {}\n", self.tree));
+        for parent in &self.parents {
+            out.push_str(&format!("parent {}\n", parent));
+        }
+        out.push_str(&format!("author {}\n", self.author));
+        out.push_str(&format!("committer {}\n", self.committer));
+        for (key, value) in &self.extra_headers {
+            write_header(&mut out, key, value);
+        }
+        out.push('\n');
+        out.push_str(&self.message);
+        out.into_bytes()
+    }
+
+    /// The first line of the message.
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub object: Oid,
+    pub kind: ObjectType,
+    pub name: String,
+    pub tagger: Option<Signature>,
+    pub extra_headers: Vec<(String, String)>,
+    pub message: String,
+}
+
+impl Tag {
+    pub fn parse(data: &[u8]) -> GitResult<Tag> {
+        let text = String::from_utf8_lossy(data);
+        let (headers, message) = split_headers(&text);
+        let mut object = None;
+        let mut kind = None;
+        let mut name = None;
+        let mut tagger = None;
+        let mut extra_headers = Vec::new();
+        for (key, value) in headers {
+            match key.as_str() {
+                "object" => object = Some(value.parse::<Oid>()?),
+                "type" => kind = Some(ObjectType::parse(&value)?),
+                "tag" => name = Some(value),
+                "tagger" => tagger = Some(Signature::parse(&value)?),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+        let missing = |field: &str| GitError::Corrupt(format!("tag is missing {}", field));
+        Ok(Tag {
+            object: object.ok_or_else(|| missing("object"))?,
+            kind: kind.ok_or_else(|| missing("type"))?,
+            name: name.ok_or_else(|| missing("tag"))?,
+            tagger,
+            extra_headers,
+            message,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = String::new();
+        out.push_str(&format!("object {}\n", self.object));
+        out.push_str(&format!("type {}\n", self.kind));
+        out.push_str(&format!("tag {}\n", self.name));
+        if let Some(tagger) = &self.tagger {
+            out.push_str(&format!("tagger {}\n", tagger));
+        }
+        for (key, value) in &self.extra_headers {
+            write_header(&mut out, key, value);
+        }
+        out.push('\n');
+        out.push_str(&self.message);
+        out.into_bytes()
+    }
+}
+
+/// Split a commit or tag body into its headers and message. Continuation
+/// lines (starting with a space) are folded into the preceding header.
+fn split_headers(text: &str) -> (Vec<(String, String)>, String) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut rest = text;
+    loop {
+        let (line, remainder) = match rest.find('\n') {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => (rest, ""),
+        };
+        rest = remainder;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(continuation) = line.strip_prefix(' ') {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push('\n');
+                value.push_str(continuation);
+            }
+            continue;
+        }
+        let (key, value) = match line.find(' ') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => (line, ""),
+        };
+        headers.push((key.to_string(), value.to_string()));
+        if rest.is_empty() {
+            break;
+        }
+    }
+    (headers, rest.to_string())
+}
+
+fn write_header(out: &mut String, key: &str, value: &str) {
+    out.push_str(key);
+    out.push(' ');
+    out.push_str(&value.replace('\n', "\n "));
+    out.push('\n');
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitObject {
+    Blob(Vec<u8>),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl GitObject {
+    pub fn parse(kind: ObjectType, data: &[u8]) -> GitResult<GitObject> {
+        Ok(match kind {
+            ObjectType::Blob => GitObject::Blob(data.to_vec()),
+            ObjectType::Tree => GitObject::Tree(Tree::parse(data)?),
+            ObjectType::Commit => GitObject::Commit(Commit::parse(data)?),
+            ObjectType::Tag => GitObject::Tag(Tag::parse(data)?),
+        })
+    }
+
+    pub fn object_type(&self) -> ObjectType {
+        match self {
+            GitObject::Blob(_) => ObjectType::Blob,
+            GitObject::Tree(_) => ObjectType::Tree,
+            GitObject::Commit(_) => ObjectType::Commit,
+            GitObject::Tag(_) => ObjectType::Tag,
+        }
+    }
+
+    /// The object body, without the `<type> <len>\0` header.
+    pub fn serialize(&self) -> Vec<u8> {
+        match self {
+            GitObject::Blob(data) => data.clone(),
+            GitObject::Tree(tree) => tree.serialize(),
+            GitObject::Commit(commit) => commit.serialize(),
+            GitObject::Tag(tag) => tag.serialize(),
+        }
+    }
+
+    pub fn id(&self) -> Oid {
+        hash_object(self.object_type(), &self.serialize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_blob_hash() {
+        let id = GitObject::Blob(Vec::new()).id();
+        assert_eq!(id.to_string(), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+    }
+
+    #[test]
+    fn tree_sorts_directories_with_trailing_slash() {
+        let blob = GitObject::Blob(b"x".to_vec()).id();
+        let tree = Tree {
+            entries: vec![
+                TreeEntry {
+                    mode: MODE_TREE,
+                    name: "a".to_string(),
+                    oid: blob,
+                },
+                TreeEntry {
+                    mode: MODE_FILE,
+                    name: "a.txt".to_string(),
+                    oid: blob,
+                },
+            ],
+        };
+        let parsed = Tree::parse(&tree.serialize()).unwrap();
+        assert_eq!(parsed.entries[0].name, "a.txt");
+        assert_eq!(parsed.entries[1].name, "a");
+    }
+
+    #[test]
+    fn commit_round_trip() {
+        let raw = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+                   author A <a@example.com> 1700000000 +0100\n\
+                   committer B <b@example.com> 1700000001 -0230\n\
+                   gpgsig -----BEGIN-----\n \n abc\n -----END-----\n\
+                   \n\
+                   subject\n\nbody\n";
+        let commit = Commit::parse(raw.as_bytes()).unwrap();
+        assert!(commit.parents.is_empty());
+        assert_eq!(commit.summary(), "subject");
+        assert_eq!(
+            commit.extra_headers[0].1,
+            "-----BEGIN-----\n\nabc\n-----END-----"
+        );
+        assert_eq!(String::from_utf8(commit.serialize()).unwrap(), raw);
+    }
+}
@@ -0,0 +1,133 @@
+//! A bounded cache of parsed objects, so walks that keep coming back to the
+//! same commits and trees (`log`, `blame`, merge-base) only inflate and
+//! parse each one once.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::core::object::GitObject;
+use crate::core::oid::Oid;
+
+/// How many objects a repository caches unless `core.objectCacheSize`
+/// says otherwise.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// A least-recently-used cache of parsed objects, holding at most
+/// `capacity` of them.
+#[derive(Debug, Clone)]
+pub struct ObjectCache {
+    capacity: usize,
+    /// Each object along with the tick it was last used at.
+    entries: HashMap<Oid, (GitObject, u64)>,
+    /// The same objects ordered by when they were last used.
+    by_use: BTreeMap<u64, Oid>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl ObjectCache {
+    pub fn new(capacity: usize) -> ObjectCache {
+        ObjectCache {
+            capacity,
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// How many lookups found their object, and how many didn't.
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+
+    /// A copy of the cached object, marking it as the most recently used.
+    pub fn get(&mut self, id: &Oid) -> Option<GitObject> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get_mut(id) {
+            Some((object, last_used)) => {
+                self.by_use.remove(last_used);
+                self.by_use.insert(tick, *id);
+                *last_used = tick;
+                self.hits += 1;
+                Some(object.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Cache `object`, evicting the least recently used one if full.
+    pub fn insert(&mut self, id: Oid, object: GitObject) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.insert(id, (object, self.tick)) {
+            self.by_use.remove(&last_used);
+        }
+        self.by_use.insert(self.tick, id);
+        while self.entries.len() > self.capacity {
+            match self.by_use.pop_first() {
+                Some((_, oldest)) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Forget `id`, e.g. because the object was deleted.
+    pub fn remove(&mut self, id: &Oid) {
+        if let Some((_, last_used)) = self.entries.remove(id) {
+            self.by_use.remove(&last_used);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob(n: u8) -> (Oid, GitObject) {
+        (Oid::from_raw([n; 20]), GitObject::Blob(vec![n]))
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let mut cache = ObjectCache::new(2);
+        let (a, a_blob) = blob(1);
+        let (b, b_blob) = blob(2);
+        let (c, c_blob) = blob(3);
+        cache.insert(a, a_blob.clone());
+        cache.insert(b, b_blob);
+        assert_eq!(cache.get(&a), Some(a_blob));
+        // `b` is now the oldest.
+        cache.insert(c, c_blob);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&a).is_some());
+        assert!(cache.get(&c).is_some());
+        assert_eq!(cache.stats(), (3, 1));
+
+        let mut disabled = ObjectCache::new(0);
+        disabled.insert(a, blob(1).1);
+        assert!(disabled.is_empty());
+    }
+}
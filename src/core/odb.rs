@@ -0,0 +1,611 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::core::commit_graph::CommitGraph;
+use crate::core::object::{hash_object, Commit, GitObject, ObjectType, Tag, Tree};
+use crate::core::object_cache::ObjectCache;
+use crate::core::oid::{self, Oid};
+use crate::core::pack::Pack;
+use crate::error::{GitError, GitResult};
+
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The object store under `.git/objects`. Objects are written loose, and
+/// read loose or, failing that, out of the packs in `objects/pack`.
+#[derive(Debug, Clone)]
+pub struct ObjectDatabase {
+    objects_dir: PathBuf,
+    /// Shared between clones, so every handle on a repository benefits.
+    cache: Option<Arc<Mutex<ObjectCache>>>,
+    commit_graph: Option<Arc<CommitGraph>>,
+    /// The packs opened so far, by index path, shared like the cache.
+    packs: Arc<Mutex<Vec<(PathBuf, Pack)>>>,
+}
+
+impl ObjectDatabase {
+    /// An object database without a cache.
+    pub fn new<P: Into<PathBuf>>(objects_dir: P) -> ObjectDatabase {
+        ObjectDatabase {
+            objects_dir: objects_dir.into(),
+            cache: None,
+            commit_graph: None,
+            packs: Arc::default(),
+        }
+    }
+
+    /// Serve parsed objects from `cache` where possible, or from disk
+    /// every time if it's `None`.
+    pub fn with_cache(mut self, cache: Option<ObjectCache>) -> ObjectDatabase {
+        self.cache = cache.map(|cache| Arc::new(Mutex::new(cache)));
+        self
+    }
+
+    /// Let history walks read commits from `graph` rather than from their
+    /// objects, for the commits it has.
+    pub fn with_commit_graph(mut self, graph: Option<CommitGraph>) -> ObjectDatabase {
+        self.commit_graph = graph.map(Arc::new);
+        self
+    }
+
+    pub fn commit_graph(&self) -> Option<&CommitGraph> {
+        self.commit_graph.as_deref()
+    }
+
+    /// The cache's hit and miss counts, if there is one.
+    pub fn cache_stats(&self) -> Option<(u64, u64)> {
+        self.cache.as_ref().map(|cache| lock_cache(cache).stats())
+    }
+
+    pub fn objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    /// Where `id` is stored as a loose object.
+    pub fn loose_path(&self, id: &Oid) -> PathBuf {
+        self.objects_dir.join(id.fan_out_path())
+    }
+
+    /// The loose objects in fan-out directory `fanout`, i.e. those whose
+    /// id starts with that byte, sorted. Stray files are skipped.
+    pub fn loose_objects_in(&self, fanout: u8) -> GitResult<Vec<Oid>> {
+        let prefix = format!("{:02x}", fanout);
+        let entries = match fs::read_dir(self.objects_dir.join(&prefix)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut ids = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            let hex = format!("{}{}", prefix, name.to_string_lossy());
+            if hex.len() == 40 && oid::is_hex(&hex) {
+                ids.push(hex.parse::<Oid>()?);
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Every loose object, sorted.
+    pub fn loose_objects(&self) -> GitResult<Vec<Oid>> {
+        let mut ids = Vec::new();
+        for fanout in 0..=255 {
+            ids.extend(self.loose_objects_in(fanout)?);
+        }
+        Ok(ids)
+    }
+
+    /// The objects whose hex id starts with `prefix`, which must be
+    /// at least two hex digits.
+    pub fn find_prefix(&self, prefix: &str) -> GitResult<Vec<Oid>> {
+        let prefix = prefix.to_ascii_lowercase();
+        let fanout = match prefix
+            .get(..2)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            Some(fanout) if oid::is_hex(&prefix) => fanout,
+            _ => return Err(GitError::InvalidOid(prefix)),
+        };
+        let mut ids = self.objects_in(fanout)?;
+        ids.retain(|id| id.to_string().starts_with(&prefix));
+        Ok(ids)
+    }
+
+    /// The objects, loose or packed, whose id starts with the byte
+    /// `fanout`, sorted.
+    fn objects_in(&self, fanout: u8) -> GitResult<Vec<Oid>> {
+        let mut ids = self.loose_objects_in(fanout)?;
+        for (_, pack) in self.packs()?.iter() {
+            let packed = pack.index.ids();
+            let start = packed.partition_point(|id| id.as_bytes()[0] < fanout);
+            let end = packed.partition_point(|id| id.as_bytes()[0] <= fanout);
+            ids.extend_from_slice(&packed[start..end]);
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// The shortest prefix of `id`, at least `min_len` digits long, that no
+    /// other object shares.
+    pub fn abbreviate(&self, id: &Oid, min_len: usize) -> GitResult<String> {
+        let hex = id.to_string();
+        let shared = self
+            .objects_in(id.as_bytes()[0])?
+            .iter()
+            .filter(|other| *other != id)
+            .map(|other| {
+                let other = other.to_string();
+                hex.bytes()
+                    .zip(other.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            })
+            .max()
+            .unwrap_or(0);
+        let len = min_len.max(shared + 1).min(hex.len());
+        Ok(hex[..len].to_string())
+    }
+
+    pub fn contains(&self, id: &Oid) -> bool {
+        self.loose_path(id).is_file()
+            || self.packs().is_ok_and(|packs| {
+                packs
+                    .iter()
+                    .any(|(_, pack)| pack.index.position(id).is_some())
+            })
+    }
+
+    /// The packs in `objects/pack`, opening any that turned up since the
+    /// last look.
+    fn packs(&self) -> GitResult<MutexGuard<'_, Vec<(PathBuf, Pack)>>> {
+        let mut packs = self
+            .packs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let entries = match fs::read_dir(self.objects_dir.join("pack")) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(packs),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "idx")
+                && !packs.iter().any(|(known, _)| *known == path)
+            {
+                let pack = Pack::open(&path)?;
+                packs.push((path, pack));
+            }
+        }
+        Ok(packs)
+    }
+
+    /// Read `id` out of whichever pack has it.
+    fn read_packed(&self, id: &Oid) -> GitResult<(ObjectType, Vec<u8>)> {
+        for (_, pack) in self.packs()?.iter() {
+            if let Some(position) = pack.index.position(id) {
+                let object = pack.read_at(pack.index.offset(position), &mut HashMap::new())?;
+                return Ok((object.kind, object.data));
+            }
+        }
+        Err(GitError::ObjectNotFound(*id))
+    }
+
+    /// Add a finished pack and its index to `objects/pack`, named after
+    /// the pack's `checksum` as git names them, returning the pack's path.
+    ///
+    /// Each file goes in through a temporary one so nothing ever sees it
+    /// half written, and the index goes last: readers only look for packs
+    /// through theirs.
+    pub fn install_pack(&self, pack: &[u8], index: &[u8], checksum: &Oid) -> GitResult<PathBuf> {
+        let dir = self.objects_dir().join("pack");
+        fs::create_dir_all(&dir)?;
+        let base = dir.join(format!("pack-{}", checksum));
+        let pack_path = base.with_extension("pack");
+        for (path, data) in [(&pack_path, pack), (&base.with_extension("idx"), index)] {
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, data)?;
+            fs::rename(&tmp, path)?;
+        }
+        Ok(pack_path)
+    }
+
+    /// Delete the loose copy of `id`, and its fan-out directory if that
+    /// leaves it empty.
+    pub fn remove_loose(&self, id: &Oid) -> GitResult<()> {
+        if let Some(cache) = &self.cache {
+            lock_cache(cache).remove(id);
+        }
+        let path = self.loose_path(id);
+        fs::remove_file(&path)?;
+        if let Some(dir) = path.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+
+    /// Read an object's type and body.
+    pub fn read_raw(&self, id: &Oid) -> GitResult<(ObjectType, Vec<u8>)> {
+        let file = match self.open_loose(id) {
+            Err(GitError::ObjectNotFound(_)) => return self.read_packed(id),
+            file => file?,
+        };
+        let mut data = Vec::new();
+        ZlibDecoder::new(file).read_to_end(&mut data)?;
+        parse_loose(id, &data)
+    }
+
+    /// An object's type and size, inflating no more of it than the header.
+    pub fn read_header(&self, id: &Oid) -> GitResult<(ObjectType, usize)> {
+        let file = match self.open_loose(id) {
+            Err(GitError::ObjectNotFound(_)) => {
+                let (kind, data) = self.read_packed(id)?;
+                return Ok((kind, data.len()));
+            }
+            file => file?,
+        };
+        // Headers are a type and a size, so a little inflating is plenty.
+        let mut decoder = ZlibDecoder::new(file);
+        let mut header = Vec::new();
+        let mut buf = [0; 32];
+        while header.len() < 64 {
+            let read = decoder.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            header.extend_from_slice(&buf[..read]);
+            if let Some(nul) = header.iter().position(|&b| b == 0) {
+                return parse_header(id, &header[..nul]);
+            }
+        }
+        Err(bad_header(id))
+    }
+
+    /// Copy an object's body into `writer` a chunk at a time as it's
+    /// inflated, so that a large blob never has to fit in memory. The
+    /// cache is bypassed, since there'd be nothing parsed to keep. Packed
+    /// objects are read whole.
+    pub fn read_object_streaming(&self, id: &Oid, writer: &mut impl Write) -> GitResult<()> {
+        let file = match self.open_loose(id) {
+            Err(GitError::ObjectNotFound(_)) => {
+                writer.write_all(&self.read_packed(id)?.1)?;
+                return Ok(());
+            }
+            file => file?,
+        };
+        let mut reader = BufReader::new(ZlibDecoder::new(file));
+        let mut header = Vec::new();
+        reader.by_ref().take(64).read_until(0, &mut header)?;
+        if header.pop() != Some(0) {
+            return Err(bad_header(id));
+        }
+        let (_, size) = parse_header(id, &header)?;
+        // Copying one byte more than the header promises catches objects
+        // that are longer than they say.
+        let copied = io::copy(&mut reader.take(size as u64 + 1), writer)?;
+        if copied != size as u64 {
+            return Err(bad_header(id));
+        }
+        Ok(())
+    }
+
+    /// Read and parse an object, going through the cache if there is one.
+    pub fn read(&self, id: &Oid) -> GitResult<GitObject> {
+        if let Some(cache) = &self.cache {
+            if let Some(object) = lock_cache(cache).get(id) {
+                return Ok(object);
+            }
+        }
+        let (kind, data) = self.read_raw(id)?;
+        let object = GitObject::parse(kind, &data)?;
+        if let Some(cache) = &self.cache {
+            lock_cache(cache).insert(*id, object.clone());
+        }
+        Ok(object)
+    }
+
+    pub fn read_blob(&self, id: &Oid) -> GitResult<Vec<u8>> {
+        match self.read(id)? {
+            GitObject::Blob(data) => Ok(data),
+            other => Err(unexpected_type(id, ObjectType::Blob, other.object_type())),
+        }
+    }
+
+    pub fn read_tree(&self, id: &Oid) -> GitResult<Tree> {
+        match self.read(id)? {
+            GitObject::Tree(tree) => Ok(tree),
+            other => Err(unexpected_type(id, ObjectType::Tree, other.object_type())),
+        }
+    }
+
+    pub fn read_commit(&self, id: &Oid) -> GitResult<Commit> {
+        match self.read(id)? {
+            GitObject::Commit(commit) => Ok(commit),
+            other => Err(unexpected_type(id, ObjectType::Commit, other.object_type())),
+        }
+    }
+
+    pub fn read_tag(&self, id: &Oid) -> GitResult<Tag> {
+        match self.read(id)? {
+            GitObject::Tag(tag) => Ok(tag),
+            other => Err(unexpected_type(id, ObjectType::Tag, other.object_type())),
+        }
+    }
+
+    /// Follow annotated tags from `id` to the first object that isn't a
+    /// tag, returning it and its type.
+    pub fn peel(&self, id: &Oid) -> GitResult<(Oid, ObjectType)> {
+        let mut current = *id;
+        loop {
+            match self.read_raw(&current)? {
+                (ObjectType::Tag, data) => current = Tag::parse(&data)?.object,
+                (kind, _) => return Ok((current, kind)),
+            }
+        }
+    }
+
+    /// Follow annotated tags from `id` down to the commit they name.
+    pub fn peel_to_commit(&self, id: &Oid) -> GitResult<Oid> {
+        match self.peel(id)? {
+            (commit, ObjectType::Commit) => Ok(commit),
+            (other, kind) => Err(unexpected_type(&other, ObjectType::Commit, kind)),
+        }
+    }
+
+    fn open_loose(&self, id: &Oid) -> GitResult<fs::File> {
+        match fs::File::open(self.loose_path(id)) {
+            Ok(file) => Ok(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(GitError::ObjectNotFound(*id)),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn write(&self, object: &GitObject) -> GitResult<Oid> {
+        self.write_raw(object.object_type(), &object.serialize())
+    }
+
+    /// Store an object body as a loose object, returning its id. Writing an
+    /// object that already exists is a no-op.
+    pub fn write_raw(&self, kind: ObjectType, body: &[u8]) -> GitResult<Oid> {
+        let id = hash_object(kind, body);
+        let path = self.loose_path(&id);
+        if path.exists() {
+            return Ok(id);
+        }
+        let dir = path
+            .parent()
+            .expect("loose object paths have a fan-out dir");
+        fs::create_dir_all(dir)?;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(format!("{} {}\0", kind, body.len()).as_bytes())?;
+        encoder.write_all(body)?;
+        let compressed = encoder.finish()?;
+
+        // Write to a temporary file first so readers never see a partial object.
+        let tmp = dir.join(format!(
+            "tmp_obj_{}_{}",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&tmp, &compressed)?;
+        fs::rename(&tmp, &path)?;
+        Ok(id)
+    }
+}
+
+/// Somewhere for new objects to go. Commands that create objects take one
+/// of these so a caller can preview what they would write.
+pub trait ObjectWriter {
+    fn write(&mut self, object: &GitObject) -> GitResult<Oid>;
+
+    /// Whether what's written is kept. A dry run's writer only hashes, so
+    /// work that only matters for stored objects, like signing, can be
+    /// skipped.
+    fn stores(&self) -> bool {
+        true
+    }
+}
+
+/// Writes loose objects into an object database.
+#[derive(Debug)]
+pub struct LooseObjectWriter<'a> {
+    odb: &'a ObjectDatabase,
+}
+
+impl<'a> LooseObjectWriter<'a> {
+    pub fn new(odb: &'a ObjectDatabase) -> LooseObjectWriter<'a> {
+        LooseObjectWriter { odb }
+    }
+}
+
+impl ObjectWriter for LooseObjectWriter<'_> {
+    fn write(&mut self, object: &GitObject) -> GitResult<Oid> {
+        self.odb.write(object)
+    }
+}
+
+/// Hashes objects without storing them, for dry runs.
+#[derive(Debug, Default)]
+pub struct NullObjectWriter {
+    /// How many objects would have been written.
+    pub count: usize,
+}
+
+impl ObjectWriter for NullObjectWriter {
+    fn write(&mut self, object: &GitObject) -> GitResult<Oid> {
+        self.count += 1;
+        Ok(hash_object(object.object_type(), &object.serialize()))
+    }
+
+    fn stores(&self) -> bool {
+        false
+    }
+}
+
+/// A poisoned cache is still a valid cache: entries are only ever
+/// inserted whole.
+fn lock_cache(cache: &Mutex<ObjectCache>) -> MutexGuard<'_, ObjectCache> {
+    cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn bad_header(id: &Oid) -> GitError {
+    GitError::Corrupt(format!("loose object {} has a bad header", id))
+}
+
+/// Parse `<type> <size>`, the part of a loose object before the NUL.
+fn parse_header(id: &Oid, header: &[u8]) -> GitResult<(ObjectType, usize)> {
+    let header = std::str::from_utf8(header).map_err(|_| bad_header(id))?;
+    let mut parts = header.splitn(2, ' ');
+    let kind = ObjectType::parse(parts.next().ok_or_else(|| bad_header(id))?)?;
+    let size: usize = parts
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| bad_header(id))?;
+    Ok((kind, size))
+}
+
+fn parse_loose(id: &Oid, data: &[u8]) -> GitResult<(ObjectType, Vec<u8>)> {
+    let nul = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| bad_header(id))?;
+    let (kind, size) = parse_header(id, &data[..nul])?;
+    let body = &data[nul + 1..];
+    if body.len() != size {
+        return Err(bad_header(id));
+    }
+    Ok((kind, body.to_vec()))
+}
+
+fn unexpected_type(id: &Oid, expected: ObjectType, found: ObjectType) -> GitError {
+    GitError::Corrupt(format!("object {} is a {}, not a {}", id, found, expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_then_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let odb = ObjectDatabase::new(dir.path());
+        let id = odb.write(&GitObject::Blob(b"hello\n".to_vec())).unwrap();
+        assert_eq!(id.to_string(), "ce013625030ba8dba906f756967f9e9ca394464a");
+        assert!(odb.contains(&id));
+        assert_eq!(odb.read_blob(&id).unwrap(), b"hello\n");
+        assert!(odb.read_tree(&id).is_err());
+    }
+
+    #[test]
+    fn cached_reads_match_fresh_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let fresh = ObjectDatabase::new(dir.path());
+        let cached = fresh.clone().with_cache(Some(ObjectCache::new(16)));
+        let blob = fresh.write(&GitObject::Blob(b"hello\n".to_vec())).unwrap();
+        let tree = fresh
+            .write(&GitObject::Tree(Tree {
+                entries: vec![crate::core::object::TreeEntry {
+                    mode: crate::core::object::MODE_FILE,
+                    name: "hello".to_string(),
+                    oid: blob,
+                }],
+            }))
+            .unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                cached.read_blob(&blob).unwrap(),
+                fresh.read_blob(&blob).unwrap()
+            );
+            assert_eq!(
+                cached.read_tree(&tree).unwrap(),
+                fresh.read_tree(&tree).unwrap()
+            );
+        }
+        assert_eq!(cached.cache_stats(), Some((2, 2)));
+        assert!(cached.read_commit(&tree).is_err());
+        assert_eq!(fresh.cache_stats(), None);
+    }
+
+    /// Checks what's written against the expected bytes as it arrives,
+    /// keeping none of it.
+    struct Expect<'a> {
+        rest: &'a [u8],
+        largest_write: usize,
+    }
+
+    impl Write for Expect<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            assert!(buf.len() <= self.rest.len(), "more bytes than expected");
+            assert_eq!(buf, &self.rest[..buf.len()]);
+            self.rest = &self.rest[buf.len()..];
+            self.largest_write = self.largest_write.max(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streams_large_blobs_in_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let odb = ObjectDatabase::new(dir.path().join("objects"));
+        let content: Vec<u8> = (0u32..4 << 20)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        let id = odb.write_raw(ObjectType::Blob, &content).unwrap();
+
+        let mut expect = Expect {
+            rest: &content,
+            largest_write: 0,
+        };
+        odb.read_object_streaming(&id, &mut expect).unwrap();
+        assert!(expect.rest.is_empty());
+        assert!(expect.largest_write < 64 * 1024);
+
+        let work_dir = dir.path().join("work");
+        crate::core::worktree::write_blob(
+            &odb,
+            &work_dir,
+            "big/file",
+            crate::core::object::MODE_FILE,
+            &id,
+        )
+        .unwrap();
+        assert!(fs::read(work_dir.join("big/file")).unwrap() == content);
+        let missing = Oid::from_raw([0; 20]);
+        assert!(matches!(
+            odb.read_object_streaming(&missing, &mut io::sink()),
+            Err(GitError::ObjectNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn null_writer_only_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let odb = ObjectDatabase::new(dir.path());
+        let blob = GitObject::Blob(b"hello\n".to_vec());
+
+        let mut null = NullObjectWriter::default();
+        let id = null.write(&blob).unwrap();
+        assert_eq!(id.to_string(), "ce013625030ba8dba906f756967f9e9ca394464a");
+        assert_eq!(null.count, 1);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        assert_eq!(LooseObjectWriter::new(&odb).write(&blob).unwrap(), id);
+        assert!(odb.contains(&id));
+    }
+}
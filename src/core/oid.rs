@@ -0,0 +1,172 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{GitError, GitResult};
+
+/// A SHA-1 object id. It parses from and displays as 40 hex digits; the
+/// raw bytes are what trees, indexes and packs store.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Oid([u8; 20]);
+
+/// The all-zeros id git uses for "no object", e.g. when a ref is created.
+pub const NULL_OID: Oid = Oid([0; 20]);
+
+impl Oid {
+    pub const fn from_raw(bytes: [u8; 20]) -> Oid {
+        Oid(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == NULL_OID
+    }
+
+    /// The first `len` hex digits, all 40 if it's longer. This doesn't
+    /// check the prefix is unique; [`ObjectDatabase::abbreviate`] does.
+    ///
+    /// [`ObjectDatabase::abbreviate`]: crate::core::odb::ObjectDatabase::abbreviate
+    pub fn abbrev(&self, len: usize) -> String {
+        let mut hex = self.to_string();
+        hex.truncate(len);
+        hex
+    }
+
+    /// Where the loose object is kept under `objects/`: `ab/cdef…`.
+    pub fn fan_out_path(&self) -> String {
+        let hex = self.to_string();
+        format!("{}/{}", &hex[..2], &hex[2..])
+    }
+}
+
+impl fmt::Display for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Ids are long; the hex is what anyone reading a `Debug` dump wants.
+impl fmt::Debug for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Oid({})", self)
+    }
+}
+
+impl FromStr for Oid {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> GitResult<Oid> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 40 {
+            return Err(GitError::InvalidOid(s.to_string()));
+        }
+        let mut oid = [0; 20];
+        for (i, chunk) in bytes.chunks(2).enumerate() {
+            let hi = hex_value(chunk[0]).ok_or_else(|| GitError::InvalidOid(s.to_string()))?;
+            let lo = hex_value(chunk[1]).ok_or_else(|| GitError::InvalidOid(s.to_string()))?;
+            oid[i] = (hi << 4) | lo;
+        }
+        Ok(Oid(oid))
+    }
+}
+
+impl From<[u8; 20]> for Oid {
+    fn from(bytes: [u8; 20]) -> Oid {
+        Oid(bytes)
+    }
+}
+
+/// For ids read straight out of binary formats; the slice must be 20
+/// bytes long.
+impl TryFrom<&[u8]> for Oid {
+    type Error = GitError;
+
+    fn try_from(bytes: &[u8]) -> GitResult<Oid> {
+        from_bytes(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Oid {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Build an id from a 20 byte slice, as found in trees and indexes.
+pub fn from_bytes(bytes: &[u8]) -> GitResult<Oid> {
+    if bytes.len() != 20 {
+        return Err(GitError::Corrupt(format!(
+            "expected a 20 byte object id, found {} bytes",
+            bytes.len()
+        )));
+    }
+    let mut oid = [0; 20];
+    oid.copy_from_slice(bytes);
+    Ok(Oid(oid))
+}
+
+pub fn is_hex(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| hex_value(b).is_some())
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let hex = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+        let oid: Oid = hex.parse().unwrap();
+        assert_eq!(oid.as_bytes()[0], 0xe6);
+        assert_eq!(oid.to_string(), hex);
+        assert_eq!(oid.to_string(), hex);
+        assert_eq!(hex.to_uppercase().parse::<Oid>().unwrap(), oid);
+    }
+
+    #[test]
+    fn rejects_bad_hex() {
+        for bad in [
+            "e69de29b",
+            "z69de29bb2d1d6434b8b29ae775ad8c2e48c5391",
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c53910",
+            "",
+        ]
+        .iter()
+        {
+            match bad.parse::<Oid>() {
+                Err(GitError::InvalidOid(s)) => assert_eq!(s, *bad),
+                other => panic!("{:?} parsed as {:?}", bad, other),
+            }
+        }
+        assert!(from_bytes(&[0; 19]).is_err());
+    }
+
+    #[test]
+    fn abbreviates_and_finds_its_loose_path() {
+        let oid: Oid = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".parse().unwrap();
+        assert_eq!(oid.abbrev(7), "e69de29");
+        assert_eq!(oid.abbrev(50).len(), 40);
+        assert_eq!(
+            oid.fan_out_path(),
+            "e6/9de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+        );
+        assert!(NULL_OID.is_null());
+        assert!(!oid.is_null());
+        assert_eq!(format!("{:?}", oid), format!("Oid({})", oid));
+    }
+}
@@ -0,0 +1,800 @@
+//! Packfiles and their version 2 `.idx` indexes.
+//!
+//! A pack is a `PACK` header, a run of zlib-compressed entries and a
+//! SHA-1 of everything before it. Each entry starts with its type and
+//! inflated size in a variable-length header. Besides whole objects an
+//! entry can be a delta against another object, named by its offset
+//! earlier in the pack (`OFS_DELTA`) or by its id (`REF_DELTA`).
+//!
+//! The index lists the pack's object ids in sorted order, with a fanout
+//! table counting the ids up to each first byte, each entry's CRC32 as
+//! stored in the pack, and its offset.
+//!
+//! [`PackWriter`] writes both, with whole objects or `REF_DELTA`s from
+//! [`create_delta`].
+
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use flate2::bufread::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use sha1::{Digest, Sha1};
+
+use crate::core::object::{hash_object, ObjectType};
+use crate::core::oid::{self, Oid};
+use crate::error::{GitError, GitResult};
+
+const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+const IDX_SIGNATURE: &[u8; 4] = b"\xfftOc";
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// The length of the base blocks [`create_delta`] looks for in its target,
+/// and so the shortest copy it makes.
+const DELTA_BLOCK: usize = 16;
+/// The most bytes one copy instruction can take from the base.
+const MAX_COPY: usize = 0x10000;
+/// How many places in the base one block is remembered at, which bounds
+/// the work on repetitive content.
+const MAX_BLOCK_STARTS: usize = 64;
+
+fn corrupt(what: impl Into<String>) -> GitError {
+    GitError::Corrupt(what.into())
+}
+
+/// Check that `data` ends with the SHA-1 of what comes before it.
+fn check_trailer(data: &[u8], what: &str) -> GitResult<Oid> {
+    if data.len() < 20 {
+        return Err(corrupt(format!("{} is truncated", what)));
+    }
+    let (body, trailer) = data.split_at(data.len() - 20);
+    let actual: [u8; 20] = Sha1::digest(body).into();
+    if actual[..] != trailer[..] {
+        return Err(corrupt(format!("{} checksum mismatch", what)));
+    }
+    Ok(Oid::from_raw(actual))
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().expect("four bytes"))
+}
+
+/// A parsed `.idx` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackIndex {
+    fanout: Vec<u32>,
+    ids: Vec<Oid>,
+    crcs: Vec<u32>,
+    offsets: Vec<u64>,
+    /// The checksum of the pack this indexes.
+    pack_checksum: Oid,
+}
+
+impl PackIndex {
+    pub fn load(path: &Path) -> GitResult<PackIndex> {
+        PackIndex::parse(&fs::read(path)?)
+    }
+
+    /// Parse a version 2 index, checking its own checksum.
+    pub fn parse(data: &[u8]) -> GitResult<PackIndex> {
+        check_trailer(data, "pack index")?;
+        if data.len() < 8 + 256 * 4 + 40 || &data[..4] != IDX_SIGNATURE {
+            return Err(corrupt("not a version 2 pack index"));
+        }
+        if read_u32(data, 4) != 2 {
+            return Err(corrupt(format!(
+                "unsupported pack index version {}",
+                read_u32(data, 4)
+            )));
+        }
+        let fanout: Vec<u32> = (0..256).map(|i| read_u32(data, 8 + i * 4)).collect();
+        if fanout.windows(2).any(|w| w[0] > w[1]) {
+            return Err(corrupt("pack index fanout is not sorted"));
+        }
+        let count = fanout[255] as usize;
+        let ids_at = 8 + 256 * 4;
+        let crcs_at = ids_at + count * 20;
+        let offsets_at = crcs_at + count * 4;
+        let large_at = offsets_at + count * 4;
+        if data.len() < large_at + 40 {
+            return Err(corrupt("pack index is truncated"));
+        }
+        let ids: Vec<Oid> = (0..count)
+            .map(|i| {
+                data[ids_at + i * 20..ids_at + (i + 1) * 20]
+                    .try_into()
+                    .expect("twenty bytes")
+            })
+            .collect();
+        let crcs = (0..count)
+            .map(|i| read_u32(data, crcs_at + i * 4))
+            .collect();
+        let large_count = (data.len() - 40 - large_at) / 8;
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = read_u32(data, offsets_at + i * 4);
+            if offset & 0x8000_0000 == 0 {
+                offsets.push(u64::from(offset));
+                continue;
+            }
+            // Offsets past 2GiB live in a table of their own.
+            let index = (offset & 0x7fff_ffff) as usize;
+            if index >= large_count {
+                return Err(corrupt("pack index large offset out of range"));
+            }
+            let at = large_at + index * 8;
+            offsets.push(u64::from_be_bytes(
+                data[at..at + 8].try_into().expect("eight bytes"),
+            ));
+        }
+        let trailer_at = data.len() - 40;
+        Ok(PackIndex {
+            fanout,
+            ids,
+            crcs,
+            offsets,
+            pack_checksum: data[trailer_at..trailer_at + 20]
+                .try_into()
+                .expect("twenty bytes"),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// The ids in the pack, sorted.
+    pub fn ids(&self) -> &[Oid] {
+        &self.ids
+    }
+
+    pub fn pack_checksum(&self) -> &Oid {
+        &self.pack_checksum
+    }
+
+    /// Where `id` is in the index, using the fanout table to narrow the
+    /// search to ids with the same first byte.
+    pub fn position(&self, id: &Oid) -> Option<usize> {
+        let first = usize::from(id.as_bytes()[0]);
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1] as usize
+        };
+        let end = self.fanout[first] as usize;
+        self.ids[start..end]
+            .binary_search(id)
+            .ok()
+            .map(|found| start + found)
+    }
+
+    pub fn offset(&self, position: usize) -> u64 {
+        self.offsets[position]
+    }
+
+    pub fn crc32(&self, position: usize) -> u32 {
+        self.crcs[position]
+    }
+}
+
+/// What a pack entry holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Object(ObjectType),
+    /// A delta against the entry at this offset.
+    OfsDelta(u64),
+    /// A delta against the object with this id.
+    RefDelta(Oid),
+}
+
+/// The header of one pack entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryHeader {
+    pub kind: EntryKind,
+    /// The inflated size of the entry's data: the object, or the delta.
+    pub size: usize,
+    /// Where the compressed data starts.
+    pub data_offset: u64,
+}
+
+/// A pack's contents, read into memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackFile {
+    data: Vec<u8>,
+}
+
+impl PackFile {
+    pub fn load(path: &Path) -> GitResult<PackFile> {
+        PackFile::parse(fs::read(path)?)
+    }
+
+    /// Check a pack's header and trailing checksum.
+    pub fn parse(data: Vec<u8>) -> GitResult<PackFile> {
+        check_trailer(&data, "pack")?;
+        if data.len() < 32 || &data[..4] != PACK_SIGNATURE {
+            return Err(corrupt("not a pack"));
+        }
+        match read_u32(&data, 4) {
+            2 | 3 => Ok(PackFile { data }),
+            version => Err(corrupt(format!("unsupported pack version {}", version))),
+        }
+    }
+
+    /// How many entries the header says the pack has.
+    pub fn object_count(&self) -> u32 {
+        read_u32(&self.data, 8)
+    }
+
+    pub fn checksum(&self) -> Oid {
+        self.data[self.data.len() - 20..]
+            .try_into()
+            .expect("twenty bytes")
+    }
+
+    /// Where the entries end and the trailer starts.
+    pub fn entries_end(&self) -> u64 {
+        (self.data.len() - 20) as u64
+    }
+
+    /// The raw bytes of the pack between two offsets, as the index's CRCs
+    /// cover them.
+    pub fn raw(&self, start: u64, end: u64) -> GitResult<&[u8]> {
+        self.data
+            .get(start as usize..end as usize)
+            .ok_or_else(|| corrupt(format!("pack offset {} out of range", start)))
+    }
+
+    pub fn entry_header(&self, offset: u64) -> GitResult<EntryHeader> {
+        let bad = || corrupt(format!("bad pack entry at offset {}", offset));
+        let data = &self.data[..self.data.len() - 20];
+        let mut at = offset as usize;
+        let mut byte = *data.get(at).ok_or_else(bad)?;
+        at += 1;
+        let type_bits = (byte >> 4) & 7;
+        let mut size = usize::from(byte & 0x0f);
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = *data.get(at).ok_or_else(bad)?;
+            at += 1;
+            if shift > 56 {
+                return Err(bad());
+            }
+            size |= usize::from(byte & 0x7f) << shift;
+            shift += 7;
+        }
+        let kind = match type_bits {
+            1 => EntryKind::Object(ObjectType::Commit),
+            2 => EntryKind::Object(ObjectType::Tree),
+            3 => EntryKind::Object(ObjectType::Blob),
+            4 => EntryKind::Object(ObjectType::Tag),
+            OBJ_OFS_DELTA => {
+                // A big-endian base-128 number where each continuation
+                // also adds one, so that no two encodings are equal.
+                let mut byte = *data.get(at).ok_or_else(bad)?;
+                at += 1;
+                let mut distance = u64::from(byte & 0x7f);
+                while byte & 0x80 != 0 {
+                    byte = *data.get(at).ok_or_else(bad)?;
+                    at += 1;
+                    distance = ((distance + 1) << 7) | u64::from(byte & 0x7f);
+                }
+                EntryKind::OfsDelta(offset.checked_sub(distance).ok_or_else(bad)?)
+            }
+            OBJ_REF_DELTA => {
+                let base = data.get(at..at + 20).ok_or_else(bad)?;
+                at += 20;
+                EntryKind::RefDelta(oid::from_bytes(base)?)
+            }
+            _ => return Err(bad()),
+        };
+        Ok(EntryHeader {
+            kind,
+            size,
+            data_offset: at as u64,
+        })
+    }
+
+    /// Inflate an entry's data, returning it with the offset just past
+    /// its compressed bytes.
+    pub fn inflate(&self, header: &EntryHeader) -> GitResult<(Vec<u8>, u64)> {
+        let bad = || {
+            corrupt(format!(
+                "bad compressed data at offset {}",
+                header.data_offset
+            ))
+        };
+        let input = self
+            .data
+            .get(header.data_offset as usize..self.data.len() - 20)
+            .ok_or_else(bad)?;
+        let mut decoder = ZlibDecoder::new(input);
+        let mut out = Vec::with_capacity(header.size);
+        // Reading one byte past the promised size catches entries that
+        // inflate to more than their header says.
+        decoder
+            .by_ref()
+            .take(header.size as u64 + 1)
+            .read_to_end(&mut out)
+            .map_err(|_| bad())?;
+        if out.len() != header.size {
+            return Err(bad());
+        }
+        Ok((out, header.data_offset + decoder.total_in()))
+    }
+}
+
+/// A pack together with its index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pack {
+    pub index: PackIndex,
+    pub file: PackFile,
+}
+
+/// An object read out of a pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedObject {
+    pub kind: ObjectType,
+    pub data: Vec<u8>,
+    /// How many deltas were applied to get it: 0 for a whole object.
+    pub depth: usize,
+}
+
+impl Pack {
+    /// Open `<name>.idx` and the `<name>.pack` next to it, checking that
+    /// they belong together.
+    pub fn open(idx_path: &Path) -> GitResult<Pack> {
+        let index = PackIndex::load(idx_path)?;
+        let file = PackFile::load(&idx_path.with_extension("pack"))?;
+        if file.checksum() != *index.pack_checksum() {
+            return Err(corrupt(format!(
+                "{} does not index its pack",
+                idx_path.display()
+            )));
+        }
+        if file.object_count() as usize != index.len() {
+            return Err(corrupt("pack and index disagree on the object count"));
+        }
+        Ok(Pack { index, file })
+    }
+
+    /// Read the object whose entry is at `offset`, applying any deltas.
+    /// `cache` keeps resolved objects by offset so that a chain's bases
+    /// are only resolved once across calls.
+    pub fn read_at(
+        &self,
+        offset: u64,
+        cache: &mut HashMap<u64, PackedObject>,
+    ) -> GitResult<PackedObject> {
+        if let Some(object) = cache.get(&offset) {
+            return Ok(object.clone());
+        }
+        // Walk down to the first cached or whole object, then apply the
+        // deltas back up.
+        let mut chain = Vec::new();
+        let mut current = offset;
+        let mut base = loop {
+            if let Some(object) = cache.get(&current) {
+                break object.clone();
+            }
+            let header = self.file.entry_header(current)?;
+            let (data, _) = self.file.inflate(&header)?;
+            let base_offset = match header.kind {
+                EntryKind::Object(kind) => {
+                    break PackedObject {
+                        kind,
+                        data,
+                        depth: 0,
+                    }
+                }
+                EntryKind::OfsDelta(base) => base,
+                EntryKind::RefDelta(id) => match self.index.position(&id) {
+                    Some(position) => self.index.offset(position),
+                    None => return Err(corrupt(format!("delta base {} is not in the pack", id))),
+                },
+            };
+            if chain.len() > self.index.len() {
+                return Err(corrupt(format!("delta loop at offset {}", offset)));
+            }
+            chain.push((current, data));
+            current = base_offset;
+        };
+        cache.insert(current, base.clone());
+        while let Some((at, delta)) = chain.pop() {
+            base = PackedObject {
+                kind: base.kind,
+                data: apply_delta(&base.data, &delta)?,
+                depth: base.depth + 1,
+            };
+            cache.insert(at, base.clone());
+        }
+        Ok(base)
+    }
+}
+
+/// Rebuild an object from its delta base. A delta starts with the base's
+/// size and the result's size, then copies ranges out of the base or
+/// inserts literal bytes.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> GitResult<Vec<u8>> {
+    let bad = || corrupt("malformed delta");
+    let mut at = 0;
+    let varint = |at: &mut usize| -> GitResult<usize> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = *delta.get(*at).ok_or_else(bad)?;
+            *at += 1;
+            value |= usize::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            if shift > 56 {
+                return Err(bad());
+            }
+        }
+    };
+    if varint(&mut at)? != base.len() {
+        return Err(bad());
+    }
+    let size = varint(&mut at)?;
+    let mut out = Vec::with_capacity(size);
+    while at < delta.len() {
+        let op = delta[at];
+        at += 1;
+        if op & 0x80 != 0 {
+            // Which of the offset and size bytes follow is in the low bits.
+            let mut fields = [0usize; 7];
+            for (bit, field) in fields.iter_mut().enumerate() {
+                if op & (1 << bit) != 0 {
+                    *field = usize::from(*delta.get(at).ok_or_else(bad)?);
+                    at += 1;
+                }
+            }
+            let start = fields[0] | fields[1] << 8 | fields[2] << 16 | fields[3] << 24;
+            let mut len = fields[4] | fields[5] << 8 | fields[6] << 16;
+            if len == 0 {
+                len = 0x10000;
+            }
+            let end = start.checked_add(len).ok_or_else(bad)?;
+            out.extend_from_slice(base.get(start..end).ok_or_else(bad)?);
+        } else if op != 0 {
+            let len = usize::from(op);
+            out.extend_from_slice(delta.get(at..at + len).ok_or_else(bad)?);
+            at += len;
+        } else {
+            return Err(bad());
+        }
+    }
+    if out.len() != size {
+        return Err(bad());
+    }
+    Ok(out)
+}
+
+/// A delta that rebuilds `target` from `base`, for [`apply_delta`]. Runs
+/// of the target found in the base become copies and the rest inserts,
+/// so a delta between unrelated contents is the target and then some.
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut blocks: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    let last = base.len().saturating_sub(DELTA_BLOCK - 1);
+    for start in (0..last).step_by(DELTA_BLOCK) {
+        let starts = blocks.entry(&base[start..start + DELTA_BLOCK]).or_default();
+        if starts.len() < MAX_BLOCK_STARTS {
+            starts.push(start);
+        }
+    }
+
+    let mut out = Vec::new();
+    push_delta_size(&mut out, base.len());
+    push_delta_size(&mut out, target.len());
+    // Target bytes from `pending` up to `at` are still to be inserted.
+    let (mut pending, mut at) = (0, 0);
+    while at + DELTA_BLOCK <= target.len() {
+        // (length, base start, target start)
+        let mut best = (0, 0, 0);
+        for &start in blocks
+            .get(&target[at..at + DELTA_BLOCK])
+            .into_iter()
+            .flatten()
+        {
+            let forward = base[start..]
+                .iter()
+                .zip(&target[at..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            // A match can also take back bytes not yet inserted.
+            let mut back = 0;
+            while back < at - pending
+                && back < start
+                && base[start - back - 1] == target[at - back - 1]
+            {
+                back += 1;
+            }
+            if forward + back > best.0 {
+                best = (forward + back, start - back, at - back);
+            }
+        }
+        let (len, from, to) = best;
+        if len < DELTA_BLOCK {
+            at += 1;
+            continue;
+        }
+        push_inserts(&mut out, &target[pending..to]);
+        push_copies(&mut out, from, len);
+        at = to + len;
+        pending = at;
+    }
+    push_inserts(&mut out, &target[pending..]);
+    out
+}
+
+/// A size at the head of a delta, seven bits at a time, lowest first.
+fn push_delta_size(out: &mut Vec<u8>, mut size: usize) {
+    while size >= 0x80 {
+        out.push(0x80 | (size & 0x7f) as u8);
+        size >>= 7;
+    }
+    out.push(size as u8);
+}
+
+/// Copy instructions for `len` bytes of the base from `from`: a byte whose
+/// low bits say which bytes of the offset and size follow, the rest being
+/// zero.
+fn push_copies(out: &mut Vec<u8>, mut from: usize, mut len: usize) {
+    while len > 0 {
+        let n = len.min(MAX_COPY);
+        let at = out.len();
+        out.push(0x80);
+        let fields = (0..4)
+            .map(|i| (from >> (8 * i)) as u8)
+            .chain((0..3).map(|i| (n >> (8 * i)) as u8));
+        for (bit, byte) in fields.enumerate() {
+            if byte != 0 {
+                out[at] |= 1 << bit;
+                out.push(byte);
+            }
+        }
+        from += n;
+        len -= n;
+    }
+}
+
+/// Insert instructions for `data`, at most 127 bytes each.
+fn push_inserts(out: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(0x7f) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+/// Builds a pack and its index in memory, an entry at a time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackWriter {
+    data: Vec<u8>,
+    /// The id, CRC32 and offset of each entry so far.
+    entries: Vec<(Oid, u32, u64)>,
+}
+
+impl Default for PackWriter {
+    fn default() -> PackWriter {
+        PackWriter::new()
+    }
+}
+
+impl PackWriter {
+    pub fn new() -> PackWriter {
+        let mut data = PACK_SIGNATURE.to_vec();
+        data.extend_from_slice(&2u32.to_be_bytes());
+        // The count is filled in once it's known.
+        data.extend_from_slice(&0u32.to_be_bytes());
+        PackWriter {
+            data,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add `body` as a whole object of type `kind`.
+    pub fn add_object(&mut self, kind: ObjectType, body: &[u8]) -> GitResult<Oid> {
+        let id = hash_object(kind, body);
+        let type_bits = match kind {
+            ObjectType::Commit => 1,
+            ObjectType::Tree => 2,
+            ObjectType::Blob => 3,
+            ObjectType::Tag => 4,
+        };
+        self.push_entry(id, type_bits, None, body)?;
+        Ok(id)
+    }
+
+    /// Add `body`, of type `kind`, as a `REF_DELTA` against `base`, which
+    /// should be in the pack too for it to be read. `delta` rebuilds
+    /// `body` from `base`'s contents, as [`create_delta`] makes it.
+    pub fn add_delta(
+        &mut self,
+        kind: ObjectType,
+        body: &[u8],
+        base: &Oid,
+        delta: &[u8],
+    ) -> GitResult<Oid> {
+        let id = hash_object(kind, body);
+        self.push_entry(id, OBJ_REF_DELTA, Some(base), delta)?;
+        Ok(id)
+    }
+
+    fn push_entry(
+        &mut self,
+        id: Oid,
+        type_bits: u8,
+        base: Option<&Oid>,
+        data: &[u8],
+    ) -> GitResult<()> {
+        let start = self.data.len();
+        push_entry_header(&mut self.data, type_bits, data.len());
+        if let Some(base) = base {
+            self.data.extend_from_slice(base.as_bytes());
+        }
+        let mut encoder = ZlibEncoder::new(&mut self.data, Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()?;
+        let crc = crc32(&self.data[start..]);
+        self.entries.push((id, crc, start as u64));
+        Ok(())
+    }
+
+    /// The finished pack and its index, with the pack's checksum, which
+    /// names them.
+    pub fn finish(mut self) -> (Vec<u8>, Vec<u8>, Oid) {
+        let count = self.entries.len() as u32;
+        self.data[8..12].copy_from_slice(&count.to_be_bytes());
+        let checksum: [u8; 20] = Sha1::digest(&self.data).into();
+        self.data.extend_from_slice(&checksum);
+        let checksum = Oid::from_raw(checksum);
+        let index = write_index(&mut self.entries, &checksum);
+        (self.data, index, checksum)
+    }
+}
+
+/// An entry's type and inflated size, four bits of the size in the first
+/// byte and seven in each after it.
+fn push_entry_header(out: &mut Vec<u8>, type_bits: u8, size: usize) {
+    let mut byte = (type_bits << 4) | (size & 0x0f) as u8;
+    let mut rest = size >> 4;
+    while rest != 0 {
+        out.push(byte | 0x80);
+        byte = (rest & 0x7f) as u8;
+        rest >>= 7;
+    }
+    out.push(byte);
+}
+
+/// A version 2 index of `entries`, each an id with its CRC32 and offset,
+/// for the pack with checksum `pack_checksum`. The entries are sorted by
+/// id in place.
+pub fn write_index(entries: &mut [(Oid, u32, u64)], pack_checksum: &Oid) -> Vec<u8> {
+    entries.sort_unstable_by_key(|&(id, ..)| id);
+    let mut out = IDX_SIGNATURE.to_vec();
+    out.extend_from_slice(&2u32.to_be_bytes());
+    let mut counted = 0;
+    for first in 0..=255u8 {
+        while entries
+            .get(counted)
+            .is_some_and(|(id, ..)| id.as_bytes()[0] <= first)
+        {
+            counted += 1;
+        }
+        out.extend_from_slice(&(counted as u32).to_be_bytes());
+    }
+    for (id, ..) in entries.iter() {
+        out.extend_from_slice(id.as_bytes());
+    }
+    for (_, crc, _) in entries.iter() {
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+    // Offsets past 2GiB go in a table of their own, pointed to from here.
+    let mut large = Vec::new();
+    for &(_, _, offset) in entries.iter() {
+        match u32::try_from(offset) {
+            Ok(small) if small & 0x8000_0000 == 0 => out.extend_from_slice(&small.to_be_bytes()),
+            _ => {
+                let index = 0x8000_0000 | large.len() as u32;
+                out.extend_from_slice(&index.to_be_bytes());
+                large.push(offset);
+            }
+        }
+    }
+    for offset in large {
+        out.extend_from_slice(&offset.to_be_bytes());
+    }
+    out.extend_from_slice(pack_checksum.as_bytes());
+    let checksum: [u8; 20] = Sha1::digest(&out).into();
+    out.extend_from_slice(&checksum);
+    out
+}
+
+/// The CRC32 the index records for an entry's raw bytes.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::object::hash_object;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn reads_objects_through_delta_chains() {
+        for name in ["ofs-delta.idx", "ref-delta.idx"] {
+            let pack = Pack::open(&fixture(name)).unwrap();
+            assert_eq!(pack.index.len(), 13);
+            let mut cache = HashMap::new();
+            let mut depths = Vec::new();
+            for (position, id) in pack.index.ids().iter().enumerate() {
+                let object = pack
+                    .read_at(pack.index.offset(position), &mut cache)
+                    .unwrap();
+                assert_eq!(hash_object(object.kind, &object.data), *id, "{}", name);
+                depths.push(object.depth);
+            }
+            depths.sort();
+            assert_eq!(depths[depths.len() - 3..], [1, 2, 3], "{}", name);
+            let missing = Oid::from_raw([0xab; 20]);
+            assert_eq!(pack.index.position(&missing), None);
+        }
+    }
+
+    #[test]
+    fn applies_copies_and_inserts() {
+        let base = b"hello, world";
+        // Sizes 12 and 11, copy five bytes from offset 0, insert six.
+        let delta = [12, 11, 0x90, 5, 6, b' ', b't', b'h', b'e', b'r', b'e'];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello there");
+        assert!(apply_delta(b"short", &delta).is_err());
+        assert!(apply_delta(base, &[12, 11, 0x90, 50]).is_err());
+    }
+
+    #[test]
+    fn creates_deltas_that_apply_back() {
+        let base: Vec<u8> = (0..200_000u32).map(|n| (n * 7 % 251) as u8).collect();
+        let mut target = base.clone();
+        target[10] ^= 1;
+        target.splice(150_000..150_000, b"inserted".iter().copied());
+        target.truncate(190_000);
+        let cases: [(&[u8], &[u8]); 5] = [
+            (&base, &target),
+            (&target, &base),
+            (b"", b"all new"),
+            (b"all gone", b""),
+            (b"short", b"short"),
+        ];
+        for (base, target) in cases {
+            let delta = create_delta(base, target);
+            assert_eq!(apply_delta(base, &delta).unwrap(), target);
+        }
+        // Copies longer than one instruction allows are split up.
+        assert!(create_delta(&base, &target).len() < 100);
+    }
+}
@@ -0,0 +1,176 @@
+//! The `.git/packed-refs` file, where `git pack-refs` and `git clone` keep
+//! most refs instead of loose files.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::oid::Oid;
+use crate::error::{GitError, GitResult};
+
+const HEADER_PREFIX: &str = "# pack-refs with:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub oid: Oid,
+    /// What an annotated tag ultimately points at, from its `^<oid>` line.
+    pub peeled: Option<Oid>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackedRefs {
+    /// The traits listed in the header, e.g. `peeled` and `sorted`.
+    pub traits: Vec<String>,
+    /// Sorted by name.
+    refs: Vec<PackedRef>,
+}
+
+impl PackedRefs {
+    /// Load `packed-refs`; a missing file has no refs.
+    pub fn load(path: &Path) -> GitResult<PackedRefs> {
+        match fs::read_to_string(path) {
+            Ok(text) => PackedRefs::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(PackedRefs::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> GitResult<PackedRefs> {
+        let corrupt =
+            |number: usize| GitError::Corrupt(format!("packed-refs: bad line {}", number + 1));
+        let mut packed = PackedRefs::default();
+        for (number, line) in text.lines().enumerate() {
+            if let Some(traits) = line.strip_prefix(HEADER_PREFIX) {
+                packed.traits = traits.split_whitespace().map(str::to_string).collect();
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(peeled) = line.strip_prefix('^') {
+                let last = packed.refs.last_mut().ok_or_else(|| corrupt(number))?;
+                if last.peeled.is_some() {
+                    return Err(corrupt(number));
+                }
+                last.peeled = Some(peeled.parse::<Oid>().map_err(|_| corrupt(number))?);
+                continue;
+            }
+            let mut parts = line.splitn(2, ' ');
+            let id = parts
+                .next()
+                .and_then(|hex| hex.parse::<Oid>().ok())
+                .ok_or_else(|| corrupt(number))?;
+            let name = parts.next().ok_or_else(|| corrupt(number))?;
+            packed.refs.push(PackedRef {
+                name: name.to_string(),
+                oid: id,
+                peeled: None,
+            });
+        }
+        if !packed.has_trait("sorted") {
+            packed.refs.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        Ok(packed)
+    }
+
+    pub fn has_trait(&self, name: &str) -> bool {
+        self.traits.iter().any(|t| t == name)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&PackedRef> {
+        self.refs
+            .binary_search_by(|r| r.name.as_str().cmp(name))
+            .ok()
+            .map(|i| &self.refs[i])
+    }
+
+    pub fn refs(&self) -> &[PackedRef] {
+        &self.refs
+    }
+
+    /// Add a ref, replacing any packed ref with the same name.
+    pub fn insert(&mut self, packed: PackedRef) {
+        match self
+            .refs
+            .binary_search_by(|r| r.name.as_str().cmp(&packed.name))
+        {
+            Ok(i) => self.refs[i] = packed,
+            Err(i) => self.refs.insert(i, packed),
+        }
+    }
+
+    /// Drop `name`, returning whether it was present.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.refs.len();
+        self.refs.retain(|r| r.name != name);
+        self.refs.len() != before
+    }
+
+    /// The file contents, always written sorted with a header.
+    pub fn serialize(&self) -> String {
+        let mut traits = self.traits.clone();
+        if !traits.iter().any(|t| t == "sorted") {
+            traits.push("sorted".to_string());
+        }
+        let mut out = format!("{} {} \n", HEADER_PREFIX, traits.join(" "));
+        for r in &self.refs {
+            out.push_str(&format!("{} {}\n", r.oid, r.name));
+            if let Some(peeled) = &r.peeled {
+                out.push_str(&format!("^{}\n", peeled));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `packed-refs` from a `git clone` of a repo with a lightweight tag
+    /// and two annotated tags.
+    const CLONE_FIXTURE: &str = include_str!("../../tests/fixtures/packed-refs");
+
+    #[test]
+    fn parses_a_real_clone() {
+        let packed = PackedRefs::parse(CLONE_FIXTURE).unwrap();
+        assert_eq!(packed.traits, vec!["peeled", "fully-peeled", "sorted"]);
+        assert_eq!(packed.refs().len(), 5);
+
+        let annotated = packed.find("refs/tags/v1.0").unwrap();
+        assert_eq!(
+            annotated.oid.to_string(),
+            "17c53fc890c62d77e3c6aa17dd5ae0944ca23001"
+        );
+        assert_eq!(
+            annotated.peeled.map(|p| p.to_string()).as_deref(),
+            Some("53181693e090b76ba96ba743759c4712782043c6")
+        );
+        assert_eq!(packed.find("refs/tags/v1.1-light").unwrap().peeled, None);
+        assert!(packed.find("refs/heads/main").is_none());
+    }
+
+    #[test]
+    fn serializes_back_to_the_same_file() {
+        let packed = PackedRefs::parse(CLONE_FIXTURE).unwrap();
+        assert_eq!(packed.serialize(), CLONE_FIXTURE);
+    }
+
+    #[test]
+    fn rejects_orphan_peel_lines() {
+        assert!(PackedRefs::parse("^53181693e090b76ba96ba743759c4712782043c6\n").is_err());
+        assert!(PackedRefs::parse("not-an-oid refs/heads/x\n").is_err());
+    }
+
+    #[test]
+    fn sorts_unsorted_files() {
+        let packed = PackedRefs::parse(
+            "670284a0b9ad86abaf35be65616d427233a73ae2 refs/heads/b\n\
+             1f4e60d0e36329ddb989541a385fdb3e367b6db9 refs/heads/a\n",
+        )
+        .unwrap();
+        assert!(packed.find("refs/heads/a").is_some());
+        assert!(packed.find("refs/heads/b").is_some());
+    }
+}
@@ -0,0 +1,234 @@
+//! Git-style patches: the `diff --git` sections `git diff` and
+//! `git format-patch` write for each changed file.
+
+use crate::core::diff::{self, DiffAlgorithm};
+use crate::core::object::MODE_GITLINK;
+use crate::core::odb::ObjectDatabase;
+use crate::core::oid::Oid;
+use crate::core::regex::Regex;
+use crate::core::tree::{FlatEntry, FlatTree};
+use crate::core::word_diff::{self, WordDiffMode};
+use crate::error::GitResult;
+
+/// Lines of context around each change, git's default.
+pub const CONTEXT: usize = 3;
+
+/// How far the `index` line abbreviates blob ids.
+const ABBREV: usize = 7;
+
+/// How the hunks of a section are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PatchOptions<'a> {
+    pub algorithm: DiffAlgorithm,
+    pub word_diff: WordDiffMode,
+    /// What a word is for `word_diff`, as `diff.wordRegex` sets it; runs
+    /// of non-whitespace otherwise.
+    pub word_regex: Option<&'a Regex>,
+}
+
+/// The patch turning `old` into `new`: a section for every path whose
+/// blob or mode differs, in path order.
+pub fn write_tree_patch(
+    out: &mut Vec<u8>,
+    odb: &ObjectDatabase,
+    old: &FlatTree,
+    new: &FlatTree,
+) -> GitResult<()> {
+    let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    for path in paths {
+        let (old, new) = (old.get(path), new.get(path));
+        if old != new {
+            write_file_patch(out, odb, path, old, new)?;
+        }
+    }
+    Ok(())
+}
+
+/// The section for one path, `None` on the side where it doesn't exist.
+/// A path that turns from one kind of entry into another, such as a file
+/// into a symlink, is written as a deletion and an addition, as git does.
+pub fn write_file_patch(
+    out: &mut Vec<u8>,
+    odb: &ObjectDatabase,
+    path: &str,
+    old: Option<&FlatEntry>,
+    new: Option<&FlatEntry>,
+) -> GitResult<()> {
+    let (old_data, new_data) = (content(odb, old)?, content(odb, new)?);
+    write_file_patch_with(
+        out,
+        odb,
+        path,
+        old.map(|e| (e, &old_data[..])),
+        new.map(|e| (e, &new_data[..])),
+        &PatchOptions::default(),
+    )
+}
+
+/// [`write_file_patch`] for contents already at hand, such as a working
+/// tree file's, which needn't be in `odb`, with its hunks written as
+/// `options` asks. A submodule's content is the `Subproject commit` line
+/// git shows for it.
+pub fn write_file_patch_with(
+    out: &mut Vec<u8>,
+    odb: &ObjectDatabase,
+    path: &str,
+    old: Option<(&FlatEntry, &[u8])>,
+    new: Option<(&FlatEntry, &[u8])>,
+    options: &PatchOptions,
+) -> GitResult<()> {
+    if let (Some((old_entry, _)), Some((new_entry, _))) = (old, new) {
+        if kind(old_entry.mode) != kind(new_entry.mode) {
+            write_file_patch_with(out, odb, path, old, None, options)?;
+            return write_file_patch_with(out, odb, path, None, new, options);
+        }
+    }
+    write_section(out, odb, (path, path), None, old, new, options)
+}
+
+/// The section for a file renamed from `from` to `to`: the `similarity
+/// index` and `rename` lines, then any change to its mode or content.
+pub fn write_rename_patch(
+    out: &mut Vec<u8>,
+    odb: &ObjectDatabase,
+    (from, to): (&str, &str),
+    similarity: u32,
+    old: (&FlatEntry, &[u8]),
+    new: (&FlatEntry, &[u8]),
+    options: &PatchOptions,
+) -> GitResult<()> {
+    write_section(
+        out,
+        odb,
+        (from, to),
+        Some(("rename", similarity)),
+        Some(old),
+        Some(new),
+        options,
+    )
+}
+
+/// [`write_rename_patch`] for a file copied from `from` to `to`, with
+/// `copy` lines in place of the `rename` ones.
+pub fn write_copy_patch(
+    out: &mut Vec<u8>,
+    odb: &ObjectDatabase,
+    (from, to): (&str, &str),
+    similarity: u32,
+    old: (&FlatEntry, &[u8]),
+    new: (&FlatEntry, &[u8]),
+    options: &PatchOptions,
+) -> GitResult<()> {
+    write_section(
+        out,
+        odb,
+        (from, to),
+        Some(("copy", similarity)),
+        Some(old),
+        Some(new),
+        options,
+    )
+}
+
+fn write_section(
+    out: &mut Vec<u8>,
+    odb: &ObjectDatabase,
+    (old_path, new_path): (&str, &str),
+    // How the paths are paired, `rename` or `copy`, and how alike the
+    // two files are.
+    pairing: Option<(&str, u32)>,
+    old: Option<(&FlatEntry, &[u8])>,
+    new: Option<(&FlatEntry, &[u8])>,
+    options: &PatchOptions,
+) -> GitResult<()> {
+    let (old_data, new_data) = (
+        old.map_or(&[][..], |(_, d)| d),
+        new.map_or(&[][..], |(_, d)| d),
+    );
+    let (old, new) = (old.map(|(e, _)| e), new.map(|(e, _)| e));
+    out.extend_from_slice(format!("diff --git a/{} b/{}\n", old_path, new_path).as_bytes());
+    match (old, new) {
+        (None, Some(new)) => {
+            out.extend_from_slice(format!("new file mode {:o}\n", new.mode).as_bytes())
+        }
+        (Some(old), None) => {
+            out.extend_from_slice(format!("deleted file mode {:o}\n", old.mode).as_bytes())
+        }
+        (Some(old), Some(new)) if old.mode != new.mode => out.extend_from_slice(
+            format!("old mode {:o}\nnew mode {:o}\n", old.mode, new.mode).as_bytes(),
+        ),
+        _ => {}
+    }
+    if let Some((how, similarity)) = pairing {
+        out.extend_from_slice(
+            format!(
+                "similarity index {}%\n{} from {}\n{} to {}\n",
+                similarity, how, old_path, how, new_path
+            )
+            .as_bytes(),
+        );
+    }
+    let (old_id, new_id) = (old.map(|e| e.oid), new.map(|e| e.oid));
+    if old_id == new_id {
+        // Only the mode changed.
+        return Ok(());
+    }
+    let mut index = format!(
+        "index {}..{}",
+        abbreviate(odb, old_id)?,
+        abbreviate(odb, new_id)?
+    );
+    if let (Some(old), Some(new)) = (old, new) {
+        if old.mode == new.mode {
+            index.push_str(&format!(" {:o}", old.mode));
+        }
+    }
+    out.extend_from_slice(index.as_bytes());
+    out.push(b'\n');
+
+    let old_name = old.map_or("/dev/null".to_string(), |_| format!("a/{}", old_path));
+    let new_name = new.map_or("/dev/null".to_string(), |_| format!("b/{}", new_path));
+    if diff::is_binary(old_data) || diff::is_binary(new_data) {
+        out.extend_from_slice(
+            format!("Binary files {} and {} differ\n", old_name, new_name).as_bytes(),
+        );
+        return Ok(());
+    }
+    let hunks = diff::unified_hunks_with(old_data, new_data, CONTEXT, options.algorithm);
+    if !hunks.is_empty() {
+        out.extend_from_slice(format!("--- {}\n+++ {}\n", old_name, new_name).as_bytes());
+        out.extend_from_slice(&word_diff::word_diff_hunks(
+            &hunks,
+            options.word_diff,
+            options.word_regex,
+        ));
+    }
+    Ok(())
+}
+
+/// The file type bits of `mode`: files, symlinks and submodules can't be
+/// diffed against one another.
+fn kind(mode: u32) -> u32 {
+    mode & 0o170000
+}
+
+fn abbreviate(odb: &ObjectDatabase, id: Option<Oid>) -> GitResult<String> {
+    match id {
+        Some(id) => odb.abbreviate(&id, ABBREV),
+        None => Ok("0".repeat(ABBREV)),
+    }
+}
+
+/// What a side of the diff holds: a blob's bytes, or the commit a
+/// submodule is at, as git shows it.
+pub fn content(odb: &ObjectDatabase, entry: Option<&FlatEntry>) -> GitResult<Vec<u8>> {
+    Ok(match entry {
+        None => Vec::new(),
+        Some(entry) if entry.mode == MODE_GITLINK => {
+            format!("Subproject commit {}\n", entry.oid).into_bytes()
+        }
+        Some(entry) => odb.read_blob(&entry.oid)?,
+    })
+}
@@ -0,0 +1,179 @@
+//! Pathspecs restricting a command to part of the tree, as in
+//! `git log -- src/ '*.rs'`.
+//!
+//! A plain path matches itself and everything under it, a path ending in
+//! `/` only a directory's contents, and anything with a glob character is
+//! matched against whole paths with wildmatch, where `*` crosses `/` like
+//! it does in git pathspecs.
+
+use crate::core::odb::ObjectDatabase;
+use crate::core::oid::Oid;
+use crate::core::tree;
+use crate::core::wildmatch::wildmatch;
+use crate::error::GitResult;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    /// A path, standing for a file or a whole directory. The empty path
+    /// is the whole tree.
+    Literal(String),
+    /// The contents of a directory.
+    Directory(String),
+    Glob(String),
+}
+
+/// A list of pathspecs; a path matches if any of them does. An empty list
+/// matches everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pathspec {
+    items: Vec<Item>,
+}
+
+impl Pathspec {
+    pub fn parse<S: AsRef<str>>(specs: &[S]) -> Pathspec {
+        let items = specs
+            .iter()
+            .map(|spec| {
+                let spec = spec.as_ref();
+                let spec = spec.strip_prefix("./").unwrap_or(spec);
+                if spec.contains(['*', '?', '[']) {
+                    Item::Glob(spec.to_string())
+                } else if spec == "." {
+                    Item::Literal(String::new())
+                } else if let Some(dir) = spec.strip_suffix('/') {
+                    Item::Directory(dir.trim_end_matches('/').to_string())
+                } else {
+                    Item::Literal(spec.to_string())
+                }
+            })
+            .collect();
+        Pathspec { items }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Whether the `/`-separated path of a file is selected.
+    pub fn matches(&self, path: &str) -> bool {
+        self.is_empty()
+            || self.items.iter().any(|item| match item {
+                Item::Literal(literal) => is_within(path, literal),
+                Item::Directory(dir) => path.len() > dir.len() && is_within(path, dir),
+                Item::Glob(glob) => wildmatch(glob.as_bytes(), path.as_bytes(), false),
+            })
+    }
+
+    /// Whether anything under the directory `dir` could be selected, so
+    /// a walk can skip directories that can't.
+    pub fn may_match_within(&self, dir: &str) -> bool {
+        self.is_empty()
+            || self.items.iter().any(|item| match item {
+                Item::Literal(path) | Item::Directory(path) => {
+                    is_within(path, dir) || is_within(dir, path)
+                }
+                Item::Glob(_) => true,
+            })
+    }
+
+    /// Whether the selected paths differ between two trees, with `None`
+    /// standing for the empty tree. Without globs only the entries at the
+    /// given paths are looked up, reading just the trees along the way.
+    pub fn changed(&self, odb: &ObjectDatabase, old: Option<&Oid>, new: &Oid) -> GitResult<bool> {
+        if old == Some(new) {
+            return Ok(false);
+        }
+        if self.items.iter().any(|item| matches!(item, Item::Glob(_))) {
+            let selected = |tree: Option<&Oid>| -> GitResult<tree::FlatTree> {
+                let mut flat = match tree {
+                    Some(tree) => tree::flatten(odb, tree)?,
+                    None => tree::FlatTree::new(),
+                };
+                flat.retain(|path, _| self.matches(path));
+                Ok(flat)
+            };
+            return Ok(selected(old)? != selected(Some(new))?);
+        }
+        if self.is_empty() {
+            return Ok(true);
+        }
+        for item in &self.items {
+            let (path, dir_only) = match item {
+                Item::Literal(path) => (path, false),
+                Item::Directory(dir) => (dir, true),
+                Item::Glob(_) => unreachable!("globs are handled above"),
+            };
+            if path.is_empty() {
+                return Ok(true);
+            }
+            let entry_at = |tree: Option<&Oid>| -> GitResult<Option<(u32, Oid)>> {
+                let entry = match tree {
+                    Some(tree) => tree::find_path(odb, tree, path)?,
+                    None => None,
+                };
+                Ok(entry
+                    .filter(|entry| !dir_only || entry.is_tree())
+                    .map(|entry| (entry.mode, entry.oid)))
+            };
+            if entry_at(old)? != entry_at(Some(new))? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Whether `path` is `dir` or somewhere under it.
+fn is_within(path: &str, dir: &str) -> bool {
+    dir.is_empty()
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_repo, write_commit};
+
+    #[test]
+    fn matches_paths_directories_and_globs() {
+        let spec = Pathspec::parse(&["src", "docs/", "*.md"]);
+        assert!(spec.matches("src"));
+        assert!(spec.matches("src/main.rs"));
+        assert!(!spec.matches("srcs/main.rs"));
+        assert!(spec.matches("docs/guide.txt"));
+        assert!(!spec.matches("docs"));
+        assert!(spec.matches("README.md"));
+        assert!(spec.matches("notes/deep/todo.md"));
+        assert!(!spec.matches("Cargo.toml"));
+        assert!(Pathspec::parse(&["."]).matches("anything"));
+        assert!(Pathspec::parse::<&str>(&[]).matches("anything"));
+    }
+
+    #[test]
+    fn compares_only_the_selected_entries() {
+        let (_dir, repo) = init_repo();
+        let odb = repo.odb();
+        let tree_of = |id| odb.read_commit(&id).unwrap().tree;
+        let one = write_commit(&repo, &[], &[("src/a.rs", "a"), ("b.md", "b")], "one");
+        let two = write_commit(&repo, &[one], &[("src/a.rs", "a"), ("b.md", "c")], "two");
+        let (one, two) = (tree_of(one), tree_of(two));
+
+        let src = Pathspec::parse(&["src"]);
+        assert!(!src.changed(odb, Some(&one), &two).unwrap());
+        assert!(src.changed(odb, None, &two).unwrap());
+        assert!(Pathspec::parse(&["b.md"])
+            .changed(odb, Some(&one), &two)
+            .unwrap());
+        assert!(Pathspec::parse(&["*.md"])
+            .changed(odb, Some(&one), &two)
+            .unwrap());
+        assert!(!Pathspec::parse(&["*.rs"])
+            .changed(odb, Some(&one), &two)
+            .unwrap());
+        assert!(!Pathspec::parse(&["b.md/"])
+            .changed(odb, Some(&one), &two)
+            .unwrap());
+    }
+}
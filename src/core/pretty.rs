@@ -0,0 +1,306 @@
+//! `--pretty=format:` strings, expanded once per commit.
+//!
+//! Supported placeholders:
+//!
+//! | placeholder | expands to |
+//! |---|---|
+//! | `%H` `%h` | the commit id, full or abbreviated |
+//! | `%T` `%t` | the tree id, full or abbreviated |
+//! | `%P` `%p` | the parent ids, full or abbreviated, space separated |
+//! | `%an` `%ae` | the author's name and email |
+//! | `%ad` `%aD` `%ai` `%aI` `%at` `%ar` | the author date: in the chosen [`DateFormat`], RFC 2822, ISO, strict ISO, unix time or relative |
+//! | `%cn` `%ce` `%cd` ... | the same for the committer |
+//! | `%s` `%b` `%B` | the subject, the body, and the whole message |
+//! | `%d` `%D` | ref decorations, with and without ` (...)` around them |
+//! | `%n` `%%` | a newline and a literal `%` |
+//!
+//! Anything else after a `%` comes out as written, as it does in git.
+
+use std::collections::HashMap;
+
+use crate::core::object::{Commit, ObjectType};
+use crate::core::odb::ObjectDatabase;
+use crate::core::oid::Oid;
+use crate::core::refs;
+use crate::core::signature::{DateFormat, Signature};
+use crate::error::GitResult;
+use crate::repository::{Head, Repository};
+
+/// How far ids are abbreviated by `%h`, `%t` and `%p`.
+const ABBREV: usize = 7;
+
+/// The ref names to show next to each commit, as `--decorate` shows them.
+/// Build it once and share it between every commit being formatted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Decorations {
+    names: HashMap<Oid, Vec<String>>,
+}
+
+impl Decorations {
+    /// Every ref's short name, `tag: ` and all, under the object it points
+    /// at and, for annotated tags, the object they peel to. Like git the
+    /// newest-sorting name comes first, after HEAD.
+    pub fn load(repo: &Repository) -> GitResult<Decorations> {
+        let odb = repo.odb();
+        let mut names: HashMap<Oid, Vec<String>> = HashMap::new();
+        for reference in refs::iter(repo)? {
+            let reference = reference?;
+            let short = refs::shorten(&reference.name);
+            let name = if reference.name.starts_with("refs/tags/") {
+                format!("tag: {}", short)
+            } else {
+                short.to_string()
+            };
+            let peeled = match reference.peeled() {
+                Some(peeled) => peeled,
+                None => match odb.read_header(&reference.target) {
+                    Ok((ObjectType::Tag, _)) => odb.peel(&reference.target)?.0,
+                    _ => reference.target,
+                },
+            };
+            if peeled != reference.target {
+                names.entry(peeled).or_default().push(name.clone());
+            }
+            names.entry(reference.target).or_default().push(name);
+        }
+        for list in names.values_mut() {
+            list.reverse();
+        }
+        match repo.head()? {
+            Head::Branch(branch, Some(id)) => {
+                let list = names.entry(id).or_default();
+                let short = refs::shorten(&branch);
+                list.retain(|name| name != short);
+                list.insert(0, format!("HEAD -> {}", short));
+            }
+            Head::Detached(id) => names.entry(id).or_default().insert(0, "HEAD".to_string()),
+            Head::Branch(_, None) | Head::Unborn(_) => {}
+        }
+        Ok(Decorations { names })
+    }
+
+    pub fn get(&self, id: &Oid) -> &[String] {
+        self.names.get(id).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+/// Expands format strings for commits.
+#[derive(Debug, Clone, Copy)]
+pub struct CommitFormatter<'a> {
+    odb: &'a ObjectDatabase,
+    date_format: DateFormat,
+    decorations: Option<&'a Decorations>,
+}
+
+impl<'a> CommitFormatter<'a> {
+    pub fn new(odb: &'a ObjectDatabase) -> CommitFormatter<'a> {
+        CommitFormatter {
+            odb,
+            date_format: DateFormat::default(),
+            decorations: None,
+        }
+    }
+
+    /// The style of `%ad` and `%cd`.
+    pub fn date_format(mut self, date_format: DateFormat) -> Self {
+        self.date_format = date_format;
+        self
+    }
+
+    /// What `%d` and `%D` show; without this they're always empty.
+    pub fn decorations(mut self, decorations: &'a Decorations) -> Self {
+        self.decorations = Some(decorations);
+        self
+    }
+
+    pub fn format(&self, template: &str, id: &Oid, commit: &Commit) -> GitResult<String> {
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(percent) = rest.find('%') {
+            out.push_str(&rest[..percent]);
+            rest = &rest[percent + 1..];
+            let consumed = self.expand(rest, id, commit, &mut out)?;
+            if consumed == 0 {
+                out.push('%');
+            }
+            rest = &rest[consumed..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Expand the placeholder at the start of `spec`, the text after a
+    /// `%`, returning how many bytes of it were used: 0 when it isn't one.
+    fn expand(&self, spec: &str, id: &Oid, commit: &Commit, out: &mut String) -> GitResult<usize> {
+        let mut chars = spec.chars();
+        let first = match chars.next() {
+            Some(c) => c,
+            None => return Ok(0),
+        };
+        match first {
+            'H' => out.push_str(&id.to_string()),
+            'h' => out.push_str(&self.odb.abbreviate(id, ABBREV)?),
+            'T' => out.push_str(&commit.tree.to_string()),
+            't' => out.push_str(&self.odb.abbreviate(&commit.tree, ABBREV)?),
+            'P' => {
+                let parents: Vec<String> = commit.parents.iter().map(Oid::to_string).collect();
+                out.push_str(&parents.join(" "));
+            }
+            'p' => {
+                let mut parents = Vec::new();
+                for parent in &commit.parents {
+                    parents.push(self.odb.abbreviate(parent, ABBREV)?);
+                }
+                out.push_str(&parents.join(" "));
+            }
+            's' => out.push_str(&subject(&commit.message)),
+            'b' => out.push_str(body(&commit.message)),
+            'B' => out.push_str(&commit.message),
+            'd' | 'D' => {
+                let names = self.decorations.map(|d| d.get(id)).unwrap_or(&[]);
+                if first == 'D' {
+                    out.push_str(&names.join(", "));
+                } else if !names.is_empty() {
+                    out.push_str(&format!(" ({})", names.join(", ")));
+                }
+            }
+            'n' => out.push('\n'),
+            '%' => out.push('%'),
+            'a' | 'c' => {
+                let person = if first == 'a' {
+                    &commit.author
+                } else {
+                    &commit.committer
+                };
+                match chars.next().and_then(|c| self.person(person, c)) {
+                    Some(expanded) => out.push_str(&expanded),
+                    None => return Ok(0),
+                }
+                return Ok(2);
+            }
+            _ => return Ok(0),
+        }
+        Ok(first.len_utf8())
+    }
+
+    /// The `%a<c>` or `%c<c>` placeholder for a signature.
+    fn person(&self, person: &Signature, c: char) -> Option<String> {
+        Some(match c {
+            'n' => person.name.clone(),
+            'e' => person.email.clone(),
+            'd' => person.format_date(self.date_format),
+            'D' => person.to_rfc2822(),
+            'i' => person.to_iso_date(),
+            'I' => person.to_iso8601(),
+            't' => person.time.to_string(),
+            'r' => person.format_date(DateFormat::Relative),
+            _ => return None,
+        })
+    }
+}
+
+/// The first paragraph of a message, its lines joined by spaces.
+pub(crate) fn subject(message: &str) -> String {
+    let lines: Vec<&str> = message
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .take_while(|line| !line.trim().is_empty())
+        .map(str::trim)
+        .collect();
+    lines.join(" ")
+}
+
+/// Everything after the first paragraph of a message, from its next
+/// non-blank line on.
+fn body(message: &str) -> &str {
+    let mut seen_subject = false;
+    let mut in_gap = false;
+    let mut offset = 0;
+    for line in message.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if !seen_subject {
+            seen_subject = !blank;
+        } else if blank {
+            in_gap = true;
+        } else if in_gap {
+            return &message[offset..];
+        }
+        offset += line.len();
+    }
+    ""
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tag;
+    use crate::core::object::GitObject;
+    use crate::test_utils::{git, init_repo, set_ref, signature, write_commit};
+
+    #[test]
+    fn expands_placeholders_like_git() {
+        let (_dir, repo) = init_repo();
+        let odb = repo.odb();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        let commit = Commit {
+            tree: odb.read_commit(&base).unwrap().tree,
+            parents: vec![base],
+            author: Signature::new("Ann Author", "ann@example.com", 1_112_911_993, -420),
+            committer: Signature::new("Cee Committer", "cee@example.com", 1_112_912_000, 330),
+            extra_headers: Vec::new(),
+            message: "Subject that\nwraps\n\n\nBody one\n\nBody two\n".to_string(),
+        };
+        let tip = odb.write(&GitObject::Commit(commit.clone())).unwrap();
+        set_ref(&repo, "refs/heads/master", &tip);
+        set_ref(&repo, "refs/heads/topic", &tip);
+        set_ref(&repo, "refs/tags/light", &tip);
+        tag::create_annotated(&repo, "v1", "master", "release", signature(), false).unwrap();
+        let decorations = Decorations::load(&repo).unwrap();
+
+        let cases = [
+            "%H %h %T %t",
+            "%P|%p",
+            "%an <%ae> %ad|%aD|%ai|%aI|%at",
+            "%cn <%ce> %cd|%cD|%ci|%cI|%ct",
+            "[%s]%n[%b]%n[%B]",
+            "%d|%D",
+            "100%% %x %q %a? %z",
+            "%",
+            "trailing %a",
+        ];
+        for (template, date) in cases.iter().flat_map(|t| {
+            [
+                (t, DateFormat::Default),
+                (t, DateFormat::Iso),
+                (t, DateFormat::Unix),
+            ]
+        }) {
+            let formatter = CommitFormatter::new(odb)
+                .date_format(date)
+                .decorations(&decorations);
+            let ours = formatter.format(template, &tip, &commit).unwrap();
+            let flag = match date {
+                DateFormat::Iso => "--date=iso",
+                DateFormat::Unix => "--date=unix",
+                _ => "--date=default",
+            };
+            let format = format!("--format={}", template);
+            if let Some(theirs) = git(&repo, &["log", "-1", "--decorate", flag, &format, "master"])
+            {
+                // `--format` ends every commit with a newline.
+                assert_eq!(format!("{}\n", ours), theirs, "{}", template);
+            }
+        }
+
+        let plain = CommitFormatter::new(odb);
+        assert_eq!(plain.format("%d[%D]", &tip, &commit).unwrap(), "[]");
+        assert_eq!(
+            plain.format("%s / %b", &tip, &commit).unwrap(),
+            "Subject that wraps / Body one\n\nBody two\n"
+        );
+        assert_eq!(
+            decorations.get(&tip),
+            ["HEAD -> master", "tag: v1", "tag: light", "topic"]
+        );
+    }
+}
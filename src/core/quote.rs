@@ -0,0 +1,71 @@
+//! Paths as git writes them for others to read back: wrapped in double
+//! quotes, with C escapes, whenever they have a byte that would otherwise
+//! be unprintable or ambiguous.
+
+/// `path` as git shows it. One with control characters, `"`, `\` or
+/// bytes outside ASCII, which `core.quotePath` quotes by default, is put
+/// in double quotes, the common control characters escaped as in C and
+/// other such bytes in octal; any other path is shown as it is.
+pub fn quote_path(path: &str) -> String {
+    quote(path, false)
+}
+
+/// [`quote_path`], also quoting a path with a space in it, as the short
+/// status format does so that its paths can be split from the codes.
+pub fn quote_path_with_spaces(path: &str) -> String {
+    quote(path, true)
+}
+
+fn quote(path: &str, spaces: bool) -> String {
+    let needs_quoting = |b: u8| b < 0x20 || b == b'"' || b == b'\\' || b >= 0x7f;
+    if !path
+        .bytes()
+        .any(|b| needs_quoting(b) || (spaces && b == b' '))
+    {
+        return path.to_string();
+    }
+    let mut out = String::with_capacity(path.len() + 2);
+    out.push('"');
+    for b in path.bytes() {
+        match b {
+            b'\x07' => out.push_str("\\a"),
+            b'\x08' => out.push_str("\\b"),
+            b'\t' => out.push_str("\\t"),
+            b'\n' => out.push_str("\\n"),
+            b'\x0b' => out.push_str("\\v"),
+            b'\x0c' => out.push_str("\\f"),
+            b'\r' => out.push_str("\\r"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b if needs_quoting(b) => out.push_str(&format!("\\{:03o}", b)),
+            b => out.push(b as char),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_paths_as_git_does() {
+        let cases = [
+            ("plain/path.txt", "plain/path.txt"),
+            ("with space", "with space"),
+            ("tab\there", "\"tab\\there\""),
+            ("new\nline", "\"new\\nline\""),
+            ("say \"hi\"", "\"say \\\"hi\\\"\""),
+            ("back\\slash", "\"back\\\\slash\""),
+            ("caf\u{e9}", "\"caf\\303\\251\""),
+            ("bell\x07\x1b", "\"bell\\a\\033\""),
+            ("del\x7f", "\"del\\177\""),
+        ];
+        for (path, quoted) in cases {
+            assert_eq!(quote_path(path), quoted, "{:?}", path);
+        }
+        assert_eq!(quote_path_with_spaces("with space"), "\"with space\"");
+        assert_eq!(quote_path_with_spaces("tab\there"), "\"tab\\there\"");
+    }
+}
@@ -0,0 +1,363 @@
+//! Ref logs under `.git/logs`, recording every value a ref has had.
+//!
+//! Each line is `<old> <new> <name> <<email>> <time> <tz>\t<message>`, with
+//! an all-zero old id when the ref was created.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::core::lockfile::LockFile;
+use crate::core::oid::{Oid, NULL_OID};
+use crate::core::signature::Signature;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// One line of a ref log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// The ref's value before the update, or `None` if it was created.
+    pub old: Option<Oid>,
+    pub new: Oid,
+    /// Who made the update, and when.
+    pub signature: Signature,
+    pub message: String,
+}
+
+/// Where `refname`'s log lives.
+pub fn path(repo: &Repository, refname: &str) -> PathBuf {
+    repo.git_dir().join("logs").join(refname)
+}
+
+/// Whether updates to `refname` get logged. An existing log is always
+/// appended to; otherwise `core.logAllRefUpdates` decides, defaulting to
+/// true in repositories with a working tree. When true, branches,
+/// remote-tracking refs, notes and HEAD are logged; `always` logs every ref.
+pub fn should_log(repo: &Repository, refname: &str) -> GitResult<bool> {
+    if path(repo, refname).is_file() {
+        return Ok(true);
+    }
+    let config = repo.config()?;
+    let setting = config.get("core", None, "logAllRefUpdates");
+    if setting.is_some_and(|value| value.eq_ignore_ascii_case("always")) {
+        return Ok(true);
+    }
+    let enabled = config
+        .get_bool("core", None, "logAllRefUpdates")?
+        .unwrap_or(!repo.is_bare());
+    Ok(enabled
+        && (refname == "HEAD"
+            || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                .iter()
+                .any(|prefix| refname.starts_with(prefix))))
+}
+
+/// Append an entry to `refname`'s log, stamped with the committer identity.
+/// Unlike a commit, a ref update isn't refused for want of one: as git
+/// does, an identity is made up from the login name.
+pub fn append(
+    repo: &Repository,
+    refname: &str,
+    old: Option<Oid>,
+    new: Oid,
+    message: &str,
+) -> GitResult<()> {
+    let signature = match repo.signature() {
+        Err(GitError::MissingIdentity) => {
+            let user = env::var("USER")
+                .or_else(|_| env::var("LOGNAME"))
+                .unwrap_or_else(|_| "unknown".to_string());
+            Signature::now(&user, &format!("{}@localhost", user))
+        }
+        signature => signature?,
+    };
+    // An entry has to stay on one line.
+    let message: String = message
+        .trim()
+        .chars()
+        .map(|c| if c == '\n' { ' ' } else { c })
+        .collect();
+    let line = format_entry(&ReflogEntry {
+        old,
+        new,
+        signature,
+        message,
+    });
+    let path = path(repo, refname);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Replace `refname`'s log with `entries`, oldest first, for when an entry
+/// is dropped rather than added.
+pub fn rewrite(repo: &Repository, refname: &str, entries: &[ReflogEntry]) -> GitResult<()> {
+    let mut lock = LockFile::acquire(&path(repo, refname)).map_err(|err| {
+        if err.kind() == io::ErrorKind::AlreadyExists {
+            GitError::RefLockConflict(refname.to_string())
+        } else {
+            err.into()
+        }
+    })?;
+    for entry in entries {
+        lock.write_all(format_entry(entry).as_bytes())?;
+    }
+    lock.commit()?;
+    Ok(())
+}
+
+fn format_entry(entry: &ReflogEntry) -> String {
+    format!(
+        "{} {} {}\t{}\n",
+        entry.old.unwrap_or(NULL_OID),
+        entry.new,
+        entry.signature,
+        entry.message
+    )
+}
+
+/// Append to the log if [`should_log`] says so.
+pub fn record(
+    repo: &Repository,
+    refname: &str,
+    old: Option<Oid>,
+    new: Oid,
+    message: &str,
+) -> GitResult<()> {
+    if should_log(repo, refname)? {
+        append(repo, refname, old, new, message)?;
+    }
+    Ok(())
+}
+
+/// Every entry in `refname`'s log, oldest first. A ref without a log has
+/// no entries.
+pub fn read(repo: &Repository, refname: &str) -> GitResult<Vec<ReflogEntry>> {
+    let path = path(repo, refname);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(_) if !path.is_file() => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    text.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| parse_entry(refname, line))
+        .collect()
+}
+
+fn parse_entry(refname: &str, line: &str) -> GitResult<ReflogEntry> {
+    let corrupt = || GitError::Corrupt(format!("bad reflog entry for {}: {}", refname, line));
+    let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+    let old = head.get(..40).ok_or_else(corrupt)?;
+    let new = head.get(41..81).ok_or_else(corrupt)?;
+    let signature = head.get(82..).ok_or_else(corrupt)?;
+    if head.as_bytes()[40] != b' ' || head.as_bytes()[81] != b' ' {
+        return Err(corrupt());
+    }
+    let old = old.parse::<Oid>()?;
+    Ok(ReflogEntry {
+        old: if old == NULL_OID { None } else { Some(old) },
+        new: new.parse::<Oid>()?,
+        signature: Signature::parse(signature)?,
+        message: message.to_string(),
+    })
+}
+
+/// The value `refname` had `n` updates ago, as `refname@{n}` names it:
+/// `0` is the current value as of the newest entry.
+pub fn nth_value(repo: &Repository, refname: &str, n: usize) -> GitResult<Oid> {
+    let entries = read(repo, refname)?;
+    let too_short = || GitError::ReflogTooShort {
+        refname: refname.to_string(),
+        entries: entries.len(),
+    };
+    if n == 0 {
+        return entries.last().map(|e| e.new).ok_or_else(too_short);
+    }
+    // Each entry's old value is the previous one's new value, except that
+    // the oldest entry may still remember what the ref held before logging
+    // started.
+    match entries.len().checked_sub(n).map(|i| entries[i].old) {
+        Some(Some(id)) => Ok(id),
+        _ => Err(too_short()),
+    }
+}
+
+/// The branch (or commit, for a detached HEAD) that was checked out before
+/// the `n`th most recent checkout, as `@{-n}` names it.
+pub fn previous_checkout(repo: &Repository, n: usize) -> GitResult<Option<String>> {
+    if n == 0 {
+        return Ok(None);
+    }
+    let entries = read(repo, "HEAD")?;
+    let previous = entries
+        .iter()
+        .rev()
+        .filter_map(|entry| {
+            let moves = entry.message.strip_prefix("checkout: moving from ")?;
+            moves.split_once(" to ").map(|(from, _)| from.to_string())
+        })
+        .nth(n - 1);
+    Ok(previous)
+}
+
+/// The name of every ref that has a log, sorted.
+pub fn logged_refs(repo: &Repository) -> GitResult<Vec<String>> {
+    let mut names = Vec::new();
+    let logs = repo.git_dir().join("logs");
+    if logs.join("HEAD").is_file() {
+        names.push("HEAD".to_string());
+    }
+    let mut dirs = vec![(logs.join("refs"), "refs".to_string())];
+    while let Some((dir, name)) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let child = format!("{}/{}", name, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                dirs.push((entry.path(), child));
+            } else {
+                names.push(child);
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Remove `refname`'s log along with the ref itself.
+pub fn delete(repo: &Repository, refname: &str) -> GitResult<()> {
+    let path = path(repo, refname);
+    // Checking first also covers a parent path that is now another ref's
+    // log file rather than a directory.
+    if !path.is_file() {
+        return Ok(());
+    }
+    fs::remove_file(&path)?;
+    // Like the refs themselves, `logs/refs/heads` and friends stay.
+    let top = repo.git_dir().join("logs").join("refs");
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current.parent() == Some(top.as_path()) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::branch;
+    use crate::core::refs;
+    use crate::test_utils::{checkout, git, init_repo, write_commit};
+
+    /// The log's lines with each timestamp and zone cut out.
+    fn entries(repo: &Repository, refname: &str) -> Vec<String> {
+        fs::read_to_string(path(repo, refname))
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let (head, message) = line.split_once('\t').unwrap();
+                let ident = head.rsplitn(3, ' ').nth(2).unwrap();
+                format!("{}\t{}", ident, message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn logs_a_branch_from_creation_to_deletion() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        let next = write_commit(&repo, &[base], &[("a", "b")], "next");
+        checkout(&repo, "master", &base);
+        let (base_hex, next_hex) = (base.to_string(), next.to_string());
+
+        branch::create(&repo, "topic", "master", false).unwrap();
+        refs::update(&repo, "refs/heads/topic", next, Some(Some(base)), "moved").unwrap();
+        assert_eq!(
+            entries(&repo, "refs/heads/topic"),
+            vec![
+                format!(
+                    "{} {} A U Thor <author@example.com>\tbranch: Created from master",
+                    NULL_OID, base_hex
+                ),
+                format!(
+                    "{} {} A U Thor <author@example.com>\tmoved",
+                    base_hex, next_hex
+                ),
+            ]
+        );
+
+        branch::delete(&repo, "topic", true).unwrap();
+        assert!(!path(&repo, "refs/heads/topic").exists());
+        assert!(repo.git_dir().join("logs/refs/heads").is_dir());
+    }
+
+    #[test]
+    fn reads_entries_git_can_read_too() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("a", "a")], "base");
+        let next = write_commit(&repo, &[base], &[("a", "b")], "next");
+        refs::update(&repo, "refs/heads/topic", base, Some(None), "created").unwrap();
+        refs::update(&repo, "refs/heads/topic", next, None, "moved\non").unwrap();
+
+        let entries = read(&repo, "refs/heads/topic").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].old, entries[0].new), (None, base));
+        assert_eq!((entries[1].old, entries[1].new), (Some(base), next));
+        assert_eq!(entries[1].message, "moved on");
+        assert_eq!(entries[1].signature.email, "author@example.com");
+        assert_eq!(nth_value(&repo, "refs/heads/topic", 1).unwrap(), base);
+        assert!(read(&repo, "refs/heads/missing").unwrap().is_empty());
+
+        let output = match git(&repo, &["log", "-g", "--format=%H %gs", "topic"]) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(output, format!("{} moved on\n{} created\n", next, base));
+    }
+
+    #[test]
+    fn follows_core_log_all_ref_updates() {
+        let (_dir, repo) = init_repo();
+        assert!(should_log(&repo, "HEAD").unwrap());
+        assert!(should_log(&repo, "refs/heads/topic").unwrap());
+        assert!(!should_log(&repo, "refs/tags/v1.0").unwrap());
+
+        let mut config = fs::read_to_string(repo.config_path()).unwrap();
+        config.push_str("[core]\n\tlogAllRefUpdates = always\n");
+        fs::write(repo.config_path(), &config).unwrap();
+        assert!(should_log(&repo, "refs/tags/v1.0").unwrap());
+
+        config.push_str("[core]\n\tlogAllRefUpdates = false\n");
+        fs::write(repo.config_path(), &config).unwrap();
+        assert!(!should_log(&repo, "refs/heads/topic").unwrap());
+    }
+
+    #[test]
+    fn logs_updates_without_an_identity() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let id = repo
+            .odb()
+            .write(&crate::core::object::GitObject::Blob(Vec::new()))
+            .unwrap();
+        refs::update(&repo, "refs/heads/master", id, None, "created").unwrap();
+        let logged = read(&repo, "refs/heads/master").unwrap();
+        assert_eq!(logged.len(), 1);
+        if env::var_os("GIT_COMMITTER_EMAIL").is_none() {
+            assert!(logged[0].signature.email.ends_with("@localhost"));
+        }
+    }
+}
@@ -0,0 +1,1197 @@
+//! Reading, writing and resolving references.
+//!
+//! Everything goes through [`read`], which looks a single fully-qualified
+//! name up in the ref store: loose files first, then `packed-refs`. Writes
+//! go through [`update`] and [`delete`], which hold git's `.lock` files so
+//! concurrent writers fail instead of clobbering each other.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::core::lockfile::LockFile;
+use crate::core::object::ObjectType;
+use crate::core::oid::Oid;
+use crate::core::packed_refs::{PackedRef, PackedRefs};
+use crate::core::reflog;
+use crate::core::wildmatch::wildmatch;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// How many symbolic refs we'll follow before assuming a cycle. Matches git.
+pub const MAX_SYMREF_DEPTH: usize = 5;
+
+/// What a ref contains: another ref's name, or an object id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefTarget {
+    Symbolic(String),
+    Direct(Oid),
+}
+
+/// A ref resolved to the object it points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub name: String,
+    pub target: Oid,
+    peeled: Option<Oid>,
+}
+
+impl Reference {
+    /// For an annotated tag, the object the tag ultimately points at, when
+    /// known without reading the tag (i.e. from `packed-refs`).
+    pub fn peeled(&self) -> Option<Oid> {
+        self.peeled
+    }
+}
+
+/// The names git tries, in order, when given a short name like `main`.
+fn lookup_candidates(name: &str) -> Vec<String> {
+    let mut candidates = Vec::new();
+    // `%s` only applies to full names and pseudo-refs like `HEAD` or
+    // `ORIG_HEAD`, otherwise `config` would "resolve" to `.git/config`.
+    if name.starts_with("refs/") || is_pseudo_ref(name) {
+        candidates.push(name.to_string());
+    }
+    candidates.push(format!("refs/{}", name));
+    candidates.push(format!("refs/tags/{}", name));
+    candidates.push(format!("refs/heads/{}", name));
+    candidates.push(format!("refs/remotes/{}", name));
+    candidates.push(format!("refs/remotes/{}/HEAD", name));
+    candidates
+}
+
+fn is_pseudo_ref(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_uppercase() || b == b'_')
+}
+
+/// Refuse names that would escape the ref store.
+fn check_safe(name: &str) -> GitResult<()> {
+    let unsafe_name = name.is_empty()
+        || name.starts_with('/')
+        || name
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..");
+    if unsafe_name {
+        Err(GitError::RefNotFound(name.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check `name` against git's ref name rules, as `git check-ref-format`
+/// does. Unless `allow_onelevel` is set the name needs at least two
+/// components, like `refs/heads` or `heads/main`; short branch and tag
+/// names are checked with it set. The error says which rule was broken.
+pub fn validate_name(name: &str, allow_onelevel: bool) -> GitResult<()> {
+    let invalid = |reason: String| {
+        Err(GitError::InvalidRefName {
+            name: name.to_string(),
+            reason,
+        })
+    };
+    if name.is_empty() {
+        return invalid("it is empty".to_string());
+    }
+    if name == "@" {
+        return invalid("it is the single character '@'".to_string());
+    }
+    if let Some(c) = name.chars().find(|&c| c.is_ascii_control()) {
+        return invalid(format!("it contains the control character {:?}", c));
+    }
+    if let Some(c) = name.chars().find(|&c| " ~^:?*[\\".contains(c)) {
+        return invalid(format!("it contains '{}'", c));
+    }
+    if name.contains("..") {
+        return invalid("it contains '..'".to_string());
+    }
+    if name.contains("@{") {
+        return invalid("it contains '@{'".to_string());
+    }
+    if name.starts_with('/') || name.ends_with('/') {
+        return invalid("it starts or ends with '/'".to_string());
+    }
+    if name.contains("//") {
+        return invalid("it contains '//'".to_string());
+    }
+    if name.ends_with('.') {
+        return invalid("it ends with '.'".to_string());
+    }
+    for component in name.split('/') {
+        if component.starts_with('.') {
+            return invalid(format!("component '{}' starts with '.'", component));
+        }
+        if component.ends_with(".lock") {
+            return invalid(format!("component '{}' ends with '.lock'", component));
+        }
+    }
+    if !allow_onelevel && !name.contains('/') {
+        return invalid("it has only one level".to_string());
+    }
+    Ok(())
+}
+
+/// `name` with repeated slashes collapsed and any leading ones dropped, as
+/// `git check-ref-format --normalize` prints it.
+pub fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c == '/' && (normalized.is_empty() || normalized.ends_with('/')) {
+            continue;
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+/// The short form of a full ref name, as `--short` prints it: without
+/// `refs/heads/`, `refs/tags/` or `refs/remotes/`.
+pub fn shorten(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+/// Read a single fully-qualified ref without following it. `Ok(None)`
+/// means the ref doesn't exist. A loose ref shadows a packed one.
+pub fn read(repo: &Repository, name: &str) -> GitResult<Option<RefTarget>> {
+    check_safe(name)?;
+    if let Some(target) = read_loose(repo, name)? {
+        return Ok(Some(target));
+    }
+    let packed = packed_refs(repo)?;
+    Ok(packed.find(name).map(|r| RefTarget::Direct(r.oid)))
+}
+
+pub fn packed_refs(repo: &Repository) -> GitResult<PackedRefs> {
+    PackedRefs::load(&repo.git_dir().join("packed-refs"))
+}
+
+fn read_loose(repo: &Repository, name: &str) -> GitResult<Option<RefTarget>> {
+    let path = repo.git_dir().join(name);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        // A directory such as `refs/heads` isn't a ref, and nor is anything
+        // under a file such as `refs/heads/main/topic` when `main` exists.
+        Err(_) if !path.is_file() => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    parse_target(name, &contents).map(Some)
+}
+
+fn parse_target(name: &str, contents: &str) -> GitResult<RefTarget> {
+    let contents = contents.trim_end();
+    if let Some(target) = contents.strip_prefix("ref:") {
+        return Ok(RefTarget::Symbolic(target.trim().to_string()));
+    }
+    contents
+        .parse::<Oid>()
+        .map(RefTarget::Direct)
+        .map_err(|_| GitError::Corrupt(format!("ref {} has invalid contents", name)))
+}
+
+/// What the fully-qualified ref `name` contains, without following it.
+pub fn resolve_symbolic(repo: &Repository, name: &str) -> GitResult<RefTarget> {
+    read(repo, name)?.ok_or_else(|| GitError::RefNotFound(name.to_string()))
+}
+
+/// Follow a fully-qualified ref through any symbolic refs, returning the
+/// name of the ref at the end of the chain and its value. The value is
+/// `None` when the chain ends at a ref that doesn't exist yet, e.g. HEAD on
+/// an unborn branch.
+pub fn follow(repo: &Repository, name: &str) -> GitResult<(String, Option<Oid>)> {
+    let mut current = name.to_string();
+    for _ in 0..=MAX_SYMREF_DEPTH {
+        match read(repo, &current)? {
+            Some(RefTarget::Direct(id)) => return Ok((current, Some(id))),
+            Some(RefTarget::Symbolic(target)) => current = target,
+            None => return Ok((current, None)),
+        }
+    }
+    Err(GitError::Corrupt(format!(
+        "ref {} is a symbolic ref loop or nests too deeply",
+        name
+    )))
+}
+
+/// Resolve `name` to an object id using git's lookup order: the name
+/// itself, then under `refs/`, `refs/tags/`, `refs/heads/`,
+/// `refs/remotes/` and finally `refs/remotes/<name>/HEAD`.
+pub fn resolve(repo: &Repository, name: &str) -> GitResult<Oid> {
+    let refname = expand(repo, name)?.ok_or_else(|| GitError::RefNotFound(name.to_string()))?;
+    match follow(repo, &refname)? {
+        (_, Some(id)) => Ok(id),
+        (branch, None) => Err(GitError::UnbornBranch(branch)),
+    }
+}
+
+/// The full name of the first existing ref in `name`'s lookup order, e.g.
+/// `refs/heads/main` for `main`.
+pub fn expand(repo: &Repository, name: &str) -> GitResult<Option<String>> {
+    for candidate in lookup_candidates(name) {
+        if check_safe(&candidate).is_ok() && read(repo, &candidate)?.is_some() {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// Every ref under `refs/`, sorted by name, with loose refs taking
+/// precedence over packed ones. Dangling symbolic refs are skipped, and a
+/// ref that can't be read is an error; use [`iter`] to get past it.
+pub fn list(repo: &Repository) -> GitResult<Vec<Reference>> {
+    iter(repo)?.collect()
+}
+
+/// Refs in name order, as [`iter`] and friends find them. A loose ref that
+/// can't be read comes out as an `Err` in its place, so one broken file
+/// doesn't hide every other ref.
+#[derive(Debug)]
+pub struct RefIter {
+    refs: std::vec::IntoIter<GitResult<Reference>>,
+}
+
+impl Iterator for RefIter {
+    type Item = GitResult<Reference>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.refs.next()
+    }
+}
+
+/// Every ref under `refs/`, merged from loose files and `packed-refs` with
+/// the loose ones taking precedence.
+pub fn iter(repo: &Repository) -> GitResult<RefIter> {
+    iter_prefixed(repo, "refs/")
+}
+
+/// The refs whose names start with `prefix`, e.g. `refs/tags/`. Only the
+/// loose refs under the prefix's directory are read.
+pub fn iter_prefixed(repo: &Repository, prefix: &str) -> GitResult<RefIter> {
+    let mut refs = BTreeMap::new();
+    for packed in packed_refs(repo)?.refs() {
+        if packed.name.starts_with(prefix) {
+            refs.insert(
+                packed.name.clone(),
+                Ok(Reference {
+                    name: packed.name.clone(),
+                    target: packed.oid,
+                    peeled: packed.peeled,
+                }),
+            );
+        }
+    }
+    let dir = match prefix.rfind('/') {
+        Some(slash) if prefix.starts_with("refs/") => &prefix[..slash],
+        _ => "refs",
+    };
+    let mut loose = Vec::new();
+    if check_safe(dir).is_ok() {
+        collect_loose(&repo.git_dir().join(dir), dir, &mut loose)?;
+    }
+    for name in loose.into_iter().filter(|name| name.starts_with(prefix)) {
+        match follow(repo, &name) {
+            Ok((_, Some(target))) => {
+                let reference = Reference {
+                    name: name.clone(),
+                    target,
+                    peeled: None,
+                };
+                refs.insert(name, Ok(reference));
+            }
+            // A dangling symbolic ref still hides a packed ref of the
+            // same name.
+            Ok((_, None)) => {
+                refs.remove(&name);
+            }
+            Err(err) => {
+                refs.insert(name, Err(err));
+            }
+        }
+    }
+    Ok(RefIter {
+        refs: refs.into_values().collect::<Vec<_>>().into_iter(),
+    })
+}
+
+/// The refs matching a `git for-each-ref` pattern. A pattern without glob
+/// characters matches itself and everything below it, so `refs/heads`
+/// matches `refs/heads/main` but not `refs/headsup`; otherwise it's matched
+/// as a glob whose `*` stays within one path component.
+pub fn iter_matching(repo: &Repository, pattern: &str) -> GitResult<RefIter> {
+    let glob_start = pattern.find(['*', '?', '[', '\\']);
+    let prefix = &pattern[..glob_start.unwrap_or(pattern.len())];
+    let matches = |name: &str| match glob_start {
+        Some(_) => wildmatch(pattern.as_bytes(), name.as_bytes(), true),
+        None => name == pattern || pattern.ends_with('/') || name[pattern.len()..].starts_with('/'),
+    };
+    let refs: Vec<_> = iter_prefixed(repo, prefix)?
+        .filter(|item| match item {
+            Ok(reference) => matches(&reference.name),
+            Err(_) => true,
+        })
+        .collect();
+    Ok(RefIter {
+        refs: refs.into_iter(),
+    })
+}
+
+/// Point `name` at `new`, following symbolic refs so that updating `HEAD`
+/// moves the checked-out branch. When `expected_old` is given the update
+/// only happens if the ref currently has that value, with `Some(None)`
+/// meaning the ref must not exist yet.
+pub fn update(
+    repo: &Repository,
+    name: &str,
+    new: Oid,
+    expected_old: Option<Option<Oid>>,
+    reflog_msg: &str,
+) -> GitResult<()> {
+    check_safe(name)?;
+    let (target, _) = follow(repo, name)?;
+    write_direct(repo, &target, new, expected_old, reflog_msg)
+}
+
+/// Like [`update`], but overwrites `name` itself even if it is a symbolic
+/// ref: this is how HEAD is detached.
+pub fn update_no_deref(
+    repo: &Repository,
+    name: &str,
+    new: Oid,
+    expected_old: Option<Option<Oid>>,
+    reflog_msg: &str,
+) -> GitResult<()> {
+    check_safe(name)?;
+    write_direct(repo, name, new, expected_old, reflog_msg)
+}
+
+fn write_direct(
+    repo: &Repository,
+    target: &str,
+    new: Oid,
+    expected_old: Option<Option<Oid>>,
+    reflog_msg: &str,
+) -> GitResult<()> {
+    let mut lock = lock(repo, target)?;
+    // Re-read under the lock; whatever we saw before may be stale.
+    let (_, current) = follow(repo, target)?;
+    check_expected(target, current, expected_old)?;
+    lock.write_all(format!("{}\n", new).as_bytes())?;
+    lock.commit()?;
+
+    log_update(repo, target, current, new, reflog_msg)
+}
+
+fn log_update(
+    repo: &Repository,
+    target: &str,
+    old: Option<Oid>,
+    new: Oid,
+    reflog_msg: &str,
+) -> GitResult<()> {
+    reflog::record(repo, target, old, new, reflog_msg)?;
+    // Moving the checked-out branch moves HEAD too, so it gets an entry
+    // of its own.
+    if target != "HEAD" {
+        if let Some(RefTarget::Symbolic(head)) = read(repo, "HEAD")? {
+            if head == target {
+                reflog::record(repo, "HEAD", old, new, reflog_msg)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What a [`Transaction`] does to one ref.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Update {
+        new: Oid,
+        expected_old: Option<Option<Oid>>,
+    },
+    Delete {
+        expected_old: Option<Oid>,
+    },
+}
+
+/// A set of ref updates and deletions applied all together or not at all,
+/// for fetches and pushes that move many refs at once.
+///
+/// [`Transaction::commit`] takes every ref's lock before touching
+/// anything, so a conflicting writer or an unexpected old value fails the
+/// whole transaction while the refs are still as they were.
+#[derive(Debug)]
+pub struct Transaction<'a> {
+    repo: &'a Repository,
+    changes: Vec<(String, Change)>,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(repo: &'a Repository) -> Transaction<'a> {
+        Transaction {
+            repo,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Point `name` at `new`, following symbolic refs, with
+    /// `expected_old` checked as for [`update`].
+    pub fn update(
+        &mut self,
+        name: &str,
+        new: Oid,
+        expected_old: Option<Option<Oid>>,
+    ) -> &mut Transaction<'a> {
+        let change = Change::Update { new, expected_old };
+        self.changes.push((name.to_string(), change));
+        self
+    }
+
+    /// Create `name`, which must not exist yet.
+    pub fn create(&mut self, name: &str, new: Oid) -> &mut Transaction<'a> {
+        self.update(name, new, Some(None))
+    }
+
+    /// Delete `name` itself, as [`delete`] does.
+    pub fn delete(&mut self, name: &str, expected_old: Option<Oid>) -> &mut Transaction<'a> {
+        let change = Change::Delete { expected_old };
+        self.changes.push((name.to_string(), change));
+        self
+    }
+
+    /// Apply every change, logging each update with `reflog_msg`.
+    ///
+    /// All the locks are taken first, in name order so two transactions
+    /// can't each hold a lock the other is waiting for. Only once every
+    /// expected value has been checked and every new value written to its
+    /// lock file is anything renamed into place. Any earlier failure drops
+    /// the locks and leaves the refs untouched; the reflogs are written
+    /// after every ref has moved.
+    pub fn commit(self, reflog_msg: &str) -> GitResult<()> {
+        let repo = self.repo;
+        let mut changes = Vec::new();
+        for (name, change) in self.changes {
+            check_safe(&name)?;
+            let target = match change {
+                Change::Update { .. } => follow(repo, &name)?.0,
+                Change::Delete { .. } => name,
+            };
+            changes.push((target, change));
+        }
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some(pair) = changes.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(GitError::InvalidRefName {
+                name: pair[0].0.clone(),
+                reason: "it is changed more than once in one transaction".to_string(),
+            });
+        }
+
+        let mut locks = Vec::new();
+        for (name, _) in &changes {
+            locks.push(lock(repo, name)?);
+        }
+        let mut olds = Vec::new();
+        for (name, change) in &changes {
+            let (_, current) = follow(repo, name)?;
+            match *change {
+                Change::Update { expected_old, .. } => check_expected(name, current, expected_old)?,
+                Change::Delete { expected_old } => {
+                    if read(repo, name)?.is_none() {
+                        return Err(GitError::RefNotFound(name.clone()));
+                    }
+                    if let Some(expected) = expected_old {
+                        check_expected(name, current, Some(Some(expected)))?;
+                    }
+                }
+            }
+            olds.push(current);
+        }
+
+        let packed = packed_refs(repo)?;
+        let mut packed_update = None;
+        let unpacked: Vec<&String> = changes
+            .iter()
+            .filter(|(name, change)| {
+                matches!(change, Change::Delete { .. }) && packed.find(name).is_some()
+            })
+            .map(|(name, _)| name)
+            .collect();
+        if !unpacked.is_empty() {
+            let mut packed_lock = lock(repo, "packed-refs")?;
+            let mut packed = packed_refs(repo)?;
+            for name in unpacked {
+                packed.remove(name);
+            }
+            packed_lock.write_all(packed.serialize().as_bytes())?;
+            packed_update = Some(packed_lock);
+        }
+        for ((_, change), lock) in changes.iter().zip(&mut locks) {
+            if let Change::Update { new, .. } = change {
+                lock.write_all(format!("{}\n", new).as_bytes())?;
+            }
+        }
+
+        // Past this point nothing is left to check.
+        if let Some(packed_lock) = packed_update {
+            packed_lock.commit()?;
+        }
+        let refs_root = repo.git_dir().join("refs");
+        for ((name, change), lock) in changes.iter().zip(locks) {
+            match *change {
+                Change::Update { .. } => lock.commit()?,
+                Change::Delete { .. } => {
+                    let path = repo.git_dir().join(name);
+                    match fs::remove_file(&path) {
+                        Ok(()) => {}
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                        Err(err) => return Err(err.into()),
+                    }
+                    drop(lock);
+                    prune_empty_dirs(&refs_root, &path);
+                }
+            }
+        }
+        // The logs only once every ref has moved, so failing to write one
+        // can't leave the rest of the transaction undone.
+        for ((name, change), old) in changes.iter().zip(olds) {
+            match *change {
+                Change::Update { new, .. } => log_update(repo, name, old, new, reflog_msg)?,
+                Change::Delete { .. } => reflog::delete(repo, name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Delete the ref `name` itself (a symbolic ref is removed, not its
+/// target), from both its loose file and `packed-refs`. `expected_old`
+/// works as for [`update`].
+pub fn delete(repo: &Repository, name: &str, expected_old: Option<Oid>) -> GitResult<()> {
+    check_safe(name)?;
+    let ref_lock = lock(repo, name)?;
+    let current = match read(repo, name)? {
+        Some(RefTarget::Direct(id)) => Some(id),
+        Some(RefTarget::Symbolic(_)) => follow(repo, name)?.1,
+        None => return Err(GitError::RefNotFound(name.to_string())),
+    };
+    if let Some(expected) = expected_old {
+        check_expected(name, current, Some(Some(expected)))?;
+    }
+
+    if packed_refs(repo)?.find(name).is_some() {
+        let mut packed_lock = lock(repo, "packed-refs")?;
+        let mut packed = packed_refs(repo)?;
+        packed.remove(name);
+        packed_lock.write_all(packed.serialize().as_bytes())?;
+        packed_lock.commit()?;
+    }
+    let path = repo.git_dir().join(name);
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    drop(ref_lock);
+    prune_empty_dirs(&repo.git_dir().join("refs"), &path);
+    reflog::delete(repo, name)
+}
+
+/// Make `name` a symbolic ref pointing at `target`, e.g. to switch `HEAD`
+/// to another branch. Like `git symbolic-ref -m`, the change is only
+/// logged when there's a `reflog_msg`.
+pub fn update_symbolic(
+    repo: &Repository,
+    name: &str,
+    target: &str,
+    reflog_msg: Option<&str>,
+) -> GitResult<()> {
+    check_safe(name)?;
+    check_safe(target)?;
+    let mut lock = lock(repo, name)?;
+    let (_, old) = follow(repo, name)?;
+    lock.write_all(format!("ref: {}\n", target).as_bytes())?;
+    lock.commit()?;
+    if let (Some(message), (_, Some(new))) = (reflog_msg, follow(repo, name)?) {
+        reflog::record(repo, name, old, new, message)?;
+    }
+    Ok(())
+}
+
+/// Move the loose refs `should_pack` picks into `packed-refs`, returning
+/// their names. `should_pack` is given each loose ref's name and whether
+/// it's already packed; symbolic refs are never packed.
+///
+/// The new `packed-refs` is written under its lock and renamed into place,
+/// and the loose files stay until it is, so readers see every ref
+/// throughout. With `prune`, each loose file is then removed under the
+/// ref's own lock, and only if it still holds the value that was packed;
+/// a ref updated in the meantime keeps its newer loose value.
+pub fn pack<F>(repo: &Repository, should_pack: F, prune: bool) -> GitResult<Vec<String>>
+where
+    F: Fn(&str, bool) -> bool,
+{
+    let mut packed_lock = lock(repo, "packed-refs")?;
+    let mut packed = packed_refs(repo)?;
+    let mut loose = Vec::new();
+    collect_loose(&repo.git_dir().join("refs"), "refs", &mut loose)?;
+    loose.sort();
+    let mut moved = Vec::new();
+    for name in loose {
+        if let Some(RefTarget::Direct(id)) = read_loose(repo, &name)? {
+            if should_pack(&name, packed.find(&name).is_some()) {
+                packed.insert(PackedRef {
+                    name: name.clone(),
+                    oid: id,
+                    peeled: None,
+                });
+                moved.push((name, id));
+            }
+        }
+    }
+    // Every annotated tag gets its `^` line, so the file can claim to be
+    // fully peeled.
+    let mut peeled = PackedRefs::default();
+    for r in packed.refs() {
+        let mut r = r.clone();
+        r.peeled = match repo.odb().read_raw(&r.oid) {
+            Ok((ObjectType::Tag, _)) => Some(repo.odb().peel(&r.oid)?.0),
+            _ => None,
+        };
+        peeled.insert(r);
+    }
+    peeled.traits = vec!["peeled".to_string(), "fully-peeled".to_string()];
+    packed_lock.write_all(peeled.serialize().as_bytes())?;
+    packed_lock.commit()?;
+
+    if prune {
+        for (name, id) in &moved {
+            let ref_lock = match lock(repo, name) {
+                Ok(ref_lock) => ref_lock,
+                // Someone is updating it right now; leave it to them.
+                Err(GitError::RefLockConflict(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            if read_loose(repo, name)? == Some(RefTarget::Direct(*id)) {
+                let path = repo.git_dir().join(name);
+                fs::remove_file(&path)?;
+                drop(ref_lock);
+                prune_empty_dirs(&repo.git_dir().join("refs"), &path);
+            }
+        }
+    }
+    Ok(moved.into_iter().map(|(name, _)| name).collect())
+}
+
+/// Remove the now-empty directories between `path` and `root`, so
+/// deleting `refs/heads/a/b` leaves room for a ref named `refs/heads/a`.
+/// Top-level directories like `refs/heads` are kept.
+fn prune_empty_dirs(root: &Path, path: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current.parent() == Some(root)
+            || !current.starts_with(root)
+            || fs::remove_dir(current).is_err()
+        {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+/// Take `<name>.lock` under the git dir.
+fn lock(repo: &Repository, name: &str) -> GitResult<LockFile> {
+    LockFile::acquire(&repo.git_dir().join(name)).map_err(|err| {
+        if err.kind() == io::ErrorKind::AlreadyExists {
+            GitError::RefLockConflict(name.to_string())
+        } else {
+            err.into()
+        }
+    })
+}
+
+fn check_expected(
+    name: &str,
+    current: Option<Oid>,
+    expected: Option<Option<Oid>>,
+) -> GitResult<()> {
+    let expected = match expected {
+        Some(expected) if expected != current => expected,
+        _ => return Ok(()),
+    };
+    let describe = |id: Option<Oid>| match id {
+        Some(id) => id.to_string(),
+        None => "nothing".to_string(),
+    };
+    Err(GitError::RefCasFailed(format!(
+        "{}: expected {}, found {}",
+        name,
+        describe(expected),
+        describe(current)
+    )))
+}
+
+fn collect_loose(dir: &Path, prefix: &str, names: &mut Vec<String>) -> GitResult<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_loose(&entry.path(), &name, names)?;
+        } else if !name.ends_with(".lock") {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{init_repo, set_ref, write_commit};
+
+    #[test]
+    fn resolves_in_git_lookup_order() {
+        let (_dir, repo) = init_repo();
+        let a = write_commit(&repo, &[], &[("a", "a")], "a");
+        let b = write_commit(&repo, &[], &[("b", "b")], "b");
+        set_ref(&repo, "refs/heads/topic", &a);
+        set_ref(&repo, "refs/heads/both", &a);
+        set_ref(&repo, "refs/tags/both", &b);
+        set_ref(&repo, "refs/remotes/origin/main", &b);
+        fs::write(
+            repo.git_dir().join("refs/remotes/origin/HEAD"),
+            "ref: refs/remotes/origin/main\n",
+        )
+        .unwrap();
+
+        assert_eq!(resolve(&repo, "topic").unwrap(), a);
+        assert_eq!(resolve(&repo, "heads/topic").unwrap(), a);
+        assert_eq!(resolve(&repo, "refs/heads/topic").unwrap(), a);
+        // Tags win over branches of the same name.
+        assert_eq!(resolve(&repo, "both").unwrap(), b);
+        assert_eq!(resolve(&repo, "origin/main").unwrap(), b);
+        assert_eq!(resolve(&repo, "origin").unwrap(), b);
+        match resolve(&repo, "config") {
+            Err(GitError::RefNotFound(name)) => assert_eq!(name, "config"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn distinguishes_unborn_branches() {
+        let (_dir, repo) = init_repo();
+        assert_eq!(
+            resolve_symbolic(&repo, "HEAD").unwrap(),
+            RefTarget::Symbolic("refs/heads/master".to_string())
+        );
+        match resolve(&repo, "HEAD") {
+            Err(GitError::UnbornBranch(name)) => assert_eq!(name, "refs/heads/master"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let a = write_commit(&repo, &[], &[("a", "a")], "a");
+        set_ref(&repo, "refs/heads/master", &a);
+        assert_eq!(resolve(&repo, "HEAD").unwrap(), a);
+        assert_eq!(
+            resolve_symbolic(&repo, "refs/heads/master").unwrap(),
+            RefTarget::Direct(a)
+        );
+    }
+
+    /// A repo whose refs look like a fresh `git clone`: packed remote
+    /// branches and tags, plus a loose `main` and `origin/HEAD`.
+    fn cloned_repo() -> (tempfile::TempDir, Repository) {
+        let (dir, repo) = init_repo();
+        fs::copy(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/packed-refs"),
+            repo.git_dir().join("packed-refs"),
+        )
+        .unwrap();
+        fs::create_dir_all(repo.git_dir().join("refs/remotes/origin")).unwrap();
+        fs::write(
+            repo.git_dir().join("refs/remotes/origin/HEAD"),
+            "ref: refs/remotes/origin/main\n",
+        )
+        .unwrap();
+        let main = "670284a0b9ad86abaf35be65616d427233a73ae2"
+            .parse::<Oid>()
+            .unwrap();
+        set_ref(&repo, "refs/heads/main", &main);
+        (dir, repo)
+    }
+
+    #[test]
+    fn falls_back_to_packed_refs() {
+        let (_dir, repo) = cloned_repo();
+        let hex = |name: &str| resolve(&repo, name).unwrap().to_string();
+        assert_eq!(hex("v1.0"), "17c53fc890c62d77e3c6aa17dd5ae0944ca23001");
+        assert_eq!(
+            hex("origin/feature"),
+            "1f4e60d0e36329ddb989541a385fdb3e367b6db9"
+        );
+        assert_eq!(hex("origin"), "670284a0b9ad86abaf35be65616d427233a73ae2");
+
+        // A loose ref wins over a packed one of the same name.
+        let other = write_commit(&repo, &[], &[("x", "x")], "x");
+        set_ref(&repo, "refs/tags/v1.0", &other);
+        assert_eq!(resolve(&repo, "v1.0").unwrap(), other);
+    }
+
+    #[test]
+    fn lists_loose_and_packed_refs_with_peeled_tags() {
+        let (_dir, repo) = cloned_repo();
+        let refs = list(&repo).unwrap();
+        let names: Vec<&str> = refs.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "refs/heads/main",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/feature",
+                "refs/remotes/origin/main",
+                "refs/tags/v1.0",
+                "refs/tags/v1.1-light",
+                "refs/tags/v2.0",
+            ]
+        );
+        let v2 = refs.iter().find(|r| r.name == "refs/tags/v2.0").unwrap();
+        assert_eq!(
+            v2.peeled().map(|p| p.to_string()).as_deref(),
+            Some("1f4e60d0e36329ddb989541a385fdb3e367b6db9")
+        );
+        let light = refs
+            .iter()
+            .find(|r| r.name == "refs/tags/v1.1-light")
+            .unwrap();
+        assert_eq!(light.peeled(), None);
+    }
+
+    #[test]
+    fn detects_symref_cycles() {
+        let (_dir, repo) = init_repo();
+        let heads = repo.git_dir().join("refs/heads");
+        fs::write(heads.join("a"), "ref: refs/heads/b\n").unwrap();
+        fs::write(heads.join("b"), "ref: refs/heads/a\n").unwrap();
+        assert!(matches!(resolve(&repo, "a"), Err(GitError::Corrupt(_))));
+    }
+
+    #[test]
+    fn updates_with_compare_and_swap() {
+        let (_dir, repo) = init_repo();
+        let a = write_commit(&repo, &[], &[("a", "a")], "a");
+        let b = write_commit(&repo, &[], &[("b", "b")], "b");
+
+        update(&repo, "refs/heads/topic", a, Some(None), "create").unwrap();
+        assert!(matches!(
+            update(&repo, "refs/heads/topic", b, Some(None), "create"),
+            Err(GitError::RefCasFailed(_))
+        ));
+        assert!(matches!(
+            update(&repo, "refs/heads/topic", b, Some(Some(b)), "move"),
+            Err(GitError::RefCasFailed(_))
+        ));
+        update(&repo, "refs/heads/topic", b, Some(Some(a)), "move").unwrap();
+        assert_eq!(resolve(&repo, "topic").unwrap(), b);
+        assert!(!repo.git_dir().join("refs/heads/topic.lock").exists());
+
+        // Updating HEAD moves the branch it points at.
+        update(&repo, "HEAD", a, None, "commit").unwrap();
+        assert_eq!(resolve(&repo, "refs/heads/master").unwrap(), a);
+    }
+
+    #[test]
+    fn refuses_to_update_a_locked_ref() {
+        let (_dir, repo) = init_repo();
+        let a = write_commit(&repo, &[], &[("a", "a")], "a");
+        let b = write_commit(&repo, &[], &[("b", "b")], "b");
+        set_ref(&repo, "refs/heads/topic", &a);
+        let lock_path = repo.git_dir().join("refs/heads/topic.lock");
+        fs::write(&lock_path, "").unwrap();
+
+        match update(&repo, "refs/heads/topic", b, None, "move") {
+            Err(GitError::RefLockConflict(name)) => assert_eq!(name, "refs/heads/topic"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(resolve(&repo, "topic").unwrap(), a);
+        // Someone else's lock is theirs to clean up.
+        assert!(lock_path.exists());
+    }
+
+    #[test]
+    fn deletes_packed_and_loose_refs() {
+        let (_dir, repo) = cloned_repo();
+        let tag = "17c53fc890c62d77e3c6aa17dd5ae0944ca23001"
+            .parse::<Oid>()
+            .unwrap();
+        let wrong = "670284a0b9ad86abaf35be65616d427233a73ae2"
+            .parse::<Oid>()
+            .unwrap();
+        assert!(matches!(
+            delete(&repo, "refs/tags/v1.0", Some(wrong)),
+            Err(GitError::RefCasFailed(_))
+        ));
+        delete(&repo, "refs/tags/v1.0", Some(tag)).unwrap();
+        assert_eq!(read(&repo, "refs/tags/v1.0").unwrap(), None);
+        let packed = packed_refs(&repo).unwrap();
+        assert!(packed.find("refs/tags/v1.0").is_none());
+        assert!(packed.find("refs/tags/v2.0").unwrap().peeled.is_some());
+        assert!(!repo.git_dir().join("packed-refs.lock").exists());
+
+        delete(&repo, "refs/heads/main", None).unwrap();
+        assert_eq!(read(&repo, "refs/heads/main").unwrap(), None);
+        assert!(matches!(
+            delete(&repo, "refs/heads/main", None),
+            Err(GitError::RefNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_garbage_and_escaping_names() {
+        let (_dir, repo) = init_repo();
+        fs::write(repo.git_dir().join("refs/heads/junk"), "not an oid\n").unwrap();
+        assert!(matches!(resolve(&repo, "junk"), Err(GitError::Corrupt(_))));
+        assert!(matches!(
+            resolve(&repo, "../config"),
+            Err(GitError::RefNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn validates_names_like_check_ref_format() {
+        // (name, allow_onelevel, the violated rule if it's invalid)
+        let cases: &[(&str, bool, Option<&str>)] = &[
+            ("refs/heads/main", false, None),
+            ("heads/feature/x-1", false, None),
+            ("main", true, None),
+            ("main", false, Some("only one level")),
+            ("HEAD", true, None),
+            ("v1.0", true, None),
+            ("a.lock.b", true, None),
+            ("@", true, Some("'@'")),
+            ("a@b", true, None),
+            ("", true, Some("empty")),
+            ("feature/..foo", true, Some("'..'")),
+            ("a..b", true, Some("'..'")),
+            ("-bad", true, None),
+            ("a b", true, Some("contains ' '")),
+            ("a\tb", true, Some("control character")),
+            ("a\x7fb", true, Some("control character")),
+            ("a~b", true, Some("contains '~'")),
+            ("a^b", true, Some("contains '^'")),
+            ("a:b", true, Some("contains ':'")),
+            ("a?b", true, Some("contains '?'")),
+            ("a*b", true, Some("contains '*'")),
+            ("a[b", true, Some("contains '['")),
+            ("a\\b", true, Some("contains '\\'")),
+            ("end.lock", true, Some("ends with '.lock'")),
+            ("refs/x.lock/y", false, Some("ends with '.lock'")),
+            ("refs/.hidden", false, Some("starts with '.'")),
+            (".hidden", true, Some("starts with '.'")),
+            ("refs//x", false, Some("'//'")),
+            ("/refs/x", false, Some("'/'")),
+            ("refs/x/", false, Some("'/'")),
+            ("refs/x.", false, Some("ends with '.'")),
+            ("refs/x@{1}", false, Some("'@{'")),
+        ];
+        for &(name, allow_onelevel, violated) in cases {
+            let result = validate_name(name, allow_onelevel);
+            match (violated, result) {
+                (None, Ok(())) => {}
+                (Some(rule), Err(GitError::InvalidRefName { reason, .. })) => {
+                    assert!(reason.contains(rule), "{:?}: {}", name, reason)
+                }
+                (_, result) => panic!("{:?}: unexpected {:?}", name, result),
+            }
+
+            // git would take a leading `-` for an option.
+            if name.starts_with('-') {
+                continue;
+            }
+            let mut command = std::process::Command::new("git");
+            command.arg("check-ref-format");
+            if allow_onelevel {
+                command.arg("--allow-onelevel");
+            }
+            let status = command
+                .arg(name)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status();
+            if let Ok(status) = status {
+                assert_eq!(
+                    status.success(),
+                    violated.is_none(),
+                    "git disagrees on {:?}",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn normalizes_repeated_slashes() {
+        assert_eq!(normalize_name("refs//heads///main"), "refs/heads/main");
+        assert_eq!(normalize_name("/refs/heads/main"), "refs/heads/main");
+        assert_eq!(normalize_name("refs/heads/"), "refs/heads/");
+        assert!(validate_name(&normalize_name("//refs//x"), false).is_ok());
+    }
+
+    #[test]
+    fn iterates_by_prefix_and_pattern() {
+        let (_dir, repo) = init_repo();
+        let id = write_commit(&repo, &[], &[("a", "a")], "base");
+        let hex = id.to_string();
+        fs::write(
+            repo.git_dir().join("packed-refs"),
+            format!(
+                "{hex} refs/heads/feature/packed\n{hex} refs/heads/main\n{hex} refs/tags/v1\n",
+                hex = hex
+            ),
+        )
+        .unwrap();
+        set_ref(&repo, "refs/heads/main", &id);
+        set_ref(&repo, "refs/heads/feature/a", &id);
+        set_ref(&repo, "refs/heads/feature/deep/b", &id);
+        set_ref(&repo, "refs/heads/featureless", &id);
+        set_ref(&repo, "refs/remotes/origin/main", &id);
+        let names = |refs: RefIter| -> Vec<String> { refs.map(|r| r.unwrap().name).collect() };
+
+        assert_eq!(
+            names(iter(&repo).unwrap()),
+            vec![
+                "refs/heads/feature/a",
+                "refs/heads/feature/deep/b",
+                "refs/heads/feature/packed",
+                "refs/heads/featureless",
+                "refs/heads/main",
+                "refs/remotes/origin/main",
+                "refs/tags/v1",
+            ]
+        );
+        assert_eq!(
+            names(iter_prefixed(&repo, "refs/tags/").unwrap()),
+            vec!["refs/tags/v1"]
+        );
+        assert_eq!(
+            names(iter_matching(&repo, "refs/heads/feature/*").unwrap()),
+            vec!["refs/heads/feature/a", "refs/heads/feature/packed"]
+        );
+        assert_eq!(
+            names(iter_matching(&repo, "refs/heads/feature").unwrap()),
+            vec![
+                "refs/heads/feature/a",
+                "refs/heads/feature/deep/b",
+                "refs/heads/feature/packed"
+            ]
+        );
+        assert_eq!(
+            names(iter_matching(&repo, "refs/heads/f").unwrap()),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn broken_loose_refs_do_not_stop_iteration() {
+        let (_dir, repo) = init_repo();
+        let id = write_commit(&repo, &[], &[("a", "a")], "base");
+        set_ref(&repo, "refs/heads/a", &id);
+        fs::write(repo.git_dir().join("refs/heads/b"), "").unwrap();
+        fs::write(repo.git_dir().join("refs/heads/c"), "garbage\n").unwrap();
+        set_ref(&repo, "refs/heads/d", &id);
+
+        let items: Vec<GitResult<Reference>> = iter(&repo).unwrap().collect();
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].as_ref().unwrap().name, "refs/heads/a");
+        assert!(items[1].is_err());
+        assert!(items[2].is_err());
+        assert_eq!(items[3].as_ref().unwrap().name, "refs/heads/d");
+        assert!(list(&repo).is_err());
+    }
+
+    #[test]
+    fn transactions_apply_every_change() {
+        let (_dir, repo) = init_repo();
+        let a = write_commit(&repo, &[], &[("a", "a")], "a");
+        let b = write_commit(&repo, &[a], &[("a", "b")], "b");
+        update(&repo, "refs/heads/master", a, None, "").unwrap();
+        update(&repo, "refs/heads/old", a, None, "").unwrap();
+
+        let mut tx = Transaction::new(&repo);
+        tx.create("refs/heads/new", b)
+            .update("HEAD", b, Some(Some(a)))
+            .delete("refs/heads/old", Some(a));
+        tx.commit("fetch: fast-forward").unwrap();
+
+        assert_eq!(resolve(&repo, "new").unwrap(), b);
+        assert_eq!(resolve(&repo, "master").unwrap(), b);
+        assert_eq!(read(&repo, "refs/heads/old").unwrap(), None);
+        for log in ["refs/heads/new", "refs/heads/master", "HEAD"] {
+            let entries = reflog::read(&repo, log).unwrap();
+            assert_eq!(entries.last().unwrap().message, "fetch: fast-forward");
+        }
+        assert!(!repo.git_dir().join("logs/refs/heads/old").exists());
+    }
+
+    #[test]
+    fn failed_transactions_change_nothing() {
+        let (_dir, repo) = init_repo();
+        let a = write_commit(&repo, &[], &[("a", "a")], "a");
+        let b = write_commit(&repo, &[a], &[("a", "b")], "b");
+        update(&repo, "refs/heads/one", a, None, "").unwrap();
+        update(&repo, "refs/heads/two", a, None, "").unwrap();
+        let unchanged = |repo: &Repository| {
+            assert_eq!(resolve(repo, "one").unwrap(), a);
+            assert_eq!(resolve(repo, "two").unwrap(), a);
+            assert_eq!(read(repo, "refs/heads/three").unwrap(), None);
+            let heads = fs::read_dir(repo.git_dir().join("refs/heads")).unwrap();
+            assert!(heads
+                .map(|e| e.unwrap().file_name())
+                .all(|name| !name.to_string_lossy().ends_with(".lock") || name == "two.lock"));
+        };
+
+        // Someone else is in the middle of updating `two`.
+        let held = LockFile::acquire(&repo.git_dir().join("refs/heads/two")).unwrap();
+        let mut tx = Transaction::new(&repo);
+        tx.update("refs/heads/one", b, None)
+            .create("refs/heads/three", b)
+            .update("refs/heads/two", b, None);
+        assert!(matches!(
+            tx.commit("push"),
+            Err(GitError::RefLockConflict(name)) if name == "refs/heads/two"
+        ));
+        unchanged(&repo);
+        drop(held);
+
+        let mut tx = Transaction::new(&repo);
+        tx.update("refs/heads/one", b, Some(Some(a)))
+            .delete("refs/heads/two", Some(b));
+        assert!(matches!(tx.commit("push"), Err(GitError::RefCasFailed(_))));
+        unchanged(&repo);
+    }
+
+    #[test]
+    fn unwritable_reflogs_do_not_split_transactions() {
+        let (_dir, repo) = init_repo();
+        let a = write_commit(&repo, &[], &[("a", "a")], "a");
+        let b = write_commit(&repo, &[a], &[("a", "b")], "b");
+        update(&repo, "refs/heads/one", a, None, "").unwrap();
+        update(&repo, "refs/heads/two", a, None, "").unwrap();
+        // A directory where `one`'s log should be can't be appended to.
+        let log = repo.git_dir().join("logs/refs/heads/one");
+        fs::remove_file(&log).unwrap();
+        fs::create_dir(&log).unwrap();
+
+        let mut tx = Transaction::new(&repo);
+        tx.update("refs/heads/one", b, Some(Some(a)))
+            .update("refs/heads/two", b, Some(Some(a)));
+        assert!(matches!(tx.commit("push"), Err(GitError::Io(_))));
+        assert_eq!(resolve(&repo, "one").unwrap(), b);
+        assert_eq!(resolve(&repo, "two").unwrap(), b);
+    }
+}
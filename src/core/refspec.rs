@@ -0,0 +1,144 @@
+//! Refspecs: `[+]<src>[:<dst>]`, the rules a fetch or push follows to
+//! decide which refs on one side go to which on the other.
+//!
+//! Either both sides have a single `*`, which stands for the same part of
+//! the name on each, or neither does and the spec names one ref. A leading
+//! `+` lets the destination be updated even when it doesn't fast-forward.
+
+use crate::error::{GitError, GitResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefSpec {
+    force: bool,
+    src: String,
+    dst: Option<String>,
+}
+
+impl RefSpec {
+    pub fn parse(spec: &str) -> GitResult<RefSpec> {
+        let invalid = || GitError::InvalidRefspec(spec.to_string());
+        let (force, rest) = match spec.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        // `<src>:` is the same as no destination at all.
+        let (src, dst) = match rest.split_once(':') {
+            Some((src, "")) => (src, None),
+            Some((src, dst)) => (src, Some(dst)),
+            None => (rest, None),
+        };
+        let wildcards = |side: &str| side.matches('*').count();
+        let src_wildcards = wildcards(src);
+        if src.is_empty() || src_wildcards > 1 || src.contains(':') {
+            return Err(invalid());
+        }
+        if let Some(dst) = dst {
+            if dst.contains(':') || wildcards(dst) != src_wildcards {
+                return Err(invalid());
+            }
+        }
+        Ok(RefSpec {
+            force,
+            src: src.to_string(),
+            dst: dst.map(str::to_string),
+        })
+    }
+
+    /// Whether the spec starts with `+`, allowing non-fast-forward updates.
+    pub fn is_force(&self) -> bool {
+        self.force
+    }
+
+    /// Whether the spec is a pattern rather than naming a single ref.
+    pub fn is_wildcard(&self) -> bool {
+        self.src.contains('*')
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    pub fn dst(&self) -> Option<&str> {
+        self.dst.as_deref()
+    }
+
+    /// The ref `src_ref` goes to under this spec, or `None` if the spec
+    /// doesn't match it or has no destination.
+    pub fn matches(&self, src_ref: &str) -> Option<String> {
+        map(&self.src, self.dst.as_deref()?, src_ref)
+    }
+
+    /// The reverse of [`RefSpec::matches`]: the source ref that would go
+    /// to `dst_ref`.
+    pub fn reverse(&self, dst_ref: &str) -> Option<String> {
+        map(self.dst.as_deref()?, &self.src, dst_ref)
+    }
+}
+
+fn map(from: &str, to: &str, name: &str) -> Option<String> {
+    match (from.split_once('*'), to.split_once('*')) {
+        (Some((prefix, suffix)), Some((to_prefix, to_suffix))) => {
+            let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some(format!("{}{}{}", to_prefix, matched, to_suffix))
+        }
+        (None, None) if from == name => Some(to.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_refs_through_wildcard_and_exact_specs() {
+        let spec = RefSpec::parse("+refs/heads/*:refs/remotes/origin/*").unwrap();
+        assert!(spec.is_wildcard());
+        assert_eq!(
+            spec.matches("refs/heads/main").as_deref(),
+            Some("refs/remotes/origin/main")
+        );
+        assert_eq!(
+            spec.matches("refs/heads/topic/x").as_deref(),
+            Some("refs/remotes/origin/topic/x")
+        );
+        assert_eq!(spec.matches("refs/tags/v1"), None);
+        assert_eq!(
+            spec.reverse("refs/remotes/origin/main").as_deref(),
+            Some("refs/heads/main")
+        );
+
+        let exact = RefSpec::parse("refs/heads/main:refs/remotes/origin/trunk").unwrap();
+        assert!(!exact.is_wildcard());
+        assert_eq!(
+            exact.matches("refs/heads/main").as_deref(),
+            Some("refs/remotes/origin/trunk")
+        );
+        assert_eq!(exact.matches("refs/heads/main2"), None);
+        assert_eq!(RefSpec::parse("refs/heads/main").unwrap().dst(), None);
+        assert_eq!(RefSpec::parse("refs/heads/main:").unwrap().dst(), None);
+
+        for bad in [
+            "",
+            "refs/heads/*:refs/remotes/x",
+            "refs/*/*:refs/*/*",
+            "a:b:c",
+            ":refs/heads/x",
+        ] {
+            assert!(matches!(
+                RefSpec::parse(bad),
+                Err(GitError::InvalidRefspec(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn detects_the_force_prefix() {
+        assert!(RefSpec::parse("+refs/heads/*:refs/remotes/origin/*")
+            .unwrap()
+            .is_force());
+        let spec = RefSpec::parse("refs/heads/*:refs/remotes/origin/*").unwrap();
+        assert!(!spec.is_force());
+        assert_eq!(spec.src(), "refs/heads/*");
+    }
+}
@@ -0,0 +1,420 @@
+//! POSIX extended regular expressions, as git compiles `diff.wordRegex`.
+//!
+//! Enough of the syntax for word patterns is here: alternation, groups,
+//! the `*`, `+`, `?` and `{m,n}` repeats, `.`, bracket expressions with
+//! ranges and `[:class:]` names, the `^` and `$` anchors, and GNU's `\w`,
+//! `\s` and their negations. As with git's `REG_NEWLINE`, `.` and negated
+//! brackets don't match a newline and the anchors match at line
+//! boundaries. Text is matched by UTF-8 character, so a match never ends
+//! inside one; a byte that isn't valid UTF-8 is a character of its own.
+//!
+//! Matches are leftmost-longest, as POSIX has them.
+
+use std::collections::BTreeSet;
+
+use crate::error::{GitError, GitResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Regex {
+    root: Node,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Char(char),
+    /// `.`
+    Any,
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+    LineStart,
+    LineEnd,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClassItem {
+    Range(char, char),
+    Named(CharClass),
+}
+
+/// The `[:name:]` classes, and `\w`'s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Xdigit,
+    Word,
+}
+
+impl CharClass {
+    fn named(name: &str) -> Option<CharClass> {
+        Some(match name {
+            "alnum" => CharClass::Alnum,
+            "alpha" => CharClass::Alpha,
+            "blank" => CharClass::Blank,
+            "cntrl" => CharClass::Cntrl,
+            "digit" => CharClass::Digit,
+            "graph" => CharClass::Graph,
+            "lower" => CharClass::Lower,
+            "print" => CharClass::Print,
+            "punct" => CharClass::Punct,
+            "space" => CharClass::Space,
+            "upper" => CharClass::Upper,
+            "xdigit" => CharClass::Xdigit,
+            _ => return None,
+        })
+    }
+
+    fn contains(self, c: char) -> bool {
+        match self {
+            CharClass::Alnum => c.is_alphanumeric(),
+            CharClass::Alpha => c.is_alphabetic(),
+            CharClass::Blank => c == ' ' || c == '\t',
+            CharClass::Cntrl => c.is_control(),
+            CharClass::Digit => c.is_ascii_digit(),
+            CharClass::Graph => !c.is_whitespace() && !c.is_control(),
+            CharClass::Lower => c.is_lowercase(),
+            CharClass::Print => !c.is_control(),
+            CharClass::Punct => c.is_ascii_punctuation(),
+            CharClass::Space => c.is_whitespace(),
+            CharClass::Upper => c.is_uppercase(),
+            CharClass::Xdigit => c.is_ascii_hexdigit(),
+            CharClass::Word => c.is_alphanumeric() || c == '_',
+        }
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> GitResult<Regex> {
+        let mut parser = Parser {
+            pattern,
+            chars: pattern.chars().collect(),
+            at: 0,
+        };
+        let root = parser.alternation()?;
+        if parser.at < parser.chars.len() {
+            return Err(parser.error("unmatched )"));
+        }
+        Ok(Regex { root })
+    }
+
+    /// The first match in `text` at or after `start`, as byte offsets,
+    /// the longest of those that start there.
+    pub fn find_at(&self, text: &[u8], start: usize) -> Option<(usize, usize)> {
+        let mut at = start;
+        while at <= text.len() {
+            if let Some(&end) = ends(&self.root, text, at).iter().next_back() {
+                return Some((at, end));
+            }
+            match decode(text, at) {
+                Some((_, len)) => at += len,
+                None => break,
+            }
+        }
+        None
+    }
+}
+
+/// The character at `at` and its length in bytes.
+fn decode(text: &[u8], at: usize) -> Option<(char, usize)> {
+    let first = *text.get(at)?;
+    let len = match first {
+        0x00..=0x7f => return Some((char::from(first), 1)),
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 0,
+    };
+    let c = text
+        .get(at..at + len)
+        .and_then(|bytes| std::str::from_utf8(bytes).ok())
+        .and_then(|s| s.chars().next());
+    Some(match c {
+        Some(c) => (c, len),
+        None => (char::REPLACEMENT_CHARACTER, 1),
+    })
+}
+
+/// Every offset a match of `node` starting at `at` can end at.
+fn ends(node: &Node, text: &[u8], at: usize) -> BTreeSet<usize> {
+    let one = |test: &dyn Fn(char) -> bool| match decode(text, at) {
+        Some((c, len)) if test(c) => std::iter::once(at + len).collect(),
+        _ => BTreeSet::new(),
+    };
+    match node {
+        Node::Char(want) => one(&|c| c == *want),
+        Node::Any => one(&|c| c != '\n'),
+        Node::Class { negated, items } => one(&|c| {
+            let found = items.iter().any(|item| match *item {
+                ClassItem::Range(low, high) => low <= c && c <= high,
+                ClassItem::Named(class) => class.contains(c),
+            });
+            if *negated {
+                !found && c != '\n'
+            } else {
+                found
+            }
+        }),
+        Node::LineStart if at == 0 || text[at - 1] == b'\n' => std::iter::once(at).collect(),
+        Node::LineEnd if at == text.len() || text[at] == b'\n' => std::iter::once(at).collect(),
+        Node::LineStart | Node::LineEnd => BTreeSet::new(),
+        Node::Concat(nodes) => {
+            let mut positions: BTreeSet<usize> = std::iter::once(at).collect();
+            for node in nodes {
+                positions = positions
+                    .iter()
+                    .flat_map(|&p| ends(node, text, p))
+                    .collect();
+                if positions.is_empty() {
+                    break;
+                }
+            }
+            positions
+        }
+        Node::Alternate(nodes) => nodes.iter().flat_map(|n| ends(n, text, at)).collect(),
+        Node::Repeat { node, min, max } => {
+            let mut found = BTreeSet::new();
+            let mut seen = BTreeSet::new();
+            let mut current: BTreeSet<usize> = std::iter::once(at).collect();
+            let mut count = 0;
+            loop {
+                if count >= *min {
+                    found.extend(current.iter().copied());
+                    // Past the minimum, only positions not reached
+                    // before can lead anywhere new.
+                    current.retain(|p| seen.insert(*p));
+                }
+                if current.is_empty() || Some(count) == *max || count > text.len() + min {
+                    break;
+                }
+                current = current.iter().flat_map(|&p| ends(node, text, p)).collect();
+                count += 1;
+            }
+            found
+        }
+    }
+}
+
+struct Parser<'a> {
+    pattern: &'a str,
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> GitError {
+        GitError::InvalidRegex {
+            pattern: self.pattern.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.at += 1;
+        c
+    }
+
+    fn alternation(&mut self) -> GitResult<Node> {
+        let mut branches = vec![self.concatenation()?];
+        while self.peek() == Some('|') {
+            self.at += 1;
+            branches.push(self.concatenation()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.pop().expect("one branch"),
+            _ => Node::Alternate(branches),
+        })
+    }
+
+    fn concatenation(&mut self) -> GitResult<Node> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let mut node = self.atom()?;
+            while let Some((min, max)) = self.repeat()? {
+                node = Node::Repeat {
+                    node: Box::new(node),
+                    min,
+                    max,
+                };
+            }
+            nodes.push(node);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> GitResult<Node> {
+        Ok(match self.next().expect("a character") {
+            '(' => {
+                let node = self.alternation()?;
+                if self.next() != Some(')') {
+                    return Err(self.error("unmatched ("));
+                }
+                node
+            }
+            '[' => self.bracket()?,
+            '.' => Node::Any,
+            '^' => Node::LineStart,
+            '$' => Node::LineEnd,
+            '*' | '+' | '?' => return Err(self.error("nothing to repeat")),
+            '\\' => match self.next() {
+                None => return Err(self.error("trailing backslash")),
+                Some(c @ ('w' | 'W' | 's' | 'S')) => Node::Class {
+                    negated: c.is_ascii_uppercase(),
+                    items: vec![ClassItem::Named(if c.eq_ignore_ascii_case(&'w') {
+                        CharClass::Word
+                    } else {
+                        CharClass::Space
+                    })],
+                },
+                Some(c) => Node::Char(c),
+            },
+            c => Node::Char(c),
+        })
+    }
+
+    /// A repeat after an atom, as its bounds.
+    fn repeat(&mut self) -> GitResult<Option<(usize, Option<usize>)>> {
+        let bounds = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.at += 1;
+                let min = self.number().ok_or_else(|| self.error("bad repeat"))?;
+                let max = if self.peek() == Some(',') {
+                    self.at += 1;
+                    self.number()
+                } else {
+                    Some(min)
+                };
+                if self.peek() != Some('}') || max.is_some_and(|max| max < min) {
+                    return Err(self.error("bad repeat"));
+                }
+                (min, max)
+            }
+            _ => return Ok(None),
+        };
+        self.at += 1;
+        Ok(Some(bounds))
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.at;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.at += 1;
+        }
+        self.chars[start..self.at]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    /// A bracket expression, after its `[`. A `]` first is literal, as is
+    /// a backslash anywhere.
+    fn bracket(&mut self) -> GitResult<Node> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.at += 1;
+        }
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.next().ok_or_else(|| self.error("unmatched ["))?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            if c == '[' && self.peek() == Some(':') {
+                let rest: String = self.chars[self.at + 1..].iter().collect();
+                let end = rest.find(":]").ok_or_else(|| self.error("unmatched [:"))?;
+                let class = CharClass::named(&rest[..end])
+                    .ok_or_else(|| self.error("unknown character class"))?;
+                items.push(ClassItem::Named(class));
+                self.at += 1 + rest[..end].chars().count() + 2;
+                continue;
+            }
+            if self.peek() == Some('-') && self.chars.get(self.at + 1).is_some_and(|&c| c != ']') {
+                self.at += 1;
+                let high = self.next().expect("a range end");
+                if high < c {
+                    return Err(self.error("invalid range end"));
+                }
+                items.push(ClassItem::Range(c, high));
+            } else {
+                items.push(ClassItem::Range(c, c));
+            }
+        }
+        Ok(Node::Class { negated, items })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<String> {
+        let regex = Regex::new(pattern).unwrap();
+        regex
+            .find_at(text.as_bytes(), 0)
+            .map(|(start, end)| text[start..end].to_string())
+    }
+
+    #[test]
+    fn finds_leftmost_longest_matches() {
+        assert_eq!(find("[a-z]+|[0-9]+", "  abc123").as_deref(), Some("abc"));
+        assert_eq!(find("a|ab|abc", "xabcd").as_deref(), Some("abc"));
+        assert_eq!(find("(ab)*c", "ababcz").as_deref(), Some("ababc"));
+        assert_eq!(find("[^[:space:]]+", "\t über  ").as_deref(), Some("über"));
+        assert_eq!(find("x{2,3}", "xxxxx").as_deref(), Some("xxx"));
+        assert_eq!(
+            find("\\w+", "--snake_case--").as_deref(),
+            Some("snake_case")
+        );
+        assert_eq!(find("[]a]+", "b]a]c").as_deref(), Some("]a]"));
+        assert_eq!(find("^b", "ab\nbc").as_deref(), Some("b"));
+        assert_eq!(find("a.c", "a\nc").as_deref(), None);
+        assert_eq!(find("é.", "café!").as_deref(), Some("é!"));
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        for pattern in [
+            "(a",
+            "a)",
+            "[a",
+            "*a",
+            "a{2,1}",
+            "[[:nope:]]",
+            "[z-a]",
+            "\\",
+        ] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,418 @@
+//! Rename detection: pairing deleted files with added ones that have the
+//! same or similar content, as `git diff -M` does.
+//!
+//! Exact renames, with the same blob on both sides, are found first by
+//! id alone. The files left are then compared as git's diffcore does:
+//! each is cut into chunks, a line or at most 64 bytes, and two files are
+//! as similar as the bytes of their chunks in common are of the larger.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+use crate::core::diff::{self, DeltaStatus, FileDelta};
+use crate::core::object::MODE_GITLINK;
+use crate::core::odb::ObjectDatabase;
+use crate::error::GitResult;
+
+/// `diff.renameLimit`'s default.
+pub const DEFAULT_LIMIT: usize = 1000;
+
+/// The longest chunk content is cut into.
+const MAX_CHUNK: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenameOptions {
+    /// The lowest similarity, as a percentage, at which files are paired,
+    /// as `-M<n>%` sets.
+    pub threshold: u32,
+    /// Compare contents only while there are at most this many deleted
+    /// files, and as many added, to compare; past it only exact renames
+    /// are found. As `diff.renameLimit` does, 0 means git's own cap.
+    pub limit: usize,
+    /// Also pair added files with modified ones they're copies of, as
+    /// `-C` does.
+    pub copies: bool,
+}
+
+impl Default for RenameOptions {
+    fn default() -> RenameOptions {
+        RenameOptions {
+            threshold: 50,
+            limit: DEFAULT_LIMIT,
+            copies: false,
+        }
+    }
+}
+
+/// Rewrite each added file in `deltas` that's paired with a deleted one
+/// into a [`DeltaStatus::Renamed`] delta, dropping the deletion. Renames
+/// keep the place of the added file. With `options.copies`, added files
+/// left over that are copies of modified files become
+/// [`DeltaStatus::Copied`] deltas, the modified files staying. Returns
+/// whether there were too many files for `options.limit` to compare
+/// their contents.
+pub fn detect_renames(
+    odb: &ObjectDatabase,
+    deltas: &mut Vec<FileDelta>,
+    options: &RenameOptions,
+) -> GitResult<bool> {
+    let candidate = |delta: &FileDelta, status| {
+        delta.status == status
+            && delta
+                .old
+                .or(delta.new)
+                .is_some_and(|e| e.mode != MODE_GITLINK)
+    };
+    let mut sources: Vec<usize> = (0..deltas.len())
+        .filter(|&i| candidate(&deltas[i], DeltaStatus::Deleted))
+        .collect();
+    let mut targets: Vec<usize> = (0..deltas.len())
+        .filter(|&i| candidate(&deltas[i], DeltaStatus::Added))
+        .collect();
+
+    // (target, source, similarity)
+    let mut pairs = Vec::new();
+    targets.retain(|&target| {
+        let new = deltas[target].new.expect("an added file");
+        let matches = sources.iter().enumerate().filter(|(_, &source)| {
+            let old = deltas[source].old.expect("a deleted file");
+            old.oid == new.oid && same_kind(old.mode, new.mode)
+        });
+        let best = matches
+            .clone()
+            .find(|(_, &source)| same_name(&deltas[source].path, &deltas[target].path))
+            .or_else(|| matches.clone().next());
+        match best {
+            Some((n, &source)) => {
+                pairs.push((target, source, 100));
+                sources.remove(n);
+                false
+            }
+            None => true,
+        }
+    });
+
+    let limit = if options.limit == 0 {
+        32767
+    } else {
+        options.limit
+    };
+    let too_many = sources.len().saturating_mul(targets.len()) > limit.saturating_mul(limit);
+    if !too_many && !sources.is_empty() && !targets.is_empty() {
+        let read = |i: usize| -> GitResult<Vec<u8>> {
+            let entry = deltas[i].old.or(deltas[i].new).expect("one side");
+            odb.read_blob(&entry.oid)
+        };
+        let mut old_chunks = Vec::with_capacity(sources.len());
+        for &source in &sources {
+            let data = read(source)?;
+            old_chunks.push((data.len(), chunks(&data)));
+        }
+        // (similarity, same name, target, source)
+        let mut scores = Vec::new();
+        for &target in &targets {
+            let data = read(target)?;
+            let new_chunks = chunks(&data);
+            let new = deltas[target].new.expect("an added file");
+            for (n, &source) in sources.iter().enumerate() {
+                if !same_kind(deltas[source].old.expect("a deleted file").mode, new.mode) {
+                    continue;
+                }
+                let (old_len, old_chunks) = &old_chunks[n];
+                let score = similarity(*old_len, old_chunks, data.len(), &new_chunks, options);
+                if score >= options.threshold {
+                    let name = same_name(&deltas[source].path, &deltas[target].path);
+                    scores.push((score, name, target, source));
+                }
+            }
+        }
+        // The best pairs first, then in path order.
+        scores.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)));
+        let (mut used_targets, mut used_sources) = (Vec::new(), Vec::new());
+        for (score, _, target, source) in scores {
+            if !used_targets.contains(&target) && !used_sources.contains(&source) {
+                used_targets.push(target);
+                used_sources.push(source);
+                pairs.push((target, source, score));
+            }
+        }
+    }
+
+    // (target, source, similarity), the sources staying as they are.
+    let mut copies = Vec::new();
+    let mut too_many_copies = false;
+    if options.copies {
+        let renamed: Vec<usize> = pairs.iter().map(|&(target, _, _)| target).collect();
+        targets.retain(|target| !renamed.contains(target));
+        let sources: Vec<usize> = (0..deltas.len())
+            .filter(|&i| candidate(&deltas[i], DeltaStatus::Modified))
+            .collect();
+        too_many_copies = sources.len().saturating_mul(targets.len()) > limit.saturating_mul(limit);
+        let mut old_chunks = Vec::with_capacity(sources.len());
+        if !too_many_copies {
+            for &source in &sources {
+                let data = odb.read_blob(&deltas[source].old.expect("a modified file").oid)?;
+                old_chunks.push((data.len(), chunks(&data)));
+            }
+        }
+        for &target in &targets {
+            let new = deltas[target].new.expect("an added file");
+            let data = if too_many_copies {
+                None
+            } else {
+                Some(odb.read_blob(&new.oid)?)
+            };
+            let new_chunks = data.as_deref().map(chunks);
+            // (similarity, same name, source)
+            let mut best: Option<(u32, bool, usize)> = None;
+            for (n, &source) in sources.iter().enumerate() {
+                let old = deltas[source].old.expect("a modified file");
+                if !same_kind(old.mode, new.mode) {
+                    continue;
+                }
+                let score = match (&data, &new_chunks) {
+                    _ if old.oid == new.oid => 100,
+                    (Some(data), Some(new_chunks)) => {
+                        let (old_len, old_chunks) = &old_chunks[n];
+                        similarity(*old_len, old_chunks, data.len(), new_chunks, options)
+                    }
+                    _ => continue,
+                };
+                let name = same_name(&deltas[source].path, &deltas[target].path);
+                if score >= options.threshold
+                    && best.is_none_or(|(best, best_name, _)| (score, name) > (best, best_name))
+                {
+                    best = Some((score, name, source));
+                }
+            }
+            if let Some((score, _, source)) = best {
+                copies.push((target, source, score));
+            }
+        }
+    }
+
+    let mut removed = vec![false; deltas.len()];
+    for (target, source, similarity) in pairs {
+        removed[source] = true;
+        let (from, old) = (deltas[source].path.clone(), deltas[source].old);
+        let delta = &mut deltas[target];
+        delta.status = DeltaStatus::Renamed { similarity };
+        delta.from = Some(from);
+        delta.old = old;
+    }
+    for (target, source, similarity) in copies {
+        let (from, old) = (deltas[source].path.clone(), deltas[source].old);
+        let delta = &mut deltas[target];
+        delta.status = DeltaStatus::Copied { similarity };
+        delta.from = Some(from);
+        delta.old = old;
+    }
+    let mut n = 0;
+    deltas.retain(|_| {
+        n += 1;
+        !removed[n - 1]
+    });
+    Ok(too_many || too_many_copies)
+}
+
+fn same_kind(old: u32, new: u32) -> bool {
+    old & 0o170000 == new & 0o170000
+}
+
+/// Whether two paths end in the same file name, which makes a pair the
+/// likelier one when others score the same.
+fn same_name(old: &str, new: &str) -> bool {
+    old.rsplit('/').next() == new.rsplit('/').next()
+}
+
+/// Each distinct chunk of `data`, by its hash, with the bytes it covers.
+/// Text is cut after each newline, and a `\r` before one isn't counted,
+/// so line endings don't make files dissimilar.
+fn chunks(data: &[u8]) -> HashMap<u64, usize> {
+    let text = !diff::is_binary(data);
+    let mut chunks = HashMap::new();
+    let mut bytes = data.iter().peekable();
+    while bytes.peek().is_some() {
+        let mut hasher = DefaultHasher::new();
+        let mut len = 0;
+        while let Some(&byte) = bytes.next() {
+            if text && byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+                continue;
+            }
+            hasher.write_u8(byte);
+            len += 1;
+            if len == MAX_CHUNK || (text && byte == b'\n') {
+                break;
+            }
+        }
+        *chunks.entry(hasher.finish()).or_insert(0) += len;
+    }
+    chunks
+}
+
+/// How alike two files are as a percentage: the bytes of chunks they
+/// share out of the larger file's size. Files too different in size to
+/// reach the threshold aren't compared.
+fn similarity(
+    old_len: usize,
+    old: &HashMap<u64, usize>,
+    new_len: usize,
+    new: &HashMap<u64, usize>,
+    options: &RenameOptions,
+) -> u32 {
+    let (larger, smaller) = (old_len.max(new_len), old_len.min(new_len));
+    if new_len == 0
+        || larger * (100 - options.threshold.min(100) as usize) < (larger - smaller) * 100
+    {
+        return 0;
+    }
+    let common: usize = old
+        .iter()
+        .filter_map(|(hash, &len)| new.get(hash).map(|&other| len.min(other)))
+        .sum();
+    (common * 100 / larger) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::diff::tree_diff;
+    use crate::core::pathspec::Pathspec;
+    use crate::test_utils::{git, init_repo, write_commit};
+
+    /// The `--name-status` lines for the changes from `old` to `new`,
+    /// checked against git's with the same options.
+    fn renames(old: &[(&str, &str)], new: &[(&str, &str)], options: RenameOptions) -> String {
+        let (_dir, repo) = init_repo();
+        let odb = repo.odb();
+        let old = write_commit(&repo, &[], old, "old");
+        let new = write_commit(&repo, &[], new, "new");
+        let tree = |id| odb.read_commit(&id).unwrap().tree;
+        let (old_tree, new_tree) = (tree(old), tree(new));
+        let mut deltas =
+            tree_diff(odb, Some(&old_tree), Some(&new_tree), &Pathspec::default()).unwrap();
+        detect_renames(odb, &mut deltas, &options).unwrap();
+        let lines: String = deltas
+            .iter()
+            .map(|d| match (d.status, &d.from) {
+                (DeltaStatus::Renamed { similarity }, Some(from)) => {
+                    format!("R{:03}\t{}\t{}\n", similarity, from, d.path)
+                }
+                (DeltaStatus::Copied { similarity }, Some(from)) => {
+                    format!("C{:03}\t{}\t{}\n", similarity, from, d.path)
+                }
+                _ => format!("{}\t{}\n", d.status.letter(), d.path),
+            })
+            .collect();
+        let detect = if options.copies { "-C" } else { "-M" };
+        let threshold = format!("{}{}%", detect, options.threshold);
+        let limit = format!("diff.renameLimit={}", options.limit);
+        let (old, new) = (old.to_string(), new.to_string());
+        let args = [
+            "-c",
+            &limit,
+            "diff-tree",
+            "-r",
+            "--name-status",
+            &threshold,
+            &old,
+            &new,
+        ];
+        if let Some(theirs) = git(&repo, &args) {
+            assert_eq!(lines, theirs);
+        }
+        lines
+    }
+
+    fn numbered(lines: std::ops::Range<u32>) -> String {
+        lines.map(|n| format!("line {}\n", n)).collect()
+    }
+
+    #[test]
+    fn finds_exact_renames() {
+        let content = numbered(0..10);
+        assert_eq!(
+            renames(
+                &[("a/old.txt", &content), ("empty", ""), ("keep", "k\n")],
+                &[("b/old.txt", &content), ("also-empty", ""), ("keep", "k\n")],
+                RenameOptions::default()
+            ),
+            "R100\tempty\talso-empty\nR100\ta/old.txt\tb/old.txt\n"
+        );
+    }
+
+    #[test]
+    fn pairs_edited_files_above_the_threshold() {
+        // One file gets a line changed, the other most of it rewritten.
+        let content = numbered(0..20);
+        let edited = content.replace("line 3\n", "line three\n");
+        let other = content.replace("line", "row");
+        let rewritten = numbered(0..8) + &numbered(100..112);
+        let old = [("x/a.txt", content.as_str()), ("x/b.txt", other.as_str())];
+        let new = [
+            ("y/a.txt", edited.as_str()),
+            ("y/b.txt", rewritten.as_str()),
+        ];
+
+        assert_eq!(
+            renames(&old, &new, RenameOptions::default()),
+            "D\tx/b.txt\nR092\tx/a.txt\ty/a.txt\nA\ty/b.txt\n"
+        );
+        let strict = RenameOptions {
+            threshold: 96,
+            ..RenameOptions::default()
+        };
+        assert_eq!(
+            renames(&old, &new, strict),
+            "D\tx/a.txt\nD\tx/b.txt\nA\ty/a.txt\nA\ty/b.txt\n"
+        );
+    }
+
+    #[test]
+    fn only_finds_exact_renames_past_the_limit() {
+        let (same, similar) = (numbered(0..10), numbered(20..30));
+        let old = [("a", same.as_str()), ("b", similar.as_str()), ("c", "c\n")];
+        let edited = similar.replace("line 25", "line five");
+        let new = [("a2", same.as_str()), ("b2", edited.as_str()), ("d", "d\n")];
+        let options = RenameOptions {
+            limit: 1,
+            ..RenameOptions::default()
+        };
+        assert_eq!(
+            renames(&old, &new, options),
+            "R100\ta\ta2\nD\tb\nA\tb2\nD\tc\nA\td\n"
+        );
+        assert_eq!(
+            renames(&old, &new, RenameOptions::default()),
+            "R100\ta\ta2\nR087\tb\tb2\nD\tc\nA\td\n"
+        );
+    }
+
+    #[test]
+    fn finds_copies_of_modified_files() {
+        let content = numbered(0..20);
+        let old = [("src/lib.rs", content.as_str()), ("other", "o\n")];
+        let edited = content.replace("line 9\n", "line nine\n");
+        let copied = content.replace("line 2\n", "line two\n");
+        let new = [
+            ("other", "o\n"),
+            ("src/copy.rs", copied.as_str()),
+            ("src/lib.rs", edited.as_str()),
+            ("src/same.rs", content.as_str()),
+        ];
+        let options = RenameOptions {
+            copies: true,
+            ..RenameOptions::default()
+        };
+        assert_eq!(
+            renames(&old, &new, options),
+            "C094\tsrc/lib.rs\tsrc/copy.rs\nM\tsrc/lib.rs\nC100\tsrc/lib.rs\tsrc/same.rs\n"
+        );
+        // Without -C they're only added.
+        assert_eq!(
+            renames(&old, &new, RenameOptions::default()),
+            "A\tsrc/copy.rs\nM\tsrc/lib.rs\nA\tsrc/same.rs\n"
+        );
+    }
+}
@@ -0,0 +1,481 @@
+//! Revision expressions, as `git rev-parse` understands them.
+//!
+//! An expression is a base naming an object followed by any number of
+//! suffixes, applied left to right:
+//!
+//! - `^` or `^N`: the commit's first or `N`th parent, with `^0` being the
+//!   commit itself.
+//! - `~` or `~N`: the `N`th generation ancestor, following first parents.
+//! - `^{type}`: tags peeled until an object of `type` turns up, or `^{}`
+//!   to peel every tag.
+//!
+//! Ranges select sets of commits for a walk instead: see [`parse_range`].
+//!
+//! The base is a full or abbreviated hex id, a ref name in any form
+//! [`refs::resolve`] takes, `@` for HEAD, `ref@{N}` for the value a ref
+//! had `N` updates ago, or `@{-N}` for the `N`th previously checked out
+//! branch.
+//!
+//! Where a base could mean more than one thing, git's precedence holds:
+//! a full 40-digit id is always that object, then a ref wins over an
+//! abbreviated id it happens to spell, and among refs a tag wins over a
+//! branch of the same name, which wins over a remote-tracking branch.
+
+use crate::core::object::{Commit, ObjectType, Tag};
+use crate::core::oid::{self, Oid};
+use crate::core::reflog;
+use crate::core::refs;
+use crate::core::revwalk;
+use crate::error::{GitError, GitResult};
+use crate::repository::{Head, Repository};
+
+/// Abbreviated ids shorter than this are taken as ref names only.
+pub const MIN_ABBREV: usize = 4;
+
+/// Resolve the revision expression `spec` to an object id.
+pub fn parse(repo: &Repository, spec: &str) -> GitResult<Oid> {
+    let base_len = spec.find(['^', '~']).unwrap_or(spec.len());
+    let mut id = resolve_base(repo, &spec[..base_len])?;
+    let mut rest = &spec[base_len..];
+    while let Some(op) = rest.chars().next() {
+        rest = &rest[1..];
+        let step = if op == '^' && rest.starts_with('{') {
+            let close = rest.find('}').ok_or_else(|| GitError::BadRevision {
+                rev: spec.to_string(),
+                reason: "missing '}'".to_string(),
+            })?;
+            let peel_to = &rest[1..close];
+            rest = &rest[close + 1..];
+            Step::Peel(peel_to)
+        } else {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let count = match &rest[..digits] {
+                "" => Some(1),
+                number => number.parse().ok(),
+            };
+            rest = &rest[digits..];
+            // Only another suffix can follow the count.
+            let ends = rest.is_empty() || rest.starts_with(['^', '~']);
+            match (op, count) {
+                ('^', Some(n)) if ends => Step::Parent(n),
+                ('~', Some(n)) if ends => Step::Ancestor(n),
+                _ => {
+                    rest = "";
+                    Step::Invalid
+                }
+            }
+        };
+        let taken = &spec[..spec.len() - rest.len()];
+        let fail = |reason: String| GitError::BadRevision {
+            rev: taken.to_string(),
+            reason,
+        };
+        id = match step {
+            Step::Parent(n) => parent(repo, id, n)?.map_err(fail)?,
+            Step::Ancestor(n) => ancestor(repo, id, n)?.map_err(fail)?,
+            Step::Peel(kind) => peel(repo, id, kind)?.map_err(fail)?,
+            Step::Invalid => return Err(fail("not a valid revision suffix".to_string())),
+        };
+    }
+    Ok(id)
+}
+
+/// The commits a set of revision arguments selects: everything reachable
+/// from an included commit and from no excluded one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RevSpecSet {
+    pub include: Vec<Oid>,
+    pub exclude: Vec<Oid>,
+}
+
+/// Translate revision arguments as `git rev-list` takes them: `A..B`
+/// includes `B` and excludes `A`, `A...B` includes both and excludes their
+/// merge bases, `^A` excludes `A`, and anything else is included. Either
+/// side of a range defaults to HEAD, as does the whole set when `args` is
+/// empty.
+pub fn parse_range(repo: &Repository, args: &[String]) -> GitResult<RevSpecSet> {
+    let mut set = RevSpecSet::default();
+    if args.is_empty() {
+        set.include.push(parse(repo, "HEAD")?);
+    }
+    let side = |rev: &str| parse(repo, if rev.is_empty() { "HEAD" } else { rev });
+    for arg in args {
+        if let Some((left, right)) = arg.split_once("...") {
+            check_range_side(arg, left)?;
+            check_range_side(arg, right)?;
+            let (left, right) = (side(left)?, side(right)?);
+            let odb = repo.odb();
+            let (left, right) = (odb.peel_to_commit(&left)?, odb.peel_to_commit(&right)?);
+            set.exclude
+                .extend(revwalk::merge_bases(odb, &left, &right)?);
+            set.include.extend([left, right]);
+        } else if let Some((left, right)) = arg.split_once("..") {
+            check_range_side(arg, left)?;
+            check_range_side(arg, right)?;
+            set.exclude.push(side(left)?);
+            set.include.push(side(right)?);
+        } else if let Some(rev) = arg.strip_prefix('^') {
+            if rev.is_empty() || rev.starts_with('^') {
+                return Err(GitError::BadRange(arg.to_string()));
+            }
+            set.exclude.push(parse(repo, rev)?);
+        } else {
+            set.include.push(parse(repo, arg)?);
+        }
+    }
+    Ok(set)
+}
+
+/// Refs can't contain `..`, so finding it in either side of a range means
+/// the range has too many ends, or an end that is itself negated.
+fn check_range_side(range: &str, side: &str) -> GitResult<()> {
+    if side.contains("..") || side.starts_with('^') {
+        return Err(GitError::BadRange(range.to_string()));
+    }
+    Ok(())
+}
+
+enum Step<'a> {
+    Parent(usize),
+    Ancestor(usize),
+    Peel(&'a str),
+    Invalid,
+}
+
+/// Errors that belong to one step come back as a reason, for [`parse`] to
+/// attach the step to; anything else, like a missing object, is passed up
+/// as it is.
+type StepResult = Result<Oid, String>;
+
+fn resolve_base(repo: &Repository, base: &str) -> GitResult<Oid> {
+    let unknown = || GitError::UnknownRevision(base.to_string());
+    if base == "@" {
+        return resolve_base(repo, "HEAD");
+    }
+    if let Some((name, selector)) = split_reflog_selector(base) {
+        if let Some(back) = selector.strip_prefix('-') {
+            let n: usize = back.parse().map_err(|_| unknown())?;
+            if !name.is_empty() {
+                return Err(unknown());
+            }
+            let previous = reflog::previous_checkout(repo, n)?.ok_or_else(unknown)?;
+            return resolve_base(repo, &previous);
+        }
+        let n: usize = selector.parse().map_err(|_| unknown())?;
+        let refname = match name {
+            "" => match repo.head()? {
+                Head::Branch(branch, _) | Head::Unborn(branch) => branch,
+                Head::Detached(_) => "HEAD".to_string(),
+            },
+            name => refs::expand(repo, name)?.ok_or_else(unknown)?,
+        };
+        return reflog::nth_value(repo, &refname, n);
+    }
+    if base.len() == 40 && oid::is_hex(base) {
+        return base.parse::<Oid>();
+    }
+    // Like git, a ref wins over an abbreviated id that happens to match.
+    match refs::resolve(repo, base) {
+        Err(GitError::RefNotFound(_)) => {}
+        resolved => return resolved,
+    }
+    if base.len() < MIN_ABBREV || !oid::is_hex(base) {
+        return Err(unknown());
+    }
+    match repo.odb().find_prefix(base)?.as_slice() {
+        [] => Err(unknown()),
+        [id] => Ok(*id),
+        _ => Err(GitError::BadRevision {
+            rev: base.to_string(),
+            reason: "short object id is ambiguous".to_string(),
+        }),
+    }
+}
+
+/// Split `name@{selector}` into its parts.
+fn split_reflog_selector(rev: &str) -> Option<(&str, &str)> {
+    let inner = rev.strip_suffix('}')?;
+    let at = inner.rfind("@{")?;
+    Some((&inner[..at], &inner[at + 2..]))
+}
+
+fn to_commit(repo: &Repository, id: Oid) -> GitResult<StepResult> {
+    Ok(match repo.odb().peel(&id)? {
+        (commit, ObjectType::Commit) => Ok(commit),
+        (_, kind) => Err(format!("{} is a {}, not a commit", id, kind)),
+    })
+}
+
+fn parent(repo: &Repository, id: Oid, n: usize) -> GitResult<StepResult> {
+    let commit = match to_commit(repo, id)? {
+        Ok(commit) => commit,
+        failed => return Ok(failed),
+    };
+    if n == 0 {
+        return Ok(Ok(commit));
+    }
+    let parents = repo.odb().read_commit(&commit)?.parents;
+    Ok(match parents.get(n - 1) {
+        Some(parent) => Ok(*parent),
+        None => Err(match parents.len() {
+            0 => "commit has no parents".to_string(),
+            1 => "commit has only 1 parent".to_string(),
+            count => format!("commit has only {} parents", count),
+        }),
+    })
+}
+
+fn ancestor(repo: &Repository, id: Oid, n: usize) -> GitResult<StepResult> {
+    let mut current = match to_commit(repo, id)? {
+        Ok(commit) => commit,
+        failed => return Ok(failed),
+    };
+    for walked in 0..n {
+        current = match repo.odb().read_commit(&current)?.parents.first() {
+            Some(parent) => *parent,
+            None => {
+                let count = walked + 1;
+                return Ok(Err(format!(
+                    "only {} commit{} in history",
+                    count,
+                    if count == 1 { "" } else { "s" }
+                )));
+            }
+        };
+    }
+    Ok(Ok(current))
+}
+
+fn peel(repo: &Repository, id: Oid, kind: &str) -> GitResult<StepResult> {
+    let odb = repo.odb();
+    let wanted = match kind {
+        "" => return Ok(Ok(odb.peel(&id)?.0)),
+        "object" => {
+            return match odb.contains(&id) {
+                true => Ok(Ok(id)),
+                false => Err(GitError::ObjectNotFound(id)),
+            }
+        }
+        other => match ObjectType::parse(other) {
+            Ok(wanted) => wanted,
+            Err(_) => return Ok(Err(format!("unknown object type '{}'", other))),
+        },
+    };
+    let mut current = id;
+    loop {
+        let (found, data) = odb.read_raw(&current)?;
+        if found == wanted {
+            return Ok(Ok(current));
+        }
+        current = match found {
+            ObjectType::Tag => Tag::parse(&data)?.object,
+            ObjectType::Commit if wanted == ObjectType::Tree => Commit::parse(&data)?.tree,
+            _ => {
+                return Ok(Err(format!(
+                    "{} peels to a {}, not a {}",
+                    id, found, wanted
+                )))
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tag;
+    use crate::test_utils::{git, init_repo, set_ref, signature, write_commit};
+
+    /// A merge `M` of `B` and `C`, where `B` and `C` both build on `A`,
+    /// with a lightweight tag `v1` and an annotated tag `v2` on `M`.
+    fn fixture() -> (tempfile::TempDir, Repository, [Oid; 4]) {
+        let (dir, repo) = init_repo();
+        let a = write_commit(&repo, &[], &[("f", "a")], "A");
+        let b = write_commit(&repo, &[a], &[("f", "b")], "B");
+        let c = write_commit(&repo, &[a], &[("f", "c"), ("g", "c")], "C");
+        let m = write_commit(&repo, &[b, c], &[("f", "m"), ("g", "c")], "M");
+        repo.set_head_commit(&m, "").unwrap();
+        tag::create_lightweight(&repo, "v1", "HEAD", false).unwrap();
+        tag::create_annotated(&repo, "v2", "HEAD", "two", signature(), false).unwrap();
+        (dir, repo, [a, b, c, m])
+    }
+
+    #[test]
+    fn resolves_expressions_like_git() {
+        let (_dir, repo, [a, b, c, m]) = fixture();
+        let tree = |id: Oid| repo.odb().read_commit(&id).unwrap().tree;
+        let mut cases: Vec<(String, Oid)> = vec![
+            ("HEAD", m),
+            ("@", m),
+            ("master", m),
+            ("refs/heads/master", m),
+            ("HEAD^", b),
+            ("HEAD^1", b),
+            ("HEAD^2", c),
+            ("HEAD^0", m),
+            ("HEAD~", b),
+            ("HEAD~2", a),
+            ("HEAD^^", a),
+            ("HEAD^2~1", a),
+            ("@~1^{tree}", tree(b)),
+            ("HEAD~0^2^{tree}", tree(c)),
+            ("v1", m),
+            ("v2^{commit}", m),
+            ("v2^{}", m),
+            ("v2^{tree}", tree(m)),
+            ("v2~1", b),
+            ("master@{0}", m),
+        ]
+        .into_iter()
+        .map(|(spec, id)| (spec.to_string(), id))
+        .collect();
+        cases.push((format!("{}^", c.abbrev(10)), a));
+        cases.push(("v2^{tag}".to_string(), refs::resolve(&repo, "v2").unwrap()));
+        for (spec, want) in cases {
+            assert_eq!(parse(&repo, &spec).unwrap(), want, "{}", spec);
+            if let Some(output) = git(&repo, &["rev-parse", "--verify", "-q", &spec]) {
+                assert_eq!(output.trim(), want.to_string(), "{}", spec);
+            }
+        }
+    }
+
+    #[test]
+    fn prefers_refs_as_git_does() {
+        let (_dir, repo, [a, b, c, m]) = fixture();
+        set_ref(&repo, "refs/heads/v1", &a);
+        set_ref(&repo, "refs/heads/topic", &b);
+        set_ref(&repo, "refs/remotes/topic", &c);
+        // A branch spelling the start of another commit's id.
+        let prefix = c.abbrev(7);
+        set_ref(&repo, &format!("refs/heads/{}", prefix), &a);
+        let cases = [
+            ("v1", m),
+            ("heads/v1", a),
+            ("topic", b),
+            ("remotes/topic", c),
+            (&prefix, a),
+        ];
+        for (spec, want) in cases {
+            assert_eq!(parse(&repo, spec).unwrap(), want, "{}", spec);
+            if let Some(output) = git(&repo, &["rev-parse", "--verify", "-q", spec]) {
+                assert_eq!(output.trim(), want.to_string(), "{}", spec);
+            }
+        }
+        assert_eq!(parse(&repo, &c.to_string()).unwrap(), c);
+    }
+
+    #[test]
+    fn names_the_step_that_failed() {
+        let (_dir, repo, [_, _, _, m]) = fixture();
+        let reason = |spec: &str| match parse(&repo, spec) {
+            Err(GitError::BadRevision { rev, reason }) => format!("{}: {}", rev, reason),
+            other => panic!("{}: {:?}", spec, other),
+        };
+        assert_eq!(reason("HEAD~5"), "HEAD~5: only 3 commits in history");
+        assert_eq!(reason("HEAD^3"), "HEAD^3: commit has only 2 parents");
+        assert_eq!(reason("HEAD^^2^"), "HEAD^^2: commit has only 1 parent");
+        assert_eq!(
+            reason("HEAD^{tree}^"),
+            format!(
+                "HEAD^{{tree}}^: {} is a tree, not a commit",
+                repo.odb().read_commit(&m).unwrap().tree
+            )
+        );
+        assert_eq!(
+            reason("v1^{tag}"),
+            format!("v1^{{tag}}: {} peels to a commit, not a tag", m)
+        );
+        assert_eq!(
+            reason("HEAD^{nope}"),
+            "HEAD^{nope}: unknown object type 'nope'"
+        );
+        assert_eq!(reason("HEAD~x"), "HEAD~x: not a valid revision suffix");
+        assert_eq!(reason("HEAD~-1"), "HEAD~-1: not a valid revision suffix");
+        assert_eq!(reason("HEAD^2x"), "HEAD^2x: not a valid revision suffix");
+        assert_eq!(
+            reason("HEAD~99999999999999999999"),
+            "HEAD~99999999999999999999: not a valid revision suffix"
+        );
+        assert!(matches!(
+            parse(&repo, "nope~1"),
+            Err(GitError::UnknownRevision(base)) if base == "nope"
+        ));
+    }
+
+    /// Walk `args` the way `git rev-list` would, newest first.
+    fn select(repo: &Repository, args: &[&str]) -> GitResult<Vec<Oid>> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut walk = revwalk::RevWalk::new(repo.odb());
+        walk.push_specs(&parse_range(repo, &args)?)?;
+        walk.map(|item| item.map(|(id, _)| id)).collect()
+    }
+
+    #[test]
+    fn ranges_select_like_rev_list() {
+        // A criss-cross: `left` and `right` each merge the other's first
+        // commit, so `l2` and `r2` have two merge bases, `l1` and `r1`.
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("f", "base")], "base");
+        let l1 = write_commit(&repo, &[base], &[("f", "l1")], "l1");
+        let r1 = write_commit(&repo, &[base], &[("f", "r1")], "r1");
+        let l2 = write_commit(&repo, &[l1, r1], &[("f", "l2")], "l2");
+        let r2 = write_commit(&repo, &[r1, l1], &[("f", "r2")], "r2");
+        let l3 = write_commit(&repo, &[l2], &[("f", "l3")], "l3");
+        set_ref(&repo, "refs/heads/left", &l3);
+        set_ref(&repo, "refs/heads/right", &r2);
+        set_ref(&repo, "refs/tags/l1", &l1);
+        repo.set_head_commit(&l3, "").unwrap();
+        assert_eq!(revwalk::merge_bases(repo.odb(), &l3, &r2).unwrap().len(), 2);
+
+        let cases: Vec<(Vec<&str>, Vec<Oid>)> = vec![
+            (vec!["left...right"], vec![l3, l2, r2]),
+            (vec!["right...left"], vec![l3, l2, r2]),
+            (vec!["right..left"], vec![l3, l2]),
+            (vec!["left..right"], vec![r2]),
+            (vec!["right.."], vec![l3, l2]),
+            (vec!["..right"], vec![r2]),
+            (vec!["left", "^right"], vec![l3, l2]),
+            (vec!["left", "right", "^l1"], vec![l3, l2, r2, r1]),
+            (vec!["^left"], vec![]),
+            (vec![], vec![l3, l2, l1, r1, base]),
+        ];
+        for (args, want) in cases {
+            let mut got = select(&repo, &args).unwrap();
+            let mut expected = want.clone();
+            got.sort();
+            expected.sort();
+            assert_eq!(got, expected, "{:?}", args);
+            if args.is_empty() {
+                continue;
+            }
+            let mut rev_list = vec!["rev-list"];
+            rev_list.extend(&args);
+            if let Some(output) = git(&repo, &rev_list) {
+                let mut theirs: Vec<Oid> =
+                    output.lines().map(|l| l.parse::<Oid>().unwrap()).collect();
+                theirs.sort();
+                assert_eq!(got, theirs, "{:?}", args);
+            }
+        }
+    }
+
+    #[test]
+    fn tells_bad_ranges_from_unknown_revisions() {
+        let (_dir, repo) = init_repo();
+        let base = write_commit(&repo, &[], &[("f", "base")], "base");
+        repo.set_head_commit(&base, "").unwrap();
+        for bad in ["a..b..c", "a...b..c", "^", "^HEAD..HEAD", "HEAD..^HEAD"] {
+            assert!(
+                matches!(select(&repo, &[bad]), Err(GitError::BadRange(_))),
+                "{}",
+                bad
+            );
+        }
+        assert!(matches!(
+            select(&repo, &["HEAD..nope"]),
+            Err(GitError::UnknownRevision(rev)) if rev == "nope"
+        ));
+    }
+}
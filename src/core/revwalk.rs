@@ -0,0 +1,792 @@
+//! Walking commit history from a set of tips.
+//!
+//! Like `git rev-list`, a walk starts from the commits pushed onto it and
+//! leaves out every commit reachable from a hidden one, so pushing `B` and
+//! hiding `A` gives `A..B`. Commits come out newest first by committer date
+//! unless topological order is asked for.
+//!
+//! Hidden commits are found by walking both sides together in date order
+//! and stopping once nothing interesting is left to visit. Committer dates
+//! can go backwards when clocks are skewed, so the walk carries on for
+//! [`SLOP`] more commits before trusting that nothing it has already
+//! listed is reachable from a hidden commit.
+//!
+//! Given a [`Pathspec`], the walk only lists commits that change the
+//! selected paths, and simplifies history the way git does by default: a
+//! merge whose tree matches one of its parents there is only followed
+//! through that parent.
+//!
+//! The commits a shallow clone was cut off at, as [`shallow_commits`] lists
+//! them, are walked as roots whether or not their parents are around.
+//!
+//! A commit older than the walk's `since` time is treated as though it
+//! were hidden, along with what's reachable through it, so that the walk
+//! still goes on for [`SLOP`] commits past the cutoff in case a skewed
+//! clock put a newer commit behind an older one. Commits newer than
+//! `until` are walked through but not listed.
+//!
+//! When the object database has a [`CommitGraph`], walks take each
+//! commit's parents, tree and date from it where they can, and only read
+//! the commit objects they end up listing.
+//!
+//! [`shallow_commits`]: crate::core::shallow::shallow_commits
+//! [`CommitGraph`]: crate::core::commit_graph::CommitGraph
+
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::vec;
+
+use crate::core::object::Commit;
+use crate::core::odb::ObjectDatabase;
+use crate::core::oid::Oid;
+use crate::core::pathspec::Pathspec;
+use crate::core::revparse::RevSpecSet;
+use crate::error::GitResult;
+
+/// How many uninteresting commits the walk visits past the point where
+/// only uninteresting ones are left, to make up for clock skew.
+pub const SLOP: usize = 5;
+
+/// A generation number for commits nothing knows one for; they sort as
+/// though they could be newer than anything.
+pub const GENERATION_UNKNOWN: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sort {
+    /// Newest committer date first, the default for `git rev-list`.
+    #[default]
+    CommitTimeDescending,
+    /// No commit before all of its children, keeping lines of history
+    /// together like `--topo-order`.
+    Topological,
+}
+
+#[derive(Debug)]
+struct Node {
+    tree: Oid,
+    parents: Vec<Oid>,
+    /// The committer date.
+    time: i64,
+    /// The commit itself, unless it was found in the commit-graph and
+    /// hasn't been needed since.
+    commit: Option<Commit>,
+    uninteresting: bool,
+    /// Whether the commit's parents have been queued.
+    visited: bool,
+}
+
+impl Node {
+    fn read(odb: &ObjectDatabase, id: &Oid, uninteresting: bool) -> GitResult<Node> {
+        let graphed = match odb.commit_graph() {
+            Some(graph) => graph.get(id)?,
+            None => None,
+        };
+        let (tree, parents, time, commit) = match graphed {
+            Some(graphed) => (graphed.tree, graphed.parents, graphed.time, None),
+            None => {
+                let commit = odb.read_commit(id)?;
+                let time = commit.committer.time;
+                (commit.tree, commit.parents.clone(), time, Some(commit))
+            }
+        };
+        Ok(Node {
+            tree,
+            parents,
+            time,
+            commit,
+            uninteresting,
+            visited: false,
+        })
+    }
+}
+
+/// Whether the commit `id` can be read, from the commit-graph or the
+/// object database.
+fn has_commit(odb: &ObjectDatabase, id: &Oid) -> bool {
+    odb.commit_graph()
+        .is_some_and(|graph| graph.lookup(id).is_some())
+        || odb.contains(id)
+}
+
+/// What ancestry walks need to know about a commit.
+#[derive(Debug)]
+struct Summary {
+    /// The committer date.
+    time: i64,
+    /// From the commit-graph, or [`GENERATION_UNKNOWN`] for a commit
+    /// outside it or in a graph written without generation numbers.
+    generation: u32,
+    parents: Vec<Oid>,
+}
+
+impl Summary {
+    fn read(odb: &ObjectDatabase, id: &Oid) -> GitResult<Summary> {
+        if let Some(Some(graphed)) = odb.commit_graph().map(|graph| graph.get(id)).transpose()? {
+            return Ok(Summary {
+                time: graphed.time,
+                generation: match graphed.generation {
+                    0 => GENERATION_UNKNOWN,
+                    generation => generation,
+                },
+                parents: graphed.parents,
+            });
+        }
+        let commit = odb.read_commit(id)?;
+        Ok(Summary {
+            time: commit.committer.time,
+            generation: GENERATION_UNKNOWN,
+            parents: commit.parents,
+        })
+    }
+}
+
+/// A commit waiting in the queue. Higher generations come out first, then
+/// newer committer dates, then whichever was queued first.
+#[derive(Debug, PartialEq, Eq)]
+struct Queued {
+    generation: u32,
+    time: i64,
+    seq: u64,
+    id: Oid,
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Queued) -> Ordering {
+        self.generation
+            .cmp(&other.generation)
+            .then(self.time.cmp(&other.time))
+            .then(other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Queued) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// An iterator over commits and their ids. Parents that aren't in the
+/// object database, as in a shallow clone, are treated as though the
+/// commit were a root.
+#[derive(Debug)]
+pub struct RevWalk<'a> {
+    odb: &'a ObjectDatabase,
+    sort: Sort,
+    reverse: bool,
+    generations: HashMap<Oid, u32>,
+    pathspec: Pathspec,
+    shallow: HashSet<Oid>,
+    since: Option<i64>,
+    until: Option<i64>,
+    nodes: HashMap<Oid, Node>,
+    queue: BinaryHeap<Queued>,
+    seq: u64,
+    output: Option<vec::IntoIter<(Oid, Commit)>>,
+}
+
+impl<'a> RevWalk<'a> {
+    pub fn new(odb: &'a ObjectDatabase) -> RevWalk<'a> {
+        RevWalk {
+            odb,
+            sort: Sort::default(),
+            reverse: false,
+            generations: HashMap::new(),
+            pathspec: Pathspec::default(),
+            shallow: HashSet::new(),
+            since: None,
+            until: None,
+            nodes: HashMap::new(),
+            queue: BinaryHeap::new(),
+            seq: 0,
+            output: None,
+        }
+    }
+
+    pub fn sort(&mut self, sort: Sort) -> &mut Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Yield the commits in the opposite order, oldest first.
+    pub fn reverse(&mut self, reverse: bool) -> &mut Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// Known generation numbers, which let the walk order commits without
+    /// relying on their dates alone.
+    pub fn generations(&mut self, generations: HashMap<Oid, u32>) -> &mut Self {
+        self.generations = generations;
+        self
+    }
+
+    /// Only list commits that change the paths `pathspec` selects.
+    pub fn pathspec(&mut self, pathspec: Pathspec) -> &mut Self {
+        self.pathspec = pathspec;
+        self
+    }
+
+    /// Treat these commits as having no parents, as git does the shallow
+    /// boundary of a shallow clone.
+    pub fn shallow(&mut self, shallow: HashSet<Oid>) -> &mut Self {
+        self.shallow = shallow;
+        self
+    }
+
+    /// Only list commits made at or after `time`, in seconds since the
+    /// epoch, by committer date.
+    pub fn since(&mut self, time: i64) -> &mut Self {
+        self.since = Some(time);
+        self
+    }
+
+    /// Only list commits made at or before `time`.
+    pub fn until(&mut self, time: i64) -> &mut Self {
+        self.until = Some(time);
+        self
+    }
+
+    /// Start the walk from `id`, peeling tags down to a commit.
+    pub fn push(&mut self, id: &Oid) -> GitResult<&mut Self> {
+        let commit = self.odb.peel_to_commit(id)?;
+        self.add(commit, false)?;
+        Ok(self)
+    }
+
+    /// Leave out `id` and everything reachable from it.
+    pub fn hide(&mut self, id: &Oid) -> GitResult<&mut Self> {
+        let commit = self.odb.peel_to_commit(id)?;
+        self.add(commit, true)?;
+        Ok(self)
+    }
+
+    /// Walk the commits `specs` selects.
+    pub fn push_specs(&mut self, specs: &RevSpecSet) -> GitResult<&mut Self> {
+        for id in &specs.include {
+            self.push(id)?;
+        }
+        for id in &specs.exclude {
+            self.hide(id)?;
+        }
+        Ok(self)
+    }
+
+    /// Queue `id` if it's new, or mark it uninteresting if asked.
+    fn add(&mut self, id: Oid, uninteresting: bool) -> GitResult<()> {
+        match self.nodes.entry(id) {
+            Entry::Occupied(_) if uninteresting => self.mark_uninteresting(id),
+            Entry::Occupied(_) => {}
+            Entry::Vacant(vacant) => {
+                let node = Node::read(self.odb, &id, uninteresting)?;
+                self.seq += 1;
+                self.queue.push(Queued {
+                    generation: self
+                        .generations
+                        .get(&id)
+                        .copied()
+                        .unwrap_or(GENERATION_UNKNOWN),
+                    time: node.time,
+                    seq: self.seq,
+                    id,
+                });
+                vacant.insert(node);
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark `id` uninteresting along with the ancestors already visited
+    /// through it; the rest inherit it as they're queued.
+    fn mark_uninteresting(&mut self, id: Oid) {
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if let Some(node) = self.nodes.get_mut(&id) {
+                if !node.uninteresting {
+                    node.uninteresting = true;
+                    if node.visited && !self.shallow.contains(&id) {
+                        pending.extend(node.parents.iter().copied());
+                    }
+                }
+            }
+        }
+    }
+
+    fn everybody_uninteresting(&self) -> bool {
+        self.queue
+            .iter()
+            .all(|queued| self.nodes[&queued.id].uninteresting)
+    }
+
+    /// Run the walk to completion, in date order.
+    fn limit(&mut self) -> GitResult<Vec<Oid>> {
+        let mut list = Vec::new();
+        let mut slop = SLOP;
+        while let Some(Queued { id, .. }) = self.queue.pop() {
+            let node = self.nodes.get_mut(&id).expect("queued commits have nodes");
+            node.visited = true;
+            let time = node.time;
+            if self.since.is_some_and(|since| time < since) {
+                node.uninteresting = true;
+            }
+            let uninteresting = node.uninteresting;
+            let odb = self.odb;
+            let mut parents: Vec<Oid> = if self.shallow.contains(&id) {
+                Vec::new()
+            } else {
+                node.parents
+                    .iter()
+                    .filter(|parent| has_commit(odb, parent))
+                    .copied()
+                    .collect()
+            };
+            let mut treesame = false;
+            if !uninteresting && !self.pathspec.is_empty() {
+                let (same, followed) = self.simplify(&id, &parents)?;
+                treesame = same;
+                parents = followed;
+            }
+            for parent in parents {
+                self.add(parent, uninteresting)?;
+            }
+            let too_new = self.until.is_some_and(|until| time > until);
+            if !uninteresting && !treesame && !too_new {
+                list.push(id);
+            }
+            if self.everybody_uninteresting() {
+                slop -= 1;
+                if slop == 0 {
+                    break;
+                }
+            } else {
+                slop = SLOP;
+            }
+        }
+        list.retain(|id| !self.nodes[id].uninteresting);
+        Ok(list)
+    }
+
+    /// Whether a commit leaves the pathspec's paths as one of its parents
+    /// has them, and the parents to walk on through: just the first such
+    /// parent if there is one. A root commit is unchanged if it has none
+    /// of the paths.
+    fn simplify(&self, id: &Oid, parents: &[Oid]) -> GitResult<(bool, Vec<Oid>)> {
+        let tree = self.nodes[id].tree;
+        if parents.is_empty() {
+            return Ok((!self.pathspec.changed(self.odb, None, &tree)?, Vec::new()));
+        }
+        for parent in parents {
+            let parent_tree = match self.nodes.get(parent) {
+                Some(node) => node.tree,
+                None => match self
+                    .odb
+                    .commit_graph()
+                    .map(|graph| graph.get(parent))
+                    .transpose()?
+                {
+                    Some(Some(graphed)) => graphed.tree,
+                    _ => self.odb.read_commit(parent)?.tree,
+                },
+            };
+            if !self.pathspec.changed(self.odb, Some(&parent_tree), &tree)? {
+                return Ok((true, vec![*parent]));
+            }
+        }
+        Ok((false, parents.to_vec()))
+    }
+
+    /// The parents the walk goes through: none for a shallow commit.
+    fn parents(&self, id: &Oid) -> &[Oid] {
+        if self.shallow.contains(id) {
+            &[]
+        } else {
+            &self.nodes[id].parents
+        }
+    }
+
+    /// Reorder `list` so every commit comes after all of its children,
+    /// otherwise following one line of history as far as it goes, like
+    /// git's graph order.
+    fn topological(&self, list: Vec<Oid>) -> Vec<Oid> {
+        let mut children: HashMap<Oid, usize> = list.iter().map(|id| (*id, 0)).collect();
+        for id in &list {
+            for parent in self.parents(id) {
+                if let Some(count) = children.get_mut(parent) {
+                    *count += 1;
+                }
+            }
+        }
+        let mut stack: Vec<Oid> = list
+            .iter()
+            .rev()
+            .filter(|id| children[*id] == 0)
+            .copied()
+            .collect();
+        let mut sorted = Vec::with_capacity(list.len());
+        while let Some(id) = stack.pop() {
+            for parent in self.parents(&id) {
+                if let Some(count) = children.get_mut(parent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        stack.push(*parent);
+                    }
+                }
+            }
+            sorted.push(id);
+        }
+        sorted
+    }
+
+    fn prepare(&mut self) -> GitResult<vec::IntoIter<(Oid, Commit)>> {
+        let mut list = self.limit()?;
+        if self.sort == Sort::Topological {
+            list = self.topological(list);
+        }
+        if self.reverse {
+            list.reverse();
+        }
+        let mut nodes = std::mem::take(&mut self.nodes);
+        let mut commits = Vec::with_capacity(list.len());
+        for id in list {
+            if let Some(node) = nodes.remove(&id) {
+                let commit = match node.commit {
+                    Some(commit) => commit,
+                    None => self.odb.read_commit(&id)?,
+                };
+                commits.push((id, commit));
+            }
+        }
+        Ok(commits.into_iter())
+    }
+}
+
+impl Iterator for RevWalk<'_> {
+    type Item = GitResult<(Oid, Commit)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.output.is_none() {
+            match self.prepare() {
+                Ok(output) => self.output = Some(output),
+                Err(err) => {
+                    self.output = Some(Vec::new().into_iter());
+                    return Some(Err(err));
+                }
+            }
+        }
+        self.output.as_mut()?.next().map(Ok)
+    }
+}
+
+/// Every best common ancestor of `a` and `b`: the common ancestors that
+/// aren't themselves ancestors of another common ancestor. Criss-cross
+/// merges leave more than one. Newest first.
+pub fn merge_bases(odb: &ObjectDatabase, a: &Oid, b: &Oid) -> GitResult<Vec<Oid>> {
+    Painter::new(odb).merge_bases(a, b)
+}
+
+/// Whether `a` is `b` or one of its ancestors.
+pub fn is_ancestor(odb: &ObjectDatabase, a: &Oid, b: &Oid) -> GitResult<bool> {
+    Painter::new(odb).is_ancestor(a, b)
+}
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 2;
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+
+/// git's paint-down-to-common walk. Both sides are painted down through
+/// their parents in generation and then date order; a commit reached from
+/// both is a common ancestor, and everything below it is painted stale.
+/// The walk stops once only stale commits are queued, so history older
+/// than the merge bases is never read.
+///
+/// With generation numbers from a commit-graph, asking whether `a` is an
+/// ancestor of `b` can stop sooner: no commit of a lower generation than
+/// `a` can lead to it, so the walk ends once it gets below `a`'s. Commits
+/// the graph doesn't cover count as newer than anything, which keeps that
+/// right when only older history is in the graph.
+pub(crate) struct Painter<'a> {
+    odb: &'a ObjectDatabase,
+    /// Every commit read so far, kept across walks.
+    commits: HashMap<Oid, Summary>,
+    flags: HashMap<Oid, u8>,
+    queue: BinaryHeap<Queued>,
+    seq: u64,
+}
+
+impl<'a> Painter<'a> {
+    pub(crate) fn new(odb: &'a ObjectDatabase) -> Painter<'a> {
+        Painter {
+            odb,
+            commits: HashMap::new(),
+            flags: HashMap::new(),
+            queue: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+
+    /// How many commits have been read, from the commit-graph or the
+    /// object database.
+    #[cfg(test)]
+    pub(crate) fn visited(&self) -> usize {
+        self.commits.len()
+    }
+
+    pub(crate) fn merge_bases(&mut self, a: &Oid, b: &Oid) -> GitResult<Vec<Oid>> {
+        let candidates = self.paint_down(a, b, false, 0)?;
+        if candidates.len() < 2 {
+            return Ok(candidates);
+        }
+        // A common ancestor of a common ancestor is common too, but not
+        // one of the best.
+        let mut bases = Vec::new();
+        for candidate in &candidates {
+            let mut redundant = false;
+            for other in candidates.iter().filter(|c| *c != candidate) {
+                if self.is_ancestor(candidate, other)? {
+                    redundant = true;
+                    break;
+                }
+            }
+            if !redundant {
+                bases.push(*candidate);
+            }
+        }
+        Ok(bases)
+    }
+
+    pub(crate) fn is_ancestor(&mut self, a: &Oid, b: &Oid) -> GitResult<bool> {
+        if a == b {
+            return Ok(true);
+        }
+        let generation = self.summary(a)?.generation;
+        if generation > self.summary(b)?.generation {
+            return Ok(false);
+        }
+        Ok(self.paint_down(a, b, true, generation)?.contains(a))
+    }
+
+    /// Paint `one` and `two` and walk until only stale commits are left,
+    /// returning the common ancestors found, newest first. With
+    /// `stop_at_one` the walk ends as soon as `one` is reached from `two`,
+    /// and commits below `min_generation` aren't walked through.
+    fn paint_down(
+        &mut self,
+        one: &Oid,
+        two: &Oid,
+        stop_at_one: bool,
+        min_generation: u32,
+    ) -> GitResult<Vec<Oid>> {
+        self.flags.clear();
+        self.queue.clear();
+        if one == two {
+            return Ok(vec![*one]);
+        }
+        self.paint(*one, PARENT1)?;
+        self.paint(*two, PARENT2)?;
+        let mut result = Vec::new();
+        while self.queue.iter().any(|q| self.flags[&q.id] & STALE == 0) {
+            let id = match self.queue.pop() {
+                Some(queued) if queued.generation >= min_generation => queued.id,
+                _ => break,
+            };
+            let mut flags = self.flags[&id] & (PARENT1 | PARENT2 | STALE);
+            if flags == PARENT1 | PARENT2 {
+                let own = self.flags.entry(id).or_default();
+                if *own & RESULT == 0 {
+                    *own |= RESULT;
+                    result.push(id);
+                }
+                // Everything further back is an ancestor of this one.
+                flags |= STALE;
+            }
+            let parents = self.commits[&id].parents.clone();
+            for parent in parents {
+                if self.flags.get(&parent).copied().unwrap_or(0) & flags == flags {
+                    continue;
+                }
+                if !has_commit(self.odb, &parent) {
+                    continue;
+                }
+                self.paint(parent, flags)?;
+                if stop_at_one && parent == *one && flags & PARENT2 != 0 {
+                    return Ok(vec![*one]);
+                }
+            }
+        }
+        // A commit can be painted stale after it was taken for a result,
+        // when a newer common ancestor turns out to reach it.
+        result.retain(|id| self.flags[id] & STALE == 0);
+        Ok(result)
+    }
+
+    /// A commit's summary, read the first time it's asked for.
+    fn summary(&mut self, id: &Oid) -> GitResult<&Summary> {
+        Ok(match self.commits.entry(*id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Summary::read(self.odb, id)?),
+        })
+    }
+
+    /// Add `flags` to a commit's paint and queue it.
+    fn paint(&mut self, id: Oid, flags: u8) -> GitResult<()> {
+        let summary = self.summary(&id)?;
+        let (generation, time) = (summary.generation, summary.time);
+        *self.flags.entry(id).or_default() |= flags;
+        self.seq += 1;
+        self.queue.push(Queued {
+            generation,
+            time,
+            seq: self.seq,
+            id,
+        });
+        Ok(())
+    }
+}
+
+/// `start` and every commit reachable from it.
+pub fn ancestors(odb: &ObjectDatabase, start: &Oid) -> GitResult<HashSet<Oid>> {
+    let mut seen = HashSet::new();
+    let mut queue = vec![*start];
+    while let Some(id) = queue.pop() {
+        if seen.insert(id) {
+            queue.extend(Summary::read(odb, &id)?.parents);
+        }
+    }
+    Ok(seen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::repository::Repository;
+    use crate::test_utils::{git, init_repo, set_ref, write_commit_at};
+
+    fn walk(walk: &mut RevWalk) -> Vec<Oid> {
+        walk.map(|item| item.unwrap().0).collect()
+    }
+
+    /// What `git rev-list <args>` says, if git is around.
+    fn rev_list(repo: &Repository, args: &[&str]) -> Option<Vec<Oid>> {
+        let mut full = vec!["rev-list"];
+        full.extend_from_slice(args);
+        let output = git(repo, &full)?;
+        Some(output.lines().map(|l| l.parse::<Oid>().unwrap()).collect())
+    }
+
+    /// ```text
+    ///   a - b - d - f   (main)
+    ///    \     /
+    ///     c - e         (side)
+    /// ```
+    /// with `c` and `e` dated earlier than `b`.
+    fn history(repo: &Repository) -> [Oid; 6] {
+        let a = write_commit_at(repo, &[], &[("f", "a")], "a", 100);
+        let c = write_commit_at(repo, &[a], &[("f", "c")], "c", 110);
+        let e = write_commit_at(repo, &[c], &[("f", "e")], "e", 120);
+        let b = write_commit_at(repo, &[a], &[("f", "b")], "b", 200);
+        let d = write_commit_at(repo, &[b, e], &[("f", "d")], "d", 300);
+        let f = write_commit_at(repo, &[d], &[("f", "f")], "f", 400);
+        set_ref(repo, "refs/heads/main", &f);
+        set_ref(repo, "refs/heads/side", &e);
+        [a, b, c, d, e, f]
+    }
+
+    #[test]
+    fn orders_merges_by_date_or_topology() {
+        let (_dir, repo) = init_repo();
+        let [a, b, c, d, e, f] = history(&repo);
+        let odb = repo.odb();
+
+        let mut by_date = RevWalk::new(odb);
+        by_date.push(&f).unwrap();
+        assert_eq!(walk(&mut by_date), vec![f, d, b, e, c, a]);
+
+        let mut topo = RevWalk::new(odb);
+        topo.push(&f).unwrap().sort(Sort::Topological);
+        let topo = walk(&mut topo);
+        assert_eq!(topo, vec![f, d, e, c, b, a]);
+        if let Some(expected) = rev_list(&repo, &["--topo-order", "main"]) {
+            assert_eq!(topo, expected);
+        }
+
+        let mut reversed = RevWalk::new(odb);
+        reversed.push(&f).unwrap().reverse(true);
+        assert_eq!(walk(&mut reversed), vec![a, c, e, b, d, f]);
+
+        // Pushing the same commit twice, or one reachable from another,
+        // still lists each commit once.
+        let mut twice = RevWalk::new(odb);
+        twice.push(&f).unwrap().push(&e).unwrap().push(&f).unwrap();
+        assert_eq!(walk(&mut twice).len(), 6);
+    }
+
+    #[test]
+    fn hiding_a_commit_gives_a_range() {
+        let (_dir, repo) = init_repo();
+        let [_, b, c, d, e, f] = history(&repo);
+        let odb = repo.odb();
+
+        let mut range = RevWalk::new(odb);
+        range.push(&f).unwrap().hide(&b).unwrap();
+        let range = walk(&mut range);
+        assert_eq!(range, vec![f, d, e, c]);
+        let exclude = format!("^{}", b);
+        if let Some(expected) = rev_list(&repo, &["main", &exclude]) {
+            assert_eq!(range, expected);
+        }
+
+        let mut side_only = RevWalk::new(odb);
+        side_only.push(&e).unwrap().hide(&f).unwrap();
+        assert!(walk(&mut side_only).is_empty());
+    }
+
+    #[test]
+    fn survives_clock_skew_and_missing_parents() {
+        let (_dir, repo) = init_repo();
+        // `old` claims to be older than everything below it, so the
+        // hidden side only reaches `base` after `mid` has been listed.
+        let root = write_commit_at(&repo, &[], &[("f", "root")], "root", 100);
+        let base = write_commit_at(&repo, &[root], &[("f", "base")], "base", 200);
+        let old = write_commit_at(&repo, &[base], &[("f", "old")], "old", 10);
+        let hidden = write_commit_at(&repo, &[old], &[("f", "hidden")], "hidden", 500);
+        let mid = write_commit_at(&repo, &[base], &[("f", "mid")], "mid", 300);
+        let tip = write_commit_at(&repo, &[mid], &[("f", "tip")], "tip", 400);
+        let odb = repo.odb();
+
+        let mut skewed = RevWalk::new(odb);
+        skewed.push(&tip).unwrap().hide(&hidden).unwrap();
+        assert_eq!(walk(&mut skewed), vec![tip, mid]);
+
+        // Cut the history below `base`, as a shallow clone would.
+        std::fs::remove_file(odb.loose_path(&root)).unwrap();
+        let mut shallow = RevWalk::new(odb);
+        shallow.push(&tip).unwrap();
+        assert_eq!(walk(&mut shallow), vec![tip, mid, base]);
+    }
+
+    #[test]
+    fn follows_a_treesame_parent_through_merges() {
+        let (_dir, repo) = init_repo();
+        let a = write_commit_at(&repo, &[], &[("x", "1"), ("y", "1")], "a", 100);
+        let b = write_commit_at(&repo, &[a], &[("x", "1"), ("y", "2")], "b", 200);
+        let c = write_commit_at(&repo, &[a], &[("x", "2"), ("y", "1")], "c", 300);
+        let d = write_commit_at(&repo, &[b, c], &[("x", "2"), ("y", "2")], "d", 400);
+        // An evil merge, matching neither parent.
+        let e = write_commit_at(&repo, &[d, a], &[("x", "3"), ("y", "2")], "e", 500);
+        set_ref(&repo, "refs/heads/main", &e);
+
+        for (path, expected) in [("x", vec![e, c, a]), ("y", vec![b, a]), ("z", vec![])] {
+            let mut walk_paths = RevWalk::new(repo.odb());
+            walk_paths
+                .push(&e)
+                .unwrap()
+                .pathspec(Pathspec::parse(&[path]));
+            assert_eq!(walk(&mut walk_paths), expected, "{}", path);
+            if let Some(theirs) = rev_list(&repo, &["main", "--", path]) {
+                assert_eq!(expected, theirs, "{}", path);
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! `.git/shallow`, which lists the commits a shallow clone cut history
+//! off at. Their parents are missing on purpose, so anything walking
+//! history treats them as roots.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::core::oid::Oid;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+pub fn path(repo: &Repository) -> PathBuf {
+    repo.git_dir().join("shallow")
+}
+
+/// The shallow boundary commits, one hex id per line of the file. A
+/// repository without the file isn't shallow and has none.
+pub fn shallow_commits(repo: &Repository) -> GitResult<HashSet<Oid>> {
+    let text = match fs::read_to_string(path(repo)) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => return Err(err.into()),
+    };
+    text.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse::<Oid>()
+                .map_err(|_| GitError::Corrupt(format!("bad line in .git/shallow: {}", line)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_repo;
+
+    #[test]
+    fn reads_one_id_per_line() {
+        let (_dir, repo) = init_repo();
+        assert!(shallow_commits(&repo).unwrap().is_empty());
+        let a = "1111111111111111111111111111111111111111";
+        let b = "2222222222222222222222222222222222222222";
+        fs::write(path(&repo), format!("{}\n{}\n", a, b)).unwrap();
+        let expected: HashSet<Oid> = [a.parse::<Oid>().unwrap(), b.parse::<Oid>().unwrap()]
+            .iter()
+            .copied()
+            .collect();
+        assert_eq!(shallow_commits(&repo).unwrap(), expected);
+        fs::write(path(&repo), "not an id\n").unwrap();
+        assert!(matches!(shallow_commits(&repo), Err(GitError::Corrupt(_))));
+    }
+}
@@ -0,0 +1,467 @@
+use std::env;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::config::Config;
+use crate::core::date;
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// The `Name <email> <seconds> <±HHMM>` identity attached to commits and tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Seconds since the unix epoch.
+    pub time: i64,
+    /// Offset from UTC in minutes.
+    pub offset: i32,
+}
+
+impl Signature {
+    pub fn new(name: &str, email: &str, time: i64, offset: i32) -> Signature {
+        Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            time,
+            offset,
+        }
+    }
+
+    /// A signature stamped with the current time and the local timezone's
+    /// offset at that time.
+    pub fn now(name: &str, email: &str) -> Signature {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        Signature::new(name, email, time, local_offset(time))
+    }
+
+    pub fn parse(s: &str) -> GitResult<Signature> {
+        let corrupt = || GitError::Corrupt(format!("malformed signature: {}", s));
+        let open = s.find('<').ok_or_else(corrupt)?;
+        let close = s[open..].find('>').map(|i| open + i).ok_or_else(corrupt)?;
+        let name = s[..open].trim_end().to_string();
+        let email = s[open + 1..close].to_string();
+
+        let mut date = s[close + 1..].split_whitespace();
+        let time = date
+            .next()
+            .and_then(|t| t.parse::<i64>().ok())
+            .ok_or_else(corrupt)?;
+        let offset = date.next().map(parse_offset).transpose()?.unwrap_or(0);
+        Ok(Signature {
+            name,
+            email,
+            time,
+            offset,
+        })
+    }
+
+    /// The date the way `git log` shows it by default, e.g.
+    /// `Thu Apr 7 15:13:13 2005 -0700`.
+    pub fn to_default_date(&self) -> String {
+        let date = CivilTime::at(self.time, self.offset);
+        format!(
+            "{} {} {} {:02}:{:02}:{:02} {} {}",
+            WEEKDAYS[date.weekday],
+            MONTHS[date.month - 1],
+            date.day,
+            date.hour,
+            date.minute,
+            date.second,
+            date.year,
+            format_offset(self.offset)
+        )
+    }
+
+    /// The date as RFC 2822 has it, e.g. `Thu, 7 Apr 2005 15:13:13 -0700`,
+    /// the way `git log --date=rfc` shows it.
+    pub fn to_rfc2822(&self) -> String {
+        let date = CivilTime::at(self.time, self.offset);
+        format!(
+            "{}, {} {} {} {:02}:{:02}:{:02} {}",
+            WEEKDAYS[date.weekday],
+            date.day,
+            MONTHS[date.month - 1],
+            date.year,
+            date.hour,
+            date.minute,
+            date.second,
+            format_offset(self.offset)
+        )
+    }
+
+    /// The date in strict ISO 8601, e.g. `2005-04-07T15:13:13-07:00`, the
+    /// way `git log --date=iso-strict` shows it.
+    pub fn to_iso8601(&self) -> String {
+        let date = CivilTime::at(self.time, self.offset);
+        let offset = format_offset(self.offset);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}:{}",
+            date.year,
+            date.month,
+            date.day,
+            date.hour,
+            date.minute,
+            date.second,
+            &offset[..3],
+            &offset[3..]
+        )
+    }
+
+    /// The date the way `git log --date=iso` shows it, e.g.
+    /// `2005-04-07 15:13:13 -0700`.
+    pub fn to_iso_date(&self) -> String {
+        let date = CivilTime::at(self.time, self.offset);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+            date.year,
+            date.month,
+            date.day,
+            date.hour,
+            date.minute,
+            date.second,
+            format_offset(self.offset)
+        )
+    }
+
+    /// How long before `now` the signature was made, rounded the way
+    /// `--date=relative` rounds it, e.g. `3 weeks ago`.
+    pub fn to_relative_date(&self, now: i64) -> String {
+        if now < self.time {
+            return "in the future".to_string();
+        }
+        let ago = |count: i64, unit: &str| {
+            format!(
+                "{} {}{} ago",
+                count,
+                unit,
+                if count == 1 { "" } else { "s" }
+            )
+        };
+        let seconds = now - self.time;
+        if seconds < 90 {
+            return ago(seconds, "second");
+        }
+        let minutes = (seconds + 30) / 60;
+        if minutes < 90 {
+            return ago(minutes, "minute");
+        }
+        let hours = (minutes + 30) / 60;
+        if hours < 36 {
+            return ago(hours, "hour");
+        }
+        let days = (hours + 12) / 24;
+        if days < 14 {
+            return ago(days, "day");
+        }
+        if days < 70 {
+            return ago((days + 3) / 7, "week");
+        }
+        if days < 365 {
+            return ago((days + 15) / 30, "month");
+        }
+        if days < 1825 {
+            let total_months = (days * 12 * 2 + 365) / (365 * 2);
+            let (years, months) = (total_months / 12, total_months % 12);
+            if months == 0 {
+                return ago(years, "year");
+            }
+            let years = format!("{} year{}", years, if years == 1 { "" } else { "s" });
+            return format!("{}, {}", years, ago(months, "month"));
+        }
+        ago((days + 183) / 365, "year")
+    }
+
+    pub fn format_date(&self, format: DateFormat) -> String {
+        match format {
+            DateFormat::Default => self.to_default_date(),
+            DateFormat::Iso => self.to_iso_date(),
+            DateFormat::IsoStrict => self.to_iso8601(),
+            DateFormat::Rfc2822 => self.to_rfc2822(),
+            DateFormat::Unix => self.time.to_string(),
+            DateFormat::Relative => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0);
+                self.to_relative_date(now)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name,
+            self.email,
+            self.time,
+            format_offset(self.offset)
+        )
+    }
+}
+
+/// The author and committer to stamp a new commit with, each worked out
+/// the way git does: the name and email from `GIT_AUTHOR_NAME` and
+/// `GIT_AUTHOR_EMAIL` (or the `GIT_COMMITTER_*` pair), else `user.name` and
+/// `user.email`, and the date from `GIT_AUTHOR_DATE` (`GIT_COMMITTER_DATE`)
+/// as [`date::parse_git_date`] reads it, else now in the local timezone.
+/// Without a name and email there's no guessing from the host; it's an
+/// error.
+pub fn default_signatures(repo: &Repository) -> GitResult<(Signature, Signature)> {
+    let now = date::to_unix(SystemTime::now());
+    signatures_from(&repo.config()?, |name| env::var(name).ok(), now)
+}
+
+/// [`default_signatures`] with the environment looked up through `env`.
+fn signatures_from(
+    config: &Config,
+    env: impl Fn(&str) -> Option<String>,
+    now: i64,
+) -> GitResult<(Signature, Signature)> {
+    let identity = |role: &str| -> GitResult<Signature> {
+        let var = |field: &str| env(&format!("GIT_{}_{}", role, field));
+        let name = var("NAME").or_else(|| config.get("user", None, "name").map(str::to_string));
+        let email = var("EMAIL").or_else(|| config.get("user", None, "email").map(str::to_string));
+        let (name, email) = match (name, email) {
+            (Some(name), Some(email)) if !name.is_empty() && !email.is_empty() => (name, email),
+            _ => return Err(GitError::MissingIdentity),
+        };
+        let (time, offset) = match var("DATE") {
+            Some(date) => date::parse_git_date(&date)?,
+            None => (now, local_offset(now)),
+        };
+        Ok(Signature::new(&name, &email, time, offset))
+    };
+    Ok((identity("AUTHOR")?, identity("COMMITTER")?))
+}
+
+/// The styles `--date=` picks between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateFormat {
+    #[default]
+    Default,
+    Iso,
+    IsoStrict,
+    Rfc2822,
+    /// Seconds since the epoch.
+    Unix,
+    Relative,
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+pub(crate) const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A moment broken down into calendar fields, as seen in some timezone.
+struct CivilTime {
+    year: i64,
+    /// 1 to 12.
+    month: usize,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    /// 0 for Sunday.
+    weekday: usize,
+}
+
+impl CivilTime {
+    /// Break down `time`, in seconds since the epoch, as a clock `offset`
+    /// minutes ahead of UTC would show it.
+    fn at(time: i64, offset: i32) -> CivilTime {
+        let local = time + i64::from(offset) * 60;
+        let days = local.div_euclid(86_400);
+        let seconds = local.rem_euclid(86_400) as u32;
+        let (year, month, day) = civil_from_days(days);
+        CivilTime {
+            year,
+            month,
+            day,
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+            second: seconds % 60,
+            // The epoch was a Thursday.
+            weekday: (days + 4).rem_euclid(7) as usize,
+        }
+    }
+}
+
+/// The proleptic Gregorian date `days` after 1970-01-01, using Howard
+/// Hinnant's `civil_from_days`: counting in 400-year eras starting on
+/// March 1st puts the leap day at the end of each year.
+pub(crate) fn civil_from_days(days: i64) -> (i64, usize, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as usize;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The local timezone's offset from UTC at `time`, in minutes.
+#[cfg(unix)]
+pub(crate) fn local_offset(time: i64) -> i32 {
+    let time = time as libc::time_t;
+    // SAFETY: `tm` is plain data that `localtime_r` fills in, and both
+    // pointers are valid for the duration of the call.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+    (tm.tm_gmtoff / 60) as i32
+}
+
+#[cfg(not(unix))]
+pub(crate) fn local_offset(_time: i64) -> i32 {
+    0
+}
+
+/// Parse a `±HHMM` timezone offset into minutes.
+pub fn parse_offset(s: &str) -> GitResult<i32> {
+    let corrupt = || GitError::Corrupt(format!("malformed timezone offset: {}", s));
+    let (sign, digits) = match s.as_bytes().first() {
+        Some(b'+') => (1, &s[1..]),
+        Some(b'-') => (-1, &s[1..]),
+        _ => return Err(corrupt()),
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(corrupt());
+    }
+    let hours: i32 = digits[..2].parse().map_err(|_| corrupt())?;
+    let minutes: i32 = digits[2..].parse().map_err(|_| corrupt())?;
+    Ok(sign * (hours * 60 + minutes))
+}
+
+pub fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("{}{:02}{:02}", sign, offset / 60, offset % 60)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_identities_in_git_order() {
+        let config = |text: &str| Config::parse(text).unwrap();
+        let full = config("[user]\n\tname = Config Name\n\temail = config@example.com\n");
+        let no_env = |_: &str| None;
+        let now = 1_700_000_000;
+
+        let (author, committer) = signatures_from(&full, no_env, now).unwrap();
+        assert_eq!(author, committer);
+        assert_eq!(
+            (author.name.as_str(), author.email.as_str(), author.time),
+            ("Config Name", "config@example.com", now)
+        );
+        assert_eq!(author.offset, local_offset(now));
+
+        let env = |name: &str| {
+            match name {
+                "GIT_AUTHOR_NAME" => Some("Env Author"),
+                "GIT_AUTHOR_DATE" => Some("@1112911993 -0700"),
+                "GIT_COMMITTER_EMAIL" => Some("env@example.com"),
+                "GIT_COMMITTER_DATE" => Some("Thu, 7 Apr 2005 22:13:13 +0200"),
+                _ => None,
+            }
+            .map(str::to_string)
+        };
+        let (author, committer) = signatures_from(&full, env, now).unwrap();
+        assert_eq!(
+            author.to_string(),
+            "Env Author <config@example.com> 1112911993 -0700"
+        );
+        assert_eq!(
+            committer.to_string(),
+            "Config Name <env@example.com> 1112904793 +0200"
+        );
+
+        let nameless = config("[user]\n\temail = config@example.com\n");
+        assert!(matches!(
+            signatures_from(&nameless, no_env, now),
+            Err(GitError::MissingIdentity)
+        ));
+        let only_author = |name: &str| (name == "GIT_AUTHOR_NAME").then(|| "A".to_string());
+        assert!(matches!(
+            signatures_from(&nameless, only_author, now),
+            Err(GitError::MissingIdentity)
+        ));
+        let bad_date = |name: &str| (name == "GIT_AUTHOR_DATE").then(|| "soon".to_string());
+        assert!(matches!(
+            signatures_from(&full, bad_date, now),
+            Err(GitError::InvalidDate(_))
+        ));
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let line = "A U Thor <author@example.com> 1112911993 -0700";
+        let sig = Signature::parse(line).unwrap();
+        assert_eq!(sig.name, "A U Thor");
+        assert_eq!(sig.email, "author@example.com");
+        assert_eq!(sig.time, 1112911993);
+        assert_eq!(sig.offset, -420);
+        assert_eq!(sig.to_string(), line);
+    }
+
+    #[test]
+    fn formats_dates_in_their_own_timezone() {
+        let sig = Signature::parse("C O Mitter <c@example.com> 1112911993 -0500").unwrap();
+        assert_eq!(sig.offset, -300);
+        assert_eq!(sig.to_rfc2822(), "Thu, 7 Apr 2005 17:13:13 -0500");
+        assert_eq!(sig.to_iso8601(), "2005-04-07T17:13:13-05:00");
+        assert_eq!(sig.to_default_date(), "Thu Apr 7 17:13:13 2005 -0500");
+        assert_eq!(sig.to_iso_date(), "2005-04-07 17:13:13 -0500");
+        assert_eq!(sig.format_date(DateFormat::Unix), "1112911993");
+        assert_eq!(
+            sig.to_string(),
+            "C O Mitter <c@example.com> 1112911993 -0500"
+        );
+
+        // Far enough east to reach the next day.
+        let east = Signature::new("A", "a@example.com", 1112911993, 13 * 60 + 45);
+        assert_eq!(east.to_rfc2822(), "Fri, 8 Apr 2005 11:58:13 +1345");
+        assert_eq!(east.to_iso8601(), "2005-04-08T11:58:13+13:45");
+
+        let leap = Signature::new("A", "a@example.com", 951_782_400, 0);
+        assert_eq!(leap.to_rfc2822(), "Tue, 29 Feb 2000 00:00:00 +0000");
+        // Half-hour offsets west of UTC keep their sign.
+        let west = Signature::new("A", "a@example.com", 0, -(3 * 60 + 30));
+        assert_eq!(west.to_iso8601(), "1969-12-31T20:30:00-03:30");
+        assert_eq!(parse_offset("-0330").unwrap(), -210);
+        assert!(parse_offset("+130").is_err());
+    }
+
+    #[test]
+    fn rounds_relative_dates_like_git() {
+        let sig = Signature::new("A", "a@example.com", 1_000_000_000, 0);
+        let at = |ago: i64| sig.to_relative_date(sig.time + ago);
+        assert_eq!(at(1), "1 second ago");
+        assert_eq!(at(89), "89 seconds ago");
+        assert_eq!(at(90), "2 minutes ago");
+        assert_eq!(at(3 * 3600), "3 hours ago");
+        assert_eq!(at(36 * 3600), "2 days ago");
+        assert_eq!(at(20 * 86_400), "3 weeks ago");
+        assert_eq!(at(100 * 86_400), "3 months ago");
+        assert_eq!(at(400 * 86_400), "1 year, 1 month ago");
+        assert_eq!(at(730 * 86_400), "2 years ago");
+        assert_eq!(at(3000 * 86_400), "8 years ago");
+        assert_eq!(at(-5), "in the future");
+    }
+}
@@ -0,0 +1,401 @@
+//! Signing commits and tags with an external program, and checking those
+//! signatures, the way git drives `gpg`, `gpgsm` and `ssh-keygen`.
+//!
+//! `gpg.format` picks the kind of signature: `openpgp` (the default) runs
+//! `gpg.openpgp.program` or `gpg.program`, `x509` runs
+//! `gpg.x509.program`, `ssh` runs `gpg.ssh.program`. Each defaults to
+//! the usual binary. The key is `user.signingKey`, falling back to the
+//! signer's identity for the gpg formats; ssh has no such default.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::core::config::Config;
+use crate::core::object::{Commit, Tag};
+use crate::core::signature::{self, Signature};
+use crate::error::{GitError, GitResult};
+use crate::repository::Repository;
+
+/// The header a commit's signature is kept in.
+pub const COMMIT_SIGNATURE_HEADER: &str = "gpgsig";
+
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningFormat {
+    OpenPgp,
+    X509,
+    Ssh,
+}
+
+/// What checking a signature found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// Made by a key the verifier trusts.
+    Good,
+    /// Doesn't match the signed data.
+    Bad,
+    /// Can't be checked: the key is missing, unknown or untrusted.
+    Unknown,
+}
+
+/// The configured signing program and key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signer {
+    pub format: SigningFormat,
+    pub program: String,
+    pub key: Option<String>,
+}
+
+impl Signer {
+    pub fn from_config(config: &Config) -> GitResult<Signer> {
+        let format = match config.get("gpg", None, "format").unwrap_or("openpgp") {
+            "openpgp" => SigningFormat::OpenPgp,
+            "x509" => SigningFormat::X509,
+            "ssh" => SigningFormat::Ssh,
+            other => {
+                return Err(GitError::InvalidConfigValue {
+                    key: "gpg.format".to_string(),
+                    value: other.to_string(),
+                })
+            }
+        };
+        let program = match format {
+            SigningFormat::OpenPgp => config
+                .get("gpg", Some("openpgp"), "program")
+                .or_else(|| config.get("gpg", None, "program"))
+                .unwrap_or("gpg"),
+            SigningFormat::X509 => config
+                .get("gpg", Some("x509"), "program")
+                .unwrap_or("gpgsm"),
+            SigningFormat::Ssh => config
+                .get("gpg", Some("ssh"), "program")
+                .unwrap_or("ssh-keygen"),
+        };
+        Ok(Signer {
+            format,
+            program: program.to_string(),
+            key: config.get("user", None, "signingkey").map(str::to_string),
+        })
+    }
+
+    /// The ASCII-armored signature of `payload`, made for `identity` when
+    /// no signing key is configured.
+    pub fn sign(
+        &self,
+        repo: &Repository,
+        payload: &[u8],
+        identity: &Signature,
+    ) -> GitResult<String> {
+        let output = match self.format {
+            SigningFormat::OpenPgp | SigningFormat::X509 => {
+                let key = match &self.key {
+                    Some(key) => key.clone(),
+                    None => format!("{} <{}>", identity.name, identity.email),
+                };
+                run(&self.program, &["--status-fd=2", "-bsau", &key], payload)?
+            }
+            SigningFormat::Ssh => {
+                let key = self.key.as_deref().ok_or_else(|| {
+                    GitError::SigningFailed(
+                        "user.signingKey needs to be set for ssh signing".to_string(),
+                    )
+                })?;
+                let file = TempFile::new(repo, payload)?;
+                let args = ["-Y", "sign", "-n", "git", "-f", key, file.path_str()];
+                let mut output = run(&self.program, &args, b"")?;
+                let sig_path = file.sig_path();
+                if output.status.success() {
+                    output.stdout = fs::read(&sig_path)
+                        .map_err(|err| GitError::SigningFailed(err.to_string()))?;
+                }
+                let _ = fs::remove_file(sig_path);
+                output
+            }
+        };
+        let signature = String::from_utf8_lossy(&output.stdout).into_owned();
+        if !output.status.success() || signature.trim().is_empty() {
+            return Err(GitError::SigningFailed(
+                String::from_utf8_lossy(&output.stderr)
+                    .trim_end()
+                    .to_string(),
+            ));
+        }
+        Ok(signature)
+    }
+
+    /// Check that `signature` was made over `payload`.
+    pub fn verify(
+        &self,
+        repo: &Repository,
+        payload: &[u8],
+        signature: &str,
+    ) -> GitResult<SignatureStatus> {
+        let file = TempFile::new(repo, signature.as_bytes())?;
+        match self.format {
+            SigningFormat::OpenPgp | SigningFormat::X509 => {
+                let args = [
+                    "--keyid-format=long",
+                    "--status-fd=1",
+                    "--verify",
+                    file.path_str(),
+                    "-",
+                ];
+                // gpg exits non-zero for bad signatures too, so only its
+                // status lines say what it found.
+                let output = run(&self.program, &args, payload)?;
+                let status = String::from_utf8_lossy(&output.stdout);
+                let found = |keyword: &str| {
+                    status
+                        .lines()
+                        .any(|line| line.starts_with(&format!("[GNUPG:] {} ", keyword)))
+                };
+                Ok(if found("BADSIG") {
+                    SignatureStatus::Bad
+                } else if found("GOODSIG") && output.status.success() {
+                    SignatureStatus::Good
+                } else {
+                    SignatureStatus::Unknown
+                })
+            }
+            SigningFormat::Ssh => {
+                let config = repo.config()?;
+                let check = ["-Y", "check-novalidate", "-n", "git", "-s", file.path_str()];
+                if !run(&self.program, &check, payload)?.status.success() {
+                    return Ok(SignatureStatus::Bad);
+                }
+                let allowed = match config.get("gpg", Some("ssh"), "allowedsignersfile") {
+                    Some(allowed) => allowed,
+                    None => return Ok(SignatureStatus::Unknown),
+                };
+                let find = [
+                    "-Y",
+                    "find-principals",
+                    "-f",
+                    allowed,
+                    "-s",
+                    file.path_str(),
+                ];
+                let found = run(&self.program, &find, b"")?;
+                let principals = String::from_utf8_lossy(&found.stdout);
+                let principal = match principals.lines().next() {
+                    Some(principal) if found.status.success() => principal,
+                    _ => return Ok(SignatureStatus::Unknown),
+                };
+                let verify = [
+                    "-Y",
+                    "verify",
+                    "-n",
+                    "git",
+                    "-f",
+                    allowed,
+                    "-I",
+                    principal,
+                    "-s",
+                    file.path_str(),
+                ];
+                Ok(if run(&self.program, &verify, payload)?.status.success() {
+                    SignatureStatus::Good
+                } else {
+                    SignatureStatus::Bad
+                })
+            }
+        }
+    }
+}
+
+/// Sign `commit` into its `gpgsig` header, replacing any signature it
+/// had, so hashing it afterwards gives the signed commit's id.
+pub fn sign_commit(repo: &Repository, commit: &mut Commit) -> GitResult<()> {
+    commit
+        .extra_headers
+        .retain(|(key, _)| key != COMMIT_SIGNATURE_HEADER);
+    let signature =
+        Signer::from_config(&repo.config()?)?.sign(repo, &commit.serialize(), &commit.committer)?;
+    commit.extra_headers.push((
+        COMMIT_SIGNATURE_HEADER.to_string(),
+        signature.trim_end_matches('\n').to_string(),
+    ));
+    Ok(())
+}
+
+/// What was signed and the signature, for a signed commit.
+pub fn commit_signature(commit: &Commit) -> Option<(Vec<u8>, String)> {
+    let signature = commit
+        .extra_headers
+        .iter()
+        .find(|(key, _)| key == COMMIT_SIGNATURE_HEADER)?
+        .1
+        .clone();
+    let mut payload = commit.clone();
+    payload
+        .extra_headers
+        .retain(|(key, _)| key != COMMIT_SIGNATURE_HEADER);
+    Some((payload.serialize(), signature + "\n"))
+}
+
+/// Sign `tag` by appending the signature to its message, which is where
+/// tags carry theirs.
+pub fn sign_tag(repo: &Repository, tag: &mut Tag) -> GitResult<()> {
+    let identity = match &tag.tagger {
+        Some(tagger) => tagger.clone(),
+        None => signature::default_signatures(repo)?.1,
+    };
+    let signature =
+        Signer::from_config(&repo.config()?)?.sign(repo, &tag.serialize(), &identity)?;
+    tag.message.push_str(&signature);
+    Ok(())
+}
+
+/// Run `program` with `input` on its stdin, collecting what it prints. A
+/// program that can't be started is reported the way one that failed is.
+fn run(program: &str, args: &[&str], input: &[u8]) -> GitResult<Output> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| GitError::SigningFailed(format!("cannot run {}: {}", program, err)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A program that exits without reading everything has still
+        // answered; its status says how it went.
+        let _ = stdin.write_all(input);
+    }
+    Ok(child.wait_with_output()?)
+}
+
+/// A file in the git directory holding data for the signing program,
+/// removed again when dropped.
+struct TempFile {
+    path: PathBuf,
+    path_str: String,
+}
+
+impl TempFile {
+    fn new(repo: &Repository, contents: &[u8]) -> GitResult<TempFile> {
+        let path = repo.git_dir().join(format!(
+            "tmp_sig_{}_{}",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, contents)?;
+        let path_str = path.to_string_lossy().into_owned();
+        Ok(TempFile { path, path_str })
+    }
+
+    fn path_str(&self) -> &str {
+        &self.path_str
+    }
+
+    /// Where `ssh-keygen -Y sign` leaves the signature.
+    fn sig_path(&self) -> PathBuf {
+        let mut sig = self.path.clone().into_os_string();
+        sig.push(".sig");
+        PathBuf::from(sig)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config::config_set;
+    use crate::test_utils::{fake_gpg, init_repo, signature, write_script};
+
+    #[test]
+    fn signs_and_verifies_with_the_configured_program() {
+        let (dir, repo) = init_repo();
+        let gpg = fake_gpg(dir.path());
+        config_set(&repo, "gpg.program", gpg.to_str().unwrap()).unwrap();
+        let signer = Signer::from_config(&repo.config().unwrap()).unwrap();
+        assert_eq!(signer.format, SigningFormat::OpenPgp);
+
+        let identity = signature();
+        let signed = signer.sign(&repo, b"payload\n", &identity).unwrap();
+        assert!(signed.starts_with("-----BEGIN PGP SIGNATURE-----\n"));
+        assert!(signed.contains("Comment: A U Thor <author@example.com>\n"));
+        assert_eq!(
+            signer.verify(&repo, b"payload\n", &signed).unwrap(),
+            SignatureStatus::Good
+        );
+        assert_eq!(
+            signer.verify(&repo, b"tampered\n", &signed).unwrap(),
+            SignatureStatus::Bad
+        );
+        config_set(&repo, "user.signingKey", "0123ABCD").unwrap();
+        let signer = Signer::from_config(&repo.config().unwrap()).unwrap();
+        let signed = signer.sign(&repo, b"payload\n", &identity).unwrap();
+        assert!(signed.contains("Comment: 0123ABCD\n"));
+
+        let failing = write_script(
+            dir.path(),
+            "failing-gpg",
+            "echo 'gpg: signing failed: No secret key' >&2\nexit 2\n",
+        );
+        let broken = Signer {
+            program: failing.to_str().unwrap().to_string(),
+            ..signer.clone()
+        };
+        assert!(matches!(
+            broken.sign(&repo, b"payload\n", &identity),
+            Err(GitError::SigningFailed(stderr)) if stderr == "gpg: signing failed: No secret key"
+        ));
+        let missing = Signer {
+            program: dir.path().join("no-such-gpg").to_str().unwrap().to_string(),
+            ..signer
+        };
+        assert!(matches!(
+            missing.sign(&repo, b"payload\n", &identity),
+            Err(GitError::SigningFailed(_))
+        ));
+    }
+
+    #[test]
+    fn signs_with_ssh_keygen() {
+        let (dir, repo) = init_repo();
+        let ssh_keygen = write_script(
+            dir.path(),
+            "ssh-keygen",
+            "key=$6\nfor file; do :; done\n\
+             printf -- '-----BEGIN SSH SIGNATURE-----\\n%s\\n-----END SSH SIGNATURE-----\\n' \
+             \"$key\" > \"$file.sig\"\n",
+        );
+        config_set(&repo, "gpg.format", "ssh").unwrap();
+        config_set(&repo, "gpg.ssh.program", ssh_keygen.to_str().unwrap()).unwrap();
+        let signer = Signer::from_config(&repo.config().unwrap()).unwrap();
+        assert!(matches!(
+            signer.sign(&repo, b"payload\n", &signature()),
+            Err(GitError::SigningFailed(_))
+        ));
+
+        config_set(&repo, "user.signingKey", "/keys/id_ed25519").unwrap();
+        let signer = Signer::from_config(&repo.config().unwrap()).unwrap();
+        assert_eq!(
+            signer.sign(&repo, b"payload\n", &signature()).unwrap(),
+            "-----BEGIN SSH SIGNATURE-----\n/keys/id_ed25519\n-----END SSH SIGNATURE-----\n"
+        );
+        let leftovers = fs::read_dir(repo.git_dir())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with("tmp_sig_")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+
+        config_set(&repo, "gpg.format", "smime").unwrap();
+        assert!(matches!(
+            Signer::from_config(&repo.config().unwrap()),
+            Err(GitError::InvalidConfigValue { .. })
+        ));
+    }
+}
@@ -0,0 +1,167 @@
+//! Conversions between nested tree objects and flat `path -> entry` maps.
+
+use std::collections::BTreeMap;
+
+use crate::core::index::Index;
+use crate::core::object::{GitObject, Tree, TreeEntry, MODE_TREE};
+use crate::core::odb::{ObjectDatabase, ObjectWriter};
+use crate::core::oid::Oid;
+use crate::error::GitResult;
+
+/// A non-tree entry of a flattened tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlatEntry {
+    pub mode: u32,
+    pub oid: Oid,
+}
+
+/// Every blob (and gitlink) in a tree, keyed by its `/`-separated path.
+pub type FlatTree = BTreeMap<String, FlatEntry>;
+
+pub fn flatten(odb: &ObjectDatabase, tree: &Oid) -> GitResult<FlatTree> {
+    let mut flat = FlatTree::new();
+    flatten_into(odb, tree, "", &mut flat)?;
+    Ok(flat)
+}
+
+fn flatten_into(
+    odb: &ObjectDatabase,
+    tree: &Oid,
+    prefix: &str,
+    flat: &mut FlatTree,
+) -> GitResult<()> {
+    for entry in odb.read_tree(tree)?.entries {
+        let path = format!("{}{}", prefix, entry.name);
+        if entry.is_tree() {
+            flatten_into(odb, &entry.oid, &format!("{}/", path), flat)?;
+        } else {
+            flat.insert(
+                path,
+                FlatEntry {
+                    mode: entry.mode,
+                    oid: entry.oid,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The entry at `path`, a `/`-separated path, under `tree`, reading only
+/// the trees along the way.
+pub fn find_path(odb: &ObjectDatabase, tree: &Oid, path: &str) -> GitResult<Option<TreeEntry>> {
+    let mut tree = *tree;
+    let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+    while let Some(name) = components.next() {
+        let entry = match odb
+            .read_tree(&tree)?
+            .entries
+            .into_iter()
+            .find(|e| e.name == name)
+        {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if components.peek().is_none() {
+            return Ok(Some(entry));
+        }
+        if !entry.is_tree() {
+            return Ok(None);
+        }
+        tree = entry.oid;
+    }
+    Ok(None)
+}
+
+/// The stage 0 entries of an index, as a flat tree.
+pub fn from_index(index: &Index) -> FlatTree {
+    index
+        .entries()
+        .iter()
+        .filter(|e| e.stage() == 0)
+        .map(|e| {
+            (
+                e.path.clone(),
+                FlatEntry {
+                    mode: e.mode,
+                    oid: e.oid,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Write the nested tree objects for a flat map, bottom-up, returning the
+/// id of the root tree.
+pub fn build(writer: &mut dyn ObjectWriter, flat: &FlatTree) -> GitResult<Oid> {
+    let entries: Vec<(&str, FlatEntry)> = flat.iter().map(|(p, e)| (p.as_str(), *e)).collect();
+    build_level(writer, &entries)
+}
+
+/// `entries` are paths relative to the directory being built, in sorted order.
+fn build_level(writer: &mut dyn ObjectWriter, entries: &[(&str, FlatEntry)]) -> GitResult<Oid> {
+    let mut tree = Tree::default();
+    let mut i = 0;
+    while i < entries.len() {
+        let (path, entry) = entries[i];
+        match path.find('/') {
+            None => {
+                tree.entries.push(TreeEntry {
+                    mode: entry.mode,
+                    name: path.to_string(),
+                    oid: entry.oid,
+                });
+                i += 1;
+            }
+            Some(slash) => {
+                let dir = &path[..slash];
+                let mut children = Vec::new();
+                while i < entries.len() {
+                    match entries[i]
+                        .0
+                        .strip_prefix(dir)
+                        .and_then(|p| p.strip_prefix('/'))
+                    {
+                        Some(rest) => children.push((rest, entries[i].1)),
+                        None => break,
+                    }
+                    i += 1;
+                }
+                tree.entries.push(TreeEntry {
+                    mode: MODE_TREE,
+                    name: dir.to_string(),
+                    oid: build_level(writer, &children)?,
+                });
+            }
+        }
+    }
+    writer.write(&GitObject::Tree(tree))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::object::MODE_FILE;
+    use crate::core::odb::LooseObjectWriter;
+
+    #[test]
+    fn build_then_flatten() {
+        let dir = tempfile::tempdir().unwrap();
+        let odb = ObjectDatabase::new(dir.path());
+        let blob = odb.write(&GitObject::Blob(b"x\n".to_vec())).unwrap();
+        let mut flat = FlatTree::new();
+        for path in &["a.txt", "a/b/c.txt", "a/d.txt", "a-b"] {
+            flat.insert(
+                path.to_string(),
+                FlatEntry {
+                    mode: MODE_FILE,
+                    oid: blob,
+                },
+            );
+        }
+        let root = build(&mut LooseObjectWriter::new(&odb), &flat).unwrap();
+        // Matches `git write-tree` for the same four files.
+        assert_eq!(root.to_string(), "0b3db807cbaa60e218db345d319a75ee57493aab");
+        assert_eq!(flatten(&odb, &root).unwrap(), flat);
+    }
+}
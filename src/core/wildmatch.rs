@@ -0,0 +1,150 @@
+//! Glob matching compatible with git's wildmatch.
+
+/// Match `text` against a glob. With `pathname` set, `*`, `?` and
+/// bracket expressions don't match `/`, while `**` as a whole path
+/// component matches any number of directories; without it `/` is an
+/// ordinary character, as for `git tag -l` patterns.
+pub fn wildmatch(pattern: &[u8], text: &[u8], pathname: bool) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*')
+            if pathname
+                && pattern.starts_with(b"**")
+                && (pattern.len() == 2 || pattern[2] == b'/') =>
+        {
+            if pattern.len() == 2 {
+                return true;
+            }
+            let rest = &pattern[3..];
+            if wildmatch(rest, text, pathname) {
+                return true;
+            }
+            text.iter()
+                .enumerate()
+                .any(|(i, &b)| b == b'/' && wildmatch(rest, &text[i + 1..], pathname))
+        }
+        Some(b'*') => {
+            let rest = &pattern[pattern.iter().take_while(|&&b| b == b'*').count()..];
+            for i in 0..=text.len() {
+                if wildmatch(rest, &text[i..], pathname) {
+                    return true;
+                }
+                if pathname && i < text.len() && text[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => match text.first() {
+            Some(&b) if !pathname || b != b'/' => wildmatch(&pattern[1..], &text[1..], pathname),
+            _ => false,
+        },
+        Some(b'[') => match (parse_class(&pattern[1..]), text.first()) {
+            (Some((matches, len)), Some(&b)) => {
+                (!pathname || b != b'/')
+                    && matches(b)
+                    && wildmatch(&pattern[1 + len..], &text[1..], pathname)
+            }
+            (Some(_), None) => false,
+            // An unterminated class is a literal `[`.
+            (None, _) => {
+                text.first() == Some(&b'[') && wildmatch(&pattern[1..], &text[1..], pathname)
+            }
+        },
+        Some(b'\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && wildmatch(&pattern[2..], &text[1..], pathname)
+        }
+        Some(&c) => text.first() == Some(&c) && wildmatch(&pattern[1..], &text[1..], pathname),
+    }
+}
+
+/// Parse a bracket expression following its `[`, returning a matcher and
+/// the number of bytes consumed including the closing `]`.
+fn parse_class(pattern: &[u8]) -> Option<(impl Fn(u8) -> bool, usize)> {
+    let mut i = 0;
+    let negated = matches!(pattern.first(), Some(b'!') | Some(b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges: Vec<(u8, u8)> = Vec::new();
+    let mut named: Vec<fn(&u8) -> bool> = Vec::new();
+    let mut first = true;
+    loop {
+        let b = *pattern.get(i)?;
+        if b == b']' && !first {
+            break;
+        }
+        first = false;
+        if b == b'[' && pattern.get(i + 1) == Some(&b':') {
+            let close = pattern[i + 2..].windows(2).position(|w| w == b":]")?;
+            named.push(match &pattern[i + 2..i + 2 + close] {
+                b"alnum" => u8::is_ascii_alphanumeric,
+                b"alpha" => u8::is_ascii_alphabetic,
+                b"blank" => |b: &u8| *b == b' ' || *b == b'\t',
+                b"cntrl" => u8::is_ascii_control,
+                b"digit" => u8::is_ascii_digit,
+                b"graph" => u8::is_ascii_graphic,
+                b"lower" => u8::is_ascii_lowercase,
+                b"print" => |b: &u8| b.is_ascii_graphic() || *b == b' ',
+                b"punct" => u8::is_ascii_punctuation,
+                b"space" => u8::is_ascii_whitespace,
+                b"upper" => u8::is_ascii_uppercase,
+                b"xdigit" => u8::is_ascii_hexdigit,
+                _ => return None,
+            });
+            i += close + 4;
+            continue;
+        }
+        let (low, width) = match b {
+            b'\\' => (*pattern.get(i + 1)?, 2),
+            _ => (b, 1),
+        };
+        i += width;
+        if pattern.get(i) == Some(&b'-') && pattern.get(i + 1).is_some_and(|&b| b != b']') {
+            let (high, width) = match pattern[i + 1] {
+                b'\\' => (*pattern.get(i + 2)?, 2),
+                b => (b, 1),
+            };
+            ranges.push((low, high));
+            i += 1 + width;
+        } else {
+            ranges.push((low, low));
+        }
+    }
+    let matcher = move |b: u8| {
+        let hit = ranges.iter().any(|&(low, high)| low <= b && b <= high)
+            || named.iter().any(|class| class(&b));
+        hit != negated
+    };
+    Some((matcher, i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_path_globs() {
+        assert!(wildmatch(b"*.o", b"main.o", true));
+        assert!(!wildmatch(b"*.o", b"src/main.o", true));
+        assert!(wildmatch(b"src/*.rs", b"src/lib.rs", true));
+        assert!(wildmatch(b"**/build", b"a/b/build", true));
+        assert!(wildmatch(b"**/build", b"build", true));
+        assert!(wildmatch(b"a/**/z", b"a/z", true));
+        assert!(wildmatch(b"a/**/z", b"a/b/c/z", true));
+        assert!(wildmatch(b"out/**", b"out/x/y", true));
+        assert!(!wildmatch(b"out/**", b"out", true));
+        assert!(wildmatch(b"file[0-9].txt", b"file3.txt", true));
+        assert!(!wildmatch(b"file[!0-9].txt", b"file3.txt", true));
+        assert!(wildmatch(b"[[:upper:]]*", b"README", true));
+        assert!(wildmatch(b"\\*", b"*", true));
+        assert!(!wildmatch(b"?", b"/", true));
+    }
+
+    #[test]
+    fn slashes_are_ordinary_without_pathname() {
+        assert!(wildmatch(b"release*", b"release/1.0", false));
+        assert!(!wildmatch(b"release*", b"release/1.0", true));
+        assert!(wildmatch(b"v?.0", b"v1.0", false));
+    }
+}
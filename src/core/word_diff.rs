@@ -0,0 +1,304 @@
+//! Word diffs: the changed lines of each hunk diffed again a word at a
+//! time, as `git diff --word-diff` shows them.
+//!
+//! The hunks are the line diff's, so their headers still give the lines
+//! they cover. Within a hunk, each run of removed and added lines is cut
+//! into words, runs of non-whitespace or matches of a word regex, and
+//! the two lists of words diffed. Everything between words comes from
+//! the new side.
+
+use crate::core::diff::{self, DiffOp};
+use crate::core::regex::Regex;
+
+/// How changed words are shown, as `--word-diff=<mode>` picks it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WordDiffMode {
+    /// Whole lines, as an ordinary patch.
+    #[default]
+    None,
+    /// Changes inline, as `[-removed-]{+added+}`.
+    Plain,
+    /// A line for each piece of text, starting ` `, `-` or `+`, with a
+    /// `~` line wherever the new text has a newline.
+    Porcelain,
+}
+
+/// How one kind of text is written: what goes before and after it, and
+/// what stands for a newline in it.
+struct Style {
+    prefix: &'static str,
+    suffix: &'static str,
+    newline: &'static str,
+}
+
+struct Styles {
+    context: Style,
+    old: Style,
+    new: Style,
+}
+
+fn styles(mode: WordDiffMode) -> Styles {
+    let style = |prefix, suffix, newline| Style {
+        prefix,
+        suffix,
+        newline,
+    };
+    match mode {
+        WordDiffMode::Porcelain => Styles {
+            context: style(" ", "\n", "~\n"),
+            old: style("-", "\n", "~\n"),
+            new: style("+", "\n", "~\n"),
+        },
+        _ => Styles {
+            context: style("", "", "\n"),
+            old: style("[-", "-]", "\n"),
+            new: style("{+", "+}", "\n"),
+        },
+    }
+}
+
+/// Rewrite `hunks`, as [`diff::unified_hunks`] writes them, as a word
+/// diff in `mode`, cutting words with `regex` when there is one.
+/// [`WordDiffMode::None`] leaves them as they are.
+pub fn word_diff_hunks(hunks: &[u8], mode: WordDiffMode, regex: Option<&Regex>) -> Vec<u8> {
+    if mode == WordDiffMode::None {
+        return hunks.to_vec();
+    }
+    let styles = styles(mode);
+    let mut out = Vec::new();
+    let (mut old, mut new) = (Vec::new(), Vec::new());
+    for line in diff::split_lines(hunks) {
+        match line.first() {
+            Some(b'-') => old.extend_from_slice(&line[1..]),
+            Some(b'+') => new.extend_from_slice(&line[1..]),
+            // The changed lines are shown word by word, so there's no
+            // line for the marker to follow.
+            Some(b'\\') => {}
+            Some(b' ') => {
+                show_words(&mut out, &mut old, &mut new, &styles, regex);
+                if mode == WordDiffMode::Porcelain {
+                    out.extend_from_slice(line);
+                    out.extend_from_slice(b"~\n");
+                } else {
+                    out.extend_from_slice(&line[1..]);
+                }
+            }
+            _ => {
+                show_words(&mut out, &mut old, &mut new, &styles, regex);
+                out.extend_from_slice(line);
+            }
+        }
+    }
+    show_words(&mut out, &mut old, &mut new, &styles, regex);
+    out
+}
+
+/// Where each word of `text` starts and ends, as git finds them: the
+/// matches of `regex`, each cut short at a newline, or else the runs of
+/// characters other than spaces, tabs and line endings.
+pub fn words(text: &[u8], regex: Option<&Regex>) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut at = 0;
+    while at < text.len() {
+        let (start, end) = match regex {
+            Some(regex) => match regex.find_at(text, at) {
+                Some((start, end)) => {
+                    let end = text[start..end]
+                        .iter()
+                        .position(|&b| b == b'\n')
+                        .map_or(end, |n| start + n);
+                    // Like git, a match that's empty, or only a newline,
+                    // ends the words.
+                    if start >= end {
+                        break;
+                    }
+                    (start, end)
+                }
+                None => break,
+            },
+            None => {
+                let space = |b: &u8| matches!(b, b' ' | b'\t' | b'\n' | b'\r');
+                let start = match text[at..].iter().position(|b| !space(b)) {
+                    Some(n) => at + n,
+                    None => break,
+                };
+                let end = text[start..]
+                    .iter()
+                    .position(space)
+                    .map_or(text.len(), |n| start + n);
+                (start, end)
+            }
+        };
+        words.push((start, end));
+        at = end;
+    }
+    words
+}
+
+/// Write the word diff of the removed text `old` against the added text
+/// `new`, emptying both.
+fn show_words(
+    out: &mut Vec<u8>,
+    old: &mut Vec<u8>,
+    new: &mut Vec<u8>,
+    styles: &Styles,
+    regex: Option<&Regex>,
+) {
+    if old.is_empty() && new.is_empty() {
+        return;
+    }
+    if new.is_empty() {
+        write_styled(out, old, &styles.old);
+        old.clear();
+        return;
+    }
+    let (old_words, new_words) = (words(old, regex), words(new, regex));
+    let old_text: Vec<&[u8]> = old_words.iter().map(|&(s, e)| &old[s..e]).collect();
+    let new_text: Vec<&[u8]> = new_words.iter().map(|&(s, e)| &new[s..e]).collect();
+    let ops = diff::diff(&old_text, &new_text);
+    // The byte range of `len` words from `first`, or the point after the
+    // word before when there are none.
+    let range = |words: &[(usize, usize)], first: usize, len: usize| match len {
+        0 if first == 0 => (0, 0),
+        0 => (words[first - 1].1, words[first - 1].1),
+        _ => (words[first].0, words[first + len - 1].1),
+    };
+
+    // How much of `new` has been written.
+    let mut written = 0;
+    let mut i = 0;
+    while i < ops.len() {
+        let (old_first, old_len, new_first, new_len) = match ops[i] {
+            DiffOp::Equal { .. } => {
+                i += 1;
+                continue;
+            }
+            DiffOp::Delete { old, new, len } => match ops.get(i + 1) {
+                Some(&DiffOp::Insert { len: added, .. }) => {
+                    i += 1;
+                    (old, len, new, added)
+                }
+                _ => (old, len, new, 0),
+            },
+            DiffOp::Insert { old, new, len } => (old, 0, new, len),
+        };
+        i += 1;
+        let (old_start, old_end) = range(&old_words, old_first, old_len);
+        let (new_start, new_end) = range(&new_words, new_first, new_len);
+        write_styled(out, &new[written..new_start], &styles.context);
+        write_styled(out, &old[old_start..old_end], &styles.old);
+        write_styled(out, &new[new_start..new_end], &styles.new);
+        written = new_end;
+    }
+    write_styled(out, &new[written..], &styles.context);
+    old.clear();
+    new.clear();
+}
+
+/// Write `text` in `style`, each of its lines wrapped on its own.
+fn write_styled(out: &mut Vec<u8>, text: &[u8], style: &Style) {
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (line, newline) = match rest.iter().position(|&b| b == b'\n') {
+            Some(n) => (&rest[..n], true),
+            None => (rest, false),
+        };
+        if !line.is_empty() {
+            out.extend_from_slice(style.prefix.as_bytes());
+            out.extend_from_slice(line);
+            out.extend_from_slice(style.suffix.as_bytes());
+        }
+        if !newline {
+            break;
+        }
+        out.extend_from_slice(style.newline.as_bytes());
+        rest = &rest[line.len() + 1..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{git, init_repo, write_commit};
+
+    /// The word diff of `old` against `new`, checked against git's hunks
+    /// for the same files.
+    fn word_diff(old: &str, new: &str, mode: WordDiffMode, regex: Option<&str>) -> String {
+        let compiled = regex.map(|r| Regex::new(r).unwrap());
+        let hunks = diff::unified_hunks(old.as_bytes(), new.as_bytes(), 3);
+        let ours = String::from_utf8(word_diff_hunks(&hunks, mode, compiled.as_ref())).unwrap();
+
+        let (_dir, repo) = init_repo();
+        let old = write_commit(&repo, &[], &[("file", old)], "old").to_string();
+        let new = write_commit(&repo, &[], &[("file", new)], "new").to_string();
+        let mode = match mode {
+            WordDiffMode::Porcelain => "--word-diff=porcelain",
+            _ => "--word-diff=plain",
+        };
+        let regex = regex.map(|r| format!("--word-diff-regex={}", r));
+        let mut args = vec!["diff", mode, &old, &new];
+        args.extend(regex.as_deref());
+        if let Some(theirs) = git(&repo, &args) {
+            let hunks = &theirs[theirs.find("\n@@").unwrap() + 1..];
+            assert_eq!(ours, hunks);
+        }
+        ours
+    }
+
+    #[test]
+    fn shows_a_reworded_sentence_word_by_word() {
+        let old = "Intro\nThe quick brown fox jumps over the dog.\nOutro\n";
+        let new = "Intro\nThe quick red fox leaps over the lazy dog.\nOutro\n";
+        assert_eq!(
+            word_diff(old, new, WordDiffMode::Plain, None),
+            "@@ -1,3 +1,3 @@\nIntro\n\
+             The quick [-brown-]{+red+} fox [-jumps-]{+leaps+} over the {+lazy+} dog.\n\
+             Outro\n"
+        );
+        assert_eq!(
+            word_diff(old, new, WordDiffMode::Porcelain, None),
+            "@@ -1,3 +1,3 @@\n Intro\n~\n The quick \n-brown\n+red\n  fox \n-jumps\n\
+             +leaps\n  over the \n+lazy\n  dog.\n~\n Outro\n~\n"
+        );
+        // A regex can cut words finer than whitespace does.
+        assert_eq!(
+            word_diff(
+                "x = f(a, b);\n",
+                "x = f(a, c);\n",
+                WordDiffMode::Plain,
+                Some("[a-z]+|[^[:space:]]")
+            ),
+            "@@ -1 +1 @@\nx = f(a, [-b-]{+c+});\n"
+        );
+    }
+
+    #[test]
+    fn shows_whole_lines_added_and_removed() {
+        let old = "keep\ngone line\nkeep too\n";
+        let new = "keep\nkeep too\nnew line\n  indented\n";
+        assert_eq!(
+            word_diff(old, new, WordDiffMode::Plain, None),
+            "@@ -1,3 +1,4 @@\nkeep\n[-gone line-]\nkeep too\n{+new line+}\n{+  indented+}\n"
+        );
+    }
+
+    #[test]
+    fn cuts_unicode_words_on_character_boundaries() {
+        let old = "naïve café ☕ résumé\n";
+        let new = "naïve cafés ☕ resume\n";
+        let hunks = diff::unified_hunks(old.as_bytes(), new.as_bytes(), 3);
+        let plain = |regex: Option<&Regex>| {
+            String::from_utf8(word_diff_hunks(&hunks, WordDiffMode::Plain, regex)).unwrap()
+        };
+        assert_eq!(
+            plain(None),
+            "@@ -1 +1 @@\nnaïve [-café-]{+cafés+} ☕ [-résumé-]{+resume+}\n"
+        );
+        // Each character a word of its own.
+        let chars = Regex::new("[^[:space:]]").unwrap();
+        assert_eq!(
+            plain(Some(&chars)),
+            "@@ -1 +1 @@\nnaïve café{+s+} ☕ r[-é-]{+e+}sum[-é-]{+e+}\n"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Reading and writing tracked files in the working tree.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::core::index::{Index, IndexEntry};
+use crate::core::object::{hash_object, ObjectType, MODE_EXECUTABLE, MODE_FILE, MODE_SYMLINK};
+use crate::core::odb::ObjectDatabase;
+use crate::core::oid::Oid;
+use crate::core::tree::{FlatEntry, FlatTree};
+use crate::error::{GitError, GitResult};
+
+/// The git mode a file on disk would be recorded with.
+pub fn mode_of(meta: &fs::Metadata) -> u32 {
+    if meta.file_type().is_symlink() {
+        return MODE_SYMLINK;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if meta.permissions().mode() & 0o111 != 0 {
+            return MODE_EXECUTABLE;
+        }
+    }
+    MODE_FILE
+}
+
+/// The content git would store for a file: symlinks store their target.
+pub fn read_content(path: &Path, meta: &fs::Metadata) -> GitResult<Vec<u8>> {
+    if meta.file_type().is_symlink() {
+        let target = fs::read_link(path)?;
+        return Ok(target.to_string_lossy().into_owned().into_bytes());
+    }
+    Ok(fs::read(path)?)
+}
+
+/// Hash a working tree file as a blob without storing it. Returns `None`
+/// when nothing (or a directory) is at `path`.
+pub fn hash_file(work_dir: &Path, path: &str) -> GitResult<Option<FlatEntry>> {
+    let full = work_dir.join(path);
+    let meta = match fs::symlink_metadata(&full) {
+        Ok(meta) if meta.is_dir() => return Ok(None),
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let content = read_content(&full, &meta)?;
+    Ok(Some(FlatEntry {
+        mode: mode_of(&meta),
+        oid: hash_object(ObjectType::Blob, &content),
+    }))
+}
+
+/// Blobs bigger than this are streamed to disk rather than read into
+/// memory first.
+pub const STREAM_THRESHOLD: usize = 1024 * 1024;
+
+/// Write a blob out to the working tree, creating parent directories and
+/// replacing whatever was at `path` before.
+pub fn write_blob(
+    odb: &ObjectDatabase,
+    work_dir: &Path,
+    path: &str,
+    mode: u32,
+    oid: &Oid,
+) -> GitResult<()> {
+    match odb.read_header(oid)? {
+        (ObjectType::Blob, size) if size > STREAM_THRESHOLD && mode != MODE_SYMLINK => {
+            let full = make_room(work_dir, path)?;
+            let mut file = fs::File::create(&full)?;
+            odb.read_object_streaming(oid, &mut file)?;
+            set_mode(&full, mode)
+        }
+        _ => {
+            let content = odb.read_blob(oid)?;
+            write_content(work_dir, path, mode, &content)
+        }
+    }
+}
+
+pub fn write_content(work_dir: &Path, path: &str, mode: u32, content: &[u8]) -> GitResult<()> {
+    let full = make_room(work_dir, path)?;
+    if mode == MODE_SYMLINK {
+        #[cfg(unix)]
+        {
+            let target = String::from_utf8_lossy(content).into_owned();
+            std::os::unix::fs::symlink(target, &full)?;
+            return Ok(());
+        }
+    }
+    fs::write(&full, content)?;
+    set_mode(&full, mode)
+}
+
+/// Clear the way for a file at `path`, returning where it goes.
+fn make_room(work_dir: &Path, path: &str) -> GitResult<PathBuf> {
+    let full = work_dir.join(path);
+    if let Some(parent) = full.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::symlink_metadata(&full) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(&full)?,
+        Ok(_) => fs::remove_file(&full)?,
+        Err(_) => {}
+    }
+    Ok(full)
+}
+
+fn set_mode(full: &Path, mode: u32) -> GitResult<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if mode == MODE_EXECUTABLE {
+            fs::set_permissions(full, fs::Permissions::from_mode(0o755))?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (full, mode);
+    Ok(())
+}
+
+/// Delete a tracked file, pruning any directories left empty.
+pub fn remove_file(work_dir: &Path, path: &str) -> GitResult<()> {
+    let full = work_dir.join(path);
+    match fs::remove_file(&full) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    }
+    let mut dir = full.parent();
+    while let Some(d) = dir {
+        if d == work_dir || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+    Ok(())
+}
+
+/// `path`, given relative to `work_dir` or as an absolute path inside it,
+/// as the `/`-separated relative path the index uses.
+pub fn relative_path(work_dir: &Path, path: &Path) -> GitResult<String> {
+    let relative = if path.is_absolute() {
+        path.strip_prefix(work_dir)
+            .map_err(|_| GitError::PathOutsideRepository(path.to_path_buf()))?
+    } else {
+        path
+    };
+    let mut parts: Vec<String> = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            Component::ParentDir if parts.pop().is_some() => {}
+            _ => return Err(GitError::PathOutsideRepository(path.to_path_buf())),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// An index entry for a working tree file, with fresh stat information.
+pub fn stat_entry(work_dir: &Path, path: &str, oid: Oid, mode: u32) -> GitResult<IndexEntry> {
+    let mut entry = IndexEntry::new(path, oid, mode);
+    let meta = fs::symlink_metadata(work_dir.join(path))?;
+    entry.update_stat(&meta);
+    Ok(entry)
+}
+
+/// Bring the working tree from `from` to `to`, touching only paths whose
+/// content or mode differ.
+pub fn update(
+    odb: &ObjectDatabase,
+    work_dir: &Path,
+    from: &FlatTree,
+    to: &FlatTree,
+) -> GitResult<()> {
+    for path in from.keys() {
+        if !to.contains_key(path) {
+            remove_file(work_dir, path)?;
+        }
+    }
+    for (path, entry) in to {
+        if from.get(path) != Some(entry) {
+            write_blob(odb, work_dir, path, entry.mode, &entry.oid)?;
+        }
+    }
+    Ok(())
+}
+
+/// An index matching `tree`, stat'ing the checked out files where present.
+pub fn index_from_tree(work_dir: &Path, tree: &FlatTree) -> GitResult<Index> {
+    let mut index = Index::new();
+    for (path, entry) in tree {
+        let index_entry = match stat_entry(work_dir, path, entry.oid, entry.mode) {
+            Ok(e) => e,
+            Err(_) => IndexEntry::new(path, entry.oid, entry.mode),
+        };
+        index.add(index_entry);
+    }
+    Ok(index)
+}
@@ -0,0 +1,338 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::core::oid::Oid;
+use crate::repository::RepositoryState;
+
+/// Every fallible operation in grit returns a `GitResult`.
+pub type GitResult<T> = Result<T, GitError>;
+
+#[derive(Debug)]
+pub enum GitError {
+    Io(io::Error),
+    /// The path is not inside a git repository.
+    NotARepository(PathBuf),
+    /// The path isn't inside the repository's working tree.
+    PathOutsideRepository(PathBuf),
+    /// The path isn't in the index.
+    NotTracked(PathBuf),
+    /// Nothing in the working tree or the index is at the path.
+    NoSuchPath(PathBuf),
+    /// Something is already at the path and would be replaced.
+    WouldOverwrite(PathBuf),
+    /// The operation needs a working tree but the repository is bare.
+    BareRepository,
+    ObjectNotFound(Oid),
+    /// An object, index or ref file could not be parsed.
+    Corrupt(String),
+    InvalidOid(String),
+    RefNotFound(String),
+    /// The ref exists but holds an object id rather than another ref's name.
+    NotASymbolicRef(String),
+    /// The name isn't allowed as a ref name, for the given reason.
+    InvalidRefName {
+        name: String,
+        reason: String,
+    },
+    /// A ref with this name already exists.
+    RefExists(String),
+    /// There's already a remote with this name.
+    RemoteExists(String),
+    /// No remote with this name is configured.
+    RemoteNotFound(String),
+    /// A refspec that isn't `[+]<src>[:<dst>]` with matching wildcards.
+    InvalidRefspec(String),
+    /// Someone else holds the lock on this ref.
+    RefLockConflict(String),
+    /// The ref didn't have the value the caller expected.
+    RefCasFailed(String),
+    /// HEAD points at this branch, which has no commits yet.
+    UnbornBranch(String),
+    /// The branch is checked out, so it can't be deleted.
+    BranchCheckedOut(String),
+    /// The branch has commits that HEAD doesn't contain.
+    BranchNotMerged(String),
+    /// The branch has no upstream configured for what's being pulled.
+    NoUpstream(String),
+    /// The named revision doesn't resolve to anything.
+    UnknownRevision(String),
+    /// A revision range isn't of the form `A..B` or `A...B`.
+    BadRange(String),
+    /// One step of a revision expression can't be taken; `rev` is the
+    /// expression up to and including that step.
+    BadRevision {
+        rev: String,
+        reason: String,
+    },
+    /// A date, as `--since` takes it, that can't be made sense of.
+    InvalidDate(String),
+    /// `log --follow` was given other than exactly one path.
+    FollowNeedsOnePath,
+    /// A `ref@{n}` selector asked for more entries than the ref's log has.
+    ReflogTooShort {
+        refname: String,
+        entries: usize,
+    },
+    /// The path doesn't exist in the named revision.
+    PathNotInRevision {
+        path: PathBuf,
+        rev: String,
+    },
+    /// No tag is reachable from the commit being described.
+    NoTagFound(Oid),
+    /// `describe` was asked for an exact match and no tag is on the commit.
+    NoExactMatch(Oid),
+    /// A `for-each-ref` format names a field there's no such thing as.
+    UnknownFormatField(String),
+    /// A regular expression, such as `diff.wordRegex`, that can't be
+    /// compiled.
+    InvalidRegex {
+        pattern: String,
+        reason: String,
+    },
+    /// A config key name on the command line isn't `section[.subsection].key`.
+    InvalidConfigKey(String),
+    /// A config value can't be read as the type asked for.
+    InvalidConfigValue {
+        key: String,
+        value: String,
+    },
+    /// The config key has several values, so the operation is ambiguous.
+    MultipleConfigValues(String),
+    /// `user.name` / `user.email` aren't configured.
+    MissingIdentity,
+    /// Only a fast-forward was allowed, and HEAD has diverged from this.
+    NotFastForward(String),
+    /// The commit would record the same tree as its parent.
+    NothingToCommit,
+    /// No message was given for a new commit.
+    EmptyCommitMessage,
+    /// HEAD can't be amended until this operation is concluded.
+    CannotAmend(RepositoryState),
+    /// Another operation has to be concluded or aborted first.
+    OperationInProgress(RepositoryState),
+    /// There's no operation of this kind to continue or abort.
+    NoOperationInProgress(RepositoryState),
+    /// This is a merge commit, so which parent to take its changes
+    /// against has to be given.
+    MainlineRequired(Oid),
+    /// The index still has conflict stages for these paths.
+    UnresolvedConflicts(Vec<PathBuf>),
+    /// Uncommitted changes to these paths would be clobbered.
+    LocalChanges(Vec<PathBuf>),
+    /// Untracked files are where a checkout would write tracked ones.
+    UntrackedWouldBeOverwritten(Vec<PathBuf>),
+    /// Applying changes would leave these paths conflicted.
+    WouldConflict(Vec<PathBuf>),
+    /// There's nothing in the index or working tree to save.
+    NoLocalChanges,
+    /// A hunk of a patch to this path doesn't match the file; `hunk`
+    /// counts from 1.
+    PatchDoesNotApply {
+        path: PathBuf,
+        hunk: usize,
+    },
+    /// The signing program failed; this is what it printed.
+    SigningFailed(String),
+    /// There's no transport for a URL like this one.
+    UnsupportedUrl(String),
+    /// A TLS connection to this URL couldn't be made.
+    Tls {
+        url: String,
+        reason: String,
+    },
+    /// A server answered a request with other than success.
+    HttpStatus {
+        url: String,
+        status: u16,
+    },
+    /// The other side of a connection sent something the protocol
+    /// doesn't allow, or reported an error of its own.
+    Protocol(String),
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitError::Io(err) => write!(f, "{}", err),
+            GitError::NotARepository(path) => {
+                write!(f, "not a git repository: {}", path.display())
+            }
+            GitError::PathOutsideRepository(path) => {
+                write!(f, "{} is outside the repository", path.display())
+            }
+            GitError::NotTracked(path) => write!(f, "'{}' is not tracked", path.display()),
+            GitError::NoSuchPath(path) => {
+                write!(f, "pathspec '{}' did not match any files", path.display())
+            }
+            GitError::WouldOverwrite(path) => {
+                write!(f, "'{}' already exists", path.display())
+            }
+            GitError::BareRepository => write!(f, "this operation must be run in a work tree"),
+            GitError::ObjectNotFound(id) => write!(f, "object {} not found", id),
+            GitError::Corrupt(msg) => write!(f, "corrupt data: {}", msg),
+            GitError::InvalidOid(s) => write!(f, "invalid object id: {}", s),
+            GitError::RefNotFound(name) => write!(f, "reference not found: {}", name),
+            GitError::NotASymbolicRef(name) => write!(f, "ref {} is not a symbolic ref", name),
+            GitError::InvalidRefName { name, reason } => {
+                write!(f, "'{}' is not a valid ref name: {}", name, reason)
+            }
+            GitError::RefExists(name) => write!(f, "a ref named '{}' already exists", name),
+            GitError::RemoteExists(name) => write!(f, "remote {} already exists", name),
+            GitError::RemoteNotFound(name) => write!(f, "no such remote: '{}'", name),
+            GitError::InvalidRefspec(spec) => write!(f, "invalid refspec '{}'", spec),
+            GitError::RefLockConflict(name) => write!(
+                f,
+                "unable to lock ref {}: {}.lock exists; another git process may be running",
+                name, name
+            ),
+            GitError::RefCasFailed(msg) => write!(f, "cannot update ref {}", msg),
+            GitError::UnbornBranch(name) => {
+                write!(f, "branch {} does not have any commits yet", name)
+            }
+            GitError::BranchCheckedOut(name) => {
+                write!(f, "cannot delete branch '{}': it is checked out", name)
+            }
+            GitError::BranchNotMerged(name) => write!(
+                f,
+                "the branch '{}' is not fully merged; force the deletion to discard it",
+                name
+            ),
+            GitError::NoUpstream(name) => {
+                write!(
+                    f,
+                    "there is no tracking information for the branch '{}'",
+                    name
+                )
+            }
+            GitError::UnknownRevision(rev) => write!(f, "unknown revision: {}", rev),
+            GitError::BadRange(range) => write!(f, "bad revision range: {}", range),
+            GitError::BadRevision { rev, reason } => write!(f, "{}: {}", rev, reason),
+            GitError::InvalidDate(date) => write!(f, "invalid date: '{}'", date),
+            GitError::FollowNeedsOnePath => write!(f, "--follow requires exactly one pathspec"),
+            GitError::ReflogTooShort { refname, entries } => {
+                write!(f, "log for '{}' only has {} entries", refname, entries)
+            }
+            GitError::PathNotInRevision { path, rev } => {
+                write!(f, "path '{}' does not exist in '{}'", path.display(), rev)
+            }
+            GitError::NoTagFound(id) => {
+                write!(f, "no tags can describe '{}'", id)
+            }
+            GitError::NoExactMatch(id) => {
+                write!(f, "no tag exactly matches '{}'", id)
+            }
+            GitError::UnknownFormatField(field) => write!(f, "unknown field name: {}", field),
+            GitError::InvalidRegex { pattern, reason } => {
+                write!(f, "invalid regular expression '{}': {}", pattern, reason)
+            }
+            GitError::InvalidConfigKey(key) => write!(f, "invalid config key: {}", key),
+            GitError::InvalidConfigValue { key, value } => {
+                write!(f, "bad config value '{}' for '{}'", value, key)
+            }
+            GitError::MultipleConfigValues(key) => {
+                write!(f, "{} has multiple values", key)
+            }
+            GitError::MissingIdentity => write!(
+                f,
+                "*** Please tell me who you are.\n\n\
+                 Run\n\n  \
+                 git config --global user.email \"you@example.com\"\n  \
+                 git config --global user.name \"Your Name\"\n\n\
+                 to set your account's default identity.\n\
+                 Omit --global to set the identity only in this repository."
+            ),
+            GitError::PatchDoesNotApply { path, hunk } => write!(
+                f,
+                "{}: patch does not apply (hunk #{})",
+                path.display(),
+                hunk
+            ),
+            GitError::SigningFailed(stderr) => write!(f, "failed to sign the data: {}", stderr),
+            GitError::UnsupportedUrl(url) => write!(f, "unsupported URL: {}", url),
+            GitError::Tls { url, reason } => {
+                write!(f, "unable to access '{}': {}", url, reason)
+            }
+            GitError::HttpStatus { url, status } => {
+                write!(
+                    f,
+                    "unable to access '{}': the server returned {}",
+                    url, status
+                )
+            }
+            GitError::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            GitError::NotFastForward(name) => {
+                write!(f, "not possible to fast-forward to {}, aborting", name)
+            }
+            GitError::NothingToCommit => write!(f, "nothing to commit"),
+            GitError::EmptyCommitMessage => {
+                write!(f, "aborting commit due to empty commit message")
+            }
+            GitError::CannotAmend(state) => write!(
+                f,
+                "you are in the middle of a {} -- cannot amend",
+                state.as_str()
+            ),
+            GitError::OperationInProgress(state) => {
+                write!(f, "a {} is already in progress", state.as_str())
+            }
+            GitError::NoOperationInProgress(state) => {
+                write!(f, "no {} in progress", state.as_str())
+            }
+            GitError::MainlineRequired(id) => {
+                write!(f, "commit {} is a merge but no mainline was given", id)
+            }
+            GitError::UnresolvedConflicts(paths) => {
+                writeln!(f, "cannot commit with unresolved conflicts in:")?;
+                for path in paths {
+                    writeln!(f, "\t{}", path.display())?;
+                }
+                Ok(())
+            }
+            GitError::LocalChanges(paths) => {
+                writeln!(
+                    f,
+                    "local changes to the following files would be overwritten:"
+                )?;
+                for path in paths {
+                    writeln!(f, "\t{}", path.display())?;
+                }
+                Ok(())
+            }
+            GitError::UntrackedWouldBeOverwritten(paths) => {
+                writeln!(
+                    f,
+                    "the following untracked working tree files would be overwritten by checkout:"
+                )?;
+                for path in paths {
+                    writeln!(f, "\t{}", path.display())?;
+                }
+                Ok(())
+            }
+            GitError::WouldConflict(paths) => {
+                writeln!(f, "changes to the following files would conflict:")?;
+                for path in paths {
+                    writeln!(f, "\t{}", path.display())?;
+                }
+                Ok(())
+            }
+            GitError::NoLocalChanges => write!(f, "no local changes to save"),
+        }
+    }
+}
+
+impl std::error::Error for GitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GitError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GitError {
+    fn from(err: io::Error) -> Self {
+        GitError::Io(err)
+    }
+}
@@ -1,3 +1,15 @@
+pub mod commands;
+pub mod core;
+pub mod error;
+pub mod remote;
+pub mod repository;
+
+#[cfg(test)]
+mod test_utils;
+
+pub use crate::error::{GitError, GitResult};
+pub use crate::repository::{Head, Repository};
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,780 @@
+//! The smart HTTP protocol, as far as a clone needs it: a `GET` of
+//! `info/refs?service=git-upload-pack` for the refs, then a `POST` to
+//! `git-upload-pack` wanting all of them, which is answered with a pack
+//! of everything they reach.
+//!
+//! Both sides talk in [pkt-lines](super::pktline). Answers may come
+//! gzipped or chunked. A user and password in the URL are sent as basic
+//! authentication.
+//!
+//! An `https://` URL is fetched through `openssl s_client`, the way
+//! signing runs `gpg`: there's no TLS library among the dependencies. The
+//! server's certificate has to check out against the system's CAs, or
+//! those in the file `GIT_SSL_CAINFO` names, as it does for git.
+
+use std::collections::HashSet;
+use std::env;
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use flate2::read::GzDecoder;
+
+use crate::core::oid::Oid;
+use crate::error::{GitError, GitResult};
+use crate::remote::pktline::{self, read_pkt_line};
+use crate::remote::RefAdvertisement;
+
+/// How grit introduces itself, to the server and in the `agent`
+/// capability.
+const USER_AGENT: &str = concat!("git/grit-", env!("CARGO_PKG_VERSION"));
+
+/// The capabilities asked for when the server has them.
+const WANTED_CAPABILITIES: [&str; 3] = ["side-band-64k", "ofs-delta", "no-progress"];
+
+/// The refs the repository at `url` advertises and a pack of every
+/// object they reach. A repository without refs gives an empty pack.
+pub fn fetch_pack_http(url: &str) -> GitResult<(Vec<RefAdvertisement>, Vec<u8>)> {
+    let url = Url::parse(url)?;
+    let response = url.request("GET", "info/refs?service=git-upload-pack", None)?;
+    if response.header("content-type") != Some("application/x-git-upload-pack-advertisement") {
+        return Err(GitError::Protocol(format!(
+            "{} is not a smart HTTP server",
+            url.display
+        )));
+    }
+    let (refs, capabilities) = parse_advertisement(&response.body)?;
+    let mut seen = HashSet::new();
+    let wants: Vec<Oid> = refs
+        .iter()
+        .map(|r| r.oid)
+        .filter(|id| seen.insert(*id))
+        .collect();
+    if wants.is_empty() {
+        return Ok((refs, Vec::new()));
+    }
+
+    let has = |name: &str| capabilities.iter().any(|c| c == name);
+    let mut asked: Vec<String> = WANTED_CAPABILITIES
+        .iter()
+        .filter(|c| has(c))
+        .map(|c| c.to_string())
+        .collect();
+    asked.push(format!("agent={}", USER_AGENT));
+    let mut request = Vec::new();
+    for (n, want) in wants.iter().enumerate() {
+        let line = match n {
+            0 => format!("want {} {}\n", want, asked.join(" ")),
+            _ => format!("want {}\n", want),
+        };
+        pktline::write_pkt_line(&mut request, line.as_bytes())?;
+    }
+    pktline::write_flush(&mut request)?;
+    pktline::write_pkt_line(&mut request, b"done\n")?;
+    let body = ("application/x-git-upload-pack-request", request.as_slice());
+    let response = url.request("POST", "git-upload-pack", Some(body))?;
+    let pack = read_pack(&response.body, has("side-band-64k"))?;
+    Ok((refs, pack))
+}
+
+/// The refs and capabilities in the body of an `info/refs` answer.
+fn parse_advertisement(body: &[u8]) -> GitResult<(Vec<RefAdvertisement>, Vec<String>)> {
+    let mut rest = body;
+    match read_pkt_line(&mut rest)? {
+        Some(line) if line == b"# service=git-upload-pack\n" => {}
+        _ => return Err(protocol("the advertisement doesn't name its service")),
+    }
+    if read_pkt_line(&mut rest)?.is_some() {
+        return Err(protocol("expected a flush after the service line"));
+    }
+
+    let mut refs: Vec<RefAdvertisement> = Vec::new();
+    let mut capabilities = Vec::new();
+    let mut first = true;
+    while let Some(line) = read_pkt_line(&mut rest)? {
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        // The first line carries the capabilities after a NUL.
+        let line = match line.iter().position(|&b| b == 0) {
+            Some(nul) if first => {
+                let list = std::str::from_utf8(&line[nul + 1..])
+                    .map_err(|_| protocol("capabilities that aren't UTF-8"))?;
+                capabilities = list.split(' ').map(str::to_string).collect();
+                &line[..nul]
+            }
+            _ => line,
+        };
+        first = false;
+        let line = std::str::from_utf8(line).map_err(|_| protocol("a ref that isn't UTF-8"))?;
+        let (hex, name) = line
+            .split_once(' ')
+            .ok_or_else(|| protocol(&format!("bad ref line '{}'", line)))?;
+        let id = hex.parse::<Oid>()?;
+        // An empty repository advertises only its capabilities.
+        if name == "capabilities^{}" {
+            continue;
+        }
+        if let Some(tag) = name.strip_suffix("^{}") {
+            match refs.last_mut() {
+                Some(last) if last.name == tag => last.peeled = Some(id),
+                _ => return Err(protocol(&format!("{} doesn't follow its tag", name))),
+            }
+            continue;
+        }
+        refs.push(RefAdvertisement {
+            name: name.to_string(),
+            oid: id,
+            peeled: None,
+            symref_target: None,
+        });
+    }
+
+    for (name, target) in capabilities
+        .iter()
+        .filter_map(|c| c.strip_prefix("symref=")?.split_once(':'))
+    {
+        if let Some(symref) = refs.iter_mut().find(|r| r.name == name) {
+            symref.symref_target = Some(target.to_string());
+        }
+    }
+    Ok((refs, capabilities))
+}
+
+/// The pack in the body of an upload-pack answer, taken out of the side
+/// band's packets when `side_band` was asked for.
+fn read_pack(body: &[u8], side_band: bool) -> GitResult<Vec<u8>> {
+    let mut rest = body;
+    // Nothing was offered as common, so the server has nothing to ack.
+    match read_pkt_line(&mut rest)? {
+        Some(line) if line.strip_suffix(b"\n").unwrap_or(&line) == b"NAK" => {}
+        Some(line) if line.starts_with(b"ERR ") => {
+            return Err(protocol(&String::from_utf8_lossy(&line[4..])));
+        }
+        _ => return Err(protocol("expected a NAK before the pack")),
+    }
+    let pack = if side_band {
+        let mut pack = Vec::new();
+        while let Some(packet) = read_pkt_line(&mut rest)? {
+            match packet.split_first() {
+                Some((1, data)) => pack.extend_from_slice(data),
+                // Progress messages.
+                Some((2, _)) => {}
+                Some((3, message)) => {
+                    return Err(protocol(String::from_utf8_lossy(message).trim_end()));
+                }
+                _ => return Err(protocol("a packet on no known side band")),
+            }
+        }
+        pack
+    } else {
+        rest.to_vec()
+    };
+    if !pack.starts_with(b"PACK") {
+        return Err(protocol("the server's answer has no pack in it"));
+    }
+    Ok(pack)
+}
+
+fn protocol(msg: &str) -> GitError {
+    GitError::Protocol(msg.to_string())
+}
+
+/// Where an `http://` or `https://` URL points, and who to say is
+/// asking.
+struct Url {
+    tls: bool,
+    /// The host as the `Host` header gives it, with any port.
+    authority: String,
+    host: String,
+    port: u16,
+    /// The repository's path, without a trailing slash.
+    path: String,
+    /// The `Authorization` header for the URL's user and password.
+    authorization: Option<String>,
+    /// The URL without the user and password, for messages.
+    display: String,
+}
+
+impl Url {
+    fn parse(url: &str) -> GitResult<Url> {
+        let unsupported = || GitError::UnsupportedUrl(url.to_string());
+        let (scheme, rest) = url
+            .split_once("://")
+            .filter(|(scheme, _)| *scheme == "http" || *scheme == "https")
+            .ok_or_else(unsupported)?;
+        let tls = scheme == "https";
+        let (authority, path) = match rest.find('/') {
+            Some(n) => rest.split_at(n),
+            None => (rest, ""),
+        };
+        let (user, authority) = match authority.rfind('@') {
+            Some(n) => (Some(&authority[..n]), &authority[n + 1..]),
+            None => (None, authority),
+        };
+        // A port follows the last colon, unless it's inside an IPv6
+        // address's brackets.
+        let (host, port) = match authority.rfind(':') {
+            Some(n) if !authority[n..].contains(']') => {
+                let port = authority[n + 1..].parse().map_err(|_| unsupported())?;
+                (&authority[..n], port)
+            }
+            _ => (authority, if tls { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err(unsupported());
+        }
+        let path = path.trim_end_matches('/');
+        Ok(Url {
+            tls,
+            authority: authority.to_string(),
+            host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port,
+            path: path.to_string(),
+            authorization: user.map(|user| format!("Basic {}", base64(&percent_decode(user)))),
+            display: format!("{}://{}{}", scheme, authority, path),
+        })
+    }
+
+    /// `method` the repository's `path`, sending `body` as its content
+    /// type, and read the whole answer, which must be a success.
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<(&str, &[u8])>,
+    ) -> GitResult<Response> {
+        let mut head = format!(
+            "{} {}/{} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}\r\n\
+             Accept-Encoding: gzip\r\nConnection: close\r\n",
+            method, self.path, path, self.authority, USER_AGENT
+        );
+        if let Some(authorization) = &self.authorization {
+            head.push_str(&format!("Authorization: {}\r\n", authorization));
+        }
+        if let Some((content_type, body)) = body {
+            head.push_str(&format!(
+                "Content-Type: {}\r\nAccept: application/x-git-upload-pack-result\r\n\
+                 Content-Length: {}\r\n",
+                content_type,
+                body.len()
+            ));
+        }
+        head.push_str("\r\n");
+        let mut request = head.into_bytes();
+        if let Some((_, body)) = body {
+            request.extend_from_slice(body);
+        }
+
+        let raw = if self.tls {
+            self.send_tls(&request)?
+        } else {
+            let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+            stream.write_all(&request)?;
+            let mut raw = Vec::new();
+            stream.read_to_end(&mut raw)?;
+            raw
+        };
+        let response = Response::parse(&raw)?;
+        if response.status != 200 {
+            return Err(GitError::HttpStatus {
+                url: self.display.clone(),
+                status: response.status,
+            });
+        }
+        Ok(response)
+    }
+
+    /// Send `request` with `openssl s_client` and read the answer, once
+    /// the server's certificate has been checked.
+    fn send_tls(&self, request: &[u8]) -> GitResult<Vec<u8>> {
+        let connect = match self.host.contains(':') {
+            true => format!("[{}]:{}", self.host, self.port),
+            false => format!("{}:{}", self.host, self.port),
+        };
+        let mut command = Command::new("openssl");
+        // `-quiet` keeps the session's details out of the answer, and
+        // keeps reading once the request has all been sent.
+        command.args([
+            "s_client",
+            "-quiet",
+            "-connect",
+            &connect,
+            "-verify_return_error",
+        ]);
+        if self.host.parse::<IpAddr>().is_ok() {
+            command.args(["-verify_ip", &self.host]);
+        } else {
+            command.args(["-servername", &self.host, "-verify_hostname", &self.host]);
+        }
+        if let Some(ca_file) = env::var_os("GIT_SSL_CAINFO") {
+            command.arg("-CAfile").arg(ca_file);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| self.tls_failed(format!("cannot run openssl: {}", err)))?;
+        let mut stdin = child.stdin.take().expect("stdin was piped");
+        // Written alongside the reading, so a server that answers before
+        // it has read everything can't leave both sides stuck on full
+        // pipes.
+        let output = thread::scope(|scope| {
+            scope.spawn(move || {
+                // If openssl gave up without reading it all, its status
+                // says why.
+                let _ = stdin.write_all(request);
+            });
+            child.wait_with_output()
+        })?;
+        if !output.status.success() {
+            return Err(self.tls_failed(tls_failure(&output.stderr)));
+        }
+        Ok(output.stdout)
+    }
+
+    fn tls_failed(&self, reason: String) -> GitError {
+        GitError::Tls {
+            url: self.display.clone(),
+            reason,
+        }
+    }
+}
+
+/// Why `openssl s_client` failed, out of what it printed: the reason the
+/// certificate was refused if it was, or else its last line.
+fn tls_failure(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let refused = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("verify error:"))
+        .and_then(|error| error.rsplit(':').next());
+    match refused {
+        Some(reason) => format!("the server's certificate was refused: {}", reason),
+        None => stderr
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty())
+            .unwrap_or("openssl failed")
+            .to_string(),
+    }
+}
+
+struct Response {
+    status: u16,
+    /// Names lowercased, in the order they came.
+    headers: Vec<(String, String)>,
+    /// Unchunked and unzipped.
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn parse(raw: &[u8]) -> GitResult<Response> {
+        let end = find(raw, b"\r\n\r\n")
+            .ok_or_else(|| protocol("the HTTP answer has no end to its headers"))?;
+        let head = std::str::from_utf8(&raw[..end])
+            .map_err(|_| protocol("HTTP headers that aren't UTF-8"))?;
+        let mut lines = head.split("\r\n");
+        let status_line = lines.next().unwrap_or_default();
+        let status = status_line
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| protocol(&format!("bad HTTP status line '{}'", status_line)))?;
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        let mut response = Response {
+            status,
+            headers,
+            body: raw[end + 4..].to_vec(),
+        };
+
+        if response
+            .header("transfer-encoding")
+            .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"))
+        {
+            response.body = unchunk(&response.body)?;
+        } else if let Some(len) = response.header("content-length") {
+            let len = len
+                .parse()
+                .map_err(|_| protocol(&format!("bad Content-Length '{}'", len)))?;
+            if response.body.len() < len {
+                return Err(protocol("the HTTP answer is cut short"));
+            }
+            response.body.truncate(len);
+        }
+        if response
+            .header("content-encoding")
+            .is_some_and(|coding| coding.eq_ignore_ascii_case("gzip"))
+        {
+            let mut body = Vec::new();
+            GzDecoder::new(response.body.as_slice()).read_to_end(&mut body)?;
+            response.body = body;
+        }
+        Ok(response)
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A chunked body put back together.
+fn unchunk(mut data: &[u8]) -> GitResult<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let end = find(data, b"\r\n").ok_or_else(|| protocol("a chunk without a size"))?;
+        let size = std::str::from_utf8(&data[..end])
+            .ok()
+            .map(|line| line.split(';').next().unwrap_or_default().trim())
+            .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| protocol("a bad chunk size"))?;
+        data = &data[end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if data.len() < size + 2 {
+            return Err(protocol("the HTTP answer is cut short"));
+        }
+        body.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// `text` with its `%XX` escapes decoded.
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = text
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::index_pack::index_pack;
+    use crate::core::oid::NULL_OID;
+    use crate::core::tree;
+    use crate::repository::Repository;
+    use crate::test_utils::{git, init_repo, set_ref, write_commit};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+    use std::thread::{self, JoinHandle};
+
+    #[test]
+    fn parses_a_recorded_advertisement() {
+        // As git-http-backend sends it for a repository with a branch and
+        // an annotated tag.
+        let body = concat!(
+            "001e# service=git-upload-pack\n",
+            "0000",
+            "009da1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0 HEAD\0multi_ack thin-pack ",
+            "side-band side-band-64k ofs-delta shallow symref=HEAD:refs/heads/main agent=git/2.39.5\n",
+            "003da1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0 refs/heads/main\n",
+            "003a0123456789abcdef0123456789abcdef01234567 refs/tags/v1\n",
+            "003da1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0 refs/tags/v1^{}\n",
+            "0000",
+        );
+        let (refs, capabilities) = parse_advertisement(body.as_bytes()).unwrap();
+        let tip = "a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0"
+            .parse::<Oid>()
+            .unwrap();
+        let tag = "0123456789abcdef0123456789abcdef01234567"
+            .parse::<Oid>()
+            .unwrap();
+        let advertised = |name: &str, oid, peeled, symref_target: Option<&str>| RefAdvertisement {
+            name: name.to_string(),
+            oid,
+            peeled,
+            symref_target: symref_target.map(str::to_string),
+        };
+        assert_eq!(
+            refs,
+            [
+                advertised("HEAD", tip, None, Some("refs/heads/main")),
+                advertised("refs/heads/main", tip, None, None),
+                advertised("refs/tags/v1", tag, Some(tip), None),
+            ]
+        );
+        assert!(capabilities.iter().any(|c| c == "side-band-64k"));
+
+        let empty = concat!(
+            "001e# service=git-upload-pack\n",
+            "0000",
+            "00470000000000000000000000000000000000000000 capabilities^{}\0ofs-delta\n",
+            "0000",
+        );
+        assert_eq!(parse_advertisement(empty.as_bytes()).unwrap().0, []);
+        for bad in ["001e# service=git-upload-pack\n0000003fxyz", "0000", "00"] {
+            assert!(matches!(
+                parse_advertisement(bad.as_bytes()),
+                Err(GitError::Protocol(_))
+            ));
+        }
+    }
+
+    /// Serve the repositories under `root` with `git http-backend` for
+    /// `requests` requests, gzipping and chunking each answer, returning
+    /// the URL `root` is at and, once they're done, the request headers.
+    fn serve(root: &Path, requests: usize) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let root: PathBuf = root.to_path_buf();
+        let server = thread::spawn(move || {
+            let mut heads = Vec::new();
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut raw = Vec::new();
+                let mut buf = [0; 4096];
+                let end = loop {
+                    let n = stream.read(&mut buf).unwrap();
+                    raw.extend_from_slice(&buf[..n]);
+                    if let Some(end) = find(&raw, b"\r\n\r\n") {
+                        break end;
+                    }
+                };
+                let head = String::from_utf8(raw[..end].to_vec()).unwrap();
+                let header = |name: &str| {
+                    head.lines()
+                        .filter_map(|line| line.split_once(": "))
+                        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                        .map(|(_, value)| value.to_string())
+                };
+                let len: usize = header("content-length").map_or(0, |len| len.parse().unwrap());
+                let mut body = raw[end + 4..].to_vec();
+                while body.len() < len {
+                    let n = stream.read(&mut buf).unwrap();
+                    body.extend_from_slice(&buf[..n]);
+                }
+                let mut words = head.split(' ');
+                let (method, target) = (words.next().unwrap(), words.next().unwrap());
+                let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+                let mut child = Command::new("git")
+                    .arg("http-backend")
+                    .env("GIT_PROJECT_ROOT", &root)
+                    .env("GIT_HTTP_EXPORT_ALL", "1")
+                    .env("GIT_CONFIG_NOSYSTEM", "1")
+                    .env("HOME", &root)
+                    .env("REQUEST_METHOD", method)
+                    .env("PATH_INFO", path)
+                    .env("QUERY_STRING", query)
+                    .env("CONTENT_TYPE", header("content-type").unwrap_or_default())
+                    .env("CONTENT_LENGTH", len.to_string())
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .unwrap();
+                child.stdin.take().unwrap().write_all(&body).unwrap();
+                let output = child.wait_with_output().unwrap().stdout;
+                let cgi_end = find(&output, b"\r\n\r\n").unwrap();
+                let cgi_head = String::from_utf8(output[..cgi_end].to_vec()).unwrap();
+                let mut status = "200 OK".to_string();
+                let mut answer = Vec::new();
+                for line in cgi_head.split("\r\n") {
+                    match line.strip_prefix("Status: ") {
+                        Some(s) => status = s.to_string(),
+                        None => answer.extend_from_slice(format!("{}\r\n", line).as_bytes()),
+                    }
+                }
+                let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+                gzip.write_all(&output[cgi_end + 4..]).unwrap();
+                let zipped = gzip.finish().unwrap();
+                stream
+                    .write_all(format!("HTTP/1.1 {}\r\n", status).as_bytes())
+                    .unwrap();
+                stream.write_all(&answer).unwrap();
+                stream
+                    .write_all(b"Content-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n")
+                    .unwrap();
+                for chunk in zipped.chunks(1000) {
+                    stream
+                        .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                        .unwrap();
+                    stream.write_all(chunk).unwrap();
+                    stream.write_all(b"\r\n").unwrap();
+                }
+                stream.write_all(b"0\r\n\r\n").unwrap();
+                heads.push(head);
+            }
+            heads
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn fetches_a_pack_from_git_http_backend() {
+        let (dir, server) = init_repo();
+        let base = write_commit(&server, &[], &[("a", "a\n")], "base");
+        let tip = write_commit(&server, &[base], &[("a", "b\n"), ("dir/c", "c\n")], "tip");
+        set_ref(&server, "refs/heads/master", &tip);
+        let shown = match git(&server, &["tag", "-a", "-m", "v1", "v1", "HEAD~"])
+            .and_then(|_| git(&server, &["show-ref", "--head", "-d"]))
+        {
+            Some(shown) => shown,
+            None => return,
+        };
+
+        let (root, handle) = serve(dir.path(), 2);
+        let url = root.replace("http://", "http://user:pa%73s@") + "/.git/";
+        let (refs, pack) = fetch_pack_http(&url).unwrap();
+        let heads = handle.join().unwrap();
+        assert!(heads[0].starts_with("GET /.git/info/refs?service=git-upload-pack HTTP/1.1\r\n"));
+        assert!(heads[1].starts_with("POST /.git/git-upload-pack HTTP/1.1\r\n"));
+        // "user:pass"
+        assert!(heads[0].contains("\r\nAuthorization: Basic dXNlcjpwYXNz"));
+
+        let mut ours = String::new();
+        for r in &refs {
+            ours.push_str(&format!("{} {}\n", r.oid, r.name));
+            if let Some(peeled) = r.peeled {
+                ours.push_str(&format!("{} {}^{{}}\n", peeled, r.name));
+            }
+        }
+        assert_eq!(ours, shown);
+        assert_eq!(refs[0].symref_target.as_deref(), Some("refs/heads/master"));
+
+        let (_clone_dir, clone) = init_repo();
+        let pack_path = clone.git_dir().join("objects/pack/fetched.pack");
+        fs::write(&pack_path, pack).unwrap();
+        index_pack(&clone, &pack_path).unwrap();
+        let clone = Repository::open(clone.git_dir()).unwrap();
+        let commit = clone.odb().read_commit(&tip).unwrap();
+        assert_eq!(commit.parents, [base]);
+        let flat = tree::flatten(clone.odb(), &commit.tree).unwrap();
+        assert_eq!(clone.odb().read_blob(&flat["dir/c"].oid).unwrap(), b"c\n");
+    }
+
+    #[test]
+    fn fetches_over_https() {
+        let dir = tempfile::tempdir().unwrap();
+        let openssl = |args: &str| {
+            Command::new("openssl")
+                .args(args.split(' '))
+                .current_dir(dir.path())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()
+        };
+        let made = openssl(
+            "req -x509 -newkey rsa:2048 -nodes -days 1 -subj /CN=localhost \
+             -addext subjectAltName=IP:127.0.0.1 -keyout key.pem -out cert.pem",
+        );
+        match made.map(|child| child.wait_with_output().unwrap().status) {
+            Some(status) => assert!(status.success()),
+            None => return,
+        }
+        // `-HTTP` answers a GET with the file at its path, which holds the
+        // whole response: here an empty repository's advertisement.
+        let refs = dir
+            .path()
+            .join("repo.git/info/refs?service=git-upload-pack");
+        fs::create_dir_all(refs.parent().unwrap()).unwrap();
+        let mut answer =
+            b"HTTP/1.0 200 OK\r\nContent-Type: application/x-git-upload-pack-advertisement\r\n\r\n"
+                .to_vec();
+        pktline::write_pkt_line(&mut answer, b"# service=git-upload-pack\n").unwrap();
+        pktline::write_flush(&mut answer).unwrap();
+        let line = format!("{} capabilities^{{}}\0ofs-delta\n", NULL_OID);
+        pktline::write_pkt_line(&mut answer, line.as_bytes()).unwrap();
+        pktline::write_flush(&mut answer).unwrap();
+        fs::write(&refs, answer).unwrap();
+
+        let mut server =
+            openssl("s_server -HTTP -accept 127.0.0.1:0 -cert cert.pem -key key.pem").unwrap();
+        let mut stdout = BufReader::new(server.stdout.take().unwrap());
+        let address = loop {
+            let mut line = String::new();
+            if stdout.read_line(&mut line).unwrap() == 0 {
+                panic!("openssl s_server exited without listening");
+            }
+            if let Some(address) = line.trim().strip_prefix("ACCEPT ") {
+                break address.to_string();
+            }
+        };
+        let url = format!("https://{}/repo.git", address);
+
+        // Its certificate is signed by nobody the system knows.
+        let refused = fetch_pack_http(&url);
+        env::set_var("GIT_SSL_CAINFO", dir.path().join("cert.pem"));
+        let fetched = fetch_pack_http(&url);
+        env::remove_var("GIT_SSL_CAINFO");
+        server.kill().unwrap();
+        server.wait().unwrap();
+        match refused {
+            Err(GitError::Tls { reason, .. }) => {
+                assert!(reason.contains("self-signed"), "{}", reason)
+            }
+            other => panic!(
+                "expected a refused certificate, got {:?}",
+                other.map(|_| ())
+            ),
+        }
+        assert_eq!(fetched.unwrap(), (Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_speak() {
+        for url in ["git://example.com/repo", "http://"] {
+            assert!(matches!(
+                fetch_pack_http(url),
+                Err(GitError::UnsupportedUrl(_))
+            ));
+        }
+        assert_eq!(base64(b"user:pass"), "dXNlcjpwYXNz");
+        assert_eq!(base64(b"ab"), "YWI=");
+    }
+}
@@ -0,0 +1,136 @@
+//! The local transport: another repository on the same filesystem, read
+//! directly rather than spoken to.
+//!
+//! Its refs are listed as a server would advertise them, and the objects
+//! a fetch wants are copied across as one new pack. As in a fetch over
+//! the wire, an object the receiving repository already has is taken to
+//! come with everything it reaches, so only history it lacks is walked.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::core::object::{GitObject, ObjectType, MODE_GITLINK};
+use crate::core::oid::Oid;
+use crate::core::pack::PackWriter;
+use crate::core::refs::{self, RefTarget};
+use crate::error::GitResult;
+use crate::remote::RefAdvertisement;
+use crate::repository::Repository;
+
+/// `src`'s refs as `git upload-pack` would advertise them: `HEAD` first,
+/// naming the branch it's on, then every ref in name order, tags with
+/// what they peel to. An unborn `HEAD` isn't listed.
+pub fn advertise(src: &Repository) -> GitResult<Vec<RefAdvertisement>> {
+    let odb = src.odb();
+    let mut refs = Vec::new();
+    if let Some(head) = src.head_commit()? {
+        let symref_target = match refs::read(src, "HEAD")? {
+            Some(RefTarget::Symbolic(name)) => Some(name),
+            _ => None,
+        };
+        refs.push(RefAdvertisement {
+            name: "HEAD".to_string(),
+            oid: head,
+            peeled: None,
+            symref_target,
+        });
+    }
+    for reference in refs::iter(src)? {
+        let reference = reference?;
+        let peeled = match reference.peeled() {
+            Some(peeled) => Some(peeled),
+            None => match odb.peel(&reference.target)? {
+                (peeled, _) if peeled != reference.target => Some(peeled),
+                _ => None,
+            },
+        };
+        refs.push(RefAdvertisement {
+            name: reference.name,
+            oid: reference.target,
+            peeled,
+            symref_target: None,
+        });
+    }
+    Ok(refs)
+}
+
+/// Copy everything reachable from `wants` that `dst` doesn't have from
+/// `src` into a pack in `dst`, returning the pack's path, or `None` when
+/// nothing was missing.
+pub fn copy_objects(
+    src: &Repository,
+    dst: &Repository,
+    wants: &[Oid],
+) -> GitResult<Option<PathBuf>> {
+    let (from, to) = (src.odb(), dst.odb());
+    let mut seen = HashSet::new();
+    let mut pending = wants.to_vec();
+    let mut writer = PackWriter::new();
+    while let Some(id) = pending.pop() {
+        if !seen.insert(id) || to.contains(&id) {
+            continue;
+        }
+        let (kind, body) = from.read_raw(&id)?;
+        // Blobs link to nothing, so aren't parsed.
+        if kind != ObjectType::Blob {
+            match from.read(&id)? {
+                GitObject::Commit(commit) => {
+                    pending.push(commit.tree);
+                    pending.extend(commit.parents);
+                }
+                GitObject::Tree(tree) => pending.extend(
+                    tree.entries
+                        .iter()
+                        .filter(|entry| entry.mode != MODE_GITLINK)
+                        .map(|entry| entry.oid),
+                ),
+                GitObject::Tag(tag) => pending.push(tag.object),
+                GitObject::Blob(_) => {}
+            }
+        }
+        writer.add_object(kind, &body)?;
+    }
+    if writer.is_empty() {
+        return Ok(None);
+    }
+
+    let (pack, index, checksum) = writer.finish();
+    to.install_pack(&pack, &index, &checksum).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{checkout, git, init_repo, write_commit};
+
+    #[test]
+    fn copies_only_the_history_that_is_missing() {
+        let (_src_dir, src) = init_repo();
+        let (_dst_dir, dst) = init_repo();
+        let first = write_commit(&src, &[], &[("a", "a\n")], "first");
+        let second = write_commit(&src, &[first], &[("a", "a\n"), ("b", "b\n")], "second");
+        checkout(&src, "master", &second);
+
+        let advertised = advertise(&src).unwrap();
+        let names: Vec<&str> = advertised.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["HEAD", "refs/heads/master"]);
+        assert_eq!(
+            advertised[0].symref_target.as_deref(),
+            Some("refs/heads/master")
+        );
+
+        // The first commit, its tree and blob.
+        copy_objects(&src, &dst, &[first]).unwrap().unwrap();
+        assert!(dst.odb().contains(&first) && !dst.odb().contains(&second));
+        // Then the second commit, its tree and the new blob, and nothing
+        // already copied.
+        let pack = copy_objects(&src, &dst, &[second]).unwrap().unwrap();
+        let idx = crate::core::pack::PackIndex::load(&pack.with_extension("idx")).unwrap();
+        assert_eq!(idx.len(), 3);
+        assert_eq!(copy_objects(&src, &dst, &[second]).unwrap(), None);
+        if let Some(out) = git(&dst, &["fsck", "--connectivity-only", &second.to_string()]) {
+            assert_eq!(out, "");
+        }
+    }
+}
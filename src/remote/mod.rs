@@ -0,0 +1,20 @@
+//! Talking to other repositories: what they advertise and how their
+//! objects are fetched.
+
+pub mod http;
+pub mod local;
+pub mod pktline;
+
+use crate::core::oid::Oid;
+
+/// A ref as a remote advertises it before a fetch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefAdvertisement {
+    pub name: String,
+    pub oid: Oid,
+    /// What an annotated tag points at, from the `^{}` line after it.
+    pub peeled: Option<Oid>,
+    /// The ref a symbolic ref, such as `HEAD`, points at, when the remote
+    /// says.
+    pub symref_target: Option<String>,
+}
@@ -0,0 +1,163 @@
+//! pkt-lines, the framing every git wire protocol is spoken in.
+//!
+//! A pkt-line is four hex digits giving its length, the digits included,
+//! then that much data. The lengths too short to hold any data are
+//! special packets: `0000` is a flush, ending a list or a message, and,
+//! in protocol v2, `0001` is a delimiter between the sections of one
+//! message and `0002` the end of a response.
+
+use std::io::{ErrorKind, Read, Write};
+
+use crate::error::{GitError, GitResult};
+
+/// The longest pkt-line, its length digits included, as git sends and
+/// accepts them.
+pub const MAX_PKT_LEN: usize = 65520;
+
+/// The most data one pkt-line can carry.
+pub const MAX_PKT_DATA: usize = MAX_PKT_LEN - 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    Data(Vec<u8>),
+    /// `0000`
+    Flush,
+    /// `0001`
+    Delim,
+    /// `0002`
+    ResponseEnd,
+}
+
+/// The next packet from `r`.
+pub fn read_packet(r: &mut impl Read) -> GitResult<Packet> {
+    let mut header = [0; 4];
+    read_exact(r, &mut header)?;
+    let len = std::str::from_utf8(&header)
+        .ok()
+        .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| {
+            GitError::Protocol(format!(
+                "bad pkt-line length '{}'",
+                String::from_utf8_lossy(&header)
+            ))
+        })?;
+    match len {
+        0 => return Ok(Packet::Flush),
+        1 => return Ok(Packet::Delim),
+        2 => return Ok(Packet::ResponseEnd),
+        3 => return Err(GitError::Protocol("bad pkt-line length 3".to_string())),
+        _ => {}
+    }
+    if len > MAX_PKT_LEN {
+        return Err(GitError::Protocol(format!(
+            "a pkt-line of {} bytes is longer than {}",
+            len, MAX_PKT_LEN
+        )));
+    }
+    let mut data = vec![0; len - 4];
+    read_exact(r, &mut data)?;
+    Ok(Packet::Data(data))
+}
+
+/// The next data line from `r`, or `None` for a flush. Any other special
+/// packet is an error, as protocol v0 has none.
+pub fn read_pkt_line(r: &mut impl Read) -> GitResult<Option<Vec<u8>>> {
+    match read_packet(r)? {
+        Packet::Data(data) => Ok(Some(data)),
+        Packet::Flush => Ok(None),
+        other => Err(GitError::Protocol(format!("unexpected {:?} packet", other))),
+    }
+}
+
+/// Write `data` to `w` as one pkt-line.
+pub fn write_pkt_line(w: &mut impl Write, data: &[u8]) -> GitResult<()> {
+    if data.len() > MAX_PKT_DATA {
+        return Err(GitError::Protocol(format!(
+            "{} bytes is too much for one pkt-line",
+            data.len()
+        )));
+    }
+    w.write_all(format!("{:04x}", data.len() + 4).as_bytes())?;
+    w.write_all(data)?;
+    Ok(())
+}
+
+pub fn write_flush(w: &mut impl Write) -> GitResult<()> {
+    w.write_all(b"0000")?;
+    Ok(())
+}
+
+pub fn write_delim(w: &mut impl Write) -> GitResult<()> {
+    w.write_all(b"0001")?;
+    Ok(())
+}
+
+/// [`Read::read_exact`], with running out of data a protocol error.
+fn read_exact(r: &mut impl Read, buf: &mut [u8]) -> GitResult<()> {
+    r.read_exact(buf).map_err(|err| match err.kind() {
+        ErrorKind::UnexpectedEof => {
+            GitError::Protocol("the stream ends in the middle of a pkt-line".to_string())
+        }
+        _ => GitError::Io(err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_lines_and_special_packets() {
+        let mut out = Vec::new();
+        write_pkt_line(&mut out, b"want 1234\n").unwrap();
+        write_pkt_line(&mut out, b"").unwrap();
+        write_delim(&mut out).unwrap();
+        write_pkt_line(&mut out, &[b'x'; MAX_PKT_DATA]).unwrap();
+        write_flush(&mut out).unwrap();
+        assert!(out.starts_with(b"000ewant 1234\n00040001fff0xxx"));
+        assert!(out.ends_with(b"xx0000"));
+
+        let mut r = out.as_slice();
+        assert_eq!(
+            read_pkt_line(&mut r).unwrap(),
+            Some(b"want 1234\n".to_vec())
+        );
+        assert_eq!(read_pkt_line(&mut r).unwrap(), Some(Vec::new()));
+        assert_eq!(read_packet(&mut r).unwrap(), Packet::Delim);
+        assert_eq!(read_pkt_line(&mut r).unwrap().unwrap().len(), MAX_PKT_DATA);
+        assert_eq!(read_pkt_line(&mut r).unwrap(), None);
+        assert!(r.is_empty());
+        // A v0 reader doesn't take a delimiter for a flush.
+        assert!(matches!(
+            read_pkt_line(&mut &b"0001"[..]),
+            Err(GitError::Protocol(_))
+        ));
+        assert_eq!(read_packet(&mut &b"0002"[..]).unwrap(), Packet::ResponseEnd);
+    }
+
+    #[test]
+    fn rejects_bad_and_oversized_lines() {
+        let mut out = Vec::new();
+        assert!(matches!(
+            write_pkt_line(&mut out, &[0; MAX_PKT_DATA + 1]),
+            Err(GitError::Protocol(_))
+        ));
+        assert!(out.is_empty());
+        for bad in [
+            &b"fff1"[..],
+            b"ffff",
+            b"0003",
+            b"00zz",
+            b"+00a",
+            b"00",
+            b"0009abc",
+        ] {
+            assert!(
+                matches!(read_packet(&mut &bad[..]), Err(GitError::Protocol(_))),
+                "{:?}",
+                String::from_utf8_lossy(bad)
+            );
+        }
+    }
+}
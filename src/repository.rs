@@ -0,0 +1,297 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::commit_graph::CommitGraph;
+use crate::core::config::Config;
+use crate::core::index::Index;
+use crate::core::object_cache::{self, ObjectCache};
+use crate::core::odb::ObjectDatabase;
+use crate::core::oid::Oid;
+use crate::core::refs::{self, RefTarget};
+use crate::core::revparse;
+use crate::core::signature::{self, Signature};
+use crate::error::{GitError, GitResult};
+
+/// What HEAD points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    /// HEAD is on a branch, given by its full name, along with the branch
+    /// tip.
+    Branch(String, Option<Oid>),
+    /// HEAD holds a commit id directly.
+    Detached(Oid),
+    /// HEAD is on a branch, given by its full name, with no commits yet.
+    Unborn(String),
+}
+
+/// An operation left part-way through, as the files it keeps in the git
+/// directory until it's concluded or aborted show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepositoryState {
+    Clean,
+    /// `MERGE_HEAD` exists.
+    Merge,
+    /// `CHERRY_PICK_HEAD` exists.
+    CherryPick,
+    /// `REVERT_HEAD` exists.
+    Revert,
+    /// `rebase-apply/applying` exists: `am` stopped at a patch.
+    ApplyMailbox,
+    /// `rebase-merge/` or `rebase-apply/` exists.
+    Rebase,
+}
+
+impl RepositoryState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RepositoryState::Clean => "clean",
+            RepositoryState::Merge => "merge",
+            RepositoryState::CherryPick => "cherry-pick",
+            RepositoryState::Revert => "revert",
+            RepositoryState::ApplyMailbox => "am",
+            RepositoryState::Rebase => "rebase",
+        }
+    }
+}
+
+/// A git repository: its `.git` directory and, unless bare, its working tree.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    git_dir: PathBuf,
+    work_dir: Option<PathBuf>,
+    odb: ObjectDatabase,
+}
+
+impl Repository {
+    /// Create a repository with a working tree at `path`.
+    pub fn init<P: AsRef<Path>>(path: P) -> GitResult<Repository> {
+        let work_dir = path.as_ref().to_path_buf();
+        let git_dir = work_dir.join(".git");
+        create_git_dir(&git_dir, false)?;
+        Repository::from_parts(git_dir, Some(work_dir))
+    }
+
+    /// Create a bare repository at `path`.
+    pub fn init_bare<P: AsRef<Path>>(path: P) -> GitResult<Repository> {
+        let git_dir = path.as_ref().to_path_buf();
+        create_git_dir(&git_dir, true)?;
+        Repository::from_parts(git_dir, None)
+    }
+
+    /// Open the repository at `path`, which is either a working tree
+    /// containing `.git` or a bare repository.
+    pub fn open<P: AsRef<Path>>(path: P) -> GitResult<Repository> {
+        let path = path.as_ref();
+        let dot_git = path.join(".git");
+        if is_git_dir(&dot_git) {
+            return Repository::from_parts(dot_git, Some(path.to_path_buf()));
+        }
+        if is_git_dir(path) {
+            return Repository::from_parts(path.to_path_buf(), None);
+        }
+        Err(GitError::NotARepository(path.to_path_buf()))
+    }
+
+    fn from_parts(git_dir: PathBuf, work_dir: Option<PathBuf>) -> GitResult<Repository> {
+        let config = Config::load_for_repo(&git_dir.join("config"), &git_dir)?;
+        // `core.objectCacheSize` is how many parsed objects to keep; zero
+        // turns the cache off.
+        let capacity = match config.get_int("core", None, "objectCacheSize")? {
+            Some(size) => usize::try_from(size).unwrap_or(0),
+            None => object_cache::DEFAULT_CAPACITY,
+        };
+        let objects_dir = git_dir.join("objects");
+        let commit_graph = match config.get_bool("core", None, "commitGraph")? {
+            Some(false) => None,
+            _ => CommitGraph::open_in(&objects_dir.join("info"))?,
+        };
+        let odb = ObjectDatabase::new(objects_dir)
+            .with_cache(Some(capacity).filter(|&n| n > 0).map(ObjectCache::new))
+            .with_commit_graph(commit_graph);
+        Ok(Repository {
+            git_dir,
+            work_dir,
+            odb,
+        })
+    }
+
+    /// Replace the object cache, e.g. with `None` to always read from disk.
+    pub fn set_object_cache(&mut self, cache: Option<ObjectCache>) {
+        self.odb = self.odb.clone().with_cache(cache);
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    pub fn work_dir(&self) -> Option<&Path> {
+        self.work_dir.as_deref()
+    }
+
+    /// The working tree, or `GitError::BareRepository`.
+    pub fn require_work_dir(&self) -> GitResult<&Path> {
+        self.work_dir().ok_or(GitError::BareRepository)
+    }
+
+    pub fn is_bare(&self) -> bool {
+        self.work_dir.is_none()
+    }
+
+    pub fn odb(&self) -> &ObjectDatabase {
+        &self.odb
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.git_dir.join("config")
+    }
+
+    pub fn config(&self) -> GitResult<Config> {
+        Config::load_for_repo(&self.config_path(), &self.git_dir)
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.git_dir.join("index")
+    }
+
+    pub fn read_index(&self) -> GitResult<Index> {
+        Index::load(&self.index_path())
+    }
+
+    pub fn write_index(&self, index: &Index) -> GitResult<()> {
+        index.save(&self.index_path())
+    }
+
+    /// The committer identity, as [`signature::default_signatures`] works
+    /// it out, for stamping reflog entries and the like.
+    pub fn signature(&self) -> GitResult<Signature> {
+        Ok(signature::default_signatures(self)?.1)
+    }
+
+    pub fn head(&self) -> GitResult<Head> {
+        match refs::resolve_symbolic(self, "HEAD")? {
+            RefTarget::Direct(id) => Ok(Head::Detached(id)),
+            RefTarget::Symbolic(_) => match refs::follow(self, "HEAD")? {
+                (branch, Some(id)) => Ok(Head::Branch(branch, Some(id))),
+                (branch, None) => Ok(Head::Unborn(branch)),
+            },
+        }
+    }
+
+    /// The commit HEAD points at, or `None` on an unborn branch.
+    pub fn head_commit(&self) -> GitResult<Option<Oid>> {
+        Ok(refs::follow(self, "HEAD")?.1)
+    }
+
+    /// Move HEAD, or the branch it points at, to `id`, logging the move with
+    /// `reflog_msg`. A detached HEAD is updated in place.
+    pub fn set_head_commit(&self, id: &Oid, reflog_msg: &str) -> GitResult<()> {
+        refs::update(self, "HEAD", *id, None, reflog_msg)
+    }
+
+    /// Which operation, if any, is in progress. A rebase wins over the
+    /// merge or pick it is in the middle of, as in git.
+    pub fn state(&self) -> RepositoryState {
+        let exists = |name: &str| self.git_dir.join(name).exists();
+        if exists("rebase-apply/applying") {
+            RepositoryState::ApplyMailbox
+        } else if exists("rebase-merge") || exists("rebase-apply") {
+            RepositoryState::Rebase
+        } else if exists("MERGE_HEAD") {
+            RepositoryState::Merge
+        } else if exists("CHERRY_PICK_HEAD") {
+            RepositoryState::CherryPick
+        } else if exists("REVERT_HEAD") {
+            RepositoryState::Revert
+        } else {
+            RepositoryState::Clean
+        }
+    }
+
+    /// Resolve a revision expression such as `HEAD~2`, `v1.0^{tree}`, an
+    /// abbreviated id or `main@{1}`; see [`revparse`] for the full syntax.
+    pub fn resolve_rev(&self, rev: &str) -> GitResult<Oid> {
+        revparse::parse(self, rev)
+    }
+}
+
+fn is_git_dir(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir()
+}
+
+fn create_git_dir(git_dir: &Path, bare: bool) -> GitResult<()> {
+    for dir in &["objects/info", "objects/pack", "refs/heads", "refs/tags"] {
+        fs::create_dir_all(git_dir.join(dir))?;
+    }
+    let head = git_dir.join("HEAD");
+    if !head.exists() {
+        fs::write(head, "ref: refs/heads/master\n")?;
+    }
+    let config = git_dir.join("config");
+    if !config.exists() {
+        fs::write(
+            config,
+            format!(
+                "[core]\n\trepositoryformatversion = 0\n\tfilemode = true\n\tbare = {}\n",
+                bare
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_then_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        assert!(!repo.is_bare());
+        assert_eq!(repo.head_commit().unwrap(), None);
+
+        let reopened = Repository::open(dir.path()).unwrap();
+        assert_eq!(reopened.git_dir(), dir.path().join(".git"));
+        assert!(Repository::open(dir.path().join(".git/objects")).is_err());
+    }
+
+    #[test]
+    fn repeated_reads_come_from_the_cache() {
+        let (_dir, mut repo) = crate::test_utils::init_repo();
+        let mut id = crate::test_utils::write_commit(&repo, &[], &[("a", "a")], "base");
+        for n in 0..20 {
+            let parents = [id];
+            id = crate::test_utils::write_commit(&repo, &parents, &[("a", "a")], &n.to_string());
+        }
+        let walk = |repo: &Repository| {
+            for _ in 0..50 {
+                let mut next = Some(id);
+                while let Some(commit) = next {
+                    next = repo
+                        .odb()
+                        .read_commit(&commit)
+                        .unwrap()
+                        .parents
+                        .first()
+                        .copied();
+                }
+            }
+        };
+
+        walk(&repo);
+        let (hits, misses) = repo.odb().cache_stats().unwrap();
+        assert_eq!(misses, 21);
+        assert_eq!(hits, 50 * 21 - 21);
+
+        repo.set_object_cache(None);
+        walk(&repo);
+        assert_eq!(repo.odb().cache_stats(), None);
+
+        let mut config = fs::read_to_string(repo.config_path()).unwrap();
+        config.push_str("[core]\n\tobjectCacheSize = 0\n");
+        fs::write(repo.config_path(), config).unwrap();
+        let reopened = Repository::open(repo.work_dir().unwrap()).unwrap();
+        assert_eq!(reopened.odb().cache_stats(), None);
+    }
+}
@@ -0,0 +1,213 @@
+//! Fixtures shared by the unit tests.
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use tempfile::TempDir;
+
+use crate::core::object::{Commit, GitObject, MODE_FILE};
+use crate::core::odb::LooseObjectWriter;
+use crate::core::oid::Oid;
+use crate::core::signature::Signature;
+use crate::core::tree::{self, FlatEntry, FlatTree};
+use crate::core::worktree;
+use crate::repository::Repository;
+
+/// A fresh non-bare repository with an identity configured. Keep the
+/// `TempDir` alive for as long as the repository is used.
+pub fn init_repo() -> (TempDir, Repository) {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let config = repo.config_path();
+    let mut text = fs::read_to_string(&config).unwrap();
+    text.push_str("[user]\n\tname = A U Thor\n\temail = author@example.com\n");
+    fs::write(config, text).unwrap();
+    (dir, repo)
+}
+
+pub fn signature() -> Signature {
+    Signature::new("A U Thor", "author@example.com", 1_700_000_000, 0)
+}
+
+/// Write a commit whose tree holds exactly `files`, without touching the
+/// index, working tree or any refs.
+pub fn write_commit(
+    repo: &Repository,
+    parents: &[Oid],
+    files: &[(&str, &str)],
+    message: &str,
+) -> Oid {
+    write_commit_at(repo, parents, files, message, signature().time)
+}
+
+/// [`write_commit`] with the author and committer dates set to `time`.
+pub fn write_commit_at(
+    repo: &Repository,
+    parents: &[Oid],
+    files: &[(&str, &str)],
+    message: &str,
+    time: i64,
+) -> Oid {
+    let signature = Signature {
+        time,
+        ..signature()
+    };
+    let odb = repo.odb();
+    let mut flat = FlatTree::new();
+    for (path, content) in files {
+        let oid = odb
+            .write(&GitObject::Blob(content.as_bytes().to_vec()))
+            .unwrap();
+        flat.insert(
+            path.to_string(),
+            FlatEntry {
+                mode: MODE_FILE,
+                oid,
+            },
+        );
+    }
+    let commit = Commit {
+        tree: tree::build(&mut LooseObjectWriter::new(odb), &flat).unwrap(),
+        parents: parents.to_vec(),
+        author: signature.clone(),
+        committer: signature,
+        extra_headers: Vec::new(),
+        message: format!("{}\n", message),
+    };
+    odb.write(&GitObject::Commit(commit)).unwrap()
+}
+
+pub fn set_ref(repo: &Repository, name: &str, id: &Oid) {
+    let path = repo.git_dir().join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, format!("{}\n", id)).unwrap();
+}
+
+/// Point HEAD at `branch`, set the branch to `id`, and make the index and
+/// working tree match that commit.
+pub fn checkout(repo: &Repository, branch: &str, id: &Oid) {
+    let work_dir = repo.work_dir().unwrap();
+    let current = tree::from_index(&repo.read_index().unwrap());
+    let target = tree::flatten(repo.odb(), &repo.odb().read_commit(id).unwrap().tree).unwrap();
+    worktree::update(repo.odb(), work_dir, &current, &target).unwrap();
+    repo.write_index(&worktree::index_from_tree(work_dir, &target).unwrap())
+        .unwrap();
+    let refname = format!("refs/heads/{}", branch);
+    set_ref(repo, &refname, id);
+    fs::write(repo.git_dir().join("HEAD"), format!("ref: {}\n", refname)).unwrap();
+}
+
+/// Write `content` to `path` in the working tree and stage it.
+pub fn stage_file(repo: &Repository, path: &str, content: &str) {
+    let work_dir = repo.work_dir().unwrap();
+    worktree::write_content(work_dir, path, MODE_FILE, content.as_bytes()).unwrap();
+    let oid = repo
+        .odb()
+        .write(&GitObject::Blob(content.as_bytes().to_vec()))
+        .unwrap();
+    let mut index = repo.read_index().unwrap();
+    index.add(worktree::stat_entry(work_dir, path, oid, MODE_FILE).unwrap());
+    repo.write_index(&index).unwrap();
+}
+
+pub fn read_file(repo: &Repository, path: &str) -> String {
+    fs::read_to_string(repo.work_dir().unwrap().join(path)).unwrap()
+}
+
+/// Every index entry's path, id and mode, as `ls-files --stage` lists them.
+pub fn staged(repo: &Repository) -> Vec<(String, Oid, u32)> {
+    let index = repo.read_index().unwrap();
+    index
+        .entries()
+        .iter()
+        .map(|entry| (entry.path.clone(), entry.oid, entry.mode))
+        .collect()
+}
+
+/// The id staged at `path`, if it's staged without conflicts.
+pub fn staged_oid(repo: &Repository, path: &str) -> Option<Oid> {
+    repo.read_index().unwrap().get(path, 0).map(|e| e.oid)
+}
+
+/// Run the real `git` against `repo` for interop checks, returning its
+/// stdout. `None` means git isn't installed and the check should be skipped.
+pub fn git(repo: &Repository, args: &[&str]) -> Option<String> {
+    git_with_input(repo, args, "")
+}
+
+/// [`git`] with `input` fed to its stdin.
+pub fn git_with_input(repo: &Repository, args: &[&str], input: &str) -> Option<String> {
+    let mut command = Command::new("git");
+    // Without a work tree of its own git would take the current directory.
+    if let Some(work_dir) = repo.work_dir() {
+        command.current_dir(work_dir);
+    }
+    let mut child = command
+        .arg("--git-dir")
+        .arg(repo.git_dir())
+        .args(args)
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("HOME", repo.git_dir())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+/// An executable shell script at `dir/name`, standing in for a program
+/// such as `gpg` that grit runs.
+pub fn write_script(dir: &Path, name: &str, script: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+/// A stand-in for `gpg` at `dir/gpg` that "signs" by checksumming what it's
+/// given, putting the key in a `Comment:` line, and verifies by checking
+/// that checksum, printing status lines the way gpg does.
+pub fn fake_gpg(dir: &Path) -> PathBuf {
+    write_script(
+        dir,
+        "gpg",
+        r#"while [ $# -gt 0 ]; do
+  case "$1" in
+    -bsau) key=$2; shift ;;
+    --verify) sig=$2; shift ;;
+  esac
+  shift
+done
+sum=$(cksum)
+if [ -n "$key" ]; then
+  echo "[GNUPG:] SIG_CREATED D 1 8 00 0 0" >&2
+  printf -- '-----BEGIN PGP SIGNATURE-----\nComment: %s\n\n%s\n-----END PGP SIGNATURE-----\n' "$key" "$sum"
+  exit 0
+fi
+echo "[GNUPG:] NEWSIG"
+if [ "$(tail -n 2 "$sig" | head -n 1)" = "$sum" ]; then
+  echo "[GNUPG:] GOODSIG 0123456789ABCDEF A U Thor <author@example.com>"
+else
+  echo "[GNUPG:] BADSIG 0123456789ABCDEF A U Thor <author@example.com>"
+  exit 1
+fi
+"#,
+    )
+}